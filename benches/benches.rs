@@ -2,7 +2,7 @@ use std::path::Path;
 
 use adae::{Engine, StoredAudioClipKey, Timestamp};
 
-use criterion::{criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
 
 fn import_audio_clip(e: &mut Engine) -> StoredAudioClipKey {
     e.import_audio_clip(Path::new(concat!(
@@ -32,6 +32,43 @@ fn criterion_benchmark(c: &mut Criterion) {
             p.poll();
         });
     });
+
+    // Run with and without `--features f64` to compare the internal sample formats,
+    // since Criterion compares each run to the previous one
+    let mut group = c.benchmark_group("Mixer tracks");
+    for tracks in [4, 16, 64] {
+        for threads in [1, 4] {
+            group.bench_with_input(
+                BenchmarkId::new(format!("{threads} threads"), tracks),
+                &tracks,
+                |b, &tracks| {
+                    let (mut e, mut p) = Engine::dummy_with_processor();
+                    e.set_mixer_threads(threads);
+
+                    let ck = import_audio_clip(&mut e);
+                    for at in e.add_audio_tracks(tracks).unwrap().collect::<Vec<_>>() {
+                        e.add_audio_clip(
+                            e.audio_timeline_track_key(at).unwrap(),
+                            ck,
+                            Timestamp::zero(),
+                            None,
+                        )
+                        .unwrap();
+                    }
+                    e.set_loop_region(Some((Timestamp::zero(), Timestamp::from_beats(4))))
+                        .unwrap();
+                    e.play().unwrap();
+
+                    let mut data = vec![0.0_f32; 512 * 2];
+                    b.iter(|| {
+                        p.poll();
+                        p.output(&mut data);
+                    });
+                },
+            );
+        }
+    }
+    group.finish();
 }
 
 criterion_group! {
@@ -1,60 +1,113 @@
 use core::sync::atomic::Ordering;
 use cpal::traits::{DeviceTrait, StreamTrait};
 use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::cmp::min;
 use std::collections::HashMap;
-use std::collections::HashSet;
 use std::error::Error;
 use std::fmt::Debug;
 use std::fmt::Display;
 use std::iter::zip;
-use std::panic;
-use std::path::Path;
+use std::num::NonZeroU32;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::AtomicBool;
 use std::sync::mpsc::sync_channel;
-use std::sync::Arc;
-use std::thread::{self, JoinHandle};
-use std::time::Duration;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle, Thread};
+use std::time::{Duration, Instant};
 
 mod components;
 pub mod config;
 pub mod error;
 mod info;
 mod processor;
+mod save;
 mod utils;
 
-use crate::engine::utils::panic_msg;
+use crate::engine::utils::{panic_msg, AtomicF32, MovingAverage};
+pub use utils::{amplitude_to_db, db_to_amplitude, rms};
 
-pub use components::audio_clip_store::{ImportError, InvalidStoredAudioClipError};
-pub use components::mixer::{InvalidMixerTrackError, MixerTrackOverflowError};
+use components::audio_clip_store::DecodedClips;
+pub use components::audio_clip_store::{
+    ClipOverflowError, ImportError, ImportHandle, InvalidStoredAudioClipError,
+};
+pub use components::effect::compressor::{Compressor, CompressorSettings};
+pub use components::effect::delay::{Delay, DelaySettings, DelayTime, MAX_DELAY};
+pub use components::effect::{
+    EffectInterface, EffectKey, EffectOverflowError, EffectState, InvalidEffectError,
+};
+pub use components::limiter::LimiterSettings;
+pub use components::loudness_meter::LoudnessReading;
+pub use components::mixer::{
+    AddSendError, InvalidMixerTrackError, InvalidSendError, MixerTrackOverflowError,
+    SetSidechainError,
+};
+pub use components::noise::{NoiseSettings, NoiseType};
+pub use components::output_capture::StartOutputCaptureError;
+pub use components::recorder::WavFormat;
+use components::recorder::{recorder, Recorder, RecorderInput, Recording};
+pub use components::saturation::SaturationSettings;
+pub use components::stored_audio_clip::Downmix;
 pub use components::stored_audio_clip::StoredAudioClip;
 pub use components::stored_audio_clip::StoredAudioClipKey;
+pub use components::stored_audio_clip::{ImportError as FileImportError, MAX_SAMPLE_RATE};
+pub use components::test_tone::{TestToneSettings, Waveform};
 pub use components::timeline::AudioClip;
 pub use components::timeline::AudioClipKey;
 pub use components::timeline::AudioClipReconstructionError;
 pub use components::timeline::AudioClipState;
+pub use components::timeline::EndBehavior;
 pub use components::timeline::InvalidAudioClipError;
 pub use components::timeline::InvalidAudioClipsError;
+pub use components::timeline::InvalidLoopRegionError;
+pub use components::timeline::InvalidTempoChangeError;
 pub use components::timeline::MoveAudioClipToTrackError;
 pub use components::timeline::Timestamp;
 pub use components::timeline::{
-    AddClipError, InvalidTimelineTrackError, MoveAudioClipError, TimelineTrackKey,
-    TimelineTrackOverflowError, TimelineTrackState,
+    AddClipError, CrossfadeError, DeleteTimeError, InvalidTimelineTrackError, MergeAudioClipsError,
+    MoveAudioClipError, OverlapPolicy, RemoveStoredAudioClipError, RemoveTempoChangeError,
+    ReverseAudioClipError, SetOverlapPolicyError, SplitAudioClipError, TempoOverlapError,
+    TimelineTrackKey, TimelineTrackOverflowError, TimelineTrackState,
+};
+pub use components::timeline::{Direction, Tempo, TempoMap};
+pub use components::timeline::{
+    GridDivision, InvalidTimeSignatureChangeError, InvalidTimeSignatureError, TimeSignature,
+    TimeSignatureMap, MAX_DENOMINATOR,
 };
+pub use components::timeline::{InvalidMarkerError, Marker, MarkerKey, MarkerOverflowError};
+pub use components::timeline::{MAX_PITCH, MAX_STRETCH, MIN_STRETCH};
+pub use components::MixPoint;
 pub use components::MixerTrack;
 pub use components::{MixerTrackKey, MixerTrackState};
-use config::{Config, SampleFormat};
+use config::{Config, DitherMode, Host, ResampleQuality, SampleFormat};
 use config::{SampleFormatFloat, SampleFormatInt, SampleFormatIntUnsigned};
-use processor::{processor, Processor, ProcessorInterface, ProcessorState};
+pub use processor::RenderIter;
+use processor::{processor, render, render_iter, Processor, ProcessorInterface, ProcessorState};
+pub use save::LoadStateError;
 
+use self::utils::denormals::FlushDenormals;
+use self::utils::dither::Dither;
 use self::utils::key_generator::key_type;
 use self::utils::key_generator::KeyGenerator;
+pub use self::utils::ringbuffer::EventQueueStats;
 
 /// Internally used sample format.
-type Sample = f32;
+#[cfg(not(feature = "f64"))]
+pub(crate) type Sample = f32;
+/// Internally used sample format.
+#[cfg(feature = "f64")]
+pub(crate) type Sample = f64;
 /// Internally used channel count.
 const CHANNELS: usize = 2;
-/// Biggest possible requested buffer size.
+/// Default for [`Config::max_buffer_size`].
 const MAX_BUFFER_SIZE_DEFAULT: usize = 1056;
+/// Default for [`Config::parameter_smoothing`].
+const PARAMETER_SMOOTHING_DEFAULT: f32 = 0.005;
+/// Default for [`Config::max_events_per_poll`].
+pub const MAX_EVENTS_PER_POLL_DEFAULT: usize = 256;
+/// Number of buffers the processing load is averaged over.
+const LOAD_WINDOW: usize = 32;
 // CHANNELS and MAX_BUFFER_SIZE_DEFAULT are both usize, because they are mostly used for initializing and indexing Vec's.
 
 key_type!(pub struct AudioTrackKey(u32));
@@ -62,21 +115,147 @@ key_type!(pub struct AudioTrackKey(u32));
 struct StartedStream {
     stopped_flag: Arc<AtomicBool>,
     join_handle: JoinHandle<()>,
+    stream_status: Arc<StreamStatus>,
+    processing_load: Arc<AtomicF32>,
     processor_interface: ProcessorInterface,
+    /// Is `None` if no input device is configured.
+    recorder: Option<Recorder>,
     import_errors: Vec<ImportError>,
 }
 
+/// Why the stream stopped, if it has, shared between the engine and the stream thread.
+#[derive(Default)]
+struct StreamStatus {
+    /// Set once the audio thread has panicked, see [`Engine::is_stream_dead`].
+    dead: AtomicBool,
+    error: Mutex<Option<StreamError>>,
+    /// The payload of the panic that killed the audio thread, until it is turned into [`StreamError::Panicked`].
+    panic: Mutex<Option<Box<dyn Any + Send>>>,
+}
+impl StreamStatus {
+    /// Leave `err` for [`Engine::stream_error`].
+    ///
+    /// Only the first error is kept, since any following ones are likely caused by it.
+    fn report(&self, err: StreamError) {
+        let mut error = lock(&self.error);
+        if error.is_none() && !self.dead.load(Ordering::Acquire) {
+            *error = Some(err);
+        }
+    }
+
+    /// Mark the audio thread as dead after it has panicked.
+    ///
+    /// This may be called on the audio thread, so the payload is neither formatted nor dropped here,
+    /// but kept until [`Self::error`] turns it into a message.
+    /// The allocation of the payload itself can not be avoided, since that is done by the panic.
+    fn report_panic(&self, payload: Box<dyn Any + Send>) {
+        if !self.dead.swap(true, Ordering::AcqRel) {
+            *lock(&self.panic) = Some(payload);
+        }
+    }
+
+    fn is_dead(&self) -> bool {
+        self.dead.load(Ordering::Acquire)
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.is_dead() || lock(&self.error).is_some()
+    }
+
+    fn error(&self) -> Option<StreamError> {
+        let mut error = lock(&self.error);
+        if error.is_none() {
+            if let Some(payload) = lock(&self.panic).take() {
+                *error = Some(StreamError::Panicked(panic_msg(payload)));
+            }
+        }
+        error.clone()
+    }
+}
+
+/// Lock a mutex of the [`StreamStatus`].
+///
+/// The lock is recovered if it has been poisoned, since the error is often the result of a panic.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Passed to the callbacks of the streams,
+/// to stop the stream thread and leave the error for [`Engine::stream_error`].
+#[derive(Clone)]
+struct ErrorReporter {
+    status: Arc<StreamStatus>,
+    stream_thread: Thread,
+}
+impl ErrorReporter {
+    fn report(&self, err: StreamError) {
+        self.status.report(err);
+        self.stream_thread.unpark();
+    }
+
+    fn report_panic(&self, payload: Box<dyn Any + Send>) {
+        self.status.report_panic(payload);
+        self.stream_thread.unpark();
+    }
+}
+
+/// Times the processing of each buffer against the duration of the buffer,
+/// and leaves the smoothed fraction for [`Engine::processing_load`].
+struct LoadMeter {
+    sample_rate: u32,
+    average: MovingAverage,
+    load: Arc<AtomicF32>,
+}
+impl LoadMeter {
+    fn new(sample_rate: u32) -> (Self, Arc<AtomicF32>) {
+        let load = Arc::new(AtomicF32::new(0.0));
+        (
+            LoadMeter {
+                sample_rate,
+                average: MovingAverage::new(0.0, LOAD_WINDOW),
+                load: Arc::clone(&load),
+            },
+            load,
+        )
+    }
+
+    /// Run `process`, which produces a buffer of `buffer_size` frames.
+    fn measure(&mut self, buffer_size: usize, process: impl FnOnce()) {
+        let start = Instant::now();
+        process();
+        let elapsed = start.elapsed().as_secs_f32();
+
+        if buffer_size != 0 {
+            let duration = buffer_size as f32 / self.sample_rate as f32;
+            self.average.push(elapsed / duration);
+            self.load.store(self.average.average(), Ordering::Relaxed);
+        }
+    }
+}
+
 /// The Adae audio engine.
 pub struct Engine {
     /// Signal whether the stream should stop.
     stopped: Arc<AtomicBool>,
     join_handle: Option<JoinHandle<()>>,
+    /// Why the stream stopped, if it has.
+    stream_status: Arc<StreamStatus>,
+    /// Fraction of the time available for each buffer that is spent processing it.
+    processing_load: Arc<AtomicF32>,
 
     config: Config,
     processor_interface: ProcessorInterface,
 
+    /// Is `None` if no input device is configured.
+    recorder: Option<Recorder>,
+    armed_track: Option<AudioTrackKey>,
+    /// The position of the playhead when the current recording was started.
+    recording_start: Option<Timestamp>,
+
     key_generator: KeyGenerator<AudioTrackKey>,
     audio_tracks: HashMap<AudioTrackKey, (TimelineTrackKey, MixerTrackKey)>,
+    /// The keys of [`Self::audio_tracks`], in the order they are presented in.
+    audio_track_order: Vec<AudioTrackKey>,
 }
 impl Engine {
     /// Create a clean, empty instance of the engine with the default config.
@@ -92,44 +271,116 @@ impl Engine {
     }
 
     /// Create a new instance of the engine from the given state with the given config.
+    ///
+    /// This blocks until the stream has started, which can take a while on some machines.
+    /// If this is undesirable, see [`Engine::new_async`].
     pub fn new(
         config: Config,
         state: &EngineState,
     ) -> Result<(Self, impl Iterator<Item = ImportError>), InvalidConfigError> {
+        let (tx, rx) = sync_channel(1);
+        Self::new_async(config, state, move |res| {
+            // The receiver is only dropped if this has already been called
+            let _ = tx.send(res);
+        });
+        let (engine, import_errors) = rx
+            .recv()
+            .expect("Engine startup was abandoned without a result")?;
+
+        Ok((engine, import_errors.into_iter()))
+    }
+
+    /// Like [`Engine::new`], but returns immediately,
+    /// while the stream is started and the stored audio clips in the state are imported on other threads.
+    ///
+    /// Once the stream has either started or failed, `on_ready` is called from one of those threads
+    /// with the engine and any import errors, or the error that prevented it from starting.
+    pub fn new_async(
+        config: Config,
+        state: &EngineState,
+        on_ready: impl FnOnce(Result<(Self, Vec<ImportError>), InvalidConfigError>) + Send + 'static,
+    ) {
+        let audio_tracks = state.audio_tracks.clone();
+        let engine_config = config.clone();
+        Self::start_stream(&config, state, DecodedClips::new(), move |res| {
+            on_ready(res.map(|started_stream| {
+                Self::from_started_stream(engine_config, started_stream, &audio_tracks)
+            }))
+        });
+    }
+
+    fn from_started_stream(
+        config: Config,
+        started_stream: StartedStream,
+        audio_tracks: &[(AudioTrackKey, TimelineTrackKey, MixerTrackKey)],
+    ) -> (Self, Vec<ImportError>) {
         let StartedStream {
             stopped_flag,
             join_handle,
+            stream_status,
+            processing_load,
             processor_interface,
+            recorder,
             import_errors,
-        } = Self::start_stream(&config, state)?;
+        } = started_stream;
 
         let engine = Engine {
             stopped: stopped_flag,
             join_handle: Some(join_handle),
+            stream_status,
+            processing_load,
             config,
             processor_interface,
-            key_generator: KeyGenerator::from_iter(
-                state.audio_tracks.iter().map(|(key, _, _)| *key),
-            ),
-            audio_tracks: HashMap::from_iter(state.audio_tracks.iter().map(
+            recorder,
+            armed_track: None,
+            recording_start: None,
+            key_generator: KeyGenerator::from_iter(audio_tracks.iter().map(|(key, _, _)| *key)),
+            audio_tracks: HashMap::from_iter(audio_tracks.iter().map(
                 |(key, timeline_track_key, mixer_track_key)| {
                     (*key, (*timeline_track_key, *mixer_track_key))
                 },
             )),
+            audio_track_order: audio_tracks.iter().map(|(key, _, _)| *key).collect(),
         };
 
-        Ok((engine, import_errors.into_iter()))
+        (engine, import_errors)
+    }
+
+    /// Like [`Engine::start_stream`], but blocks until the stream has started.
+    fn start_stream_blocking(
+        config: &Config,
+        state: &EngineState,
+        decoded_clips: DecodedClips,
+    ) -> Result<StartedStream, InvalidConfigError> {
+        let (tx, rx) = sync_channel(1);
+        Self::start_stream(config, state, decoded_clips, move |res| {
+            let _ = tx.send(res);
+        });
+        rx.recv()
+            .expect("Stream startup was abandoned without a result")
     }
 
-    /// Starts a stream with the given config and state.
+    /// Starts a stream with the given config and state, without waiting for it to start.
     ///
-    /// Returns a the stop flag, the join handle, the processor interface and a (possibly empty) list of import errors.
+    /// Once the stream has started or failed, `on_started` is called with the stop flag, the join handle,
+    /// the processor interface and a (possibly empty) list of import errors, or the error.
+    /// This may happen on the calling thread if the config is rejected right away.
+    ///
+    /// Stored clips in `decoded_clips` are reused rather than imported again.
     fn start_stream(
         config: &Config,
         state: &EngineState,
-    ) -> Result<StartedStream, InvalidConfigError> {
+        decoded_clips: DecodedClips,
+        on_started: impl FnOnce(Result<StartedStream, InvalidConfigError>) + Send + 'static,
+    ) {
         let device = config.output_device.clone();
         let output_config = config.output_config.clone();
+        if output_config.channels != 1 && usize::from(output_config.channels) != CHANNELS {
+            on_started(Err(InvalidConfigError::UnsupportedChannels(
+                output_config.channels,
+            )));
+            return;
+        }
         let stream_config = cpal::StreamConfig {
             channels: output_config.channels,
             sample_rate: cpal::SampleRate(output_config.sample_rate),
@@ -144,10 +395,47 @@ impl Engine {
         let max_buffer_size = match output_config.buffer_size {
             // If usize is smaller than our buffersize we have bigger problems
             Some(size) => size.try_into().expect("Buffer size overflows usize"),
-            None => MAX_BUFFER_SIZE_DEFAULT,
+            None => config.max_buffer_size,
+        };
+        let processor_state = state.processor.clone();
+        let dither = Dither::new(config.dither, &output_config.sample_format);
+        let resample_quality = config.resample_quality;
+        let parameter_smoothing = config.parameter_smoothing;
+        let max_events_per_poll = config.max_events_per_poll;
+        let flush_denormals = config.flush_denormals;
+
+        let (recorder, input) = match &config.input_device {
+            Some(input_device) => {
+                let input_config = match &config.input_config {
+                    Some(input_config) => input_config.clone(),
+                    None => match input_device.default_config() {
+                        Ok(input_config) => input_config,
+                        Err(_) => {
+                            on_started(Err(InvalidConfigError::DeviceNotAvailable));
+                            return;
+                        }
+                    },
+                };
+                let input_stream_config = cpal::StreamConfig {
+                    channels: input_config.channels,
+                    sample_rate: cpal::SampleRate(input_config.sample_rate),
+                    buffer_size: match input_config.buffer_size {
+                        Some(size) => cpal::BufferSize::Fixed(size),
+                        None => cpal::BufferSize::Default,
+                    },
+                };
+                let (recorder, recorder_input) =
+                    recorder(input_config.channels, input_config.sample_rate);
+                let input = (
+                    input_device.clone(),
+                    input_config.sample_format,
+                    input_stream_config,
+                    recorder_input,
+                );
+                (Some(recorder), Some(input))
+            }
+            None => (None, None),
         };
-        let (processor_interface, processor, import_errors) =
-            processor(&state.processor, &stream_config, max_buffer_size);
 
         use SampleFormat::*;
         use SampleFormatFloat::*;
@@ -176,24 +464,90 @@ impl Engine {
 
         let stopped1 = Arc::new(AtomicBool::new(false));
         let stopped2 = Arc::clone(&stopped1);
+        let stream_status1 = Arc::new(StreamStatus::default());
+        let stream_status2 = Arc::clone(&stream_status1);
+        let (load_meter, processing_load) = LoadMeter::new(output_config.sample_rate);
         let join_handle = thread::spawn(move || {
             // Since cpal::Stream doesn't implement the Send trait, it has to live in this thread.
 
-            let res = create_stream(&device.raw().unwrap(), &stream_config, processor);
+            let error_reporter = ErrorReporter {
+                status: Arc::clone(&stream_status2),
+                stream_thread: thread::current(),
+            };
+
+            // Importing the stored audio clips can take a while, so this is also done here
+            let (processor_interface, mut processor, import_errors) = processor(
+                &processor_state,
+                &stream_config,
+                max_buffer_size,
+                resample_quality,
+                parameter_smoothing,
+                &decoded_clips,
+            );
+            // Dropped here rather than on the audio thread, in case this held the last reference
+            drop(decoded_clips);
+            processor.set_dither(dither);
+            processor.set_max_events_per_poll(max_events_per_poll);
+
+            let res = device
+                .raw()
+                .map_err(|_| InvalidConfigError::DeviceNotAvailable)
+                .and_then(|device| {
+                    create_stream(
+                        &device,
+                        &stream_config,
+                        processor,
+                        load_meter,
+                        error_reporter.clone(),
+                        flush_denormals,
+                    )
+                });
+            let res = res.and_then(|stream| {
+                let input_stream = match input {
+                    Some((device, sample_format, stream_config, recorder_input)) => {
+                        let device = device
+                            .raw()
+                            .map_err(|_| InvalidConfigError::DeviceNotAvailable)?;
+                        Some(Self::create_input_stream(
+                            &device,
+                            &sample_format,
+                            &stream_config,
+                            recorder_input,
+                            error_reporter,
+                        )?)
+                    }
+                    None => None,
+                };
+
+                stream.play().map_err(|e| match e {
+                    cpal::PlayStreamError::DeviceNotAvailable => {
+                        InvalidConfigError::DeviceNotAvailable
+                    }
+                    _ => InvalidConfigError::Other,
+                })?;
+                if let Some(input_stream) = &input_stream {
+                    input_stream.play().map_err(|e| match e {
+                        cpal::PlayStreamError::DeviceNotAvailable => {
+                            InvalidConfigError::DeviceNotAvailable
+                        }
+                        _ => InvalidConfigError::Other,
+                    })?;
+                }
+
+                Ok((stream, input_stream))
+            });
 
-            let stream = match res {
-                Ok(stream) => {
-                    tx.send(None).unwrap();
-                    stream
+            let (stream, input_stream) = match res {
+                Ok(streams) => {
+                    tx.send(Ok((processor_interface, import_errors))).unwrap();
+                    streams
                 }
                 Err(e) => {
-                    tx.send(Some(e)).unwrap();
+                    tx.send(Err(e)).unwrap();
                     return;
                 }
             };
 
-            stream.play().unwrap();
-
             println!(
                 "Host: {}\nDevice: {}\nChannels: {}\nSample format: {}\nSample rate: {}\nBuffer size: {}",
                 device.host().name(),
@@ -205,29 +559,37 @@ impl Engine {
 
             );
 
-            while !stopped2.load(Ordering::Acquire) {
+            // The stream is also stopped if it fails, e.g. because the device is disconnected
+            while !stopped2.load(Ordering::Acquire) && !stream_status2.is_stopped() {
                 // Parking the thread is more efficient than spinning, but can risk unparking seemingly randomly, hence the 'stopped' flag.
                 thread::park();
             }
 
             // Just to be explicit
+            drop(input_stream);
             drop(stream);
             println!("Stream terminated");
         });
 
-        let res = rx
-            .recv_timeout(Duration::from_secs(30))
-            .expect("Attempt to start stream timed out");
-
-        match res {
-            Some(e) => Err(e),
-            None => Ok(StartedStream {
-                stopped_flag: stopped1,
-                join_handle,
-                processor_interface,
-                import_errors,
-            }),
-        }
+        // Waits for the stream thread, which can not hand over its own join handle
+        thread::spawn(move || {
+            let res = match rx.recv() {
+                Ok(res) => res,
+                // The stream thread panicked before it was ready
+                Err(_) => Err(InvalidConfigError::Other),
+            };
+            on_started(
+                res.map(|(processor_interface, import_errors)| StartedStream {
+                    stopped_flag: stopped1,
+                    join_handle,
+                    stream_status: stream_status1,
+                    processing_load,
+                    processor_interface,
+                    recorder,
+                    import_errors,
+                }),
+            );
+        });
     }
 
     /// Create a cpal stream with the given sample type.
@@ -235,17 +597,103 @@ impl Engine {
         device: &cpal::Device,
         config: &cpal::StreamConfig,
         mut processor: Processor,
+        mut load_meter: LoadMeter,
+        error_reporter: ErrorReporter,
+        flush_denormals: bool,
     ) -> Result<cpal::Stream, InvalidConfigError> {
+        let channels = usize::from(config.channels);
+        let panic_reporter = error_reporter.clone();
+        // Once the processor has panicked, it may be left in any state, so it is not touched again
+        let mut panicked = false;
         device
             .build_output_stream(
                 config,
                 move |data: &mut [T], _info| {
+                    if panicked {
+                        data.fill(T::EQUILIBRIUM);
+                        return;
+                    }
+                    let _flush = FlushDenormals::new(flush_denormals);
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                        no_heap! {{
+                            load_meter.measure(data.len() / channels, || {
+                                processor.poll();
+                                processor.output(data);
+                            });
+                        }}
+                    }));
+                    if let Err(e) = result {
+                        panicked = true;
+                        data.fill(T::EQUILIBRIUM);
+                        panic_reporter.report_panic(e);
+                    }
+                },
+                move |err| error_reporter.report(err.into()),
+                None,
+            )
+            .map_err(|e| match e {
+                cpal::BuildStreamError::DeviceNotAvailable => {
+                    InvalidConfigError::DeviceNotAvailable
+                }
+                cpal::BuildStreamError::StreamConfigNotSupported => InvalidConfigError::Other,
+                cpal::BuildStreamError::InvalidArgument => InvalidConfigError::Other,
+
+                e => panic!("Stream could not be created: {e}"),
+            })
+    }
+
+    /// Create a cpal input stream, passing everything it captures on to `recorder_input`.
+    fn create_input_stream(
+        device: &cpal::Device,
+        sample_format: &SampleFormat,
+        config: &cpal::StreamConfig,
+        recorder_input: RecorderInput,
+        error_reporter: ErrorReporter,
+    ) -> Result<cpal::Stream, InvalidConfigError> {
+        use SampleFormat::*;
+        use SampleFormatFloat::*;
+        use SampleFormatInt::*;
+        use SampleFormatIntUnsigned::*;
+        let create_input_stream = match sample_format {
+            Int(s) => match s {
+                I8 => Self::create_input_stream_of_type::<i8>,
+                I16 => Self::create_input_stream_of_type::<i16>,
+                I32 => Self::create_input_stream_of_type::<i32>,
+                I64 => Self::create_input_stream_of_type::<i64>,
+            },
+            IntUnsigned(s) => match s {
+                U8 => Self::create_input_stream_of_type::<u8>,
+                U16 => Self::create_input_stream_of_type::<u16>,
+                U32 => Self::create_input_stream_of_type::<u32>,
+                U64 => Self::create_input_stream_of_type::<u64>,
+            },
+            Float(s) => match s {
+                F32 => Self::create_input_stream_of_type::<f32>,
+                F64 => Self::create_input_stream_of_type::<f64>,
+            },
+        };
+        create_input_stream(device, config, recorder_input, error_reporter)
+    }
+
+    /// Create a cpal input stream with the given sample type.
+    fn create_input_stream_of_type<T: 'static + cpal::SizedSample>(
+        device: &cpal::Device,
+        config: &cpal::StreamConfig,
+        mut recorder_input: RecorderInput,
+        error_reporter: ErrorReporter,
+    ) -> Result<cpal::Stream, InvalidConfigError>
+    where
+        Sample: cpal::FromSample<T>,
+    {
+        device
+            .build_input_stream(
+                config,
+                move |data: &[T], _info| {
                     no_heap! {{
-                        processor.poll();
-                        processor.output(data);
+                        recorder_input.push(data);
                     }}
                 },
-                |err| panic!("{err}"),
+                move |err| error_reporter.report(err.into()),
                 None,
             )
             .map_err(|e| match e {
@@ -278,14 +726,25 @@ impl Engine {
     /// Like [`Engine::dummy()`], but uses the given state instead of the default state.
     #[doc(hidden)]
     pub fn dummy_from_state(state: &EngineState) -> (Self, impl Iterator<Item = ImportError>) {
-        let (stopped, join_handle, processor_interface, import_errors) =
-            Self::start_dummy_stream(state);
+        let (
+            stopped,
+            join_handle,
+            stream_status,
+            processing_load,
+            processor_interface,
+            import_errors,
+        ) = Self::start_dummy_stream(state);
 
         let engine = Engine {
             stopped,
             join_handle: Some(join_handle),
+            stream_status,
+            processing_load,
             config: Config::dummy(),
             processor_interface,
+            recorder: None,
+            armed_track: None,
+            recording_start: None,
             key_generator: KeyGenerator::from_iter(
                 state.audio_tracks.iter().map(|(key, _, _)| *key),
             ),
@@ -294,6 +753,7 @@ impl Engine {
                     (*key, (*timeline_track_key, *mixer_track_key))
                 },
             )),
+            audio_track_order: state.audio_tracks.iter().map(|(key, _, _)| *key).collect(),
         };
 
         (engine, import_errors.into_iter())
@@ -328,13 +788,21 @@ impl Engine {
                 buffer_size: cpal::BufferSize::Default,
             },
             1024,
+            ResampleQuality::default(),
+            PARAMETER_SMOOTHING_DEFAULT,
+            &DecodedClips::new(),
         );
 
         let engine = Engine {
             stopped: Arc::new(AtomicBool::new(false)),
             join_handle: None,
+            stream_status: Arc::new(StreamStatus::default()),
+            processing_load: Arc::new(AtomicF32::new(0.0)),
             config: Config::dummy(),
             processor_interface,
+            recorder: None,
+            armed_track: None,
+            recording_start: None,
             key_generator: KeyGenerator::from_iter(
                 state.audio_tracks.iter().map(|(key, _, _)| *key),
             ),
@@ -343,6 +811,7 @@ impl Engine {
                     (*key, (*timeline_track_key, *mixer_track_key))
                 },
             )),
+            audio_track_order: state.audio_tracks.iter().map(|(key, _, _)| *key).collect(),
         };
 
         (engine, processor, import_errors.into_iter())
@@ -354,6 +823,8 @@ impl Engine {
     ) -> (
         Arc<AtomicBool>,
         JoinHandle<()>,
+        Arc<StreamStatus>,
+        Arc<AtomicF32>,
         ProcessorInterface,
         impl Iterator<Item = ImportError>,
     ) {
@@ -365,30 +836,48 @@ impl Engine {
                 buffer_size: cpal::BufferSize::Default,
             },
             1024,
+            ResampleQuality::default(),
+            PARAMETER_SMOOTHING_DEFAULT,
+            &DecodedClips::new(),
         );
 
         let mut data = vec![0.0; 2048];
+        let (mut load_meter, processing_load) = LoadMeter::new(48_000);
 
         let stopped1 = Arc::new(AtomicBool::new(false));
         let stopped2 = Arc::clone(&stopped1);
+        let stream_status1 = Arc::new(StreamStatus::default());
+        let stream_status2 = Arc::clone(&stream_status1);
         let join_handle = thread::spawn(move || {
             while !stopped2.load(Ordering::Acquire) {
-                let data = &mut data[..];
-                no_heap! {{
-                    processor.poll();
-                    processor.output(data);
-                }}
-                let data = &mut data[..1024];
-                no_heap! {{
-                    processor.poll();
-                    processor.output(data);
-                }}
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    let data = &mut data[..];
+                    no_heap! {{
+                        load_meter.measure(1024, || {
+                            processor.poll();
+                            processor.output(data);
+                        });
+                    }}
+                    let data = &mut data[..1024];
+                    no_heap! {{
+                        load_meter.measure(512, || {
+                            processor.poll();
+                            processor.output(data);
+                        });
+                    }}
+                }));
+                if let Err(e) = result {
+                    stream_status2.report_panic(e);
+                    return;
+                }
             }
         });
 
         (
             stopped1,
             join_handle,
+            stream_status1,
+            processing_load,
             processor_interface,
             import_errors.into_iter(),
         )
@@ -399,62 +888,310 @@ impl Engine {
         self.stopped.store(true, Ordering::Release);
         if let Some(h) = self.join_handle.take() {
             h.thread().unpark();
-            let r = h.join();
-            if let Err(e) = r {
-                let s = panic_msg(e);
-                panic!("Failed to terminate stream: {s}");
+            // A panic is kept as the stream error instead of being passed on,
+            // so the engine can still be dropped
+            if let Err(e) = h.join() {
+                self.stream_status.report_panic(e);
             }
         }
     }
 
+    /// List the hosts (audio backends) available on this platform,
+    /// from which devices and their supported configs can be discovered.
+    pub fn available_hosts() -> Vec<Host> {
+        Host::available().collect()
+    }
+
+    /// The error that caused the stream to stop, if any.
+    ///
+    /// Once this happens, e.g. because the output device has been disconnected,
+    /// no more audio is output until the stream is restarted with [`Engine::set_config`].
+    /// If the audio thread has panicked, see [`Engine::is_stream_dead`].
+    pub fn stream_error(&self) -> Option<StreamError> {
+        self.stream_status.error()
+    }
+
+    /// Whether the audio thread has panicked, see [`StreamError::Panicked`].
+    ///
+    /// Until the stream is restarted with [`Engine::set_config`], the transport, recording and output capture
+    /// return [`StreamDeadError`] or their own `StreamDead` variant, since they can not work without the audio thread.
+    /// Changes to the project itself still succeed, since they are kept by the engine and carried over by the restart.
+    pub fn is_stream_dead(&self) -> bool {
+        self.stream_status.is_dead()
+    }
+
+    fn check_stream(&self) -> Result<(), StreamDeadError> {
+        if self.is_stream_dead() {
+            Err(StreamDeadError)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The fraction of the time available for each buffer that is spent processing it,
+    /// averaged over the last few buffers.
+    ///
+    /// Once this approaches 1, the output is at risk of dropping out.
+    pub fn processing_load(&self) -> f32 {
+        self.processing_load.load(Ordering::Relaxed)
+    }
+
+    /// Whether the device has asked for more frames at once than the intermediate buffers have room for,
+    /// since the stream was started.
+    ///
+    /// The output is still correct, but is processed in parts, which is less efficient.
+    /// Passing a config with a bigger [`Config::max_buffer_size`] to [`Engine::set_config`] avoids this.
+    pub fn max_buffer_size_exceeded(&self) -> bool {
+        self.processor_interface.max_buffer_size_exceeded()
+    }
+
+    /// Counters of the changes to the timeline sent to the audio thread, since the stream was started.
+    ///
+    /// Changes are applied once the audio thread polls them, a limited number per buffer,
+    /// so [`EventQueueStats::pending`] grows while changes are made faster than that, or while the audio thread is stalled.
+    pub fn event_queue_stats(&self) -> EventQueueStats {
+        self.processor_interface.timeline.event_queue_stats()
+    }
+
     /// Get the config that is currently in use.
     pub fn config(&self) -> &Config {
         &self.config
     }
     /// Restart the engine with the given config.
     pub fn set_config(&mut self, config: Config) -> Result<(), InvalidConfigError> {
+        self.restart_stream(config, DecodedClips::new())
+    }
+    /// Restart the engine with only the buffer size changed, see [`OutputConfig::buffer_size`](config::OutputConfig::buffer_size).
+    ///
+    /// Unlike [`Engine::set_config`], the stored clips are not imported again,
+    /// except for those streamed from disk, so this is much faster for projects with many clips.
+    pub fn set_buffer_size(&mut self, buffer_size: Option<u32>) -> Result<(), InvalidConfigError> {
+        let mut config = self.config.clone();
+        config.output_config.buffer_size = buffer_size;
+        let decoded_clips = self.processor_interface.timeline.decoded_clips();
+        self.restart_stream(config, decoded_clips)
+    }
+    fn restart_stream(
+        &mut self,
+        config: Config,
+        decoded_clips: DecodedClips,
+    ) -> Result<(), InvalidConfigError> {
         let state = self.state();
+        let mixer_threads = self.mixer_threads();
 
         self.stop_stream();
 
         let StartedStream {
             stopped_flag,
             join_handle,
+            stream_status,
+            processing_load,
             processor_interface,
+            recorder,
             import_errors,
-        } = Self::start_stream(&config, &state)?;
+        } = Self::start_stream_blocking(&config, &state, decoded_clips)?;
 
         debug_assert!(import_errors.is_empty());
 
         self.stopped = stopped_flag;
         self.join_handle = Some(join_handle);
+        self.stream_status = stream_status;
+        self.processing_load = processing_load;
         self.processor_interface = processor_interface;
+        self.set_mixer_threads(mixer_threads);
+        // Any recording in progress is discarded
+        self.recorder = recorder;
+        self.recording_start = None;
 
         self.config = config;
 
         Ok(())
     }
 
-    /// Get the current BPM multiplied by 100.
+    /// Get the BPM at the start of the timeline multiplied by 100.
     ///
     /// For example, a return value of 12000 means 120 BPM.
     pub fn bpm_cents(&self) -> u16 {
         self.processor_interface.timeline.bpm_cents()
     }
 
+    /// Set the BPM at the start of the timeline multiplied by 100, which lasts until the first tempo change.
+    ///
+    /// Same as [`Engine::add_tempo_change`] at [`Timestamp::zero()`],
+    /// so the playhead stays at the same beat, while its position in samples changes.
+    /// Likewise, the tempo is left unchanged if this would make clips overlap.
+    pub fn set_bpm_cents(&mut self, bpm_cents: u16) -> Result<(), TempoOverlapError> {
+        self.processor_interface.timeline.set_bpm_cents(bpm_cents)
+    }
+
+    /// Get the tempo along the entire timeline.
+    pub fn tempo_map(&self) -> &TempoMap {
+        self.processor_interface.timeline.tempo_map()
+    }
+
+    /// Change the tempo to `bpm_cents` from `position` and until the next tempo change.
+    ///
+    /// If a change already exists at `position` it is replaced.
+    /// A change at [`Timestamp::zero()`] sets the initial tempo.
+    ///
+    /// The playhead keeps its position on the timeline.
+    /// Clips without a set length keep their length in samples, so their ends move on the timeline.
+    /// If this would make any of them overlap other clips in a way their track does not allow,
+    /// the tempo is left unchanged, and a [`TempoOverlapError`] listing the clips is returned.
+    pub fn add_tempo_change(
+        &mut self,
+        position: Timestamp,
+        bpm_cents: u16,
+    ) -> Result<(), TempoOverlapError> {
+        self.processor_interface
+            .timeline
+            .add_tempo_change(position, bpm_cents)
+    }
+
+    /// Remove the tempo change at `position`, such that the preceding tempo continues until the next change.
+    ///
+    /// The initial tempo at [`Timestamp::zero()`] cannot be removed.
+    /// Like [`Engine::add_tempo_change`], this fails if it would make clips overlap.
+    pub fn remove_tempo_change(
+        &mut self,
+        position: Timestamp,
+    ) -> Result<(), RemoveTempoChangeError> {
+        self.processor_interface
+            .timeline
+            .remove_tempo_change(position)
+    }
+
+    /// Get the time signature along the entire timeline.
+    ///
+    /// Use [`Timestamp::to_bars_beats`] with this to find the bar and beat of a position.
+    pub fn time_signatures(&self) -> &TimeSignatureMap {
+        self.processor_interface.timeline.time_signatures()
+    }
+
+    /// The lines of a grid for e.g. drawing a ruler, from `from` and until, but not including, `to`.
+    ///
+    /// The grid follows the time signature, starting over at each change.
+    /// The lines are positions on the timeline, so they follow the tempo as well,
+    /// and can be converted to seconds with [`Timestamp::seconds`] if needed.
+    ///
+    /// The lines are produced lazily, so the range can be as long as needed, up to [`Timestamp::infinity()`].
+    pub fn beat_grid(
+        &self,
+        from: Timestamp,
+        to: Timestamp,
+        division: GridDivision,
+    ) -> impl Iterator<Item = Timestamp> + '_ {
+        self.time_signatures().grid(from, to, division)
+    }
+
+    /// Change the time signature to `numerator`/`denominator` from `position` and until the next time signature change.
+    ///
+    /// If a change already exists at `position` it is replaced.
+    /// A change at [`Timestamp::zero()`] sets the initial time signature, which is 4/4 by default.
+    /// Bars are counted from `position`, so it should usually be the start of a bar.
+    ///
+    /// Unless [`Engine::set_metronome_beats_per_bar`] is set, the metronome accents the first beat of each bar.
+    pub fn set_time_signature(
+        &mut self,
+        position: Timestamp,
+        numerator: u32,
+        denominator: u32,
+    ) -> Result<(), InvalidTimeSignatureError> {
+        let time_signature = TimeSignature::new(numerator, denominator)?;
+        self.processor_interface
+            .timeline
+            .set_time_signature(position, time_signature);
+        Ok(())
+    }
+
+    /// Remove the time signature change at `position`, such that the preceding time signature continues until the next change.
+    ///
+    /// The initial time signature at [`Timestamp::zero()`] cannot be removed.
+    pub fn remove_time_signature(
+        &mut self,
+        position: Timestamp,
+    ) -> Result<(), InvalidTimeSignatureChangeError> {
+        self.processor_interface
+            .timeline
+            .remove_time_signature(position)
+    }
+
     /// Play timeline from the current playhead position.
-    pub fn play(&mut self) {
-        self.processor_interface.timeline.play()
+    ///
+    /// If a count-in is set, see [`Engine::set_count_in`], the playhead only starts moving after it.
+    pub fn play(&mut self) -> Result<(), StreamDeadError> {
+        self.check_stream()?;
+        self.processor_interface.timeline.play();
+        Ok(())
     }
     /// Pause playback of the timeline, without resetting the playhead position.
-    pub fn pause(&mut self) {
-        self.processor_interface.timeline.pause()
+    pub fn pause(&mut self) -> Result<(), StreamDeadError> {
+        self.check_stream()?;
+        self.processor_interface.timeline.pause();
+        Ok(())
+    }
+    /// Whether the timeline is currently playing, as opposed to paused.
+    pub fn is_playing(&self) -> bool {
+        self.processor_interface.timeline.is_playing()
+    }
+    /// Whether playback has started, but is still counting in, see [`Engine::set_count_in`].
+    ///
+    /// This reports the state of the audio thread, so it might be slightly delayed after [`Engine::play()`].
+    /// A recording should be started once this turns false, to line up with the playhead.
+    pub fn is_counting_in(&self) -> bool {
+        self.processor_interface.timeline.is_counting_in()
     }
     /// Set the current playhead position.
     ///
     /// This can be done both while the timeline is playing and while it is paused.
-    pub fn jump_to(&mut self, position: Timestamp) {
-        self.processor_interface.timeline.jump_to(position)
+    pub fn jump_to(&mut self, position: Timestamp) -> Result<(), StreamDeadError> {
+        self.check_stream()?;
+        self.processor_interface.timeline.jump_to(position);
+        Ok(())
+    }
+    /// Play a short snippet of `duration` from `position`, e.g. to check an edit point,
+    /// after which the playhead returns to where it was.
+    ///
+    /// This leaves the transport alone, so [`Engine::is_playing()`] stays false, and the loop region is not respected during the snippet.
+    /// It is ignored while the timeline is playing.
+    /// Starting playback during the snippet ends it, and plays from where the playhead was before the snippet,
+    /// while jumping during the snippet ends it at the new position.
+    pub fn preview_at(
+        &mut self,
+        position: Timestamp,
+        duration: Duration,
+    ) -> Result<(), StreamDeadError> {
+        self.check_stream()?;
+        self.processor_interface
+            .timeline
+            .preview_at(position, duration);
+        Ok(())
+    }
+    /// Jump `delta` forward or backward from the current playhead position.
+    ///
+    /// Jumping backward stops at the start of the timeline.
+    /// The jump is relative to [`Engine::playhead_position()`], so it is subject to the same delay.
+    pub fn jump_relative(
+        &mut self,
+        delta: Timestamp,
+        direction: Direction,
+    ) -> Result<(), StreamDeadError> {
+        self.check_stream()?;
+        self.processor_interface
+            .timeline
+            .jump_relative(delta, direction);
+        Ok(())
+    }
+    /// Jump a whole number of beats from the current playhead position, backward if `beats` is negative.
+    ///
+    /// See [`Engine::jump_relative()`].
+    pub fn nudge(&mut self, beats: i32) -> Result<(), StreamDeadError> {
+        let direction = if beats < 0 {
+            Direction::Backward
+        } else {
+            Direction::Forward
+        };
+        self.jump_relative(Timestamp::from_beats(beats.unsigned_abs()), direction)
     }
     /// Get the current playhead position.
     ///
@@ -462,31 +1199,369 @@ impl Engine {
     pub fn playhead_position(&mut self) -> Timestamp {
         self.processor_interface.timeline.playhead_position()
     }
-
-    /// Immutably borrow the master track, which is always present on the mixer.
-    pub fn master(&self) -> &MixerTrack {
-        self.processor_interface.mixer.master()
+    /// Get the current playhead position in frames at the output sample rate.
+    ///
+    /// Unlike [`Engine::playhead_position()`], this does not depend on the tempo, so it is sample-accurate.
+    /// It is subject to the same delay.
+    pub fn playhead_samples(&self) -> u64 {
+        self.processor_interface.timeline.playhead_samples()
     }
-    /// Mutably borrow the master track, which is always present on the mixer.
-    pub fn master_mut(&mut self) -> &mut MixerTrack {
-        self.processor_interface.mixer.master_mut()
+    /// Get the current playhead position in seconds from the start of the timeline.
+    ///
+    /// Like [`Engine::playhead_samples()`], this does not depend on the tempo.
+    pub fn playhead_seconds(&self) -> f64 {
+        self.processor_interface.timeline.playhead_seconds()
     }
 
-    /// Get the keys of all audio tracks currently in the engine.
-    pub fn audio_tracks(&self) -> impl Iterator<Item = AudioTrackKey> + '_ {
-        self.audio_tracks.keys().copied()
+    /// Get the region that playback loops within, if any.
+    pub fn loop_region(&self) -> Option<(Timestamp, Timestamp)> {
+        self.processor_interface.timeline.loop_region()
+    }
+    /// Set the start and end of the region that playback should loop within, or `None` to stop looping.
+    ///
+    /// When the playhead reaches the end of the region, it wraps back around to the start.
+    /// The loop only takes effect while the playhead is before the end of the region,
+    /// so playback that has already passed it will continue as normal.
+    ///
+    /// Returns an error if the region does not end after it starts.
+    pub fn set_loop_region(
+        &mut self,
+        loop_region: Option<(Timestamp, Timestamp)>,
+    ) -> Result<(), InvalidLoopRegionError> {
+        self.processor_interface
+            .timeline
+            .set_loop_region(loop_region)
     }
 
-    /// Check if the engine contains an audio track with the given key.
-    pub fn has_audio_track(&self, key: AudioTrackKey) -> bool {
-        self.audio_tracks.contains_key(&key)
+    /// Add a named marker at `position` on the timeline.
+    ///
+    /// Markers have no effect on playback, but the playhead can jump between them.
+    pub fn add_marker(
+        &mut self,
+        position: Timestamp,
+        name: String,
+    ) -> Result<MarkerKey, MarkerOverflowError> {
+        self.processor_interface.timeline.add_marker(position, name)
+    }
+    /// Remove the marker, returning it.
+    pub fn remove_marker(&mut self, key: MarkerKey) -> Result<Marker, InvalidMarkerError> {
+        self.processor_interface.timeline.remove_marker(key)
+    }
+    /// Get all markers on the timeline, in order of position.
+    pub fn markers(&self) -> impl Iterator<Item = &Marker> + '_ {
+        self.processor_interface.timeline.markers()
+    }
+    /// Jump to the first marker after the current playhead position.
+    ///
+    /// Returns the key of the marker, or `None` if there are no markers after the playhead, in which case it stays in place.
+    pub fn jump_to_next_marker(&mut self) -> Result<Option<MarkerKey>, StreamDeadError> {
+        self.check_stream()?;
+        Ok(self.processor_interface.timeline.jump_to_next_marker())
+    }
+    /// Jump to the last marker before the current playhead position.
+    ///
+    /// Returns the key of the marker, or `None` if there are no markers before the playhead, in which case it stays in place.
+    pub fn jump_to_previous_marker(&mut self) -> Result<Option<MarkerKey>, StreamDeadError> {
+        self.check_stream()?;
+        Ok(self.processor_interface.timeline.jump_to_previous_marker())
     }
 
-    /// Get the key of the timeline track that corresponds to the given audio track.
-    pub fn audio_timeline_track_key(
-        &self,
-        audio_track_key: AudioTrackKey,
-    ) -> Result<TimelineTrackKey, InvalidAudioTrackError> {
+    /// Whether the metronome is clicking on each beat during playback.
+    pub fn metronome_enabled(&self) -> bool {
+        self.processor_interface.timeline.metronome().enabled()
+    }
+    /// Enable or disable the metronome.
+    ///
+    /// When enabled, a click is mixed into the output at every beat while the timeline is playing.
+    /// It is disabled by default.
+    pub fn set_metronome_enabled(&mut self, enabled: bool) {
+        self.processor_interface
+            .timeline
+            .metronome()
+            .set_enabled(enabled)
+    }
+    /// The linear gain of the metronome's clicks.
+    pub fn metronome_gain(&self) -> f32 {
+        self.processor_interface.timeline.metronome().gain()
+    }
+    /// Set the linear gain of the metronome's clicks, which is 1 by default.
+    pub fn set_metronome_gain(&mut self, gain: f32) {
+        self.processor_interface.timeline.metronome().set_gain(gain)
+    }
+    /// The fixed number of beats in each bar used to accent downbeats, if any.
+    pub fn metronome_beats_per_bar(&self) -> Option<NonZeroU32> {
+        self.processor_interface
+            .timeline
+            .metronome()
+            .beats_per_bar()
+    }
+    /// Accent the first beat of every bar of `beats_per_bar` beats with a higher pitched click,
+    /// or `None` to accent the first beat of every bar of the time signature, see [`Engine::set_time_signature`].
+    ///
+    /// A value of 1 accents every beat, making all clicks the same.
+    pub fn set_metronome_beats_per_bar(&mut self, beats_per_bar: Option<NonZeroU32>) {
+        self.processor_interface
+            .timeline
+            .metronome()
+            .set_beats_per_bar(beats_per_bar)
+    }
+
+    /// The number of beats clicked before playback starts.
+    pub fn count_in(&self) -> u32 {
+        self.processor_interface.timeline.metronome().count_in()
+    }
+    /// Click `beats` beats every time playback starts, before the playhead starts moving.
+    ///
+    /// Only the clicks are heard during the count-in, even if the metronome is disabled,
+    /// and they follow the tempo and time signature at the playhead.
+    /// Pausing during the count-in cancels it. It is 0 by default, which disables the count-in.
+    pub fn set_count_in(&mut self, beats: u32) {
+        self.processor_interface
+            .timeline
+            .metronome()
+            .set_count_in(beats)
+    }
+
+    /// Get the audio track that recordings are placed on, if any.
+    pub fn armed_track(&self) -> Option<AudioTrackKey> {
+        self.armed_track
+    }
+    /// Select the audio track that recordings should be placed on.
+    pub fn arm_track(
+        &mut self,
+        audio_track_key: AudioTrackKey,
+    ) -> Result<(), InvalidAudioTrackError> {
+        if !self.has_audio_track(audio_track_key) {
+            return Err(InvalidAudioTrackError {
+                key: audio_track_key,
+            });
+        }
+        self.armed_track = Some(audio_track_key);
+        Ok(())
+    }
+    /// Stop placing recordings on the armed track.
+    pub fn disarm_track(&mut self) {
+        self.armed_track = None;
+    }
+
+    /// Whether the input device is currently being recorded.
+    pub fn is_recording(&self) -> bool {
+        self.recording_start.is_some()
+    }
+    /// Start recording from the configured input device.
+    ///
+    /// The recording will be placed on the armed track, at the current position of the playhead,
+    /// once [`Engine::stop_recording()`] is called.
+    pub fn start_recording(&mut self) -> Result<(), StartRecordingError> {
+        self.check_stream()
+            .map_err(StartRecordingError::StreamDead)?;
+        let recorder = self
+            .recorder
+            .as_mut()
+            .ok_or(StartRecordingError::NoInputDevice)?;
+        if self.armed_track.is_none() {
+            return Err(StartRecordingError::NoArmedTrack);
+        }
+        if self.recording_start.is_some() {
+            return Err(StartRecordingError::AlreadyRecording);
+        }
+
+        recorder.start();
+        self.recording_start = Some(self.processor_interface.timeline.playhead_position());
+        Ok(())
+    }
+    /// Stop recording, and save what has been recorded as a .wav file at `path`,
+    /// converted to the sample rate of the engine.
+    ///
+    /// The file is written in the given `format`, dithered according to [`Config::dither`] if it is 16-bit.
+    /// [`WavFormat::default()`] keeps the full precision of the recording.
+    ///
+    /// The file is imported like any other audio clip, and placed on the armed track where the recording was started.
+    /// `path` should not refer to a file that has already been imported.
+    ///
+    /// Returns the key of the newly placed clip.
+    pub fn stop_recording(
+        &mut self,
+        path: &Path,
+        format: WavFormat,
+    ) -> Result<AudioClipKey, StopRecordingError> {
+        let recording = self
+            .recorder
+            .as_mut()
+            .and_then(|recorder| recorder.stop())
+            .ok_or(StopRecordingError::NotRecording)?;
+        let start = self
+            .recording_start
+            .take()
+            .expect("Recorder was recording without a start position");
+
+        let audio_track_key = self.armed_track.ok_or(StopRecordingError::NoArmedTrack)?;
+        let timeline_track_key = self
+            .audio_timeline_track_key(audio_track_key)
+            .expect("Armed track was deleted without being disarmed");
+
+        let recording = recording.resample(self.config.output_config.sample_rate);
+        recording
+            .save(path, format, self.config.dither)
+            .map_err(|e| StopRecordingError::Save(e.to_string()))?;
+
+        let stored_clip_key = self
+            .import_audio_clip(path)
+            .map_err(StopRecordingError::Import)?;
+        self.add_audio_clip(timeline_track_key, stored_clip_key, start, None)
+            .map_err(StopRecordingError::AddClip)
+    }
+
+    /// Whether the output is currently being captured to a file.
+    pub fn is_capturing_output(&self) -> bool {
+        self.processor_interface.output_capture.is_capturing()
+    }
+    /// Start writing the final output of the engine to a 32-bit floating point .wav file at `path`,
+    /// while it keeps playing to the output device.
+    ///
+    /// Unlike offline rendering, this captures exactly what is heard, including any changes made while playing.
+    /// The file is written in the background, and if that falls behind, the output that does not fit is left out.
+    /// Restarting the engine with [`Engine::set_config`] ends the capture.
+    pub fn start_output_capture(&mut self, path: &Path) -> Result<(), StartOutputCaptureError> {
+        self.check_stream()
+            .map_err(StartOutputCaptureError::StreamDead)?;
+        self.processor_interface.output_capture.start(path)
+    }
+    /// Stop capturing the output, and finish writing the file.
+    pub fn stop_output_capture(&mut self) -> Result<(), StopOutputCaptureError> {
+        match self.processor_interface.output_capture.stop() {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(StopOutputCaptureError::NotCapturing),
+            Err(e) => Err(StopOutputCaptureError::Save(e.to_string())),
+        }
+    }
+
+    /// Immutably borrow the master track, which is always present on the mixer.
+    pub fn master(&self) -> &MixerTrack {
+        self.processor_interface.mixer.master()
+    }
+    /// Mutably borrow the master track, which is always present on the mixer.
+    pub fn master_mut(&mut self) -> &mut MixerTrack {
+        self.processor_interface.mixer.master_mut()
+    }
+
+    /// Get the loudness of the output of the master track, as measured by ITU-R BS.1770.
+    ///
+    /// The measurement only runs while enabled via [`Self::set_master_loudness_enabled`].
+    pub fn master_loudness(&self) -> LoudnessReading {
+        self.processor_interface.mixer.loudness_meter().read()
+    }
+    pub fn master_loudness_enabled(&self) -> bool {
+        self.processor_interface.mixer.loudness_meter().enabled()
+    }
+    /// Enabling the measurement discards the previous one, so the integrated loudness starts over.
+    pub fn set_master_loudness_enabled(&self, enabled: bool) {
+        self.processor_interface
+            .mixer
+            .loudness_meter()
+            .set_enabled(enabled);
+    }
+
+    /// Get the linear gain applied to the output of the master track.
+    pub fn master_gain(&self) -> f32 {
+        self.processor_interface.mixer.master_gain()
+    }
+    /// Set a linear gain applied to the output of the master track, ahead of the saturation and limiter.
+    ///
+    /// Unlike the volume of the master track, this is not automated, so it can serve as a fixed output trim for gain staging.
+    /// It is 1 by default, and is kept in the engine's state.
+    pub fn set_master_gain(&mut self, gain: f32) {
+        self.processor_interface.mixer.set_master_gain(gain)
+    }
+
+    /// Get the settings of the soft-clipper placed after the master track, ahead of the limiter.
+    ///
+    /// Is `None` while the saturation is bypassed, which it is by default.
+    pub fn master_saturation(&self) -> Option<SaturationSettings> {
+        self.processor_interface.mixer.saturation().settings()
+    }
+    /// Gently round off the peaks of the master track, or bypass the saturation with `None`.
+    ///
+    /// Unlike the limiter, it adds no delay, but the output is not guaranteed to stay under any ceiling.
+    pub fn set_master_saturation(&mut self, settings: Option<SaturationSettings>) {
+        self.processor_interface
+            .mixer
+            .saturation_mut()
+            .set_settings(settings);
+    }
+
+    /// Get the settings of the brickwall limiter placed after the master track.
+    ///
+    /// Is `None` while the limiter is bypassed, which it is by default.
+    pub fn master_limiter(&self) -> Option<LimiterSettings> {
+        self.processor_interface.mixer.limiter().settings()
+    }
+    /// Limit the output of the master track, so it never exceeds the ceiling, or bypass the limiter with `None`.
+    ///
+    /// The limiter looks a little ahead, so it delays the output by a few milliseconds while it is enabled.
+    pub fn set_master_limiter(&mut self, settings: Option<LimiterSettings>) {
+        self.processor_interface
+            .mixer
+            .limiter_mut()
+            .set_settings(settings);
+    }
+
+    /// The number of threads the mixer tracks are processed on, including the audio thread itself.
+    ///
+    /// Is 1 by default.
+    pub fn mixer_threads(&self) -> usize {
+        self.processor_interface.mixer.threads()
+    }
+    /// Spread the processing of the mixer tracks across `threads` threads, including the audio thread itself.
+    ///
+    /// Only tracks that don't send to each other are processed at the same time,
+    /// and only when there are enough of them for it to pay off.
+    /// Zero is treated as one.
+    pub fn set_mixer_threads(&mut self, threads: usize) {
+        self.processor_interface.mixer.set_threads(threads);
+    }
+
+    /// Get the keys of all audio tracks currently in the engine, in order.
+    ///
+    /// New tracks are added at the end, and can be moved with [`Engine::reorder_audio_track`].
+    pub fn audio_tracks(&self) -> impl Iterator<Item = AudioTrackKey> + '_ {
+        self.audio_track_order.iter().copied()
+    }
+
+    /// Get the position of the audio track in [`Engine::audio_tracks`].
+    pub fn audio_track_index(&self, key: AudioTrackKey) -> Result<usize, InvalidAudioTrackError> {
+        self.audio_track_order
+            .iter()
+            .position(|&k| k == key)
+            .ok_or(InvalidAudioTrackError { key })
+    }
+
+    /// Move the audio track to `new_index` in [`Engine::audio_tracks`], shifting the tracks in between.
+    ///
+    /// Indices past the end move the track to the end.
+    /// The order has no effect on the output, but is kept in the state of the engine.
+    pub fn reorder_audio_track(
+        &mut self,
+        key: AudioTrackKey,
+        new_index: usize,
+    ) -> Result<(), InvalidAudioTrackError> {
+        let index = self.audio_track_index(key)?;
+        self.audio_track_order.remove(index);
+        let new_index = min(new_index, self.audio_track_order.len());
+        self.audio_track_order.insert(new_index, key);
+        Ok(())
+    }
+
+    /// Check if the engine contains an audio track with the given key.
+    pub fn has_audio_track(&self, key: AudioTrackKey) -> bool {
+        self.audio_tracks.contains_key(&key)
+    }
+
+    /// Get the key of the timeline track that corresponds to the given audio track.
+    pub fn audio_timeline_track_key(
+        &self,
+        audio_track_key: AudioTrackKey,
+    ) -> Result<TimelineTrackKey, InvalidAudioTrackError> {
         let &(timeline_track_key, _) =
             self.audio_tracks
                 .get(&audio_track_key)
@@ -536,6 +1611,7 @@ impl Engine {
 
         self.audio_tracks
             .insert(audio_track_key, (timeline_track_key, mixer_track_key));
+        self.audio_track_order.push(audio_track_key);
         Ok(audio_track_key)
     }
 
@@ -573,6 +1649,7 @@ impl Engine {
         ) {
             self.audio_tracks.insert(audio_track_key, tuple);
         }
+        self.audio_track_order.extend(&audio_track_keys);
 
         Ok(audio_track_keys.into_iter())
     }
@@ -598,6 +1675,7 @@ impl Engine {
             .track_state(timeline_track_key)
             .unwrap();
         let mixer_track_state = self.mixer_track(mixer_track_key).unwrap().state();
+        let index = self.audio_track_index(audio_track_key).unwrap();
 
         self.processor_interface
             .timeline
@@ -609,10 +1687,15 @@ impl Engine {
             .unwrap();
 
         self.audio_tracks.remove(&audio_track_key);
+        self.audio_track_order.remove(index);
         self.key_generator.free(audio_track_key).unwrap();
+        if self.armed_track == Some(audio_track_key) {
+            self.armed_track = None;
+        }
 
         Ok(AudioTrackState {
             key: audio_track_key,
+            index,
             timeline_track_state,
             mixer_track_state,
         })
@@ -663,6 +1746,7 @@ impl Engine {
         .map(
             |(&key, (timeline_track_state, mixer_track_state))| AudioTrackState {
                 key,
+                index: self.audio_track_index(key).unwrap(),
                 timeline_track_state,
                 mixer_track_state,
             },
@@ -678,8 +1762,13 @@ impl Engine {
             .delete_tracks(mixer_track_keys)
             .unwrap();
 
+        self.audio_track_order
+            .retain(|key| !audio_track_keys.contains(key));
         for &key in audio_track_keys.iter() {
             self.audio_tracks.remove(&key);
+            if self.armed_track == Some(key) {
+                self.armed_track = None;
+            }
             self.key_generator.free(key).unwrap();
         }
 
@@ -689,6 +1778,8 @@ impl Engine {
     /// Reconstruct an audio track that has been deleted.
     ///
     /// A state can be obtained using [`Engine::audio_track_state()`].
+    /// The track is put back at the position it was deleted from in [`Engine::audio_tracks`],
+    /// or at the end if there are no longer that many tracks.
     ///
     /// # Errors
     /// - [`AudioTrackReconstructionError::AudioTracks`] when the key of the audio track is already in use.
@@ -730,6 +1821,8 @@ impl Engine {
 
         self.audio_tracks
             .insert(audio_track_key, (timeline_track_key, mixer_track_key));
+        let index = min(state.index, self.audio_track_order.len());
+        self.audio_track_order.insert(index, audio_track_key);
         self.key_generator.reserve(audio_track_key).unwrap();
 
         Ok(audio_track_key)
@@ -738,6 +1831,7 @@ impl Engine {
     /// Reconstruct a set of audio tracks that have been deleted.
     ///
     /// A state can be obtained using [`Engine::audio_track_state()`].
+    /// Like with [`Engine::reconstruct_audio_track`], the tracks are put back at the positions they were deleted from.
     ///
     /// # Errors
     /// - [`AudioTrackReconstructionError::AudioTracks`] when the key of the audio track is already in use.
@@ -791,15 +1885,80 @@ impl Engine {
             self.key_generator.reserve(audio_track_key).unwrap();
         }
 
+        // Inserted from the front, so the tracks before each one are already in place
+        let mut positioned: Vec<_> = states_vec
+            .iter()
+            .map(|state| (state.index, state.key))
+            .collect();
+        positioned.sort();
+        for (index, key) in positioned {
+            let index = min(index, self.audio_track_order.len());
+            self.audio_track_order.insert(index, key);
+        }
+
         let audio_track_keys = states_vec.into_iter().map(|state| state.key);
         Ok(audio_track_keys)
     }
 
     /// Import audio clip from file.
+    ///
+    /// If the same file has already been imported, even via another path,
+    /// or a file with identical contents has, the existing clip is returned instead.
     pub fn import_audio_clip(&mut self, path: &Path) -> Result<StoredAudioClipKey, ImportError> {
         self.processor_interface.timeline.import_audio_clip(path)
     }
 
+    /// Import audio clip from file, without loading it into memory.
+    ///
+    /// The clip is instead decoded from disk while it is being played,
+    /// which is preferable for long recordings.
+    /// Each playing instance of the clip keeps at most roughly `max_cached_frames` frames decoded ahead of its position.
+    ///
+    /// If the same file has already been imported, the existing clip is returned unchanged,
+    /// like with [`Engine::import_audio_clip`].
+    pub fn import_audio_clip_streaming(
+        &mut self,
+        path: &Path,
+        max_cached_frames: usize,
+    ) -> Result<StoredAudioClipKey, ImportError> {
+        self.processor_interface
+            .timeline
+            .import_audio_clip_streaming(path, max_cached_frames)
+    }
+
+    /// How clips with more than two channels are mixed down to stereo when imported.
+    pub fn downmix(&self) -> &Downmix {
+        self.processor_interface.timeline.downmix()
+    }
+    /// Set how clips with more than two channels are mixed down to stereo when imported.
+    ///
+    /// Clips that have already been imported are not affected.
+    pub fn set_downmix(&mut self, downmix: Downmix) {
+        self.processor_interface.timeline.set_downmix(downmix);
+    }
+
+    /// Like [`Engine::import_audio_clip`], but the file is decoded on another thread, without blocking.
+    ///
+    /// The returned handle reports the progress of the decoding.
+    /// Once it is done, pass the handle to [`Engine::finish_audio_clip_import`] to get the key of the clip.
+    /// Until then, the clip cannot be used anywhere.
+    pub fn import_audio_clip_async(&self, path: &Path) -> ImportHandle {
+        self.processor_interface
+            .timeline
+            .import_audio_clip_async(path)
+    }
+
+    /// Add the clip of an asynchronous import to the engine,
+    /// blocking until it has been decoded, if it has not yet.
+    pub fn finish_audio_clip_import(
+        &mut self,
+        handle: ImportHandle,
+    ) -> Result<StoredAudioClipKey, ImportError> {
+        self.processor_interface
+            .timeline
+            .finish_audio_clip_import(handle)
+    }
+
     /// Get an imported audio clip.
     pub fn stored_audio_clip(
         &self,
@@ -808,17 +1967,60 @@ impl Engine {
         self.processor_interface.timeline.stored_audio_clip(key)
     }
 
+    /// Whether the imported audio clip has a different sample rate than the engine,
+    /// in which case every clip playing it is resampled on the fly.
+    ///
+    /// Resampling costs processing time, so clips that are used a lot may be worth converting to the engine's sample rate beforehand.
+    /// The same is shown for each clip on the timeline by [`AudioClip::resampled`].
+    pub fn is_resampling(
+        &self,
+        key: StoredAudioClipKey,
+    ) -> Result<bool, InvalidStoredAudioClipError> {
+        self.processor_interface
+            .timeline
+            .stored_audio_clip_resampled(key)
+    }
+
     /// Get all currently imported audio clips.
     pub fn stored_audio_clips(&self) -> impl Iterator<Item = Arc<StoredAudioClip>> + '_ {
         self.processor_interface.timeline.stored_audio_clips()
     }
 
+    /// The path that the audio clip was imported from, as it was given on import.
+    ///
+    /// Returns `None` if the key is invalid.
+    pub fn stored_audio_clip_path(&self, key: StoredAudioClipKey) -> Option<PathBuf> {
+        self.processor_interface
+            .timeline
+            .stored_audio_clip_path(key)
+    }
+
+    /// The number of clips on the timeline that play the imported audio clip.
+    ///
+    /// While this is zero, the clip can be removed with [`Engine::remove_stored_audio_clip`].
+    pub fn stored_clip_usage(&self, key: StoredAudioClipKey) -> usize {
+        self.processor_interface.timeline.stored_clip_usage(key)
+    }
+
+    /// Remove an imported audio clip, freeing its memory.
+    ///
+    /// Fails if any clip on the timeline still plays it, in which case those clips must be deleted first.
+    pub fn remove_stored_audio_clip(
+        &mut self,
+        key: StoredAudioClipKey,
+    ) -> Result<(), RemoveStoredAudioClipError> {
+        self.processor_interface
+            .timeline
+            .remove_stored_audio_clip(key)
+    }
+
     /// Add an audio clip to the given track's timeline.
     ///
     /// # Errors
     /// - [`AddClipError::InvalidTimelineTrack`] when the timeline track key is invalid.
     /// - [`AddClipError::InvalidClip`] when the stored audio clip key is invalid.
-    /// - [`AddClipError::Overlapping`] when the clip would overlap with another clip on the same track.
+    /// - [`AddClipError::Overlapping`] when the clip would overlap with another clip on the same track,
+    ///   in a way the [`OverlapPolicy`] of the track does not allow.
     pub fn add_audio_clip(
         &mut self,
         timeline_track_key: TimelineTrackKey,
@@ -834,6 +2036,24 @@ impl Engine {
         )
     }
 
+    /// Add a copy of the audio clip to the same track, starting at `new_start`.
+    ///
+    /// The copy plays the same region of the same stored clip, with the same gain, fades and other settings,
+    /// but does not inherit the crossfade of the original.
+    ///
+    /// # Errors
+    /// - [`AddClipError::InvalidAudioClip`] when the audio clip key is invalid.
+    /// - [`AddClipError::Overlapping`] when the copy would overlap with another clip on the same track.
+    pub fn duplicate_audio_clip(
+        &mut self,
+        audio_clip_key: AudioClipKey,
+        new_start: Timestamp,
+    ) -> Result<AudioClipKey, AddClipError> {
+        self.processor_interface
+            .timeline
+            .duplicate_audio_clip(audio_clip_key, new_start)
+    }
+
     /// Get a reference to the audio clip with the given key.
     pub fn audio_clip(
         &self,
@@ -862,6 +2082,68 @@ impl Engine {
             .audio_clips(timeline_track_key)
     }
 
+    /// Get the number of audio clips on the given track.
+    pub fn audio_clip_count(
+        &self,
+        timeline_track_key: TimelineTrackKey,
+    ) -> Result<usize, InvalidTimelineTrackError> {
+        self.processor_interface
+            .timeline
+            .audio_clip_count(timeline_track_key)
+    }
+
+    /// Get how far the arrangement extends, i.e. the end of the last clip on any track.
+    ///
+    /// This is zero if there are no clips.
+    pub fn timeline_length(&self) -> Timestamp {
+        self.processor_interface.timeline.length()
+    }
+
+    /// Check whether a new audio clip could be added to the given track from `start` to `end`,
+    /// without overlapping any of the clips already on it.
+    ///
+    /// This follows the same rules as [`Engine::add_audio_clip`], so it can be used to validate a placement before committing to it.
+    pub fn range_is_free(
+        &self,
+        timeline_track_key: TimelineTrackKey,
+        start: Timestamp,
+        end: Timestamp,
+    ) -> Result<bool, InvalidTimelineTrackError> {
+        self.processor_interface
+            .timeline
+            .range_is_free(timeline_track_key, start, end)
+    }
+
+    /// Get the number of audio clips on the given track that are playing at `position`.
+    ///
+    /// This is at most two, since clips may only overlap where they crossfade,
+    /// unless the track has the [`OverlapPolicy::Layer`].
+    /// It is computed without touching the audio thread.
+    pub fn audio_clip_count_at(
+        &self,
+        timeline_track_key: TimelineTrackKey,
+        position: Timestamp,
+    ) -> Result<usize, InvalidTimelineTrackError> {
+        self.processor_interface
+            .timeline
+            .audio_clip_count_at(timeline_track_key, position)
+    }
+
+    /// Get the audio clip on the given track that is playing at `position`, if any.
+    ///
+    /// A clip covers the positions from its start up to, but not including, its end,
+    /// the same as when checking whether clips overlap.
+    /// Where clips overlap, e.g. in a crossfade, the one that starts latest is returned.
+    pub fn audio_clip_at(
+        &self,
+        timeline_track_key: TimelineTrackKey,
+        position: Timestamp,
+    ) -> Result<Option<AudioClipKey>, InvalidTimelineTrackError> {
+        self.processor_interface
+            .timeline
+            .audio_clip_at(timeline_track_key, position)
+    }
+
     /// Delete the audio clip with the given key.
     pub fn delete_audio_clip(
         &mut self,
@@ -889,7 +2171,7 @@ impl Engine {
     /// - [`AudioClipReconstructionError::InvalidTrack`] when the timeline track this clip was on no longer exists.
     /// - [`AudioClipReconstructionError::InvalidStoredClip`] when the stored audio clip this clip was based on no longer exists.
     /// - [`AudioClipReconstructionError::KeyInUse`] when the audio clip's key is already in use,
-    ///     either because it was never deleted or because it has been repurposed for another clip.
+    ///   either because it was never deleted or because it has been repurposed for another clip.
     /// - [`AudioClipReconstructionError::Overlapping`] when the clip would overlap with another clip on the same track.
     pub fn reconstruct_audio_clip(
         &mut self,
@@ -908,7 +2190,7 @@ impl Engine {
     /// - [`AudioClipReconstructionError::InvalidTrack`] when the timeline track this clip was on no longer exists.
     /// - [`AudioClipReconstructionError::InvalidStoredClip`] when the stored audio clip this clip was based on no longer exists.
     /// - [`AudioClipReconstructionError::KeyInUse`] when the audio clip's key is already in use,
-    ///     either because it was never deleted or because it has been repurposed for another clip.
+    ///   either because it was never deleted or because it has been repurposed for another clip.
     /// - [`AudioClipReconstructionError::Overlapping`] when the clip would overlap with another clip on the same track.
     pub fn reconstruct_audio_clips(
         &mut self,
@@ -931,6 +2213,100 @@ impl Engine {
             .audio_clip_move(audio_clip_key, new_start)
     }
 
+    /// Set the start position of the clip, letting it crossfade with the clips before and after it where it overlaps them.
+    ///
+    /// Each crossfade is as long as the overlap it covers, as if set by [`Engine::set_crossfade`],
+    /// and the clip no longer crossfades with the clip before it if it is moved clear of it.
+    ///
+    /// # Errors
+    /// - [`MoveAudioClipError::InvalidClip`] when the audio clip key is invalid.
+    /// - [`MoveAudioClipError::Overlapping`] when the clip would cover another clip entirely, be covered by one,
+    ///   or overlap more than one clip at either end.
+    pub fn audio_clip_move_with_crossfade(
+        &mut self,
+        audio_clip_key: AudioClipKey,
+        new_start: Timestamp,
+    ) -> Result<(), MoveAudioClipError> {
+        self.processor_interface
+            .timeline
+            .audio_clip_move_with_crossfade(audio_clip_key, new_start)
+    }
+
+    /// Move the start of the clip to the nearest multiple of `division`,
+    /// e.g. `Timestamp::from_beats(1)` for whole beats or `Timestamp::from_beat_units(256)` for quarter beats.
+    ///
+    /// The clip is left in place if it would overlap another clip.
+    pub fn quantize_audio_clip(
+        &mut self,
+        audio_clip_key: AudioClipKey,
+        division: Timestamp,
+    ) -> Result<(), MoveAudioClipError> {
+        self.processor_interface
+            .timeline
+            .quantize_audio_clip(audio_clip_key, division)
+    }
+
+    /// Quantize each of the clips like [`Engine::quantize_audio_clip`], in the given order.
+    ///
+    /// Either all clips are moved, or none of them are.
+    /// If any clip would overlap another clip once it is moved, all clips moved before it are moved back.
+    pub fn quantize_audio_clips(
+        &mut self,
+        audio_clip_keys: &[AudioClipKey],
+        division: Timestamp,
+    ) -> Result<(), MoveAudioClipError> {
+        self.processor_interface
+            .timeline
+            .quantize_audio_clips(audio_clip_keys, division)
+    }
+
+    /// Move all of the clips `delta` forward or backward, keeping their positions relative to each other.
+    ///
+    /// Either all clips are moved, or none of them are.
+    /// The clips may be on different tracks, and keys given more than once are only moved once.
+    ///
+    /// # Errors
+    /// - [`MoveAudioClipError::InvalidClip`] when any of the audio clip keys are invalid.
+    /// - [`MoveAudioClipError::Overlapping`] when any of the clips would overlap a clip that is not being moved.
+    /// - [`MoveAudioClipError::OutOfBounds`] when any of the clips would be moved outside of the timeline.
+    pub fn move_audio_clips(
+        &mut self,
+        audio_clip_keys: &[AudioClipKey],
+        delta: Timestamp,
+        direction: Direction,
+    ) -> Result<(), MoveAudioClipError> {
+        self.processor_interface
+            .timeline
+            .move_audio_clips(audio_clip_keys, delta, direction)
+    }
+
+    /// Make room for `length` of new material at `at`, by moving every clip starting at or after `at` later.
+    ///
+    /// Clips starting before `at` are left in place, even if they end after it.
+    ///
+    /// # Errors
+    /// - [`MoveAudioClipError::OutOfBounds`] when any of the clips would be moved past the end of the timeline.
+    pub fn insert_time(
+        &mut self,
+        at: Timestamp,
+        length: Timestamp,
+    ) -> Result<(), MoveAudioClipError> {
+        self.processor_interface.timeline.insert_time(at, length)
+    }
+
+    /// Cut everything between `start` and `end` out of the timeline,
+    /// moving every clip after the range back by its length.
+    ///
+    /// Clips crossing either end of the range are split there, keeping only the parts outside of it.
+    /// Either the whole range is deleted, or nothing is changed.
+    ///
+    /// # Errors
+    /// - [`DeleteTimeError::EmptyRange`] when `end` is not after `start`.
+    /// - [`DeleteTimeError::InCrossfade`] when a clip would have to be split inside a crossfade.
+    pub fn delete_time(&mut self, start: Timestamp, end: Timestamp) -> Result<(), DeleteTimeError> {
+        self.processor_interface.timeline.delete_time(start, end)
+    }
+
     /// Move clip to the given position on another track.
     pub fn audio_clip_move_to_track(
         &mut self,
@@ -972,12 +2348,383 @@ impl Engine {
             .audio_clip_crop_end(audio_clip_key, new_length)
     }
 
-    /// Get an immutable reference to the mixer track with the given key.
-    pub fn mixer_track(&self, key: MixerTrackKey) -> Result<&MixerTrack, InvalidMixerTrackError> {
-        self.processor_interface.mixer.track(key)
-    }
-
-    /// Get a mutable reference to the mixer track with the given key.
+    /// Crop the start of each clip to the length paired with it, like [`Engine::audio_clip_crop_start`].
+    ///
+    /// Either all clips are cropped, or none of them are.
+    /// The clips may be on different tracks, and only the first length given for each clip is used.
+    ///
+    /// # Errors
+    /// - [`MoveAudioClipError::InvalidClip`] when any of the audio clip keys are invalid.
+    /// - [`MoveAudioClipError::Overlapping`] when any of the clips would overlap another clip once all of them are cropped.
+    pub fn audio_clip_crop_start_multiple(
+        &mut self,
+        crops: &[(AudioClipKey, Timestamp)],
+    ) -> Result<(), MoveAudioClipError> {
+        self.processor_interface
+            .timeline
+            .audio_clip_crop_start_multiple(crops)
+    }
+
+    /// Crop the end of each clip to the length paired with it, like [`Engine::audio_clip_crop_end`].
+    ///
+    /// Either all clips are cropped, or none of them are.
+    /// The clips may be on different tracks, and only the first length given for each clip is used.
+    ///
+    /// # Errors
+    /// - [`MoveAudioClipError::InvalidClip`] when any of the audio clip keys are invalid.
+    /// - [`MoveAudioClipError::Overlapping`] when any of the clips would overlap another clip once all of them are cropped.
+    pub fn audio_clip_crop_end_multiple(
+        &mut self,
+        crops: &[(AudioClipKey, Timestamp)],
+    ) -> Result<(), MoveAudioClipError> {
+        self.processor_interface
+            .timeline
+            .audio_clip_crop_end_multiple(crops)
+    }
+
+    /// Crop away the silence at the start and end of the clip, i.e. everything quieter than `threshold_db` dBFS.
+    ///
+    /// Only the clip's region of the stored clip changes, like with [`Engine::audio_clip_crop_start`] and [`Engine::audio_clip_crop_end`].
+    /// If the clip is entirely silent, it is left as is.
+    pub fn trim_clip_silence(
+        &mut self,
+        audio_clip_key: AudioClipKey,
+        threshold_db: f32,
+    ) -> Result<(), MoveAudioClipError> {
+        self.processor_interface
+            .timeline
+            .audio_clip_trim_silence(audio_clip_key, threshold_db)
+    }
+
+    /// Split the clip in two at `at`, which must lie strictly inside the clip.
+    ///
+    /// The original clip is removed, and the two new clips are returned in order.
+    /// The first clip keeps the original fade-in, and the second keeps the original fade-out.
+    pub fn split_audio_clip(
+        &mut self,
+        audio_clip_key: AudioClipKey,
+        at: Timestamp,
+    ) -> Result<(AudioClipKey, AudioClipKey), SplitAudioClipError> {
+        self.processor_interface
+            .timeline
+            .split_audio_clip(audio_clip_key, at)
+    }
+
+    /// Replace the given clips with a single clip, undoing [`Engine::split_audio_clip`].
+    ///
+    /// The clips must follow each other directly on the same track,
+    /// and together play one continuous stretch of the same stored clip,
+    /// with the same gain, stretch, pitch and direction, and without fading between each other.
+    /// The merged clip keeps the fade-in, name and color of the first clip, the fade-out of the last one,
+    /// and the gain envelopes of all of them.
+    pub fn merge_audio_clips(
+        &mut self,
+        audio_clip_keys: &[AudioClipKey],
+    ) -> Result<AudioClipKey, MergeAudioClipsError> {
+        self.processor_interface
+            .timeline
+            .merge_audio_clips(audio_clip_keys)
+    }
+
+    /// Toggle whether the clip is played backwards.
+    ///
+    /// The clip keeps its place and length on the timeline, and plays the same region of the source backwards.
+    /// Clips streamed from disk can not be reversed.
+    pub fn reverse_audio_clip(
+        &mut self,
+        audio_clip_key: AudioClipKey,
+    ) -> Result<(), ReverseAudioClipError> {
+        let timeline = &mut self.processor_interface.timeline;
+        let reversed = timeline
+            .audio_clip(audio_clip_key)
+            .map_err(ReverseAudioClipError::InvalidClip)?
+            .reversed();
+        timeline.audio_clip_set_reversed(audio_clip_key, !reversed)
+    }
+
+    /// Set whether the clip is heard.
+    ///
+    /// An inactive clip keeps its place on the timeline, e.g. for comparing with and without it,
+    /// but outputs silence. Neighbouring clips play as before.
+    pub fn audio_clip_set_active(
+        &mut self,
+        audio_clip_key: AudioClipKey,
+        active: bool,
+    ) -> Result<(), InvalidAudioClipError> {
+        self.processor_interface
+            .timeline
+            .audio_clip_set_active(audio_clip_key, active)
+    }
+
+    /// Get the clip that is soloed with [`Engine::solo_audio_clip`], if any.
+    pub fn soloed_audio_clip(&self) -> Option<AudioClipKey> {
+        self.processor_interface.timeline.soloed_audio_clip()
+    }
+    /// Make the timeline output only the given clip, e.g. for listening to a single take while editing,
+    /// or `None` to hear everything again.
+    ///
+    /// Every other clip, and the test tones and noise of the tracks, is silenced, even on tracks that are soloed.
+    /// The soloed clip still plays through its track, so if the track is muted,
+    /// or silenced by another track's solo, nothing is heard.
+    /// The clip stops being soloed if it is deleted.
+    pub fn solo_audio_clip(
+        &mut self,
+        audio_clip_key: Option<AudioClipKey>,
+    ) -> Result<(), InvalidAudioClipError> {
+        self.processor_interface
+            .timeline
+            .solo_audio_clip(audio_clip_key)
+    }
+
+    /// Set what the clip plays once its length reaches past the end of the stored clip,
+    /// e.g. holding the last sample or looping, rather than going silent.
+    ///
+    /// This only matters if the clip has been extended with [`Engine::audio_clip_crop_end`].
+    pub fn audio_clip_set_end_behavior(
+        &mut self,
+        audio_clip_key: AudioClipKey,
+        end_behavior: EndBehavior,
+    ) -> Result<(), InvalidAudioClipError> {
+        self.processor_interface
+            .timeline
+            .audio_clip_set_end_behavior(audio_clip_key, end_behavior)
+    }
+
+    /// Stretch the clip to play `stretch` times as long as the source clip, without changing its pitch.
+    ///
+    /// The clip keeps playing the same region of the source clip, so its length on the timeline changes accordingly.
+    /// `1.0` plays the clip at its original speed, and the stretch is clamped between [`MIN_STRETCH`] and [`MAX_STRETCH`].
+    pub fn audio_clip_set_stretch(
+        &mut self,
+        audio_clip_key: AudioClipKey,
+        stretch: f32,
+    ) -> Result<(), MoveAudioClipError> {
+        self.processor_interface
+            .timeline
+            .audio_clip_set_stretch(audio_clip_key, stretch)
+    }
+
+    /// Shift the pitch of the clip by `pitch` semitones, without changing its length.
+    ///
+    /// Fractions of a semitone are cents, e.g. `0.25` for 25 cents up,
+    /// so rounding to whole semitones is left to the caller.
+    /// The pitch is clamped to at most [`MAX_PITCH`] semitones in either direction.
+    pub fn audio_clip_set_pitch(
+        &mut self,
+        audio_clip_key: AudioClipKey,
+        pitch: f32,
+    ) -> Result<(), InvalidAudioClipError> {
+        self.processor_interface
+            .timeline
+            .audio_clip_set_pitch(audio_clip_key, pitch)
+    }
+
+    /// Let the start of `clip_b` overlap the end of `clip_a` by `length`, crossfading between the two.
+    ///
+    /// `clip_b` must be the clip directly after `clip_a` on the same track.
+    /// It stays in place, while `clip_a` is extended or cropped to end `length` after the start of `clip_b`,
+    /// so a `length` of zero removes the crossfade.
+    /// The fades use complementary equal-power gain ramps across the overlap.
+    pub fn set_crossfade(
+        &mut self,
+        clip_a: AudioClipKey,
+        clip_b: AudioClipKey,
+        length: Timestamp,
+    ) -> Result<(), CrossfadeError> {
+        self.processor_interface
+            .timeline
+            .set_crossfade(clip_a, clip_b, length)
+    }
+
+    /// Set the linear gain of the clip, where `1.0` is unity gain.
+    pub fn audio_clip_set_gain(
+        &mut self,
+        audio_clip_key: AudioClipKey,
+        gain: f32,
+    ) -> Result<(), InvalidAudioClipError> {
+        self.processor_interface
+            .timeline
+            .audio_clip_set_gain(audio_clip_key, gain)
+    }
+
+    /// Set the breakpoints of the clip's gain envelope, which is applied on top of its gain.
+    ///
+    /// Each breakpoint is a position in samples at the source clip's sample rate, and a linear gain.
+    /// The positions are relative to the start of the clip on the timeline, so the envelope follows the clip when it is moved or cropped.
+    /// The gain is linearly interpolated between the breakpoints, and held before the first and after the last one.
+    pub fn audio_clip_set_gain_envelope(
+        &mut self,
+        audio_clip_key: AudioClipKey,
+        gain_envelope: Vec<(usize, f32)>,
+    ) -> Result<(), InvalidAudioClipError> {
+        self.processor_interface
+            .timeline
+            .audio_clip_set_gain_envelope(audio_clip_key, gain_envelope)
+    }
+
+    /// Set the duration of the linear fade-in at the start of the clip.
+    ///
+    /// The fade is relative to the start of the clip on the timeline, so it follows the clip when it is moved or cropped.
+    /// If the fade is longer than the clip, it will be cut short during playback.
+    ///
+    /// Only linear fades are supported for now, there is no equal-power curve.
+    /// Overlapping clips are blended with equal-power ramps by [`Engine::set_crossfade`] instead.
+    pub fn audio_clip_set_fade_in(
+        &mut self,
+        audio_clip_key: AudioClipKey,
+        fade_in: Timestamp,
+    ) -> Result<(), InvalidAudioClipError> {
+        self.processor_interface
+            .timeline
+            .audio_clip_set_fade_in(audio_clip_key, fade_in)
+    }
+
+    /// Set the duration of the linear fade-out at the end of the clip.
+    ///
+    /// If the fade is longer than the clip, it will be cut short during playback.
+    ///
+    /// Like the fade-in, this is always linear.
+    pub fn audio_clip_set_fade_out(
+        &mut self,
+        audio_clip_key: AudioClipKey,
+        fade_out: Timestamp,
+    ) -> Result<(), InvalidAudioClipError> {
+        self.processor_interface
+            .timeline
+            .audio_clip_set_fade_out(audio_clip_key, fade_out)
+    }
+
+    /// Set the display name of the clip.
+    pub fn audio_clip_set_name(
+        &mut self,
+        audio_clip_key: AudioClipKey,
+        name: String,
+    ) -> Result<(), InvalidAudioClipError> {
+        self.processor_interface
+            .timeline
+            .audio_clip_set_name(audio_clip_key, name)
+    }
+
+    /// Set the display color of the clip as RGBA, e.g. `0xFF0000FF` for opaque red.
+    pub fn audio_clip_set_color(
+        &mut self,
+        audio_clip_key: AudioClipKey,
+        color: u32,
+    ) -> Result<(), InvalidAudioClipError> {
+        self.processor_interface
+            .timeline
+            .audio_clip_set_color(audio_clip_key, color)
+    }
+
+    /// Get the display name of the timeline track.
+    pub fn timeline_track_name(
+        &self,
+        key: TimelineTrackKey,
+    ) -> Result<&str, InvalidTimelineTrackError> {
+        self.processor_interface.timeline.track_name(key)
+    }
+    /// Set the display name of the timeline track.
+    ///
+    /// The name of the mixer track is set separately via [`MixerTrack::set_name`].
+    pub fn set_timeline_track_name(
+        &mut self,
+        key: TimelineTrackKey,
+        name: String,
+    ) -> Result<(), InvalidTimelineTrackError> {
+        self.processor_interface.timeline.set_track_name(key, name)
+    }
+
+    /// Get the display color of the timeline track as RGBA.
+    pub fn timeline_track_color(
+        &self,
+        key: TimelineTrackKey,
+    ) -> Result<u32, InvalidTimelineTrackError> {
+        self.processor_interface.timeline.track_color(key)
+    }
+    /// Set the display color of the timeline track as RGBA, e.g. `0xFF0000FF` for opaque red.
+    pub fn set_timeline_track_color(
+        &mut self,
+        key: TimelineTrackKey,
+        color: u32,
+    ) -> Result<(), InvalidTimelineTrackError> {
+        self.processor_interface
+            .timeline
+            .set_track_color(key, color)
+    }
+
+    /// Get how clips placed on top of each other on the timeline track are handled.
+    ///
+    /// Is [`OverlapPolicy::Reject`] by default.
+    pub fn track_overlap_policy(
+        &self,
+        key: TimelineTrackKey,
+    ) -> Result<OverlapPolicy, InvalidTimelineTrackError> {
+        self.processor_interface.timeline.track_overlap_policy(key)
+    }
+    /// Set how clips placed on top of each other on the timeline track are handled.
+    ///
+    /// # Errors
+    /// - [`SetOverlapPolicyError::InvalidTimelineTrack`] when the timeline track key is invalid.
+    /// - [`SetOverlapPolicyError::Overlapping`] when the clips already on the track overlap in a way the new policy does not allow,
+    ///   e.g. when leaving [`OverlapPolicy::Layer`] while clips are layered.
+    pub fn set_track_overlap_policy(
+        &mut self,
+        key: TimelineTrackKey,
+        policy: OverlapPolicy,
+    ) -> Result<(), SetOverlapPolicyError> {
+        self.processor_interface
+            .timeline
+            .set_track_overlap_policy(key, policy)
+    }
+
+    /// Get the settings of the test tone generated on the timeline track, if any.
+    pub fn track_test_tone(
+        &self,
+        key: TimelineTrackKey,
+    ) -> Result<Option<TestToneSettings>, InvalidTimelineTrackError> {
+        self.processor_interface.timeline.track_test_tone(key)
+    }
+    /// Generate a test tone on the timeline track, or stop it with `None`.
+    ///
+    /// The tone is mixed with the clips and passed to the output track, like any other audio on the track.
+    /// Unlike the clips, it is heard whether or not the timeline is playing.
+    pub fn set_track_test_tone(
+        &mut self,
+        key: TimelineTrackKey,
+        settings: Option<TestToneSettings>,
+    ) -> Result<(), InvalidTimelineTrackError> {
+        self.processor_interface
+            .timeline
+            .set_track_test_tone(key, settings)
+    }
+
+    /// Get the settings of the noise generated on the timeline track, if any.
+    pub fn track_noise(
+        &self,
+        key: TimelineTrackKey,
+    ) -> Result<Option<NoiseSettings>, InvalidTimelineTrackError> {
+        self.processor_interface.timeline.track_noise(key)
+    }
+    /// Generate noise on the timeline track, or stop it with `None`.
+    ///
+    /// Like the test tone, the noise is passed to the output track whether or not the timeline is playing,
+    /// and can be combined with it.
+    pub fn set_track_noise(
+        &mut self,
+        key: TimelineTrackKey,
+        settings: Option<NoiseSettings>,
+    ) -> Result<(), InvalidTimelineTrackError> {
+        self.processor_interface
+            .timeline
+            .set_track_noise(key, settings)
+    }
+
+    /// Get an immutable reference to the mixer track with the given key.
+    pub fn mixer_track(&self, key: MixerTrackKey) -> Result<&MixerTrack, InvalidMixerTrackError> {
+        self.processor_interface.mixer.track(key)
+    }
+
+    /// Get a mutable reference to the mixer track with the given key.
     pub fn mixer_track_mut(
         &mut self,
         key: MixerTrackKey,
@@ -985,22 +2732,265 @@ impl Engine {
         self.processor_interface.mixer.track_mut(key)
     }
 
+    /// Send a copy of the output of the mixer track `source`, scaled by `gain`, to the input of the mixer track `target`.
+    /// This is in addition to the regular output of `source`, and is applied after its volume and panning.
+    ///
+    /// If the send already exists, its gain is updated instead.
+    /// Sends that would make a track feed back into itself are rejected.
+    pub fn add_send(
+        &mut self,
+        source: MixerTrackKey,
+        target: MixerTrackKey,
+        gain: f32,
+    ) -> Result<(), AddSendError> {
+        self.processor_interface
+            .mixer
+            .add_send(source, target, gain)
+    }
+
+    pub fn remove_send(
+        &mut self,
+        source: MixerTrackKey,
+        target: MixerTrackKey,
+    ) -> Result<(), InvalidSendError> {
+        self.processor_interface.mixer.remove_send(source, target)
+    }
+
+    /// Let the dynamics effects of the mixer track `key`, like its compressors, react to the output of the mixer track `source`,
+    /// instead of the track's own signal. This is commonly used for ducking.
+    ///
+    /// `None` makes them react to the track's own signal again.
+    /// If `source` is deleted, the sidechain is removed as well.
+    ///
+    /// # Errors
+    /// Sidechains that would make a track depend on its own output, through sends or other sidechains, are rejected.
+    pub fn set_sidechain(
+        &mut self,
+        key: MixerTrackKey,
+        source: Option<MixerTrackKey>,
+    ) -> Result<(), SetSidechainError> {
+        self.processor_interface.mixer.set_sidechain(key, source)
+    }
+
+    /// Render the clips of the audio track offline, through the effects of its mixer track,
+    /// and save the result as a .wav file at `path`, which is imported like any other audio clip.
+    ///
+    /// The volume, panning and sends of the mixer track are not rendered, so they still apply after freezing.
+    /// The rendering spans from the start of the first clip on the track to the end of the last one.
+    ///
+    /// If `replace` is true, the clips on the track are replaced by a single clip of the frozen audio,
+    /// and the effects are removed from the mixer track, since they are already baked in.
+    /// What was removed is returned, so the freeze can be undone.
+    /// `path` should not refer to a file that has already been imported.
+    ///
+    /// # Errors
+    /// - [`FreezeTrackError::InvalidAudioTrack`] when the audio track key is invalid.
+    /// - [`FreezeTrackError::Empty`] when there are no clips on the track.
+    /// - [`FreezeTrackError::Save`] when the rendered audio could not be written to `path`.
+    /// - [`FreezeTrackError::Import`] when the clips could not be loaded for rendering,
+    ///   or the rendered audio could not be imported.
+    pub fn freeze_track(
+        &mut self,
+        audio_track_key: AudioTrackKey,
+        path: &Path,
+        replace: bool,
+    ) -> Result<FrozenTrack, FreezeTrackError> {
+        let timeline_track_key = self
+            .audio_timeline_track_key(audio_track_key)
+            .map_err(FreezeTrackError::InvalidAudioTrack)?;
+        let mixer_track_key = self
+            .audio_mixer_track_key(audio_track_key)
+            .map_err(FreezeTrackError::InvalidAudioTrack)?;
+
+        let tempo_map = self.tempo_map();
+        let clips: Vec<_> = self
+            .audio_clips(timeline_track_key)
+            .expect("Audio track has no timeline track")
+            .map(|clip| (clip.key, clip.start(), clip.end(tempo_map)))
+            .collect();
+        let start = clips
+            .iter()
+            .map(|&(_, start, _)| start)
+            .min()
+            .ok_or(FreezeTrackError::Empty)?;
+        let end = clips.iter().map(|&(_, _, end)| end).max().unwrap();
+
+        let sample_rate = self.config.output_config.sample_rate;
+        let state = self
+            .processor_interface
+            .isolated_track_state(timeline_track_key, true)
+            .expect("Audio track has no timeline track");
+        let samples = render(&state, sample_rate, start, end).map_err(FreezeTrackError::Import)?;
+        Recording::from_interleaved(&samples, CHANNELS, sample_rate)
+            .save(path, WavFormat::default(), DitherMode::None)
+            .map_err(|e| FreezeTrackError::Save(e.to_string()))?;
+        let stored_clip = self
+            .import_audio_clip(path)
+            .map_err(FreezeTrackError::Import)?;
+
+        if !replace {
+            return Ok(FrozenTrack {
+                stored_clip,
+                clip: None,
+                clips: Vec::new(),
+                effects: Vec::new(),
+            });
+        }
+
+        let clips = self
+            .delete_audio_clips(clips.iter().map(|&(key, _, _)| key))
+            .expect("Clips on the track were invalid")
+            .collect();
+        let clip = self
+            .add_audio_clip(timeline_track_key, stored_clip, start, None)
+            .expect("Frozen clip could not be added to the emptied track");
+
+        let mixer_track = self
+            .mixer_track_mut(mixer_track_key)
+            .expect("Audio track has no mixer track");
+        let effect_keys: Vec<_> = mixer_track.effects().collect();
+        let effects = effect_keys
+            .into_iter()
+            .map(|key| {
+                mixer_track
+                    .remove_effect(key)
+                    .expect("Effect on the track was invalid")
+            })
+            .collect();
+
+        Ok(FrozenTrack {
+            stored_clip,
+            clip: Some(clip),
+            clips,
+            effects,
+        })
+    }
+
+    /// Render the clips of the timeline track offline from `start` to `end`,
+    /// returning the interleaved output at the engine's sample rate.
+    ///
+    /// Only the clips are rendered, without the effects, volume or panning of the mixer track.
+    /// This runs synchronously, so it is best suited for short regions such as previews.
+    /// If `end` is not after `start`, the output is empty.
+    ///
+    /// # Errors
+    /// - [`RenderTrackError::InvalidTimelineTrack`] when the timeline track key is invalid.
+    /// - [`RenderTrackError::Import`] when the clips could not be loaded for rendering.
+    pub fn render_track_region(
+        &self,
+        key: TimelineTrackKey,
+        start: Timestamp,
+        end: Timestamp,
+    ) -> Result<Vec<Sample>, RenderTrackError> {
+        let state = self
+            .processor_interface
+            .isolated_track_state(key, false)
+            .map_err(RenderTrackError::InvalidTimelineTrack)?;
+        let sample_rate = self.config.output_config.sample_rate;
+        render(&state, sample_rate, start, end).map_err(RenderTrackError::Import)
+    }
+
+    /// Render the full mix offline from `start` to `end`, yielding the interleaved output at the engine's sample rate
+    /// in chunks of `chunk_frames` frames, apart from the last one which may be shorter.
+    ///
+    /// Each chunk is only rendered once it is requested, so long renders can be passed on to e.g. an encoder
+    /// without ever holding all of the output at once.
+    /// The render works on a snapshot of the engine, so later changes to the engine do not affect it.
+    /// The loop region is ignored, and a `chunk_frames` of zero is treated as one.
+    /// If `end` is not after `start`, nothing is yielded.
+    ///
+    /// # Errors
+    /// - [`ImportError`] when the clips could not be loaded for rendering.
+    pub fn render_iter(
+        &self,
+        start: Timestamp,
+        end: Timestamp,
+        chunk_frames: usize,
+    ) -> Result<RenderIter, ImportError> {
+        let mut state = self.processor_interface.state();
+        state.timeline.loop_region = None;
+        let sample_rate = self.config.output_config.sample_rate;
+        render_iter(&state, sample_rate, start, end, chunk_frames)
+    }
+
+    /// Render each audio track offline from `start` to `end`, through the effects of its mixer track,
+    /// and save each as a separate .wav file in the existing directory `dir`, named after the key of the audio track.
+    ///
+    /// All files span the same range, so they line up when imported elsewhere.
+    /// As with [`Engine::freeze_track`], the volume, panning and sends of the mixer tracks are not rendered.
+    /// If `respect_mute_solo` is true, tracks that are muted or silenced by another track's solo are skipped.
+    /// The files are written in the given `format`, dithered according to [`Config::dither`] if it is 16-bit.
+    ///
+    /// Returns the path of each rendered track, in the order of [`Engine::audio_tracks`].
+    ///
+    /// # Errors
+    /// - [`RenderStemsError::Import`] when the clips could not be loaded for rendering.
+    /// - [`RenderStemsError::Save`] when a rendered track could not be written to `dir`.
+    pub fn render_stems(
+        &self,
+        dir: &Path,
+        start: Timestamp,
+        end: Timestamp,
+        respect_mute_solo: bool,
+        format: WavFormat,
+    ) -> Result<Vec<(AudioTrackKey, PathBuf)>, RenderStemsError> {
+        let mixer_track = |key| {
+            self.mixer_track(key)
+                .expect("Audio track has no mixer track")
+        };
+        let any_soloed = self
+            .audio_tracks
+            .values()
+            .any(|&(_, mixer_track_key)| mixer_track(mixer_track_key).soloed());
+
+        let audio_tracks: Vec<_> = self
+            .audio_tracks()
+            .map(|key| {
+                let (timeline_track_key, mixer_track_key) = self.audio_tracks[&key];
+                (key, timeline_track_key, mixer_track_key)
+            })
+            .filter(|&(_, _, mixer_track_key)| {
+                let track = mixer_track(mixer_track_key);
+                let silenced = track.muted() || (any_soloed && !track.soloed());
+                !respect_mute_solo || !silenced
+            })
+            .collect();
+
+        let sample_rate = self.config.output_config.sample_rate;
+        let mut stems = Vec::with_capacity(audio_tracks.len());
+        for (key, timeline_track_key, _) in audio_tracks {
+            let state = self
+                .processor_interface
+                .isolated_track_state(timeline_track_key, true)
+                .expect("Audio track has no timeline track");
+            let samples =
+                render(&state, sample_rate, start, end).map_err(RenderStemsError::Import)?;
+
+            let path = dir.join(format!("audio_track_{}.wav", u32::from(key)));
+            Recording::from_interleaved(&samples, CHANNELS, sample_rate)
+                .save(&path, format, self.config.dither)
+                .map_err(|e| RenderStemsError::Save(e.to_string()))?;
+            stems.push((key, path));
+        }
+        Ok(stems)
+    }
+
     /// Get the current state of the engine.
     ///
     /// This can be used to recreate this exact state at a later time using [`Engine::new()`].
     pub fn state(&self) -> EngineState {
+        // Kept in order, which also means saving the same engine twice gives the same result
+        let audio_tracks = self
+            .audio_tracks()
+            .map(|key| {
+                let (timeline_track_key, mixer_track_key) = self.audio_tracks[&key];
+                (key, timeline_track_key, mixer_track_key)
+            })
+            .collect();
+
         EngineState {
             processor: self.processor_interface.state(),
-
-            audio_tracks: self
-                .audio_tracks
-                .iter()
-                .map(
-                    |(&audio_track_key, &(timeline_track_key, mixer_track_key))| {
-                        (audio_track_key, timeline_track_key, mixer_track_key)
-                    },
-                )
-                .collect(),
+            audio_tracks,
         }
     }
 }
@@ -1015,59 +3005,55 @@ impl Drop for Engine {
 ///
 /// This can be used to recreate this exact state at a later time,
 /// suitable for saving to a file.
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
 pub struct EngineState {
     processor: ProcessorState,
 
     // AudioTrackState is not used because the individual track's states are kept in the mixer and timeline's state.
+    /// In the order of [`Engine::audio_tracks`].
     audio_tracks: Vec<(AudioTrackKey, TimelineTrackKey, MixerTrackKey)>,
 }
-impl PartialEq for EngineState {
-    fn eq(&self, other: &Self) -> bool {
-        let self_set: HashSet<_> = HashSet::from_iter(self.audio_tracks.iter());
-        let other_set = HashSet::from_iter(other.audio_tracks.iter());
-
-        debug_assert_eq!(
-            self_set.len(),
-            self.audio_tracks.len(),
-            "Duplicate audio tracks in EngineState: {:?}",
-            self.audio_tracks
-        );
-        debug_assert_eq!(
-            other_set.len(),
-            other.audio_tracks.len(),
-            "Duplicate audio tracks in EngineState: {:?}",
-            other.audio_tracks
-        );
-
-        self.processor == other.processor && self_set == other_set
-    }
-}
-impl Eq for EngineState {}
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct AudioTrackState {
     key: AudioTrackKey,
+    /// The position of the track in [`Engine::audio_tracks`].
+    index: usize,
     timeline_track_state: TimelineTrackState,
     mixer_track_state: MixerTrackState,
 }
 
+/// The result of [`Engine::freeze_track`], containing what is needed to undo the freeze.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrozenTrack {
+    /// The imported frozen audio.
+    pub stored_clip: StoredAudioClipKey,
+    /// The clip playing the frozen audio, if it replaced the clips on the track.
+    pub clip: Option<AudioClipKey>,
+    /// The clips that were replaced.
+    pub clips: Vec<AudioClipState>,
+    /// The effects that were removed from the mixer track, in the order they were applied.
+    pub effects: Vec<EffectState>,
+}
+
 /// Scaling used by [`MixerTrack::read_meter`]
 ///
 /// `∛|sample / 2|`
-pub fn meter_scale(sample: Sample) -> Sample {
+pub fn meter_scale(sample: f32) -> f32 {
     (sample / 2.0).abs().powf(1.0 / 3.0)
 }
 /// Approximate inverse of scaling used by [`MixerTrack::read_meter`]
 ///
 /// `2 ⋅ value³`
-pub fn inverse_meter_scale(value: Sample) -> Sample {
+pub fn inverse_meter_scale(value: f32) -> f32 {
     value.powi(3) * 2.0
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum InvalidConfigError {
     DeviceNotAvailable,
+    /// The output must have either two channels, or a single channel which the output is downmixed to.
+    UnsupportedChannels(u16),
     Other,
 }
 impl Display for InvalidConfigError {
@@ -1077,12 +3063,62 @@ impl Display for InvalidConfigError {
                 f,
                 "Engine received unsupported conifguration: Device is not available"
             ),
+            InvalidConfigError::UnsupportedChannels(channels) => write!(
+                f,
+                "Engine received unsupported conifguration: Output can not have {channels} channels"
+            ),
             InvalidConfigError::Other => write!(f, "Engine received unsupported conifguration"),
         }
     }
 }
 impl Error for InvalidConfigError {}
 
+/// An error that occurred while the stream was running, causing it to stop.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamError {
+    /// The device is no longer available, e.g. because it has been disconnected.
+    DeviceNotAvailable,
+    /// An error specific to the host, described by the message.
+    Other(String),
+    /// The processing on the audio thread panicked with the given message.
+    ///
+    /// This is a bug in the engine, and the output is silenced, since its state can not be trusted.
+    Panicked(String),
+}
+impl From<cpal::StreamError> for StreamError {
+    fn from(err: cpal::StreamError) -> Self {
+        match err {
+            cpal::StreamError::DeviceNotAvailable => Self::DeviceNotAvailable,
+            cpal::StreamError::BackendSpecific { err } => Self::Other(err.description),
+        }
+    }
+}
+impl Display for StreamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StreamError::DeviceNotAvailable => {
+                write!(f, "Stream stopped: Device is no longer available")
+            }
+            StreamError::Other(description) => write!(f, "Stream stopped: {description}"),
+            StreamError::Panicked(msg) => write!(f, "Stream stopped: Audio thread panicked: {msg}"),
+        }
+    }
+}
+impl Error for StreamError {}
+
+/// The audio thread has panicked, see [`Engine::is_stream_dead`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamDeadError;
+impl Display for StreamDeadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The audio thread has panicked, and the stream must be restarted"
+        )
+    }
+}
+impl Error for StreamDeadError {}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum AudioTrackOverflowError {
     MixerTracks(MixerTrackOverflowError),
@@ -1125,6 +3161,112 @@ impl Display for InvalidAudioTracksError {
 }
 impl Error for InvalidAudioTracksError {}
 
+#[derive(Debug, PartialEq, Eq)]
+pub enum StartRecordingError {
+    NoInputDevice,
+    NoArmedTrack,
+    AlreadyRecording,
+    StreamDead(StreamDeadError),
+}
+impl Display for StartRecordingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoInputDevice => write!(f, "No input device is configured"),
+            Self::NoArmedTrack => write!(f, "No track is armed for recording"),
+            Self::AlreadyRecording => write!(f, "A recording is already in progress"),
+            Self::StreamDead(e) => Display::fmt(e, f),
+        }
+    }
+}
+impl Error for StartRecordingError {}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum StopRecordingError {
+    NotRecording,
+    /// The track was disarmed or deleted while recording, so the recording has been discarded.
+    NoArmedTrack,
+    Save(String),
+    Import(ImportError),
+    AddClip(AddClipError),
+}
+impl Display for StopRecordingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotRecording => write!(f, "No recording is in progress"),
+            Self::NoArmedTrack => write!(f, "No track is armed for recording"),
+            Self::Save(msg) => write!(f, "Recording could not be saved: {msg}"),
+            Self::Import(e) => Display::fmt(e, f),
+            Self::AddClip(e) => Display::fmt(e, f),
+        }
+    }
+}
+impl Error for StopRecordingError {}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum StopOutputCaptureError {
+    NotCapturing,
+    Save(String),
+}
+impl Display for StopOutputCaptureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotCapturing => write!(f, "The output is not being captured"),
+            Self::Save(msg) => write!(f, "Captured output could not be saved: {msg}"),
+        }
+    }
+}
+impl Error for StopOutputCaptureError {}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FreezeTrackError {
+    InvalidAudioTrack(InvalidAudioTrackError),
+    /// There are no clips on the track to freeze.
+    Empty,
+    Save(String),
+    Import(ImportError),
+}
+impl Display for FreezeTrackError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidAudioTrack(e) => Display::fmt(e, f),
+            Self::Empty => write!(f, "There are no clips on the track to freeze"),
+            Self::Save(msg) => write!(f, "Frozen track could not be saved: {msg}"),
+            Self::Import(e) => Display::fmt(e, f),
+        }
+    }
+}
+impl Error for FreezeTrackError {}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RenderTrackError {
+    InvalidTimelineTrack(InvalidTimelineTrackError),
+    Import(ImportError),
+}
+impl Display for RenderTrackError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidTimelineTrack(e) => Display::fmt(e, f),
+            Self::Import(e) => Display::fmt(e, f),
+        }
+    }
+}
+impl Error for RenderTrackError {}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RenderStemsError {
+    Save(String),
+    Import(ImportError),
+}
+impl Display for RenderStemsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Save(msg) => write!(f, "Rendered track could not be saved: {msg}"),
+            Self::Import(e) => Display::fmt(e, f),
+        }
+    }
+}
+impl Error for RenderStemsError {}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum AudioTrackReconstructionError {
     AudioTracks(AudioTrackKey),
@@ -1141,3 +3283,40 @@ impl Display for AudioTrackReconstructionError {
     }
 }
 impl Error for AudioTrackReconstructionError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn panicked_stream_is_dead() {
+        let mut e = Engine::dummy();
+        assert!(!e.is_stream_dead());
+        assert_eq!(e.play(), Ok(()));
+
+        e.stream_status.report_panic(Box::new("Oh no"));
+        assert!(e.is_stream_dead());
+        assert_eq!(
+            e.stream_error(),
+            Some(StreamError::Panicked("Oh no".to_string()))
+        );
+        assert_eq!(e.play(), Err(StreamDeadError));
+        assert_eq!(e.jump_to_next_marker(), Err(StreamDeadError));
+        assert_eq!(
+            e.start_recording(),
+            Err(StartRecordingError::StreamDead(StreamDeadError))
+        );
+
+        // Only the first error is kept
+        e.stream_status.report(StreamError::DeviceNotAvailable);
+        e.stream_status.report_panic(Box::new("Oh no, again"));
+        assert_eq!(
+            e.stream_error(),
+            Some(StreamError::Panicked("Oh no".to_string()))
+        );
+
+        // Changes to the project are kept for the restart
+        e.set_bpm_cents(150_00).unwrap();
+        assert_eq!(e.bpm_cents(), 150_00);
+    }
+}
@@ -1,12 +1,24 @@
 pub mod audio_meter;
+mod automation;
+mod dc_blocker;
 mod delay;
+pub mod effect;
+pub mod limiter;
+pub mod loudness_meter;
+pub mod metronome;
+pub mod output_capture;
+pub mod recorder;
+pub mod saturation;
+mod spectrum_analyzer;
 mod track;
 pub use track::{MixerTrack, MixerTrackKey, MixerTrackState};
 pub mod mixer;
 mod mixing;
+pub mod noise;
 pub use mixing::MixPoint;
 mod audio_clip_reader;
 pub mod audio_clip_store;
 mod parameter;
 pub mod stored_audio_clip;
+pub mod test_tone;
 pub mod timeline;
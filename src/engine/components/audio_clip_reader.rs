@@ -6,19 +6,34 @@ use std::{
     sync::Arc,
 };
 
-use rubato::{FftFixedOut, Resampler};
+use rubato::{
+    FastFixedOut, FftFixedOut, PolynomialDegree, Resampler, ResamplerConstructionError,
+    SincFixedOut, SincInterpolationParameters, SincInterpolationType, WindowFunction,
+};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    engine::{info::Info, utils::non_copy_array, Sample, CHANNELS},
+    engine::{config::ResampleQuality, info::Info, utils::non_copy_array, Sample, CHANNELS},
     StoredAudioClipKey,
 };
 
 use super::stored_audio_clip::{StoredAudioClip, SAMPLES_PER_WAVEFORM_CHUNK};
+use streamer::Streamer;
+
+mod streamer;
+
+/// Pitch shifted clips are played back at a multiple of this sample rate,
+/// keeping the resampler's FFTs at a reasonable size while still allowing shifts by single cents.
+const PITCHED_SAMPLE_RATE_STEP: u32 = 10;
 
 pub struct AudioClipReader {
     inner: Arc<StoredAudioClip>,
-    resampler: Option<FftFixedOut<Sample>>,
+    /// Is `Some` if the inner clip is streamed from disk.
+    streamer: Option<Streamer>,
+    resampler: Option<ClipResampler>,
+    /// The sample rate the inner clip is played back at.
+    /// This differs from the clip's own sample rate if it is pitch shifted.
+    playback_sample_rate: u32,
 
     /// The position in the inner clip where the resampler will draw from.
     /// If `resampler` is none, this is not used.
@@ -26,6 +41,8 @@ pub struct AudioClipReader {
     /// The position as it would be appear from the output,
     /// in the domain of the output sample rate.
     position: ResampledSamples,
+    /// If `Some`, the inner clip is read backwards, such that position `p` reads the sample at `reflection - 1 - p`.
+    reflection: Option<OriginalSamples>,
 
     channel_scale_buffer: [Vec<Sample>; CHANNELS],
     /// How many frames in the resample buffer are unused (at the end)
@@ -34,21 +51,42 @@ pub struct AudioClipReader {
     output_buffer: Vec<Sample>,
 }
 impl AudioClipReader {
+    #[cfg(test)]
     pub fn new(clip: Arc<StoredAudioClip>, max_buffer_size: usize, sample_rate: u32) -> Self {
+        Self::with_quality(
+            clip,
+            max_buffer_size,
+            sample_rate,
+            0.0,
+            ResampleQuality::default(),
+        )
+    }
+
+    /// Creates a reader which shifts the pitch of the clip `semitones` up,
+    /// by playing it back at a different sample rate.
+    /// This also changes the speed of the clip, which should be compensated for elsewhere.
+    ///
+    /// If the clip has to be resampled, this is done at the given `quality`.
+    pub fn with_quality(
+        clip: Arc<StoredAudioClip>,
+        max_buffer_size: usize,
+        sample_rate: u32,
+        semitones: f32,
+        quality: ResampleQuality,
+    ) -> Self {
         let resampler_chunk_size = 1024;
 
-        let clip_sample_rate: usize = clip
-            .sample_rate()
+        let playback_sample_rate = pitched_sample_rate(clip.sample_rate(), semitones);
+        let clip_sample_rate: usize = playback_sample_rate
             .try_into()
             .expect("Clip sample rate too high");
 
         let (resampler, max_input_size, delay) = if clip_sample_rate != sample_rate as usize {
-            let r = FftFixedOut::new(
+            let r = ClipResampler::new(
+                quality,
                 clip_sample_rate,
                 sample_rate as usize,
                 resampler_chunk_size,
-                1,
-                CHANNELS,
             )
             .expect("Failed to create resampler");
             let m = r.input_frames_max();
@@ -58,12 +96,22 @@ impl AudioClipReader {
             (None, max_buffer_size, ResampledSamples::new(0))
         };
 
+        let streamer = clip.streaming_source().map(|(path, max_cached_frames)| {
+            let downmix = clip
+                .streaming_downmix()
+                .expect("Streamed clip without downmix");
+            Streamer::new(path, clip.channels(), max_cached_frames, downmix)
+        });
+
         let mut audio_clip_reader = AudioClipReader {
             inner: clip,
+            streamer,
             resampler,
+            playback_sample_rate,
 
             inner_position: OriginalSamples::new(0),
             position: ResampledSamples::new(0),
+            reflection: None,
 
             channel_scale_buffer: non_copy_array![vec![0.0; max_input_size]; CHANNELS],
             resample_buffer_unused: ResampledSamples::new(0),
@@ -85,10 +133,20 @@ impl AudioClipReader {
         self.inner.sample_rate()
     }
 
+    /// How many times faster the inner clip is played back, due to pitch shifting.
+    pub fn playback_speed(&self) -> f64 {
+        self.playback_sample_rate as f64 / self.inner.sample_rate() as f64
+    }
+
     pub fn channels_original(&self) -> usize {
         self.inner.channels()
     }
 
+    /// Whether the inner clip is played back at a different sample rate than the output, and so has to be resampled.
+    pub fn resampled(&self) -> bool {
+        self.resampler.is_some()
+    }
+
     /// Throws out the given delay from the start of the clip.
     /// Remember to set `self.position` after calling this.
     fn chop_delay(&mut self, delay: ResampledSamples, sample_rate: u32) {
@@ -111,6 +169,29 @@ impl AudioClipReader {
         });
     }
 
+    /// The largest buffer size that can be requested from [`Self::output`].
+    pub fn max_buffer_size(&self) -> usize {
+        self.output_buffer.len() / CHANNELS
+    }
+
+    /// Whether the inner clip is streamed from disk, rather than kept in memory.
+    pub fn streamed(&self) -> bool {
+        self.streamer.is_some()
+    }
+
+    /// Read the given region of the inner clip backwards, or everything forwards if `None`.
+    ///
+    /// Positions are unaffected, so reading from the start of the region yields its last sample and vice versa.
+    /// Positions outside of the region are mirrored as well, which keeps the resampler fed across its bounds.
+    ///
+    /// Clips streamed from disk can only be read forwards, so for these this does nothing.
+    pub fn set_reversed_region(&mut self, region: Option<Range<OriginalSamples>>) {
+        if self.streamed() {
+            return;
+        }
+        self.reflection = region.map(|region| region.start + region.end);
+    }
+
     /// Returns the current position in samples relative to the start of the clip, within the given `sample_rate`.
     pub fn position(&self) -> ResampledSamples {
         self.position
@@ -123,7 +204,7 @@ impl AudioClipReader {
     pub fn jump_original(&mut self, position: OriginalSamples, sample_rate: u32) {
         let desired_pos_original = position;
         let desired_pos_resampled =
-            desired_pos_original.into_resampled(sample_rate, self.inner.sample_rate());
+            desired_pos_original.into_resampled(sample_rate, self.playback_sample_rate);
 
         self.jump(desired_pos_original, desired_pos_resampled, sample_rate)
     }
@@ -154,12 +235,16 @@ impl AudioClipReader {
 
         self.position = pos_resample;
         self.inner_position = pos_original;
+
+        if let Some(streamer) = &mut self.streamer {
+            streamer.seek(pos_original.into());
+        }
     }
 
     /// The length of the inner clip in frames (samples per channel), converted relative to the given sample rate.
     pub fn len_resampled(&self, sample_rate: u32) -> ResampledSamples {
         self.len_original()
-            .into_resampled(sample_rate, self.inner.sample_rate())
+            .into_resampled(sample_rate, self.playback_sample_rate)
     }
     /// The length of the inner clip in frames (samples per channel), before resampling.
     pub fn len_original(&self) -> OriginalSamples {
@@ -179,6 +264,45 @@ impl AudioClipReader {
         &self.inner.waveform_data()[start..start + length]
     }
 
+    /// Reads `range` of the inner clip into `output`, either from memory or from the streamer.
+    ///
+    /// If `reflection` is `Some`, the range is mirrored around it and read backwards.
+    ///
+    /// See [`Self::scale_channels`].
+    fn read_channels(
+        inner: &StoredAudioClip,
+        streamer: &mut Option<Streamer>,
+        reflection: Option<OriginalSamples>,
+        range: Range<OriginalSamples>,
+        output: &mut [&mut [Sample]; CHANNELS],
+    ) {
+        if let Some(reflection) = reflection {
+            let data = inner
+                .audio_data()
+                .expect("Streamed clip was read backwards");
+
+            // Anything mirrored past the end of the inner clip is silent
+            let len = OriginalSamples::new(inner.length());
+            let mirrored_end = reflection.saturating_sub(range.start);
+            let mirrored = min(reflection.saturating_sub(range.end), len)..min(mirrored_end, len);
+            let read: usize = (mirrored.end - mirrored.start).into();
+            let skipped: usize = min(range.end - range.start, mirrored_end - mirrored.end).into();
+
+            Self::scale_channels(data, mirrored, output);
+            for channel in output.iter_mut() {
+                channel[..read].reverse();
+                channel[..read + skipped].rotate_right(skipped);
+            }
+            return;
+        }
+
+        match (inner.audio_data(), streamer) {
+            (Some(data), _) => Self::scale_channels(data, range, output),
+            (None, Some(streamer)) => streamer.read(range.start.into()..range.end.into(), output),
+            (None, None) => unreachable!("Streamed clip has no streamer"),
+        }
+    }
+
     /// Scales `range` of each channel in `input` to down to two channels and writes it to `output`.
     ///
     /// `input` should have either 1 or two channels.
@@ -262,7 +386,7 @@ impl AudioClipReader {
         // self.len_original() cannot be used, since self is already borrowed by resampler
         let len_original = OriginalSamples::new(self.inner.length());
 
-        let resampled_length = len_original.into_resampled(sample_rate, self.inner.sample_rate());
+        let resampled_length = len_original.into_resampled(sample_rate, self.playback_sample_rate);
         let remaining = resampled_length - self.position;
 
         let output_size = min(buffer_size, remaining);
@@ -284,13 +408,16 @@ impl AudioClipReader {
                     &mut c.next().unwrap()[..resampler.input_frames_next()],
                     &mut c.next().unwrap()[..resampler.input_frames_next()],
                 ];
-                Self::scale_channels(self.inner.audio_data(), range, &mut channel_scale_buffer);
-
-                let result = resampler.process_into_buffer(
-                    &channel_scale_buffer,
-                    &mut self.resample_buffer,
-                    None,
+                Self::read_channels(
+                    &self.inner,
+                    &mut self.streamer,
+                    self.reflection,
+                    range,
+                    &mut channel_scale_buffer,
                 );
+
+                let result =
+                    resampler.process_into_buffer(&channel_scale_buffer, &mut self.resample_buffer);
                 debug_assert!(result.is_ok(), "Resampler error: {:?}", result);
                 self.resample_buffer_unused = resample_chunk_size;
             }
@@ -337,7 +464,13 @@ impl AudioClipReader {
             &mut c.next().unwrap()[..output_size.into()],
             &mut c.next().unwrap()[..output_size.into()],
         ];
-        Self::scale_channels(self.inner.audio_data(), range, &mut channel_scale_buffer);
+        Self::read_channels(
+            &self.inner,
+            &mut self.streamer,
+            self.reflection,
+            range,
+            &mut channel_scale_buffer,
+        );
         Self::interleave(
             &self.channel_scale_buffer,
             ResampledSamples::new(0)..ResampledSamples::new(output_size.into()),
@@ -357,6 +490,102 @@ impl AudioClipReader {
         }
     }
 }
+/// One of rubato's resamplers, as picked by a [`ResampleQuality`].
+enum ClipResampler {
+    Linear(FastFixedOut<Sample>),
+    Sinc(SincFixedOut<Sample>),
+    Fft(FftFixedOut<Sample>),
+}
+impl ClipResampler {
+    /// Creates a resampler which outputs `chunk_size` frames at a time.
+    fn new(
+        quality: ResampleQuality,
+        input_sample_rate: usize,
+        output_sample_rate: usize,
+        chunk_size: usize,
+    ) -> Result<Self, ResamplerConstructionError> {
+        let ratio = output_sample_rate as f64 / input_sample_rate as f64;
+        Ok(match quality {
+            ResampleQuality::Linear => ClipResampler::Linear(FastFixedOut::new(
+                ratio,
+                1.0,
+                PolynomialDegree::Linear,
+                chunk_size,
+                CHANNELS,
+            )?),
+            ResampleQuality::Sinc => ClipResampler::Sinc(SincFixedOut::new(
+                ratio,
+                1.0,
+                SincInterpolationParameters {
+                    sinc_len: 128,
+                    f_cutoff: 0.95,
+                    oversampling_factor: 256,
+                    interpolation: SincInterpolationType::Linear,
+                    window: WindowFunction::BlackmanHarris2,
+                },
+                chunk_size,
+                CHANNELS,
+            )?),
+            ResampleQuality::Fft => ClipResampler::Fft(FftFixedOut::new(
+                input_sample_rate,
+                output_sample_rate,
+                chunk_size,
+                1,
+                CHANNELS,
+            )?),
+        })
+    }
+
+    fn input_frames_max(&self) -> usize {
+        match self {
+            ClipResampler::Linear(r) => r.input_frames_max(),
+            ClipResampler::Sinc(r) => r.input_frames_max(),
+            ClipResampler::Fft(r) => r.input_frames_max(),
+        }
+    }
+    fn input_frames_next(&self) -> usize {
+        match self {
+            ClipResampler::Linear(r) => r.input_frames_next(),
+            ClipResampler::Sinc(r) => r.input_frames_next(),
+            ClipResampler::Fft(r) => r.input_frames_next(),
+        }
+    }
+    fn output_frames_max(&self) -> usize {
+        match self {
+            ClipResampler::Linear(r) => r.output_frames_max(),
+            ClipResampler::Sinc(r) => r.output_frames_max(),
+            ClipResampler::Fft(r) => r.output_frames_max(),
+        }
+    }
+    /// The number of frames the output lags behind the input.
+    fn output_delay(&self) -> usize {
+        match self {
+            ClipResampler::Linear(r) => r.output_delay(),
+            ClipResampler::Sinc(r) => r.output_delay(),
+            ClipResampler::Fft(r) => r.output_delay(),
+        }
+    }
+    fn reset(&mut self) {
+        match self {
+            ClipResampler::Linear(r) => r.reset(),
+            ClipResampler::Sinc(r) => r.reset(),
+            ClipResampler::Fft(r) => r.reset(),
+        }
+    }
+
+    fn process_into_buffer(
+        &mut self,
+        input: &[&mut [Sample]; CHANNELS],
+        output: &mut [Vec<Sample>; CHANNELS],
+    ) -> rubato::ResampleResult<(usize, usize)> {
+        match self {
+            ClipResampler::Linear(r) => r.process_into_buffer(input, output, None),
+            ClipResampler::Sinc(r) => r.process_into_buffer(input, output, None),
+            ClipResampler::Fft(r) => r.process_into_buffer(input, output, None),
+        }
+    }
+}
+
 impl Debug for AudioClipReader {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("AudioClipReader")
@@ -367,6 +596,16 @@ impl Debug for AudioClipReader {
     }
 }
 
+/// The sample rate a clip recorded at `sample_rate` should be played back at, to shift it `semitones` up.
+fn pitched_sample_rate(sample_rate: u32, semitones: f32) -> u32 {
+    if semitones == 0.0 {
+        return sample_rate;
+    }
+    let pitched = sample_rate as f64 * 2_f64.powf(semitones as f64 / 12.0);
+    let steps = (pitched / PITCHED_SAMPLE_RATE_STEP as f64).round() as u32;
+    steps * PITCHED_SAMPLE_RATE_STEP
+}
+
 /// A number of samples in the domain of the clip's original sample rate.
 #[repr(transparent)]
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Serialize, Deserialize, Hash)]
@@ -574,6 +813,40 @@ mod tests {
         }
     }
 
+    #[test]
+    fn output_resampling_qualities() {
+        for quality in [
+            ResampleQuality::Linear,
+            ResampleQuality::Sinc,
+            ResampleQuality::Fft,
+        ] {
+            let ac = StoredAudioClip::import(
+                StoredAudioClipKey::new(0),
+                &test_file_path("48000 16-bit.wav"),
+            )
+            .unwrap();
+            let mut acr = AudioClipReader::with_quality(Arc::new(ac), 2000, 44_100, 0.0, quality);
+
+            let output = acr.output(&Info {
+                sample_rate: 44_100,
+                buffer_size: 2000,
+            });
+
+            // The left channel flips every 50 frames of the original,
+            // so the middle of each half period should have the right sign if the delay is compensated for
+            let half_period = 50.0 * 44_100.0 / 48_000.0;
+            for k in 1..40 {
+                let frame = ((k as f64 + 0.5) * half_period) as usize;
+                let ls = output[frame * CHANNELS];
+                let expected = if k % 2 == 0 { 1.0 } else { -1.0 };
+                assert!(
+                    (ls - expected).abs() < 0.2,
+                    "{quality:?}, frame {frame}: {ls}"
+                );
+            }
+        }
+    }
+
     #[test]
     fn output_big_buffer() {
         let ac = StoredAudioClip::import(
@@ -736,4 +1009,132 @@ mod tests {
 
         assert_eq!(acr.position(), ResampledSamples::new(1_322_978));
     }
+
+    #[test]
+    fn output_reversed() {
+        let ac = Arc::new(
+            StoredAudioClip::import(
+                StoredAudioClipKey::new(0),
+                &test_file_path("48000 16-bit.wav"),
+            )
+            .unwrap(),
+        );
+        let info = Info {
+            sample_rate: 48_000,
+            buffer_size: 300,
+        };
+
+        let mut forward = AudioClipReader::new(Arc::clone(&ac), 300, 48_000);
+        forward.jump_original(OriginalSamples::new(1000), 48_000);
+        let expected: Vec<_> = forward
+            .output(&info)
+            .chunks_exact(CHANNELS)
+            .rev()
+            .flatten()
+            .copied()
+            .collect();
+
+        let mut reversed = AudioClipReader::new(ac, 300, 48_000);
+        reversed.set_reversed_region(Some(OriginalSamples::new(1000)..OriginalSamples::new(1300)));
+        reversed.jump_original(OriginalSamples::new(1000), 48_000);
+        no_heap! {{
+            let output = reversed.output(&info);
+            assert_eq!(output, &expected[..]);
+        }}
+    }
+
+    #[test]
+    fn output_reversed_resampling() {
+        let ac = Arc::new(
+            StoredAudioClip::import(
+                StoredAudioClipKey::new(0),
+                &test_file_path("44100 16-bit.wav"),
+            )
+            .unwrap(),
+        );
+        let info = Info {
+            sample_rate: 48_000,
+            buffer_size: 2050,
+        };
+
+        let mut reversed = AudioClipReader::new(ac, 2050, 48_000);
+        let len = reversed.len_original();
+        reversed.set_reversed_region(Some(OriginalSamples::new(0)..len));
+        reversed.jump_original(OriginalSamples::new(0), 48_000);
+
+        for _ in 0..3 {
+            let output = reversed.output(&info);
+            assert_eq!(output.len(), 2050 * CHANNELS);
+            for &mut s in output {
+                assert_ne!(s, 0.0);
+            }
+        }
+    }
+
+    fn streaming_reader(file: &str, buffer_size: usize) -> (AudioClipReader, AudioClipReader) {
+        let path = test_file_path(file);
+        let loaded = StoredAudioClip::import(StoredAudioClipKey::new(0), &path).unwrap();
+        let streamed =
+            StoredAudioClip::import_streaming(StoredAudioClipKey::new(0), &path, 20_000).unwrap();
+
+        let loaded_reader = AudioClipReader::new(Arc::new(loaded), buffer_size, 48_000);
+        let streamed_reader = AudioClipReader::new(Arc::new(streamed), buffer_size, 48_000);
+        (loaded_reader, streamed_reader)
+    }
+
+    /// Block until the decoder thread has caught up with the reader.
+    fn wait_for_decoder(reader: &AudioClipReader) {
+        let streamer = reader.streamer.as_ref().unwrap();
+        while !streamer.is_filled() {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn output_streaming() {
+        let (mut loaded, mut streamed) = streaming_reader("48000 16-bit.wav", 1000);
+        let info = Info {
+            sample_rate: 48_000,
+            buffer_size: 1000,
+        };
+
+        // More than the cache can hold at once
+        for _ in 0..60 {
+            wait_for_decoder(&streamed);
+            assert_eq!(loaded.output(&info), streamed.output(&info));
+        }
+    }
+
+    #[test]
+    fn output_streaming_resampling() {
+        let (mut loaded, mut streamed) = streaming_reader("44100 16-bit.wav", 1000);
+        let info = Info {
+            sample_rate: 48_000,
+            buffer_size: 1000,
+        };
+
+        for _ in 0..60 {
+            wait_for_decoder(&streamed);
+            assert_eq!(loaded.output(&info), streamed.output(&info));
+        }
+    }
+
+    #[test]
+    fn jump_streaming() {
+        let (mut loaded, mut streamed) = streaming_reader("48000 16-bit.wav", 1000);
+        let info = Info {
+            sample_rate: 48_000,
+            buffer_size: 1000,
+        };
+
+        for position in [500_000, 1_000, 1_300_000] {
+            loaded.jump_original(OriginalSamples::new(position), 48_000);
+            streamed.jump_original(OriginalSamples::new(position), 48_000);
+            wait_for_decoder(&streamed);
+
+            for _ in 0..3 {
+                assert_eq!(loaded.output(&info), streamed.output(&info));
+            }
+        }
+    }
 }
@@ -0,0 +1,379 @@
+use std::{
+    cmp::{max, min},
+    ops::Range,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread::{self, Thread},
+    time::Duration,
+};
+
+use ringbuf::{
+    traits::{Consumer, Observer, Producer, Split},
+    HeapCons, HeapProd, HeapRb,
+};
+
+use crate::engine::{
+    components::stored_audio_clip::{Downmix, Downmixer, OpenedFile},
+    Sample, CHANNELS,
+};
+
+/// Number of frames decoded at a time by the background thread.
+const CHUNK_FRAMES: usize = 4096;
+
+/// Reads a clip from disk, by decoding it ahead of the playback position in a background thread.
+///
+/// Decoded audio is handed over in chunks through a bounded ringbuffer,
+/// so reading never blocks or allocates.
+/// If the decoder falls behind, the missing frames are read as silence.
+pub struct Streamer {
+    shared: Arc<Shared>,
+    decoder_thread: Thread,
+
+    chunks: HeapCons<Box<Chunk>>,
+    recycled: HeapProd<Box<Chunk>>,
+    current: Option<Box<Chunk>>,
+
+    generation: usize,
+}
+impl Streamer {
+    /// Start decoding the file at `path` from the beginning,
+    /// keeping roughly at most `max_cached_frames` frames decoded ahead.
+    ///
+    /// If the file has more than two channels, it is mixed down with `downmix`.
+    pub fn new(path: &Path, channels: usize, max_cached_frames: usize, downmix: &Downmix) -> Self {
+        let chunk_count = max(2, max_cached_frames.div_ceil(CHUNK_FRAMES));
+
+        let (chunks_producer, chunks_consumer) = HeapRb::new(chunk_count).split();
+        // Room for every chunk in circulation, so pushing never fails
+        let (recycled_producer, recycled_consumer) = HeapRb::new(chunk_count + 2).split();
+
+        let shared = Arc::new(Shared {
+            seek_to: AtomicUsize::new(0),
+            generation: AtomicUsize::new(0),
+            end_of_file: AtomicUsize::new(usize::MAX),
+            stopped: AtomicBool::new(false),
+        });
+
+        let join_handle = match OpenedFile::open(path) {
+            Ok(file) => {
+                let mut decoder = Decoder {
+                    file,
+                    channels,
+                    downmix: downmix.clone(),
+                    downmixer: None,
+                    shared: Arc::clone(&shared),
+                    chunks: chunks_producer,
+                    recycled: recycled_consumer,
+
+                    generation: 0,
+                    position: 0,
+                    target: 0,
+                    end_of_file: false,
+
+                    decoded: vec![Vec::new(); channels],
+                    decoded_offset: 0,
+                    chunk: None,
+                };
+
+                // Fill the cache up front, so playback can start right away
+                while decoder.step() {}
+
+                thread::spawn(move || decoder.run())
+            }
+            // The file has been moved or deleted since it was imported, so there is nothing to play
+            Err(_) => thread::spawn(|| {}),
+        };
+
+        Streamer {
+            shared,
+            decoder_thread: join_handle.thread().clone(),
+
+            chunks: chunks_consumer,
+            recycled: recycled_producer,
+            current: None,
+
+            generation: 0,
+        }
+    }
+
+    /// Make the decoder continue from `frame`.
+    pub fn seek(&mut self, frame: usize) {
+        // Make room for the decoder to continue right away
+        self.recycle_current();
+        while let Some(chunk) = self.chunks.try_pop() {
+            self.current = Some(chunk);
+            self.recycle_current();
+        }
+
+        self.generation = self.generation.wrapping_add(1);
+        self.shared.seek_to.store(frame, Ordering::Release);
+        self.shared
+            .generation
+            .store(self.generation, Ordering::Release);
+        self.decoder_thread.unpark();
+    }
+
+    /// Write the frames in `range` to the start of each of the `output` channels,
+    /// scaling mono to stereo, and padding the rest of the output with zeroes.
+    ///
+    /// Ranges are expected to be read in sequence, with [`Self::seek`] called inbetween jumps.
+    pub fn read(&mut self, range: Range<usize>, output: &mut [&mut [Sample]; CHANNELS]) {
+        let mut position = range.start;
+
+        while position < range.end {
+            let written = position - range.start;
+
+            let Some(chunk) = self.current.as_ref() else {
+                match self.chunks.try_pop() {
+                    Some(chunk) => {
+                        self.current = Some(chunk);
+                        self.decoder_thread.unpark();
+                        continue;
+                    }
+                    None => {
+                        // Decoder has fallen behind
+                        break;
+                    }
+                }
+            };
+
+            let is_stale = chunk.generation != self.generation || chunk.end() <= position;
+            if is_stale {
+                self.recycle_current();
+                continue;
+            }
+
+            if position < chunk.start {
+                let n = min(range.end, chunk.start) - position;
+                for channel in output.iter_mut() {
+                    channel[written..written + n].fill(0.0);
+                }
+                position += n;
+                continue;
+            }
+
+            let n = min(range.end, chunk.end()) - position;
+            let chunk_range = position - chunk.start..position - chunk.start + n;
+            for (channel_i, channel) in output.iter_mut().enumerate() {
+                let input = &chunk.data[channel_i % chunk.data.len()][chunk_range.clone()];
+                channel[written..written + n].copy_from_slice(input);
+            }
+            position += n;
+        }
+
+        let written = position - range.start;
+        for channel in output.iter_mut() {
+            channel[written..].fill(0.0);
+        }
+    }
+
+    /// Whether the decoder has filled the cache, or reached the end of the file, since the last seek.
+    #[cfg(test)]
+    pub fn is_filled(&self) -> bool {
+        self.chunks.is_full() || self.shared.end_of_file.load(Ordering::Acquire) == self.generation
+    }
+
+    fn recycle_current(&mut self) {
+        if let Some(chunk) = self.current.take() {
+            let _result = self.recycled.try_push(chunk);
+            debug_assert!(_result.is_ok(), "Recycling ringbuffer is full");
+        }
+    }
+}
+impl Drop for Streamer {
+    fn drop(&mut self) {
+        self.shared.stopped.store(true, Ordering::Release);
+        self.decoder_thread.unpark();
+    }
+}
+
+/// State shared between the [`Streamer`] and its [`Decoder`].
+struct Shared {
+    /// The frame to continue from when `generation` changes.
+    seek_to: AtomicUsize,
+    /// Incremented every time the streamer seeks.
+    generation: AtomicUsize,
+    /// The generation in which the decoder last reached the end of the file, or `usize::MAX` if it has not.
+    end_of_file: AtomicUsize,
+    stopped: AtomicBool,
+}
+
+struct Chunk {
+    /// The generation the chunk was decoded in.
+    generation: usize,
+    /// The frame of the first sample in the chunk.
+    start: usize,
+    /// List of channel buffers, in the file's original channel count.
+    data: Vec<Vec<Sample>>,
+}
+impl Chunk {
+    fn new(channels: usize) -> Self {
+        Chunk {
+            generation: 0,
+            start: 0,
+            data: vec![Vec::with_capacity(CHUNK_FRAMES); channels],
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.data[0].len()
+    }
+
+    fn end(&self) -> usize {
+        self.start + self.len()
+    }
+
+    fn reset(&mut self, generation: usize, start: usize) {
+        self.generation = generation;
+        self.start = start;
+        for channel in self.data.iter_mut() {
+            channel.clear();
+        }
+    }
+}
+
+/// The background half of the [`Streamer`].
+struct Decoder {
+    file: OpenedFile,
+    channels: usize,
+    downmix: Downmix,
+    /// Created from the first decoded buffer.
+    downmixer: Option<Downmixer>,
+    shared: Arc<Shared>,
+    chunks: HeapProd<Box<Chunk>>,
+    recycled: HeapCons<Box<Chunk>>,
+
+    generation: usize,
+    /// The frame of the next sample in `decoded`.
+    position: usize,
+    /// The frame that was last seeked to.
+    target: usize,
+    end_of_file: bool,
+
+    /// The most recently decoded packet.
+    decoded: Vec<Vec<Sample>>,
+    /// How much of `decoded` has been moved into chunks.
+    decoded_offset: usize,
+    /// The chunk currently being filled.
+    chunk: Option<Box<Chunk>>,
+}
+impl Decoder {
+    fn run(mut self) {
+        loop {
+            if self.shared.stopped.load(Ordering::Acquire) {
+                return;
+            }
+
+            let progressed = self.step();
+            if !progressed {
+                thread::park_timeout(Duration::from_millis(5));
+            }
+        }
+    }
+
+    /// Decode a bit more of the file, if there is room for it.
+    ///
+    /// Returns `false` if there was nothing to do.
+    fn step(&mut self) -> bool {
+        let generation = self.shared.generation.load(Ordering::Acquire);
+        if generation != self.generation {
+            self.generation = generation;
+            self.target = self.shared.seek_to.load(Ordering::Acquire);
+            self.position = self.file.seek(self.target as u64).unwrap_or(0) as usize;
+            self.end_of_file = false;
+
+            for channel in self.decoded.iter_mut() {
+                channel.clear();
+            }
+            self.decoded_offset = 0;
+            if let Some(chunk) = self.chunk.as_mut() {
+                chunk.reset(generation, max(self.position, self.target));
+            }
+        }
+
+        if self.end_of_file || self.chunks.is_full() {
+            return false;
+        }
+
+        if self.decoded_offset == self.decoded[0].len() {
+            self.decode_next();
+            return true;
+        }
+
+        let chunk = self.chunk.get_or_insert_with(|| {
+            let mut new_chunk = self
+                .recycled
+                .try_pop()
+                .unwrap_or_else(|| Box::new(Chunk::new(self.channels)));
+            new_chunk.reset(generation, self.position);
+            new_chunk
+        });
+
+        let n = min(
+            CHUNK_FRAMES - chunk.len(),
+            self.decoded[0].len() - self.decoded_offset,
+        );
+        let decoded_range = self.decoded_offset..self.decoded_offset + n;
+        for (output, input) in chunk.data.iter_mut().zip(self.decoded.iter()) {
+            output.extend_from_slice(&input[decoded_range.clone()]);
+        }
+        self.decoded_offset += n;
+        self.position += n;
+
+        if chunk.len() == CHUNK_FRAMES {
+            self.push_chunk();
+        }
+
+        true
+    }
+
+    fn decode_next(&mut self) {
+        for channel in self.decoded.iter_mut() {
+            channel.clear();
+        }
+        self.decoded_offset = 0;
+
+        match self.file.next() {
+            Ok(Some(buffer)) => {
+                let downmixer = match &mut self.downmixer {
+                    Some(downmixer) => downmixer,
+                    None => match Downmixer::new(&self.downmix, buffer.spec().channels) {
+                        Ok(downmixer) => self.downmixer.insert(downmixer),
+                        // Was already checked when the clip was imported
+                        Err(_) => {
+                            self.end_of_file = true;
+                            return;
+                        }
+                    },
+                };
+                downmixer.extend(&mut self.decoded, buffer);
+            }
+            Ok(None) | Err(_) => {
+                self.end_of_file = true;
+                if self.chunk.as_ref().is_some_and(|chunk| chunk.len() > 0) {
+                    self.push_chunk();
+                }
+                self.shared
+                    .end_of_file
+                    .store(self.generation, Ordering::Release);
+                return;
+            }
+        }
+
+        // Accurate seeking might land slightly before the target
+        if self.position < self.target {
+            let skipped = min(self.target - self.position, self.decoded[0].len());
+            self.decoded_offset = skipped;
+            self.position += skipped;
+        }
+    }
+
+    fn push_chunk(&mut self) {
+        let chunk = self.chunk.take().unwrap();
+        let result = self.chunks.try_push(chunk);
+        debug_assert!(result.is_ok(), "Pushed to full chunk ringbuffer");
+    }
+}
@@ -1,24 +1,42 @@
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
     error::Error,
     fmt::Display,
+    fs::{self, File},
+    hash::Hasher,
+    io::{self, Read},
     path::{Path, PathBuf},
     sync::Arc,
+    thread::{self, JoinHandle},
 };
 
 use super::{
     audio_clip_reader::AudioClipReader,
-    stored_audio_clip::{self, StoredAudioClip, StoredAudioClipKey},
+    stored_audio_clip::{self, DecodeProgress, Downmix, StoredAudioClip, StoredAudioClipKey},
 };
-use crate::engine::utils::key_generator::{self, KeyGenerator};
+use crate::engine::config::ResampleQuality;
+use crate::engine::utils::key_generator::{self, Key, KeyGenerator};
+
+/// Fully decoded clips that can be handed to a new store, so they don't need to be decoded again.
+pub type DecodedClips = HashMap<StoredAudioClipKey, Arc<StoredAudioClip>>;
 
 pub struct AudioClipStore {
     max_buffer_size: usize,
     sample_rate: u32,
+    resample_quality: ResampleQuality,
+    /// Applied to clips with more than two channels as they are imported.
+    downmix: Downmix,
 
-    paths: HashMap<PathBuf, StoredAudioClipKey>,
+    /// The path each clip was imported from, as it was given.
+    paths: HashMap<StoredAudioClipKey, PathBuf>,
+    /// Used to recognize files that have already been imported,
+    /// either by their canonicalized path, or by the hash of their contents.
+    canonical_paths: HashMap<PathBuf, StoredAudioClipKey>,
+    hashes: HashMap<u64, StoredAudioClipKey>,
     clips: HashMap<StoredAudioClipKey, Arc<StoredAudioClip>>,
+    /// The maximum number of cached frames of each clip that is streamed from disk.
+    streamed: HashMap<StoredAudioClipKey, usize>,
 
     key_generator: KeyGenerator<StoredAudioClipKey>,
 }
@@ -29,66 +47,254 @@ impl AudioClipStore {
     /// If a track fails to import, it will be skipped.
     /// If the state contains no tracks, it is guaranteed that no errors will occur.
     ///
+    /// Clips in the state are kept under their own keys, even if they refer to the same file.
+    ///
     /// # Panics
     /// If the state contains duplicate keys.
+    #[cfg(test)]
     pub fn new(
         state: &AudioClipStoreState,
         sample_rate: u32,
         max_buffer_size: usize,
+        resample_quality: ResampleQuality,
     ) -> (Self, Vec<ImportError>) {
-        let paths = HashMap::from_iter(
-            state
-                .clips
-                .iter()
-                .map(|(path, key)| (path.to_owned(), *key)),
-        );
+        Self::with_decoded(
+            state,
+            sample_rate,
+            max_buffer_size,
+            resample_quality,
+            &DecodedClips::new(),
+        )
+    }
+
+    /// Like [`Self::new`], but clips in `decoded` are reused instead of being imported again,
+    /// if the state holds them under the same key and they are not streamed.
+    pub fn with_decoded(
+        state: &AudioClipStoreState,
+        sample_rate: u32,
+        max_buffer_size: usize,
+        resample_quality: ResampleQuality,
+        decoded: &DecodedClips,
+    ) -> (Self, Vec<ImportError>) {
+        let streamed = HashMap::from_iter(state.streamed.iter().copied());
+
+        let mut store = AudioClipStore {
+            max_buffer_size,
+            sample_rate,
+            resample_quality,
+            downmix: state.downmix.clone(),
+
+            paths: HashMap::with_capacity(state.clips.len()),
+            canonical_paths: HashMap::with_capacity(state.clips.len()),
+            hashes: HashMap::with_capacity(state.clips.len()),
+            clips: HashMap::with_capacity(state.clips.len()),
+            streamed,
 
-        let mut key_generator = KeyGenerator::new();
+            key_generator: KeyGenerator::new(),
+        };
 
-        let mut clips = HashMap::with_capacity(paths.len());
         let mut errors = Vec::new();
-        for (path, &key) in paths.iter() {
-            match StoredAudioClip::import(key, path) {
+        for (path, key) in &state.clips {
+            let key = *key;
+            let result = match (decoded.get(&key), store.streamed.get(&key)) {
+                (Some(clip), None) if clip.streaming_source().is_none() => Ok(Arc::clone(clip)),
+                (_, Some(&max_cached_frames)) => StoredAudioClip::import_streaming_with(
+                    key,
+                    path,
+                    max_cached_frames,
+                    &store.downmix,
+                )
+                .map(Arc::new),
+                (_, None) => StoredAudioClip::import_with(
+                    key,
+                    path,
+                    &store.downmix,
+                    &DecodeProgress::default(),
+                )
+                .map(Arc::new),
+            };
+            match result {
                 Ok(clip) => {
-                    key_generator
+                    store
+                        .key_generator
                         .reserve(key)
                         .expect("State contains duplicate keys");
-                    clips.insert(key, Arc::new(clip));
+                    store.clips.insert(key, clip);
+                    store.paths.insert(key, path.to_owned());
+                    store.remember(key, fs::canonicalize(path).ok(), content_hash(path).ok());
                 }
                 Err(error) => errors.push(error.into()),
             }
         }
 
-        let store = AudioClipStore {
-            max_buffer_size,
-            sample_rate,
-
-            paths,
-            clips,
-
-            key_generator,
-        };
-
         (store, errors)
     }
 
+    /// If the file has already been imported, either from the same path or from another file with the same contents,
+    /// the existing clip is returned as is.
     pub fn import(&mut self, path: &Path) -> Result<StoredAudioClipKey, ImportError> {
-        if let Some(&key) = self.paths.get(path) {
+        self.import_inner(path, None)
+    }
+
+    /// Like [`Self::import`], but the clip will be streamed from disk instead of being loaded into memory.
+    pub fn import_streaming(
+        &mut self,
+        path: &Path,
+        max_cached_frames: usize,
+    ) -> Result<StoredAudioClipKey, ImportError> {
+        self.import_inner(path, Some(max_cached_frames))
+    }
+
+    fn import_inner(
+        &mut self,
+        path: &Path,
+        max_cached_frames: Option<usize>,
+    ) -> Result<StoredAudioClipKey, ImportError> {
+        // If any of these fail, the file cannot be read, which the import will report
+        let canonical_path = fs::canonicalize(path).ok();
+        if let Some(&key) = canonical_path
+            .as_ref()
+            .and_then(|canonical_path| self.canonical_paths.get(canonical_path))
+        {
             // Clip is already imported
             return Ok(key);
         }
+        let hash = content_hash(path).ok();
+        if let Some(&key) = hash.and_then(|hash| self.hashes.get(&hash)) {
+            // Same contents as a clip that is already imported
+            self.remember(key, canonical_path, None);
+            return Ok(key);
+        }
 
         let key = self.key_generator.next()?;
 
-        let clip = StoredAudioClip::import(key, path)?;
+        let clip = match max_cached_frames {
+            Some(max_cached_frames) => {
+                StoredAudioClip::import_streaming_with(key, path, max_cached_frames, &self.downmix)?
+            }
+            None => {
+                StoredAudioClip::import_with(key, path, &self.downmix, &DecodeProgress::default())?
+            }
+        };
 
         // Commit only if no errors occur
         self.clips.insert(key, Arc::new(clip));
-        self.paths.insert(path.to_owned(), key);
+        self.paths.insert(key, path.to_owned());
+        if let Some(max_cached_frames) = max_cached_frames {
+            self.streamed.insert(key, max_cached_frames);
+        }
+        self.remember(key, canonical_path, hash);
 
         Ok(key)
     }
 
+    /// Like [`Self::import`], but the file is decoded on another thread.
+    ///
+    /// The clip is only added to the store once the returned handle is passed to [`Self::finish_import`].
+    pub fn import_async(&self, path: &Path) -> ImportHandle {
+        let canonical_path = fs::canonicalize(path).ok();
+        if let Some(&key) = canonical_path
+            .as_ref()
+            .and_then(|canonical_path| self.canonical_paths.get(canonical_path))
+        {
+            // Clip is already imported
+            return ImportHandle {
+                progress: Arc::new(DecodeProgress::default()),
+                import: PendingImport::Imported(key),
+            };
+        }
+
+        let progress = Arc::new(DecodeProgress::default());
+        let thread = {
+            let path = path.to_owned();
+            let downmix = self.downmix.clone();
+            let progress = Arc::clone(&progress);
+            thread::spawn(move || {
+                // The actual key is decided on when the import is finished
+                let clip = StoredAudioClip::import_with(
+                    StoredAudioClipKey::new(0),
+                    &path,
+                    &downmix,
+                    &progress,
+                )?;
+                Ok((clip, content_hash(&path).ok()))
+            })
+        };
+
+        ImportHandle {
+            progress,
+            import: PendingImport::Decoding {
+                path: path.to_owned(),
+                canonical_path,
+                thread,
+            },
+        }
+    }
+
+    /// Wait for the import to finish, and add the clip to the store.
+    ///
+    /// If the same file has been imported in the meantime, the existing clip is returned instead.
+    pub fn finish_import(
+        &mut self,
+        handle: ImportHandle,
+    ) -> Result<StoredAudioClipKey, ImportError> {
+        let (path, canonical_path, thread) = match handle.import {
+            PendingImport::Imported(key) => return Ok(key),
+            PendingImport::Decoding {
+                path,
+                canonical_path,
+                thread,
+            } => (path, canonical_path, thread),
+        };
+        let (mut clip, hash) = thread.join().expect("Import thread panicked")?;
+
+        let existing = canonical_path
+            .as_ref()
+            .and_then(|canonical_path| self.canonical_paths.get(canonical_path))
+            .or_else(|| hash.and_then(|hash| self.hashes.get(&hash)));
+        if let Some(&key) = existing {
+            self.remember(key, canonical_path, None);
+            return Ok(key);
+        }
+
+        let key = self.key_generator.next()?;
+        clip.set_key(key);
+
+        self.clips.insert(key, Arc::new(clip));
+        self.paths.insert(key, path);
+        self.remember(key, canonical_path, hash);
+
+        Ok(key)
+    }
+
+    /// Make future imports of the same file return `key`, unless they already return another key.
+    fn remember(
+        &mut self,
+        key: StoredAudioClipKey,
+        canonical_path: Option<PathBuf>,
+        hash: Option<u64>,
+    ) {
+        if let Some(canonical_path) = canonical_path {
+            self.canonical_paths.entry(canonical_path).or_insert(key);
+        }
+        if let Some(hash) = hash {
+            self.hashes.entry(hash).or_insert(key);
+        }
+    }
+
+    pub fn downmix(&self) -> &Downmix {
+        &self.downmix
+    }
+    /// Only affects clips imported from now on.
+    pub fn set_downmix(&mut self, downmix: Downmix) {
+        self.downmix = downmix;
+    }
+
+    /// The path the clip was imported from.
+    pub fn path(&self, key: StoredAudioClipKey) -> Option<&Path> {
+        self.paths.get(&key).map(PathBuf::as_path)
+    }
+
     pub fn get(
         &self,
         key: StoredAudioClipKey,
@@ -102,32 +308,133 @@ impl AudioClipStore {
         self.clips.values().cloned()
     }
 
+    /// Remove the clip from the store.
+    ///
+    /// Its data is freed once the last reader of it is dropped.
+    pub fn remove(&mut self, key: StoredAudioClipKey) -> Result<(), InvalidStoredAudioClipError> {
+        self.clips
+            .remove(&key)
+            .ok_or(InvalidStoredAudioClipError { key })?;
+        self.paths.remove(&key);
+        self.streamed.remove(&key);
+        self.canonical_paths.retain(|_, &mut k| k != key);
+        self.hashes.retain(|_, &mut k| k != key);
+        self.key_generator
+            .free(key)
+            .expect("Clip key exists in clips but not in key_generator");
+        Ok(())
+    }
+
     pub fn reader(
         &self,
         key: StoredAudioClipKey,
+    ) -> Result<AudioClipReader, InvalidStoredAudioClipError> {
+        self.reader_pitched(key, 0.0)
+    }
+
+    /// Like [`Self::reader`], but shifting the pitch of the clip `semitones` up.
+    pub fn reader_pitched(
+        &self,
+        key: StoredAudioClipKey,
+        semitones: f32,
     ) -> Result<AudioClipReader, InvalidStoredAudioClipError> {
         let clip = self.get(key)?;
-        Ok(AudioClipReader::new(
+        Ok(AudioClipReader::with_quality(
             clip,
             self.max_buffer_size,
             self.sample_rate,
+            semitones,
+            self.resample_quality,
         ))
     }
 
+    /// All clips that are fully decoded in memory, for [`Self::with_decoded`].
+    pub fn decoded(&self) -> DecodedClips {
+        self.clips
+            .iter()
+            .filter(|(_, clip)| clip.streaming_source().is_none())
+            .map(|(&key, clip)| (key, Arc::clone(clip)))
+            .collect()
+    }
+
     pub fn state(&self) -> AudioClipStoreState {
+        let mut clips: Vec<(PathBuf, StoredAudioClipKey)> = self
+            .paths
+            .iter()
+            .map(|(&key, path)| (path.to_owned(), key))
+            .collect();
+        clips.sort_by_key(|&(_, key)| key);
+        let mut streamed: Vec<(StoredAudioClipKey, usize)> = self
+            .streamed
+            .iter()
+            .map(|(&key, &max_cached_frames)| (key, max_cached_frames))
+            .collect();
+        streamed.sort();
+
         AudioClipStoreState {
-            clips: self
-                .paths
-                .iter()
-                .map(|(path, &key)| (path.to_owned(), key))
-                .collect(),
+            clips,
+            streamed,
+            downmix: self.downmix.clone(),
         }
     }
 }
 
+/// An import running in the background, acquired via [`AudioClipStore::import_async`].
+#[derive(Debug)]
+pub struct ImportHandle {
+    progress: Arc<DecodeProgress>,
+    import: PendingImport,
+}
+impl ImportHandle {
+    /// The number of frames decoded so far, and the total number of frames in the file, if known.
+    pub fn progress(&self) -> (usize, Option<usize>) {
+        (self.progress.decoded_frames(), self.progress.total_frames())
+    }
+
+    /// Whether the decoding is done, such that finishing the import will not block.
+    pub fn is_finished(&self) -> bool {
+        match &self.import {
+            PendingImport::Imported(_) => true,
+            PendingImport::Decoding { thread, .. } => thread.is_finished(),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum PendingImport {
+    /// The file had already been imported.
+    Imported(StoredAudioClipKey),
+    Decoding {
+        path: PathBuf,
+        canonical_path: Option<PathBuf>,
+        /// Returns the clip along with the hash of the file.
+        thread: JoinHandle<Result<(StoredAudioClip, Option<u64>), stored_audio_clip::ImportError>>,
+    },
+}
+
+/// Hash of the contents of the file at `path`.
+fn content_hash(path: &Path) -> io::Result<u64> {
+    let mut file = File::open(path)?;
+    let mut hasher = DefaultHasher::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(hasher.finish());
+        }
+        hasher.write(&buffer[..read]);
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct AudioClipStoreState {
     pub clips: Vec<(PathBuf, StoredAudioClipKey)>,
+    /// The clips that are streamed from disk, along with their maximum number of cached frames.
+    #[serde(default)]
+    pub streamed: Vec<(StoredAudioClipKey, usize)>,
+    /// Used when importing clips with more than two channels.
+    #[serde(default)]
+    pub downmix: Downmix,
 }
 impl PartialEq for AudioClipStoreState {
     fn eq(&self, other: &Self) -> bool {
@@ -147,7 +454,10 @@ impl PartialEq for AudioClipStoreState {
             other.clips
         );
 
-        self_set == other_set
+        let self_streamed: HashSet<_> = HashSet::from_iter(self.streamed.iter());
+        let other_streamed = HashSet::from_iter(other.streamed.iter());
+
+        self_set == other_set && self_streamed == other_streamed && self.downmix == other.downmix
     }
 }
 impl Eq for AudioClipStoreState {}
@@ -197,3 +507,58 @@ impl From<stored_audio_clip::ImportError> for ImportError {
         ImportError::Other(e)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::utils::test_file_path;
+
+    #[test]
+    fn state_with_same_file_twice() {
+        let path = test_file_path("44100 16-bit.wav");
+        let state = AudioClipStoreState {
+            clips: vec![
+                (path.clone(), StoredAudioClipKey::new(0)),
+                (path.clone(), StoredAudioClipKey::new(1)),
+            ],
+            streamed: vec![],
+            downmix: Downmix::default(),
+        };
+
+        let (mut store, errors) =
+            AudioClipStore::new(&state, 48_000, 1024, ResampleQuality::default());
+
+        assert!(errors.is_empty());
+        assert!(store.get(StoredAudioClipKey::new(0)).is_ok());
+        assert!(store.get(StoredAudioClipKey::new(1)).is_ok());
+        assert_eq!(store.state(), state);
+        // The first key is reused
+        assert_eq!(store.import(&path), Ok(StoredAudioClipKey::new(0)));
+    }
+
+    #[test]
+    fn reuse_decoded() {
+        let path = test_file_path("44100 16-bit.wav");
+        let state = AudioClipStoreState {
+            clips: vec![(path, StoredAudioClipKey::new(0))],
+            streamed: vec![],
+            downmix: Downmix::default(),
+        };
+        let (store, _) = AudioClipStore::new(&state, 48_000, 1024, ResampleQuality::default());
+
+        let (new_store, errors) = AudioClipStore::with_decoded(
+            &state,
+            48_000,
+            512,
+            ResampleQuality::default(),
+            &store.decoded(),
+        );
+        assert!(errors.is_empty());
+        assert_eq!(new_store.state(), state);
+        // The very same data
+        assert!(Arc::ptr_eq(
+            &store.get(StoredAudioClipKey::new(0)).unwrap(),
+            &new_store.get(StoredAudioClipKey::new(0)).unwrap(),
+        ));
+    }
+}
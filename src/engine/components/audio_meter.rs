@@ -63,7 +63,7 @@ impl AudioMeter {
     ///
     /// Results are scaled and smoothed to avoid jittering, suitable for reading every frame.
     /// If this is not desirable see [`AudioMeter::read_raw`].
-    pub fn read(&mut self) -> [[Sample; CHANNELS]; 3] {
+    pub fn read(&mut self) -> [[f32; CHANNELS]; 3] {
         let mut peak = [0.0; CHANNELS];
         let mut long_peak = [0.0; CHANNELS];
         let mut rms = [0.0; CHANNELS];
@@ -127,7 +127,7 @@ impl AudioMeter {
     /// Same as [`AudioMeter::read`], except results are not smoothed or scaled.
     ///
     /// Long peak stays in place for 1 second since it was last changed, before snapping to the current peak.
-    pub fn read_raw(&self) -> [[Sample; CHANNELS]; 3] {
+    pub fn read_raw(&self) -> [[f32; CHANNELS]; 3] {
         let mut result = [[0.0; CHANNELS]; 3];
         for (result_frame, atomic_frame) in
             zip(result.iter_mut(), [&self.peak, &self.long_peak, &self.rms])
@@ -173,7 +173,7 @@ impl AudioMeterProcessor {
         }
 
         for (peak, max) in zip(self.peak.iter(), max_values) {
-            peak.store(max, Ordering::Relaxed);
+            peak.store(max as f32, Ordering::Relaxed);
         }
     }
 
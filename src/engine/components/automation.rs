@@ -0,0 +1,65 @@
+use super::timeline::Timestamp;
+
+/// The value of an automation curve at `position`,
+/// linearly interpolated between the breakpoints around it.
+///
+/// `points` must be sorted by position.
+/// Before the first point the curve holds its first value, and after the last point it holds its last value.
+/// Returns `None` if there are no points.
+pub fn automation_value(points: &[(Timestamp, f32)], position: Timestamp) -> Option<f32> {
+    let index = points.partition_point(|&(point, _)| point <= position);
+    match (index.checked_sub(1).map(|i| points[i]), points.get(index)) {
+        (None, None) => None,
+        (Some((_, value)), None) | (None, Some(&(_, value))) => Some(value),
+        (Some((start, start_value)), Some(&(end, end_value))) => {
+            let progress =
+                (position - start).beat_units() as f64 / (end - start).beat_units() as f64;
+            Some(start_value + (end_value - start_value) * progress as f32)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty() {
+        assert_eq!(automation_value(&[], Timestamp::zero()), None);
+    }
+
+    #[test]
+    fn interpolates_between_points() {
+        let points = [
+            (Timestamp::from_beats(2), 0.0),
+            (Timestamp::from_beats(6), 1.0),
+            (Timestamp::from_beats(8), 0.5),
+        ];
+
+        let value = |beat_units| automation_value(&points, Timestamp::from_beat_units(beat_units));
+        assert_eq!(value(0), Some(0.0));
+        assert_eq!(value(2 * 1024), Some(0.0));
+        assert_eq!(value(3 * 1024), Some(0.25));
+        assert_eq!(value(6 * 1024), Some(1.0));
+        assert_eq!(value(7 * 1024), Some(0.75));
+        assert_eq!(value(8 * 1024), Some(0.5));
+        assert_eq!(value(100 * 1024), Some(0.5));
+    }
+
+    #[test]
+    fn jump_at_same_position() {
+        let points = [
+            (Timestamp::from_beats(4), 0.0),
+            (Timestamp::from_beats(4), 1.0),
+        ];
+
+        assert_eq!(
+            automation_value(&points, Timestamp::from_beats(3)),
+            Some(0.0)
+        );
+        assert_eq!(
+            automation_value(&points, Timestamp::from_beats(4)),
+            Some(1.0)
+        );
+    }
+}
@@ -0,0 +1,79 @@
+use std::f32::consts::TAU;
+use std::iter::zip;
+
+use crate::engine::{Sample, CHANNELS};
+
+/// Frequency in Hz below which the signal is attenuated.
+const CUTOFF: f32 = 10.0;
+
+/// One-pole high-pass filter, removing any DC offset from the signal.
+#[derive(Debug, Default)]
+pub struct DcBlocker {
+    last_input: [Sample; CHANNELS],
+    last_output: [Sample; CHANNELS],
+}
+impl DcBlocker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Filter the interleaved `buffer` in place.
+    pub fn process(&mut self, buffer: &mut [Sample], sample_rate: u32) {
+        let pole = (1.0 - TAU * CUTOFF / sample_rate as f32) as Sample;
+
+        for frame in buffer.chunks_exact_mut(CHANNELS) {
+            for ((sample, last_input), last_output) in
+                zip(zip(frame, &mut self.last_input), &mut self.last_output)
+            {
+                let output = *sample - *last_input + pole * *last_output;
+                *last_input = *sample;
+                *last_output = output;
+                *sample = output;
+            }
+        }
+    }
+
+    /// Forget the previous samples, so the filter starts over from silence.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removes_offset() {
+        let mut blocker = DcBlocker::new();
+        let mut buffer = vec![0.5; 48_000 * CHANNELS];
+
+        no_heap! {{
+            blocker.process(&mut buffer, 48_000);
+        }}
+
+        assert!(buffer[..CHANNELS].iter().all(|&s| s == 0.5));
+        assert!(buffer[buffer.len() - CHANNELS..]
+            .iter()
+            .all(|s| s.abs() < 1e-6));
+    }
+
+    #[test]
+    fn keeps_audible_frequencies() {
+        let mut blocker = DcBlocker::new();
+        let frames = 48_000;
+        let sine = |i: usize| (TAU * 1000.0 * i as f32 / 48_000.0).sin() as Sample;
+        let mut buffer: Vec<Sample> = (0..frames)
+            .flat_map(|i| [sine(i) + 0.3; CHANNELS])
+            .collect();
+
+        no_heap! {{
+            blocker.process(&mut buffer, 48_000);
+        }}
+
+        // Once settled, only the sine is left
+        for i in frames - 100..frames {
+            assert!((buffer[i * CHANNELS] - sine(i)).abs() < 0.01);
+        }
+    }
+}
@@ -1,24 +1,80 @@
-// Module temporarily out of use
-#![allow(dead_code)]
-
 use crate::engine::{Sample, CHANNELS};
 
-use crate::engine::utils::CircularArray;
-
 /// Circular sample delay
+///
+/// The delay can be changed while running, up to the maximum it was created with.
 pub struct DelayPoint {
-    history: CircularArray<Sample>,
+    history: Vec<Sample>,
+    position: usize,
+    /// Delay in frames, between 1 and the max delay.
+    sample_delay: usize,
 }
 impl DelayPoint {
-    pub fn new(sample_delay: usize) -> Self {
+    /// Create a delay of `sample_delay` frames, which can later be set as high as `max_sample_delay` frames.
+    pub fn with_max_delay(sample_delay: usize, max_sample_delay: usize) -> Self {
+        let max_sample_delay = max_sample_delay.max(1);
         Self {
-            history: CircularArray::new(0.0, sample_delay * CHANNELS),
+            history: vec![0.0; max_sample_delay * CHANNELS],
+            position: 0,
+            sample_delay: sample_delay.clamp(1, max_sample_delay),
         }
     }
 
-    pub fn next(&mut self, buffer: &mut [Sample]) {
+    pub fn max_delay(&self) -> usize {
+        self.history.len() / CHANNELS
+    }
+
+    /// Clamped between 1 and [`Self::max_delay`].
+    pub fn set_delay(&mut self, sample_delay: usize) {
+        self.sample_delay = sample_delay.clamp(1, self.max_delay());
+    }
+
+    /// The sample that the next call to [`Self::push`] will be delayed against.
+    pub fn delayed(&self) -> Sample {
+        let len = self.history.len();
+        self.history[(self.position + len - self.sample_delay * CHANNELS) % len]
+    }
+
+    /// Insert a sample at the back of the delay.
+    pub fn push(&mut self, sample: Sample) {
+        self.history[self.position] = sample;
+        self.position += 1;
+        self.position %= self.history.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn next(d: &mut DelayPoint, buffer: &mut [Sample]) {
         for sample in buffer {
-            *sample = self.history.push_pop(*sample);
+            let delayed = d.delayed();
+            d.push(*sample);
+            *sample = delayed;
         }
     }
+
+    #[test]
+    fn delays_by_frames() {
+        let mut d = DelayPoint::with_max_delay(2, 2);
+        let mut buffer: Vec<Sample> = (1..=8).map(|s| s as Sample).collect();
+        next(&mut d, &mut buffer);
+        assert_eq!(buffer, [0.0, 0.0, 0.0, 0.0, 1.0, 2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn delay_can_be_shortened() {
+        let mut d = DelayPoint::with_max_delay(3, 3);
+        let mut buffer: Vec<Sample> = (1..=6).map(|s| s as Sample).collect();
+        next(&mut d, &mut buffer);
+
+        d.set_delay(1);
+        let mut buffer = [7.0, 8.0];
+        next(&mut d, &mut buffer);
+        assert_eq!(buffer, [5.0, 6.0]);
+
+        d.set_delay(10);
+        assert_eq!(d.sample_delay, 3);
+    }
 }
@@ -0,0 +1,105 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::{Debug, Display};
+
+use crate::engine::info::Info;
+use crate::engine::utils::key_generator;
+use crate::engine::utils::key_generator::key_type;
+use crate::engine::Sample;
+
+pub mod compressor;
+use compressor::{compressor, Compressor, CompressorSettings};
+pub mod delay;
+use delay::{delay, Delay, DelaySettings};
+
+key_type!(pub struct EffectKey(u32));
+
+/// The part of an effect that lives on the audio thread.
+pub trait Effect: Send + Debug {
+    /// Receive any changes made through the corresponding [`EffectInterface`].
+    fn poll(&mut self);
+
+    /// Process the interleaved `buffer` in place.
+    ///
+    /// Must not allocate.
+    fn process(&mut self, info: &Info, buffer: &mut [Sample]);
+
+    /// Same as [`Self::process`], except effects that react to the level of their input,
+    /// react to the interleaved `sidechain` of the same length instead.
+    ///
+    /// Other effects ignore the sidechain, which is the default.
+    fn process_sidechained(&mut self, info: &Info, buffer: &mut [Sample], sidechain: &[Sample]) {
+        let _ = sidechain;
+        self.process(info, buffer);
+    }
+}
+
+/// Creates a corresponding pair of [`EffectInterface`] and [`Effect`] from a saved state.
+pub fn effect(state: &EffectState) -> (EffectInterface, Box<dyn Effect>) {
+    match *state {
+        EffectState::Compressor(settings) => {
+            let (compressor, compressor_processor) = compressor(settings);
+            (
+                EffectInterface::Compressor(compressor),
+                Box::new(compressor_processor),
+            )
+        }
+        EffectState::Delay(settings) => {
+            let (delay, delay_processor) = delay(settings);
+            (EffectInterface::Delay(delay), Box::new(delay_processor))
+        }
+    }
+}
+
+/// Used to adjust an effect placed on a [`MixerTrack`](crate::MixerTrack).
+#[derive(Debug)]
+pub enum EffectInterface {
+    Compressor(Compressor),
+    Delay(Delay),
+}
+impl EffectInterface {
+    pub fn state(&self) -> EffectState {
+        match self {
+            EffectInterface::Compressor(compressor) => {
+                EffectState::Compressor(compressor.settings())
+            }
+            EffectInterface::Delay(delay) => EffectState::Delay(delay.settings()),
+        }
+    }
+}
+
+/// Contains everything needed to reconstruct an effect.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum EffectState {
+    Compressor(CompressorSettings),
+    Delay(DelaySettings),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidEffectError {
+    pub key: EffectKey,
+}
+impl Display for InvalidEffectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let key = self.key;
+        write!(f, "No effect with key, {key:?}, on track")
+    }
+}
+impl Error for InvalidEffectError {}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct EffectOverflowError;
+impl Display for EffectOverflowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The max number of effects on the track has been exceeded"
+        )
+    }
+}
+impl Error for EffectOverflowError {}
+impl From<key_generator::OverflowError> for EffectOverflowError {
+    fn from(_: key_generator::OverflowError) -> Self {
+        Self
+    }
+}
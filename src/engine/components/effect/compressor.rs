@@ -0,0 +1,305 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+
+use super::Effect;
+use crate::engine::info::Info;
+use crate::engine::utils::ringbuffer::{self, ringbuffer};
+use crate::engine::utils::MovingAverage;
+use crate::engine::{Sample, CHANNELS};
+use crate::{inverse_meter_scale, meter_scale};
+
+/// Number of frames the detected level is averaged over, before attack and release are applied.
+const DETECTION_WINDOW: usize = 64;
+
+pub fn compressor(settings: CompressorSettings) -> (Compressor, CompressorProcessor) {
+    let (settings_sender, settings_receiver) = ringbuffer();
+
+    (
+        Compressor {
+            settings,
+            settings_sender,
+        },
+        CompressorProcessor {
+            settings,
+            settings_receiver,
+
+            detection: MovingAverage::new(0.0, DETECTION_WINDOW),
+            envelope: 0.0,
+        },
+    )
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct CompressorSettings {
+    /// The level above which the signal is compressed,
+    /// on the same scale as [`MixerTrack::read_meter`](crate::MixerTrack::read_meter).
+    pub threshold: f32,
+    /// How many times a rise in level above the threshold is reduced, e.g. `4.0` for 4:1.
+    pub ratio: f32,
+    /// Seconds it takes for the compressor to respond to a rise in level.
+    pub attack: f32,
+    /// Seconds it takes for the compressor to let go after a fall in level.
+    pub release: f32,
+    /// Gain applied after compression, to make up for the lost level.
+    pub makeup_gain: f32,
+}
+impl Default for CompressorSettings {
+    fn default() -> Self {
+        Self {
+            threshold: meter_scale(0.5),
+            ratio: 4.0,
+            attack: 0.01,
+            release: 0.1,
+            makeup_gain: 1.0,
+        }
+    }
+}
+
+/// Acquired via the [`compressor`] function.
+pub struct Compressor {
+    settings: CompressorSettings,
+    settings_sender: ringbuffer::Sender<CompressorSettings>,
+}
+impl Compressor {
+    pub fn settings(&self) -> CompressorSettings {
+        self.settings
+    }
+    pub fn set_settings(&mut self, settings: CompressorSettings) {
+        self.settings = settings;
+        self.settings_sender.send(settings);
+    }
+
+    pub fn threshold(&self) -> f32 {
+        self.settings.threshold
+    }
+    pub fn set_threshold(&mut self, threshold: f32) {
+        self.set_settings(CompressorSettings {
+            threshold,
+            ..self.settings
+        });
+    }
+
+    pub fn ratio(&self) -> f32 {
+        self.settings.ratio
+    }
+    /// Ratios below 1 are treated as 1, i.e. no compression.
+    pub fn set_ratio(&mut self, ratio: f32) {
+        self.set_settings(CompressorSettings {
+            ratio,
+            ..self.settings
+        });
+    }
+
+    pub fn attack(&self) -> f32 {
+        self.settings.attack
+    }
+    pub fn set_attack(&mut self, attack: f32) {
+        self.set_settings(CompressorSettings {
+            attack,
+            ..self.settings
+        });
+    }
+
+    pub fn release(&self) -> f32 {
+        self.settings.release
+    }
+    pub fn set_release(&mut self, release: f32) {
+        self.set_settings(CompressorSettings {
+            release,
+            ..self.settings
+        });
+    }
+
+    pub fn makeup_gain(&self) -> f32 {
+        self.settings.makeup_gain
+    }
+    pub fn set_makeup_gain(&mut self, makeup_gain: f32) {
+        self.set_settings(CompressorSettings {
+            makeup_gain,
+            ..self.settings
+        });
+    }
+}
+impl Debug for Compressor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Compressor")
+            .field("settings", &self.settings)
+            .finish_non_exhaustive()
+    }
+}
+
+pub struct CompressorProcessor {
+    settings: CompressorSettings,
+    settings_receiver: ringbuffer::Receiver<CompressorSettings>,
+
+    /// Level of the input in meter scale, averaged over [`DETECTION_WINDOW`].
+    detection: MovingAverage,
+    /// The detected level after attack and release have been applied.
+    envelope: f32,
+}
+impl CompressorProcessor {
+    /// The portion of the distance to a new level that the envelope should move each frame,
+    /// to cover most of it in `time` seconds.
+    fn coefficient(time: f32, sample_rate: u32) -> f32 {
+        if time <= 0.0 {
+            1.0
+        } else {
+            1.0 - (-1.0 / (time * sample_rate as f32)).exp()
+        }
+    }
+
+    /// Compress `buffer` according to the level of `sidechain`, or of `buffer` itself if there is none.
+    fn compress(&mut self, info: &Info, buffer: &mut [Sample], sidechain: Option<&[Sample]>) {
+        let CompressorSettings {
+            threshold,
+            ratio,
+            attack,
+            release,
+            makeup_gain,
+        } = self.settings;
+
+        let attack = Self::coefficient(attack, info.sample_rate);
+        let release = Self::coefficient(release, info.sample_rate);
+        let threshold = inverse_meter_scale(threshold);
+        let exponent = 1.0 - 1.0 / ratio.max(1.0);
+
+        for (i, frame) in buffer.chunks_mut(CHANNELS).enumerate() {
+            let detected = match sidechain {
+                Some(sidechain) => &sidechain[i * CHANNELS..(i + 1) * CHANNELS],
+                None => &*frame,
+            };
+            let peak = detected.iter().fold(0.0, |max: Sample, s| max.max(s.abs()));
+            self.detection.push(meter_scale(peak as f32));
+
+            let level = self.detection.average();
+            let coefficient = if level > self.envelope {
+                attack
+            } else {
+                release
+            };
+            self.envelope += coefficient * (level - self.envelope);
+
+            let envelope = inverse_meter_scale(self.envelope);
+            let gain = if envelope > threshold {
+                (threshold / envelope).powf(exponent)
+            } else {
+                1.0
+            };
+
+            for sample in frame.iter_mut() {
+                *sample *= (gain * makeup_gain) as Sample;
+            }
+        }
+    }
+}
+impl Debug for CompressorProcessor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CompressorProcessor")
+            .field("settings", &self.settings)
+            .field("envelope", &self.envelope)
+            .finish_non_exhaustive()
+    }
+}
+impl Effect for CompressorProcessor {
+    fn poll(&mut self) {
+        while let Some(settings) = self.settings_receiver.recv() {
+            self.settings = settings;
+        }
+    }
+
+    fn process(&mut self, info: &Info, buffer: &mut [Sample]) {
+        self.compress(info, buffer, None);
+    }
+
+    fn process_sidechained(&mut self, info: &Info, buffer: &mut [Sample], sidechain: &[Sample]) {
+        self.compress(info, buffer, Some(sidechain));
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(settings: CompressorSettings, level: Sample) -> Sample {
+        let (_, mut cp) = compressor(settings);
+        let info = Info {
+            sample_rate: 48_000,
+            buffer_size: 1000,
+        };
+
+        let mut buffer = vec![level; 1000 * CHANNELS];
+        no_heap! {{
+            for _ in 0..48 {
+                buffer.fill(level);
+                cp.process(&info, &mut buffer);
+            }
+        }}
+        buffer[buffer.len() - 1]
+    }
+
+    #[test]
+    fn loud_is_attenuated() {
+        let settings = CompressorSettings {
+            threshold: meter_scale(0.1),
+            ratio: 4.0,
+            attack: 0.001,
+            release: 0.1,
+            makeup_gain: 1.0,
+        };
+
+        let quiet = run(settings, 0.05);
+        let loud = run(settings, 0.8);
+
+        // Below the threshold, nothing happens
+        assert!((quiet - 0.05).abs() < 1e-4);
+        assert!(loud / 0.8 < quiet / 0.05);
+        // 0.8 is 18 dB above the threshold, which should end up 4.5 dB above it
+        assert!((loud - 0.1 * Sample::powf(8.0, 0.25)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn sidechain_triggers_compression() {
+        let settings = CompressorSettings {
+            threshold: meter_scale(0.1),
+            ratio: 4.0,
+            attack: 0.001,
+            release: 0.1,
+            makeup_gain: 1.0,
+        };
+        let info = Info {
+            sample_rate: 48_000,
+            buffer_size: 1000,
+        };
+        let run = |level: Sample, sidechain_level: Sample| {
+            let (_, mut cp) = compressor(settings);
+            let mut buffer = vec![level; 1000 * CHANNELS];
+            let sidechain = vec![sidechain_level; 1000 * CHANNELS];
+            no_heap! {{
+                for _ in 0..48 {
+                    buffer.fill(level);
+                    cp.process_sidechained(&info, &mut buffer, &sidechain);
+                }
+            }}
+            buffer[buffer.len() - 1]
+        };
+
+        // Ducked by the loud sidechain, even though the signal itself is below the threshold
+        assert!(run(0.05, 0.8) < 0.05 / 2.0);
+        // Left alone by the silent sidechain, even though the signal itself is loud
+        assert!((run(0.8, 0.0) - 0.8).abs() < 1e-4);
+    }
+
+    #[test]
+    fn settings_are_received() {
+        let (mut c, mut cp) = compressor(CompressorSettings::default());
+
+        c.set_ratio(8.0);
+        c.set_makeup_gain(2.0);
+        no_heap! {{
+            cp.poll();
+        }}
+
+        assert_eq!(cp.settings, c.settings());
+        assert_eq!(cp.settings.ratio, 8.0);
+        assert_eq!(cp.settings.makeup_gain, 2.0);
+    }
+}
@@ -0,0 +1,241 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+
+use super::Effect;
+use crate::engine::components::delay::DelayPoint;
+use crate::engine::components::timeline::Timestamp;
+use crate::engine::info::Info;
+use crate::engine::utils::ringbuffer::{self, ringbuffer};
+use crate::engine::Sample;
+
+/// The longest delay time in seconds.
+pub const MAX_DELAY: f32 = 2.0;
+/// The highest sample rate at which the full [`MAX_DELAY`] is available.
+/// At higher sample rates the delay is capped to the same number of samples.
+const MAX_DELAY_SAMPLE_RATE: u32 = 192_000;
+/// Feedback is capped below 1, so the echoes always die out.
+const MAX_FEEDBACK: f32 = 0.99;
+
+pub fn delay(settings: DelaySettings) -> (Delay, DelayProcessor) {
+    let (settings_sender, settings_receiver) = ringbuffer();
+    let max_sample_delay = (MAX_DELAY * MAX_DELAY_SAMPLE_RATE as f32) as usize;
+
+    (
+        Delay {
+            settings,
+            settings_sender,
+        },
+        DelayProcessor {
+            settings,
+            settings_receiver,
+
+            delay_point: DelayPoint::with_max_delay(0, max_sample_delay),
+        },
+    )
+}
+
+/// How long each echo is delayed.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum DelayTime {
+    /// A fixed time in seconds.
+    Seconds(f32),
+    /// A musical length, played at the tempo given by [`DelaySettings::bpm_cents`].
+    Synced(Timestamp),
+}
+impl DelayTime {
+    /// The delay in frames, capped to [`MAX_DELAY`] seconds.
+    fn samples(self, sample_rate: u32, bpm_cents: u16) -> usize {
+        let seconds = match self {
+            DelayTime::Seconds(seconds) => seconds,
+            DelayTime::Synced(length) => length.seconds(sample_rate, bpm_cents) as f32,
+        };
+        (seconds.clamp(0.0, MAX_DELAY) * sample_rate as f32).round() as usize
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct DelaySettings {
+    pub time: DelayTime,
+    /// The tempo that a [`DelayTime::Synced`] time is measured in, multiplied by 100.
+    ///
+    /// Set this to [`Engine::bpm_cents`](crate::Engine::bpm_cents) to follow the project tempo.
+    pub bpm_cents: u16,
+    /// Portion of each echo that is fed back into the delay, between 0 and 1.
+    pub feedback: f32,
+    /// Portion of the output that is delayed, where `0.0` is only the dry signal and `1.0` is only the echoes.
+    pub mix: f32,
+}
+impl Default for DelaySettings {
+    fn default() -> Self {
+        Self {
+            time: DelayTime::Synced(Timestamp::from_beats(1)),
+            bpm_cents: 120_00,
+            feedback: 0.4,
+            mix: 0.3,
+        }
+    }
+}
+
+/// Acquired via the [`delay`] function.
+pub struct Delay {
+    settings: DelaySettings,
+    settings_sender: ringbuffer::Sender<DelaySettings>,
+}
+impl Delay {
+    pub fn settings(&self) -> DelaySettings {
+        self.settings
+    }
+    pub fn set_settings(&mut self, settings: DelaySettings) {
+        self.settings = settings;
+        self.settings_sender.send(settings);
+    }
+
+    pub fn time(&self) -> DelayTime {
+        self.settings.time
+    }
+    /// Times longer than [`MAX_DELAY`] are treated as [`MAX_DELAY`].
+    pub fn set_time(&mut self, time: DelayTime) {
+        self.set_settings(DelaySettings {
+            time,
+            ..self.settings
+        });
+    }
+
+    pub fn bpm_cents(&self) -> u16 {
+        self.settings.bpm_cents
+    }
+    pub fn set_bpm_cents(&mut self, bpm_cents: u16) {
+        self.set_settings(DelaySettings {
+            bpm_cents,
+            ..self.settings
+        });
+    }
+
+    pub fn feedback(&self) -> f32 {
+        self.settings.feedback
+    }
+    pub fn set_feedback(&mut self, feedback: f32) {
+        self.set_settings(DelaySettings {
+            feedback,
+            ..self.settings
+        });
+    }
+
+    pub fn mix(&self) -> f32 {
+        self.settings.mix
+    }
+    pub fn set_mix(&mut self, mix: f32) {
+        self.set_settings(DelaySettings {
+            mix,
+            ..self.settings
+        });
+    }
+}
+impl Debug for Delay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Delay")
+            .field("settings", &self.settings)
+            .finish_non_exhaustive()
+    }
+}
+
+pub struct DelayProcessor {
+    settings: DelaySettings,
+    settings_receiver: ringbuffer::Receiver<DelaySettings>,
+
+    /// Preallocated for the longest possible delay, so changing the time never allocates.
+    delay_point: DelayPoint,
+}
+impl Debug for DelayProcessor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DelayProcessor")
+            .field("settings", &self.settings)
+            .finish_non_exhaustive()
+    }
+}
+impl Effect for DelayProcessor {
+    fn poll(&mut self) {
+        while let Some(settings) = self.settings_receiver.recv() {
+            self.settings = settings;
+        }
+    }
+
+    fn process(&mut self, info: &Info, buffer: &mut [Sample]) {
+        let DelaySettings {
+            time,
+            bpm_cents,
+            feedback,
+            mix,
+        } = self.settings;
+
+        self.delay_point
+            .set_delay(time.samples(info.sample_rate, bpm_cents));
+        let feedback = feedback.clamp(0.0, MAX_FEEDBACK) as Sample;
+        let mix = mix.clamp(0.0, 1.0) as Sample;
+
+        for sample in buffer.iter_mut() {
+            let delayed = self.delay_point.delayed();
+            self.delay_point.push(*sample + delayed * feedback);
+            *sample = *sample * (1.0 - mix) + delayed * mix;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::CHANNELS;
+
+    #[test]
+    fn echoes_decay() {
+        let settings = DelaySettings {
+            time: DelayTime::Seconds(0.01),
+            bpm_cents: 120_00,
+            feedback: 0.5,
+            mix: 1.0,
+        };
+        let (_, mut dp) = delay(settings);
+        let info = Info {
+            sample_rate: 48_000,
+            buffer_size: 2000,
+        };
+
+        // One impulse, followed by silence
+        let mut buffer = vec![0.0; 2000 * CHANNELS];
+        buffer[0] = 1.0;
+        no_heap! {{
+            dp.process(&info, &mut buffer);
+        }}
+
+        // Delayed by 480 frames, and halved for each echo
+        assert_eq!(buffer[0], 0.0);
+        assert_eq!(buffer[480 * CHANNELS], 1.0);
+        assert_eq!(buffer[2 * 480 * CHANNELS], 0.5);
+        assert_eq!(buffer[3 * 480 * CHANNELS], 0.25);
+        assert_eq!(buffer.iter().filter(|&&s| s != 0.0).count(), 4);
+    }
+
+    #[test]
+    fn synced_time_follows_tempo() {
+        let time = DelayTime::Synced(Timestamp::from_beats(1));
+        assert_eq!(time.samples(48_000, 120_00), 24_000);
+        assert_eq!(time.samples(48_000, 60_00), 48_000);
+        // Capped to the max delay
+        assert_eq!(time.samples(48_000, 1_00), 96_000);
+    }
+
+    #[test]
+    fn settings_are_received() {
+        let (mut d, mut dp) = delay(DelaySettings::default());
+
+        d.set_time(DelayTime::Seconds(0.25));
+        d.set_feedback(0.7);
+        no_heap! {{
+            dp.poll();
+        }}
+
+        assert_eq!(dp.settings, d.settings());
+        assert_eq!(dp.settings.time, DelayTime::Seconds(0.25));
+        assert_eq!(dp.settings.feedback, 0.7);
+    }
+}
@@ -0,0 +1,208 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+
+use crate::engine::info::Info;
+use crate::engine::utils::ringbuffer::{self, ringbuffer};
+use crate::engine::utils::CircularArray;
+use crate::engine::{Sample, CHANNELS};
+
+/// Number of frames the limiter looks ahead, which is also the delay it adds to the signal.
+const LOOKAHEAD: usize = 64;
+
+pub fn limiter(settings: Option<LimiterSettings>) -> (Limiter, LimiterProcessor) {
+    let (settings_sender, settings_receiver) = ringbuffer();
+
+    (
+        Limiter {
+            settings,
+            settings_sender,
+        },
+        LimiterProcessor {
+            settings,
+            settings_receiver,
+
+            delay: CircularArray::new([0.0; CHANNELS], LOOKAHEAD),
+            required_gains: CircularArray::new(1.0, LOOKAHEAD + 1),
+            gain: 1.0,
+        },
+    )
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct LimiterSettings {
+    /// The linear amplitude that the output never exceeds.
+    pub ceiling: f32,
+    /// Seconds it takes for the limiter to let go after a peak has passed.
+    pub release: f32,
+}
+impl Default for LimiterSettings {
+    fn default() -> Self {
+        Self {
+            ceiling: 1.0,
+            release: 0.1,
+        }
+    }
+}
+
+/// Acquired via the [`limiter`] function.
+pub struct Limiter {
+    settings: Option<LimiterSettings>,
+    settings_sender: ringbuffer::Sender<Option<LimiterSettings>>,
+}
+impl Limiter {
+    /// Is `None` while the limiter is bypassed.
+    pub fn settings(&self) -> Option<LimiterSettings> {
+        self.settings
+    }
+    pub fn set_settings(&mut self, settings: Option<LimiterSettings>) {
+        self.settings = settings;
+        self.settings_sender.send(settings);
+    }
+}
+impl Debug for Limiter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Limiter")
+            .field("settings", &self.settings)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A brickwall limiter, which lowers the gain ahead of each peak, so it never exceeds the ceiling.
+pub struct LimiterProcessor {
+    settings: Option<LimiterSettings>,
+    settings_receiver: ringbuffer::Receiver<Option<LimiterSettings>>,
+
+    /// The last [`LOOKAHEAD`] input frames.
+    delay: CircularArray<[Sample; CHANNELS]>,
+    /// The gain needed to keep each frame in `delay` under the ceiling,
+    /// along with the frame that was just output from it.
+    required_gains: CircularArray<f32>,
+    /// The gain applied to the last output frame.
+    gain: f32,
+}
+impl LimiterProcessor {
+    pub fn poll(&mut self) {
+        while let Some(settings) = self.settings_receiver.recv() {
+            if settings.is_some() != self.settings.is_some() {
+                // Start over, so nothing from before the limiter was bypassed is output
+                self.delay.fill([0.0; CHANNELS]);
+                self.required_gains.fill(1.0);
+                self.gain = 1.0;
+            }
+            self.settings = settings;
+        }
+    }
+
+    /// Process the interleaved `buffer` in place.
+    ///
+    /// Does nothing while the limiter is bypassed.
+    pub fn process(&mut self, info: &Info, buffer: &mut [Sample]) {
+        let Some(LimiterSettings { ceiling, release }) = self.settings else {
+            return;
+        };
+
+        let release = if release <= 0.0 {
+            1.0
+        } else {
+            1.0 - (-1.0 / (release * info.sample_rate as f32)).exp()
+        };
+
+        for frame in buffer.chunks_exact_mut(CHANNELS) {
+            let peak = frame.iter().fold(0.0, |max: Sample, s| max.max(s.abs()));
+            let peak = peak as f32;
+            let required_gain = if peak > ceiling { ceiling / peak } else { 1.0 };
+            self.required_gains.push_pop(required_gain);
+
+            let mut input = [0.0; CHANNELS];
+            input.copy_from_slice(frame);
+            let delayed = self.delay.push_pop(input);
+
+            // The gain drops as soon as a peak enters the lookahead, and is held until it has been output
+            let window_gain = self
+                .required_gains
+                .iter()
+                .fold(1.0, |min: f32, &g| min.min(g));
+            self.gain = window_gain.min(self.gain + release * (1.0 - self.gain));
+
+            for (sample, delayed) in frame.iter_mut().zip(delayed) {
+                // Guards against rounding errors in the gain
+                *sample =
+                    (delayed * self.gain as Sample).clamp(-ceiling as Sample, ceiling as Sample);
+            }
+        }
+    }
+}
+impl Debug for LimiterProcessor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LimiterProcessor")
+            .field("settings", &self.settings)
+            .field("gain", &self.gain)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::utils::test_sine;
+
+    /// A 440 Hz sine at the given amplitude, starting `offset` frames in.
+    fn sine(amplitude: Sample, offset: usize) -> Vec<Sample> {
+        test_sine(440.0, amplitude, offset..offset + 1000)
+    }
+
+    #[test]
+    fn output_stays_under_ceiling() {
+        let settings = LimiterSettings {
+            ceiling: 0.5,
+            release: 0.05,
+        };
+        let (_, mut lp) = limiter(Some(settings));
+        let info = Info {
+            sample_rate: 48_000,
+            buffer_size: 1000,
+        };
+
+        // 6 dB above full scale
+        let mut buffers: Vec<_> = (0..48).map(|i| sine(2.0, i * 1000)).collect();
+        no_heap! {{
+            for buffer in buffers.iter_mut() {
+                lp.process(&info, buffer);
+            }
+        }}
+
+        let output = buffers.concat();
+        assert!(output.iter().all(|s| s.abs() <= settings.ceiling as Sample));
+        // The signal is kept at the ceiling, rather than being squashed far below it
+        let peak = output[output.len() / 2..]
+            .iter()
+            .fold(0.0, |max: Sample, s| max.max(s.abs()));
+        assert!(peak > 0.45);
+    }
+
+    #[test]
+    fn bypassed_is_untouched() {
+        let (mut l, mut lp) = limiter(None);
+        let info = Info {
+            sample_rate: 48_000,
+            buffer_size: 1000,
+        };
+
+        let input = sine(2.0, 0);
+        let mut buffer = input.clone();
+        no_heap! {{
+            lp.poll();
+            lp.process(&info, &mut buffer);
+        }}
+        assert_eq!(buffer, input);
+
+        l.set_settings(Some(LimiterSettings::default()));
+        no_heap! {{
+            lp.poll();
+            lp.process(&info, &mut buffer);
+        }}
+        assert!(buffer.iter().all(|s| s.abs() <= 1.0));
+        // Delayed by the lookahead
+        assert!(buffer[..LOOKAHEAD * CHANNELS].iter().all(|&s| s == 0.0));
+    }
+}
@@ -0,0 +1,384 @@
+use std::f64::consts::PI;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::engine::utils::{non_copy_array, AtomicF32, CircularArray};
+use crate::engine::{Sample, CHANNELS};
+
+/// Number of 100 ms blocks in the momentary window of 400 ms.
+const MOMENTARY_BLOCKS: usize = 4;
+/// Number of 100 ms blocks in the short-term window of 3 s.
+const SHORT_TERM_BLOCKS: usize = 30;
+
+/// Blocks quieter than this are ignored by the integrated loudness.
+const ABSOLUTE_GATE: f64 = -70.0;
+/// Blocks quieter than this, relative to the loudness of the blocks above the absolute gate,
+/// are ignored by the integrated loudness.
+const RELATIVE_GATE: f64 = -10.0;
+/// Width of each bin of the gating histogram, in LU.
+const BIN_WIDTH: f64 = 0.1;
+/// Number of bins in the gating histogram, spanning from [`ABSOLUTE_GATE`] to +10 LUFS.
+const BINS: usize = 800;
+
+pub fn loudness_meter() -> (LoudnessMeter, LoudnessMeterProcessor) {
+    let enabled1 = Arc::new(AtomicBool::new(false));
+    let enabled2 = Arc::clone(&enabled1);
+
+    let reading1 = Arc::new(non_copy_array![AtomicF32::new(f32::NEG_INFINITY); 3]);
+    let reading2 = Arc::clone(&reading1);
+
+    (
+        LoudnessMeter {
+            enabled: enabled1,
+            reading: reading1,
+        },
+        LoudnessMeterProcessor {
+            enabled: enabled2,
+            was_enabled: false,
+            reading: reading2,
+
+            sample_rate: 0,
+            filters: non_copy_array![KWeighting::new(48_000); CHANNELS],
+
+            block_frames: 0,
+            block_progress: 0,
+            block_sum: 0.0,
+            filled_blocks: 0,
+            momentary_blocks: CircularArray::new(0.0, MOMENTARY_BLOCKS),
+            short_term_blocks: CircularArray::new(0.0, SHORT_TERM_BLOCKS),
+
+            histogram: vec![(0, 0.0); BINS],
+        },
+    )
+}
+
+/// Loudness in LUFS, as measured by ITU-R BS.1770.
+///
+/// Readings are negative infinity while there is nothing to measure.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoudnessReading {
+    /// Loudness of the last 400 ms.
+    pub momentary: f32,
+    /// Loudness of the last 3 seconds.
+    pub short_term: f32,
+    /// Gated loudness of everything since the meter was enabled.
+    pub integrated: f32,
+}
+
+/// Acquired via the [`loudness_meter`] function.
+#[derive(Debug)]
+pub struct LoudnessMeter {
+    enabled: Arc<AtomicBool>,
+    /// `[momentary, short_term, integrated]`
+    reading: Arc<[AtomicF32; 3]>,
+}
+impl LoudnessMeter {
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+    /// Enabling the meter starts a new measurement, discarding the previous one.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn read(&self) -> LoudnessReading {
+        let [momentary, short_term, integrated] = &*self.reading;
+        LoudnessReading {
+            momentary: momentary.load(Ordering::Relaxed),
+            short_term: short_term.load(Ordering::Relaxed),
+            integrated: integrated.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Acquired via the [`loudness_meter`] function.
+#[derive(Debug)]
+pub struct LoudnessMeterProcessor {
+    enabled: Arc<AtomicBool>,
+    was_enabled: bool,
+    reading: Arc<[AtomicF32; 3]>,
+
+    /// The sample rate the filters are currently designed for.
+    sample_rate: u32,
+    filters: [KWeighting; CHANNELS],
+
+    /// Number of frames in each block of 100 ms.
+    block_frames: usize,
+    block_progress: usize,
+    /// Sum of the squares of the filtered samples in the current block, summed over all channels.
+    block_sum: f64,
+    /// Number of blocks measured so far, up until [`MOMENTARY_BLOCKS`].
+    filled_blocks: usize,
+    /// Mean squares of the last blocks.
+    momentary_blocks: CircularArray<f64>,
+    short_term_blocks: CircularArray<f64>,
+
+    /// Count and sum of the mean squares of the 400 ms gating blocks, sorted into bins by loudness.
+    ///
+    /// This allows the gated loudness to be calculated without storing every block,
+    /// at the cost of the relative gate being rounded down to the nearest bin.
+    histogram: Vec<(u64, f64)>,
+}
+impl LoudnessMeterProcessor {
+    /// Measure the buffer, and sync the result to the corresponding [`LoudnessMeter`].
+    pub fn report(&mut self, buffer: &[Sample], sample_rate: u32) {
+        let enabled = self.enabled.load(Ordering::Relaxed);
+        if enabled && (!self.was_enabled || sample_rate != self.sample_rate) {
+            self.reset(sample_rate);
+        }
+        self.was_enabled = enabled;
+        if !enabled {
+            return;
+        }
+
+        for frame in buffer.chunks(CHANNELS) {
+            for (filter, &sample) in self.filters.iter_mut().zip(frame) {
+                let filtered = filter.process(sample as f64);
+                self.block_sum += filtered * filtered;
+            }
+
+            self.block_progress += 1;
+            if self.block_progress == self.block_frames {
+                self.end_block();
+            }
+        }
+    }
+
+    fn reset(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.filters = non_copy_array![KWeighting::new(sample_rate); CHANNELS];
+
+        self.block_frames = (sample_rate / 10) as usize;
+        self.block_progress = 0;
+        self.block_sum = 0.0;
+        self.filled_blocks = 0;
+        self.momentary_blocks.fill(0.0);
+        self.short_term_blocks.fill(0.0);
+        self.histogram.fill((0, 0.0));
+
+        for reading in self.reading.iter() {
+            reading.store(f32::NEG_INFINITY, Ordering::Relaxed);
+        }
+    }
+
+    fn end_block(&mut self) {
+        let mean_square = self.block_sum / self.block_frames as f64;
+        self.block_progress = 0;
+        self.block_sum = 0.0;
+
+        self.momentary_blocks.push_pop(mean_square);
+        self.short_term_blocks.push_pop(mean_square);
+        self.filled_blocks = (self.filled_blocks + 1).min(MOMENTARY_BLOCKS);
+
+        let momentary = mean(self.momentary_blocks.iter().copied(), MOMENTARY_BLOCKS);
+        let short_term = mean(self.short_term_blocks.iter().copied(), SHORT_TERM_BLOCKS);
+
+        // Gating blocks of 400 ms overlap by 75%, so one ends with every block of 100 ms
+        if self.filled_blocks == MOMENTARY_BLOCKS {
+            let loudness = loudness(momentary);
+            if loudness >= ABSOLUTE_GATE {
+                let (count, sum) = &mut self.histogram[bin(loudness)];
+                *count += 1;
+                *sum += momentary;
+            }
+        }
+
+        let [a_momentary, a_short_term, a_integrated] = &*self.reading;
+        a_momentary.store(loudness(momentary) as f32, Ordering::Relaxed);
+        a_short_term.store(loudness(short_term) as f32, Ordering::Relaxed);
+        a_integrated.store(self.integrated() as f32, Ordering::Relaxed);
+    }
+
+    fn integrated(&self) -> f64 {
+        let gated_mean = |bins: &[(u64, f64)]| {
+            let (count, sum) = bins
+                .iter()
+                .fold((0, 0.0), |(count, sum), &(c, s)| (count + c, sum + s));
+            if count == 0 {
+                0.0
+            } else {
+                sum / count as f64
+            }
+        };
+
+        let absolute = loudness(gated_mean(&self.histogram));
+        if absolute == f64::NEG_INFINITY {
+            return absolute;
+        }
+
+        let relative_gate = absolute + RELATIVE_GATE;
+        let first_bin = if relative_gate < ABSOLUTE_GATE {
+            0
+        } else {
+            bin(relative_gate)
+        };
+        loudness(gated_mean(&self.histogram[first_bin..]))
+    }
+}
+
+/// Loudness in LUFS of the given mean square, summed over all channels.
+fn loudness(mean_square: f64) -> f64 {
+    -0.691 + 10.0 * mean_square.log10()
+}
+
+fn mean(values: impl Iterator<Item = f64>, count: usize) -> f64 {
+    values.sum::<f64>() / count as f64
+}
+
+/// The histogram bin of the given loudness, which must be at least [`ABSOLUTE_GATE`].
+fn bin(loudness: f64) -> usize {
+    (((loudness - ABSOLUTE_GATE) / BIN_WIDTH) as usize).min(BINS - 1)
+}
+
+/// The K-weighting filter from ITU-R BS.1770,
+/// consisting of a high shelf modelling the acoustic effect of the head, followed by a high-pass filter.
+#[derive(Debug)]
+struct KWeighting {
+    shelf: Biquad,
+    high_pass: Biquad,
+}
+impl KWeighting {
+    fn new(sample_rate: u32) -> Self {
+        let sample_rate = f64::from(sample_rate);
+
+        // The coefficients given by the standard only apply to 48 kHz,
+        // so they are derived from the underlying analog filters instead
+        let shelf = {
+            let frequency = 1_681.974_450_955_533;
+            let gain = 3.999_843_853_973_347;
+            let q = 0.707_175_236_955_419_6;
+
+            let k = (PI * frequency / sample_rate).tan();
+            let vh = 10_f64.powf(gain / 20.0);
+            let vb = vh.powf(0.499_666_774_154_541_6);
+
+            Biquad::new(
+                [
+                    vh + vb * k / q + k * k,
+                    2.0 * (k * k - vh),
+                    vh - vb * k / q + k * k,
+                ],
+                [
+                    1.0 + k / q + k * k,
+                    2.0 * (k * k - 1.0),
+                    1.0 - k / q + k * k,
+                ],
+            )
+        };
+
+        let high_pass = {
+            let frequency = 38.135_470_876_024_44;
+            let q = 0.500_327_037_323_877_3;
+
+            let k = (PI * frequency / sample_rate).tan();
+            let a0 = 1.0 + k / q + k * k;
+
+            // Unlike the shelf, the numerator is not normalized by a0
+            Biquad::new(
+                [a0, -2.0 * a0, a0],
+                [a0, 2.0 * (k * k - 1.0), 1.0 - k / q + k * k],
+            )
+        };
+
+        Self { shelf, high_pass }
+    }
+
+    fn process(&mut self, sample: f64) -> f64 {
+        self.high_pass.process(self.shelf.process(sample))
+    }
+}
+
+/// Second order IIR filter in transposed direct form II.
+#[derive(Debug)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    state: [f64; 2],
+}
+impl Biquad {
+    /// Create a filter from its coefficients, normalizing them by `a[0]`.
+    fn new(b: [f64; 3], a: [f64; 3]) -> Self {
+        Self {
+            b: b.map(|b| b / a[0]),
+            a: [a[1] / a[0], a[2] / a[0]],
+            state: [0.0; 2],
+        }
+    }
+
+    fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.state[0];
+        self.state[0] = self.b[1] * input - self.a[0] * output + self.state[1];
+        self.state[1] = self.b[2] * input - self.a[1] * output;
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+
+    use super::*;
+
+    /// Measure a 1 kHz sine on all channels, switching from `amplitude1` to `amplitude2` halfway through.
+    fn measure(amplitude1: Sample, amplitude2: Sample, seconds: usize) -> LoudnessReading {
+        let (lm, mut lmp) = loudness_meter();
+        lm.set_enabled(true);
+
+        let sample_rate = 48_000;
+        let frames = seconds * sample_rate as usize;
+        let buffer: Vec<Sample> = (0..frames)
+            .flat_map(|i| {
+                let amplitude = if i < frames / 2 {
+                    amplitude1
+                } else {
+                    amplitude2
+                };
+                let sample =
+                    amplitude * (TAU * 1000.0 * i as f32 / sample_rate as f32).sin() as Sample;
+                [sample; CHANNELS]
+            })
+            .collect();
+
+        for chunk in buffer.chunks(1024 * CHANNELS) {
+            no_heap! {{
+                lmp.report(chunk, sample_rate);
+            }}
+        }
+        lm.read()
+    }
+
+    #[test]
+    fn full_scale_sine() {
+        // A full scale 1 kHz sine on both channels should measure about 0 LUFS
+        let reading = measure(1.0, 1.0, 4);
+
+        assert!(reading.momentary.abs() < 0.1);
+        assert!(reading.short_term.abs() < 0.1);
+        assert!(reading.integrated.abs() < 0.1);
+    }
+
+    #[test]
+    fn quieter_sine() {
+        let reading = measure(0.1, 0.1, 4);
+
+        assert!((reading.momentary + 20.0).abs() < 0.1);
+        assert!((reading.integrated + 20.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn silence() {
+        let reading = measure(0.0, 0.0, 1);
+
+        assert_eq!(reading.momentary, f32::NEG_INFINITY);
+        assert_eq!(reading.integrated, f32::NEG_INFINITY);
+    }
+
+    #[test]
+    fn relative_gate() {
+        // The quiet half is more than 10 LU below the loud half, so it is gated out
+        let reading = measure(1.0, 0.01, 8);
+
+        assert!(reading.integrated.abs() < 0.2);
+        assert!(reading.momentary < -35.0);
+    }
+}
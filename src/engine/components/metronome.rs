@@ -0,0 +1,325 @@
+use std::{
+    cmp::max,
+    f32::consts::TAU,
+    num::NonZeroU32,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+use super::timeline::{Tempo, TempoMap, TimeSignature, TimeSignatureMap, Timestamp};
+use crate::engine::{utils::AtomicF32, Sample, CHANNELS};
+
+/// Frequency of the click on regular beats.
+const BEAT_FREQUENCY: f32 = 1000.0;
+/// Frequency of the click on the first beat of each bar.
+const DOWNBEAT_FREQUENCY: f32 = 1500.0;
+/// Duration of a click in seconds.
+const CLICK_DURATION: f32 = 0.05;
+
+pub fn metronome(sample_rate: u32, max_buffer_size: usize) -> (Metronome, MetronomeProcessor) {
+    let enabled1 = Arc::new(AtomicBool::new(false));
+    let enabled2 = Arc::clone(&enabled1);
+
+    let gain1 = Arc::new(AtomicF32::new(1.0));
+    let gain2 = Arc::clone(&gain1);
+
+    let beats_per_bar1 = Arc::new(AtomicU32::new(0));
+    let beats_per_bar2 = Arc::clone(&beats_per_bar1);
+
+    let count_in1 = Arc::new(AtomicU32::new(0));
+    let count_in2 = Arc::clone(&count_in1);
+
+    (
+        Metronome {
+            enabled: enabled1,
+            gain: gain1,
+            beats_per_bar: beats_per_bar1,
+            count_in: count_in1,
+        },
+        MetronomeProcessor {
+            sample_rate,
+
+            enabled: enabled2,
+            gain: gain2,
+            beats_per_bar: beats_per_bar2,
+            count_in: count_in2,
+
+            click_frequency: BEAT_FREQUENCY,
+            click_progress: None,
+
+            buffer: vec![0.0; max_buffer_size * CHANNELS],
+        },
+    )
+}
+
+/// Acquired via the [`metronome`] function.
+#[derive(Debug)]
+pub struct Metronome {
+    enabled: Arc<AtomicBool>,
+    gain: Arc<AtomicF32>,
+    /// Zero if downbeats should follow the time signature.
+    beats_per_bar: Arc<AtomicU32>,
+    /// Beats clicked before playback starts.
+    count_in: Arc<AtomicU32>,
+}
+impl Metronome {
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn gain(&self) -> f32 {
+        self.gain.load(Ordering::Relaxed)
+    }
+    pub fn set_gain(&self, gain: f32) {
+        self.gain.store(gain, Ordering::Relaxed);
+    }
+
+    pub fn beats_per_bar(&self) -> Option<NonZeroU32> {
+        NonZeroU32::new(self.beats_per_bar.load(Ordering::Relaxed))
+    }
+    pub fn set_beats_per_bar(&self, beats_per_bar: Option<NonZeroU32>) {
+        let beats_per_bar = beats_per_bar.map_or(0, NonZeroU32::get);
+        self.beats_per_bar.store(beats_per_bar, Ordering::Relaxed);
+    }
+
+    pub fn count_in(&self) -> u32 {
+        self.count_in.load(Ordering::Relaxed)
+    }
+    pub fn set_count_in(&self, beats: u32) {
+        self.count_in.store(beats, Ordering::Relaxed);
+    }
+}
+
+pub struct MetronomeProcessor {
+    sample_rate: u32,
+
+    enabled: Arc<AtomicBool>,
+    gain: Arc<AtomicF32>,
+    beats_per_bar: Arc<AtomicU32>,
+    count_in: Arc<AtomicU32>,
+
+    click_frequency: f32,
+    /// The number of frames played of the current click, if one is playing.
+    click_progress: Option<usize>,
+
+    buffer: Vec<Sample>,
+}
+impl MetronomeProcessor {
+    /// The number of beats to click before playback starts.
+    pub fn count_in(&self) -> u32 {
+        self.count_in.load(Ordering::Relaxed)
+    }
+
+    /// Synthesize the clicks of the beats between `position` and `position + buffer_size` in samples,
+    /// starting at `offset` frames into the output buffer.
+    ///
+    /// Unless a fixed number of beats per bar is set, beats where a bar of `time_signatures` starts are accented.
+    ///
+    /// The output buffer can then be retrieved with [`Self::output`].
+    pub fn render(
+        &mut self,
+        tempo_map: &TempoMap,
+        time_signatures: &TimeSignatureMap,
+        position: usize,
+        offset: usize,
+        buffer_size: usize,
+    ) {
+        if !self.enabled.load(Ordering::Relaxed) {
+            self.silence(offset, buffer_size);
+            return;
+        }
+
+        let gain = self.gain.load(Ordering::Relaxed);
+        let beats_per_bar = self.beats_per_bar.load(Ordering::Relaxed);
+
+        let sample_rate = self.sample_rate;
+        let beat_samples = |beat| tempo_map.samples(Timestamp::from_beats(beat), sample_rate);
+        let mut beat = tempo_map.timestamp(position, self.sample_rate).beats();
+        let mut next_beat = beat_samples(beat);
+        while next_beat < position {
+            beat += 1;
+            next_beat = beat_samples(beat);
+        }
+
+        for i in 0..buffer_size {
+            if position + i == next_beat {
+                let is_downbeat = if beats_per_bar != 0 {
+                    beat.is_multiple_of(beats_per_bar)
+                } else {
+                    let (_, beat_in_bar, units) =
+                        Timestamp::from_beats(beat).to_bars_beats(time_signatures);
+                    beat_in_bar == 1 && units == 0
+                };
+                self.start_click(is_downbeat);
+                beat += 1;
+                next_beat = beat_samples(beat);
+            }
+
+            let sample = self.next_click_sample(gain) as Sample;
+            self.buffer[(offset + i) * CHANNELS..(offset + i + 1) * CHANNELS].fill(sample);
+        }
+    }
+
+    /// Synthesize the clicks of a count-in, from `progress` to `progress + buffer_size` frames into it,
+    /// starting at `offset` frames into the output buffer.
+    ///
+    /// The count-in clicks every `beat_length` frames, accenting the first beat of each bar from the first one.
+    /// Unless a fixed number of beats per bar is set, the bars are as long as those of `time_signature`.
+    /// Unlike [`Self::render`], it clicks even if the metronome is disabled.
+    pub fn render_count_in(
+        &mut self,
+        beat_length: usize,
+        time_signature: TimeSignature,
+        progress: usize,
+        offset: usize,
+        buffer_size: usize,
+    ) {
+        let gain = self.gain.load(Ordering::Relaxed);
+        let beats_per_bar = match self.beats_per_bar.load(Ordering::Relaxed) {
+            0 => max(time_signature.bar_length().beats(), 1),
+            beats_per_bar => beats_per_bar,
+        };
+        for i in 0..buffer_size {
+            let pos = progress + i;
+            if pos.is_multiple_of(beat_length) {
+                let beat = (pos / beat_length) as u32;
+                self.start_click(beat.is_multiple_of(beats_per_bar));
+            }
+
+            let sample = self.next_click_sample(gain) as Sample;
+            self.buffer[(offset + i) * CHANNELS..(offset + i + 1) * CHANNELS].fill(sample);
+        }
+    }
+
+    fn start_click(&mut self, downbeat: bool) {
+        self.click_frequency = if downbeat {
+            DOWNBEAT_FREQUENCY
+        } else {
+            BEAT_FREQUENCY
+        };
+        self.click_progress = Some(0);
+    }
+
+    /// The next sample of the click that is playing, if any.
+    fn next_click_sample(&mut self, gain: f32) -> f32 {
+        let Some(progress) = self.click_progress else {
+            return 0.0;
+        };
+        let t = progress as f32 / self.sample_rate as f32;
+        if t < CLICK_DURATION {
+            self.click_progress = Some(progress + 1);
+            let envelope = 1.0 - t / CLICK_DURATION;
+            gain * envelope * envelope * (TAU * self.click_frequency * t).sin()
+        } else {
+            self.click_progress = None;
+            0.0
+        }
+    }
+
+    /// Fill `buffer_size` frames from `offset` with silence, cutting off any click that might be playing.
+    pub fn silence(&mut self, offset: usize, buffer_size: usize) {
+        self.buffer[offset * CHANNELS..(offset + buffer_size) * CHANNELS].fill(0.0);
+        self.click_progress = None;
+    }
+
+    /// The clicks rendered for the first `buffer_size` frames.
+    pub fn output(&self, buffer_size: usize) -> &[Sample] {
+        &self.buffer[..buffer_size * CHANNELS]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clicks_on_beats() {
+        let (m, mut mp) = metronome(48_000, 48_000);
+        m.set_enabled(true);
+        let tempo_map = TempoMap::new(120_00);
+        let time_signatures = TimeSignatureMap::default();
+
+        // Two beats at 120 BPM
+        no_heap! {{
+            mp.render(&tempo_map, &time_signatures, 0, 0, 48_000);
+        }}
+        let output = mp.output(48_000);
+
+        let starts: Vec<_> = (1..48_000)
+            .filter(|&i| output[(i - 1) * CHANNELS] == 0.0 && output[i * CHANNELS] != 0.0)
+            .collect();
+        // The very first click starts at the first frame, with a sine phase of zero
+        assert_eq!(starts, [1, 24_001]);
+        assert!(output[24_000 * CHANNELS..].iter().any(|&s| s != 0.0));
+        assert!(output[12_000 * CHANNELS..24_000 * CHANNELS]
+            .iter()
+            .all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn disabled_is_silent() {
+        let (_, mut mp) = metronome(48_000, 1000);
+        let tempo_map = TempoMap::new(120_00);
+
+        mp.render(&tempo_map, &TimeSignatureMap::default(), 0, 0, 1000);
+        assert!(mp.output(1000).iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn downbeats_are_higher() {
+        let (m, mut mp) = metronome(48_000, 100);
+        m.set_enabled(true);
+        m.set_beats_per_bar(NonZeroU32::new(4));
+        let tempo_map = TempoMap::new(120_00);
+
+        mp.render(&tempo_map, &TimeSignatureMap::default(), 0, 0, 10);
+        let downbeat = mp.output(10).to_vec();
+        mp.render(&tempo_map, &TimeSignatureMap::default(), 24_000, 0, 10);
+        let beat = mp.output(10).to_vec();
+
+        // A higher frequency rises faster from zero
+        assert!(downbeat[CHANNELS] > beat[CHANNELS]);
+    }
+
+    #[test]
+    fn count_in_clicks_while_disabled() {
+        let (_, mut mp) = metronome(48_000, 48_000);
+
+        // Two beats of 24 000 frames, split over two buffers
+        no_heap! {{
+            mp.render_count_in(24_000, TimeSignature::default(), 0, 0, 30_000);
+            mp.render_count_in(24_000, TimeSignature::default(), 30_000, 30_000, 18_000);
+        }}
+        let output = mp.output(48_000);
+
+        let starts: Vec<_> = (1..48_000)
+            .filter(|&i| output[(i - 1) * CHANNELS] == 0.0 && output[i * CHANNELS] != 0.0)
+            .collect();
+        assert_eq!(starts, [1, 24_001]);
+        // Only the first beat of the bar is accented
+        assert!(output[CHANNELS] > output[24_001 * CHANNELS]);
+    }
+
+    #[test]
+    fn downbeats_follow_time_signature() {
+        let (m, mut mp) = metronome(48_000, 100);
+        m.set_enabled(true);
+        let tempo_map = TempoMap::new(120_00);
+        let time_signatures = TimeSignatureMap::new(TimeSignature::new(3, 4).unwrap());
+
+        let mut first_frames = |beat: usize| {
+            mp.render(&tempo_map, &time_signatures, beat * 24_000, 0, 10);
+            mp.output(10)[CHANNELS]
+        };
+        let downbeat = first_frames(3);
+        let beat = first_frames(4);
+
+        assert!(downbeat > beat);
+        assert_eq!(first_frames(0), downbeat);
+    }
+}
@@ -4,7 +4,13 @@ use std::collections::HashSet;
 use std::error::Error;
 use std::fmt::Debug;
 use std::fmt::Display;
+use std::iter::zip;
 
+use super::limiter::{limiter, Limiter, LimiterProcessor, LimiterSettings};
+use super::loudness_meter::{loudness_meter, LoudnessMeter, LoudnessMeterProcessor};
+use super::parameter::{f32_parameter, F32Parameter, F32ParameterProcessor};
+use super::saturation::{saturation, Saturation, SaturationProcessor, SaturationSettings};
+use super::timeline::Timestamp;
 use super::track::MixerTrackKey;
 use super::track::{
     mixer_track, mixer_track_from_state, MixerTrack, MixerTrackProcessor, MixerTrackState,
@@ -16,16 +22,29 @@ use crate::engine::utils::key_generator;
 use crate::engine::utils::key_generator::KeyGenerator;
 use crate::engine::utils::remote_push::RemotePushable;
 use crate::engine::utils::remote_push::{RemotePushedHashMap, RemotePusherHashMap};
+use crate::engine::utils::ringbuffer::{self, ringbuffer};
+use crate::engine::utils::simd;
+use crate::engine::utils::smallest_pow2;
+use crate::engine::utils::unity_gain;
+use crate::engine::utils::worker_pool::WorkerPool;
 use crate::engine::Sample;
 use crate::engine::CHANNELS;
 
-pub fn mixer(state: &MixerState, max_buffer_size: usize) -> (Mixer, MixerProcessor) {
+/// The fewest tracks of the same rank, that are spread across threads instead of being processed one by one.
+const PARALLEL_MIN_TRACKS: usize = 4;
+
+/// Changes to the parameters of the tracks are smoothed over roughly `smoothing` frames.
+pub fn mixer(
+    state: &MixerState,
+    max_buffer_size: usize,
+    smoothing: usize,
+) -> (Mixer, MixerProcessor) {
     let key_generator = KeyGenerator::from_iter(state.tracks.iter().map(|state| state.key));
 
     let mut tracks = HashMap::new();
     let mut track_processors = HashMap::new();
     for state in &state.tracks {
-        let (track, track_processor) = mixer_track_from_state(state, max_buffer_size);
+        let (track, track_processor) = mixer_track_from_state(state, max_buffer_size, smoothing);
         tracks.insert(state.key, track);
         track_processors.insert(state.key, DBox::new(track_processor));
     }
@@ -38,36 +57,98 @@ pub fn mixer(state: &MixerState, max_buffer_size: usize) -> (Mixer, MixerProcess
             (key, source_out)
         }))
         .into_remote_push();
-
-    let (master, master_processor) = mixer_track_from_state(&state.master, max_buffer_size);
+    let (send_ins_pusher, send_ins_pushed) = HashMap::from_iter(state.tracks.iter().map(|state| {
+        let key = state.key;
+        let send_in = DBox::new(vec![0.0; max_buffer_size * CHANNELS]);
+        (key, send_in)
+    }))
+    .into_remote_push();
+    let (taps_pusher, taps_pushed) = HashMap::from_iter(state.tracks.iter().map(|state| {
+        let key = state.key;
+        let tap = DBox::new(vec![0.0; max_buffer_size * CHANNELS]);
+        (key, tap)
+    }))
+    .into_remote_push();
+
+    let (master, master_processor) =
+        mixer_track_from_state(&state.master, max_buffer_size, smoothing);
+    let (master_gain, master_gain_processor) =
+        f32_parameter(state.master_gain, max_buffer_size, smoothing);
+    let (loudness_meter, loudness_meter_processor) = loudness_meter();
+    let (saturation, saturation_processor) = saturation(state.master_saturation);
+    let (limiter, limiter_processor) = limiter(state.master_limiter);
+    let (pool_sender, pool_receiver) = ringbuffer();
+    let (slots_sender, slots_receiver) = ringbuffer();
+
+    let mut mixer = Mixer {
+        max_buffer_size,
+        smoothing,
+        key_generator,
+        tracks,
+        master,
+        master_gain,
+        loudness_meter,
+        saturation,
+        limiter,
+        threads: 1,
+        slot_count: 0,
+
+        track_processors: track_processors_pusher,
+        source_outs: source_outs_pusher,
+        send_ins: send_ins_pusher,
+        taps: taps_pusher,
+        pool_sender,
+        slots_sender,
+    };
+    for state in &state.tracks {
+        mixer.prune_sends(state.key);
+        mixer.prune_sidechain(state.key);
+    }
+    mixer.update_ranks();
 
     (
-        Mixer {
-            max_buffer_size,
-            key_generator,
-            tracks,
-            master,
-
-            track_processors: track_processors_pusher,
-            source_outs: source_outs_pusher,
-        },
+        mixer,
         MixerProcessor {
             tracks: track_processors_pushed,
             master: DBox::new(master_processor),
+            master_gain: master_gain_processor,
+            loudness_meter: loudness_meter_processor,
+            saturation: saturation_processor,
+            limiter: limiter_processor,
             mix_point: MixPoint::new(max_buffer_size),
             source_outs: source_outs_pushed,
+            send_ins: send_ins_pushed,
+            taps: taps_pushed,
+            buffer: vec![0.0; max_buffer_size * CHANNELS],
+            pool: None,
+            pool_receiver,
+            slots: DBox::new(Vec::new()),
+            slots_receiver,
         },
     )
 }
 
 pub struct Mixer {
     max_buffer_size: usize,
+    /// Frames that changes to the parameters of each track are smoothed over.
+    smoothing: usize,
     key_generator: KeyGenerator<MixerTrackKey>,
     tracks: HashMap<MixerTrackKey, MixerTrack>,
     master: MixerTrack,
+    master_gain: F32Parameter,
+    loudness_meter: LoudnessMeter,
+    saturation: Saturation,
+    limiter: Limiter,
+    threads: usize,
+    /// The number of tracks the processor has room to spread across threads.
+    slot_count: usize,
 
     track_processors: RemotePusherHashMap<MixerTrackKey, DBox<MixerTrackProcessor>>,
     source_outs: RemotePusherHashMap<MixerTrackKey, DBox<Vec<Sample>>>,
+    send_ins: RemotePusherHashMap<MixerTrackKey, DBox<Vec<Sample>>>,
+    taps: RemotePusherHashMap<MixerTrackKey, DBox<Vec<Sample>>>,
+    pool_sender: ringbuffer::Sender<Option<DBox<WorkerPool>>>,
+    slots_sender: ringbuffer::Sender<DBox<Vec<TrackSlot>>>,
 }
 impl Mixer {
     pub fn master(&self) -> &MixerTrack {
@@ -77,6 +158,71 @@ impl Mixer {
         &mut self.master
     }
 
+    /// The linear gain applied to the output of the master track, before it reaches the saturation and limiter.
+    pub fn master_gain(&self) -> f32 {
+        self.master_gain.get()
+    }
+    pub fn set_master_gain(&self, gain: f32) {
+        self.master_gain.set(gain);
+    }
+
+    /// Measures the loudness of the output of the master track.
+    pub fn loudness_meter(&self) -> &LoudnessMeter {
+        &self.loudness_meter
+    }
+
+    /// Soft-clips the output of the master track, before it reaches the limiter.
+    pub fn saturation(&self) -> &Saturation {
+        &self.saturation
+    }
+    pub fn saturation_mut(&mut self) -> &mut Saturation {
+        &mut self.saturation
+    }
+
+    /// Limits the output of the master track.
+    pub fn limiter(&self) -> &Limiter {
+        &self.limiter
+    }
+    pub fn limiter_mut(&mut self) -> &mut Limiter {
+        &mut self.limiter
+    }
+
+    /// The number of threads the tracks are processed on, including the audio thread itself.
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+    /// Process the tracks on `threads` threads, including the audio thread itself.
+    ///
+    /// With more than one, tracks that don't depend on each other are spread across a pool of threads,
+    /// unless there are too few of them for it to pay off.
+    /// Zero is treated as one.
+    pub fn set_threads(&mut self, threads: usize) {
+        let threads = threads.max(1);
+        if threads == self.threads {
+            return;
+        }
+
+        self.threads = threads;
+        self.ensure_slots();
+        let pool = (threads > 1).then(|| DBox::new(WorkerPool::new(threads - 1)));
+        self.pool_sender.send(pool);
+    }
+
+    /// Make sure the processor has room to spread all of the tracks across threads, if it is going to.
+    ///
+    /// Must be called before new tracks are pushed to the processor.
+    fn ensure_slots(&mut self) {
+        if self.threads == 1 || self.slot_count >= self.tracks.len() {
+            return;
+        }
+
+        self.slot_count = smallest_pow2(self.tracks.len() as f64);
+        let slots = (0..self.slot_count)
+            .map(|_| TrackSlot::new(self.max_buffer_size))
+            .collect();
+        self.slots_sender.send(DBox::new(slots));
+    }
+
     pub fn track(&self, key: MixerTrackKey) -> Result<&MixerTrack, InvalidMixerTrackError> {
         self.tracks.get(&key).ok_or(InvalidMixerTrackError { key })
     }
@@ -91,7 +237,7 @@ impl Mixer {
 
     pub fn add_track(&mut self) -> Result<MixerTrackKey, MixerTrackOverflowError> {
         let key = self.key_generator.next()?;
-        let track = mixer_track(key, self.max_buffer_size);
+        let track = mixer_track(key, self.max_buffer_size, self.smoothing);
         self.push_track(track);
         Ok(key)
     }
@@ -111,7 +257,7 @@ impl Mixer {
                 "next_key() returned error, even though it reported remaining_keys() >= count",
             );
             keys.push(key);
-            let track = mixer_track(key, self.max_buffer_size);
+            let track = mixer_track(key, self.max_buffer_size, self.smoothing);
             tracks.push(track);
         }
         self.push_tracks(tracks);
@@ -124,8 +270,11 @@ impl Mixer {
             .reserve(key)
             .expect("Track key already in use");
 
-        let track = mixer_track_from_state(state, self.max_buffer_size);
+        let track = mixer_track_from_state(state, self.max_buffer_size, self.smoothing);
         self.push_track(track);
+        self.prune_sends(key);
+        self.prune_sidechain(key);
+        self.update_ranks();
     }
     pub fn reconstruct_tracks<'a>(
         &mut self,
@@ -137,10 +286,15 @@ impl Mixer {
                 self.key_generator
                     .reserve(state.key)
                     .expect("Track key already in use");
-                mixer_track_from_state(state, self.max_buffer_size)
+                mixer_track_from_state(state, self.max_buffer_size, self.smoothing)
             })
             .collect();
-        self.push_tracks(tracks);
+        let keys: Vec<_> = self.push_tracks(tracks);
+        for key in keys {
+            self.prune_sends(key);
+            self.prune_sidechain(key);
+        }
+        self.update_ranks();
     }
 
     pub fn delete_track(&mut self, key: MixerTrackKey) -> Result<(), InvalidMixerTrackError> {
@@ -151,6 +305,10 @@ impl Mixer {
 
         self.tracks.remove(&key);
         self.track_processors.remove(key);
+        self.send_ins.remove(key);
+        self.taps.remove(key);
+        self.remove_sidechains_from(&[key]);
+        self.remove_sends_to(&[key]);
 
         Ok(())
     }
@@ -169,32 +327,264 @@ impl Mixer {
                 .expect("At least one key exists in tracks but not in key_generator");
             self.tracks.remove(key);
         }
-        self.track_processors.remove_multiple(keys);
+        self.remove_sidechains_from(&keys);
+        self.remove_sends_to(&keys);
+        // The processors are removed first, so they are never left without a send input
+        self.track_processors.remove_multiple(keys.clone());
+        self.send_ins.remove_multiple(keys.clone());
+        self.taps.remove_multiple(keys);
         Ok(())
     }
 
+    /// Send a copy of the output of `source`, scaled by `gain`, to the input of `target`,
+    /// in addition to its regular output.
+    ///
+    /// If the send already exists, its gain is updated instead.
+    pub fn add_send(
+        &mut self,
+        source: MixerTrackKey,
+        target: MixerTrackKey,
+        gain: f32,
+    ) -> Result<(), AddSendError> {
+        if !self.tracks.contains_key(&source) {
+            return Err(AddSendError::InvalidSource(InvalidMixerTrackError {
+                key: source,
+            }));
+        }
+        if !self.tracks.contains_key(&target) {
+            return Err(AddSendError::InvalidTarget(InvalidMixerTrackError {
+                key: target,
+            }));
+        }
+        if self.reaches(target, source) {
+            return Err(AddSendError::FeedbackLoop);
+        }
+
+        let track = self.tracks.get_mut(&source).unwrap();
+        let mut sends = track.sends().to_vec();
+        match sends.iter_mut().find(|(key, _)| *key == target) {
+            Some(send) => send.1 = gain,
+            None => sends.push((target, gain)),
+        }
+        track.set_sends(sends);
+
+        self.update_ranks();
+        Ok(())
+    }
+
+    pub fn remove_send(
+        &mut self,
+        source: MixerTrackKey,
+        target: MixerTrackKey,
+    ) -> Result<(), InvalidSendError> {
+        let track = self
+            .tracks
+            .get_mut(&source)
+            .ok_or(InvalidSendError { source, target })?;
+        if !track.sends().iter().any(|&(key, _)| key == target) {
+            return Err(InvalidSendError { source, target });
+        }
+
+        let sends = track
+            .sends()
+            .iter()
+            .copied()
+            .filter(|&(key, _)| key != target)
+            .collect();
+        track.set_sends(sends);
+
+        self.update_ranks();
+        Ok(())
+    }
+
+    /// Let the dynamics effects of the track `key` react to the output of `source`, instead of the track's own signal.
+    ///
+    /// `None` makes them react to the track's own signal again.
+    pub fn set_sidechain(
+        &mut self,
+        key: MixerTrackKey,
+        source: Option<MixerTrackKey>,
+    ) -> Result<(), SetSidechainError> {
+        if !self.tracks.contains_key(&key) {
+            return Err(SetSidechainError::InvalidTrack(InvalidMixerTrackError {
+                key,
+            }));
+        }
+        if let Some(source) = source {
+            if !self.tracks.contains_key(&source) {
+                return Err(SetSidechainError::InvalidSource(InvalidMixerTrackError {
+                    key: source,
+                }));
+            }
+            if self.reaches(key, source) {
+                return Err(SetSidechainError::FeedbackLoop);
+            }
+        }
+
+        self.tracks.get_mut(&key).unwrap().set_sidechain(source);
+        self.update_ranks();
+        Ok(())
+    }
+
+    /// The tracks that the output of the track is passed on to, through its sends,
+    /// or by being their sidechain.
+    fn targets(&self, key: MixerTrackKey) -> impl Iterator<Item = MixerTrackKey> + '_ {
+        let sends = self.tracks[&key].sends().iter().map(|&(target, _)| target);
+        let sidechained = self
+            .tracks
+            .values()
+            .filter(move |track| track.sidechain() == Some(key))
+            .map(|track| track.key());
+        sends.chain(sidechained)
+    }
+
+    /// Whether the signal of `from` reaches `to` through any chain of sends or sidechains,
+    /// or they are the same track.
+    fn reaches(&self, from: MixerTrackKey, to: MixerTrackKey) -> bool {
+        let mut visited = HashSet::new();
+        let mut stack = vec![from];
+        while let Some(key) = stack.pop() {
+            if key == to {
+                return true;
+            }
+            if !visited.insert(key) {
+                continue;
+            }
+            if self.tracks.contains_key(&key) {
+                stack.extend(self.targets(key));
+            }
+        }
+        false
+    }
+
+    /// Remove the sends of the track that lead to missing tracks, or would create a feedback loop.
+    fn prune_sends(&mut self, key: MixerTrackKey) {
+        let sends = self.tracks[&key].sends();
+        let pruned: Vec<_> = sends
+            .iter()
+            .copied()
+            .filter(|&(target, _)| self.tracks.contains_key(&target) && !self.reaches(target, key))
+            .collect();
+
+        let changed = pruned.len() != sends.len();
+        if changed {
+            self.tracks.get_mut(&key).unwrap().set_sends(pruned);
+        }
+    }
+
+    /// Remove the sidechain of the track if it leads to a missing track, or would create a feedback loop.
+    fn prune_sidechain(&mut self, key: MixerTrackKey) {
+        if let Some(source) = self.tracks[&key].sidechain() {
+            if !self.tracks.contains_key(&source) || self.reaches(key, source) {
+                self.tracks.get_mut(&key).unwrap().set_sidechain(None);
+            }
+        }
+    }
+
+    fn remove_sidechains_from(&mut self, sources: &[MixerTrackKey]) {
+        for track in self.tracks.values_mut() {
+            if track
+                .sidechain()
+                .is_some_and(|source| sources.contains(&source))
+            {
+                track.set_sidechain(None);
+            }
+        }
+    }
+
+    fn remove_sends_to(&mut self, targets: &[MixerTrackKey]) {
+        for track in self.tracks.values_mut() {
+            if track
+                .sends()
+                .iter()
+                .any(|(target, _)| targets.contains(target))
+            {
+                let sends = track
+                    .sends()
+                    .iter()
+                    .copied()
+                    .filter(|(target, _)| !targets.contains(target))
+                    .collect();
+                track.set_sends(sends);
+            }
+        }
+        self.update_ranks();
+    }
+
+    /// Rank each track by the longest chain of sends and sidechains leading to it,
+    /// so the processor can process every track after all of the tracks sending to it or sidechaining it.
+    ///
+    /// Also marks the tracks used as sidechains, so the processor keeps their output.
+    fn update_ranks(&self) {
+        let mut ranks: HashMap<_, _> = self.tracks.keys().map(|&key| (key, 0)).collect();
+
+        // Since there are no loops, no chain can be longer than the number of tracks
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &key in self.tracks.keys() {
+                let rank = ranks[&key];
+                for target in self.targets(key) {
+                    if ranks[&target] < rank + 1 {
+                        ranks.insert(target, rank + 1);
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        let tapped: HashSet<_> = self
+            .tracks
+            .values()
+            .filter_map(|track| track.sidechain())
+            .collect();
+        for (key, track) in &self.tracks {
+            track.set_rank(ranks[key]);
+            track.set_tapped(tapped.contains(key));
+        }
+    }
+
     fn push_track(&mut self, track: (MixerTrack, MixerTrackProcessor)) {
         let (track, track_processor) = track;
         let key = track.key();
         self.tracks.insert(key, track);
+        self.ensure_slots();
         self.source_outs
             .push((key, DBox::new(vec![0.0; self.max_buffer_size * CHANNELS])));
+        self.send_ins
+            .push((key, DBox::new(vec![0.0; self.max_buffer_size * CHANNELS])));
+        self.taps
+            .push((key, DBox::new(vec![0.0; self.max_buffer_size * CHANNELS])));
         self.track_processors
             .push((key, DBox::new(track_processor)))
     }
-    fn push_tracks(&mut self, tracks: Vec<(MixerTrack, MixerTrackProcessor)>) {
+    /// Returns the keys of the pushed tracks.
+    fn push_tracks(
+        &mut self,
+        tracks: Vec<(MixerTrack, MixerTrackProcessor)>,
+    ) -> Vec<MixerTrackKey> {
+        let mut keys = vec![];
         let mut track_processors = vec![];
         let mut source_outs = vec![];
+        let mut send_ins = vec![];
+        let mut taps = vec![];
         for track in tracks {
             let (track, track_processor) = track;
             let key = track.key();
             self.tracks.insert(key, track);
 
+            keys.push(key);
             track_processors.push((key, DBox::new(track_processor)));
             source_outs.push((key, DBox::new(vec![0.0; self.max_buffer_size * CHANNELS])));
+            send_ins.push((key, DBox::new(vec![0.0; self.max_buffer_size * CHANNELS])));
+            taps.push((key, DBox::new(vec![0.0; self.max_buffer_size * CHANNELS])));
         }
+        self.ensure_slots();
         self.source_outs.push_multiple(source_outs);
+        self.send_ins.push_multiple(send_ins);
+        self.taps.push_multiple(taps);
         self.track_processors.push_multiple(track_processors);
+        keys
     }
 
     pub fn key_in_use(&self, key: MixerTrackKey) -> bool {
@@ -206,9 +596,16 @@ impl Mixer {
     }
 
     pub fn state(&self) -> MixerState {
+        let mut tracks: Vec<MixerTrackState> =
+            self.tracks.values().map(|track| track.state()).collect();
+        tracks.sort_by_key(|track| track.key);
+
         MixerState {
-            tracks: self.tracks.values().map(|track| track.state()).collect(),
+            tracks,
             master: self.master.state(),
+            master_gain: self.master_gain.get(),
+            master_saturation: self.saturation.settings(),
+            master_limiter: self.limiter.settings(),
         }
     }
 }
@@ -216,8 +613,26 @@ impl Mixer {
 pub struct MixerProcessor {
     tracks: RemotePushedHashMap<MixerTrackKey, DBox<MixerTrackProcessor>>,
     master: DBox<MixerTrackProcessor>,
+    master_gain: F32ParameterProcessor,
+    loudness_meter: LoudnessMeterProcessor,
+    saturation: SaturationProcessor,
+    limiter: LimiterProcessor,
     source_outs: RemotePushedHashMap<MixerTrackKey, DBox<Vec<Sample>>>,
+    /// The sum of the sends each track has received in the current buffer.
+    send_ins: RemotePushedHashMap<MixerTrackKey, DBox<Vec<Sample>>>,
+    /// The output of each track from the current buffer, for the tracks using it as their sidechain.
+    ///
+    /// Only kept up to date for the tracks that are used as a sidechain.
+    taps: RemotePushedHashMap<MixerTrackKey, DBox<Vec<Sample>>>,
     mix_point: MixPoint,
+    /// The track currently being processed.
+    buffer: Vec<Sample>,
+    /// Is `None` if the tracks are processed on the audio thread alone.
+    pool: Option<DBox<WorkerPool>>,
+    pool_receiver: ringbuffer::Receiver<Option<DBox<WorkerPool>>>,
+    /// Used to spread the tracks of the same rank across the pool.
+    slots: DBox<Vec<TrackSlot>>,
+    slots_receiver: ringbuffer::Receiver<DBox<Vec<TrackSlot>>>,
 }
 impl MixerProcessor {
     pub fn source_outs(&mut self) -> &mut HashMap<MixerTrackKey, DBox<Vec<Sample>>> {
@@ -227,25 +642,217 @@ impl MixerProcessor {
     pub fn poll(&mut self) {
         self.tracks.poll();
         self.source_outs.poll();
+        self.send_ins.poll();
+        self.taps.poll();
+
+        for track in self.tracks.values_mut() {
+            track.poll();
+        }
+        self.master.poll();
+        self.saturation.poll();
+        self.limiter.poll();
+
+        while let Some(pool) = self.pool_receiver.recv() {
+            self.pool = pool;
+        }
+        while let Some(slots) = self.slots_receiver.recv() {
+            self.slots = slots;
+        }
     }
 
-    pub fn output(&mut self, info: &Info) -> &mut [Sample] {
+    /// Mix the sources into the master track.
+    ///
+    /// `playhead` is the position on the timeline at the start of the buffer, used to evaluate automation.
+    pub fn output(&mut self, info: &Info, playhead: Timestamp) -> &mut [Sample] {
         let Info {
             sample_rate: _,
             buffer_size,
         } = *info;
 
+        let any_soloed = self.tracks.values().any(|track| track.soloed());
+        let max_rank = self.tracks.values().map(|track| track.rank()).max();
+
         self.mix_point.reset();
-        for (key, track) in self.tracks.iter_mut() {
-            let buffer = self.source_outs.get_mut(key).expect("Track has no input");
-            track.process(info, buffer);
-            self.mix_point.add(buffer);
+        for rank in 0..=max_rank.unwrap_or(0) {
+            if let Some(pool) = &self.pool {
+                let count = self
+                    .tracks
+                    .values()
+                    .filter(|track| track.rank() == rank)
+                    .count();
+                if count >= PARALLEL_MIN_TRACKS && count <= self.slots.len() {
+                    Self::output_rank_parallel(
+                        pool,
+                        &mut self.slots[..count],
+                        &mut self.tracks,
+                        &self.source_outs,
+                        &mut self.send_ins,
+                        &mut self.taps,
+                        &mut self.mix_point,
+                        rank,
+                        any_soloed,
+                        info,
+                        playhead,
+                    );
+                    continue;
+                }
+            }
+
+            for (key, track) in self.tracks.iter_mut() {
+                if track.rank() != rank {
+                    continue;
+                }
+
+                let source_out = self.source_outs.get(key).expect("Track has no input");
+                let send_in = self.send_ins.get_mut(key).expect("Track has no send input");
+                for ((sample, &source), send) in zip(
+                    zip(self.buffer.iter_mut(), source_out.iter()),
+                    send_in.iter_mut(),
+                ) {
+                    *sample = source + *send;
+                    *send = 0.0;
+                }
+
+                let silenced = any_soloed && !track.soloed();
+                // The source might have been deleted since the sidechain was set
+                let sidechain = track
+                    .sidechain()
+                    .and_then(|source| self.taps.get(&source))
+                    .map(|tap| &tap[..]);
+                track.process(info, &mut self.buffer, silenced, playhead, sidechain);
+                self.mix_point.add(&self.buffer);
+
+                if track.tapped() {
+                    if let Some(tap) = self.taps.get_mut(key) {
+                        tap.copy_from_slice(&self.buffer);
+                    }
+                }
+
+                for &(target, gain) in track.sends() {
+                    // The target might have been deleted since the send was made
+                    if let Some(send_in) = self.send_ins.get_mut(&target) {
+                        simd::add_scaled(send_in, &self.buffer, gain);
+                    }
+                }
+            }
         }
         let out = &mut self.mix_point.get()[..buffer_size * CHANNELS];
 
-        self.master.process(info, out);
+        self.master.process(info, out, false, playhead, None);
+        simd::scale_frames(out, self.master_gain.get(buffer_size));
+        self.saturation.process(out);
+        self.limiter.process(info, out);
+        self.loudness_meter.report(out, info.sample_rate);
         out
     }
+
+    /// Process the tracks of the given rank across the pool, and mix them and their sends in afterwards.
+    ///
+    /// They are mixed in the same order as if they were processed one by one.
+    #[allow(clippy::too_many_arguments)]
+    fn output_rank_parallel(
+        pool: &WorkerPool,
+        slots: &mut [TrackSlot],
+        tracks: &mut HashMap<MixerTrackKey, DBox<MixerTrackProcessor>>,
+        source_outs: &HashMap<MixerTrackKey, DBox<Vec<Sample>>>,
+        send_ins: &mut HashMap<MixerTrackKey, DBox<Vec<Sample>>>,
+        taps: &mut HashMap<MixerTrackKey, DBox<Vec<Sample>>>,
+        mix_point: &mut MixPoint,
+        rank: usize,
+        any_soloed: bool,
+        info: &Info,
+        playhead: Timestamp,
+    ) {
+        let ranked = tracks.iter_mut().filter(|(_, track)| track.rank() == rank);
+        for (slot, (key, track)) in zip(slots.iter_mut(), ranked) {
+            slot.track = &mut **track;
+            slot.source_out = &**source_outs.get(key).expect("Track has no input");
+            slot.send_in = &mut **send_ins.get_mut(key).expect("Track has no send input");
+            slot.silenced = any_soloed && !track.soloed();
+            // Tracks used as sidechains always have a lower rank, so they are not written to while being read
+            slot.sidechain = match track.sidechain().and_then(|source| taps.get(&source)) {
+                Some(tap) => &**tap,
+                None => std::ptr::null(),
+            };
+            slot.tap = match taps.get_mut(key) {
+                Some(tap) if track.tapped() => &mut **tap,
+                _ => std::ptr::null_mut(),
+            };
+        }
+
+        pool.run_each(slots, |slot| {
+            // SAFETY: The pointers were just set, and each slot points to a different track
+            unsafe { slot.process(info, playhead) }
+        });
+
+        for slot in slots {
+            mix_point.add(&slot.buffer);
+
+            // SAFETY: The pointers are still valid, and no longer used by the pool
+            let track = unsafe { &*slot.track };
+            for &(target, gain) in track.sends() {
+                // The target might have been deleted since the send was made
+                if let Some(send_in) = send_ins.get_mut(&target) {
+                    simd::add_scaled(send_in, &slot.buffer, gain);
+                }
+            }
+        }
+    }
+}
+
+/// A track to be processed on whichever thread of the pool gets to it first.
+///
+/// The pointers are only set and used during a single call to [`MixerProcessor::output`].
+struct TrackSlot {
+    track: *mut MixerTrackProcessor,
+    source_out: *const Vec<Sample>,
+    send_in: *mut Vec<Sample>,
+    /// Is null if the track has no sidechain.
+    sidechain: *const Vec<Sample>,
+    /// Is null if the track is not used as a sidechain.
+    tap: *mut Vec<Sample>,
+    silenced: bool,
+    /// The output of the track.
+    buffer: Vec<Sample>,
+}
+// SAFETY: The pointers are only dereferenced while the processor is borrowed by `MixerProcessor::output`,
+// and every slot in use points to a different track.
+unsafe impl Send for TrackSlot {}
+impl TrackSlot {
+    fn new(max_buffer_size: usize) -> Self {
+        TrackSlot {
+            track: std::ptr::null_mut(),
+            source_out: std::ptr::null(),
+            send_in: std::ptr::null_mut(),
+            sidechain: std::ptr::null(),
+            tap: std::ptr::null_mut(),
+            silenced: false,
+            buffer: vec![0.0; max_buffer_size * CHANNELS],
+        }
+    }
+
+    /// Same as processing a track one by one in [`MixerProcessor::output`].
+    ///
+    /// # Safety
+    /// The pointers must be valid, and no other thread may access what they point to.
+    unsafe fn process(&mut self, info: &Info, playhead: Timestamp) {
+        let source_out = &*self.source_out;
+        let send_in = &mut *self.send_in;
+        for ((sample, &source), send) in zip(
+            zip(self.buffer.iter_mut(), source_out.iter()),
+            send_in.iter_mut(),
+        ) {
+            *sample = source + *send;
+            *send = 0.0;
+        }
+
+        let sidechain = self.sidechain.as_ref().map(|tap| &tap[..]);
+        (*self.track).process(info, &mut self.buffer, self.silenced, playhead, sidechain);
+
+        if let Some(tap) = self.tap.as_mut() {
+            tap.copy_from_slice(&self.buffer);
+        }
+    }
 }
 impl Debug for MixerProcessor {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -256,10 +863,30 @@ impl Debug for MixerProcessor {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MixerState {
     pub tracks: Vec<MixerTrackState>,
     pub master: MixerTrackState,
+    /// Linear gain applied after the master track. Defaults to unity gain.
+    #[serde(default = "unity_gain")]
+    pub master_gain: f32,
+    /// Is `None` while the saturation is bypassed.
+    #[serde(default)]
+    pub master_saturation: Option<SaturationSettings>,
+    /// Is `None` while the limiter is bypassed.
+    #[serde(default)]
+    pub master_limiter: Option<LimiterSettings>,
+}
+impl Default for MixerState {
+    fn default() -> Self {
+        Self {
+            tracks: Vec::new(),
+            master: MixerTrackState::default(),
+            master_gain: unity_gain(),
+            master_saturation: None,
+            master_limiter: None,
+        }
+    }
 }
 impl PartialEq for MixerState {
     fn eq(&self, other: &Self) -> bool {
@@ -279,7 +906,12 @@ impl PartialEq for MixerState {
             other.tracks
         );
 
-        self_set == other_set && self.master == other.master
+        self_set == other_set
+            && self.master == other.master
+            // Compared bitwise, so that the relation stays reflexive, allowing `Eq`.
+            && self.master_gain.to_bits() == other.master_gain.to_bits()
+            && self.master_saturation == other.master_saturation
+            && self.master_limiter == other.master_limiter
     }
 }
 impl Eq for MixerState {}
@@ -310,6 +942,55 @@ impl From<key_generator::OverflowError> for MixerTrackOverflowError {
     }
 }
 
+#[derive(Debug, PartialEq, Eq)]
+pub enum AddSendError {
+    InvalidSource(InvalidMixerTrackError),
+    InvalidTarget(InvalidMixerTrackError),
+    /// The target already sends to the source, directly or through other tracks.
+    FeedbackLoop,
+}
+impl Display for AddSendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidSource(e) => write!(f, "Invalid source: {e}"),
+            Self::InvalidTarget(e) => write!(f, "Invalid target: {e}"),
+            Self::FeedbackLoop => write!(f, "The send would create a feedback loop"),
+        }
+    }
+}
+impl Error for AddSendError {}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SetSidechainError {
+    InvalidTrack(InvalidMixerTrackError),
+    InvalidSource(InvalidMixerTrackError),
+    /// The track already reaches the source, through sends or other sidechains.
+    FeedbackLoop,
+}
+impl Display for SetSidechainError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidTrack(e) => write!(f, "Invalid track: {e}"),
+            Self::InvalidSource(e) => write!(f, "Invalid source: {e}"),
+            Self::FeedbackLoop => write!(f, "The sidechain would create a feedback loop"),
+        }
+    }
+}
+impl Error for SetSidechainError {}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidSendError {
+    pub source: MixerTrackKey,
+    pub target: MixerTrackKey,
+}
+impl Display for InvalidSendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let InvalidSendError { source, target } = self;
+        write!(f, "No send from track {source:?} to track {target:?}")
+    }
+}
+impl Error for InvalidSendError {}
+
 #[cfg(test)]
 mod tests {
     use crate::engine::utils::key_generator::Key;
@@ -318,7 +999,7 @@ mod tests {
 
     #[test]
     fn add_track() {
-        let (mut m, mut mp) = mixer(&MixerState::default(), 10);
+        let (mut m, mut mp) = mixer(&MixerState::default(), 10, 0);
 
         for _ in 0..50 {
             m.add_track().unwrap();
@@ -334,7 +1015,7 @@ mod tests {
 
     #[test]
     fn add_tracks() {
-        let (mut m, mut mp) = mixer(&MixerState::default(), 10);
+        let (mut m, mut mp) = mixer(&MixerState::default(), 10, 0);
 
         for _ in 0..50 {
             m.add_tracks(5).unwrap();
@@ -350,7 +1031,7 @@ mod tests {
 
     #[test]
     fn reconstruct_track() {
-        let (mut m, mut mp) = mixer(&MixerState::default(), 10);
+        let (mut m, mut mp) = mixer(&MixerState::default(), 10, 0);
 
         let mut keys = Vec::new();
         for _ in 0..50 {
@@ -377,13 +1058,26 @@ mod tests {
     #[test]
     #[should_panic]
     fn reconstruct_existing_track() {
-        let (mut m, _mp) = mixer(&MixerState::default(), 10);
+        let (mut m, _mp) = mixer(&MixerState::default(), 10, 0);
 
         let used = m.add_track().unwrap();
 
         m.reconstruct_track(&MixerTrackState {
+            input_gain: 1.0,
             panning: 0.0,
             volume: 1.0,
+            stereo_width: 1.0,
+            muted: false,
+            soloed: false,
+            phase_invert: (false, false),
+            dc_block: false,
+            effects: Vec::new(),
+            sends: Vec::new(),
+            sidechain: None,
+            name: String::new(),
+            color: 0,
+            volume_automation: Vec::new(),
+            panning_automation: Vec::new(),
 
             key: used,
         });
@@ -391,14 +1085,27 @@ mod tests {
 
     #[test]
     fn reconstruct_tracks() {
-        let (mut m, mut mp) = mixer(&MixerState::default(), 10);
+        let (mut m, mut mp) = mixer(&MixerState::default(), 10, 0);
 
         let batch_size = 5;
 
         let states: Vec<MixerTrackState> = (1..50 * batch_size + 1)
             .map(|key| MixerTrackState {
+                input_gain: 1.0,
                 panning: 0.0,
                 volume: 1.0,
+                stereo_width: 1.0,
+                muted: false,
+                soloed: false,
+                phase_invert: (false, false),
+                dc_block: false,
+                effects: Vec::new(),
+                sends: Vec::new(),
+                sidechain: None,
+                name: String::new(),
+                color: 0,
+                volume_automation: Vec::new(),
+                panning_automation: Vec::new(),
                 key: MixerTrackKey::new(key as u32),
             })
             .collect();
@@ -416,7 +1123,7 @@ mod tests {
 
     #[test]
     fn delete_track_immediately() {
-        let (mut m, mut mp) = mixer(&MixerState::default(), 10);
+        let (mut m, mut mp) = mixer(&MixerState::default(), 10, 0);
 
         let k = m.add_track().unwrap();
         m.delete_track(k).unwrap();
@@ -431,7 +1138,7 @@ mod tests {
 
     #[test]
     fn delete_track_delayed() {
-        let (mut m, mut mp) = mixer(&MixerState::default(), 10);
+        let (mut m, mut mp) = mixer(&MixerState::default(), 10, 0);
 
         let mut poll = || {
             no_heap! {{
@@ -447,4 +1154,260 @@ mod tests {
         assert_eq!(m.tracks.len(), 0);
         assert_eq!(mp.tracks.len(), 0);
     }
+
+    #[test]
+    fn solo_silences_other_tracks() {
+        let (mut m, mut mp) = mixer(&MixerState::default(), 10, 0);
+        let info = Info {
+            sample_rate: 40_000,
+            buffer_size: 10,
+        };
+
+        let k1 = m.add_track().unwrap();
+        let k2 = m.add_track().unwrap();
+        m.track(k1).unwrap().set_solo(true);
+
+        no_heap! {{
+            mp.poll();
+
+            for key in [k1, k2] {
+                mp.source_outs().get_mut(&key).unwrap().fill(1.0);
+            }
+            let out = mp.output(&info, Timestamp::zero());
+
+            // Only the soloed track should reach the master
+            for &s in out.iter() {
+                assert_eq!(s, 1.0);
+            }
+        }}
+    }
+
+    #[test]
+    fn mute_silences_track() {
+        let (mut m, mut mp) = mixer(&MixerState::default(), 10, 0);
+        let info = Info {
+            sample_rate: 40_000,
+            buffer_size: 10,
+        };
+
+        let k1 = m.add_track().unwrap();
+        let k2 = m.add_track().unwrap();
+        m.track(k1).unwrap().set_mute(true);
+
+        no_heap! {{
+            mp.poll();
+
+            for key in [k1, k2] {
+                mp.source_outs().get_mut(&key).unwrap().fill(1.0);
+            }
+            let out = mp.output(&info, Timestamp::zero());
+
+            for &s in out.iter() {
+                assert_eq!(s, 1.0);
+            }
+        }}
+    }
+
+    #[test]
+    fn send_reaches_target() {
+        let (mut m, mut mp) = mixer(&MixerState::default(), 10, 0);
+        let info = Info {
+            sample_rate: 40_000,
+            buffer_size: 10,
+        };
+
+        let k1 = m.add_track().unwrap();
+        let k2 = m.add_track().unwrap();
+        let k3 = m.add_track().unwrap();
+        // Chained, so the order of processing matters
+        m.add_send(k2, k3, 1.0).unwrap();
+        m.add_send(k1, k2, 0.5).unwrap();
+
+        no_heap! {{
+            mp.poll();
+
+            mp.source_outs().get_mut(&k1).unwrap().fill(1.0);
+            for key in [k2, k3] {
+                mp.source_outs().get_mut(&key).unwrap().fill(0.0);
+            }
+            let out = mp.output(&info, Timestamp::zero());
+
+            // 1 from k1, 0.5 from k2 and 0.5 from k3
+            for &s in out.iter() {
+                assert_eq!(s, 2.0);
+            }
+        }}
+    }
+
+    #[test]
+    fn master_gain_trims_output() {
+        let (mut m, mut mp) = mixer(&MixerState::default(), 10, 0);
+        let info = Info {
+            sample_rate: 40_000,
+            buffer_size: 10,
+        };
+
+        let k = m.add_track().unwrap();
+        m.master().set_volume(0.5);
+        m.set_master_gain(0.5);
+
+        no_heap! {{
+            mp.poll();
+
+            mp.source_outs().get_mut(&k).unwrap().fill(1.0);
+            let out = mp.output(&info, Timestamp::zero());
+
+            // Applied on top of the master volume
+            for &s in out.iter() {
+                assert_eq!(s, 0.25);
+            }
+        }}
+    }
+
+    #[test]
+    fn parallel_matches_serial() {
+        let (mut serial, mut serial_p) = mixer(&MixerState::default(), 10, 0);
+        let (mut parallel, mut parallel_p) = mixer(&MixerState::default(), 10, 0);
+        parallel.set_threads(4);
+        let info = Info {
+            sample_rate: 40_000,
+            buffer_size: 10,
+        };
+
+        let mut keys = vec![];
+        for m in [&mut serial, &mut parallel] {
+            keys = m.add_tracks(16).unwrap();
+            for (i, &key) in keys.iter().enumerate() {
+                m.track(key).unwrap().set_volume(i as f32 / 16.0);
+            }
+            // Leaves some tracks of the same rank on each side of the sends
+            for &source in &keys[..8] {
+                m.add_send(source, keys[15], 0.5).unwrap();
+            }
+        }
+
+        for _ in 0..10 {
+            let mut outputs = vec![];
+            for mp in [&mut serial_p, &mut parallel_p] {
+                no_heap! {{
+                    mp.poll();
+                    for (i, key) in keys.iter().enumerate() {
+                        mp.source_outs().get_mut(key).unwrap().fill(i as Sample / 10.0);
+                    }
+                }}
+                let out = no_heap! {{
+                    mp.output(&info, Timestamp::zero())
+                }};
+                outputs.push(out.to_vec());
+            }
+
+            for (&s, &p) in zip(&outputs[0], &outputs[1]) {
+                assert!((s - p).abs() < 1e-5, "Serial: {s}, parallel: {p}");
+            }
+        }
+    }
+
+    #[test]
+    fn volume_automation() {
+        let (mut m, mut mp) = mixer(&MixerState::default(), 10, 0);
+        let info = Info {
+            sample_rate: 40_000,
+            buffer_size: 10,
+        };
+
+        let k = m.add_track().unwrap();
+        m.track_mut(k).unwrap().set_volume_automation(vec![
+            (Timestamp::from_beats(4), 1.0),
+            (Timestamp::from_beats(0), 0.0),
+        ]);
+
+        no_heap! {{
+            mp.poll();
+
+            mp.source_outs().get_mut(&k).unwrap().fill(1.0);
+            let out = mp.output(&info, Timestamp::from_beats(1));
+
+            // The volume is smoothed, so only the end of the buffer is guaranteed to have arrived
+            let last = out[out.len() - 1];
+            assert!((last - 0.25).abs() < 1e-6);
+        }}
+    }
+
+    #[test]
+    fn send_feedback_loop() {
+        let (mut m, _mp) = mixer(&MixerState::default(), 10, 0);
+
+        let k1 = m.add_track().unwrap();
+        let k2 = m.add_track().unwrap();
+        let k3 = m.add_track().unwrap();
+        m.add_send(k1, k2, 1.0).unwrap();
+        m.add_send(k2, k3, 1.0).unwrap();
+
+        assert_eq!(m.add_send(k3, k1, 1.0), Err(AddSendError::FeedbackLoop));
+        assert_eq!(m.add_send(k1, k1, 1.0), Err(AddSendError::FeedbackLoop));
+        assert_eq!(m.add_send(k1, k3, 1.0), Ok(()));
+    }
+
+    #[test]
+    fn delete_send_target() {
+        let (mut m, mut mp) = mixer(&MixerState::default(), 10, 0);
+
+        let k1 = m.add_track().unwrap();
+        let k2 = m.add_track().unwrap();
+        m.add_send(k1, k2, 1.0).unwrap();
+        m.delete_track(k2).unwrap();
+
+        no_heap! {{
+            mp.poll();
+        }}
+
+        assert!(m.track(k1).unwrap().sends().is_empty());
+        assert_eq!(mp.send_ins.len(), 1);
+    }
+
+    #[test]
+    fn sidechain_is_processed_first() {
+        let (mut m, mut mp) = mixer(&MixerState::default(), 10, 0);
+
+        let k1 = m.add_track().unwrap();
+        let k2 = m.add_track().unwrap();
+        let k3 = m.add_track().unwrap();
+        m.add_send(k1, k2, 1.0).unwrap();
+        m.set_sidechain(k3, Some(k2)).unwrap();
+
+        no_heap! {{
+            mp.poll();
+        }}
+
+        assert_eq!(m.track(k3).unwrap().sidechain(), Some(k2));
+        assert!(mp.tracks[&k3].rank() > mp.tracks[&k2].rank());
+        assert!(mp.tracks[&k2].tapped());
+        assert!(!mp.tracks[&k3].tapped());
+        assert_eq!(
+            m.set_sidechain(k1, Some(k3)),
+            Err(SetSidechainError::FeedbackLoop)
+        );
+        assert_eq!(
+            m.set_sidechain(k1, Some(k1)),
+            Err(SetSidechainError::FeedbackLoop)
+        );
+        assert_eq!(m.add_send(k3, k1, 1.0), Err(AddSendError::FeedbackLoop));
+    }
+
+    #[test]
+    fn delete_sidechain_source() {
+        let (mut m, mut mp) = mixer(&MixerState::default(), 10, 0);
+
+        let k1 = m.add_track().unwrap();
+        let k2 = m.add_track().unwrap();
+        m.set_sidechain(k2, Some(k1)).unwrap();
+        m.delete_track(k1).unwrap();
+
+        no_heap! {{
+            mp.poll();
+        }}
+
+        assert_eq!(m.track(k2).unwrap().sidechain(), None);
+        assert_eq!(mp.taps.len(), 1);
+    }
 }
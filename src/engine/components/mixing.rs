@@ -1,10 +1,16 @@
-use std::iter::zip;
+use std::fmt::Debug;
 
-use crate::engine::{Sample, CHANNELS};
+use crate::engine::{utils::simd, Sample, CHANNELS};
 
-/// Component for the simple addition of signals.
+/// Component for the simple addition of signals, as used by the engine to mix its tracks.
 ///
-/// Mixing is done via 64-bit summing.
+/// Mixing is done via 64-bit summing:
+/// Each sample of every added buffer is converted to `f64` and added to a running sum,
+/// which is rounded to the nearest `f32` when the result is retrieved with [`MixPoint::get`].
+/// No gain is applied to the sum, and it is neither clipped nor saturated, so it can exceed `-1.0..=1.0`.
+/// The engine only clips its final output to that range, after the master track.
+///
+/// Buffers are interleaved stereo, and nothing is allocated after the mix point is created.
 pub struct MixPoint {
     sum_buffer: Vec<f64>,
     output_buffer: Vec<Sample>,
@@ -15,6 +21,7 @@ pub struct MixPoint {
     buffer_size_samples: Option<usize>,
 }
 impl MixPoint {
+    /// Create a mix point for buffers of at most `max_buffer_size` frames.
     pub fn new(max_buffer_size: usize) -> Self {
         Self {
             sum_buffer: vec![0.0; max_buffer_size * CHANNELS],
@@ -32,14 +39,15 @@ impl MixPoint {
 
     /// Add buffer to the 64-bit sum.
     /// With debug assertions enabled, this will panic if buffers of different sizes are added inbetween resets.
+    ///
+    /// Samples beyond `max_buffer_size` frames are ignored.
     pub fn add(&mut self, input_buffer: &[Sample]) {
-        if self.buffer_size_samples.is_none() {
-            self.buffer_size_samples = Some(input_buffer.len());
-        } else {
+        match self.buffer_size_samples {
+            None => self.buffer_size_samples = Some(input_buffer.len()),
+
             // Assert that all buffers added between resets are of equal size.
             #[cfg(debug_assertions)]
-            {
-                let buffer_size_samples = self.buffer_size_samples.unwrap();
+            Some(buffer_size_samples) => {
                 if buffer_size_samples != input_buffer.len() {
                     panic!(
                         "At least two buffers were of different sizes: {}, {}.",
@@ -48,12 +56,11 @@ impl MixPoint {
                     );
                 }
             }
+            #[cfg(not(debug_assertions))]
+            Some(_) => {}
         }
 
-        // Sum
-        for (sum_sample, &input_sample) in zip(self.sum_buffer.iter_mut(), input_buffer) {
-            *sum_sample += f64::from(input_sample);
-        }
+        simd::accumulate(&mut self.sum_buffer, input_buffer);
     }
 
     /// Get sum of all buffers added since last reset.
@@ -62,15 +69,19 @@ impl MixPoint {
     /// Result is not clipped.
     pub fn get(&mut self) -> &mut [Sample] {
         // Convert back to original sample format.
-        for (output_sample, &sum_sample) in
-            zip(self.output_buffer.iter_mut(), self.sum_buffer.iter())
-        {
-            *output_sample = sum_sample as Sample;
-        }
+        simd::narrow(&mut self.output_buffer, &self.sum_buffer);
 
         &mut self.output_buffer
     }
 }
+impl Debug for MixPoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MixPoint")
+            .field("max_buffer_size", &(self.sum_buffer.len() / CHANNELS))
+            .field("buffer_size_samples", &self.buffer_size_samples)
+            .finish_non_exhaustive()
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -123,6 +134,8 @@ mod tests {
 
     // Tests whether there are significant rounding errors while mixing a large amount of signals.
     // Fails without 64-bit summing.
+    // With 64-bit samples the sum has no extra precision, so this only holds for 32-bit samples.
+    #[cfg(not(feature = "f64"))]
     #[test]
     fn retains_precision() {
         let mut mp = MixPoint::new(5);
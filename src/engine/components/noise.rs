@@ -0,0 +1,148 @@
+use serde::{Deserialize, Serialize};
+
+use crate::engine::{Sample, CHANNELS};
+
+/// The spectrum of a [`Noise`] source.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NoiseType {
+    /// Equal energy at every frequency.
+    White,
+    /// Equal energy in every octave, falling by 3 dB per octave.
+    #[default]
+    Pink,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct NoiseSettings {
+    pub noise_type: NoiseType,
+    /// Linear gain, where `1.0` lets the peaks reach roughly full scale.
+    pub amplitude: f32,
+}
+impl Default for NoiseSettings {
+    fn default() -> Self {
+        Self {
+            noise_type: NoiseType::Pink,
+            amplitude: 0.125,
+        }
+    }
+}
+
+/// Brings the peaks of the pink filter down to roughly the same level as the white noise feeding it.
+const PINK_GAIN: f32 = 0.11;
+
+/// Generates noise, identical in all channels.
+#[derive(Debug, Clone, Copy)]
+pub struct Noise {
+    settings: NoiseSettings,
+    /// State of the xorshift generator the white noise is drawn from.
+    state: u64,
+    /// State of the filter that turns the white noise pink.
+    pink: [f32; 7],
+}
+impl Noise {
+    pub fn new(settings: NoiseSettings) -> Self {
+        Self {
+            settings,
+            // Xorshift gets stuck at zero
+            state: 0x9E37_79B9_7F4A_7C15,
+            pink: [0.0; 7],
+        }
+    }
+
+    pub fn set_settings(&mut self, settings: NoiseSettings) {
+        self.settings = settings;
+    }
+
+    /// Uniformly distributed in the range `[-1, 1)`.
+    fn white(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        // The top 24 bits fit exactly in the mantissa of an f32
+        (self.state >> 40) as f32 / (1 << 23) as f32 - 1.0
+    }
+
+    /// Paul Kellet's refined filter, accurate to within ±0.05 dB above 9.2 Hz at 44.1 kHz.
+    fn pink(&mut self) -> f32 {
+        let white = self.white();
+        let b = &mut self.pink;
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.153852;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.0168980;
+        let pink = b.iter().sum::<f32>() + white * 0.5362;
+        b[6] = white * 0.115926;
+        pink * PINK_GAIN
+    }
+
+    /// Add the next `buffer.len() / CHANNELS` frames of noise on top of the interleaved `buffer`.
+    pub fn add_to(&mut self, buffer: &mut [Sample]) {
+        let NoiseSettings {
+            noise_type,
+            amplitude,
+        } = self.settings;
+
+        for frame in buffer.chunks_exact_mut(CHANNELS) {
+            let value = match noise_type {
+                NoiseType::White => self.white(),
+                NoiseType::Pink => self.pink(),
+            };
+            let sample = (amplitude * value) as Sample;
+            for s in frame {
+                *s += sample;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Energy of the difference between consecutive frames, relative to the energy of the frames themselves.
+    ///
+    /// Around 2 for white noise, and lower the more the energy lies in the lower frequencies.
+    fn roughness(buffer: &[Sample]) -> Sample {
+        let frames: Vec<Sample> = buffer.chunks_exact(CHANNELS).map(|f| f[0]).collect();
+        let energy: Sample = frames.iter().map(|s| s * s).sum();
+        let diff: Sample = frames.windows(2).map(|w| (w[1] - w[0]).powi(2)).sum();
+        diff / energy
+    }
+
+    #[test]
+    fn white_within_amplitude() {
+        let mut noise = Noise::new(NoiseSettings {
+            noise_type: NoiseType::White,
+            amplitude: 0.5,
+        });
+        let mut buffer = vec![0.0; 10_000 * CHANNELS];
+        no_heap! {{
+            noise.add_to(&mut buffer);
+        }}
+
+        assert!(buffer.iter().all(|s| (-0.5..0.5).contains(s)));
+        assert!(buffer
+            .chunks_exact(CHANNELS)
+            .all(|f| f.iter().all(|&s| s == f[0])));
+        let mean = buffer.iter().sum::<Sample>() / buffer.len() as Sample;
+        assert!(mean.abs() < 0.01, "Mean: {mean}");
+        assert!((roughness(&buffer) - 2.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn pink_is_darker() {
+        let mut noise = Noise::new(NoiseSettings {
+            noise_type: NoiseType::Pink,
+            amplitude: 1.0,
+        });
+        let mut buffer = vec![0.0; 10_000 * CHANNELS];
+        no_heap! {{
+            noise.add_to(&mut buffer);
+        }}
+
+        assert!(buffer.iter().all(|s| s.abs() < 1.0));
+        assert!(roughness(&buffer) < 1.0);
+    }
+}
@@ -0,0 +1,217 @@
+use std::{
+    cmp::min,
+    error::Error,
+    fmt::Display,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use ringbuf::{
+    traits::{Consumer, Observer, Producer, Split},
+    HeapCons, HeapProd, HeapRb,
+};
+
+use crate::engine::{Sample, StreamDeadError, CHANNELS};
+use crate::wav_recorder::WavRecorder;
+
+/// How many seconds of output can be buffered before the writer has to catch up.
+const BUFFERED_SECONDS: usize = 2;
+
+/// Creates a corresponding pair of [`OutputCapture`] and [`OutputCaptureInput`],
+/// capturing the output at the given sample rate.
+///
+/// The [`OutputCaptureInput`] should live on the audio thread, while the [`OutputCapture`] should not.
+pub fn output_capture(sample_rate: u32) -> (OutputCapture, OutputCaptureInput) {
+    let capturing1 = Arc::new(AtomicBool::new(false));
+    let capturing2 = Arc::clone(&capturing1);
+
+    let (producer, consumer) =
+        HeapRb::new(CHANNELS * sample_rate as usize * BUFFERED_SECONDS).split();
+
+    (
+        OutputCapture {
+            sample_rate,
+            capturing: capturing1,
+            consumer: Some(consumer),
+            writer: None,
+        },
+        OutputCaptureInput {
+            capturing: capturing2,
+            producer,
+        },
+    )
+}
+
+pub struct OutputCaptureInput {
+    capturing: Arc<AtomicBool>,
+    producer: HeapProd<Sample>,
+}
+impl OutputCaptureInput {
+    /// Pass on a buffer of interleaved output, if a capture is in progress.
+    pub fn push(&mut self, buffer: &[Sample]) {
+        if !self.capturing.load(Ordering::Acquire) {
+            return;
+        }
+
+        // If the writer falls behind, the rest is dropped, keeping whole frames only
+        let len = min(buffer.len(), self.producer.vacant_len());
+        let len = len - len % CHANNELS;
+        self.producer.push_slice(&buffer[..len]);
+    }
+}
+
+/// Writes the output passed to the corresponding [`OutputCaptureInput`] to a file, in a background thread.
+pub struct OutputCapture {
+    sample_rate: u32,
+
+    capturing: Arc<AtomicBool>,
+    /// Is `None` while capturing, in which case it is owned by the `writer` thread.
+    consumer: Option<HeapCons<Sample>>,
+    /// Is `Some` while capturing.
+    writer: Option<Writer>,
+}
+impl OutputCapture {
+    pub fn is_capturing(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    /// Start writing the output to a .wav file at `path`, overwriting it if it already exists.
+    pub fn start(&mut self, path: &Path) -> Result<(), StartOutputCaptureError> {
+        if self.is_capturing() {
+            return Err(StartOutputCaptureError::AlreadyCapturing);
+        }
+        let mut recorder = WavRecorder::create(
+            path,
+            CHANNELS
+                .try_into()
+                .expect("Too many channels to record to .wav"),
+            self.sample_rate,
+        )
+        .map_err(|e| StartOutputCaptureError::Create(e.to_string()))?;
+
+        let mut consumer = self
+            .consumer
+            .take()
+            .expect("Consumer missing while not capturing");
+        // Throw out anything left over from the last capture
+        consumer.clear();
+
+        let stopped1 = Arc::new(AtomicBool::new(false));
+        let stopped2 = Arc::clone(&stopped1);
+        let join_handle = thread::spawn(move || {
+            let mut chunk = vec![0.0; 1024 * CHANNELS];
+            let result = loop {
+                // Checked before draining, so nothing is left behind after stopping
+                let stopped = stopped2.load(Ordering::Acquire);
+                let result = loop {
+                    let len = consumer.pop_slice(&mut chunk);
+                    if len == 0 {
+                        break Ok(());
+                    }
+                    if let Err(e) = recorder.record(&chunk[..len]) {
+                        break Err(e);
+                    }
+                };
+                if stopped || result.is_err() {
+                    break result.and_then(|()| recorder.finalize());
+                }
+                thread::sleep(Duration::from_millis(10));
+            };
+            (consumer, result)
+        });
+
+        self.writer = Some(Writer {
+            stopped: stopped1,
+            join_handle,
+        });
+        self.capturing.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    /// Stop capturing, and finish writing the file.
+    ///
+    /// Returns `Ok(false)` if no capture is in progress.
+    pub fn stop(&mut self) -> Result<bool, hound::Error> {
+        let Some(Writer {
+            stopped,
+            join_handle,
+        }) = self.writer.take()
+        else {
+            return Ok(false);
+        };
+
+        self.capturing.store(false, Ordering::Release);
+        stopped.store(true, Ordering::Release);
+        let (consumer, result) = join_handle.join().expect("Output capture thread panicked");
+        self.consumer = Some(consumer);
+
+        result.map(|()| true)
+    }
+}
+impl Drop for OutputCapture {
+    /// Finishes the file of any capture in progress, so it can still be read.
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+/// The thread writing the output while capturing.
+struct Writer {
+    stopped: Arc<AtomicBool>,
+    /// Returns the consumer along with the result of writing the file.
+    join_handle: JoinHandle<(HeapCons<Sample>, Result<(), hound::Error>)>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum StartOutputCaptureError {
+    AlreadyCapturing,
+    Create(String),
+    StreamDead(StreamDeadError),
+}
+impl Display for StartOutputCaptureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AlreadyCapturing => write!(f, "The output is already being captured"),
+            Self::Create(msg) => write!(f, "Output capture file could not be created: {msg}"),
+            Self::StreamDead(e) => Display::fmt(e, f),
+        }
+    }
+}
+impl Error for StartOutputCaptureError {}
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+
+    use super::*;
+
+    #[test]
+    fn capture() {
+        let path = temp_dir().join("adae_output_capture_test.wav");
+        let (mut capture, mut input) = output_capture(48_000);
+
+        // Ignored, since capturing has not started
+        input.push(&[1.0; 100]);
+
+        capture.start(&path).unwrap();
+        assert_eq!(
+            capture.start(&path),
+            Err(StartOutputCaptureError::AlreadyCapturing)
+        );
+        no_heap! {{
+            input.push(&[0.5; 300 * CHANNELS]);
+        }}
+        assert!(capture.stop().unwrap());
+        assert!(!capture.stop().unwrap());
+
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().channels, CHANNELS as u16);
+        assert_eq!(reader.duration(), 300);
+        assert!(reader.samples::<f32>().all(|s| s.unwrap() == 0.5));
+    }
+}
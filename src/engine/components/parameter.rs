@@ -3,13 +3,16 @@ use std::{
     sync::{atomic::Ordering, Arc},
 };
 
-use crate::engine::CHANNELS;
+use crate::engine::utils::AtomicF32;
 
-use crate::engine::utils::{AtomicF32, MovingAverage};
+/// Once the smoothed value is this close to the desired value, it snaps to it.
+const SNAP_DISTANCE: f32 = 1e-6;
 
+/// Creates a parameter, where changes are smoothed over roughly `smoothing` frames.
 pub fn f32_parameter(
     initial: f32,
     max_buffer_size: usize,
+    smoothing: usize,
 ) -> (F32Parameter, F32ParameterProcessor) {
     let desired1 = Arc::new(AtomicF32::new(initial));
     let desired2 = Arc::clone(&desired1);
@@ -17,16 +20,17 @@ pub fn f32_parameter(
         F32Parameter { desired: desired1 },
         F32ParameterProcessor {
             desired: desired2,
-            moving_average: MovingAverage::new(initial, max_buffer_size),
+            current: initial,
+            coefficient: F32ParameterProcessor::coefficient(smoothing),
 
-            buffer: vec![0.0; max_buffer_size * CHANNELS],
+            buffer: vec![0.0; max_buffer_size],
         },
     )
 }
 
 /// Representes a numeric value, controlled by the user - by a knob or slider for example.
 ///
-/// The value is smoothed (via simple moving average), to avoid distortion and clicking in the sound.
+/// The value is smoothed (via a one-pole ramp towards the new value), to avoid distortion and clicking in the sound.
 #[derive(Debug)]
 pub struct F32Parameter {
     desired: Arc<AtomicF32>,
@@ -44,27 +48,77 @@ impl F32Parameter {
 
 pub struct F32ParameterProcessor {
     desired: Arc<AtomicF32>,
-    moving_average: MovingAverage,
+    /// The smoothed value at the end of the last buffer.
+    current: f32,
+    coefficient: f32,
 
     buffer: Vec<f32>,
 }
 impl F32ParameterProcessor {
+    /// The portion of the distance to the desired value that is covered each frame,
+    /// to cover most of it in `smoothing` frames.
+    fn coefficient(smoothing: usize) -> f32 {
+        if smoothing == 0 {
+            1.0
+        } else {
+            1.0 - (-1.0 / smoothing as f32).exp()
+        }
+    }
+
+    /// Get the smoothed value for each frame of the buffer.
     pub fn get(&mut self, buffer_size: usize) -> &mut [f32] {
         let desired = self.desired.load(Ordering::Relaxed);
+        self.get_towards(buffer_size, desired)
+    }
 
-        for point in self.buffer[..buffer_size * CHANNELS].iter_mut() {
-            self.moving_average.push(desired);
-            *point = self.moving_average.average();
+    /// Same as [`Self::get`], except the value is smoothed towards `desired`
+    /// rather than the last value passed to [`F32Parameter::set`].
+    pub fn get_towards(&mut self, buffer_size: usize, desired: f32) -> &mut [f32] {
+        for point in self.buffer[..buffer_size].iter_mut() {
+            self.current += self.coefficient * (desired - self.current);
+            // The ramp only approaches the desired value, so it would otherwise never arrive
+            if (desired - self.current).abs() < SNAP_DISTANCE {
+                self.current = desired;
+            }
+            *point = self.current;
         }
 
-        &mut self.buffer[..buffer_size * CHANNELS]
+        &mut self.buffer[..buffer_size]
     }
 }
 impl Debug for F32ParameterProcessor {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("F32ParameterProcessor")
             .field("desired", &self.desired)
-            .field("moving_average", &self.moving_average)
+            .field("current", &self.current)
             .finish_non_exhaustive()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_are_smoothed() {
+        let (p, mut pp) = f32_parameter(0.0, 200, 10);
+
+        p.set(1.0);
+        let values = no_heap! {{
+            pp.get(200)
+        }};
+
+        // Rises steadily, without jumping to the new value
+        assert!(values[0] > 0.0 && values[0] < 0.2);
+        assert!(values.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(values[199], 1.0);
+    }
+
+    #[test]
+    fn no_smoothing() {
+        let (p, mut pp) = f32_parameter(0.0, 100, 0);
+
+        p.set(0.5);
+        assert!(pp.get(10).iter().all(|&v| v == 0.5));
+    }
+}
@@ -0,0 +1,390 @@
+use std::{
+    cmp::min,
+    iter::zip,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use ringbuf::{
+    traits::{Consumer, Observer, Producer, Split},
+    HeapCons, HeapProd, HeapRb,
+};
+use rubato::{FftFixedOut, Resampler};
+
+use super::audio_clip_reader::OriginalSamples;
+use crate::engine::config::{DitherMode, SampleFormat, SampleFormatFloat, SampleFormatInt};
+use crate::engine::utils::dither::Dither;
+use crate::engine::{Sample, CHANNELS};
+
+/// How many seconds of input can be buffered before the recorder has to drain it.
+const BUFFERED_SECONDS: usize = 2;
+
+/// The sample format of a written .wav file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum WavFormat {
+    /// 16-bit integer samples, which are dithered if enabled.
+    I16,
+    /// 24-bit integer samples.
+    I24,
+    /// 32-bit floating point samples, which keep levels above full scale intact.
+    #[default]
+    F32,
+}
+impl WavFormat {
+    fn bits_per_sample(self) -> u16 {
+        match self {
+            WavFormat::I16 => 16,
+            WavFormat::I24 => 24,
+            WavFormat::F32 => 32,
+        }
+    }
+
+    /// The closest [`SampleFormat`], which decides how the samples are dithered.
+    fn sample_format(self) -> SampleFormat {
+        match self {
+            WavFormat::I16 => SampleFormat::Int(SampleFormatInt::I16),
+            // Dithered just like 32-bit integers, which is not at all
+            WavFormat::I24 => SampleFormat::Int(SampleFormatInt::I32),
+            WavFormat::F32 => SampleFormat::Float(SampleFormatFloat::F32),
+        }
+    }
+}
+
+/// Creates a corresponding pair of [`Recorder`] and [`RecorderInput`],
+/// recording `channels` channels of audio at the given sample rate.
+///
+/// The [`RecorderInput`] should live in the callback of the input stream, while the [`Recorder`] should not.
+pub fn recorder(channels: u16, sample_rate: u32) -> (Recorder, RecorderInput) {
+    let channels = usize::from(channels);
+
+    let recording1 = Arc::new(AtomicBool::new(false));
+    let recording2 = Arc::clone(&recording1);
+
+    let (producer, consumer) =
+        HeapRb::new(channels * sample_rate as usize * BUFFERED_SECONDS).split();
+
+    (
+        Recorder {
+            channels,
+            sample_rate,
+
+            recording: recording1,
+            consumer: Some(consumer),
+            drain: None,
+        },
+        RecorderInput {
+            channels,
+            recording: recording2,
+            producer,
+        },
+    )
+}
+
+pub struct RecorderInput {
+    channels: usize,
+    recording: Arc<AtomicBool>,
+    producer: HeapProd<Sample>,
+}
+impl RecorderInput {
+    /// Pass on a buffer of interleaved samples from the input stream, if a recording is in progress.
+    pub fn push<T>(&mut self, data: &[T])
+    where
+        T: cpal::Sample,
+        Sample: cpal::FromSample<T>,
+    {
+        if !self.recording.load(Ordering::Acquire) {
+            return;
+        }
+
+        // If the recorder falls behind, the rest is dropped, keeping whole frames only
+        let len = min(data.len(), self.producer.vacant_len());
+        let len = len - len % self.channels;
+        self.producer.push_iter(
+            data[..len]
+                .iter()
+                .map(|&sample| sample.to_sample::<Sample>()),
+        );
+    }
+}
+
+/// Collects the audio passed to the corresponding [`RecorderInput`] in a background thread.
+pub struct Recorder {
+    channels: usize,
+    sample_rate: u32,
+
+    recording: Arc<AtomicBool>,
+    /// Is `None` while recording, in which case it is owned by the `drain` thread.
+    consumer: Option<HeapCons<Sample>>,
+    /// Is `Some` while recording.
+    drain: Option<Drain>,
+}
+impl Recorder {
+    /// Start collecting the input.
+    ///
+    /// Does nothing if a recording is already in progress.
+    pub fn start(&mut self) {
+        let Some(mut consumer) = self.consumer.take() else {
+            return;
+        };
+        // Throw out anything left over from the last recording
+        consumer.clear();
+
+        let stopped1 = Arc::new(AtomicBool::new(false));
+        let stopped2 = Arc::clone(&stopped1);
+        let join_handle = thread::spawn(move || {
+            let mut samples = Vec::new();
+            loop {
+                // Checked before draining, so nothing is left behind after stopping
+                let stopped = stopped2.load(Ordering::Acquire);
+                samples.extend(consumer.pop_iter());
+                if stopped {
+                    return (consumer, samples);
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+        });
+
+        self.drain = Some(Drain {
+            stopped: stopped1,
+            join_handle,
+        });
+        self.recording.store(true, Ordering::Release);
+    }
+
+    /// Stop collecting the input, and return what has been recorded since [`Self::start`].
+    ///
+    /// Returns `None` if no recording is in progress.
+    pub fn stop(&mut self) -> Option<Recording> {
+        let Drain {
+            stopped,
+            join_handle,
+        } = self.drain.take()?;
+
+        self.recording.store(false, Ordering::Release);
+        stopped.store(true, Ordering::Release);
+        let (consumer, samples) = join_handle.join().expect("Recorder thread panicked");
+        self.consumer = Some(consumer);
+
+        Some(Recording::from_interleaved(
+            &samples,
+            self.channels,
+            self.sample_rate,
+        ))
+    }
+}
+
+/// The thread draining the consumer of a [`Recorder`] while recording.
+struct Drain {
+    stopped: Arc<AtomicBool>,
+    /// Returns the consumer along with everything drained from it.
+    join_handle: JoinHandle<(HeapCons<Sample>, Vec<Sample>)>,
+}
+
+/// Audio collected by a [`Recorder`].
+#[derive(Debug)]
+pub struct Recording {
+    sample_rate: u32,
+    /// List of channel buffers.
+    data: Vec<Vec<Sample>>,
+}
+impl Recording {
+    /// Channels beyond the ones supported by the engine are discarded.
+    pub fn from_interleaved(samples: &[Sample], channels: usize, sample_rate: u32) -> Self {
+        let mut data = vec![Vec::with_capacity(samples.len() / channels); min(channels, CHANNELS)];
+        for frame in samples.chunks_exact(channels) {
+            for (channel, &sample) in zip(data.iter_mut(), frame) {
+                channel.push(sample);
+            }
+        }
+        Recording { sample_rate, data }
+    }
+
+    /// The number of frames in the recording.
+    pub fn length(&self) -> usize {
+        self.data[0].len()
+    }
+
+    /// Convert the recording to the given sample rate.
+    pub fn resample(self, sample_rate: u32) -> Self {
+        if sample_rate == self.sample_rate || self.length() == 0 {
+            return Recording {
+                sample_rate,
+                ..self
+            };
+        }
+
+        let chunk_size = 1024;
+        let mut resampler = FftFixedOut::<Sample>::new(
+            self.sample_rate as usize,
+            sample_rate as usize,
+            chunk_size,
+            1,
+            self.data.len(),
+        )
+        .expect("Failed to create resampler");
+
+        let length = self.length();
+        let resampled_length: usize = OriginalSamples::new(length)
+            .into_resampled(sample_rate, self.sample_rate)
+            .into();
+        let delay = resampler.output_delay();
+
+        let mut data =
+            vec![Vec::with_capacity(resampled_length + delay + chunk_size); self.data.len()];
+        let mut input = vec![Vec::new(); self.data.len()];
+        let mut position = 0;
+        while data[0].len() < resampled_length + delay {
+            // Pad the end with silence
+            let range =
+                min(position, length)..min(position + resampler.input_frames_next(), length);
+            for (input, channel) in zip(input.iter_mut(), self.data.iter()) {
+                input.clear();
+                input.extend_from_slice(&channel[range.clone()]);
+                input.resize(resampler.input_frames_next(), 0.0);
+            }
+            position += resampler.input_frames_next();
+
+            let output = resampler
+                .process(&input, None)
+                .expect("Failed to resample recording");
+            for (channel, output) in zip(data.iter_mut(), output) {
+                channel.extend_from_slice(&output);
+            }
+        }
+
+        for channel in data.iter_mut() {
+            channel.drain(..delay);
+            channel.truncate(resampled_length);
+        }
+
+        Recording { sample_rate, data }
+    }
+
+    /// Write the recording to a .wav file at `path` in the given format,
+    /// dithering the samples with `dither` if the format calls for it.
+    pub fn save(
+        &self,
+        path: &Path,
+        format: WavFormat,
+        dither: DitherMode,
+    ) -> Result<(), hound::Error> {
+        let spec = hound::WavSpec {
+            channels: self
+                .data
+                .len()
+                .try_into()
+                .expect("Too many channels to record to .wav"),
+            sample_rate: self.sample_rate,
+            bits_per_sample: format.bits_per_sample(),
+            sample_format: match format {
+                WavFormat::I16 | WavFormat::I24 => hound::SampleFormat::Int,
+                WavFormat::F32 => hound::SampleFormat::Float,
+            },
+        };
+        let mut dither = Dither::new(dither, &format.sample_format());
+        // The value of full scale in the integer formats
+        let scale = Sample::powi(2.0, i32::from(format.bits_per_sample()) - 1);
+
+        let mut writer = hound::WavWriter::create(path, spec)?;
+        for i in 0..self.length() {
+            for channel in self.data.iter() {
+                let sample = channel[i];
+                match format {
+                    WavFormat::I16 | WavFormat::I24 => {
+                        let sample = (dither.apply(sample) * scale).round();
+                        writer.write_sample(sample.clamp(-scale, scale - 1.0) as i32)?;
+                    }
+                    WavFormat::F32 => writer.write_sample(sample as f32)?,
+                }
+            }
+        }
+        writer.finalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+
+    use super::*;
+
+    #[test]
+    fn record() {
+        let (mut recorder, mut input) = recorder(2, 48_000);
+
+        // Ignored, since recording has not started
+        input.push(&[1.0_f32; 100]);
+
+        recorder.start();
+        input.push(&[0.5_f32, -0.5].repeat(300));
+        input.push(&[i16::MAX, i16::MIN].repeat(200));
+        let recording = recorder.stop().unwrap();
+
+        assert_eq!(recording.length(), 500);
+        assert!(recording.data[0][..300].iter().all(|&s| s == 0.5));
+        assert!(recording.data[1][..300].iter().all(|&s| s == -0.5));
+        assert!(recording.data[0][300..].iter().all(|&s| s > 0.99));
+        assert!(recording.data[1][300..].iter().all(|&s| s == -1.0));
+
+        assert!(recorder.stop().is_none());
+    }
+
+    #[test]
+    fn resample_length() {
+        let samples = vec![0.25; 2 * 24_000];
+        let recording = Recording::from_interleaved(&samples, 2, 24_000).resample(48_000);
+
+        assert_eq!(recording.sample_rate, 48_000);
+        assert_eq!(recording.length(), 48_000);
+        // The level is kept, away from the edges
+        for channel in recording.data.iter() {
+            assert!((channel[24_000] - 0.25).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn save() {
+        let path = temp_dir().join("adae_recorder_save_test.wav");
+        let samples = vec![0.25; 2 * 1000];
+        let recording = Recording::from_interleaved(&samples, 2, 48_000);
+        recording
+            .save(&path, WavFormat::default(), DitherMode::None)
+            .unwrap();
+
+        let reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().channels, 2);
+        assert_eq!(reader.spec().sample_rate, 48_000);
+        assert_eq!(reader.spec().bits_per_sample, 32);
+        assert_eq!(reader.duration(), 1000);
+    }
+
+    #[test]
+    fn save_integer_formats() {
+        let path = temp_dir().join("adae_recorder_save_integer_test.wav");
+        let samples = [0.5, -1.0, 1.0, 0.0];
+        let recording = Recording::from_interleaved(&samples, 2, 48_000);
+
+        recording
+            .save(&path, WavFormat::I16, DitherMode::None)
+            .unwrap();
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().bits_per_sample, 16);
+        let written: Vec<i16> = reader.samples().map(Result::unwrap).collect();
+        // Full scale is clipped to the largest representable value
+        assert_eq!(written, [16_384, -32_768, 32_767, 0]);
+
+        recording
+            .save(&path, WavFormat::I24, DitherMode::Tpdf { seed: 0 })
+            .unwrap();
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().bits_per_sample, 24);
+        let written: Vec<i32> = reader.samples().map(Result::unwrap).collect();
+        // Not dithered
+        assert_eq!(written, [4_194_304, -8_388_608, 8_388_607, 0]);
+    }
+}
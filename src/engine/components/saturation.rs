@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+
+use crate::engine::utils::ringbuffer::{self, ringbuffer};
+use crate::engine::Sample;
+
+pub fn saturation(settings: Option<SaturationSettings>) -> (Saturation, SaturationProcessor) {
+    let (settings_sender, settings_receiver) = ringbuffer();
+
+    (
+        Saturation {
+            settings,
+            settings_sender,
+        },
+        SaturationProcessor {
+            settings,
+            settings_receiver,
+        },
+    )
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct SaturationSettings {
+    /// Linear gain applied before the curve, and removed again after it.
+    ///
+    /// Quiet signals pass at the same level, while peaks above roughly `1 / drive` are rounded off.
+    pub drive: f32,
+    /// The amount of the saturated signal blended with the original, from 0 to 1.
+    pub mix: f32,
+}
+impl Default for SaturationSettings {
+    fn default() -> Self {
+        Self {
+            drive: 1.0,
+            mix: 1.0,
+        }
+    }
+}
+
+/// Acquired via the [`saturation`] function.
+pub struct Saturation {
+    settings: Option<SaturationSettings>,
+    settings_sender: ringbuffer::Sender<Option<SaturationSettings>>,
+}
+impl Saturation {
+    /// Is `None` while the saturation is bypassed.
+    pub fn settings(&self) -> Option<SaturationSettings> {
+        self.settings
+    }
+    pub fn set_settings(&mut self, settings: Option<SaturationSettings>) {
+        self.settings = settings;
+        self.settings_sender.send(settings);
+    }
+}
+impl Debug for Saturation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Saturation")
+            .field("settings", &self.settings)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A tanh soft-clipper, which gradually compresses peaks instead of cutting them off.
+pub struct SaturationProcessor {
+    settings: Option<SaturationSettings>,
+    settings_receiver: ringbuffer::Receiver<Option<SaturationSettings>>,
+}
+impl SaturationProcessor {
+    pub fn poll(&mut self) {
+        while let Some(settings) = self.settings_receiver.recv() {
+            self.settings = settings;
+        }
+    }
+
+    /// Process the interleaved `buffer` in place.
+    ///
+    /// Does nothing while the saturation is bypassed.
+    pub fn process(&mut self, buffer: &mut [Sample]) {
+        let Some(SaturationSettings { drive, mix }) = self.settings else {
+            return;
+        };
+        if drive <= 0.0 {
+            return;
+        }
+
+        let drive = drive as Sample;
+        let mix = mix.clamp(0.0, 1.0) as Sample;
+        for sample in buffer {
+            let saturated = (*sample * drive).tanh() / drive;
+            *sample += mix * (saturated - *sample);
+        }
+    }
+}
+impl Debug for SaturationProcessor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SaturationProcessor")
+            .field("settings", &self.settings)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::utils::test_sine;
+
+    /// A 440 Hz sine at the given amplitude.
+    fn sine(amplitude: Sample) -> Vec<Sample> {
+        test_sine(440.0, amplitude, 0..1000)
+    }
+
+    fn peak(buffer: &[Sample]) -> Sample {
+        buffer.iter().fold(0.0, |max: Sample, s| max.max(s.abs()))
+    }
+
+    #[test]
+    fn quiet_is_near_transparent() {
+        let (_, mut sp) = saturation(Some(SaturationSettings {
+            drive: 2.0,
+            mix: 1.0,
+        }));
+
+        let input = sine(0.01);
+        let mut buffer = input.clone();
+        no_heap! {{
+            sp.process(&mut buffer);
+        }}
+
+        for (output, input) in buffer.iter().zip(&input) {
+            assert!((output - input).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn high_drive_compresses_peaks() {
+        let (_, mut sp) = saturation(Some(SaturationSettings {
+            drive: 8.0,
+            mix: 1.0,
+        }));
+
+        let input = sine(1.0);
+        let mut buffer = input.clone();
+        no_heap! {{
+            sp.process(&mut buffer);
+        }}
+
+        assert!(peak(&buffer) <= 1.0 / 8.0);
+        // The peaks are compressed far more than the quieter parts of the wave
+        let i = input.iter().position(|&s| s > 0.01).unwrap();
+        assert!(buffer[i] / input[i] > 0.9);
+    }
+
+    #[test]
+    fn mix() {
+        let (mut s, mut sp) = saturation(None);
+
+        let input = sine(1.0);
+        let mut buffer = input.clone();
+        no_heap! {{
+            sp.poll();
+            sp.process(&mut buffer);
+        }}
+        assert_eq!(buffer, input);
+
+        s.set_settings(Some(SaturationSettings {
+            drive: 8.0,
+            mix: 0.5,
+        }));
+        no_heap! {{
+            sp.poll();
+            sp.process(&mut buffer);
+        }}
+        let saturated = (1.0 as Sample * 8.0).tanh() / 8.0;
+        assert!((peak(&buffer) - (1.0 + saturated) / 2.0).abs() < 1e-3);
+    }
+}
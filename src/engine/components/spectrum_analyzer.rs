@@ -0,0 +1,324 @@
+use realfft::num_complex::Complex;
+use realfft::{RealFftPlanner, RealToComplex};
+use std::f32::consts::TAU;
+use std::fmt::Debug;
+use std::sync::atomic::{fence, AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::engine::utils::dropper::DBox;
+use crate::engine::utils::ringbuffer::{self, ringbuffer};
+use crate::engine::utils::AtomicF32;
+use crate::engine::{Sample, CHANNELS};
+
+/// Number of samples in each FFT, unless changed with [`SpectrumAnalyzer::set_size`].
+const SPECTRUM_SIZE_DEFAULT: usize = 2048;
+const SPECTRUM_SIZE_MIN: usize = 64;
+const SPECTRUM_SIZE_MAX: usize = 16384;
+
+pub fn spectrum_analyzer() -> (SpectrumAnalyzer, SpectrumAnalyzerProcessor) {
+    let enabled1 = Arc::new(AtomicBool::new(false));
+    let enabled2 = Arc::clone(&enabled1);
+
+    let analysis = Analysis::new(SPECTRUM_SIZE_DEFAULT);
+    let (analysis_sender, analysis_receiver) = ringbuffer();
+
+    (
+        SpectrumAnalyzer {
+            enabled: enabled1,
+            size: SPECTRUM_SIZE_DEFAULT,
+            magnitudes: Arc::clone(&analysis.magnitudes),
+            analysis_sender,
+        },
+        SpectrumAnalyzerProcessor {
+            enabled: enabled2,
+            analysis: DBox::new(analysis),
+            analysis_receiver,
+        },
+    )
+}
+
+/// Acquired via the [`spectrum_analyzer`] function.
+pub struct SpectrumAnalyzer {
+    enabled: Arc<AtomicBool>,
+    size: usize,
+    magnitudes: Arc<Magnitudes>,
+    analysis_sender: ringbuffer::Sender<DBox<Analysis>>,
+}
+impl SpectrumAnalyzer {
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+    /// The analysis is skipped entirely while disabled, which it is by default.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Number of samples in each FFT.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+    /// Larger sizes give a finer frequency resolution, but react slower to changes.
+    ///
+    /// The size is rounded up to the nearest power of two, between 64 and 16384.
+    /// Changing it starts the analysis over.
+    pub fn set_size(&mut self, size: usize) {
+        let size = size
+            .next_power_of_two()
+            .clamp(SPECTRUM_SIZE_MIN, SPECTRUM_SIZE_MAX);
+        if size == self.size {
+            return;
+        }
+
+        let analysis = Analysis::new(size);
+        self.size = size;
+        self.magnitudes = Arc::clone(&analysis.magnitudes);
+        self.analysis_sender.send(DBox::new(analysis));
+    }
+
+    /// Returns the linear magnitude of the most recent audio, grouped into the given number of bins.
+    ///
+    /// The bins are spaced evenly from 0 Hz to half the sample rate,
+    /// each containing the highest magnitude within its range.
+    /// A full-scale sine reads as roughly 1 in the bin of its frequency.
+    pub fn read(&self, bins: usize) -> Vec<f32> {
+        let mut magnitudes = Vec::with_capacity(self.size / 2 + 1);
+        self.magnitudes.read(&mut magnitudes);
+
+        let len = magnitudes.len();
+        (0..bins)
+            .map(|bin| {
+                let start = bin * len / bins;
+                let end = ((bin + 1) * len / bins).max(start + 1);
+                magnitudes[start..end]
+                    .iter()
+                    .fold(0.0, |max: f32, &m| max.max(m))
+            })
+            .collect()
+    }
+}
+impl Debug for SpectrumAnalyzer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SpectrumAnalyzer")
+            .field("enabled", &self.enabled)
+            .field("size", &self.size)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Acquired via the [`spectrum_analyzer`] function.
+pub struct SpectrumAnalyzerProcessor {
+    enabled: Arc<AtomicBool>,
+    analysis: DBox<Analysis>,
+    analysis_receiver: ringbuffer::Receiver<DBox<Analysis>>,
+}
+impl SpectrumAnalyzerProcessor {
+    pub fn poll(&mut self) {
+        while let Some(analysis) = self.analysis_receiver.recv() {
+            self.analysis = analysis;
+        }
+    }
+
+    /// Feed the interleaved `buffer` to the analysis, if it is enabled.
+    pub fn report(&mut self, buffer: &[Sample]) {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let analysis = &mut *self.analysis;
+        let hop = analysis.history.len() / 2;
+        for frame in buffer.chunks_exact(CHANNELS) {
+            let mono = frame.iter().sum::<Sample>() as f32 / CHANNELS as f32;
+            analysis.history[analysis.position] = mono;
+            analysis.position = (analysis.position + 1) % analysis.history.len();
+
+            analysis.since_last += 1;
+            if analysis.since_last >= hop {
+                analysis.since_last = 0;
+                analysis.analyze();
+            }
+        }
+    }
+}
+impl Debug for SpectrumAnalyzerProcessor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SpectrumAnalyzerProcessor")
+            .field("enabled", &self.enabled)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Everything needed to analyze the spectrum at one size, allocated ahead of time.
+struct Analysis {
+    fft: Arc<dyn RealToComplex<f32>>,
+    /// Hann window.
+    window: Vec<f32>,
+    /// Scales the output of the FFT, so a full-scale sine has a magnitude of 1.
+    scale: f32,
+
+    /// The last samples of the downmixed signal, with the oldest at `position`.
+    history: Vec<f32>,
+    position: usize,
+    /// Frames since the spectrum was last analyzed.
+    since_last: usize,
+
+    input: Vec<f32>,
+    output: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+    magnitudes: Arc<Magnitudes>,
+}
+impl Analysis {
+    fn new(size: usize) -> Self {
+        let fft = RealFftPlanner::new().plan_fft_forward(size);
+        let window: Vec<f32> = (0..size)
+            .map(|i| 0.5 - 0.5 * (TAU * i as f32 / size as f32).cos())
+            .collect();
+        let scale = 2.0 / window.iter().sum::<f32>();
+
+        Self {
+            input: fft.make_input_vec(),
+            output: fft.make_output_vec(),
+            scratch: fft.make_scratch_vec(),
+            magnitudes: Arc::new(Magnitudes::new(size / 2 + 1)),
+            fft,
+            window,
+            scale,
+
+            history: vec![0.0; size],
+            position: 0,
+            since_last: 0,
+        }
+    }
+
+    fn analyze(&mut self) {
+        let (newest, oldest) = self.history.split_at(self.position);
+        for ((input, &sample), &window) in self
+            .input
+            .iter_mut()
+            .zip(oldest.iter().chain(newest))
+            .zip(&self.window)
+        {
+            *input = sample * window;
+        }
+
+        self.fft
+            .process_with_scratch(&mut self.input, &mut self.output, &mut self.scratch)
+            .expect("FFT buffers have the wrong length");
+
+        let scale = self.scale;
+        self.magnitudes
+            .write(self.output.iter().map(|bin| bin.norm() * scale));
+    }
+}
+
+/// Double buffer of the latest magnitudes, which can be written without blocking, and read without tearing.
+///
+/// The writer always writes to the buffer that is not currently being read from, and swaps them afterwards.
+/// Should it get around to writing the buffer that is being read from, the reader notices and starts over.
+struct Magnitudes {
+    /// Incremented every time the buffers are swapped.
+    generation: AtomicUsize,
+    buffers: [Vec<AtomicF32>; 2],
+}
+impl Magnitudes {
+    fn new(len: usize) -> Self {
+        Self {
+            generation: AtomicUsize::new(0),
+            buffers: [(); 2].map(|_| (0..len).map(|_| AtomicF32::new(0.0)).collect()),
+        }
+    }
+
+    /// Should only ever be called from one thread.
+    fn write(&self, magnitudes: impl Iterator<Item = f32>) {
+        let generation = self.generation.load(Ordering::Relaxed);
+        // Makes sure the previous swap is visible to any reader that sees the writes below
+        fence(Ordering::Release);
+
+        let back = &self.buffers[(generation + 1) % 2];
+        for (stored, magnitude) in back.iter().zip(magnitudes) {
+            stored.store(magnitude, Ordering::Relaxed);
+        }
+        self.generation.store(generation + 1, Ordering::Release);
+    }
+
+    fn read(&self, result: &mut Vec<f32>) {
+        loop {
+            let generation = self.generation.load(Ordering::Acquire);
+            let front = &self.buffers[generation % 2];
+            result.clear();
+            result.extend(front.iter().map(|m| m.load(Ordering::Relaxed)));
+
+            fence(Ordering::Acquire);
+            if self.generation.load(Ordering::Relaxed) == generation {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::utils::test_sine;
+
+    /// A sine at the given frequency, lasting one second.
+    fn sine(frequency: f32, amplitude: Sample) -> Vec<Sample> {
+        test_sine(frequency, amplitude, 0..48_000)
+    }
+
+    #[test]
+    fn finds_peak() {
+        let (sa, mut sap) = spectrum_analyzer();
+        sa.set_enabled(true);
+
+        // Exactly on bin 100
+        let frequency = 100.0 * 48_000.0 / SPECTRUM_SIZE_DEFAULT as f32;
+        let buffer = sine(frequency, 0.5);
+        no_heap! {{
+            sap.poll();
+            sap.report(&buffer);
+        }}
+
+        let spectrum = sa.read(SPECTRUM_SIZE_DEFAULT / 2 + 1);
+        let (peak_bin, &peak) = spectrum
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .unwrap();
+        assert_eq!(peak_bin, 100);
+        assert!((peak - 0.5).abs() < 0.01);
+        assert!(spectrum[200..].iter().all(|&m| m < 0.001));
+    }
+
+    #[test]
+    fn disabled() {
+        let (sa, mut sap) = spectrum_analyzer();
+
+        let buffer = sine(1000.0, 0.5);
+        no_heap! {{
+            sap.poll();
+            sap.report(&buffer);
+        }}
+
+        assert!(sa.read(64).iter().all(|&m| m == 0.0));
+    }
+
+    #[test]
+    fn set_size() {
+        let (mut sa, mut sap) = spectrum_analyzer();
+        sa.set_enabled(true);
+        sa.set_size(1000);
+        assert_eq!(sa.size(), 1024);
+
+        let buffer = sine(1000.0, 0.5);
+        no_heap! {{
+            sap.poll();
+            sap.report(&buffer);
+        }}
+
+        // Fewer bins than the FFT outputs
+        let spectrum = sa.read(16);
+        assert_eq!(spectrum.len(), 16);
+        assert!(spectrum[0] > 0.4);
+        assert!(spectrum[1..].iter().all(|&m| m < 0.01));
+    }
+}
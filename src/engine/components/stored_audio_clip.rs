@@ -1,32 +1,50 @@
 use std::{
     borrow::Cow,
+    cmp::{max, min},
+    collections::HashMap,
     error::Error,
     fmt::{Debug, Display},
     fs::File,
+    iter::zip,
     path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, OnceLock,
+    },
 };
 
 use symphonia::core::{
     audio::{AudioBuffer, AudioBufferRef, Signal},
-    codecs::DecoderOptions,
+    codecs::{Decoder, DecoderOptions},
     conv::IntoSample,
     errors::Error as SymphoniaError,
-    formats::FormatOptions,
+    formats::{FormatOptions, FormatReader, SeekMode, SeekTo},
     io::MediaSourceStream,
     meta::MetadataOptions,
     probe::Hint,
     sample::Sample as SymphoniaSample,
 };
 
+use super::audio_clip_reader::OriginalSamples;
 use crate::engine::{
-    utils::{key_generator::key_type, min_max},
-    Sample,
+    utils::{db_to_amplitude, key_generator::key_type, min_max},
+    Sample, CHANNELS,
 };
 
+mod downmix;
+pub use downmix::Downmix;
+pub(crate) use downmix::Downmixer;
+
 key_type!(pub struct StoredAudioClipKey(u32));
 
 /// Number of samples per chunk in the waveform data.
 pub const SAMPLES_PER_WAVEFORM_CHUNK: usize = 1024;
+/// Highest sample rate that clips can be imported at.
+pub const MAX_SAMPLE_RATE: u32 = 768_000;
+/// Number of frames summarized by each peak in the finest level of [`WaveformPeaks`], for clips loaded into memory.
+const PEAK_BLOCK_FRAMES: usize = 64;
+/// Number of results of [`StoredAudioClip::waveform_peaks`] kept around.
+const CACHED_PEAKS: usize = 8;
 
 /// An audio clip that has been imported.
 #[derive(PartialEq)]
@@ -36,90 +54,165 @@ pub struct StoredAudioClip {
     waveform_data: Vec<i16>,
 
     sample_rate: u32,
+    channels: usize,
+    /// Number of frames in total
+    length: usize,
+    data: AudioData,
+    peaks: WaveformPeaks,
+    levels: ClipLevels,
+}
+
+#[derive(PartialEq)]
+enum AudioData {
     /// List of channel buffers
-    audio_data: Vec<Vec<Sample>>,
+    Loaded(Vec<Vec<Sample>>),
+    /// The clip is decoded from disk while being played back.
+    Streamed {
+        path: PathBuf,
+        /// Maximum number of frames each reader keeps decoded ahead of its position.
+        max_cached_frames: usize,
+        downmix: Downmix,
+    },
 }
-impl StoredAudioClip {
-    pub fn import(key: StoredAudioClipKey, path: &Path) -> Result<Self, ImportError> {
-        // Currently the entire clip just gets loaded into memory immediately.
-        // I guess that could be improved.
 
-        let file =
-            Box::new(File::open(path).map_err(|_| ImportError::FileNotFound(path.to_path_buf()))?);
-        let mss = MediaSourceStream::new(file, Default::default());
+/// Min and max of the clip at successively halved resolutions,
+/// along with the latest results of [`StoredAudioClip::waveform_peaks`].
+///
+/// Everything is computed on first use.
+#[derive(Default)]
+struct WaveformPeaks {
+    levels: OnceLock<PeakLevels>,
+    /// Results by number of bins.
+    cache: Mutex<HashMap<usize, Vec<(Sample, Sample)>>>,
+}
+impl PartialEq for WaveformPeaks {
+    fn eq(&self, _other: &Self) -> bool {
+        // Only holds what can be derived from the rest of the clip
+        true
+    }
+}
 
-        let mut hint = Hint::new();
-        if let Some(os_extension) = path.extension() {
-            if let Some(extension) = os_extension.to_str() {
-                hint.with_extension(extension);
-            }
-        }
+/// The peak and RMS of each channel, as returned by [`StoredAudioClip::peak`] and [`StoredAudioClip::rms`].
+///
+/// Clips loaded into memory compute them on first use,
+/// while streamed clips have them computed during import, since the audio is not kept around.
+#[derive(Default)]
+struct ClipLevels {
+    levels: OnceLock<([f32; CHANNELS], [f32; CHANNELS])>,
+}
+impl PartialEq for ClipLevels {
+    fn eq(&self, _other: &Self) -> bool {
+        // Only holds what can be derived from the rest of the clip
+        true
+    }
+}
 
-        let format_options = FormatOptions::default();
-        let metadata_options = MetadataOptions::default();
-        let decoder_options = DecoderOptions::default();
+struct PeakLevels {
+    /// The number of frames summarized by each peak in the first level.
+    block_frames: usize,
+    /// Each level has half as many peaks as the one before it, and the last one has a single peak.
+    levels: Vec<Vec<(Sample, Sample)>>,
+}
 
-        let probed = symphonia::default::get_probe()
-            .format(&hint, mss, &format_options, &metadata_options)
-            .or(Err(ImportError::UknownFormat))?;
-        let mut format = probed.format;
+/// The overall min and max of a list of peaks.
+fn merge_peaks(peaks: impl IntoIterator<Item = (Sample, Sample)>) -> (Sample, Sample) {
+    peaks
+        .into_iter()
+        .fold((0.0, 0.0), |(min, max), (peak_min, peak_max)| {
+            (Sample::min(min, peak_min), Sample::max(max, peak_max))
+        })
+}
 
-        let track = format
-            .default_track()
-            .ok_or_else(|| ImportError::Other("No deafault track".to_owned()))?;
-        let track_id = track.id;
-        let mut decoder = symphonia::default::get_codecs()
-            .make(&track.codec_params, &decoder_options)
-            .or(Err(ImportError::UknownFormat))?;
+/// The peak and RMS of each output channel, from the peak and sum of squares of each channel of a clip with `length` frames.
+///
+/// A clip without any channels or frames is silent.
+fn channel_levels(
+    peaks: &[Sample],
+    squares: &[f64],
+    length: usize,
+) -> ([f32; CHANNELS], [f32; CHANNELS]) {
+    if peaks.is_empty() || length == 0 {
+        return ([0.0; CHANNELS], [0.0; CHANNELS]);
+    }
+
+    // Mono clips are played in every channel
+    let channel = |i: usize| min(i, peaks.len() - 1);
+    let peak = std::array::from_fn(|i| peaks[channel(i)] as f32);
+    let rms = std::array::from_fn(|i| (squares[channel(i)] / length as f64).sqrt() as f32);
+    (peak, rms)
+}
+
+/// How much of a file has been decoded so far, which can be checked from other threads during import.
+#[derive(Debug, Default)]
+pub struct DecodeProgress {
+    decoded_frames: AtomicUsize,
+    /// Zero if unknown.
+    total_frames: AtomicUsize,
+}
+impl DecodeProgress {
+    pub fn decoded_frames(&self) -> usize {
+        self.decoded_frames.load(Ordering::Relaxed)
+    }
+
+    /// The length of the file in frames, if its header states it.
+    pub fn total_frames(&self) -> Option<usize> {
+        match self.total_frames.load(Ordering::Relaxed) {
+            0 => None,
+            total => Some(total),
+        }
+    }
+}
+
+impl StoredAudioClip {
+    /// Import the clip, decoding the entire file into memory.
+    ///
+    /// Files with more than two channels are mixed down with [`Downmix::default`].
+    pub fn import(key: StoredAudioClipKey, path: &Path) -> Result<Self, ImportError> {
+        Self::import_with(key, path, &Downmix::default(), &DecodeProgress::default())
+    }
 
+    /// Like [`Self::import`], mixing files with more than two channels down with `downmix`,
+    /// while reporting how far along the decoding is to `progress`.
+    pub fn import_with(
+        key: StoredAudioClipKey,
+        path: &Path,
+        downmix: &Downmix,
+        progress: &DecodeProgress,
+    ) -> Result<Self, ImportError> {
+        let mut audio_data: Vec<Vec<Sample>> = Vec::with_capacity(2);
         let mut sample_rate = 0;
-        let mut audio_data = Vec::with_capacity(2);
-        let mut first = true;
-        loop {
-            let packet = match format.next_packet() {
-                Ok(packet) => Ok(packet),
-                Err(SymphoniaError::IoError(_)) => break,
-                Err(e) => Err(ImportError::Other(format!("{}", e))),
-            }?;
-            if packet.track_id() != track_id {
-                continue;
-            }
-            match decoder.decode(&packet) {
-                Ok(received_buffer) => {
-                    if first {
-                        first = false;
-
-                        let channels = received_buffer.spec().channels.count();
-                        sample_rate = received_buffer.spec().rate;
-
-                        // TODO: Support more than 2 channels
-                        if channels > 2 {
-                            return Err(ImportError::TooManyChannels);
-                        }
-
-                        for _ in 0..channels {
-                            audio_data.push(Vec::new());
-                        }
-                    }
 
-                    Self::extend_from_buffer(&mut audio_data, received_buffer);
+        let mut downmixer = None;
+        decode(path, progress, |buffer_ref| {
+            let downmixer = match &mut downmixer {
+                Some(downmixer) => downmixer,
+                None => {
+                    sample_rate = buffer_ref.spec().rate;
+                    let new_downmixer = Downmixer::new(downmix, buffer_ref.spec().channels)?;
+                    audio_data = vec![Vec::new(); new_downmixer.channels()];
+                    downmixer.insert(new_downmixer)
                 }
-                Err(e) => panic!("{}", e),
-            }
-        }
+            };
+
+            downmixer.extend(&mut audio_data, buffer_ref);
+            Ok(())
+        })?;
 
         let channels = audio_data.len();
-        let len = audio_data[0].len();
+        let length = audio_data.first().map_or(0, Vec::len);
+        if length == 0 {
+            return Err(ImportError::Empty);
+        }
 
-        let chunks = len / SAMPLES_PER_WAVEFORM_CHUNK;
+        let chunks = length / SAMPLES_PER_WAVEFORM_CHUNK;
         let mut waveform_data = vec![0; 2 * chunks * channels];
-        let chunk_size = len.div_ceil(chunks);
+        let chunk_size = length.div_ceil(chunks);
         for (channel_i, channel) in audio_data.iter().enumerate() {
             for (chunk_i, chunk) in channel.chunks(chunk_size).enumerate() {
                 let i = (2 * channels * chunk_i) + (2 * channel_i);
                 let (min, max) = min_max(chunk.iter().copied(), 0.0);
-                waveform_data[i] = (min * i16::MAX as f32) as i16;
-                waveform_data[i + 1] = (max * i16::MAX as f32) as i16;
+                waveform_data[i] = (min * i16::MAX as Sample) as i16;
+                waveform_data[i + 1] = (max * i16::MAX as Sample) as i16;
             }
         }
 
@@ -127,41 +220,127 @@ impl StoredAudioClip {
             key,
             waveform_data,
             sample_rate,
-            audio_data,
+            channels,
+            length,
+            data: AudioData::Loaded(audio_data),
+            peaks: WaveformPeaks::default(),
+            levels: ClipLevels::default(),
         })
     }
-    fn extend_from_buffer(data: &mut [Vec<Sample>], buffer_ref: AudioBufferRef) {
-        // Bruh
-        use AudioBufferRef as A;
-        match buffer_ref {
-            A::U8(buffer) => extend(data, buffer),
-            A::S8(buffer) => extend(data, buffer),
-            A::U16(buffer) => extend(data, buffer),
-            A::U24(buffer) => extend(data, buffer),
-            A::U32(buffer) => extend(data, buffer),
-            A::S16(buffer) => extend(data, buffer),
-            A::S24(buffer) => extend(data, buffer),
-            A::S32(buffer) => extend(data, buffer),
-            A::F32(buffer) => extend(data, buffer),
-            A::F64(buffer) => extend(data, buffer),
-        };
 
-        fn extend<S>(data: &mut [Vec<Sample>], buffer: Cow<AudioBuffer<S>>)
-        where
-            S: SymphoniaSample + IntoSample<Sample>,
-        {
-            for (chan_i, output) in data.iter_mut().enumerate() {
-                let received = buffer.chan(chan_i);
-                for &sample in received {
-                    output.push(sample.into_sample());
+    /// Import the clip without keeping the decoded audio in memory.
+    ///
+    /// The file is decoded once up front to determine its length and waveform,
+    /// after which each reader of the clip decodes it on demand in a background thread,
+    /// keeping at most `max_cached_frames` frames decoded ahead of its position.
+    pub fn import_streaming(
+        key: StoredAudioClipKey,
+        path: &Path,
+        max_cached_frames: usize,
+    ) -> Result<Self, ImportError> {
+        Self::import_streaming_with(key, path, max_cached_frames, &Downmix::default())
+    }
+
+    /// Like [`Self::import_streaming`], mixing files with more than two channels down with `downmix`.
+    pub fn import_streaming_with(
+        key: StoredAudioClipKey,
+        path: &Path,
+        max_cached_frames: usize,
+        downmix: &Downmix,
+    ) -> Result<Self, ImportError> {
+        let mut sample_rate = 0;
+        let mut channels = 0;
+        let mut length = 0;
+
+        // Min/max of each channel in the chunk currently being read
+        let mut chunk_min_max = Vec::new();
+        let mut chunk_progress = 0;
+        let mut waveform_data = Vec::new();
+
+        // Peak and sum of squares of each channel so far
+        let mut peaks = Vec::new();
+        let mut squares = Vec::new();
+
+        let mut block = Vec::new();
+        let mut downmixer = None;
+        decode(path, &DecodeProgress::default(), |buffer_ref| {
+            let downmixer = match &mut downmixer {
+                Some(downmixer) => downmixer,
+                None => {
+                    sample_rate = buffer_ref.spec().rate;
+                    let new_downmixer = Downmixer::new(downmix, buffer_ref.spec().channels)?;
+                    channels = new_downmixer.channels();
+                    chunk_min_max = vec![(0.0, 0.0); channels];
+                    peaks = vec![0.0; channels];
+                    squares = vec![0.0; channels];
+                    block = vec![Vec::new(); channels];
+                    downmixer.insert(new_downmixer)
                 }
+            };
+
+            for channel in block.iter_mut() {
+                channel.clear();
             }
+            downmixer.extend(&mut block, buffer_ref);
+
+            for ((channel, peak), square) in zip(zip(&block, &mut peaks), &mut squares) {
+                for &sample in channel {
+                    *peak = Sample::max(*peak, sample.abs());
+                    *square += (sample as f64).powi(2);
+                }
+            }
+
+            for i in 0..block[0].len() {
+                for (channel, (min, max)) in zip(&block, chunk_min_max.iter_mut()) {
+                    let sample = channel[i];
+                    *min = Sample::min(*min, sample);
+                    *max = Sample::max(*max, sample);
+                }
+
+                chunk_progress += 1;
+                if chunk_progress == SAMPLES_PER_WAVEFORM_CHUNK {
+                    for (min, max) in chunk_min_max.iter_mut() {
+                        waveform_data.push((*min * i16::MAX as Sample) as i16);
+                        waveform_data.push((*max * i16::MAX as Sample) as i16);
+                        (*min, *max) = (0.0, 0.0);
+                    }
+                    chunk_progress = 0;
+                }
+            }
+
+            length += block[0].len();
+            Ok(())
+        })?;
+
+        if length == 0 {
+            return Err(ImportError::Empty);
         }
+
+        Ok(Self {
+            key,
+            waveform_data,
+            sample_rate,
+            channels,
+            length,
+            data: AudioData::Streamed {
+                path: path.to_owned(),
+                max_cached_frames,
+                downmix: downmix.clone(),
+            },
+            peaks: WaveformPeaks::default(),
+            levels: ClipLevels {
+                levels: OnceLock::from(channel_levels(&peaks, &squares, length)),
+            },
+        })
     }
 
     pub fn key(&self) -> StoredAudioClipKey {
         self.key
     }
+    /// Used when the key is first decided on after the clip has been imported.
+    pub(crate) fn set_key(&mut self, key: StoredAudioClipKey) {
+        self.key = key;
+    }
 
     pub fn waveform_data(&self) -> &[i16] {
         &self.waveform_data
@@ -171,24 +350,399 @@ impl StoredAudioClip {
         self.sample_rate
     }
 
-    pub fn audio_data(&self) -> &[Vec<Sample>] {
-        &self.audio_data
+    /// The decoded audio, as a list of channel buffers.
+    ///
+    /// Returns `None` if the clip is streamed from disk.
+    pub fn audio_data(&self) -> Option<&[Vec<Sample>]> {
+        match &self.data {
+            AudioData::Loaded(audio_data) => Some(audio_data),
+            AudioData::Streamed { .. } => None,
+        }
+    }
+
+    /// If the clip is streamed from disk, returns the path of the file and the maximum number of frames cached per reader.
+    pub fn streaming_source(&self) -> Option<(&Path, usize)> {
+        match &self.data {
+            AudioData::Loaded(_) => None,
+            AudioData::Streamed {
+                path,
+                max_cached_frames,
+                ..
+            } => Some((path, *max_cached_frames)),
+        }
+    }
+
+    /// How the file is mixed down, if the clip is streamed from disk.
+    pub(crate) fn streaming_downmix(&self) -> Option<&Downmix> {
+        match &self.data {
+            AudioData::Loaded(_) => None,
+            AudioData::Streamed { downmix, .. } => Some(downmix),
+        }
     }
 
     /// Number of channels
     pub fn channels(&self) -> usize {
-        self.audio_data.len()
+        self.channels
     }
 
     /// Number of frames (samples per channel) in total
     pub fn length(&self) -> usize {
-        // All channels should have the same length
-        debug_assert!(self
-            .audio_data
+        self.length
+    }
+
+    /// The highest absolute sample of each output channel, where `1.0` is full scale.
+    ///
+    /// Mono clips have the same level in every channel, since that is how they are played.
+    /// Computed on first use, and cached from then on.
+    pub fn peak(&self) -> [f32; CHANNELS] {
+        self.levels().0
+    }
+
+    /// The Root Mean Square of each output channel over the entire clip, on the same scale as [`Self::peak`].
+    ///
+    /// Mono clips have the same level in every channel, since that is how they are played.
+    /// Computed on first use, and cached from then on.
+    pub fn rms(&self) -> [f32; CHANNELS] {
+        self.levels().1
+    }
+
+    fn levels(&self) -> ([f32; CHANNELS], [f32; CHANNELS]) {
+        *self.levels.levels.get_or_init(|| {
+            let AudioData::Loaded(data) = &self.data else {
+                unreachable!("Levels of streamed clips are computed during import");
+            };
+            let peaks: Vec<_> = data
+                .iter()
+                .map(|channel| {
+                    channel
+                        .iter()
+                        .fold(0.0, |peak, s| Sample::max(peak, s.abs()))
+                })
+                .collect();
+            let squares: Vec<_> = data
+                .iter()
+                .map(|channel| channel.iter().map(|&s| (s as f64).powi(2)).sum())
+                .collect();
+            channel_levels(&peaks, &squares, self.length)
+        })
+    }
+
+    /// Split the clip into `bins` sections of equal length, and get the min and max sample of each, across all channels.
+    ///
+    /// The peaks are computed from precomputed levels of detail, so this is cheap even for long clips,
+    /// and the latest results are cached.
+    /// Clips that are streamed from disk only have peaks for every [`SAMPLES_PER_WAVEFORM_CHUNK`] frames,
+    /// so zooming in further than that gives coarser results.
+    pub fn waveform_peaks(&self, bins: usize) -> Vec<(Sample, Sample)> {
+        if let Some(peaks) = self.peaks.cache.lock().unwrap().get(&bins) {
+            return peaks.clone();
+        }
+
+        let PeakLevels {
+            block_frames,
+            levels,
+        } = self.peak_levels();
+        let frames_per_bin = self.length / max(bins, 1);
+        // The coarsest level where every bin spans at least one peak
+        let level = min(
+            max(frames_per_bin / block_frames, 1).ilog2() as usize,
+            levels.len() - 1,
+        );
+        let level_frames = *block_frames << level;
+
+        let peaks: Vec<_> = (0..bins)
+            .map(|i| {
+                let start = i * self.length / bins;
+                let end = min(max((i + 1) * self.length / bins, start + 1), self.length);
+                match &self.data {
+                    AudioData::Loaded(data) if frames_per_bin < *block_frames => {
+                        Self::data_peaks(data, start, end)
+                    }
+                    _ => {
+                        let peaks = &levels[level];
+                        let first = min(start / level_frames, peaks.len());
+                        let last = min(end.div_ceil(level_frames), peaks.len());
+                        merge_peaks(peaks[first..last].iter().copied())
+                    }
+                }
+            })
+            .collect();
+
+        let mut cache = self.peaks.cache.lock().unwrap();
+        if cache.len() >= CACHED_PEAKS {
+            cache.clear();
+        }
+        cache.insert(bins, peaks.clone());
+        peaks
+    }
+
+    /// The first and last frame where any channel is louder than `threshold_db` dBFS.
+    ///
+    /// Returns `None` if the entire clip is quieter than that.
+    /// Clips that are streamed from disk are only analyzed for every [`SAMPLES_PER_WAVEFORM_CHUNK`] frames,
+    /// so the bounds are widened to the edges of the chunks they are in.
+    pub fn silence_bounds(&self, threshold_db: f32) -> Option<(OriginalSamples, OriginalSamples)> {
+        let threshold = db_to_amplitude(threshold_db) as Sample;
+        let loud = |sample: Sample| sample.abs() > threshold;
+
+        let (first, last) = match &self.data {
+            AudioData::Loaded(data) => {
+                let frame_loud = |i: usize| data.iter().any(|channel| loud(channel[i]));
+                let first = (0..self.length).find(|&i| frame_loud(i))?;
+                let last = (first..self.length).rev().find(|&i| frame_loud(i))?;
+                (first, last)
+            }
+            AudioData::Streamed { .. } => {
+                let chunks: Vec<bool> = self
+                    .waveform_data
+                    .chunks_exact(2 * self.channels)
+                    .map(|chunk| {
+                        chunk
+                            .iter()
+                            .any(|&peak| loud(peak as Sample / i16::MAX as Sample))
+                    })
+                    .collect();
+                // The frames after the last whole chunk have no waveform data, so they are assumed to be audible
+                let partial_end = chunks.len() * SAMPLES_PER_WAVEFORM_CHUNK < self.length;
+
+                let first = match chunks.iter().position(|&loud| loud) {
+                    Some(chunk) => chunk * SAMPLES_PER_WAVEFORM_CHUNK,
+                    None if partial_end => chunks.len() * SAMPLES_PER_WAVEFORM_CHUNK,
+                    None => return None,
+                };
+                let last = if partial_end {
+                    self.length - 1
+                } else {
+                    let chunk = chunks.iter().rposition(|&loud| loud)?;
+                    (chunk + 1) * SAMPLES_PER_WAVEFORM_CHUNK - 1
+                };
+                (first, last)
+            }
+        };
+        Some((OriginalSamples::new(first), OriginalSamples::new(last)))
+    }
+
+    /// The levels of detail used by [`Self::waveform_peaks`].
+    fn peak_levels(&self) -> &PeakLevels {
+        self.peaks.levels.get_or_init(|| {
+            let (block_frames, first) = match &self.data {
+                AudioData::Loaded(data) => (
+                    PEAK_BLOCK_FRAMES,
+                    (0..self.length.div_ceil(PEAK_BLOCK_FRAMES))
+                        .map(|i| {
+                            let start = i * PEAK_BLOCK_FRAMES;
+                            let end = min(start + PEAK_BLOCK_FRAMES, self.length);
+                            Self::data_peaks(data, start, end)
+                        })
+                        .collect(),
+                ),
+                // Reuse the waveform data, since the audio is not in memory
+                AudioData::Streamed { .. } => (
+                    SAMPLES_PER_WAVEFORM_CHUNK,
+                    self.waveform_data
+                        .chunks_exact(2 * self.channels)
+                        .map(|chunk| {
+                            merge_peaks(chunk.chunks_exact(2).map(|pair| {
+                                (
+                                    pair[0] as Sample / i16::MAX as Sample,
+                                    pair[1] as Sample / i16::MAX as Sample,
+                                )
+                            }))
+                        })
+                        .collect(),
+                ),
+            };
+
+            let mut levels: Vec<Vec<_>> = vec![first];
+            while levels.last().unwrap().len() > 1 {
+                let next = levels
+                    .last()
+                    .unwrap()
+                    .chunks(2)
+                    .map(|pair| merge_peaks(pair.iter().copied()))
+                    .collect();
+                levels.push(next);
+            }
+            PeakLevels {
+                block_frames,
+                levels,
+            }
+        })
+    }
+
+    /// The min and max of the frames from `start` to `end`, across all channels.
+    fn data_peaks(data: &[Vec<Sample>], start: usize, end: usize) -> (Sample, Sample) {
+        min_max(
+            data.iter()
+                .flat_map(|channel| channel[start..end].iter().copied()),
+            0.0,
+        )
+    }
+}
+
+/// A decoder positioned somewhere in a file.
+pub(crate) struct OpenedFile {
+    pub format: Box<dyn FormatReader>,
+    pub decoder: Box<dyn Decoder>,
+    pub track_id: u32,
+    /// The frame that the next decoded buffer starts at.
+    position: usize,
+}
+impl OpenedFile {
+    pub fn open(path: &Path) -> Result<Self, ImportError> {
+        let file =
+            Box::new(File::open(path).map_err(|_| ImportError::FileNotFound(path.to_path_buf()))?);
+        let mss = MediaSourceStream::new(file, Default::default());
+
+        let mut hint = Hint::new();
+        if let Some(os_extension) = path.extension() {
+            if let Some(extension) = os_extension.to_str() {
+                hint.with_extension(extension);
+            }
+        }
+
+        let format_options = FormatOptions::default();
+        let metadata_options = MetadataOptions::default();
+        let decoder_options = DecoderOptions::default();
+
+        let probed = symphonia::default::get_probe()
+            .format(&hint, mss, &format_options, &metadata_options)
+            .or(Err(ImportError::UknownFormat))?;
+        let format = probed.format;
+
+        let track = format
+            .default_track()
+            .ok_or_else(|| ImportError::Other("No deafault track".to_owned()))?;
+        let track_id = track.id;
+        if let Some(sample_rate) = track.codec_params.sample_rate {
+            if sample_rate == 0 || sample_rate > MAX_SAMPLE_RATE {
+                return Err(ImportError::UnsupportedSampleRate(sample_rate));
+            }
+        }
+        let decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &decoder_options)
+            .map_err(|_| match track.codec_params.bits_per_sample {
+                Some(bits) if !matches!(bits, 8 | 16 | 24 | 32 | 64) => {
+                    ImportError::UnsupportedBitDepth(bits)
+                }
+                _ => ImportError::UknownFormat,
+            })?;
+
+        Ok(Self {
+            format,
+            decoder,
+            track_id,
+            position: 0,
+        })
+    }
+
+    /// The length of the track in frames, if the file states it.
+    pub fn total_frames(&self) -> Option<u64> {
+        self.format
+            .tracks()
             .iter()
-            .all(|channel| channel.len() == self.audio_data[0].len()));
+            .find(|track| track.id == self.track_id)
+            .and_then(|track| track.codec_params.n_frames)
+    }
 
-        self.audio_data[0].len()
+    /// Decode the next packet of the track.
+    ///
+    /// Returns `Ok(None)` when the end of the file is reached.
+    pub fn next(&mut self) -> Result<Option<AudioBufferRef<'_>>, ImportError> {
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(SymphoniaError::IoError(_)) => return Ok(None),
+                Err(e) => return Err(self.decode_error(e)),
+            };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+            let frame = self.position;
+            return match self.decoder.decode(&packet) {
+                Ok(buffer) => {
+                    self.position += buffer.frames();
+                    Ok(Some(buffer))
+                }
+                Err(e) => Err(ImportError::Decode {
+                    frame,
+                    message: format!("{}", e),
+                }),
+            };
+        }
+    }
+
+    /// Seek to the frame at `ts`, returning the timestamp of the first frame that will actually be decoded next.
+    pub fn seek(&mut self, ts: u64) -> Result<u64, ImportError> {
+        let seeked_to = self
+            .format
+            .seek(
+                SeekMode::Accurate,
+                SeekTo::TimeStamp {
+                    ts,
+                    track_id: self.track_id,
+                },
+            )
+            .map_err(|e| ImportError::Other(format!("{}", e)))?;
+        self.decoder.reset();
+        self.position = seeked_to.actual_ts as usize;
+        Ok(seeked_to.actual_ts)
+    }
+
+    fn decode_error(&self, error: SymphoniaError) -> ImportError {
+        ImportError::Decode {
+            frame: self.position,
+            message: format!("{}", error),
+        }
+    }
+}
+
+/// Decode the entire file, passing each decoded buffer to `f`.
+fn decode<F>(path: &Path, progress: &DecodeProgress, mut f: F) -> Result<(), ImportError>
+where
+    F: FnMut(AudioBufferRef) -> Result<(), ImportError>,
+{
+    let mut file = OpenedFile::open(path)?;
+    if let Some(total_frames) = file.total_frames() {
+        progress
+            .total_frames
+            .store(total_frames as usize, Ordering::Relaxed);
+    }
+    while let Some(buffer) = file.next()? {
+        let frames = buffer.frames();
+        f(buffer)?;
+        progress.decoded_frames.fetch_add(frames, Ordering::Relaxed);
+    }
+    Ok(())
+}
+
+/// Append the contents of `buffer_ref` to the channel buffers in `data`.
+pub(crate) fn extend_from_buffer(data: &mut [Vec<Sample>], buffer_ref: AudioBufferRef) {
+    // Bruh
+    use AudioBufferRef as A;
+    match buffer_ref {
+        A::U8(buffer) => extend(data, buffer),
+        A::S8(buffer) => extend(data, buffer),
+        A::U16(buffer) => extend(data, buffer),
+        A::U24(buffer) => extend(data, buffer),
+        A::U32(buffer) => extend(data, buffer),
+        A::S16(buffer) => extend(data, buffer),
+        A::S24(buffer) => extend(data, buffer),
+        A::S32(buffer) => extend(data, buffer),
+        A::F32(buffer) => extend(data, buffer),
+        A::F64(buffer) => extend(data, buffer),
+    };
+
+    fn extend<S>(data: &mut [Vec<Sample>], buffer: Cow<AudioBuffer<S>>)
+    where
+        S: SymphoniaSample + IntoSample<Sample>,
+    {
+        for (chan_i, output) in data.iter_mut().enumerate() {
+            let received = buffer.chan(chan_i);
+            for &sample in received {
+                output.push(sample.into_sample());
+            }
+        }
     }
 }
 impl Debug for StoredAudioClip {
@@ -198,6 +752,7 @@ impl Debug for StoredAudioClip {
             .field("sample_rate", &self.sample_rate)
             .field("channels", &self.channels())
             .field("length", &self.length())
+            .field("streamed", &self.streaming_source().is_some())
             .finish_non_exhaustive()
     }
 }
@@ -206,7 +761,19 @@ impl Debug for StoredAudioClip {
 pub enum ImportError {
     FileNotFound(PathBuf),
     UknownFormat,
+    /// The file has more channels than the [`Downmix::Matrix`] it was imported with.
     TooManyChannels,
+    /// The sample rate of the file is zero, or above [`MAX_SAMPLE_RATE`].
+    UnsupportedSampleRate(u32),
+    /// The format is supported, but not at this number of bits per sample.
+    UnsupportedBitDepth(u32),
+    /// The file could not be decoded from the given frame and onwards.
+    Decode {
+        frame: usize,
+        message: String,
+    },
+    /// The file contains no audio.
+    Empty,
     Other(String),
 }
 impl Display for ImportError {
@@ -220,8 +787,20 @@ impl Display for ImportError {
             }
             Self::UknownFormat => "File format not supported".to_owned(),
             Self::TooManyChannels => {
-                "Files with more than 2 channels are not currently supported".to_owned()
+                "File has more channels than the downmix matrix covers".to_owned()
+            }
+            Self::UnsupportedSampleRate(sample_rate) => {
+                format!("Sample rate of {sample_rate} Hz is not supported")
+            }
+            Self::UnsupportedBitDepth(bits) => {
+                format!("Bit depth of {bits} bits is not supported")
+            }
+            Self::Decode { frame, message } => {
+                format!(
+                    "File could not be decoded from frame {frame}. Failed with error: {message}"
+                )
             }
+            Self::Empty => "File contains no audio".to_owned(),
             Self::Other(msg) => {
                 format!("File could not be imported. Failed with error: {}", msg)
             }
@@ -244,9 +823,9 @@ mod tests {
         assert_eq!(ac.length(), 1_322_978);
 
         // These should be 1.0 and -1.0 exactly, but sample conversion skews that a little bit
-        let first_left_sample = ac.audio_data[0][0];
+        let first_left_sample = ac.audio_data().unwrap()[0][0];
         assert!((0.999..=1.001).contains(&first_left_sample));
-        let first_right_sample = ac.audio_data[1][0];
+        let first_right_sample = ac.audio_data().unwrap()[1][0];
         assert!((-1.001..=-0.999).contains(&first_right_sample));
     }
     fn test_lossy(ac: StoredAudioClip, sample_rate: u32) {
@@ -258,6 +837,103 @@ mod tests {
         assert!(ac.length() < 1_330_000);
     }
 
+    #[test]
+    fn waveform_peaks() {
+        let path = test_file_path("44100 16-bit.wav");
+        let loaded = StoredAudioClip::import(StoredAudioClipKey(0), &path).unwrap();
+        let streamed =
+            StoredAudioClip::import_streaming(StoredAudioClipKey(0), &path, 10_000).unwrap();
+
+        // The clip is a full scale square wave, so every bin spanning a period reaches both extremes
+        for ac in [&loaded, &streamed] {
+            for bins in [1, 10, 1000] {
+                let peaks = ac.waveform_peaks(bins);
+                assert_eq!(peaks.len(), bins);
+                for (min, max) in peaks {
+                    assert!(
+                        min < -0.99 && max > 0.99,
+                        "Bins: {bins}, peak: {min}, {max}"
+                    );
+                }
+            }
+        }
+
+        // Repeated calls are served from the cache
+        assert_eq!(loaded.waveform_peaks(10), loaded.waveform_peaks(10));
+
+        // Zoomed in all the way, each bin is a single frame
+        let data = loaded.audio_data().unwrap();
+        let peaks = loaded.waveform_peaks(loaded.length());
+        for i in [0, 1000, loaded.length() - 1] {
+            let (min, max) = peaks[i];
+            let (left, right) = (data[0][i], data[1][i]);
+            assert_eq!(min, left.min(right).min(0.0));
+            assert_eq!(max, left.max(right).max(0.0));
+        }
+
+        assert!(loaded.waveform_peaks(0).is_empty());
+    }
+
+    #[test]
+    fn import_streaming_matches_import() {
+        let path = test_file_path("44100 16-bit.wav");
+        let loaded = StoredAudioClip::import(StoredAudioClipKey(0), &path).unwrap();
+        let streamed =
+            StoredAudioClip::import_streaming(StoredAudioClipKey(0), &path, 10_000).unwrap();
+
+        assert_eq!(streamed.channels(), loaded.channels());
+        assert_eq!(streamed.sample_rate(), loaded.sample_rate());
+        assert_eq!(streamed.length(), loaded.length());
+        assert_eq!(streamed.waveform_data().len(), loaded.waveform_data().len());
+        assert!(streamed.audio_data().is_none());
+        assert_eq!(streamed.streaming_source(), Some((path.as_path(), 10_000)));
+        assert_eq!(streamed.peak(), loaded.peak());
+        assert_eq!(streamed.rms(), loaded.rms());
+    }
+
+    #[test]
+    fn peak_and_rms() {
+        let path = test_file_path("44100 16-bit.wav");
+        let ac = StoredAudioClip::import(StoredAudioClipKey(0), &path).unwrap();
+
+        // The clip is mostly a full scale square wave, so the RMS is nearly as high as the peak
+        for peak in ac.peak() {
+            assert!((0.999..=1.001).contains(&peak), "Peak: {peak}");
+        }
+        for rms in ac.rms() {
+            assert!((0.98..=0.99).contains(&rms), "RMS: {rms}");
+        }
+    }
+
+    #[test]
+    fn levels_cached() {
+        let path = test_file_path("44100 16-bit.wav");
+        let mut ac = StoredAudioClip::import(StoredAudioClipKey(0), &path).unwrap();
+
+        assert!(ac.levels.levels.get().is_none());
+        ac.peak();
+        assert!(ac.levels.levels.get().is_some());
+
+        // Whatever is cached is returned, instead of being computed again
+        ac.levels = ClipLevels {
+            levels: OnceLock::from(([0.5; CHANNELS], [0.25; CHANNELS])),
+        };
+        assert_eq!(ac.peak(), [0.5; CHANNELS]);
+        assert_eq!(ac.rms(), [0.25; CHANNELS]);
+    }
+
+    #[test]
+    fn levels_of_nothing() {
+        assert_eq!(
+            channel_levels(&[], &[], 0),
+            ([0.0; CHANNELS], [0.0; CHANNELS])
+        );
+        assert_eq!(
+            channel_levels(&[0.0], &[0.0], 0),
+            ([0.0; CHANNELS], [0.0; CHANNELS])
+        );
+    }
+
     #[test]
     fn import_wav_22050_16_bit() {
         let ac =
@@ -446,4 +1122,139 @@ mod tests {
         );
         assert_eq!(result, Err(ImportError::UknownFormat));
     }
+
+    /// Write a stereo wav file with `frames` frames of silence to a temporary path.
+    fn temp_wav(name: &str, sample_rate: u32, frames: usize) -> PathBuf {
+        let path = std::env::temp_dir().join(name);
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for _ in 0..2 * frames {
+            writer.write_sample(0_i16).unwrap();
+        }
+        writer.finalize().unwrap();
+        path
+    }
+
+    #[test]
+    fn empty_file() {
+        let path = temp_wav("adae empty_file.wav", 48_000, 0);
+        let loaded = StoredAudioClip::import(StoredAudioClipKey(0), &path);
+        let streamed = StoredAudioClip::import_streaming(StoredAudioClipKey(0), &path, 1000);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, Err(ImportError::Empty));
+        assert_eq!(streamed, Err(ImportError::Empty));
+    }
+
+    /// Write a 5.1 wav file, where each channel holds a constant value.
+    fn temp_surround_wav(name: &str, values: [f32; 6]) -> PathBuf {
+        let path = std::env::temp_dir().join(name);
+        let spec = hound::WavSpec {
+            channels: 6,
+            sample_rate: 48_000,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for _ in 0..2000 {
+            for value in values {
+                writer.write_sample(value).unwrap();
+            }
+        }
+        writer.finalize().unwrap();
+        path
+    }
+
+    #[test]
+    fn downmix_surround() {
+        let path = temp_surround_wav("adae downmix_surround.wav", [0.1, 0.2, 0.3, 0.4, 0.5, 0.6]);
+        let itu = StoredAudioClip::import(StoredAudioClipKey(0), &path).unwrap();
+        let matrix = StoredAudioClip::import_with(
+            StoredAudioClipKey(0),
+            &path,
+            &Downmix::Matrix(vec![[1.0, 0.0]; 6]),
+            &DecodeProgress::default(),
+        )
+        .unwrap();
+        let streamed = StoredAudioClip::import_streaming(StoredAudioClipKey(0), &path, 1000);
+        std::fs::remove_file(&path).unwrap();
+
+        let k = std::f32::consts::FRAC_1_SQRT_2 as Sample;
+        let data = itu.audio_data().unwrap();
+        assert_eq!(itu.channels(), 2);
+        assert!((data[0][100] - (0.1 + k * 0.3 + k * 0.5)).abs() < 1e-6);
+        assert!((data[1][100] - (0.2 + k * 0.3 + k * 0.6)).abs() < 1e-6);
+
+        let data = matrix.audio_data().unwrap();
+        assert!((data[0][100] - 2.1).abs() < 1e-6);
+        assert_eq!(data[1][100], 0.0);
+
+        assert_eq!(streamed.unwrap().channels(), 2);
+    }
+
+    #[test]
+    fn silence_bounds() {
+        let path = std::env::temp_dir().join("adae silence_bounds.wav");
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 48_000,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for i in 0..8192 {
+            // -20 dBFS, surrounded by -80 dBFS
+            let sample = if (2048..5000).contains(&i) {
+                0.1
+            } else {
+                0.0001
+            };
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+        let loaded = StoredAudioClip::import(StoredAudioClipKey(0), &path).unwrap();
+        let streamed =
+            StoredAudioClip::import_streaming(StoredAudioClipKey(0), &path, 1000).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let bounds = |first, last| Some((OriginalSamples::new(first), OriginalSamples::new(last)));
+        assert_eq!(loaded.silence_bounds(-40.0), bounds(2048, 4999));
+        assert_eq!(loaded.silence_bounds(-100.0), bounds(0, 8191));
+        assert_eq!(loaded.silence_bounds(-10.0), None);
+        // Widened to whole waveform chunks
+        assert_eq!(streamed.silence_bounds(-40.0), bounds(2048, 5119));
+        assert_eq!(streamed.silence_bounds(-10.0), None);
+    }
+
+    #[test]
+    fn unsupported_sample_rate() {
+        let path = temp_wav("adae unsupported_sample_rate.wav", 1_000_000, 2000);
+        let result = StoredAudioClip::import(StoredAudioClipKey(0), &path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(result, Err(ImportError::UnsupportedSampleRate(1_000_000)));
+    }
+
+    #[test]
+    fn progress() {
+        let progress = DecodeProgress::default();
+        assert_eq!(progress.decoded_frames(), 0);
+        assert_eq!(progress.total_frames(), None);
+
+        let ac = StoredAudioClip::import_with(
+            StoredAudioClipKey(0),
+            &test_file_path("44100 16-bit.wav"),
+            &Downmix::default(),
+            &progress,
+        )
+        .unwrap();
+
+        assert_eq!(progress.decoded_frames(), ac.length());
+        assert_eq!(progress.total_frames(), Some(ac.length()));
+    }
 }
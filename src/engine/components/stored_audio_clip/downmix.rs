@@ -0,0 +1,148 @@
+use serde::{Deserialize, Serialize};
+use std::f32::consts::FRAC_1_SQRT_2;
+use symphonia::core::audio::{AudioBufferRef, Channels};
+
+use super::{extend_from_buffer, ImportError};
+use crate::engine::Sample;
+
+/// How clips with more than two channels are mixed down to stereo when imported.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub enum Downmix {
+    /// The downmix of ITU-R BS.775, based on the positions of the channels given by the file.
+    ///
+    /// Centre and surround channels are added to the front at -3 dB, and the LFE channel is dropped.
+    #[default]
+    Itu,
+    /// For each channel of the file in order, its gain into the left and right output channels.
+    ///
+    /// Files with more channels than this are rejected with [`ImportError::TooManyChannels`].
+    Matrix(Vec<[Sample; 2]>),
+}
+impl Downmix {
+    /// The gains of each of the `channels` into the left and right output channels.
+    fn coefficients(&self, channels: Channels) -> Result<Vec<[Sample; 2]>, ImportError> {
+        match self {
+            Self::Itu => Ok(channels.iter().map(itu_coefficients).collect()),
+            Self::Matrix(matrix) => {
+                if matrix.len() < channels.count() {
+                    return Err(ImportError::TooManyChannels);
+                }
+                Ok(matrix[..channels.count()].to_vec())
+            }
+        }
+    }
+}
+
+fn itu_coefficients(channel: Channels) -> [Sample; 2] {
+    const HALF_POWER: Sample = FRAC_1_SQRT_2 as Sample;
+    match channel {
+        Channels::FRONT_LEFT | Channels::FRONT_LEFT_CENTRE | Channels::FRONT_LEFT_WIDE => {
+            [1.0, 0.0]
+        }
+        Channels::FRONT_RIGHT | Channels::FRONT_RIGHT_CENTRE | Channels::FRONT_RIGHT_WIDE => {
+            [0.0, 1.0]
+        }
+        Channels::LFE1 | Channels::LFE2 => [0.0, 0.0],
+        Channels::REAR_LEFT | Channels::SIDE_LEFT | Channels::REAR_LEFT_CENTRE => [HALF_POWER, 0.0],
+        Channels::REAR_RIGHT | Channels::SIDE_RIGHT | Channels::REAR_RIGHT_CENTRE => {
+            [0.0, HALF_POWER]
+        }
+        // Centre, and any height channels
+        _ => [HALF_POWER, HALF_POWER],
+    }
+}
+
+/// Reads decoded buffers into at most two channels, mixing them down if the file has more.
+pub(crate) struct Downmixer {
+    /// `None` if the file has at most two channels, and is read as is.
+    coefficients: Option<Vec<[Sample; 2]>>,
+    /// The channels of the most recent buffer, before mixing them down.
+    decoded: Vec<Vec<Sample>>,
+}
+impl Downmixer {
+    pub fn new(downmix: &Downmix, channels: Channels) -> Result<Self, ImportError> {
+        let count = channels.count();
+        let coefficients = if count > 2 {
+            Some(downmix.coefficients(channels)?)
+        } else {
+            None
+        };
+        Ok(Self {
+            coefficients,
+            decoded: vec![Vec::new(); count],
+        })
+    }
+
+    /// The number of channels that buffers are read into.
+    pub fn channels(&self) -> usize {
+        match self.coefficients {
+            Some(_) => 2,
+            None => self.decoded.len(),
+        }
+    }
+
+    /// Append the contents of `buffer_ref` to the [`Self::channels`] buffers in `data`.
+    pub fn extend(&mut self, data: &mut [Vec<Sample>], buffer_ref: AudioBufferRef) {
+        let Some(coefficients) = &self.coefficients else {
+            extend_from_buffer(data, buffer_ref);
+            return;
+        };
+
+        for channel in self.decoded.iter_mut() {
+            channel.clear();
+        }
+        extend_from_buffer(&mut self.decoded, buffer_ref);
+
+        let [left, right] = data else {
+            unreachable!("Downmixing to {} channels", data.len());
+        };
+        for i in 0..self.decoded[0].len() {
+            let (mut l, mut r) = (0.0, 0.0);
+            for (channel, [left_gain, right_gain]) in self.decoded.iter().zip(coefficients) {
+                l += left_gain * channel[i];
+                r += right_gain * channel[i];
+            }
+            left.push(l);
+            right.push(r);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn itu_five_point_one() {
+        let channels = Channels::FRONT_LEFT
+            | Channels::FRONT_RIGHT
+            | Channels::FRONT_CENTRE
+            | Channels::LFE1
+            | Channels::REAR_LEFT
+            | Channels::REAR_RIGHT;
+
+        let coefficients = Downmix::Itu.coefficients(channels).unwrap();
+
+        let k = FRAC_1_SQRT_2 as Sample;
+        assert_eq!(
+            coefficients,
+            [
+                [1.0, 0.0],
+                [0.0, 1.0],
+                [k, k],
+                [0.0, 0.0],
+                [k, 0.0],
+                [0.0, k]
+            ]
+        );
+    }
+
+    #[test]
+    fn matrix_too_small() {
+        let channels = Channels::FRONT_LEFT | Channels::FRONT_RIGHT | Channels::FRONT_CENTRE;
+
+        let result = Downmix::Matrix(vec![[1.0, 0.0], [0.0, 1.0]]).coefficients(channels);
+
+        assert_eq!(result, Err(ImportError::TooManyChannels));
+    }
+}
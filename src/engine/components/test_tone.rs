@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+use std::f64::consts::TAU;
+
+use crate::engine::{Sample, CHANNELS};
+
+/// The shape of a [`TestTone`].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Waveform {
+    #[default]
+    Sine,
+    /// Rising from -1 to 1 over each period.
+    Saw,
+    Square,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct TestToneSettings {
+    /// In Hz, capped to half the sample rate.
+    pub frequency: f32,
+    pub waveform: Waveform,
+    /// Linear gain of the peaks, where `1.0` is full scale.
+    pub amplitude: f32,
+}
+impl Default for TestToneSettings {
+    /// A sine at 1 kHz, peaking at -18 dBFS.
+    fn default() -> Self {
+        Self {
+            frequency: 1000.0,
+            waveform: Waveform::Sine,
+            amplitude: 0.125,
+        }
+    }
+}
+
+/// Generates a periodic tone, identical in all channels.
+///
+/// The phase is kept when the settings change, so the tone can be adjusted without clicks from restarting it.
+#[derive(Debug, Clone, Copy)]
+pub struct TestTone {
+    settings: TestToneSettings,
+    /// Position within the current period, between 0 and 1.
+    phase: f64,
+}
+impl TestTone {
+    pub fn new(settings: TestToneSettings) -> Self {
+        Self {
+            settings,
+            phase: 0.0,
+        }
+    }
+
+    pub fn set_settings(&mut self, settings: TestToneSettings) {
+        self.settings = settings;
+    }
+
+    /// Add the next `buffer.len() / CHANNELS` frames of the tone on top of the interleaved `buffer`.
+    pub fn add_to(&mut self, sample_rate: u32, buffer: &mut [Sample]) {
+        let TestToneSettings {
+            frequency,
+            waveform,
+            amplitude,
+        } = self.settings;
+        let nyquist = sample_rate as f64 / 2.0;
+        let step = (frequency as f64).clamp(0.0, nyquist) / sample_rate as f64;
+
+        for frame in buffer.chunks_exact_mut(CHANNELS) {
+            let value = match waveform {
+                Waveform::Sine => (self.phase * TAU).sin(),
+                Waveform::Saw => 2.0 * self.phase - 1.0,
+                Waveform::Square if self.phase < 0.5 => 1.0,
+                Waveform::Square => -1.0,
+            };
+            let sample = amplitude as Sample * value as Sample;
+            for s in frame {
+                *s += sample;
+            }
+
+            self.phase = (self.phase + step).fract();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(buffer: &[Sample]) -> Vec<Sample> {
+        buffer
+            .chunks_exact(CHANNELS)
+            .map(|frame| frame[0])
+            .collect()
+    }
+
+    #[test]
+    fn waveforms() {
+        // Four frames per period
+        let settings = |waveform| TestToneSettings {
+            frequency: 12_000.0,
+            waveform,
+            amplitude: 0.5,
+        };
+
+        let mut buffer = vec![0.0; 4 * CHANNELS];
+        let mut tone = TestTone::new(settings(Waveform::Square));
+        no_heap! {{
+            tone.add_to(48_000, &mut buffer);
+        }}
+        assert_eq!(frames(&buffer), [0.5, 0.5, -0.5, -0.5]);
+
+        let mut buffer = vec![0.0; 4 * CHANNELS];
+        let mut tone = TestTone::new(settings(Waveform::Saw));
+        tone.add_to(48_000, &mut buffer);
+        assert_eq!(frames(&buffer), [-0.5, -0.25, 0.0, 0.25]);
+
+        let mut buffer = vec![0.0; 4 * CHANNELS];
+        let mut tone = TestTone::new(settings(Waveform::Sine));
+        tone.add_to(48_000, &mut buffer);
+        for (sample, expected) in frames(&buffer).into_iter().zip([0.0, 0.5, 0.0, -0.5]) {
+            assert!((sample - expected).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn added_to_buffer_and_continuous() {
+        let mut tone = TestTone::new(TestToneSettings {
+            frequency: 12_000.0,
+            waveform: Waveform::Square,
+            amplitude: 1.0,
+        });
+
+        let mut buffer = vec![0.25; 2 * CHANNELS];
+        tone.add_to(48_000, &mut buffer);
+        assert_eq!(frames(&buffer), [1.25, 1.25]);
+        assert!(buffer
+            .chunks_exact(CHANNELS)
+            .all(|f| f.iter().all(|&s| s == f[0])));
+
+        // Picks up where the last buffer left off
+        let mut buffer = vec![0.0; 2 * CHANNELS];
+        tone.add_to(48_000, &mut buffer);
+        assert_eq!(frames(&buffer), [-1.0, -1.0]);
+    }
+}
@@ -1,30 +1,40 @@
 mod audio_clip;
+mod marker;
+mod stretcher;
+mod tempo_map;
+mod time_signature;
 mod timestamp;
 mod track;
 
 use serde::{Deserialize, Serialize};
 use std::{
-    cmp::max,
+    cmp::{max, min, Reverse},
     collections::{HashMap, HashSet},
     error::Error,
     fmt::{Debug, Display},
     iter::zip,
-    path::Path,
+    path::{Path, PathBuf},
     sync::{
         atomic::{AtomicBool, AtomicUsize, Ordering},
         Arc,
     },
+    time::Duration,
 };
 
 use super::{
-    audio_clip_reader::OriginalSamples,
+    audio_clip_reader::{AudioClipReader, OriginalSamples},
     audio_clip_store::{
-        AudioClipStore, AudioClipStoreState, ImportError, InvalidStoredAudioClipError,
+        AudioClipStore, AudioClipStoreState, DecodedClips, ImportError, ImportHandle,
+        InvalidStoredAudioClipError,
     },
-    stored_audio_clip::{StoredAudioClip, StoredAudioClipKey},
+    metronome::{metronome, Metronome, MetronomeProcessor},
+    noise::NoiseSettings,
+    stored_audio_clip::{Downmix, StoredAudioClip, StoredAudioClipKey},
+    test_tone::TestToneSettings,
     track::MixerTrackKey,
 };
 use crate::engine::{
+    config::ResampleQuality,
     info::Info,
     utils::{
         dropper::DBox,
@@ -33,41 +43,95 @@ use crate::engine::{
         remote_push::{
             RemotePushHashMapEvent, RemotePushable, RemotePushedHashMap, RemotePusherHashMap,
         },
-        ringbuffer::{self, ringbuffer},
+        ringbuffer::{self, ringbuffer, EventQueueStats},
     },
-    Sample, CHANNELS,
+    Sample, CHANNELS, MAX_EVENTS_PER_POLL_DEFAULT,
+};
+use audio_clip::{
+    envelope_from, envelope_until, stretch_samples, unstretch_samples, AudioClipProcessor,
+};
+pub use audio_clip::{AudioClip, AudioClipKey, AudioClipState, EndBehavior};
+use marker::Markers;
+pub use marker::{InvalidMarkerError, Marker, MarkerKey, MarkerOverflowError};
+pub use stretcher::{MAX_PITCH, MAX_STRETCH, MIN_STRETCH};
+pub use tempo_map::{InvalidTempoChangeError, Tempo, TempoMap};
+pub use time_signature::{
+    GridDivision, InvalidTimeSignatureChangeError, InvalidTimeSignatureError, TimeSignature,
+    TimeSignatureMap, MAX_DENOMINATOR,
 };
-use audio_clip::AudioClipProcessor;
-pub use audio_clip::{AudioClip, AudioClipKey, AudioClipState};
 pub use timestamp::Timestamp;
 use track::TimelineTrack;
-pub use track::{TimelineTrackKey, TimelineTrackProcessor, TimelineTrackState};
+pub use track::{OverlapPolicy, TimelineTrackKey, TimelineTrackProcessor, TimelineTrackState};
 
+#[cfg(test)]
 pub(crate) fn timeline(
     state: &TimelineState,
     sample_rate: u32,
     max_buffer_size: usize,
+    resample_quality: ResampleQuality,
+) -> (Timeline, TimelineProcessor, Vec<ImportError>) {
+    timeline_with_decoded(
+        state,
+        sample_rate,
+        max_buffer_size,
+        resample_quality,
+        &DecodedClips::new(),
+    )
+}
+
+/// Like [`timeline`], but reusing the clips in `decoded` instead of importing them again,
+/// see [`AudioClipStore::with_decoded`].
+pub(crate) fn timeline_with_decoded(
+    state: &TimelineState,
+    sample_rate: u32,
+    max_buffer_size: usize,
+    resample_quality: ResampleQuality,
+    decoded: &DecodedClips,
 ) -> (Timeline, TimelineProcessor, Vec<ImportError>) {
     let TimelineState {
         bpm_cents,
+        tempo_changes,
+        time_signature,
+        time_signature_changes,
+        loop_region,
+        markers,
         audio_clip_store: store_state,
         tracks: track_states,
     } = state;
 
+    let tempo_map = Arc::new(TempoMap::from_changes(*bpm_cents, tempo_changes));
+    let time_signatures = Arc::new(TimeSignatureMap::from_changes(
+        *time_signature,
+        time_signature_changes,
+    ));
+
     let playing1 = Arc::new(AtomicBool::new(false));
     let playing2 = Arc::clone(&playing1);
 
+    let counting_in1 = Arc::new(AtomicBool::new(false));
+    let counting_in2 = Arc::clone(&counting_in1);
+
     let position1 = Arc::new(AtomicUsize::new(0));
     let position2 = Arc::clone(&position1);
 
-    let (clip_store, import_errors) =
-        AudioClipStore::new(store_state, sample_rate, max_buffer_size);
+    let (clip_store, import_errors) = AudioClipStore::with_decoded(
+        store_state,
+        sample_rate,
+        max_buffer_size,
+        resample_quality,
+        decoded,
+    );
 
     let tracks = HashMap::from_iter(track_states.iter().map(|track_state| {
         (
             track_state.key,
             TimelineTrack {
                 output_track: track_state.output_track,
+                name: track_state.name.clone(),
+                color: track_state.color,
+                overlap_policy: track_state.overlap_policy,
+                test_tone: track_state.test_tone,
+                noise: track_state.noise,
                 clips: HashMap::from_iter(track_state.clips.iter().map(|clip_state| {
                     (
                         clip_state.key,
@@ -76,6 +140,18 @@ pub(crate) fn timeline(
                             start: clip_state.start,
                             set_length: clip_state.length,
                             start_offset: clip_state.start_offset,
+                            gain: clip_state.gain,
+                            gain_envelope: clip_state.gain_envelope.clone(),
+                            fade_in: clip_state.fade_in,
+                            fade_out: clip_state.fade_out,
+                            reversed: clip_state.reversed,
+                            stretch: clip_state.stretch,
+                            pitch: clip_state.pitch,
+                            crossfade: clip_state.crossfade,
+                            active: clip_state.active,
+                            end_behavior: clip_state.end_behavior,
+                            name: clip_state.name.clone(),
+                            color: clip_state.color,
                             reader: clip_store
                                 .reader(clip_state.inner)
                                 .expect("An invalid audio clip was referenced"),
@@ -91,18 +167,31 @@ pub(crate) fn timeline(
             state.output_track,
             Arc::clone(&position1),
             sample_rate,
-            *bpm_cents,
+            Arc::clone(&tempo_map),
         );
+        track.set_layered(state.overlap_policy == OverlapPolicy::Layer);
+        track.set_test_tone(state.test_tone);
+        track.set_noise(state.noise);
 
         for clip_state in state.clips.iter() {
-            track.insert_clip(Box::new(TreeNode::new(AudioClipProcessor::new(
-                clip_state.start,
-                clip_state.length,
-                clip_state.start_offset,
-                clip_store
-                    .reader(clip_state.inner)
-                    .expect("An invalid audio clip was referenced"),
-            ))));
+            track.insert_clip(Box::new(TreeNode::new(
+                AudioClipProcessor::new(
+                    clip_state.key,
+                    clip_state.start,
+                    clip_state.length,
+                    clip_state.start_offset,
+                    clip_state.gain,
+                    clip_store
+                        .reader_pitched(clip_state.inner, clip_state.pitch)
+                        .expect("An invalid audio clip was referenced"),
+                )
+                .with_gain_envelope(clip_state.gain_envelope.clone())
+                .with_fades(clip_state.fade_in, clip_state.fade_out)
+                .with_reversed(clip_state.reversed)
+                .with_stretch(clip_state.stretch)
+                .with_active(clip_state.active)
+                .with_end_behavior(clip_state.end_behavior),
+            )));
         }
         (state.key, DBox::new(track))
     }))
@@ -121,42 +210,83 @@ pub(crate) fn timeline(
             .map(move |&clip_key| (clip_key, track_key))
     }));
 
+    let (metronome, metronome_processor) = metronome(sample_rate, max_buffer_size);
+
     let (event_sender, event_receiver) = ringbuffer();
 
     (
         Timeline {
             sample_rate,
-            bpm_cents: *bpm_cents,
+            tempo_map: Arc::clone(&tempo_map),
+            time_signatures: Arc::clone(&time_signatures),
+            loop_region: *loop_region,
+            soloed_clip: None,
+            markers: Markers::new(markers),
 
             track_key_generator,
             clip_key_generator,
             clip_to_track,
 
             playing: playing1,
+            counting_in: counting_in1,
             position: position1,
 
             clip_store,
             tracks,
             track_processors: tracks_pusher,
+            metronome,
 
             event_sender,
         },
         TimelineProcessor {
             sample_rate,
-            bpm_cents: *bpm_cents,
+            tempo_map: DBox::new(tempo_map),
+            time_signatures: DBox::new(time_signatures),
+            loop_region: *loop_region,
+            soloed_clip: None,
 
             playing: playing2,
+            was_playing: false,
+            count_in: None,
+            counting_in: counting_in2,
             position: position2,
+            preview: None,
             tracks: tracks_pushed,
+            metronome: metronome_processor,
 
             event_receiver,
+            max_events_per_poll: MAX_EVENTS_PER_POLL_DEFAULT,
         },
         import_errors,
     )
 }
 
+/// A crop of the start or end of a clip, as planned on the main thread.
+#[derive(Debug, Clone, Copy)]
+struct Crop {
+    track_key: TimelineTrackKey,
+    clip_key: AudioClipKey,
+    old_start: Timestamp,
+    old_length: Option<Timestamp>,
+    old_start_offset: OriginalSamples,
+    new_start: Timestamp,
+    new_length: Timestamp,
+    new_start_offset: OriginalSamples,
+}
+
+/// A [`Crop`] of the start of a clip,
+/// with room for the clip while it is out of the tree, so the starts never collide while cropping.
+struct StartCrop {
+    crop: Crop,
+    clip: Option<Box<TreeNode<AudioClipProcessor>>>,
+}
+
 enum Event {
     JumpTo(Timestamp),
+    Preview {
+        position: Timestamp,
+        samples: usize,
+    },
     Track(RemotePushHashMapEvent<TimelineTrackKey, DBox<TimelineTrackProcessor>>),
     AddClip {
         track_key: TimelineTrackKey,
@@ -179,6 +309,10 @@ enum Event {
         old_start: Timestamp,
         new_start: Timestamp,
     },
+    /// Moves that must be applied in the given order, so no clip is moved onto the start of another one.
+    MoveAudioClips {
+        moves: DBox<Vec<(TimelineTrackKey, Timestamp, Timestamp)>>,
+    },
     MoveAudioClipToTrack {
         old_track_key: TimelineTrackKey,
         new_track_key: TimelineTrackKey,
@@ -196,30 +330,202 @@ enum Event {
         track_key: TimelineTrackKey,
         clip_start: Timestamp,
         new_length: Timestamp,
+        new_start_offset: OriginalSamples,
+    },
+    CropAudioClipsStart {
+        crops: DBox<Vec<StartCrop>>,
+    },
+    CropAudioClipsEnd {
+        crops: DBox<Vec<Crop>>,
+    },
+    SetAudioClipGain {
+        track_key: TimelineTrackKey,
+        clip_start: Timestamp,
+        gain: f32,
+    },
+    SetAudioClipGainEnvelope {
+        track_key: TimelineTrackKey,
+        clip_start: Timestamp,
+        gain_envelope: DBox<Vec<(usize, f32)>>,
     },
+    SetAudioClipFades {
+        track_key: TimelineTrackKey,
+        clip_start: Timestamp,
+        fade_in: Timestamp,
+        fade_out: Timestamp,
+    },
+    SetAudioClipReversed {
+        track_key: TimelineTrackKey,
+        clip_start: Timestamp,
+        reversed: bool,
+    },
+    SetAudioClipActive {
+        track_key: TimelineTrackKey,
+        clip_start: Timestamp,
+        active: bool,
+    },
+    SetAudioClipEndBehavior {
+        track_key: TimelineTrackKey,
+        clip_start: Timestamp,
+        end_behavior: EndBehavior,
+    },
+    SetAudioClipStretch {
+        track_key: TimelineTrackKey,
+        clip_start: Timestamp,
+        stretch: f32,
+        new_length: Option<Timestamp>,
+    },
+    /// Replaces the reader of the clip with one of a new pitch.
+    SetAudioClipReader {
+        track_key: TimelineTrackKey,
+        clip_start: Timestamp,
+        reader: DBox<AudioClipReader>,
+    },
+    SetTrackLayered {
+        track_key: TimelineTrackKey,
+        layered: bool,
+    },
+    SetTrackTestTone {
+        track_key: TimelineTrackKey,
+        settings: Option<TestToneSettings>,
+    },
+    SetTrackNoise {
+        track_key: TimelineTrackKey,
+        settings: Option<NoiseSettings>,
+    },
+    SetTempoMap(DBox<Arc<TempoMap>>),
+    SetTimeSignatures(DBox<Arc<TimeSignatureMap>>),
+    SetLoopRegion(Option<(Timestamp, Timestamp)>),
+    SoloAudioClip(Option<AudioClipKey>),
 }
 
 pub(crate) struct Timeline {
     sample_rate: u32,
-    bpm_cents: u16,
+    tempo_map: Arc<TempoMap>,
+    /// Only used for accenting the metronome on the audio thread.
+    time_signatures: Arc<TimeSignatureMap>,
+    loop_region: Option<(Timestamp, Timestamp)>,
+    soloed_clip: Option<AudioClipKey>,
+
+    /// Only kept here, since they have no effect on playback.
+    markers: Markers,
 
     track_key_generator: KeyGenerator<TimelineTrackKey>,
     clip_key_generator: KeyGenerator<AudioClipKey>,
     clip_to_track: HashMap<AudioClipKey, TimelineTrackKey>,
 
     playing: Arc<AtomicBool>,
+    counting_in: Arc<AtomicBool>,
     /// Should not be mutated from here
     position: Arc<AtomicUsize>,
 
     clip_store: AudioClipStore,
     tracks: HashMap<TimelineTrackKey, TimelineTrack>,
     track_processors: RemotePusherHashMap<TimelineTrackKey, DBox<TimelineTrackProcessor>>,
+    metronome: Metronome,
 
     event_sender: ringbuffer::Sender<Event>,
 }
 impl Timeline {
+    /// The stored clips that are fully decoded in memory, for [`timeline_with_decoded`].
+    pub fn decoded_clips(&self) -> DecodedClips {
+        self.clip_store.decoded()
+    }
+
+    /// Counters of the events sent to the [`TimelineProcessor`].
+    pub fn event_queue_stats(&self) -> EventQueueStats {
+        self.event_sender.stats()
+    }
+
+    /// The tempo at the start of the timeline.
     pub fn bpm_cents(&self) -> u16 {
-        self.bpm_cents
+        self.tempo_map.initial_bpm_cents()
+    }
+
+    pub fn tempo_map(&self) -> &TempoMap {
+        &self.tempo_map
+    }
+
+    /// Set the tempo at the start of the timeline, which lasts until the first tempo change.
+    pub fn set_bpm_cents(&mut self, bpm_cents: u16) -> Result<(), TempoOverlapError> {
+        self.add_tempo_change(Timestamp::zero(), bpm_cents)
+    }
+
+    /// Set the tempo from `position` and until the next tempo change.
+    pub fn add_tempo_change(
+        &mut self,
+        position: Timestamp,
+        bpm_cents: u16,
+    ) -> Result<(), TempoOverlapError> {
+        let mut tempo_map = TempoMap::clone(&self.tempo_map);
+        tempo_map.insert(position, bpm_cents);
+        self.set_tempo_map(tempo_map)
+    }
+
+    pub fn remove_tempo_change(
+        &mut self,
+        position: Timestamp,
+    ) -> Result<(), RemoveTempoChangeError> {
+        let mut tempo_map = TempoMap::clone(&self.tempo_map);
+        tempo_map.remove(position)?;
+        self.set_tempo_map(tempo_map)?;
+        Ok(())
+    }
+
+    /// Replace the tempo map, unless it would make any clips overlap where their tracks do not allow it.
+    ///
+    /// Clips without a set length keep their length in samples, so their end moves on the timeline when the tempo changes.
+    fn set_tempo_map(&mut self, tempo_map: TempoMap) -> Result<(), TempoOverlapError> {
+        let mut keys: Vec<AudioClipKey> = self
+            .tracks
+            .values()
+            .flat_map(|track| {
+                track.clips.values().filter_map(|clip| {
+                    let end = clip.end(&tempo_map);
+                    let is_free =
+                        track.is_free(clip.key, clip.start, end, clip.crossfade, &tempo_map);
+                    (!is_free).then_some(clip.key)
+                })
+            })
+            .collect();
+        if !keys.is_empty() {
+            keys.sort();
+            return Err(TempoOverlapError { keys });
+        }
+
+        self.tempo_map = Arc::new(tempo_map);
+        self.event_sender
+            .send(Event::SetTempoMap(DBox::new(Arc::clone(&self.tempo_map))));
+        Ok(())
+    }
+
+    pub fn time_signatures(&self) -> &TimeSignatureMap {
+        &self.time_signatures
+    }
+
+    /// Set the time signature from `position` and until the next time signature change.
+    pub fn set_time_signature(&mut self, position: Timestamp, time_signature: TimeSignature) {
+        let mut time_signatures = TimeSignatureMap::clone(&self.time_signatures);
+        time_signatures.insert(position, time_signature);
+        self.set_time_signatures(time_signatures);
+    }
+
+    pub fn remove_time_signature(
+        &mut self,
+        position: Timestamp,
+    ) -> Result<(), InvalidTimeSignatureChangeError> {
+        let mut time_signatures = TimeSignatureMap::clone(&self.time_signatures);
+        time_signatures.remove(position)?;
+        self.set_time_signatures(time_signatures);
+        Ok(())
+    }
+
+    fn set_time_signatures(&mut self, time_signatures: TimeSignatureMap) {
+        self.time_signatures = Arc::new(time_signatures);
+        self.event_sender
+            .send(Event::SetTimeSignatures(DBox::new(Arc::clone(
+                &self.time_signatures,
+            ))));
     }
 
     pub fn play(&mut self) {
@@ -228,21 +534,150 @@ impl Timeline {
     pub fn pause(&mut self) {
         self.playing.store(false, Ordering::Release);
     }
+    pub fn is_playing(&self) -> bool {
+        self.playing.load(Ordering::Acquire)
+    }
+    /// Whether playback has started, but is still counting in.
+    pub fn is_counting_in(&self) -> bool {
+        self.counting_in.load(Ordering::Acquire)
+    }
     pub fn jump_to(&mut self, position: Timestamp) {
         self.event_sender.send(Event::JumpTo(position));
     }
+    /// Play `duration` from `position`, and then return to where the playhead was.
+    ///
+    /// Ignored while the timeline is playing.
+    pub fn preview_at(&mut self, position: Timestamp, duration: Duration) {
+        let samples = (duration.as_secs_f64() * self.sample_rate as f64).round() as usize;
+        if samples == 0 {
+            return;
+        }
+        self.event_sender.send(Event::Preview { position, samples });
+    }
+    /// Jump `delta` forward or backward from the current playhead position, stopping at zero.
+    pub fn jump_relative(&mut self, delta: Timestamp, direction: Direction) {
+        let position = self.playhead_position();
+        let new_position = match direction {
+            Direction::Forward => position.saturating_add(delta),
+            Direction::Backward => position.saturating_sub(delta),
+        };
+        self.jump_to(new_position);
+    }
     pub fn playhead_position(&mut self) -> Timestamp {
-        Timestamp::from_samples(
-            self.position.load(Ordering::Relaxed),
-            self.sample_rate,
-            self.bpm_cents,
-        )
+        self.tempo_map
+            .timestamp(self.position.load(Ordering::Relaxed), self.sample_rate)
+    }
+    /// The playhead position in frames at the output sample rate, independent of the tempo.
+    pub fn playhead_samples(&self) -> u64 {
+        self.position.load(Ordering::Relaxed) as u64
+    }
+    pub fn playhead_seconds(&self) -> f64 {
+        self.playhead_samples() as f64 / self.sample_rate as f64
+    }
+
+    pub fn metronome(&self) -> &Metronome {
+        &self.metronome
+    }
+
+    pub fn add_marker(
+        &mut self,
+        position: Timestamp,
+        name: String,
+    ) -> Result<MarkerKey, MarkerOverflowError> {
+        self.markers.add(position, name)
+    }
+    pub fn remove_marker(&mut self, key: MarkerKey) -> Result<Marker, InvalidMarkerError> {
+        self.markers.remove(key)
+    }
+    pub fn markers(&self) -> impl Iterator<Item = &Marker> + '_ {
+        self.markers.iter()
+    }
+    /// Jump to the first marker after the playhead, returning its key if there is one.
+    pub fn jump_to_next_marker(&mut self) -> Option<MarkerKey> {
+        let position = self.playhead_position();
+        let &Marker { key, position, .. } = self.markers.next_after(position)?;
+        self.jump_to(position);
+        Some(key)
+    }
+    /// Jump to the last marker before the playhead, returning its key if there is one.
+    pub fn jump_to_previous_marker(&mut self) -> Option<MarkerKey> {
+        let position = self.playhead_position();
+        let &Marker { key, position, .. } = self.markers.previous_before(position)?;
+        self.jump_to(position);
+        Some(key)
+    }
+
+    pub fn loop_region(&self) -> Option<(Timestamp, Timestamp)> {
+        self.loop_region
+    }
+    pub fn set_loop_region(
+        &mut self,
+        loop_region: Option<(Timestamp, Timestamp)>,
+    ) -> Result<(), InvalidLoopRegionError> {
+        if let Some((start, end)) = loop_region {
+            if start >= end {
+                return Err(InvalidLoopRegionError { start, end });
+            }
+        }
+
+        self.loop_region = loop_region;
+        self.event_sender.send(Event::SetLoopRegion(loop_region));
+        Ok(())
+    }
+
+    pub fn soloed_audio_clip(&self) -> Option<AudioClipKey> {
+        self.soloed_clip
+    }
+    pub fn solo_audio_clip(
+        &mut self,
+        clip_key: Option<AudioClipKey>,
+    ) -> Result<(), InvalidAudioClipError> {
+        if let Some(clip_key) = clip_key {
+            self.audio_clip(clip_key)?;
+        }
+
+        self.soloed_clip = clip_key;
+        self.event_sender.send(Event::SoloAudioClip(clip_key));
+        Ok(())
+    }
+    /// Stop soloing the clip, if it is the soloed one, e.g. because it is deleted.
+    fn unsolo_audio_clip(&mut self, clip_key: AudioClipKey) {
+        if self.soloed_clip == Some(clip_key) {
+            self.soloed_clip = None;
+            self.event_sender.send(Event::SoloAudioClip(None));
+        }
     }
 
     pub fn import_audio_clip(&mut self, path: &Path) -> Result<StoredAudioClipKey, ImportError> {
         self.clip_store.import(path)
     }
 
+    pub fn import_audio_clip_streaming(
+        &mut self,
+        path: &Path,
+        max_cached_frames: usize,
+    ) -> Result<StoredAudioClipKey, ImportError> {
+        self.clip_store.import_streaming(path, max_cached_frames)
+    }
+
+    pub fn downmix(&self) -> &Downmix {
+        self.clip_store.downmix()
+    }
+    pub fn set_downmix(&mut self, downmix: Downmix) {
+        self.clip_store.set_downmix(downmix);
+    }
+
+    pub fn import_audio_clip_async(&self, path: &Path) -> ImportHandle {
+        self.clip_store.import_async(path)
+    }
+
+    pub fn finish_audio_clip_import(
+        &mut self,
+        handle: ImportHandle,
+    ) -> Result<StoredAudioClipKey, ImportError> {
+        self.clip_store.finish_import(handle)
+    }
+
     pub fn stored_audio_clip(
         &self,
         key: StoredAudioClipKey,
@@ -250,10 +685,51 @@ impl Timeline {
         self.clip_store.get(key)
     }
 
+    /// Whether the stored clip has a different sample rate than the timeline, so it is resampled during playback.
+    pub fn stored_audio_clip_resampled(
+        &self,
+        key: StoredAudioClipKey,
+    ) -> Result<bool, InvalidStoredAudioClipError> {
+        Ok(self.clip_store.get(key)?.sample_rate() != self.sample_rate)
+    }
+
     pub fn stored_audio_clips(&self) -> impl Iterator<Item = Arc<StoredAudioClip>> + '_ {
         self.clip_store.iter()
     }
 
+    pub fn stored_audio_clip_path(&self, key: StoredAudioClipKey) -> Option<PathBuf> {
+        self.clip_store.path(key).map(Path::to_path_buf)
+    }
+
+    /// The number of clips on the timeline that play the stored clip.
+    pub fn stored_clip_usage(&self, key: StoredAudioClipKey) -> usize {
+        self.tracks
+            .values()
+            .flat_map(|track| track.clips.values())
+            .filter(|clip| clip.stored_clip_key() == key)
+            .count()
+    }
+
+    pub fn remove_stored_audio_clip(
+        &mut self,
+        key: StoredAudioClipKey,
+    ) -> Result<(), RemoveStoredAudioClipError> {
+        self.clip_store
+            .get(key)
+            .map_err(|_| RemoveStoredAudioClipError::InvalidClip(key))?;
+
+        // Each reader of the clip belongs to a clip on the timeline,
+        // so when none of them use it, only the readers of deleted clips can be left, waiting to be dropped
+        if self.stored_clip_usage(key) > 0 {
+            return Err(RemoveStoredAudioClipError::InUse(key));
+        }
+
+        self.clip_store
+            .remove(key)
+            .expect("Stored clip disappeared from the store");
+        Ok(())
+    }
+
     fn add_audio_clip_inner(
         &mut self,
         track_key: TimelineTrackKey,
@@ -268,6 +744,18 @@ impl Timeline {
             start_offset,
             start,
             length,
+            gain,
+            gain_envelope,
+            fade_in,
+            fade_out,
+            reversed,
+            stretch,
+            pitch,
+            crossfade,
+            active,
+            end_behavior,
+            name,
+            color,
             inner: stored_clip_key,
         } = clip_state;
 
@@ -280,21 +768,56 @@ impl Timeline {
             start,
             set_length: length,
             start_offset,
+            gain,
+            gain_envelope: gain_envelope.clone(),
+            fade_in,
+            fade_out,
+            reversed,
+            stretch,
+            pitch,
+            crossfade,
+            active,
+            end_behavior,
+            name,
+            color,
             reader: reader1,
         };
 
-        let reader2 = self.clip_store.reader(stored_clip_key).unwrap();
+        let reader2 = self
+            .clip_store
+            .reader_pitched(stored_clip_key, pitch)
+            .unwrap();
         let audio_clip_processor =
-            AudioClipProcessor::new(start, length, OriginalSamples::new(0), reader2);
+            AudioClipProcessor::new(clip_key, start, length, start_offset, gain, reader2)
+                .with_gain_envelope(gain_envelope)
+                .with_fades(fade_in, fade_out)
+                .with_reversed(reversed)
+                .with_stretch(stretch)
+                .with_active(active)
+                .with_end_behavior(end_behavior);
+
+        let tempo_map = &*self.tempo_map;
+        let track = self.tracks.get(&track_key).unwrap();
+        let end = audio_clip.end(tempo_map);
+        // The clips that the new one is placed on top of the end of
+        let trimmed = match track.overlap_policy {
+            OverlapPolicy::TrimExisting => track.clips_across(start, tempo_map),
+            OverlapPolicy::Reject | OverlapPolicy::Layer => Vec::new(),
+        };
+        let mut ignored = trimmed.clone();
+        ignored.push(clip_key);
+        if !track.is_free_except(&ignored, start, end, crossfade, tempo_map) {
+            return Err(AddClipError::Overlapping);
+        }
 
-        let track = self.tracks.get_mut(&track_key).unwrap();
-        for clip in track.clips.values() {
-            if clip.overlaps(&audio_clip, self.bpm_cents) {
-                return Err(AddClipError::Overlapping);
-            }
+        for trimmed_key in trimmed {
+            let trimmed_start = self.audio_clip(trimmed_key).unwrap().start;
+            self.audio_clip_crop_end(trimmed_key, start - trimmed_start)
+                .expect("Cropping a clip to end earlier made it overlap");
         }
 
         self.clip_to_track.insert(clip_key, track_key);
+        let track = self.tracks.get_mut(&track_key).unwrap();
 
         track.clips.insert(clip_key, audio_clip);
 
@@ -323,6 +846,18 @@ impl Timeline {
                     start_offset,
                     start,
                     length,
+                    gain,
+                    ref gain_envelope,
+                    fade_in,
+                    fade_out,
+                    reversed,
+                    stretch,
+                    pitch,
+                    crossfade,
+                    active,
+                    end_behavior,
+                    ref name,
+                    color,
                     inner: stored_clip_key,
                 } = *clip_state;
 
@@ -335,18 +870,39 @@ impl Timeline {
                     start,
                     set_length: length,
                     start_offset,
+                    gain,
+                    gain_envelope: gain_envelope.clone(),
+                    fade_in,
+                    fade_out,
+                    reversed,
+                    stretch,
+                    pitch,
+                    crossfade,
+                    active,
+                    end_behavior,
+                    name: name.clone(),
+                    color,
                     reader: reader1,
                 };
 
-                let reader2 = self.clip_store.reader(stored_clip_key).unwrap();
+                let reader2 = self
+                    .clip_store
+                    .reader_pitched(stored_clip_key, pitch)
+                    .unwrap();
                 let audio_clip_processor =
-                    AudioClipProcessor::new(start, length, OriginalSamples::new(0), reader2);
-
+                    AudioClipProcessor::new(clip_key, start, length, start_offset, gain, reader2)
+                        .with_gain_envelope(gain_envelope.clone())
+                        .with_fades(fade_in, fade_out)
+                        .with_reversed(reversed)
+                        .with_stretch(stretch)
+                        .with_active(active)
+                        .with_end_behavior(end_behavior);
+
+                let tempo_map = &*self.tempo_map;
                 let track = self.tracks.get_mut(&track_key).unwrap();
-                for clip in track.clips.values() {
-                    if clip.overlaps(&audio_clip, self.bpm_cents) {
-                        return Err(AddClipError::Overlapping);
-                    }
+                let end = audio_clip.end(tempo_map);
+                if !track.is_free(clip_key, start, end, crossfade, tempo_map) {
+                    return Err(AddClipError::Overlapping);
                 }
 
                 self.clip_to_track.insert(clip_key, track_key);
@@ -380,6 +936,18 @@ impl Timeline {
                 start_offset: OriginalSamples::new(0),
                 start,
                 length,
+                gain: 1.0,
+                gain_envelope: Vec::new(),
+                fade_in: Timestamp::zero(),
+                fade_out: Timestamp::zero(),
+                reversed: false,
+                stretch: 1.0,
+                pitch: 0.0,
+                crossfade: Timestamp::zero(),
+                active: true,
+                end_behavior: EndBehavior::Silence,
+                name: String::new(),
+                color: 0,
                 inner: stored_clip_key,
             },
         )?;
@@ -387,6 +955,32 @@ impl Timeline {
         Ok(key)
     }
 
+    pub fn duplicate_audio_clip(
+        &mut self,
+        clip_key: AudioClipKey,
+        new_start: Timestamp,
+    ) -> Result<AudioClipKey, AddClipError> {
+        let state = self
+            .audio_clip(clip_key)
+            .or(Err(AddClipError::InvalidAudioClip(clip_key)))?
+            .state();
+        let track_key = *self.clip_to_track.get(&clip_key).unwrap();
+
+        let key = self.clip_key_generator.peek_next().unwrap();
+        self.add_audio_clip_inner(
+            track_key,
+            AudioClipState {
+                key,
+                start: new_start,
+                // The crossfade belongs to where the original clip is placed
+                crossfade: Timestamp::zero(),
+                ..state
+            },
+        )?;
+        self.clip_key_generator.reserve(key).unwrap();
+        Ok(key)
+    }
+
     pub fn audio_clip(&self, clip_key: AudioClipKey) -> Result<&AudioClip, InvalidAudioClipError> {
         if !self.clip_key_generator.in_use(clip_key) {
             return Err(InvalidAudioClipError { clip_key });
@@ -425,6 +1019,80 @@ impl Timeline {
         Ok(track.clips.values())
     }
 
+    pub fn audio_clip_count(
+        &self,
+        track_key: TimelineTrackKey,
+    ) -> Result<usize, InvalidTimelineTrackError> {
+        let track = self
+            .tracks
+            .get(&track_key)
+            .ok_or(InvalidTimelineTrackError { key: track_key })?;
+        Ok(track.clips.len())
+    }
+
+    /// The end of the last clip on any track, or zero if there are no clips.
+    pub fn length(&self) -> Timestamp {
+        let tempo_map = &*self.tempo_map;
+        self.tracks
+            .values()
+            .flat_map(|track| track.clips.values())
+            .map(|clip| clip.end(tempo_map))
+            .max()
+            .unwrap_or(Timestamp::zero())
+    }
+
+    pub fn range_is_free(
+        &self,
+        track_key: TimelineTrackKey,
+        start: Timestamp,
+        end: Timestamp,
+    ) -> Result<bool, InvalidTimelineTrackError> {
+        let track = self
+            .tracks
+            .get(&track_key)
+            .ok_or(InvalidTimelineTrackError { key: track_key })?;
+        Ok(track.range_is_free(start, end, &*self.tempo_map))
+    }
+
+    /// The number of clips on the track that are playing at `position`,
+    /// which is more than one where clips crossfade.
+    pub fn audio_clip_count_at(
+        &self,
+        track_key: TimelineTrackKey,
+        position: Timestamp,
+    ) -> Result<usize, InvalidTimelineTrackError> {
+        let track = self
+            .tracks
+            .get(&track_key)
+            .ok_or(InvalidTimelineTrackError { key: track_key })?;
+        let tempo_map = &*self.tempo_map;
+        Ok(track
+            .clips
+            .values()
+            .filter(|clip| clip.start <= position && position < clip.end(tempo_map))
+            .count())
+    }
+
+    /// The clip on the track that is playing at `position`, if any.
+    /// Where several clips overlap, the one that starts latest is returned.
+    pub fn audio_clip_at(
+        &self,
+        track_key: TimelineTrackKey,
+        position: Timestamp,
+    ) -> Result<Option<AudioClipKey>, InvalidTimelineTrackError> {
+        let track = self
+            .tracks
+            .get(&track_key)
+            .ok_or(InvalidTimelineTrackError { key: track_key })?;
+        let tempo_map = &*self.tempo_map;
+        Ok(track
+            .clips
+            .values()
+            .filter(|clip| clip.start <= position && position < clip.end(tempo_map))
+            .max_by_key(|clip| clip.start)
+            .map(|clip| clip.key))
+    }
+
     pub fn delete_audio_clip(
         &mut self,
         clip_key: AudioClipKey,
@@ -446,6 +1114,7 @@ impl Timeline {
             track_key,
             clip_start: clip.start,
         });
+        self.unsolo_audio_clip(clip_key);
 
         Ok(clip.state())
     }
@@ -506,6 +1175,9 @@ impl Timeline {
         self.event_sender.send(Event::DeleteClips {
             track_keys_and_clip_starts: DBox::new(track_keys_and_clip_starts),
         });
+        for &clip_key in &clip_keys {
+            self.unsolo_audio_clip(clip_key);
+        }
 
         Ok(clip_states.into_iter())
     }
@@ -560,16 +1232,16 @@ impl Timeline {
         let clip = track.clips.get(&clip_key).unwrap();
 
         let old_start = clip.start;
-        let new_end = new_start + clip.length(self.bpm_cents);
-
-        // Check for overlaps
-        for other_clip in track.clips.values() {
-            let same = other_clip.key == clip.key;
-            let overlapping =
-                new_start < other_clip.end(self.bpm_cents) && other_clip.start < new_end;
-            if !same && overlapping {
-                return Err(MoveAudioClipError::Overlapping);
-            }
+        let new_end = new_start + clip.length_at(new_start, &*self.tempo_map);
+
+        if !track.is_free(
+            clip_key,
+            new_start,
+            new_end,
+            clip.crossfade,
+            &*self.tempo_map,
+        ) {
+            return Err(MoveAudioClipError::Overlapping);
         }
 
         let clip_mut = track.clips.get_mut(&clip_key).unwrap();
@@ -584,43 +1256,211 @@ impl Timeline {
         Ok(())
     }
 
-    pub fn audio_clip_move_to_track(
+    /// Same as [`Self::audio_clip_move`],
+    /// except the clips overlapping the start and end of the moved clip crossfade with it across the overlap.
+    pub fn audio_clip_move_with_crossfade(
         &mut self,
         clip_key: AudioClipKey,
         new_start: Timestamp,
-        new_track_key: TimelineTrackKey,
-    ) -> Result<(), MoveAudioClipToTrackError> {
+    ) -> Result<(), MoveAudioClipError> {
         if !self.clip_key_generator.in_use(clip_key) {
-            return Err(MoveAudioClipToTrackError::InvalidClip(
-                InvalidAudioClipError { clip_key },
-            ));
+            return Err(MoveAudioClipError::InvalidClip(InvalidAudioClipError {
+                clip_key,
+            }));
         }
 
-        let old_track_key = *self.clip_to_track.get(&clip_key).unwrap();
-        let old_track = self.tracks.get_mut(&old_track_key).unwrap();
-        let clip = old_track.clips.get(&clip_key).unwrap();
+        let tempo_map = &*self.tempo_map;
+        let track_key = *self.clip_to_track.get(&clip_key).unwrap();
+        let track = self.tracks.get_mut(&track_key).unwrap();
+        let clip = track.clips.get(&clip_key).unwrap();
 
         let old_start = clip.start;
-        let new_end = new_start + clip.length(self.bpm_cents);
+        let old_crossfade = clip.crossfade;
+        let new_end = new_start + clip.length_at(new_start, tempo_map);
+
+        // The overlap with the clip ending inside the moved one, and the clip starting inside it.
+        // Anything else overlapping it is left for `is_free` to reject.
+        let mut fade_in = Timestamp::zero();
+        let mut fade_out = None;
+        if track.overlap_policy != OverlapPolicy::Layer {
+            for other in track.clips.values() {
+                if other.key == clip_key {
+                    continue;
+                }
+                let other_end = other.end(tempo_map);
+                if other.start < new_start && new_start < other_end && other_end <= new_end {
+                    fade_in = max(fade_in, other_end - new_start);
+                } else if new_start < other.start && other.start < new_end && new_end <= other_end {
+                    fade_out = Some((other.key, other.crossfade, new_end - other.start));
+                }
+            }
+        }
 
-        let new_track =
-            self.tracks
-                .get(&new_track_key)
-                .ok_or(MoveAudioClipToTrackError::InvalidNewTrack {
-                    track_key: new_track_key,
-                })?;
+        track.clips.get_mut(&clip_key).unwrap().crossfade = fade_in;
+        if let Some((next_key, _, overlap)) = fade_out {
+            track.clips.get_mut(&next_key).unwrap().crossfade = overlap;
+        }
 
-        // Check for overlaps
-        for other_clip in new_track.clips.values() {
-            let same = other_clip.key == clip_key;
-            let overlapping =
-                new_start < other_clip.end(self.bpm_cents) && other_clip.start < new_end;
-            if !same && overlapping {
-                return Err(MoveAudioClipToTrackError::Overlapping);
+        if !track.is_free(clip_key, new_start, new_end, fade_in, tempo_map) {
+            track.clips.get_mut(&clip_key).unwrap().crossfade = old_crossfade;
+            if let Some((next_key, old_next_crossfade, _)) = fade_out {
+                track.clips.get_mut(&next_key).unwrap().crossfade = old_next_crossfade;
             }
+            return Err(MoveAudioClipError::Overlapping);
         }
 
-        let old_track_mut = self.tracks.get_mut(&old_track_key).unwrap();
+        track.clips.get_mut(&clip_key).unwrap().start = new_start;
+
+        self.event_sender.send(Event::MoveAudioClip {
+            track_key,
+            old_start,
+            new_start,
+        });
+
+        Ok(())
+    }
+
+    pub fn move_audio_clips(
+        &mut self,
+        clip_keys: &[AudioClipKey],
+        delta: Timestamp,
+        direction: Direction,
+    ) -> Result<(), MoveAudioClipError> {
+        let mut seen = HashSet::new();
+        let mut moves = Vec::with_capacity(clip_keys.len());
+        for &clip_key in clip_keys {
+            if !seen.insert(clip_key) {
+                continue;
+            }
+            let old_start = self
+                .audio_clip(clip_key)
+                .map_err(MoveAudioClipError::InvalidClip)?
+                .start;
+            let new_start = match direction {
+                Direction::Forward => old_start.checked_add(delta),
+                Direction::Backward => old_start.checked_sub(delta),
+            }
+            .ok_or(MoveAudioClipError::OutOfBounds)?;
+            let track_key = *self.clip_to_track.get(&clip_key).unwrap();
+            moves.push((track_key, clip_key, old_start, new_start));
+        }
+
+        // Check the final arrangement, where all of the clips have been moved
+        let set_starts = |tracks: &mut HashMap<TimelineTrackKey, TimelineTrack>, new: bool| {
+            for &(track_key, clip_key, old_start, new_start) in &moves {
+                let clip = tracks
+                    .get_mut(&track_key)
+                    .unwrap()
+                    .clips
+                    .get_mut(&clip_key)
+                    .unwrap();
+                clip.start = if new { new_start } else { old_start };
+            }
+        };
+        set_starts(&mut self.tracks, true);
+        let tempo_map = &*self.tempo_map;
+        let is_free = moves.iter().all(|&(track_key, clip_key, _, new_start)| {
+            let track = self.tracks.get(&track_key).unwrap();
+            let clip = track.clips.get(&clip_key).unwrap();
+            track.is_free(
+                clip_key,
+                new_start,
+                clip.end(tempo_map),
+                clip.crossfade,
+                tempo_map,
+            )
+        });
+        if !is_free {
+            set_starts(&mut self.tracks, false);
+            return Err(MoveAudioClipError::Overlapping);
+        }
+
+        // Moving the clips furthest along first, so none of them are moved onto a clip that has not been moved yet
+        match direction {
+            Direction::Forward => moves.sort_by_key(|&(_, _, old_start, _)| Reverse(old_start)),
+            Direction::Backward => moves.sort_by_key(|&(_, _, old_start, _)| old_start),
+        }
+        self.event_sender.send(Event::MoveAudioClips {
+            moves: DBox::new(
+                moves
+                    .into_iter()
+                    .map(|(track_key, _, old_start, new_start)| (track_key, old_start, new_start))
+                    .collect(),
+            ),
+        });
+
+        Ok(())
+    }
+
+    pub fn quantize_audio_clip(
+        &mut self,
+        clip_key: AudioClipKey,
+        division: Timestamp,
+    ) -> Result<(), MoveAudioClipError> {
+        let start = self
+            .audio_clip(clip_key)
+            .map_err(MoveAudioClipError::InvalidClip)?
+            .start;
+        self.audio_clip_move(clip_key, start.round_to(division))
+    }
+
+    pub fn quantize_audio_clips(
+        &mut self,
+        clip_keys: &[AudioClipKey],
+        division: Timestamp,
+    ) -> Result<(), MoveAudioClipError> {
+        let old_starts = clip_keys
+            .iter()
+            .map(|&clip_key| Ok(self.audio_clip(clip_key)?.start))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(MoveAudioClipError::InvalidClip)?;
+
+        for (i, (&clip_key, &old_start)) in zip(clip_keys, &old_starts).enumerate() {
+            if let Err(e) = self.audio_clip_move(clip_key, old_start.round_to(division)) {
+                // Moving back in reverse order restores each intermediate arrangement, so this can not overlap
+                for (&clip_key, &old_start) in zip(&clip_keys[..i], &old_starts[..i]).rev() {
+                    self.audio_clip_move(clip_key, old_start)
+                        .expect("Clip could not be moved back to where it was");
+                }
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn audio_clip_move_to_track(
+        &mut self,
+        clip_key: AudioClipKey,
+        new_start: Timestamp,
+        new_track_key: TimelineTrackKey,
+    ) -> Result<(), MoveAudioClipToTrackError> {
+        if !self.clip_key_generator.in_use(clip_key) {
+            return Err(MoveAudioClipToTrackError::InvalidClip(
+                InvalidAudioClipError { clip_key },
+            ));
+        }
+
+        let old_track_key = *self.clip_to_track.get(&clip_key).unwrap();
+        let old_track = self.tracks.get_mut(&old_track_key).unwrap();
+        let clip = old_track.clips.get(&clip_key).unwrap();
+
+        let old_start = clip.start;
+        let new_end = new_start + clip.length_at(new_start, &*self.tempo_map);
+        let crossfade = clip.crossfade;
+
+        let new_track =
+            self.tracks
+                .get(&new_track_key)
+                .ok_or(MoveAudioClipToTrackError::InvalidNewTrack {
+                    track_key: new_track_key,
+                })?;
+
+        if !new_track.is_free(clip_key, new_start, new_end, crossfade, &*self.tempo_map) {
+            return Err(MoveAudioClipToTrackError::Overlapping);
+        }
+
+        let old_track_mut = self.tracks.get_mut(&old_track_key).unwrap();
         let mut clip_mut = old_track_mut.clips.remove(&clip_key).unwrap();
 
         clip_mut.start = new_start;
@@ -645,6 +1485,141 @@ impl Timeline {
         clip_key: AudioClipKey,
         new_length: Timestamp,
     ) -> Result<(), MoveAudioClipError> {
+        let crop = self.plan_crop_start(clip_key, new_length)?;
+        self.apply_crops(&[crop])?;
+
+        self.event_sender.send(Event::CropAudioClipStart {
+            track_key: crop.track_key,
+            old_start: crop.old_start,
+            new_start: crop.new_start,
+            new_length: crop.new_length,
+            new_start_offset: crop.new_start_offset,
+        });
+
+        Ok(())
+    }
+    pub fn audio_clip_crop_end(
+        &mut self,
+        clip_key: AudioClipKey,
+        new_length: Timestamp,
+    ) -> Result<(), MoveAudioClipError> {
+        let crop = self.plan_crop_end(clip_key, new_length)?;
+        self.apply_crops(&[crop])?;
+
+        self.event_sender.send(Event::CropAudioClipEnd {
+            track_key: crop.track_key,
+            clip_start: crop.old_start,
+            new_length: crop.new_length,
+            new_start_offset: crop.new_start_offset,
+        });
+
+        Ok(())
+    }
+
+    /// Crop the start of every clip in `crops` to the given length, like [`Self::audio_clip_crop_start`].
+    ///
+    /// Either all of the clips are cropped, or none of them are.
+    /// Only the final arrangement has to be free of overlaps.
+    pub fn audio_clip_crop_start_multiple(
+        &mut self,
+        crops: &[(AudioClipKey, Timestamp)],
+    ) -> Result<(), MoveAudioClipError> {
+        let crops = self.plan_crops(crops, Self::plan_crop_start)?;
+        self.apply_crops(&crops)?;
+
+        self.event_sender.send(Event::CropAudioClipsStart {
+            crops: DBox::new(
+                crops
+                    .into_iter()
+                    .map(|crop| StartCrop { crop, clip: None })
+                    .collect(),
+            ),
+        });
+
+        Ok(())
+    }
+    /// Crop the end of every clip in `crops` to the given length, like [`Self::audio_clip_crop_end`].
+    ///
+    /// Either all of the clips are cropped, or none of them are.
+    /// Only the final arrangement has to be free of overlaps.
+    pub fn audio_clip_crop_end_multiple(
+        &mut self,
+        crops: &[(AudioClipKey, Timestamp)],
+    ) -> Result<(), MoveAudioClipError> {
+        let crops = self.plan_crops(crops, Self::plan_crop_end)?;
+        self.apply_crops(&crops)?;
+
+        self.event_sender.send(Event::CropAudioClipsEnd {
+            crops: DBox::new(crops),
+        });
+
+        Ok(())
+    }
+
+    /// Plan each crop in `crops` with `plan`, skipping all but the first crop of each clip.
+    fn plan_crops(
+        &self,
+        crops: &[(AudioClipKey, Timestamp)],
+        plan: fn(&Self, AudioClipKey, Timestamp) -> Result<Crop, MoveAudioClipError>,
+    ) -> Result<Vec<Crop>, MoveAudioClipError> {
+        let mut seen = HashSet::new();
+        crops
+            .iter()
+            .filter(|&&(clip_key, _)| seen.insert(clip_key))
+            .map(|&(clip_key, new_length)| plan(self, clip_key, new_length))
+            .collect()
+    }
+
+    /// Apply the crops to the clips, unless any of the clips would overlap afterwards.
+    fn apply_crops(&mut self, crops: &[Crop]) -> Result<(), MoveAudioClipError> {
+        let set = |tracks: &mut HashMap<TimelineTrackKey, TimelineTrack>, new: bool| {
+            for crop in crops {
+                let clip = tracks
+                    .get_mut(&crop.track_key)
+                    .unwrap()
+                    .clips
+                    .get_mut(&crop.clip_key)
+                    .unwrap();
+                if new {
+                    clip.start = crop.new_start;
+                    clip.set_length = Some(crop.new_length);
+                    clip.start_offset = crop.new_start_offset;
+                } else {
+                    clip.start = crop.old_start;
+                    clip.set_length = crop.old_length;
+                    clip.start_offset = crop.old_start_offset;
+                }
+            }
+        };
+
+        // Check the final arrangement, where all of the clips have been cropped
+        set(&mut self.tracks, true);
+        let tempo_map = &*self.tempo_map;
+        let is_free = crops.iter().all(|crop| {
+            let track = self.tracks.get(&crop.track_key).unwrap();
+            let clip = track.clips.get(&crop.clip_key).unwrap();
+            track.is_free(
+                crop.clip_key,
+                crop.new_start,
+                crop.new_start + crop.new_length,
+                clip.crossfade,
+                tempo_map,
+            )
+        });
+        if !is_free {
+            set(&mut self.tracks, false);
+            return Err(MoveAudioClipError::Overlapping);
+        }
+
+        Ok(())
+    }
+
+    /// Where the clip would start, and what it would play, if its start was cropped to `new_length`.
+    fn plan_crop_start(
+        &self,
+        clip_key: AudioClipKey,
+        new_length: Timestamp,
+    ) -> Result<Crop, MoveAudioClipError> {
         if !self.clip_key_generator.in_use(clip_key) {
             return Err(MoveAudioClipError::InvalidClip(InvalidAudioClipError {
                 clip_key,
@@ -652,98 +1627,781 @@ impl Timeline {
         }
 
         let track_key = *self.clip_to_track.get(&clip_key).unwrap();
-        let track = self.tracks.get_mut(&track_key).unwrap();
-        let clip = track.clips.get(&clip_key).unwrap();
+        let clip = &self.tracks[&track_key].clips[&clip_key];
 
         let original_sample_rate = clip.reader.sample_rate_original();
 
         let old_start = clip.start;
-        let old_length = clip.length(self.bpm_cents);
-        let clip_end = old_start + old_length;
+        let tempo_map = &*self.tempo_map;
+        let old_length = clip.length(tempo_map);
 
+        let stretch = clip.stretch;
         let old_start_offset = clip.start_offset;
-        let old_start_offset_timestamp = Timestamp::from_samples_ceil(
-            old_start_offset.into(),
+        let old_start_offset_timestamp = tempo_map.duration_until(
+            old_start,
+            stretch_samples(old_start_offset.into(), stretch),
             original_sample_rate,
-            self.bpm_cents,
         );
 
         let desired_new_start = clip.start + old_length - new_length;
 
-        let new_start = max(
-            desired_new_start,
-            old_start.saturating_sub(old_start_offset_timestamp),
-        );
-        let new_start_offset = old_start_offset
-            + OriginalSamples::new(new_start.samples(original_sample_rate, self.bpm_cents))
-            - OriginalSamples::new(old_start.samples(original_sample_rate, self.bpm_cents));
+        let (new_start, new_start_offset) = if clip.reversed {
+            // A reversed clip starts at the end of its region, so this is where the source is cropped
+            let region_end = old_start_offset
+                + OriginalSamples::new(unstretch_samples(
+                    tempo_map.duration_samples(old_start, old_length, original_sample_rate),
+                    stretch,
+                ));
+            let after_region = clip.reader.len_original().saturating_sub(region_end);
+            let after_region_timestamp = tempo_map.duration_until(
+                old_start,
+                stretch_samples(after_region.into(), stretch),
+                original_sample_rate,
+            );
+
+            let new_start = max(
+                desired_new_start,
+                old_start.saturating_sub(after_region_timestamp),
+            );
+            (new_start, old_start_offset)
+        } else {
+            let new_start = max(
+                desired_new_start,
+                old_start.saturating_sub(old_start_offset_timestamp),
+            );
+            let new_start_samples = tempo_map.samples(new_start, original_sample_rate);
+            let old_start_samples = tempo_map.samples(old_start, original_sample_rate);
+            let new_start_offset = if new_start_samples >= old_start_samples {
+                old_start_offset
+                    + OriginalSamples::new(unstretch_samples(
+                        new_start_samples - old_start_samples,
+                        stretch,
+                    ))
+            } else {
+                old_start_offset.saturating_sub(OriginalSamples::new(unstretch_samples(
+                    old_start_samples - new_start_samples,
+                    stretch,
+                )))
+            };
+            (new_start, new_start_offset)
+        };
 
         let new_length = old_length + old_start - new_start;
 
-        // Check for overlaps
-        for other_clip in track.clips.values() {
-            let same = other_clip.key == clip.key;
-            let overlapping =
-                new_start < other_clip.end(self.bpm_cents) && other_clip.start < clip_end;
-            if !same && overlapping {
-                return Err(MoveAudioClipError::Overlapping);
-            }
+        Ok(Crop {
+            track_key,
+            clip_key,
+            old_start,
+            old_length: clip.set_length,
+            old_start_offset,
+            new_start,
+            new_length,
+            new_start_offset,
+        })
+    }
+    /// What the clip would play if its end was cropped to `new_length`.
+    fn plan_crop_end(
+        &self,
+        clip_key: AudioClipKey,
+        new_length: Timestamp,
+    ) -> Result<Crop, MoveAudioClipError> {
+        if !self.clip_key_generator.in_use(clip_key) {
+            return Err(MoveAudioClipError::InvalidClip(InvalidAudioClipError {
+                clip_key,
+            }));
         }
 
-        let clip_mut = track.clips.get_mut(&clip_key).unwrap();
-        clip_mut.start = new_start;
-        clip_mut.set_length = Some(new_length);
-        clip_mut.start_offset = new_start_offset;
+        let track_key = *self.clip_to_track.get(&clip_key).unwrap();
+        let clip = &self.tracks[&track_key].clips[&clip_key];
 
-        self.event_sender.send(Event::CropAudioClipStart {
+        let clip_start = clip.start;
+
+        let (new_length, new_start_offset) = if clip.reversed {
+            // A reversed clip ends at the start of its region, so this is where the source is cropped
+            let tempo_map = &*self.tempo_map;
+            let original_sample_rate = clip.reader.sample_rate_original();
+            let stretch = clip.stretch;
+            let region_end = clip.start_offset
+                + OriginalSamples::new(unstretch_samples(
+                    tempo_map.duration_samples(
+                        clip_start,
+                        clip.length(tempo_map),
+                        original_sample_rate,
+                    ),
+                    stretch,
+                ));
+            let max_length = tempo_map.duration(
+                clip_start,
+                stretch_samples(region_end.into(), stretch),
+                original_sample_rate,
+            );
+
+            let new_length = min(new_length, max_length);
+            let new_start_offset =
+                region_end.saturating_sub(OriginalSamples::new(unstretch_samples(
+                    tempo_map.duration_samples(clip_start, new_length, original_sample_rate),
+                    stretch,
+                )));
+            (new_length, new_start_offset)
+        } else {
+            (new_length, clip.start_offset)
+        };
+
+        Ok(Crop {
             track_key,
-            old_start,
-            new_start,
+            clip_key,
+            old_start: clip_start,
+            old_length: clip.set_length,
+            old_start_offset: clip.start_offset,
+            new_start: clip_start,
             new_length,
             new_start_offset,
+        })
+    }
+
+    pub fn audio_clip_trim_silence(
+        &mut self,
+        clip_key: AudioClipKey,
+        threshold_db: f32,
+    ) -> Result<(), MoveAudioClipError> {
+        if !self.clip_key_generator.in_use(clip_key) {
+            return Err(MoveAudioClipError::InvalidClip(InvalidAudioClipError {
+                clip_key,
+            }));
+        }
+
+        let track_key = *self.clip_to_track.get(&clip_key).unwrap();
+        let clip = self.tracks[&track_key].clips.get(&clip_key).unwrap();
+        let stored_clip = self
+            .clip_store
+            .get(clip.reader.key())
+            .expect("Clip refers to a stored clip that doesn't exist");
+        let Some((first, last)) = stored_clip.silence_bounds(threshold_db) else {
+            // Nothing but silence
+            return Ok(());
+        };
+
+        let tempo_map = &*self.tempo_map;
+        let original_sample_rate = clip.reader.sample_rate_original();
+        let stretch = clip.stretch;
+        let start = clip.start;
+        let length = clip.length(tempo_map);
+        let length_samples = tempo_map.duration_samples(start, length, original_sample_rate);
+
+        let region_start = clip.start_offset;
+        let region_end =
+            region_start + OriginalSamples::new(unstretch_samples(length_samples, stretch));
+        let audible_start = max(region_start, first);
+        let audible_end = min(region_end, last + OriginalSamples::new(1));
+        if audible_start >= audible_end {
+            return Ok(());
+        }
+
+        // The silence before and after the audible part, in the order it is played
+        let silence = (audible_start - region_start, region_end - audible_end);
+        let (before, after) = if clip.reversed {
+            (silence.1, silence.0)
+        } else {
+            silence
+        };
+
+        // Rounded towards the silence, so nothing audible is cropped away
+        let start_samples = tempo_map.samples(start, original_sample_rate);
+        let new_start = tempo_map.timestamp(
+            start_samples + stretch_samples(before.into(), stretch),
+            original_sample_rate,
+        );
+        let new_end = tempo_map.timestamp_ceil(
+            start_samples + length_samples - stretch_samples(after.into(), stretch),
+            original_sample_rate,
+        );
+
+        self.audio_clip_crop_start(clip_key, start + length - new_start)?;
+        self.audio_clip_crop_end(clip_key, new_end - new_start)
+    }
+
+    pub fn split_audio_clip(
+        &mut self,
+        clip_key: AudioClipKey,
+        at: Timestamp,
+    ) -> Result<(AudioClipKey, AudioClipKey), SplitAudioClipError> {
+        let clip = self
+            .audio_clip(clip_key)
+            .map_err(SplitAudioClipError::InvalidClip)?;
+
+        let tempo_map = &*self.tempo_map;
+        let start = clip.start;
+        let end = clip.end(tempo_map);
+        if !(start < at && at < end) {
+            return Err(SplitAudioClipError::OutOfBounds);
+        }
+
+        let track_key = *self.clip_to_track.get(&clip_key).unwrap();
+        let state = clip.state();
+        let first_length = at - start;
+        let first_samples =
+            tempo_map.duration_samples(start, first_length, clip.reader.sample_rate_original());
+        let skipped_samples = OriginalSamples::new(unstretch_samples(first_samples, state.stretch));
+
+        // A reversed clip plays the end of its region first, so the split happens from the other side
+        let (first_start_offset, second_start_offset) = if state.reversed {
+            let region_samples = OriginalSamples::new(unstretch_samples(
+                tempo_map.duration_samples(start, end - start, clip.reader.sample_rate_original()),
+                state.stretch,
+            ));
+            (
+                state.start_offset + region_samples.saturating_sub(skipped_samples),
+                state.start_offset,
+            )
+        } else {
+            (state.start_offset, state.start_offset + skipped_samples)
+        };
+
+        if !self.cut_is_free(clip_key, at, at) {
+            return Err(SplitAudioClipError::InCrossfade);
+        }
+
+        // Reserve the new keys before freeing the old one, so it is not reused
+        let first_key = self.clip_key_generator.next().unwrap();
+        let second_key = self.clip_key_generator.next().unwrap();
+
+        let first = AudioClipState {
+            key: first_key,
+            length: Some(first_length),
+            start_offset: first_start_offset,
+            fade_out: Timestamp::zero(),
+            ..state.clone()
+        };
+        let second = AudioClipState {
+            key: second_key,
+            start: at,
+            length: Some(end - at),
+            start_offset: second_start_offset,
+            gain_envelope: envelope_from(&state.gain_envelope, first_samples),
+            fade_in: Timestamp::zero(),
+            crossfade: Timestamp::zero(),
+            ..state
+        };
+
+        self.delete_audio_clip(clip_key).unwrap();
+        self.add_audio_clips_inner(track_key, &[first, second])
+            .expect("Split clips overlap with other clips");
+
+        Ok((first_key, second_key))
+    }
+
+    /// Whether the clip could be replaced by the part of it before `first_end` and the part after `second_start`.
+    ///
+    /// Neither part may end up covered by a clip crossfading with the original one.
+    fn cut_is_free(
+        &self,
+        clip_key: AudioClipKey,
+        first_end: Timestamp,
+        second_start: Timestamp,
+    ) -> bool {
+        let tempo_map = &*self.tempo_map;
+        let track = &self.tracks[&self.clip_to_track[&clip_key]];
+        let clip = &track.clips[&clip_key];
+        let end = clip.end(tempo_map);
+        track.is_free(clip_key, clip.start, first_end, clip.crossfade, tempo_map)
+            && track.is_free(clip_key, second_start, end, Timestamp::zero(), tempo_map)
+    }
+
+    /// Move all clips starting at or after `at` on every track `length` later.
+    ///
+    /// Clips starting before `at` stay where they are, even if they end after it.
+    pub fn insert_time(
+        &mut self,
+        at: Timestamp,
+        length: Timestamp,
+    ) -> Result<(), MoveAudioClipError> {
+        let moved: Vec<AudioClipKey> = self
+            .tracks
+            .values()
+            .flat_map(|track| track.clips.values())
+            .filter(|clip| clip.start >= at)
+            .map(|clip| clip.key)
+            .collect();
+        self.move_audio_clips(&moved, length, Direction::Forward)
+    }
+
+    /// Remove everything between `start` and `end` on every track, and move all later clips back to fill the gap.
+    ///
+    /// Clips crossing either end of the range are split there, and only the parts outside the range are kept.
+    pub fn delete_time(&mut self, start: Timestamp, end: Timestamp) -> Result<(), DeleteTimeError> {
+        if start >= end {
+            return Err(DeleteTimeError::EmptyRange);
+        }
+
+        let tempo_map = &*self.tempo_map;
+        let mut across_start: Vec<AudioClipKey> = Vec::new();
+        let mut across_end: Vec<AudioClipKey> = Vec::new();
+        for track in self.tracks.values() {
+            across_start.extend(track.clips_across(start, tempo_map));
+            across_end.extend(track.clips_across(end, tempo_map));
+        }
+
+        // Check every split up front, so nothing is changed if any of them fail
+        let is_free = across_start.iter().all(|&key| {
+            let second_start = if across_end.contains(&key) {
+                end
+            } else {
+                start
+            };
+            self.cut_is_free(key, start, second_start)
+        }) && across_end
+            .iter()
+            .filter(|&key| !across_start.contains(key))
+            .all(|&key| self.cut_is_free(key, end, end));
+        if !is_free {
+            return Err(DeleteTimeError::InCrossfade);
+        }
+
+        for key in across_start {
+            let (_, second_key) = self
+                .split_audio_clip(key, start)
+                .expect("Clip could not be split at the start of the range");
+            // A clip crossing both ends continues as the second half
+            if let Some(end_key) = across_end.iter_mut().find(|end_key| **end_key == key) {
+                *end_key = second_key;
+            }
+        }
+        for key in across_end {
+            self.split_audio_clip(key, end)
+                .expect("Clip could not be split at the end of the range");
+        }
+
+        let clips = self.tracks.values().flat_map(|track| track.clips.values());
+        let deleted: Vec<AudioClipKey> = clips
+            .clone()
+            .filter(|clip| start <= clip.start && clip.start < end)
+            .map(|clip| clip.key)
+            .collect();
+        let moved: Vec<AudioClipKey> = clips
+            .filter(|clip| end <= clip.start)
+            .map(|clip| clip.key)
+            .collect();
+
+        let _ = self
+            .delete_audio_clips(deleted)
+            .expect("Clips in the range were invalid");
+        self.move_audio_clips(&moved, end - start, Direction::Backward)
+            .expect("Clips after the range overlap once moved back");
+
+        Ok(())
+    }
+
+    pub fn merge_audio_clips(
+        &mut self,
+        clip_keys: &[AudioClipKey],
+    ) -> Result<AudioClipKey, MergeAudioClipsError> {
+        if clip_keys.len() < 2 {
+            return Err(MergeAudioClipsError::TooFewClips);
+        }
+
+        let mut clips = clip_keys
+            .iter()
+            .map(|&clip_key| self.audio_clip(clip_key))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(MergeAudioClipsError::InvalidClip)?;
+        let track_key = self.clip_to_track[&clip_keys[0]];
+        if clip_keys
+            .iter()
+            .any(|clip_key| self.clip_to_track[clip_key] != track_key)
+        {
+            return Err(MergeAudioClipsError::DifferentTracks);
+        }
+        clips.sort_by_key(|clip| clip.start);
+
+        let tempo_map = &*self.tempo_map;
+        let sample_rate = clips[0].reader.sample_rate_original();
+        let clips: Vec<(AudioClipState, Timestamp)> = clips
+            .into_iter()
+            .map(|clip| (clip.state(), clip.end(tempo_map)))
+            .collect();
+        // The number of samples of the source clip played by the clip
+        let region_samples = |(state, end): &(AudioClipState, Timestamp)| {
+            OriginalSamples::new(unstretch_samples(
+                tempo_map.duration_samples(state.start, *end - state.start, sample_rate),
+                state.stretch,
+            ))
+        };
+
+        for pair in clips.windows(2) {
+            let [(a, a_end), (b, _)] = pair else {
+                unreachable!()
+            };
+            if b.start != *a_end {
+                return Err(MergeAudioClipsError::NotContiguous);
+            }
+
+            let matching = a.inner == b.inner
+                && a.reversed == b.reversed
+                && a.active == b.active
+                && a.gain.to_bits() == b.gain.to_bits()
+                && a.stretch.to_bits() == b.stretch.to_bits()
+                && a.pitch.to_bits() == b.pitch.to_bits()
+                && a.fade_out == Timestamp::zero()
+                && b.fade_in == Timestamp::zero()
+                && b.crossfade == Timestamp::zero();
+            // A reversed clip plays the end of its region first, so the later clip is the one read first
+            let (first_offset, second_offset, skipped) = if a.reversed {
+                (b.start_offset, a.start_offset, region_samples(&pair[1]))
+            } else {
+                (a.start_offset, b.start_offset, region_samples(&pair[0]))
+            };
+            // The offsets are rounded to whole samples, so they may be one sample apart
+            let continuous =
+                usize::from(first_offset + skipped).abs_diff(second_offset.into()) <= 1;
+            if !(matching && continuous) {
+                return Err(MergeAudioClipsError::NotContinuous);
+            }
+        }
+
+        let (first, _) = &clips[0];
+        let (last, end) = clips.last().unwrap();
+        let gain_envelope = if clips
+            .iter()
+            .all(|(state, _)| state.gain_envelope.is_empty())
+        {
+            Vec::new()
+        } else {
+            let mut gain_envelope = Vec::new();
+            for (i, (state, clip_end)) in clips.iter().enumerate() {
+                let offset =
+                    tempo_map.duration_samples(first.start, state.start - first.start, sample_rate);
+                let envelope = if state.gain_envelope.is_empty() {
+                    vec![(0, 1.0)]
+                } else {
+                    state.gain_envelope.clone()
+                };
+                // Breakpoints past the end of a clip would otherwise leak into the next one
+                let envelope = if i < clips.len() - 1 {
+                    let length = tempo_map.duration_samples(
+                        state.start,
+                        *clip_end - state.start,
+                        sample_rate,
+                    );
+                    envelope_until(&envelope, length)
+                } else {
+                    envelope
+                };
+                gain_envelope.extend(
+                    envelope
+                        .into_iter()
+                        .map(|(point, gain)| (point + offset, gain)),
+                );
+            }
+            gain_envelope
+        };
+
+        let merged = AudioClipState {
+            key: self.clip_key_generator.next().unwrap(),
+            length: Some(*end - first.start),
+            start_offset: if first.reversed {
+                last.start_offset
+            } else {
+                first.start_offset
+            },
+            gain_envelope,
+            fade_out: last.fade_out,
+            ..first.clone()
+        };
+
+        for (state, _) in &clips {
+            self.delete_audio_clip(state.key).unwrap();
+        }
+        self.add_audio_clips_inner(track_key, std::slice::from_ref(&merged))
+            .expect("Merged clip overlaps with other clips");
+
+        Ok(merged.key)
+    }
+
+    pub fn audio_clip_set_name(
+        &mut self,
+        clip_key: AudioClipKey,
+        name: String,
+    ) -> Result<(), InvalidAudioClipError> {
+        self.audio_clip_mut(clip_key)?.name = name;
+        Ok(())
+    }
+
+    pub fn audio_clip_set_color(
+        &mut self,
+        clip_key: AudioClipKey,
+        color: u32,
+    ) -> Result<(), InvalidAudioClipError> {
+        self.audio_clip_mut(clip_key)?.color = color;
+        Ok(())
+    }
+
+    pub fn audio_clip_set_gain(
+        &mut self,
+        clip_key: AudioClipKey,
+        gain: f32,
+    ) -> Result<(), InvalidAudioClipError> {
+        let clip = self.audio_clip_mut(clip_key)?;
+        clip.gain = gain;
+        let clip_start = clip.start;
+
+        let track_key = *self.clip_to_track.get(&clip_key).unwrap();
+        self.event_sender.send(Event::SetAudioClipGain {
+            track_key,
+            clip_start,
+            gain,
         });
 
         Ok(())
     }
-    pub fn audio_clip_crop_end(
+
+    /// Replace the breakpoints of the clip's gain envelope, which are sorted by position.
+    pub fn audio_clip_set_gain_envelope(
         &mut self,
         clip_key: AudioClipKey,
-        new_length: Timestamp,
+        mut gain_envelope: Vec<(usize, f32)>,
+    ) -> Result<(), InvalidAudioClipError> {
+        gain_envelope.sort_by_key(|&(position, _)| position);
+
+        let clip = self.audio_clip_mut(clip_key)?;
+        clip.gain_envelope = gain_envelope.clone();
+        let clip_start = clip.start;
+
+        let track_key = *self.clip_to_track.get(&clip_key).unwrap();
+        self.event_sender.send(Event::SetAudioClipGainEnvelope {
+            track_key,
+            clip_start,
+            gain_envelope: DBox::new(gain_envelope),
+        });
+
+        Ok(())
+    }
+
+    pub fn audio_clip_set_fade_in(
+        &mut self,
+        clip_key: AudioClipKey,
+        fade_in: Timestamp,
+    ) -> Result<(), InvalidAudioClipError> {
+        let fade_out = self.audio_clip(clip_key)?.fade_out;
+        self.audio_clip_set_fades(clip_key, fade_in, fade_out)
+    }
+    pub fn audio_clip_set_fade_out(
+        &mut self,
+        clip_key: AudioClipKey,
+        fade_out: Timestamp,
+    ) -> Result<(), InvalidAudioClipError> {
+        let fade_in = self.audio_clip(clip_key)?.fade_in;
+        self.audio_clip_set_fades(clip_key, fade_in, fade_out)
+    }
+    fn audio_clip_set_fades(
+        &mut self,
+        clip_key: AudioClipKey,
+        fade_in: Timestamp,
+        fade_out: Timestamp,
+    ) -> Result<(), InvalidAudioClipError> {
+        let clip = self.audio_clip_mut(clip_key)?;
+        clip.fade_in = fade_in;
+        clip.fade_out = fade_out;
+        let clip_start = clip.start;
+
+        let track_key = *self.clip_to_track.get(&clip_key).unwrap();
+        self.event_sender.send(Event::SetAudioClipFades {
+            track_key,
+            clip_start,
+            fade_in,
+            fade_out,
+        });
+
+        Ok(())
+    }
+
+    pub fn audio_clip_set_active(
+        &mut self,
+        clip_key: AudioClipKey,
+        active: bool,
+    ) -> Result<(), InvalidAudioClipError> {
+        let clip = self.audio_clip_mut(clip_key)?;
+        clip.active = active;
+        let clip_start = clip.start;
+
+        let track_key = *self.clip_to_track.get(&clip_key).unwrap();
+        self.event_sender.send(Event::SetAudioClipActive {
+            track_key,
+            clip_start,
+            active,
+        });
+
+        Ok(())
+    }
+
+    pub fn audio_clip_set_end_behavior(
+        &mut self,
+        clip_key: AudioClipKey,
+        end_behavior: EndBehavior,
+    ) -> Result<(), InvalidAudioClipError> {
+        let clip = self.audio_clip_mut(clip_key)?;
+        clip.end_behavior = end_behavior;
+        let clip_start = clip.start;
+
+        let track_key = *self.clip_to_track.get(&clip_key).unwrap();
+        self.event_sender.send(Event::SetAudioClipEndBehavior {
+            track_key,
+            clip_start,
+            end_behavior,
+        });
+
+        Ok(())
+    }
+
+    pub fn audio_clip_set_reversed(
+        &mut self,
+        clip_key: AudioClipKey,
+        reversed: bool,
+    ) -> Result<(), ReverseAudioClipError> {
+        let clip = self
+            .audio_clip_mut(clip_key)
+            .map_err(ReverseAudioClipError::InvalidClip)?;
+        if clip.reader.streamed() {
+            return Err(ReverseAudioClipError::Streamed);
+        }
+        clip.reversed = reversed;
+        let clip_start = clip.start;
+
+        let track_key = *self.clip_to_track.get(&clip_key).unwrap();
+        self.event_sender.send(Event::SetAudioClipReversed {
+            track_key,
+            clip_start,
+            reversed,
+        });
+
+        Ok(())
+    }
+
+    pub fn audio_clip_set_stretch(
+        &mut self,
+        clip_key: AudioClipKey,
+        stretch: f32,
     ) -> Result<(), MoveAudioClipError> {
         if !self.clip_key_generator.in_use(clip_key) {
             return Err(MoveAudioClipError::InvalidClip(InvalidAudioClipError {
                 clip_key,
             }));
         }
+        let stretch = stretch.clamp(MIN_STRETCH, MAX_STRETCH);
 
         let track_key = *self.clip_to_track.get(&clip_key).unwrap();
         let track = self.tracks.get_mut(&track_key).unwrap();
         let clip = track.clips.get(&clip_key).unwrap();
 
+        let tempo_map = &*self.tempo_map;
+        let original_sample_rate = clip.reader.sample_rate_original();
         let clip_start = clip.start;
-        let new_end = clip_start + new_length;
-
-        // Check for overlaps
-        for other_clip in track.clips.values() {
-            let same = other_clip.key == clip.key;
-            let overlapping = clip_start < other_clip.start && other_clip.start < new_end;
-            if !same && overlapping {
-                return Err(MoveAudioClipError::Overlapping);
-            }
+
+        // The same region of the source clip is kept, so a set length is scaled along with the stretch
+        let new_length = clip.set_length.map(|length| {
+            let region = unstretch_samples(
+                tempo_map.duration_samples(clip_start, length, original_sample_rate),
+                clip.stretch,
+            );
+            tempo_map.duration(
+                clip_start,
+                stretch_samples(region, stretch),
+                original_sample_rate,
+            )
+        });
+        let new_end = clip_start
+            + new_length.unwrap_or_else(|| clip.natural_length(clip_start, stretch, tempo_map));
+
+        if !track.is_free(
+            clip_key,
+            clip_start,
+            new_end,
+            clip.crossfade,
+            &*self.tempo_map,
+        ) {
+            return Err(MoveAudioClipError::Overlapping);
         }
 
         let clip_mut = track.clips.get_mut(&clip_key).unwrap();
-        clip_mut.set_length = Some(new_length);
+        clip_mut.stretch = stretch;
+        clip_mut.set_length = new_length;
 
-        self.event_sender.send(Event::CropAudioClipEnd {
+        self.event_sender.send(Event::SetAudioClipStretch {
             track_key,
             clip_start,
+            stretch,
             new_length,
         });
 
         Ok(())
     }
 
+    pub fn audio_clip_set_pitch(
+        &mut self,
+        clip_key: AudioClipKey,
+        pitch: f32,
+    ) -> Result<(), InvalidAudioClipError> {
+        let pitch = pitch.clamp(-MAX_PITCH, MAX_PITCH);
+        let clip = self.audio_clip_mut(clip_key)?;
+        clip.pitch = pitch;
+        let clip_start = clip.start;
+        let stored_clip_key = clip.reader.key();
+
+        // The resampler has to be rebuilt, which can't be done on the audio thread
+        let reader = self
+            .clip_store
+            .reader_pitched(stored_clip_key, pitch)
+            .expect("Audio clip refers to an invalid stored clip");
+
+        let track_key = *self.clip_to_track.get(&clip_key).unwrap();
+        self.event_sender.send(Event::SetAudioClipReader {
+            track_key,
+            clip_start,
+            reader: DBox::new(reader),
+        });
+
+        Ok(())
+    }
+
+    pub fn set_crossfade(
+        &mut self,
+        clip_a: AudioClipKey,
+        clip_b: AudioClipKey,
+        length: Timestamp,
+    ) -> Result<(), CrossfadeError> {
+        let a_start = self
+            .audio_clip(clip_a)
+            .map_err(CrossfadeError::InvalidClip)?
+            .start;
+        let b = self
+            .audio_clip(clip_b)
+            .map_err(CrossfadeError::InvalidClip)?;
+        let b_start = b.start;
+        let old_crossfade = b.crossfade;
+
+        let track_key = *self.clip_to_track.get(&clip_a).unwrap();
+        let same_track = *self.clip_to_track.get(&clip_b).unwrap() == track_key;
+        let between = self.tracks[&track_key]
+            .clips
+            .values()
+            .any(|clip| a_start < clip.start && clip.start < b_start);
+        if !same_track || b_start <= a_start || between {
+            return Err(CrossfadeError::NotAdjacent);
+        }
+
+        // The second clip stays in place, while the first one is extended or cropped to overlap it
+        self.audio_clip_mut(clip_b).unwrap().crossfade = length;
+        match self.audio_clip_crop_end(clip_a, b_start + length - a_start) {
+            Ok(()) => Ok(()),
+            Err(MoveAudioClipError::Overlapping) => {
+                self.audio_clip_mut(clip_b).unwrap().crossfade = old_crossfade;
+                Err(CrossfadeError::Overlapping)
+            }
+            Err(MoveAudioClipError::InvalidClip(_) | MoveAudioClipError::OutOfBounds) => {
+                unreachable!()
+            }
+        }
+    }
+
     pub fn add_track(
         &mut self,
         output: MixerTrackKey,
@@ -756,7 +2414,7 @@ impl Timeline {
             output,
             Arc::clone(&self.position),
             self.sample_rate,
-            self.bpm_cents,
+            Arc::clone(&self.tempo_map),
         );
 
         let event = self
@@ -798,7 +2456,7 @@ impl Timeline {
                         output,
                         Arc::clone(&self.position),
                         self.sample_rate,
-                        self.bpm_cents,
+                        Arc::clone(&self.tempo_map),
                     )),
                 )
             })
@@ -855,6 +2513,11 @@ impl Timeline {
             key,
             TimelineTrack {
                 output_track: state.output_track,
+                name: state.name.clone(),
+                color: state.color,
+                overlap_policy: state.overlap_policy,
+                test_tone: state.test_tone,
+                noise: state.noise,
                 clips: HashMap::from_iter(state.clips.iter().map(|clip_state| {
                     (
                         clip_state.key,
@@ -863,6 +2526,18 @@ impl Timeline {
                             start: clip_state.start,
                             set_length: clip_state.length,
                             start_offset: clip_state.start_offset,
+                            gain: clip_state.gain,
+                            gain_envelope: clip_state.gain_envelope.clone(),
+                            fade_in: clip_state.fade_in,
+                            fade_out: clip_state.fade_out,
+                            reversed: clip_state.reversed,
+                            stretch: clip_state.stretch,
+                            pitch: clip_state.pitch,
+                            crossfade: clip_state.crossfade,
+                            active: clip_state.active,
+                            end_behavior: clip_state.end_behavior,
+                            name: clip_state.name.clone(),
+                            color: clip_state.color,
                             reader: self
                                 .clip_store
                                 .reader(clip_state.inner)
@@ -877,17 +2552,30 @@ impl Timeline {
             state.output_track,
             Arc::clone(&self.position),
             self.sample_rate,
-            self.bpm_cents,
+            Arc::clone(&self.tempo_map),
         );
+        timeline_track.set_layered(state.overlap_policy == OverlapPolicy::Layer);
+        timeline_track.set_test_tone(state.test_tone);
+        timeline_track.set_noise(state.noise);
         for clip_state in state.clips.iter() {
-            timeline_track.insert_clip(Box::new(TreeNode::new(AudioClipProcessor::new(
-                clip_state.start,
-                clip_state.length,
-                clip_state.start_offset,
-                self.clip_store
-                    .reader(clip_state.inner)
-                    .expect("An invalid audio clip was referenced"),
-            ))));
+            timeline_track.insert_clip(Box::new(TreeNode::new(
+                AudioClipProcessor::new(
+                    clip_state.key,
+                    clip_state.start,
+                    clip_state.length,
+                    clip_state.start_offset,
+                    clip_state.gain,
+                    self.clip_store
+                        .reader_pitched(clip_state.inner, clip_state.pitch)
+                        .expect("An invalid audio clip was referenced"),
+                )
+                .with_gain_envelope(clip_state.gain_envelope.clone())
+                .with_fades(clip_state.fade_in, clip_state.fade_out)
+                .with_reversed(clip_state.reversed)
+                .with_stretch(clip_state.stretch)
+                .with_active(clip_state.active)
+                .with_end_behavior(clip_state.end_behavior),
+            )));
         }
 
         let event = self
@@ -911,15 +2599,24 @@ impl Timeline {
         }
 
         let tracks = states.iter().map(|state| {
-            self.tracks
-                .insert(state.key, TimelineTrack::new(state.output_track));
-
-            DBox::new(TimelineTrackProcessor::new(
+            let mut track = TimelineTrack::new(state.output_track);
+            track.name = state.name.clone();
+            track.color = state.color;
+            track.overlap_policy = state.overlap_policy;
+            track.test_tone = state.test_tone;
+            track.noise = state.noise;
+            self.tracks.insert(state.key, track);
+
+            let mut track_processor = TimelineTrackProcessor::new(
                 state.output_track,
                 Arc::clone(&self.position),
                 self.sample_rate,
-                self.bpm_cents,
-            ))
+                Arc::clone(&self.tempo_map),
+            );
+            track_processor.set_layered(state.overlap_policy == OverlapPolicy::Layer);
+            track_processor.set_test_tone(state.test_tone);
+            track_processor.set_noise(state.noise);
+            DBox::new(track_processor)
         });
 
         let event = self
@@ -928,12 +2625,139 @@ impl Timeline {
         self.event_sender.send(Event::Track(event));
     }
 
-    pub fn key_in_use(&self, key: TimelineTrackKey) -> bool {
-        self.track_key_generator.in_use(key)
+    pub fn key_in_use(&self, key: TimelineTrackKey) -> bool {
+        self.track_key_generator.in_use(key)
+    }
+
+    pub fn remaining_keys(&self) -> u32 {
+        self.track_key_generator.remaining_keys()
+    }
+
+    fn track_mut(
+        &mut self,
+        key: TimelineTrackKey,
+    ) -> Result<&mut TimelineTrack, InvalidTimelineTrackError> {
+        self.tracks
+            .get_mut(&key)
+            .ok_or(InvalidTimelineTrackError { key })
+    }
+
+    pub fn track_name(&self, key: TimelineTrackKey) -> Result<&str, InvalidTimelineTrackError> {
+        self.tracks
+            .get(&key)
+            .map(|track| track.name.as_str())
+            .ok_or(InvalidTimelineTrackError { key })
+    }
+    pub fn set_track_name(
+        &mut self,
+        key: TimelineTrackKey,
+        name: String,
+    ) -> Result<(), InvalidTimelineTrackError> {
+        self.track_mut(key)?.name = name;
+        Ok(())
+    }
+
+    pub fn track_color(&self, key: TimelineTrackKey) -> Result<u32, InvalidTimelineTrackError> {
+        self.tracks
+            .get(&key)
+            .map(|track| track.color)
+            .ok_or(InvalidTimelineTrackError { key })
+    }
+    pub fn set_track_color(
+        &mut self,
+        key: TimelineTrackKey,
+        color: u32,
+    ) -> Result<(), InvalidTimelineTrackError> {
+        self.track_mut(key)?.color = color;
+        Ok(())
+    }
+
+    pub fn track_overlap_policy(
+        &self,
+        key: TimelineTrackKey,
+    ) -> Result<OverlapPolicy, InvalidTimelineTrackError> {
+        self.tracks
+            .get(&key)
+            .map(|track| track.overlap_policy)
+            .ok_or(InvalidTimelineTrackError { key })
+    }
+    /// Fails if the clips already on the track overlap in a way the new policy does not allow.
+    pub fn set_track_overlap_policy(
+        &mut self,
+        key: TimelineTrackKey,
+        policy: OverlapPolicy,
+    ) -> Result<(), SetOverlapPolicyError> {
+        let tempo_map = &*self.tempo_map;
+        let track = self
+            .tracks
+            .get_mut(&key)
+            .ok_or(SetOverlapPolicyError::InvalidTimelineTrack(key))?;
+
+        let old_policy = track.overlap_policy;
+        track.overlap_policy = policy;
+        let is_free = track.clips.values().all(|clip| {
+            track.is_free(
+                clip.key,
+                clip.start,
+                clip.end(tempo_map),
+                clip.crossfade,
+                tempo_map,
+            )
+        });
+        if !is_free {
+            track.overlap_policy = old_policy;
+            return Err(SetOverlapPolicyError::Overlapping);
+        }
+
+        self.event_sender.send(Event::SetTrackLayered {
+            track_key: key,
+            layered: policy == OverlapPolicy::Layer,
+        });
+        Ok(())
+    }
+
+    pub fn track_test_tone(
+        &self,
+        key: TimelineTrackKey,
+    ) -> Result<Option<TestToneSettings>, InvalidTimelineTrackError> {
+        self.tracks
+            .get(&key)
+            .map(|track| track.test_tone)
+            .ok_or(InvalidTimelineTrackError { key })
+    }
+    pub fn set_track_test_tone(
+        &mut self,
+        key: TimelineTrackKey,
+        settings: Option<TestToneSettings>,
+    ) -> Result<(), InvalidTimelineTrackError> {
+        self.track_mut(key)?.test_tone = settings;
+        self.event_sender.send(Event::SetTrackTestTone {
+            track_key: key,
+            settings,
+        });
+        Ok(())
     }
 
-    pub fn remaining_keys(&self) -> u32 {
-        self.track_key_generator.remaining_keys()
+    pub fn track_noise(
+        &self,
+        key: TimelineTrackKey,
+    ) -> Result<Option<NoiseSettings>, InvalidTimelineTrackError> {
+        self.tracks
+            .get(&key)
+            .map(|track| track.noise)
+            .ok_or(InvalidTimelineTrackError { key })
+    }
+    pub fn set_track_noise(
+        &mut self,
+        key: TimelineTrackKey,
+        settings: Option<NoiseSettings>,
+    ) -> Result<(), InvalidTimelineTrackError> {
+        self.track_mut(key)?.noise = settings;
+        self.event_sender.send(Event::SetTrackNoise {
+            track_key: key,
+            settings,
+        });
+        Ok(())
     }
 
     pub fn track_state(
@@ -945,49 +2769,87 @@ impl Timeline {
         }
 
         let track = self.tracks.get(&key).unwrap();
-        let clips = track.clips.values().map(|clip| clip.state()).collect();
+        let mut clips: Vec<AudioClipState> =
+            track.clips.values().map(|clip| clip.state()).collect();
+        clips.sort_by_key(|clip| clip.key);
         let output_track = track.output_track;
 
         Ok(TimelineTrackState {
             key,
             clips,
             output_track,
+            name: track.name.clone(),
+            color: track.color,
+            overlap_policy: track.overlap_policy,
+            test_tone: track.test_tone,
+            noise: track.noise,
         })
     }
 
     pub fn state(&self) -> TimelineState {
+        let mut track_keys: Vec<TimelineTrackKey> = self.tracks.keys().copied().collect();
+        track_keys.sort();
+
         TimelineState {
-            bpm_cents: self.bpm_cents,
+            bpm_cents: self.tempo_map.initial_bpm_cents(),
+            tempo_changes: self.tempo_map.changes().to_vec(),
+            time_signature: self.time_signatures.initial(),
+            time_signature_changes: self.time_signatures.changes().to_vec(),
+            loop_region: self.loop_region,
+            markers: self.markers.iter().cloned().collect(),
             audio_clip_store: self.clip_store.state(),
-            tracks: self
-                .tracks
-                .keys()
-                .map(|&key| self.track_state(key).unwrap())
+            tracks: track_keys
+                .into_iter()
+                .map(|key| self.track_state(key).unwrap())
                 .collect(),
         }
     }
 }
 
+const NO_BUFFER_MSG: &str = "No buffer found for output track";
+
 pub struct TimelineProcessor {
     sample_rate: u32,
-    bpm_cents: u16,
+    tempo_map: DBox<Arc<TempoMap>>,
+    time_signatures: DBox<Arc<TimeSignatureMap>>,
+    loop_region: Option<(Timestamp, Timestamp)>,
+    /// The only clip that is heard, if any.
+    soloed_clip: Option<AudioClipKey>,
 
     playing: Arc<AtomicBool>,
+    /// Whether `playing` was set during the last output, so the start of playback can be detected.
+    was_playing: bool,
+    /// Is `Some` while counting in, before the playhead starts moving.
+    count_in: Option<CountIn>,
+    counting_in: Arc<AtomicBool>,
     position: Arc<AtomicUsize>,
+    preview: Option<Preview>,
 
     tracks: RemotePushedHashMap<TimelineTrackKey, DBox<TimelineTrackProcessor>>,
+    metronome: MetronomeProcessor,
 
     event_receiver: ringbuffer::Receiver<Event>,
+    /// Limits the time spent on a single poll, leaving the rest for the next one.
+    max_events_per_poll: usize,
 }
 impl TimelineProcessor {
+    pub fn set_max_events_per_poll(&mut self, max_events_per_poll: usize) {
+        self.max_events_per_poll = max_events_per_poll;
+    }
+
     pub fn poll(&mut self) {
-        for _ in 0..256 {
+        for _ in 0..self.max_events_per_poll {
             let event_option = self.event_receiver.recv();
             match event_option {
                 None => break,
 
                 Some(event) => match event {
-                    Event::JumpTo(pos) => self.jump_to(pos),
+                    Event::JumpTo(pos) => {
+                        // Jumping ends the preview where it is
+                        self.preview = None;
+                        self.jump_to(pos);
+                    }
+                    Event::Preview { position, samples } => self.preview(position, samples),
                     Event::Track(event) => self.tracks.process_event(event),
                     Event::AddClip { track_key, clip } => self.add_clip(track_key, clip),
                     Event::AddClips { track_key, clips } => self.add_clips(track_key, clips),
@@ -1003,6 +2865,7 @@ impl TimelineProcessor {
                         old_start,
                         new_start,
                     } => self.move_audio_clip(track_key, old_start, new_start),
+                    Event::MoveAudioClips { moves } => self.move_audio_clips(moves),
                     Event::MoveAudioClipToTrack {
                         old_track_key,
                         new_track_key,
@@ -1031,20 +2894,123 @@ impl TimelineProcessor {
                         track_key,
                         clip_start,
                         new_length,
-                    } => self.crop_audio_clip_end(track_key, clip_start, new_length),
+                        new_start_offset,
+                    } => self.crop_audio_clip_end(
+                        track_key,
+                        clip_start,
+                        new_length,
+                        new_start_offset,
+                    ),
+                    Event::CropAudioClipsStart { crops } => self.crop_audio_clips_start(crops),
+                    Event::CropAudioClipsEnd { crops } => self.crop_audio_clips_end(crops),
+                    Event::SetAudioClipGain {
+                        track_key,
+                        clip_start,
+                        gain,
+                    } => self.set_audio_clip_gain(track_key, clip_start, gain),
+                    Event::SetAudioClipGainEnvelope {
+                        track_key,
+                        clip_start,
+                        mut gain_envelope,
+                    } => {
+                        self.set_audio_clip_gain_envelope(track_key, clip_start, &mut gain_envelope)
+                    }
+                    Event::SetAudioClipFades {
+                        track_key,
+                        clip_start,
+                        fade_in,
+                        fade_out,
+                    } => self.set_audio_clip_fades(track_key, clip_start, fade_in, fade_out),
+                    Event::SetAudioClipReversed {
+                        track_key,
+                        clip_start,
+                        reversed,
+                    } => self.set_audio_clip_reversed(track_key, clip_start, reversed),
+                    Event::SetAudioClipActive {
+                        track_key,
+                        clip_start,
+                        active,
+                    } => self.set_audio_clip_active(track_key, clip_start, active),
+                    Event::SetAudioClipEndBehavior {
+                        track_key,
+                        clip_start,
+                        end_behavior,
+                    } => self.set_audio_clip_end_behavior(track_key, clip_start, end_behavior),
+                    Event::SetAudioClipStretch {
+                        track_key,
+                        clip_start,
+                        stretch,
+                        new_length,
+                    } => self.set_audio_clip_stretch(track_key, clip_start, stretch, new_length),
+                    Event::SetAudioClipReader {
+                        track_key,
+                        clip_start,
+                        mut reader,
+                    } => self.set_audio_clip_reader(track_key, clip_start, &mut reader),
+                    Event::SetTrackLayered { track_key, layered } => {
+                        self.set_track_layered(track_key, layered)
+                    }
+                    Event::SetTrackTestTone {
+                        track_key,
+                        settings,
+                    } => self.set_track_test_tone(track_key, settings),
+                    Event::SetTrackNoise {
+                        track_key,
+                        settings,
+                    } => self.set_track_noise(track_key, settings),
+                    Event::SetTempoMap(tempo_map) => self.set_tempo_map(tempo_map),
+                    // The old map is dropped on another thread
+                    Event::SetTimeSignatures(time_signatures) => {
+                        self.time_signatures = time_signatures
+                    }
+                    Event::SetLoopRegion(loop_region) => self.loop_region = loop_region,
+                    Event::SoloAudioClip(clip_key) => self.soloed_clip = clip_key,
                 },
             }
         }
     }
 
+    fn preview(&mut self, position: Timestamp, samples: usize) {
+        if self.playing.load(Ordering::Relaxed) {
+            return;
+        }
+        // A new preview during another one still returns to where the first one started
+        let return_to = match self.preview {
+            Some(Preview { return_to, .. }) => return_to,
+            None => self.position.load(Ordering::Relaxed),
+        };
+        self.preview = Some(Preview {
+            remaining: samples,
+            return_to,
+        });
+        self.jump_to(position);
+    }
+
     fn jump_to(&mut self, pos: Timestamp) {
-        let pos_samples = pos.samples(self.sample_rate, self.bpm_cents);
+        let pos_samples = self.tempo_map.samples(pos, self.sample_rate);
+        self.jump_to_samples(pos_samples);
+    }
+    fn jump_to_samples(&mut self, pos_samples: usize) {
         self.position.store(pos_samples, Ordering::Relaxed);
         for track in self.tracks.values_mut() {
             track.jump();
         }
     }
 
+    /// Replace the tempo map, keeping the playhead at the same position on the timeline.
+    fn set_tempo_map(&mut self, tempo_map: DBox<Arc<TempoMap>>) {
+        let pos_samples = self.position.load(Ordering::Relaxed);
+        let position = self.tempo_map.timestamp(pos_samples, self.sample_rate);
+
+        for track in self.tracks.values_mut() {
+            track.set_tempo_map(Arc::clone(&tempo_map));
+        }
+        // The old map is dropped on another thread
+        self.tempo_map = tempo_map;
+
+        self.jump_to(position);
+    }
+
     fn add_clip(
         &mut self,
         track_key: TimelineTrackKey,
@@ -1108,6 +3074,12 @@ impl TimelineProcessor {
         track.move_clip(old_start, new_start);
     }
 
+    fn move_audio_clips(&mut self, moves: DBox<Vec<(TimelineTrackKey, Timestamp, Timestamp)>>) {
+        for &(track_key, old_start, new_start) in moves.iter() {
+            self.move_audio_clip(track_key, old_start, new_start);
+        }
+    }
+
     pub fn move_audio_clip_to_track(
         &mut self,
         old_track_key: TimelineTrackKey,
@@ -1151,50 +3123,423 @@ impl TimelineProcessor {
         track_key: TimelineTrackKey,
         clip_start: Timestamp,
         new_length: Timestamp,
+        new_start_offset: OriginalSamples,
+    ) {
+        let track = self
+            .tracks
+            .get_mut(&track_key)
+            .expect("Track doesn't exist");
+
+        track.crop_clip_end(clip_start, new_length, new_start_offset);
+    }
+
+    fn crop_audio_clips_start(&mut self, mut crops: DBox<Vec<StartCrop>>) {
+        // All clips are taken out before any are put back,
+        // so a new start can never collide with the old start of another clip
+        for StartCrop { crop, clip } in crops.iter_mut() {
+            let track = self
+                .tracks
+                .get_mut(&crop.track_key)
+                .expect("Track doesn't exist");
+            let taken = track.take_clip(crop.old_start);
+
+            let mut clip_ref = (*taken).borrow_mut();
+            clip_ref.start = crop.new_start;
+            clip_ref.length = Some(crop.new_length);
+            clip_ref.start_offset = crop.new_start_offset;
+            drop(clip_ref);
+
+            *clip = Some(taken);
+        }
+
+        for StartCrop { crop, clip } in crops.iter_mut() {
+            let track = self
+                .tracks
+                .get_mut(&crop.track_key)
+                .expect("Track doesn't exist");
+            track.insert_clip(clip.take().unwrap());
+        }
+    }
+
+    fn crop_audio_clips_end(&mut self, crops: DBox<Vec<Crop>>) {
+        // The starts don't change, so the order doesn't matter
+        for crop in crops.iter() {
+            self.crop_audio_clip_end(
+                crop.track_key,
+                crop.old_start,
+                crop.new_length,
+                crop.new_start_offset,
+            );
+        }
+    }
+
+    pub fn set_audio_clip_gain(
+        &mut self,
+        track_key: TimelineTrackKey,
+        clip_start: Timestamp,
+        gain: f32,
+    ) {
+        let track = self
+            .tracks
+            .get_mut(&track_key)
+            .expect("Track doesn't exist");
+
+        track.set_clip_gain(clip_start, gain);
+    }
+
+    /// Swaps the envelope into the clip, leaving the old one in `gain_envelope`, so it is dropped off the audio thread.
+    pub fn set_audio_clip_gain_envelope(
+        &mut self,
+        track_key: TimelineTrackKey,
+        clip_start: Timestamp,
+        gain_envelope: &mut Vec<(usize, f32)>,
+    ) {
+        let track = self
+            .tracks
+            .get_mut(&track_key)
+            .expect("Track doesn't exist");
+
+        track.swap_clip_gain_envelope(clip_start, gain_envelope);
+    }
+
+    pub fn set_audio_clip_fades(
+        &mut self,
+        track_key: TimelineTrackKey,
+        clip_start: Timestamp,
+        fade_in: Timestamp,
+        fade_out: Timestamp,
+    ) {
+        let track = self
+            .tracks
+            .get_mut(&track_key)
+            .expect("Track doesn't exist");
+
+        track.set_clip_fades(clip_start, fade_in, fade_out);
+    }
+
+    pub fn set_audio_clip_reversed(
+        &mut self,
+        track_key: TimelineTrackKey,
+        clip_start: Timestamp,
+        reversed: bool,
+    ) {
+        let track = self
+            .tracks
+            .get_mut(&track_key)
+            .expect("Track doesn't exist");
+
+        track.set_clip_reversed(clip_start, reversed);
+    }
+
+    pub fn set_audio_clip_active(
+        &mut self,
+        track_key: TimelineTrackKey,
+        clip_start: Timestamp,
+        active: bool,
+    ) {
+        let track = self
+            .tracks
+            .get_mut(&track_key)
+            .expect("Track doesn't exist");
+
+        track.set_clip_active(clip_start, active);
+    }
+
+    pub fn set_audio_clip_end_behavior(
+        &mut self,
+        track_key: TimelineTrackKey,
+        clip_start: Timestamp,
+        end_behavior: EndBehavior,
+    ) {
+        let track = self
+            .tracks
+            .get_mut(&track_key)
+            .expect("Track doesn't exist");
+
+        track.set_clip_end_behavior(clip_start, end_behavior);
+    }
+
+    pub fn set_audio_clip_stretch(
+        &mut self,
+        track_key: TimelineTrackKey,
+        clip_start: Timestamp,
+        stretch: f32,
+        new_length: Option<Timestamp>,
+    ) {
+        let track = self
+            .tracks
+            .get_mut(&track_key)
+            .expect("Track doesn't exist");
+
+        track.set_clip_stretch(clip_start, stretch, new_length);
+    }
+
+    /// Swaps the reader of the clip with `reader`, leaving the old one in its place.
+    pub fn set_audio_clip_reader(
+        &mut self,
+        track_key: TimelineTrackKey,
+        clip_start: Timestamp,
+        reader: &mut AudioClipReader,
+    ) {
+        let track = self
+            .tracks
+            .get_mut(&track_key)
+            .expect("Track doesn't exist");
+
+        track.swap_clip_reader(clip_start, reader);
+    }
+
+    fn set_track_layered(&mut self, track_key: TimelineTrackKey, layered: bool) {
+        let track = self
+            .tracks
+            .get_mut(&track_key)
+            .expect("Track doesn't exist");
+
+        track.set_layered(layered);
+    }
+
+    fn set_track_test_tone(
+        &mut self,
+        track_key: TimelineTrackKey,
+        settings: Option<TestToneSettings>,
     ) {
         let track = self
             .tracks
             .get_mut(&track_key)
             .expect("Track doesn't exist");
 
-        track.crop_clip_end(clip_start, new_length);
+        track.set_test_tone(settings);
+    }
+
+    fn set_track_noise(&mut self, track_key: TimelineTrackKey, settings: Option<NoiseSettings>) {
+        let track = self
+            .tracks
+            .get_mut(&track_key)
+            .expect("Track doesn't exist");
+
+        track.set_noise(settings);
     }
 
     pub fn output(
         &mut self,
         mixer_ins: &mut HashMap<MixerTrackKey, DBox<Vec<Sample>>>,
         info: &Info,
+    ) {
+        self.output_playback(mixer_ins, info);
+
+        // Generated sources are heard whether or not the timeline is playing, but not while a clip is soloed
+        if self.soloed_clip.is_some() {
+            return;
+        }
+        for track in self.tracks.values_mut() {
+            let key = track.output_track();
+            let buffer = &mut mixer_ins.get_mut(&key).expect(NO_BUFFER_MSG)[..];
+            track.output_generators(info, buffer);
+        }
+    }
+
+    /// Output the clips and metronome, following the playhead.
+    fn output_playback(
+        &mut self,
+        mixer_ins: &mut HashMap<MixerTrackKey, DBox<Vec<Sample>>>,
+        info: &Info,
     ) {
         let Info {
             sample_rate: _,
             buffer_size,
         } = *info;
 
-        const NO_BUFFER_MSG: &str = "No buffer found for output track";
+        let playing = self.playing.load(Ordering::Relaxed);
+        let started = playing && !self.was_playing;
+        self.was_playing = playing;
+        if playing {
+            // Playback takes over from where the preview was started
+            if let Some(Preview { return_to, .. }) = self.preview.take() {
+                self.jump_to_samples(return_to);
+            }
+        }
+        if started {
+            self.start_count_in();
+        } else if !playing {
+            // Pausing during the count-in cancels it
+            self.count_in = None;
+        }
+        self.counting_in
+            .store(self.count_in.is_some(), Ordering::Release);
+
+        if !playing && self.preview.is_none() {
+            self.silence(mixer_ins, 0, buffer_size);
+            return;
+        }
+
+        // Only the metronome is heard during the count-in, and the playhead stays in place
+        let mut progress = 0;
+        if let Some(count_in) = self.count_in {
+            progress = min(buffer_size, count_in.length - count_in.progress);
+            self.silence(mixer_ins, 0, progress);
+            self.metronome.render_count_in(
+                count_in.beat_length,
+                count_in.time_signature,
+                count_in.progress,
+                0,
+                progress,
+            );
+
+            self.count_in = Some(CountIn {
+                progress: count_in.progress + progress,
+                ..count_in
+            })
+            .filter(|count_in| count_in.progress < count_in.length);
+            self.counting_in
+                .store(self.count_in.is_some(), Ordering::Release);
+        }
+
+        // Split the buffer into segments, so the playhead can wrap around at the end of the loop,
+        // or stop at the end of the preview
+        while progress < buffer_size {
+            let position = self.position.load(Ordering::Relaxed);
+            // Previews play straight through the loop region
+            let loop_region = self.loop_region.filter(|_| self.preview.is_none());
+            let loop_samples = loop_region.and_then(|(start, end)| {
+                let start = self.tempo_map.samples(start, self.sample_rate);
+                let end = self.tempo_map.samples(end, self.sample_rate);
+                // Loops are only respected when the playhead is inside of them
+                (start < end && position < end).then_some((start, end))
+            });
+
+            let segment_size = match loop_samples {
+                Some((_, end)) => min(buffer_size - progress, end - position),
+                None => buffer_size - progress,
+            };
+            let segment_size = match self.preview {
+                Some(Preview { remaining, .. }) => min(segment_size, remaining),
+                None => segment_size,
+            };
+            let segment_info = Info {
+                sample_rate: info.sample_rate,
+                buffer_size: segment_size,
+            };
+
+            for track in self.tracks.values_mut() {
+                let key = track.output_track();
+                let buffer = &mut mixer_ins.get_mut(&key).expect(NO_BUFFER_MSG)
+                    [progress * CHANNELS..(progress + segment_size) * CHANNELS];
+                track.output(&segment_info, buffer, self.soloed_clip)
+            }
+            self.metronome.render(
+                &self.tempo_map,
+                &self.time_signatures,
+                position,
+                progress,
+                segment_size,
+            );
+            self.position.fetch_add(segment_size, Ordering::Relaxed);
+            progress += segment_size;
+
+            if let Some((start, end)) = loop_samples {
+                if position + segment_size == end {
+                    self.jump_to_samples(start);
+                }
+            }
 
-        if !self.playing.load(Ordering::Relaxed) {
-            for track in self.tracks.values() {
-                let buffer = &mut mixer_ins
-                    .get_mut(&track.output_track())
-                    .expect(NO_BUFFER_MSG)[..buffer_size * CHANNELS];
-                buffer.fill(0.0);
+            if let Some(preview) = &mut self.preview {
+                preview.remaining -= segment_size;
+                if preview.remaining == 0 {
+                    let return_to = preview.return_to;
+                    self.preview = None;
+                    self.jump_to_samples(return_to);
+                    self.silence(mixer_ins, progress, buffer_size - progress);
+                    return;
+                }
             }
+        }
+    }
+
+    /// Start counting in from the playhead, if a count-in is set.
+    fn start_count_in(&mut self) {
+        let beats = self.metronome.count_in();
+        if beats == 0 {
+            self.count_in = None;
             return;
         }
 
-        for track in self.tracks.values_mut() {
-            let key = track.output_track();
-            let buffer =
-                &mut mixer_ins.get_mut(&key).expect(NO_BUFFER_MSG)[..buffer_size * CHANNELS];
-            track.output(info, buffer)
+        let position = self.playhead_position();
+        let bpm_cents = self.tempo_map.bpm_cents_at(position);
+        let beat_length = Timestamp::from_beats(1).samples(self.sample_rate, bpm_cents);
+        self.count_in = Some(CountIn {
+            progress: 0,
+            length: beats as usize * beat_length,
+            beat_length,
+            time_signature: self.time_signatures.at(position),
+        });
+    }
+
+    /// Output silence for `buffer_size` frames from `offset`.
+    fn silence(
+        &mut self,
+        mixer_ins: &mut HashMap<MixerTrackKey, DBox<Vec<Sample>>>,
+        offset: usize,
+        buffer_size: usize,
+    ) {
+        for track in self.tracks.values() {
+            let buffer = &mut mixer_ins
+                .get_mut(&track.output_track())
+                .expect(NO_BUFFER_MSG)[offset * CHANNELS..(offset + buffer_size) * CHANNELS];
+            buffer.fill(0.0);
         }
-        self.position.fetch_add(buffer_size, Ordering::Relaxed);
+        self.metronome.silence(offset, buffer_size);
+    }
+
+    /// The metronome clicks of the last buffer output by [`Self::output`].
+    pub fn playhead_position(&self) -> Timestamp {
+        self.tempo_map
+            .timestamp(self.position.load(Ordering::Relaxed), self.sample_rate)
     }
+
+    pub fn metronome_output(&self, buffer_size: usize) -> &[Sample] {
+        self.metronome.output(buffer_size)
+    }
+}
+
+/// The clicks before playback starts, at the tempo and time signature of the playhead.
+#[derive(Debug, Clone, Copy)]
+struct CountIn {
+    /// Frames played of the count-in.
+    progress: usize,
+    /// Frames in total.
+    length: usize,
+    beat_length: usize,
+    time_signature: TimeSignature,
+}
+
+/// A short snippet being played by [`Timeline::preview_at`].
+#[derive(Debug, Clone, Copy)]
+struct Preview {
+    /// Frames left to play.
+    remaining: usize,
+    /// Where the playhead was before the preview.
+    return_to: usize,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TimelineState {
+    /// The tempo at the start of the timeline.
     pub bpm_cents: u16,
+    /// Changes of tempo later on the timeline, sorted by position.
+    #[serde(default)]
+    pub tempo_changes: Vec<(Timestamp, u16)>,
+    /// The time signature at the start of the timeline.
+    #[serde(default)]
+    pub time_signature: TimeSignature,
+    /// Changes of time signature later on the timeline, sorted by position.
+    #[serde(default)]
+    pub time_signature_changes: Vec<(Timestamp, TimeSignature)>,
+    /// The start and end of the region that playback loops within, if any.
+    #[serde(default)]
+    pub loop_region: Option<(Timestamp, Timestamp)>,
+    /// Sorted by position.
+    #[serde(default)]
+    pub markers: Vec<Marker>,
     pub audio_clip_store: AudioClipStoreState,
     pub tracks: Vec<TimelineTrackState>,
 }
@@ -1203,6 +3548,11 @@ impl Default for TimelineState {
     fn default() -> Self {
         Self {
             bpm_cents: 120_00,
+            tempo_changes: Vec::new(),
+            time_signature: TimeSignature::default(),
+            time_signature_changes: Vec::new(),
+            loop_region: None,
+            markers: Vec::new(),
             audio_clip_store: Default::default(),
             tracks: Default::default(),
         }
@@ -1227,6 +3577,11 @@ impl PartialEq for TimelineState {
         );
 
         self.bpm_cents == other.bpm_cents
+            && self.tempo_changes == other.tempo_changes
+            && self.time_signature == other.time_signature
+            && self.time_signature_changes == other.time_signature_changes
+            && self.loop_region == other.loop_region
+            && self.markers == other.markers
             && self.audio_clip_store == other.audio_clip_store
             && self_set == other_set
     }
@@ -1259,10 +3614,19 @@ impl Display for InvalidTimelineTrackError {
 }
 impl Error for InvalidTimelineTrackError {}
 
+/// Which way to move along the timeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Forward,
+    Backward,
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum AddClipError {
     InvalidTimelineTrack(TimelineTrackKey),
     InvalidClip(StoredAudioClipKey),
+    /// The audio clip being duplicated does not exist.
+    InvalidAudioClip(AudioClipKey),
     Overlapping,
 }
 impl Display for AddClipError {
@@ -1272,6 +3636,7 @@ impl Display for AddClipError {
                 write!(f, "No timeline track with key, {key:?}, on timeline")
             }
             Self::InvalidClip(key) => write!(f, "No stored audio clip with key, {key:?}"),
+            Self::InvalidAudioClip(key) => write!(f, "No clip with key, {key:?}, on the timeline"),
             Self::Overlapping => write!(f, "Clip overlaps with another clip"),
         }
     }
@@ -1315,6 +3680,9 @@ impl From<AddClipError> for AudioClipReconstructionError {
             AddClipError::InvalidTimelineTrack(key) => Self::InvalidTrack(key),
             AddClipError::InvalidClip(key) => Self::InvalidStoredClip(key),
             AddClipError::Overlapping => Self::Overlapping,
+            AddClipError::InvalidAudioClip(_) => {
+                unreachable!("Reconstructing a clip does not refer to other clips")
+            }
         }
     }
 }
@@ -1345,17 +3713,202 @@ impl Error for AudioClipReconstructionError {}
 pub enum MoveAudioClipError {
     InvalidClip(InvalidAudioClipError),
     Overlapping,
+    /// The clip would be moved past the start or end of the timeline.
+    OutOfBounds,
 }
 impl Display for MoveAudioClipError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             MoveAudioClipError::InvalidClip(e) => Display::fmt(e, f),
             MoveAudioClipError::Overlapping => write!(f, "Clip overlaps with another clip"),
+            MoveAudioClipError::OutOfBounds => {
+                write!(f, "Clip would be moved outside of the timeline")
+            }
         }
     }
 }
 impl Error for MoveAudioClipError {}
 
+/// Changing the tempo would make the clips with the given keys overlap other clips,
+/// since clips without a set length keep their length in samples.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TempoOverlapError {
+    pub keys: Vec<AudioClipKey>,
+}
+impl Display for TempoOverlapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let keys = &self.keys;
+        write!(f, "Changing the tempo would make clips overlap: {keys:?}")
+    }
+}
+impl Error for TempoOverlapError {}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RemoveTempoChangeError {
+    InvalidTempoChange(InvalidTempoChangeError),
+    Overlapping(TempoOverlapError),
+}
+impl From<InvalidTempoChangeError> for RemoveTempoChangeError {
+    fn from(err: InvalidTempoChangeError) -> Self {
+        Self::InvalidTempoChange(err)
+    }
+}
+impl From<TempoOverlapError> for RemoveTempoChangeError {
+    fn from(err: TempoOverlapError) -> Self {
+        Self::Overlapping(err)
+    }
+}
+impl Display for RemoveTempoChangeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidTempoChange(e) => Display::fmt(e, f),
+            Self::Overlapping(e) => Display::fmt(e, f),
+        }
+    }
+}
+impl Error for RemoveTempoChangeError {}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidLoopRegionError {
+    pub start: Timestamp,
+    pub end: Timestamp,
+}
+impl Display for InvalidLoopRegionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let start = self.start;
+        let end = self.end;
+        write!(
+            f,
+            "Loop region must end after it starts, but was {start:?} to {end:?}"
+        )
+    }
+}
+impl Error for InvalidLoopRegionError {}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SplitAudioClipError {
+    InvalidClip(InvalidAudioClipError),
+    /// The split position is not strictly inside the clip.
+    OutOfBounds,
+    /// The split position is inside a crossfade with another clip.
+    InCrossfade,
+}
+impl Display for SplitAudioClipError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SplitAudioClipError::InvalidClip(e) => Display::fmt(e, f),
+            SplitAudioClipError::OutOfBounds => {
+                write!(f, "Attempted to split an audio clip outside of its bounds")
+            }
+            SplitAudioClipError::InCrossfade => {
+                write!(f, "Attempted to split an audio clip inside a crossfade")
+            }
+        }
+    }
+}
+impl Error for SplitAudioClipError {}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DeleteTimeError {
+    /// The range does not end after it starts.
+    EmptyRange,
+    /// An end of the range is inside a crossfade between two clips.
+    InCrossfade,
+}
+impl Display for DeleteTimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeleteTimeError::EmptyRange => write!(f, "Deleted range must end after it starts"),
+            DeleteTimeError::InCrossfade => {
+                write!(f, "Attempted to delete a range ending inside a crossfade")
+            }
+        }
+    }
+}
+impl Error for DeleteTimeError {}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MergeAudioClipsError {
+    InvalidClip(InvalidAudioClipError),
+    /// Fewer than two clips were given.
+    TooFewClips,
+    /// The clips are not all on the same track.
+    DifferentTracks,
+    /// There are gaps or overlaps between the clips.
+    NotContiguous,
+    /// The clips do not play one continuous stretch of the same stored clip with the same settings.
+    NotContinuous,
+}
+impl Display for MergeAudioClipsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MergeAudioClipsError::InvalidClip(e) => Display::fmt(e, f),
+            MergeAudioClipsError::TooFewClips => {
+                write!(f, "Attempted to merge fewer than two audio clips")
+            }
+            MergeAudioClipsError::DifferentTracks => {
+                write!(f, "Attempted to merge audio clips on different tracks")
+            }
+            MergeAudioClipsError::NotContiguous => {
+                write!(
+                    f,
+                    "Attempted to merge audio clips that do not follow each other directly"
+                )
+            }
+            MergeAudioClipsError::NotContinuous => {
+                write!(
+                    f,
+                    "Attempted to merge audio clips that do not continue the same audio"
+                )
+            }
+        }
+    }
+}
+impl Error for MergeAudioClipsError {}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReverseAudioClipError {
+    InvalidClip(InvalidAudioClipError),
+    /// The clip is streamed from disk, which can only be read forwards.
+    Streamed,
+}
+impl Display for ReverseAudioClipError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReverseAudioClipError::InvalidClip(e) => Display::fmt(e, f),
+            ReverseAudioClipError::Streamed => {
+                write!(
+                    f,
+                    "Attempted to reverse an audio clip that is streamed from disk"
+                )
+            }
+        }
+    }
+}
+impl Error for ReverseAudioClipError {}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CrossfadeError {
+    InvalidClip(InvalidAudioClipError),
+    /// The second clip is not the one directly after the first clip on the same track.
+    NotAdjacent,
+    /// The crossfade would make the first clip overlap other clips, or cover the second clip entirely.
+    Overlapping,
+}
+impl Display for CrossfadeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CrossfadeError::InvalidClip(e) => Display::fmt(e, f),
+            CrossfadeError::NotAdjacent => write!(
+                f,
+                "Attempted to crossfade between audio clips that are not adjacent"
+            ),
+            CrossfadeError::Overlapping => write!(f, "Crossfade overlaps with another clip"),
+        }
+    }
+}
+impl Error for CrossfadeError {}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum MoveAudioClipToTrackError {
     InvalidClip(InvalidAudioClipError),
@@ -1375,17 +3928,60 @@ impl Display for MoveAudioClipToTrackError {
 }
 impl Error for MoveAudioClipToTrackError {}
 
+#[derive(Debug, PartialEq, Eq)]
+pub enum RemoveStoredAudioClipError {
+    InvalidClip(StoredAudioClipKey),
+    /// The stored clip is still played by clips on the timeline.
+    InUse(StoredAudioClipKey),
+}
+impl Display for RemoveStoredAudioClipError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidClip(key) => write!(f, "No stored audio clip with key, {key:?}"),
+            Self::InUse(key) => write!(
+                f,
+                "The stored audio clip with key, {key:?}, is used by clips on the timeline"
+            ),
+        }
+    }
+}
+impl Error for RemoveStoredAudioClipError {}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SetOverlapPolicyError {
+    InvalidTimelineTrack(TimelineTrackKey),
+    /// The clips already on the track overlap in a way the policy does not allow.
+    Overlapping,
+}
+impl Display for SetOverlapPolicyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidTimelineTrack(key) => write!(f, "No timeline track with key, {key:?}"),
+            Self::Overlapping => write!(f, "Clips on the track overlap each other"),
+        }
+    }
+}
+impl Error for SetOverlapPolicyError {}
+
 #[cfg(test)]
 mod tests {
     use tests::key_generator::Key;
 
-    use crate::engine::{components::audio_clip_reader::OriginalSamples, utils::test_file_path};
+    use crate::engine::{
+        components::{audio_clip_reader::OriginalSamples, noise::NoiseType, test_tone::Waveform},
+        utils::test_file_path,
+    };
 
     use super::*;
 
     #[test]
     fn add_track() {
-        let (mut tl, mut tlp, ie) = timeline(&TimelineState::default(), 40_000, 10);
+        let (mut tl, mut tlp, ie) = timeline(
+            &TimelineState::default(),
+            40_000,
+            10,
+            ResampleQuality::default(),
+        );
         assert!(ie.is_empty());
 
         for _ in 0..50 {
@@ -1400,9 +3996,114 @@ mod tests {
         assert_eq!(tlp.tracks.len(), 50);
     }
 
+    #[test]
+    fn tempo_change_keeps_playhead() {
+        let (mut tl, mut tlp, ie) = timeline(
+            &TimelineState::default(),
+            48_000,
+            10,
+            ResampleQuality::default(),
+        );
+        assert!(ie.is_empty());
+
+        tl.jump_to(Timestamp::from_beats(4));
+        no_heap! {{
+            tlp.poll();
+        }}
+        // 4 beats at 120 BPM
+        assert_eq!(tlp.position.load(Ordering::Relaxed), 96_000);
+
+        tl.add_tempo_change(Timestamp::from_beats(2), 60_00)
+            .unwrap();
+        no_heap! {{
+            tlp.poll();
+        }}
+        // 2 beats at 120 BPM and 2 beats at 60 BPM
+        assert_eq!(tlp.position.load(Ordering::Relaxed), 144_000);
+        assert_eq!(tl.playhead_position(), Timestamp::from_beats(4));
+    }
+
+    /// A timeline playing a clip without a set length from the start,
+    /// directly followed by a clip with a set length.
+    fn back_to_back_clips() -> (Timeline, TimelineProcessor, AudioClipKey) {
+        let (mut tl, tlp, ie) = timeline(
+            &TimelineState::default(),
+            48_000,
+            1000,
+            ResampleQuality::default(),
+        );
+        assert!(ie.is_empty());
+
+        let ck = tl
+            .import_audio_clip(&test_file_path("48000 16-bit.wav"))
+            .unwrap();
+        let tk = tl.add_track(MixerTrackKey::new(0)).unwrap();
+        let first = tl.add_audio_clip(tk, ck, Timestamp::zero(), None).unwrap();
+        let first_end = tl.audio_clip(first).unwrap().end(tl.tempo_map());
+        tl.add_audio_clip(tk, ck, first_end, Some(Timestamp::from_beats(4)))
+            .unwrap();
+        tl.play();
+        (tl, tlp, first)
+    }
+
+    #[test]
+    fn tempo_change_rejumps_into_clips() {
+        let mut mixer_ins = HashMap::from([(MixerTrackKey::new(0), DBox::new(vec![0.0; 2000]))]);
+        let info = Info {
+            sample_rate: 48_000,
+            buffer_size: 1000,
+        };
+        let mut render = |tlp: &mut TimelineProcessor, buffers| {
+            let mut output = Vec::new();
+            for _ in 0..buffers {
+                tlp.output(&mut mixer_ins, &info);
+                output.extend_from_slice(&mixer_ins[&MixerTrackKey::new(0)]);
+            }
+            output
+        };
+
+        // The first clip lasts until just after beat 55 at 120 BPM
+        let (mut tl, mut tlp, first) = back_to_back_clips();
+        // Beat 53.5
+        tl.jump_to(Timestamp::from_beat_units(53 * 1024 + 512));
+        tlp.poll();
+        render(&mut tlp, 4);
+
+        // Would stretch the first clip over the second one
+        assert!(tl
+            .add_tempo_change(Timestamp::from_beats(53), 150_00)
+            .is_err());
+        assert_eq!(tl.event_queue_stats().pending, 0);
+
+        // Shrinks the first clip to end just after beat 54, with the playhead still inside it
+        let playhead = tl.playhead_position();
+        tl.add_tempo_change(Timestamp::from_beats(53), 60_00)
+            .unwrap();
+        tlp.poll();
+        assert!(playhead < tl.audio_clip(first).unwrap().end(tl.tempo_map()));
+        // Plays the end of the first clip, the gap, and the start of the second one
+        let rejumped = render(&mut tlp, 72);
+
+        // The same as jumping there with the tempo already changed
+        let (mut tl, mut tlp, _) = back_to_back_clips();
+        tl.add_tempo_change(Timestamp::from_beats(53), 60_00)
+            .unwrap();
+        tl.jump_to(playhead);
+        tlp.poll();
+        let jumped = render(&mut tlp, 72);
+
+        assert!(rejumped.iter().any(|&s| s != 0.0));
+        assert_eq!(rejumped, jumped);
+    }
+
     #[test]
     fn add_clip() {
-        let (mut tl, mut tlp, ie) = timeline(&TimelineState::default(), 40_000, 10);
+        let (mut tl, mut tlp, ie) = timeline(
+            &TimelineState::default(),
+            40_000,
+            10,
+            ResampleQuality::default(),
+        );
         assert!(ie.is_empty());
 
         let ck = tl
@@ -1419,7 +4120,12 @@ mod tests {
 
     #[test]
     fn add_overlapping() {
-        let (mut tl, _, ie) = timeline(&TimelineState::default(), 40_000, 10);
+        let (mut tl, _, ie) = timeline(
+            &TimelineState::default(),
+            40_000,
+            10,
+            ResampleQuality::default(),
+        );
         assert!(ie.is_empty());
 
         let ck = tl
@@ -1448,7 +4154,12 @@ mod tests {
 
     #[test]
     fn delete_clip_immediately() {
-        let (mut tl, mut tlp, ie) = timeline(&TimelineState::default(), 40_000, 10);
+        let (mut tl, mut tlp, ie) = timeline(
+            &TimelineState::default(),
+            40_000,
+            10,
+            ResampleQuality::default(),
+        );
         assert!(ie.is_empty());
 
         let ck = tl
@@ -1468,7 +4179,12 @@ mod tests {
 
     #[test]
     fn delete_clip_delayed() {
-        let (mut tl, mut tlp, ie) = timeline(&TimelineState::default(), 40_000, 10);
+        let (mut tl, mut tlp, ie) = timeline(
+            &TimelineState::default(),
+            40_000,
+            10,
+            ResampleQuality::default(),
+        );
         assert!(ie.is_empty());
 
         let ck = tl
@@ -1490,9 +4206,372 @@ mod tests {
         }}
     }
 
+    /// Render `buffers` buffers of 1000 frames from a timeline with a single clip of 4 beats,
+    /// which is optionally split a bit after 1.5 beats.
+    fn render_clip(split: bool, buffers: usize) -> Vec<Sample> {
+        let (mut tl, mut tlp, ie) = timeline(
+            &TimelineState::default(),
+            48_000,
+            1000,
+            ResampleQuality::default(),
+        );
+        assert!(ie.is_empty());
+
+        let ck = tl
+            .import_audio_clip(&test_file_path("48000 16-bit.wav"))
+            .unwrap();
+        let tk = tl.add_track(MixerTrackKey::new(0)).unwrap();
+        let ack = tl
+            .add_audio_clip(
+                tk,
+                ck,
+                Timestamp::from_beats(1),
+                Some(Timestamp::from_beats(4)),
+            )
+            .unwrap();
+        if split {
+            tl.split_audio_clip(ack, Timestamp::from_beat_units(1024 + 1543))
+                .unwrap();
+        }
+        tl.play();
+        tlp.poll();
+
+        let mut mixer_ins = HashMap::from([(MixerTrackKey::new(0), DBox::new(vec![0.0; 2000]))]);
+        let info = Info {
+            sample_rate: 48_000,
+            buffer_size: 1000,
+        };
+        let mut output = Vec::new();
+        for _ in 0..buffers {
+            tlp.output(&mut mixer_ins, &info);
+            output.extend_from_slice(&mixer_ins[&MixerTrackKey::new(0)]);
+        }
+        output
+    }
+
+    #[test]
+    fn split_clip_output_matches() {
+        // Spans the entire clip and a bit of silence after it
+        let unsplit = render_clip(false, 130);
+        let split = render_clip(true, 130);
+        assert_eq!(unsplit, split);
+    }
+
+    /// Render `frames` frames in buffers of 700 frames, starting from beat 1 of a timeline with a clip spanning the first 4 beats.
+    fn render_from_beat_1(
+        loop_region: Option<(Timestamp, Timestamp)>,
+        frames: usize,
+    ) -> Vec<Sample> {
+        let (mut tl, mut tlp, ie) = timeline(
+            &TimelineState::default(),
+            48_000,
+            700,
+            ResampleQuality::default(),
+        );
+        assert!(ie.is_empty());
+
+        let ck = tl
+            .import_audio_clip(&test_file_path("48000 16-bit.wav"))
+            .unwrap();
+        let tk = tl.add_track(MixerTrackKey::new(0)).unwrap();
+        tl.add_audio_clip(tk, ck, Timestamp::zero(), Some(Timestamp::from_beats(4)))
+            .unwrap();
+        tl.set_loop_region(loop_region).unwrap();
+        tl.jump_to(Timestamp::from_beats(1));
+        tl.play();
+        tlp.poll();
+
+        let mut mixer_ins = HashMap::from([(MixerTrackKey::new(0), DBox::new(vec![0.0; 1400]))]);
+        let info = Info {
+            sample_rate: 48_000,
+            buffer_size: 700,
+        };
+        let mut output = Vec::new();
+        while output.len() < frames * CHANNELS {
+            no_heap! {{
+                tlp.output(&mut mixer_ins, &info);
+            }}
+            output.extend_from_slice(&mixer_ins[&MixerTrackKey::new(0)]);
+        }
+        output.truncate(frames * CHANNELS);
+        output
+    }
+
+    #[test]
+    fn loop_wraps_within_buffer() {
+        // One beat is 24000 frames, which is not a multiple of the buffer size
+        let once = render_from_beat_1(None, 24_000);
+        let looped = render_from_beat_1(
+            Some((Timestamp::from_beats(1), Timestamp::from_beats(2))),
+            3 * 24_000,
+        );
+
+        assert_eq!(looped, once.repeat(3));
+    }
+
+    #[test]
+    fn preview_returns_to_playhead() {
+        let (mut tl, mut tlp, ie) = timeline(
+            &TimelineState::default(),
+            48_000,
+            700,
+            ResampleQuality::default(),
+        );
+        assert!(ie.is_empty());
+
+        let ck = tl
+            .import_audio_clip(&test_file_path("48000 16-bit.wav"))
+            .unwrap();
+        let tk = tl.add_track(MixerTrackKey::new(0)).unwrap();
+        tl.add_audio_clip(tk, ck, Timestamp::zero(), Some(Timestamp::from_beats(4)))
+            .unwrap();
+        // Not respected by the preview
+        tl.set_loop_region(Some((
+            Timestamp::zero(),
+            Timestamp::from_beat_units(1024 + 20),
+        )))
+        .unwrap();
+        tl.preview_at(Timestamp::from_beats(1), Duration::from_millis(25));
+        tlp.poll();
+
+        let mut mixer_ins = HashMap::from([(MixerTrackKey::new(0), DBox::new(vec![0.0; 1400]))]);
+        let info = Info {
+            sample_rate: 48_000,
+            buffer_size: 700,
+        };
+        let mut output = Vec::new();
+        for _ in 0..3 {
+            no_heap! {{
+                tlp.output(&mut mixer_ins, &info);
+            }}
+            output.extend_from_slice(&mixer_ins[&MixerTrackKey::new(0)]);
+        }
+
+        // 25 ms is 1200 frames
+        let once = render_from_beat_1(None, 1200);
+        assert_eq!(output[..1200 * CHANNELS], once);
+        assert!(output[1200 * CHANNELS..].iter().all(|&s| s == 0.0));
+        assert_eq!(tlp.position.load(Ordering::Relaxed), 0);
+        assert!(!tl.is_playing());
+    }
+
+    #[test]
+    fn test_tone_while_stopped() {
+        let (mut tl, mut tlp, ie) = timeline(
+            &TimelineState::default(),
+            48_000,
+            700,
+            ResampleQuality::default(),
+        );
+        assert!(ie.is_empty());
+
+        let tk = tl.add_track(MixerTrackKey::new(0)).unwrap();
+        let settings = TestToneSettings {
+            frequency: 12_000.0,
+            waveform: Waveform::Square,
+            amplitude: 0.5,
+        };
+        tl.set_track_test_tone(tk, Some(settings)).unwrap();
+        assert_eq!(tl.track_test_tone(tk), Ok(Some(settings)));
+        assert_eq!(tl.track_state(tk).unwrap().test_tone, Some(settings));
+
+        let mut mixer_ins = HashMap::from([(MixerTrackKey::new(0), DBox::new(vec![0.0; 1400]))]);
+        let info = Info {
+            sample_rate: 48_000,
+            buffer_size: 700,
+        };
+        no_heap! {{
+            tlp.poll();
+            tlp.output(&mut mixer_ins, &info);
+        }}
+        let output = &mixer_ins[&MixerTrackKey::new(0)];
+        assert!(output.iter().all(|&s| s.abs() == 0.5));
+        assert_eq!(tlp.position.load(Ordering::Relaxed), 0);
+
+        tl.set_track_test_tone(tk, None).unwrap();
+        tlp.poll();
+        tlp.output(&mut mixer_ins, &info);
+        assert!(mixer_ins[&MixerTrackKey::new(0)].iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn solo_audio_clip() {
+        let (mut tl, mut tlp, ie) = timeline(
+            &TimelineState::default(),
+            48_000,
+            1000,
+            ResampleQuality::default(),
+        );
+        assert!(ie.is_empty());
+
+        let ck = tl
+            .import_audio_clip(&test_file_path("48000 16-bit.wav"))
+            .unwrap();
+        let mixer_keys = [MixerTrackKey::new(0), MixerTrackKey::new(1)];
+        let tks = mixer_keys.map(|key| tl.add_track(key).unwrap());
+        let acs = tks.map(|tk| {
+            tl.add_audio_clip(tk, ck, Timestamp::zero(), Some(Timestamp::from_beats(1)))
+                .unwrap()
+        });
+        tl.set_track_test_tone(tks[1], Some(TestToneSettings::default()))
+            .unwrap();
+        tl.play();
+
+        let mut mixer_ins = HashMap::from(mixer_keys.map(|key| (key, DBox::new(vec![0.0; 2000]))));
+        let info = Info {
+            sample_rate: 48_000,
+            buffer_size: 1000,
+        };
+        let mut audible = |tl: &mut Timeline| {
+            tl.jump_to(Timestamp::from_beat_units(100));
+            tlp.poll();
+            tlp.output(&mut mixer_ins, &info);
+            mixer_keys.map(|key| mixer_ins[&key].iter().any(|&s| s != 0.0))
+        };
+        assert_eq!(audible(&mut tl), [true, true]);
+
+        tl.solo_audio_clip(Some(acs[0])).unwrap();
+        assert_eq!(tl.soloed_audio_clip(), Some(acs[0]));
+        assert_eq!(audible(&mut tl), [true, false]);
+
+        tl.solo_audio_clip(None).unwrap();
+        assert_eq!(audible(&mut tl), [true, true]);
+
+        tl.solo_audio_clip(Some(acs[1])).unwrap();
+        assert_eq!(audible(&mut tl), [false, true]);
+
+        // Deleting the soloed clip lets everything else be heard again
+        tl.delete_audio_clip(acs[1]).unwrap();
+        assert_eq!(tl.soloed_audio_clip(), None);
+        assert_eq!(
+            tl.solo_audio_clip(Some(acs[1])),
+            Err(InvalidAudioClipError { clip_key: acs[1] })
+        );
+        assert_eq!(audible(&mut tl), [true, true]);
+    }
+
+    #[test]
+    fn noise_while_stopped() {
+        let (mut tl, mut tlp, ie) = timeline(
+            &TimelineState::default(),
+            48_000,
+            700,
+            ResampleQuality::default(),
+        );
+        assert!(ie.is_empty());
+
+        let tk = tl.add_track(MixerTrackKey::new(0)).unwrap();
+        let settings = NoiseSettings {
+            noise_type: NoiseType::White,
+            amplitude: 0.5,
+        };
+        tl.set_track_noise(tk, Some(settings)).unwrap();
+        assert_eq!(tl.track_noise(tk), Ok(Some(settings)));
+        assert_eq!(tl.track_state(tk).unwrap().noise, Some(settings));
+
+        let mut mixer_ins = HashMap::from([(MixerTrackKey::new(0), DBox::new(vec![0.0; 1400]))]);
+        let info = Info {
+            sample_rate: 48_000,
+            buffer_size: 700,
+        };
+        no_heap! {{
+            tlp.poll();
+            tlp.output(&mut mixer_ins, &info);
+        }}
+        let output = &mixer_ins[&MixerTrackKey::new(0)];
+        assert!(output.iter().all(|s| (-0.5..0.5).contains(s)));
+        assert!(output.iter().any(|&s| s != 0.0));
+
+        tl.set_track_noise(tk, None).unwrap();
+        tlp.poll();
+        tlp.output(&mut mixer_ins, &info);
+        assert!(mixer_ins[&MixerTrackKey::new(0)].iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn count_in_holds_playhead() {
+        let render = |count_in: u32, buffers: usize| {
+            let (mut tl, mut tlp, ie) = timeline(
+                &TimelineState::default(),
+                48_000,
+                700,
+                ResampleQuality::default(),
+            );
+            assert!(ie.is_empty());
+
+            let ck = tl
+                .import_audio_clip(&test_file_path("48000 16-bit.wav"))
+                .unwrap();
+            let tk = tl.add_track(MixerTrackKey::new(0)).unwrap();
+            tl.add_audio_clip(tk, ck, Timestamp::zero(), Some(Timestamp::from_beats(4)))
+                .unwrap();
+            tl.metronome().set_count_in(count_in);
+            tl.play();
+            tlp.poll();
+
+            let mut mixer_ins =
+                HashMap::from([(MixerTrackKey::new(0), DBox::new(vec![0.0; 1400]))]);
+            let info = Info {
+                sample_rate: 48_000,
+                buffer_size: 700,
+            };
+            let mut output = Vec::new();
+            for _ in 0..buffers {
+                no_heap! {{
+                    tlp.output(&mut mixer_ins, &info);
+                }}
+                output.extend_from_slice(&mixer_ins[&MixerTrackKey::new(0)]);
+            }
+            (
+                output,
+                tl.is_counting_in(),
+                tlp.position.load(Ordering::Relaxed),
+            )
+        };
+
+        // One beat at 120 BPM is 24 000 frames, which ends within the 35th buffer
+        let (counting, counting_in, position) = render(1, 34);
+        assert!(counting_in);
+        assert_eq!(position, 0);
+        assert!(counting.iter().all(|&s| s == 0.0));
+
+        let (counted, counting_in, position) = render(1, 35);
+        assert!(!counting_in);
+        assert_eq!(position, 500);
+        let (uncounted, _, _) = render(0, 1);
+        assert!(counted[..24_000 * CHANNELS].iter().all(|&s| s == 0.0));
+        assert_eq!(counted[24_000 * CHANNELS..], uncounted[..500 * CHANNELS]);
+    }
+
+    #[test]
+    fn invalid_loop_region() {
+        let (mut tl, _, ie) = timeline(
+            &TimelineState::default(),
+            48_000,
+            10,
+            ResampleQuality::default(),
+        );
+        assert!(ie.is_empty());
+
+        let region = Some((Timestamp::from_beats(2), Timestamp::from_beats(2)));
+        assert_eq!(
+            tl.set_loop_region(region),
+            Err(InvalidLoopRegionError {
+                start: Timestamp::from_beats(2),
+                end: Timestamp::from_beats(2),
+            })
+        );
+        assert_eq!(tl.loop_region(), None);
+    }
+
     #[test]
     fn crop_start_offset_rounding() {
-        let (mut tl, _, ie) = timeline(&TimelineState::default(), 40_000, 10);
+        let (mut tl, _, ie) = timeline(
+            &TimelineState::default(),
+            40_000,
+            10,
+            ResampleQuality::default(),
+        );
         assert!(ie.is_empty());
 
         let ck = tl
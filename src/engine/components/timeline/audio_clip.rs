@@ -1,19 +1,35 @@
 use serde::{Deserialize, Serialize};
 use std::cmp::min;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
 
 use crate::{
     engine::{
         components::audio_clip_reader::{AudioClipReader, OriginalSamples, ResampledSamples},
         info::Info,
-        utils::{key_generator::key_type, rbtree_node},
-        Sample,
+        utils::{key_generator::key_type, rbtree_node, unity_gain},
+        Sample, CHANNELS,
     },
     StoredAudioClipKey, Timestamp,
 };
 
+use super::stretcher::Stretcher;
+use super::Tempo;
+
 // A key for an audio clip, identifying it uniquely across the entire timeline.
 key_type!(pub struct AudioClipKey(u32));
 
+/// What a clip plays once its length reaches past the end of the stored clip.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum EndBehavior {
+    #[default]
+    Silence,
+    /// Hold the last sample of the stored clip.
+    Hold,
+    /// Play the clip's region of the stored clip again from the start.
+    Loop,
+}
+
 // A mirror of `AudioClipProcessor`'s state.
 // Does no synchronization.
 #[derive(Debug)]
@@ -29,6 +45,29 @@ pub struct AudioClip {
     /// Relevant if the start has been trimmed off.
     /// This is in the domain of the source clip's sample rate.
     pub(crate) start_offset: OriginalSamples,
+    /// Linear amplitude multiplier applied to the clip's output.
+    pub(crate) gain: f32,
+    /// Breakpoints of the gain envelope, see [`AudioClipState::gain_envelope`].
+    pub(crate) gain_envelope: Vec<(usize, f32)>,
+    /// Duration of the linear fade at the start of the clip.
+    pub(crate) fade_in: Timestamp,
+    /// Duration of the linear fade at the end of the clip.
+    pub(crate) fade_out: Timestamp,
+    /// Whether the clip's region of the source clip is played backwards.
+    pub(crate) reversed: bool,
+    /// How many times longer the clip plays than the source clip, without changing the pitch.
+    pub(crate) stretch: f32,
+    /// How many semitones the clip is shifted up, without changing the length.
+    pub(crate) pitch: f32,
+    /// How far the start of the clip may overlap the end of the clip before it, crossfading between the two.
+    pub(crate) crossfade: Timestamp,
+    /// Whether the clip is heard. An inactive clip stays in place, but outputs silence.
+    pub(crate) active: bool,
+    /// What is played past the end of the stored clip.
+    pub(crate) end_behavior: EndBehavior,
+    pub(crate) name: String,
+    /// RGBA
+    pub(crate) color: u32,
 
     pub(crate) reader: AudioClipReader,
 }
@@ -36,32 +75,145 @@ impl AudioClip {
     pub fn start(&self) -> Timestamp {
         self.start
     }
-    pub fn length(&self, bpm_cents: u16) -> Timestamp {
-        self.set_length.unwrap_or(Timestamp::from_samples_ceil(
-            self.reader.len_original().into(),
+    pub fn length(&self, tempo: impl Tempo) -> Timestamp {
+        self.length_at(self.start, tempo)
+    }
+    /// The length the clip would have if it started at `start`.
+    ///
+    /// This only differs from [`Self::length`] if the clip has no set length and the tempo changes.
+    pub(crate) fn length_at(&self, start: Timestamp, tempo: impl Tempo) -> Timestamp {
+        self.set_length
+            .unwrap_or_else(|| self.natural_length(start, self.stretch, tempo))
+    }
+    /// The length the clip would have if it had no set length, started at `start` and was stretched by `stretch`.
+    pub(crate) fn natural_length(
+        &self,
+        start: Timestamp,
+        stretch: f32,
+        tempo: impl Tempo,
+    ) -> Timestamp {
+        tempo.duration(
+            start,
+            stretch_samples(self.reader.len_original().into(), stretch),
             self.reader.sample_rate_original(),
-            bpm_cents,
-        ))
+        )
     }
 
-    pub fn end(&self, bpm_cents: u16) -> Timestamp {
-        self.start + self.length(bpm_cents)
+    pub fn end(&self, tempo: impl Tempo) -> Timestamp {
+        self.start + self.length(tempo)
+    }
+    /// The length of the clip in samples at `sample_rate`, as it is currently played.
+    pub fn current_length(&self, sample_rate: u32, tempo: impl Tempo) -> usize {
+        tempo.duration_samples(self.start, self.length(tempo), sample_rate)
     }
 
-    pub fn overlaps(&self, other: &Self, bpm_cents: u16) -> bool {
+    pub fn overlaps(&self, other: &Self, tempo: impl Tempo) -> bool {
         let start1 = self.start;
-        let end1 = self.end(bpm_cents);
+        let end1 = self.end(tempo);
 
         let start2 = other.start;
-        let end2 = other.end(bpm_cents);
+        let end2 = other.end(tempo);
 
         start1 <= start2 && start2 < end1 || start2 <= start1 && start1 < end2
     }
 
-    pub fn stored_clip(&self) -> StoredAudioClipKey {
+    /// The key of the stored clip that this clip plays a region of.
+    pub fn stored_clip_key(&self) -> StoredAudioClipKey {
         self.reader.key()
     }
 
+    /// The sample rate of the stored clip that this clip plays a region of.
+    pub fn source_sample_rate(&self) -> u32 {
+        self.reader.sample_rate_original()
+    }
+
+    /// The number of channels of the stored clip that this clip plays a region of.
+    ///
+    /// The clip is still mixed into the engine's channels during playback.
+    pub fn source_channels(&self) -> usize {
+        self.reader.channels_original()
+    }
+
+    /// Whether the stored clip has a different sample rate than the engine, so it is resampled during playback.
+    ///
+    /// A pitch shifted clip is resampled regardless, see [`Self::pitch`].
+    pub fn resampled(&self) -> bool {
+        self.reader.resampled()
+    }
+
+    /// Where in the stored clip this clip starts, in samples at the stored clip's sample rate.
+    ///
+    /// This is nonzero if the start of the clip has been cropped.
+    pub fn start_offset(&self) -> usize {
+        self.start_offset.into()
+    }
+
+    /// The linear gain applied to the clip.
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+
+    /// The breakpoints of the clip's gain envelope, sorted by position.
+    ///
+    /// Each breakpoint is a position relative to the start of the clip, in samples at the source clip's sample rate,
+    /// and a linear gain, which is applied on top of [`Self::gain`].
+    pub fn gain_envelope(&self) -> &[(usize, f32)] {
+        &self.gain_envelope
+    }
+
+    /// The duration of the fade-in, as it was set.
+    /// If this is longer than the clip, the fade will be cut short during playback.
+    pub fn fade_in(&self) -> Timestamp {
+        self.fade_in
+    }
+    /// The duration of the fade-out, as it was set.
+    /// If this is longer than the clip, the fade will be cut short during playback.
+    pub fn fade_out(&self) -> Timestamp {
+        self.fade_out
+    }
+
+    /// Whether the clip is played backwards.
+    pub fn reversed(&self) -> bool {
+        self.reversed
+    }
+
+    /// How many times longer the clip plays than the source clip, without changing the pitch.
+    pub fn stretch(&self) -> f32 {
+        self.stretch
+    }
+
+    /// How many semitones the clip is shifted up, without changing the length.
+    pub fn pitch(&self) -> f32 {
+        self.pitch
+    }
+
+    /// How far the start of the clip may overlap the end of the clip before it, crossfading between the two.
+    ///
+    /// The actual crossfade is wherever the clips overlap, so it may be shorter than this.
+    pub fn crossfade(&self) -> Timestamp {
+        self.crossfade
+    }
+
+    /// Whether the clip is heard.
+    /// An inactive clip stays on the timeline, but outputs silence.
+    pub fn active(&self) -> bool {
+        self.active
+    }
+
+    /// What the clip plays once its length reaches past the end of the stored clip.
+    pub fn end_behavior(&self) -> EndBehavior {
+        self.end_behavior
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The color of the clip as RGBA, e.g. `0xFF0000FF` for opaque red.
+    pub fn color(&self) -> u32 {
+        self.color
+    }
+
     /// Get the data needed to visualize the waveform of the clip.
     /// This will divide the entirety of the clip into chunks, and for each chunk it will find the minimum and maximum value for each channel.
     ///
@@ -72,12 +224,16 @@ impl AudioClip {
     /// `
     ///
     /// The data will be normalized to fit within the range of a 16-bit signed integer, such that the highest peak in the clip will be at 32767 or -32767.
-    pub fn waveform(&self, bpm_cents: u16) -> &[i16] {
+    pub fn waveform(&self, tempo: impl Tempo) -> &[i16] {
         let start = self.start_offset;
-        let length = OriginalSamples::new(
-            self.length(bpm_cents)
-                .samples(self.reader.sample_rate_original(), bpm_cents),
-        );
+        let length = OriginalSamples::new(unstretch_samples(
+            tempo.duration_samples(
+                self.start,
+                self.length(tempo),
+                self.reader.sample_rate_original(),
+            ),
+            self.stretch,
+        ));
         self.reader.waveform(start, length)
     }
 
@@ -87,6 +243,18 @@ impl AudioClip {
             start_offset: self.start_offset,
             start: self.start,
             length: self.set_length,
+            gain: self.gain,
+            gain_envelope: self.gain_envelope.clone(),
+            fade_in: self.fade_in,
+            fade_out: self.fade_out,
+            reversed: self.reversed,
+            stretch: self.stretch,
+            pitch: self.pitch,
+            crossfade: self.crossfade,
+            active: self.active,
+            end_behavior: self.end_behavior,
+            name: self.name.clone(),
+            color: self.color,
             inner: self.reader.key(),
         }
     }
@@ -94,6 +262,8 @@ impl AudioClip {
 
 #[derive(Debug)]
 pub struct AudioClipProcessor {
+    /// The key of the corresponding [`AudioClip`].
+    pub key: AudioClipKey,
     /// Start on the timeline
     pub start: Timestamp,
     /// Duration on the timeline.
@@ -103,91 +273,460 @@ pub struct AudioClipProcessor {
     /// Relevant if the start has been trimmed off.
     /// This is in the domain of the source clip's sample rate.
     pub start_offset: OriginalSamples,
+    /// Linear amplitude multiplier applied to the output.
+    pub gain: f32,
+    /// Breakpoints of the gain envelope, relative to the start of the clip and in the domain of the source clip's sample rate.
+    pub gain_envelope: Vec<(usize, f32)>,
+    /// Duration of the fade-in, clamped to the length of the clip during output.
+    pub fade_in: Timestamp,
+    /// Duration of the fade-out, clamped to the length of the clip during output.
+    pub fade_out: Timestamp,
+    /// Whether the region of the source clip is read backwards.
+    pub reversed: bool,
+    /// How many times longer the clip plays than the source clip.
+    /// If this is anything but 1, the output goes through the stretcher.
+    pub stretch: f32,
+    /// Whether the output is heard.
+    /// An inactive clip is still read, so it stays in sync with the position.
+    pub active: bool,
+    /// What is output past the end of the source clip, if the length reaches past it.
+    pub end_behavior: EndBehavior,
 
+    /// Plays the clip back faster or slower if it is pitch shifted,
+    /// which the stretcher then compensates for.
     reader: AudioClipReader,
+    stretcher: Stretcher,
+
+    /// Frames output past the end of the source clip, including earlier rounds of a loop.
+    /// Added to the position of the reader to get the position within the clip.
+    tail: usize,
+    /// The last frame read from the source clip, which is held by [`EndBehavior::Hold`].
+    last_frame: [Sample; CHANNELS],
+    /// Output is gathered here when it has to be padded past the end of the source clip.
+    tail_buffer: Vec<Sample>,
 }
 impl AudioClipProcessor {
     pub fn new(
+        key: AudioClipKey,
         start: Timestamp,
         length: Option<Timestamp>,
         start_offset: OriginalSamples,
+        gain: f32,
         reader: AudioClipReader,
     ) -> Self {
         AudioClipProcessor {
+            key,
             start,
             length,
             start_offset,
+            gain,
+            gain_envelope: Vec::new(),
+            fade_in: Timestamp::zero(),
+            fade_out: Timestamp::zero(),
+            reversed: false,
+            stretch: 1.0,
+            active: true,
+            end_behavior: EndBehavior::Silence,
+            stretcher: Stretcher::new(reader.max_buffer_size()),
+            tail: 0,
+            last_frame: [0.0; CHANNELS],
+            tail_buffer: vec![0.0; reader.max_buffer_size() * CHANNELS],
             reader,
         }
     }
 
-    pub fn end(&self, bpm_cents: u16) -> Timestamp {
+    pub fn with_fades(mut self, fade_in: Timestamp, fade_out: Timestamp) -> Self {
+        self.fade_in = fade_in;
+        self.fade_out = fade_out;
+        self
+    }
+
+    pub fn with_gain_envelope(mut self, gain_envelope: Vec<(usize, f32)>) -> Self {
+        self.gain_envelope = gain_envelope;
+        self
+    }
+
+    pub fn with_reversed(mut self, reversed: bool) -> Self {
+        self.reversed = reversed;
+        self
+    }
+
+    pub fn with_stretch(mut self, stretch: f32) -> Self {
+        self.stretch = stretch;
+        self
+    }
+
+    pub fn with_active(mut self, active: bool) -> Self {
+        self.active = active;
+        self
+    }
+
+    pub fn with_end_behavior(mut self, end_behavior: EndBehavior) -> Self {
+        self.end_behavior = end_behavior;
+        self
+    }
+
+    /// Replace the reader, e.g. with one of a different pitch, leaving the old one in `reader`.
+    ///
+    /// Should be followed by a jump, to bring the new reader in place.
+    pub fn swap_reader(&mut self, reader: &mut AudioClipReader) {
+        std::mem::swap(&mut self.reader, reader);
+    }
+
+    /// How much the stretcher has to stretch the output of the reader.
+    fn stretcher_ratio(&self) -> f32 {
+        (self.stretch as f64 * self.reader.playback_speed()) as f32
+    }
+
+    fn stretching(&self) -> bool {
+        self.stretcher_ratio() != 1.0
+    }
+
+    pub fn end(&self, tempo: impl Tempo) -> Timestamp {
         if let Some(length) = self.length {
             self.start + length
         } else {
             self.start
-                + Timestamp::from_samples_ceil(
-                    (self.reader.len_original() - self.start_offset).into(),
+                + tempo.duration(
+                    self.start,
+                    stretch_samples(
+                        (self.reader.len_original() - self.start_offset).into(),
+                        self.stretch,
+                    ),
                     self.reader.sample_rate_original(),
-                    bpm_cents,
                 )
         }
     }
 
+    /// The region of the source clip that is played, in the domain of the source clip's sample rate.
+    fn region(&self, tempo: impl Tempo) -> Range<OriginalSamples> {
+        let len = self.reader.len_original();
+        let end = match self.length {
+            None => len,
+            Some(length) => min(
+                len,
+                self.start_offset
+                    + OriginalSamples::new(unstretch_samples(
+                        tempo.duration_samples(
+                            self.start,
+                            length,
+                            self.reader.sample_rate_original(),
+                        ),
+                        self.stretch,
+                    )),
+            ),
+        };
+        self.start_offset..end
+    }
+
+    /// Tell the reader which region to read backwards, if any.
+    /// Should be called before reading, since the region depends on the tempo.
+    fn update_reversed(&mut self, tempo: impl Tempo) {
+        let region = self.reversed.then(|| self.region(tempo));
+        self.reader.set_reversed_region(region);
+    }
+
     /// Resets the position to the start of the clip.
-    pub fn reset(&mut self, sample_rate: u32) {
+    pub fn reset(&mut self, sample_rate: u32, tempo: impl Tempo) {
+        self.update_reversed(tempo);
         self.reader.jump_original(self.start_offset, sample_rate);
+        self.stretcher.reset(0);
+        self.tail = 0;
     }
 
     /// Jumps to the given position relative to the start of the timeline.
     ///
     /// - If the position is before the start of the clip, the position is set to the start of the clip.
     /// - If the position is after the end of the clip, the position is set to the end of the clip.
-    pub fn jump(&mut self, pos: Timestamp, sample_rate: u32, bpm_cents: u16) {
-        let start_samples = OriginalSamples::new(
-            self.start
-                .samples(self.reader.sample_rate_original(), bpm_cents),
-        );
-        let pos_samples =
-            OriginalSamples::new(pos.samples(self.reader.sample_rate_original(), bpm_cents));
+    pub fn jump(&mut self, pos: Timestamp, sample_rate: u32, tempo: impl Tempo) {
+        self.update_reversed(tempo);
+
+        let original_sample_rate = self.reader.sample_rate_original();
+        let start_samples = OriginalSamples::new(tempo.samples(self.start, original_sample_rate));
+        let pos_samples = OriginalSamples::new(tempo.samples(pos, original_sample_rate));
 
         // Saturating subtraction means that if the position is before the start of the clip,
         // then the clip is reset to 0.
-        let inner_pos = pos_samples.saturating_sub(start_samples) + self.start_offset;
+        let mut offset = pos_samples.saturating_sub(start_samples);
+        let mut output_offset = tempo
+            .samples(pos, sample_rate)
+            .saturating_sub(tempo.samples(self.start, sample_rate));
+
+        // Past the end of the source clip, the position is brought back within it
+        self.tail = 0;
+        let source_length = self.source_length(sample_rate);
+        if 0 < source_length && source_length <= output_offset {
+            match self.end_behavior {
+                EndBehavior::Silence => {}
+                EndBehavior::Hold => {
+                    self.tail = output_offset - source_length;
+                    self.read_last_frame(sample_rate);
+                    self.stretcher.reset(source_length);
+                    return;
+                }
+                EndBehavior::Loop => {
+                    self.tail = output_offset - output_offset % source_length;
+                    output_offset -= self.tail;
+                    offset = ResampledSamples::new(output_offset)
+                        .into_original(sample_rate, original_sample_rate);
+                }
+            }
+        }
+
+        let inner_pos = OriginalSamples::new(unstretch_samples(offset.into(), self.stretch))
+            + self.start_offset;
 
         self.reader.jump_original(inner_pos, sample_rate);
+        self.stretcher.reset(output_offset);
+    }
+
+    /// The number of frames the source clip lasts from the start offset, as it is played at `sample_rate`.
+    fn source_length(&self, sample_rate: u32) -> usize {
+        stretch_samples(
+            (self.reader.len_original() - self.start_offset)
+                .into_resampled(sample_rate, self.reader.sample_rate_original())
+                .into(),
+            self.stretch,
+        )
+    }
+
+    /// Read the last frame of the source clip into `last_frame`, leaving the reader at the end.
+    fn read_last_frame(&mut self, sample_rate: u32) {
+        let len = self.reader.len_original();
+        self.reader
+            .jump_original(len.saturating_sub(OriginalSamples::new(1)), sample_rate);
+        let output = self.reader.output(&Info {
+            sample_rate,
+            buffer_size: 1,
+        });
+        if let Some(frame) = output.get(..CHANNELS) {
+            self.last_frame.copy_from_slice(frame);
+        }
+        self.reader.jump_original(len, sample_rate);
     }
 
-    fn length_samples(&self, sample_rate: u32, bpm_cents: u16) -> ResampledSamples {
+    fn length_samples(&self, sample_rate: u32, tempo: impl Tempo) -> ResampledSamples {
         match self.length {
-            None => (self.reader.len_original() - self.start_offset)
-                .into_resampled(sample_rate, self.reader.sample_rate_original()),
-            Some(length) => ResampledSamples::new(length.samples(sample_rate, bpm_cents)),
+            None => ResampledSamples::new(self.source_length(sample_rate)),
+            Some(length) => {
+                ResampledSamples::new(tempo.duration_samples(self.start, length, sample_rate))
+            }
         }
     }
 
     /// Outputs to a buffer of at most the requested size (via the info parameter).
     /// If the end is reached the returned buffer is smaller.
-    pub fn output(&mut self, bpm_cents: u16, info: &Info) -> &mut [Sample] {
+    pub fn output(&mut self, tempo: impl Tempo, info: &Info) -> &mut [Sample] {
         let Info {
             sample_rate,
             buffer_size,
         } = *info;
 
-        let length = self.length_samples(sample_rate, bpm_cents);
-        let pos = self.reader.position()
-            - self
-                .start_offset
-                .into_resampled(sample_rate, self.reader.sample_rate_original());
+        self.update_reversed(tempo);
+
+        let length = self.length_samples(sample_rate, tempo);
+        let pos = self.source_position(sample_rate) + ResampledSamples::new(self.tail);
         let remaining = length.saturating_sub(pos);
         let capped_buffer_size = min(buffer_size, remaining.into());
 
-        self.reader.output(&Info {
+        let gain = self.gain;
+        let length: usize = length.into();
+        let fade_in = min(
+            tempo.duration_samples(self.start, self.fade_in, sample_rate),
+            length,
+        );
+        let fade_out_start = self.end(tempo).saturating_sub(self.fade_out);
+        let fade_out = min(
+            tempo.duration_samples(fade_out_start, self.fade_out, sample_rate),
+            length,
+        );
+        let pos: usize = pos.into();
+        // Converts from the output sample rate to the domain of the envelope
+        let envelope_ratio = self.reader.sample_rate_original() as f64 / sample_rate as f64;
+
+        let capped_info = Info {
             sample_rate,
             buffer_size: capped_buffer_size,
-        })
+        };
+        let output = match self.end_behavior {
+            EndBehavior::Silence if self.stretching() => {
+                let ratio = self.stretcher_ratio();
+                self.stretcher.output(&mut self.reader, ratio, &capped_info)
+            }
+            EndBehavior::Silence => self.reader.output(&capped_info),
+            EndBehavior::Hold | EndBehavior::Loop => {
+                self.read_padded(tempo, &capped_info);
+                &mut self.tail_buffer[..capped_buffer_size * CHANNELS]
+            }
+        };
+
+        let fading_in = pos < fade_in;
+        let fading_out = fade_out > 0 && length - fade_out < pos + output.len() / CHANNELS;
+        let enveloped = !self.gain_envelope.is_empty();
+        if fading_in || fading_out || enveloped {
+            for (i, frame) in output.chunks_exact_mut(CHANNELS).enumerate() {
+                let frame_pos = pos + i;
+                let frame_gain = gain
+                    * fade_gain(frame_pos, fade_in, length, fade_out)
+                    * envelope_gain(&self.gain_envelope, frame_pos as f64 * envelope_ratio);
+                for sample in frame {
+                    *sample *= frame_gain as Sample;
+                }
+            }
+        } else if gain != 1.0 {
+            for sample in output.iter_mut() {
+                *sample *= gain as Sample;
+            }
+        }
+        output
+    }
+
+    /// How far the source clip has been read since the clip was reset, or since the start of the current loop.
+    fn source_position(&self, sample_rate: u32) -> ResampledSamples {
+        if self.stretching() {
+            ResampledSamples::new(self.stretcher.position())
+        } else {
+            self.reader.position()
+                - self
+                    .start_offset
+                    .into_resampled(sample_rate, self.reader.sample_rate_original())
+        }
     }
+
+    /// Read a full buffer into `tail_buffer`,
+    /// filling in past the end of the source clip as decided by the end behavior.
+    fn read_padded(&mut self, tempo: impl Tempo, info: &Info) {
+        let Info {
+            sample_rate,
+            buffer_size,
+        } = *info;
+
+        let mut filled = 0;
+        let mut restarted = false;
+        while filled < buffer_size {
+            let remaining_info = Info {
+                sample_rate,
+                buffer_size: buffer_size - filled,
+            };
+            let ratio = self.stretcher_ratio();
+            let output = if ratio != 1.0 {
+                self.stretcher
+                    .output(&mut self.reader, ratio, &remaining_info)
+            } else {
+                self.reader.output(&remaining_info)
+            };
+            let read = output.len() / CHANNELS;
+            self.tail_buffer[filled * CHANNELS..(filled + read) * CHANNELS].copy_from_slice(output);
+            if let Some(frame) = output.rchunks_exact(CHANNELS).next() {
+                self.last_frame.copy_from_slice(frame);
+            }
+            filled += read;
+            if filled == buffer_size {
+                break;
+            }
+
+            // The end of the source clip has been reached
+            let rest = &mut self.tail_buffer[filled * CHANNELS..buffer_size * CHANNELS];
+            match self.end_behavior {
+                EndBehavior::Loop if !(restarted && read == 0) => {
+                    // Resetting also clears the tail, which holds the earlier loops
+                    let played = self.tail + usize::from(self.source_position(sample_rate));
+                    self.reset(sample_rate, tempo);
+                    self.tail = played;
+                    restarted = true;
+                }
+                EndBehavior::Hold => {
+                    for frame in rest.chunks_exact_mut(CHANNELS) {
+                        frame.copy_from_slice(&self.last_frame);
+                    }
+                    self.tail += buffer_size - filled;
+                    filled = buffer_size;
+                }
+                // Nothing is left to loop
+                _ => {
+                    rest.fill(0.0);
+                    self.tail += buffer_size - filled;
+                    filled = buffer_size;
+                }
+            }
+        }
+    }
+}
+/// The number of samples that `samples` of the source clip span, when stretched by `stretch`.
+pub(crate) fn stretch_samples(samples: usize, stretch: f32) -> usize {
+    (samples as f64 * stretch as f64).round() as usize
+}
+/// The number of samples of the source clip that span `samples`, when stretched by `stretch`.
+pub(crate) fn unstretch_samples(samples: usize, stretch: f32) -> usize {
+    (samples as f64 / stretch as f64).round() as usize
+}
+
+/// The gain of the linear fades at the frame at `pos`, for a clip of `length` frames.
+fn fade_gain(pos: usize, fade_in: usize, length: usize, fade_out: usize) -> f32 {
+    let mut gain = 1.0;
+    if pos < fade_in {
+        gain *= pos as f32 / fade_in as f32;
+    }
+    let until_end = length.saturating_sub(pos);
+    if until_end < fade_out {
+        gain *= until_end as f32 / fade_out as f32;
+    }
+    gain
+}
+
+/// The gain of the `envelope` at `pos`, linearly interpolated between the breakpoints around it.
+///
+/// Before the first breakpoint the envelope holds its first gain, and after the last breakpoint it holds its last gain.
+/// Without any breakpoints the gain is 1.
+fn envelope_gain(envelope: &[(usize, f32)], pos: f64) -> f32 {
+    let index = envelope.partition_point(|&(point, _)| point as f64 <= pos);
+    match (
+        index.checked_sub(1).map(|i| envelope[i]),
+        envelope.get(index),
+    ) {
+        (None, None) => 1.0,
+        (Some((_, gain)), None) | (None, Some(&(_, gain))) => gain,
+        (Some((start, start_gain)), Some(&(end, end_gain))) => {
+            let progress = (pos - start as f64) / (end - start) as f64;
+            start_gain + (end_gain - start_gain) * progress as f32
+        }
+    }
+}
+
+/// The part of `envelope` from `offset` and on, moved so that `offset` becomes its start.
+///
+/// A breakpoint is inserted at the start, so the envelope keeps its shape.
+pub(crate) fn envelope_from(envelope: &[(usize, f32)], offset: usize) -> Vec<(usize, f32)> {
+    if envelope.is_empty() {
+        return Vec::new();
+    }
+    let index = envelope.partition_point(|&(point, _)| point <= offset);
+    let start = (0, envelope_gain(envelope, offset as f64));
+    std::iter::once(start)
+        .chain(
+            envelope[index..]
+                .iter()
+                .map(|&(point, gain)| (point - offset, gain)),
+        )
+        .collect()
+}
+
+/// The part of `envelope` before `end`.
+///
+/// A breakpoint is inserted at `end`, so the envelope keeps its shape up to it.
+pub(crate) fn envelope_until(envelope: &[(usize, f32)], end: usize) -> Vec<(usize, f32)> {
+    if envelope.is_empty() {
+        return Vec::new();
+    }
+    let index = envelope.partition_point(|&(point, _)| point < end);
+    let last = (end, envelope_gain(envelope, end as f64));
+    envelope[..index]
+        .iter()
+        .copied()
+        .chain(std::iter::once(last))
+        .collect()
 }
+
 impl rbtree_node::Keyed for AudioClipProcessor {
     type Key = Timestamp;
 
@@ -196,11 +735,385 @@ impl rbtree_node::Keyed for AudioClipProcessor {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AudioClipState {
     pub key: AudioClipKey,
     pub start_offset: OriginalSamples,
     pub start: Timestamp,
     pub length: Option<Timestamp>,
+    /// Linear amplitude multiplier. Defaults to unity gain.
+    #[serde(default = "unity_gain")]
+    pub gain: f32,
+    /// Breakpoints of a gain envelope applied on top of `gain`, sorted by position.
+    ///
+    /// Each position is relative to the start of the clip, in samples at the source clip's sample rate.
+    /// The gain is linearly interpolated between the breakpoints, and held before the first and after the last one.
+    #[serde(default)]
+    pub gain_envelope: Vec<(usize, f32)>,
+    #[serde(default = "Timestamp::zero")]
+    pub fade_in: Timestamp,
+    #[serde(default = "Timestamp::zero")]
+    pub fade_out: Timestamp,
+    #[serde(default)]
+    pub reversed: bool,
+    /// How many times longer the clip plays than the source clip. Defaults to no stretch.
+    #[serde(default = "no_stretch")]
+    pub stretch: f32,
+    /// Semitones the clip is shifted up, where the fraction is cents.
+    #[serde(default)]
+    pub pitch: f32,
+    /// How far the start of the clip may overlap the end of the clip before it.
+    #[serde(default = "Timestamp::zero")]
+    pub crossfade: Timestamp,
+    /// Whether the clip is heard. Defaults to active.
+    #[serde(default = "active")]
+    pub active: bool,
+    /// What the clip plays past the end of the stored clip. Defaults to silence.
+    #[serde(default)]
+    pub end_behavior: EndBehavior,
+    #[serde(default)]
+    pub name: String,
+    /// RGBA
+    #[serde(default)]
+    pub color: u32,
     pub inner: StoredAudioClipKey,
 }
+fn no_stretch() -> f32 {
+    1.0
+}
+fn active() -> bool {
+    true
+}
+impl PartialEq for AudioClipState {
+    fn eq(&self, other: &Self) -> bool {
+        // Gains, stretch and pitch are compared bitwise, so that the relation stays reflexive, allowing `Eq`.
+        self.key == other.key
+            && self.start_offset == other.start_offset
+            && self.start == other.start
+            && self.length == other.length
+            && self.gain.to_bits() == other.gain.to_bits()
+            && envelope_bits(&self.gain_envelope).eq(envelope_bits(&other.gain_envelope))
+            && self.fade_in == other.fade_in
+            && self.fade_out == other.fade_out
+            && self.reversed == other.reversed
+            && self.stretch.to_bits() == other.stretch.to_bits()
+            && self.pitch.to_bits() == other.pitch.to_bits()
+            && self.crossfade == other.crossfade
+            && self.active == other.active
+            && self.end_behavior == other.end_behavior
+            && self.name == other.name
+            && self.color == other.color
+            && self.inner == other.inner
+    }
+}
+impl Eq for AudioClipState {}
+fn envelope_bits(envelope: &[(usize, f32)]) -> impl Iterator<Item = (usize, u32)> + '_ {
+    envelope
+        .iter()
+        .map(|&(point, gain)| (point, gain.to_bits()))
+}
+impl Hash for AudioClipState {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key.hash(state);
+        self.start_offset.hash(state);
+        self.start.hash(state);
+        self.length.hash(state);
+        self.gain.to_bits().hash(state);
+        self.gain_envelope.len().hash(state);
+        envelope_bits(&self.gain_envelope).for_each(|point| point.hash(state));
+        self.fade_in.hash(state);
+        self.fade_out.hash(state);
+        self.reversed.hash(state);
+        self.stretch.to_bits().hash(state);
+        self.pitch.to_bits().hash(state);
+        self.crossfade.hash(state);
+        self.active.hash(state);
+        self.end_behavior.hash(state);
+        self.name.hash(state);
+        self.color.hash(state);
+        self.inner.hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env::temp_dir, f32::consts::TAU, sync::Arc};
+
+    use realfft::RealFftPlanner;
+
+    use super::*;
+    use crate::engine::{
+        components::stored_audio_clip::StoredAudioClip, config::ResampleQuality,
+        utils::key_generator::Key,
+    };
+
+    #[test]
+    fn fade_gain_ramps() {
+        assert_eq!(fade_gain(0, 4, 100, 0), 0.0);
+        assert_eq!(fade_gain(2, 4, 100, 0), 0.5);
+        assert_eq!(fade_gain(4, 4, 100, 0), 1.0);
+
+        assert_eq!(fade_gain(95, 0, 100, 4), 1.0);
+        assert_eq!(fade_gain(98, 0, 100, 4), 0.5);
+        assert_eq!(fade_gain(99, 0, 100, 4), 0.25);
+    }
+
+    #[test]
+    fn fade_gain_no_fades() {
+        for pos in 0..100 {
+            assert_eq!(fade_gain(pos, 0, 100, 0), 1.0);
+        }
+    }
+
+    #[test]
+    fn envelope_gain_interpolates() {
+        let envelope = [(100, 0.0), (200, 1.0), (300, 0.5)];
+        assert_eq!(envelope_gain(&[], 50.0), 1.0);
+        assert_eq!(envelope_gain(&envelope, 0.0), 0.0);
+        assert_eq!(envelope_gain(&envelope, 150.0), 0.5);
+        assert_eq!(envelope_gain(&envelope, 200.0), 1.0);
+        assert_eq!(envelope_gain(&envelope, 250.0), 0.75);
+        assert_eq!(envelope_gain(&envelope, 1000.0), 0.5);
+    }
+
+    #[test]
+    fn envelope_from_keeps_shape() {
+        let envelope = [(100, 0.0), (200, 1.0), (300, 0.5)];
+        assert_eq!(envelope_from(&[], 50), []);
+        assert_eq!(
+            envelope_from(&envelope, 150),
+            [(0, 0.5), (50, 1.0), (150, 0.5)]
+        );
+        assert_eq!(envelope_from(&envelope, 200), [(0, 1.0), (100, 0.5)]);
+        assert_eq!(envelope_from(&envelope, 400), [(0, 0.5)]);
+    }
+
+    #[test]
+    fn envelope_until_keeps_shape() {
+        let envelope = [(100, 0.0), (200, 1.0), (300, 0.5)];
+        assert_eq!(envelope_until(&[], 50), []);
+        assert_eq!(envelope_until(&envelope, 150), [(100, 0.0), (150, 0.5)]);
+        assert_eq!(envelope_until(&envelope, 200), [(100, 0.0), (200, 1.0)]);
+        assert_eq!(envelope_until(&envelope, 50), [(50, 0.0)]);
+    }
+
+    #[test]
+    fn gain_envelope_output() {
+        let sample_rate = 48_000;
+        let path = temp_dir().join("adae_audio_clip_envelope_test.wav");
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for _ in 0..4000 {
+            writer.write_sample(1.0_f32).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let stored_clip = StoredAudioClip::import(StoredAudioClipKey::new(0), &path).unwrap();
+        let reader = AudioClipReader::with_quality(
+            Arc::new(stored_clip),
+            1000,
+            sample_rate,
+            0.0,
+            ResampleQuality::Linear,
+        );
+        let mut clip = AudioClipProcessor::new(
+            AudioClipKey::new(0),
+            Timestamp::zero(),
+            None,
+            OriginalSamples::new(0),
+            0.5,
+            reader,
+        )
+        .with_gain_envelope(vec![(1000, 0.0), (3000, 1.0)]);
+        clip.reset(sample_rate, 120);
+
+        let info = Info {
+            sample_rate,
+            buffer_size: 1000,
+        };
+        let mut left: Vec<Sample> = Vec::with_capacity(4000);
+        no_heap! {{
+            for _ in 0..4 {
+                let output = clip.output(120, &info);
+                left.extend(output.iter().step_by(CHANNELS));
+            }
+        }}
+
+        assert_eq!(left.len(), 4000);
+        assert_eq!(left[500], 0.0);
+        assert_eq!(left[2000], 0.25);
+        assert_eq!(left[3500], 0.5);
+    }
+
+    #[test]
+    fn end_behavior_output() {
+        let sample_rate = 48_000;
+        let path = temp_dir().join("adae_audio_clip_end_behavior_test.wav");
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        // One beat at 120 BPM, rising from 0 to 1
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for i in 0..24_000 {
+            writer.write_sample(i as f32 / 24_000.0).unwrap();
+        }
+        writer.finalize().unwrap();
+        let stored_clip =
+            Arc::new(StoredAudioClip::import(StoredAudioClipKey::new(0), &path).unwrap());
+
+        let info = Info {
+            sample_rate,
+            buffer_size: 1000,
+        };
+        let render = |end_behavior, from: Timestamp| {
+            let reader = AudioClipReader::with_quality(
+                Arc::clone(&stored_clip),
+                1000,
+                sample_rate,
+                0.0,
+                ResampleQuality::Linear,
+            );
+            let mut clip = AudioClipProcessor::new(
+                AudioClipKey::new(0),
+                Timestamp::zero(),
+                Some(Timestamp::from_beats(3)),
+                OriginalSamples::new(0),
+                1.0,
+                reader,
+            )
+            .with_end_behavior(end_behavior);
+            clip.jump(from, sample_rate, 120_00);
+
+            let mut left: Vec<Sample> = Vec::with_capacity(72_000);
+            no_heap! {{
+                loop {
+                    let output = clip.output(120_00, &info);
+                    if output.is_empty() {
+                        break;
+                    }
+                    left.extend(output.iter().step_by(CHANNELS));
+                }
+            }}
+            left
+        };
+
+        let last = 23_999.0 / 24_000.0;
+
+        let silence = render(EndBehavior::Silence, Timestamp::zero());
+        assert_eq!(silence.len(), 24_000);
+
+        let hold = render(EndBehavior::Hold, Timestamp::zero());
+        assert_eq!(hold.len(), 72_000);
+        assert_eq!(hold[12_000], 0.5);
+        assert!(hold[24_000..].iter().all(|&s| s == last));
+
+        let looped = render(EndBehavior::Loop, Timestamp::zero());
+        assert_eq!(looped.len(), 72_000);
+        assert_eq!(looped[..24_000], looped[24_000..48_000]);
+        assert_eq!(looped[..24_000], looped[48_000..]);
+
+        // Jumping past the end of the source clip
+        let hold = render(EndBehavior::Hold, Timestamp::from_beats(2));
+        assert_eq!(hold.len(), 24_000);
+        assert!(hold.iter().all(|&s| s == last));
+
+        let looped = render(EndBehavior::Loop, Timestamp::from_beats(2));
+        assert_eq!(looped.len(), 24_000);
+        assert_eq!(looped[12_000], 0.5);
+    }
+
+    #[test]
+    fn fade_gain_overlapping() {
+        // Fades covering the entire clip should multiply
+        assert_eq!(fade_gain(50, 100, 100, 100), 0.25);
+    }
+
+    /// Render two seconds of a 440 Hz sine, shifted `semitones` up,
+    /// returning the number of frames output and the frequency with the most energy.
+    fn pitched_sine(semitones: f32) -> (usize, f32) {
+        let sample_rate = 48_000;
+        let path = temp_dir().join("adae_audio_clip_pitch_test.wav");
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for i in 0..2 * sample_rate {
+            let t = i as f32 / sample_rate as f32;
+            writer.write_sample((TAU * 440.0 * t).sin() * 0.5).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let stored_clip = StoredAudioClip::import(StoredAudioClipKey::new(0), &path).unwrap();
+        let reader = AudioClipReader::with_quality(
+            Arc::new(stored_clip),
+            1000,
+            sample_rate,
+            semitones,
+            ResampleQuality::default(),
+        );
+        let mut clip = AudioClipProcessor::new(
+            AudioClipKey::new(0),
+            Timestamp::zero(),
+            None,
+            OriginalSamples::new(0),
+            1.0,
+            reader,
+        );
+        clip.reset(sample_rate, 120);
+
+        let info = Info {
+            sample_rate,
+            buffer_size: 1000,
+        };
+        let mut left = Vec::new();
+        loop {
+            let output = clip.output(120, &info);
+            if output.is_empty() {
+                break;
+            }
+            left.extend(output.iter().step_by(CHANNELS));
+        }
+
+        // Skip the start, where the resampler is still settling
+        let fft_len = 1 << 16;
+        let mut input = left[8192..8192 + fft_len].to_vec();
+        let fft = RealFftPlanner::<Sample>::new().plan_fft_forward(fft_len);
+        let mut spectrum = fft.make_output_vec();
+        fft.process(&mut input, &mut spectrum).unwrap();
+        let peak = (0..spectrum.len())
+            .max_by(|&a, &b| spectrum[a].norm().total_cmp(&spectrum[b].norm()))
+            .unwrap();
+
+        (
+            left.len(),
+            peak as f32 * sample_rate as f32 / fft_len as f32,
+        )
+    }
+
+    #[test]
+    fn pitch_changes_frequency() {
+        for semitones in [-12.0, -2.5, 0.0, 7.0, 12.0] {
+            let (frames, frequency) = pitched_sine(semitones);
+            let expected = 440.0 * 2_f32.powf(semitones / 12.0);
+
+            // Within a couple of bins of the FFT
+            assert!(
+                (frequency - expected).abs() < 2.0,
+                "Semitones: {semitones}, frequency: {frequency}, expected: {expected}"
+            );
+            // The length is kept
+            assert_eq!(frames, 96_000, "Semitones: {semitones}");
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fmt::Display};
+
+use super::Timestamp;
+use crate::engine::utils::key_generator::{self, key_type, KeyGenerator};
+
+key_type!(pub struct MarkerKey(u32));
+
+/// A named position on the timeline, e.g. the start of a section of the arrangement.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Marker {
+    pub key: MarkerKey,
+    pub position: Timestamp,
+    pub name: String,
+}
+
+/// The markers on the timeline, kept sorted by position.
+#[derive(Debug)]
+pub struct Markers {
+    key_generator: KeyGenerator<MarkerKey>,
+    /// Sorted by position, where markers at the same position are in the order they were added.
+    markers: Vec<Marker>,
+}
+impl Markers {
+    pub fn new(states: &[Marker]) -> Self {
+        let mut markers = states.to_vec();
+        markers.sort_by_key(|marker| marker.position);
+        Markers {
+            key_generator: KeyGenerator::from_iter(markers.iter().map(|marker| marker.key)),
+            markers,
+        }
+    }
+
+    pub fn add(
+        &mut self,
+        position: Timestamp,
+        name: String,
+    ) -> Result<MarkerKey, MarkerOverflowError> {
+        let key = self.key_generator.next()?;
+        let index = self
+            .markers
+            .partition_point(|marker| marker.position <= position);
+        self.markers.insert(
+            index,
+            Marker {
+                key,
+                position,
+                name,
+            },
+        );
+        Ok(key)
+    }
+
+    pub fn remove(&mut self, key: MarkerKey) -> Result<Marker, InvalidMarkerError> {
+        let index = self
+            .markers
+            .iter()
+            .position(|marker| marker.key == key)
+            .ok_or(InvalidMarkerError { key })?;
+        self.key_generator
+            .free(key)
+            .expect("Marker key exists in markers but not in key_generator");
+        Ok(self.markers.remove(index))
+    }
+
+    /// All markers, in order of position.
+    pub fn iter(&self) -> impl Iterator<Item = &Marker> + '_ {
+        self.markers.iter()
+    }
+
+    /// The first marker strictly after `position`.
+    pub fn next_after(&self, position: Timestamp) -> Option<&Marker> {
+        let index = self
+            .markers
+            .partition_point(|marker| marker.position <= position);
+        self.markers.get(index)
+    }
+
+    /// The last marker strictly before `position`.
+    pub fn previous_before(&self, position: Timestamp) -> Option<&Marker> {
+        let index = self
+            .markers
+            .partition_point(|marker| marker.position < position);
+        index.checked_sub(1).map(|index| &self.markers[index])
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidMarkerError {
+    pub key: MarkerKey,
+}
+impl Display for InvalidMarkerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let key = self.key;
+        write!(f, "No marker with key, {key:?}, on the timeline")
+    }
+}
+impl Error for InvalidMarkerError {}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct MarkerOverflowError;
+impl Display for MarkerOverflowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The max number of markers has been exceeded")
+    }
+}
+impl Error for MarkerOverflowError {}
+impl From<key_generator::OverflowError> for MarkerOverflowError {
+    fn from(_: key_generator::OverflowError) -> Self {
+        Self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sorted_by_position() {
+        let mut markers = Markers::new(&[]);
+        let b = markers.add(Timestamp::from_beats(8), "B".into()).unwrap();
+        let a = markers.add(Timestamp::from_beats(0), "A".into()).unwrap();
+        let c = markers.add(Timestamp::from_beats(16), "C".into()).unwrap();
+
+        let keys: Vec<_> = markers.iter().map(|marker| marker.key).collect();
+        assert_eq!(keys, [a, b, c]);
+
+        assert_eq!(markers.remove(b).unwrap().name, "B");
+        assert_eq!(markers.remove(b), Err(InvalidMarkerError { key: b }));
+        let keys: Vec<_> = markers.iter().map(|marker| marker.key).collect();
+        assert_eq!(keys, [a, c]);
+    }
+
+    #[test]
+    fn next_and_previous() {
+        let mut markers = Markers::new(&[]);
+        let a = markers.add(Timestamp::from_beats(4), "A".into()).unwrap();
+        let b = markers.add(Timestamp::from_beats(8), "B".into()).unwrap();
+
+        let next = |position| markers.next_after(position).map(|marker| marker.key);
+        assert_eq!(next(Timestamp::zero()), Some(a));
+        assert_eq!(next(Timestamp::from_beats(4)), Some(b));
+        assert_eq!(next(Timestamp::from_beats(8)), None);
+
+        let previous = |position| markers.previous_before(position).map(|marker| marker.key);
+        assert_eq!(previous(Timestamp::from_beats(4)), None);
+        assert_eq!(previous(Timestamp::from_beat_units(4 * 1024 + 1)), Some(a));
+        assert_eq!(previous(Timestamp::from_beats(100)), Some(b));
+    }
+}
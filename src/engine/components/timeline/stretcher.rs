@@ -0,0 +1,320 @@
+use std::{cmp::min, f32::consts::FRAC_PI_2, fmt::Debug, iter::zip};
+
+use crate::engine::{components::audio_clip_reader::AudioClipReader, info::Info, Sample, CHANNELS};
+
+/// Number of frames between the starts of two consecutive segments in the output.
+/// Segments are twice this long, so they overlap by half.
+const HOP: usize = 512;
+/// How many frames a segment can be moved from its nominal position,
+/// to line up with the end of the previous segment.
+const TOLERANCE: usize = 256;
+/// The number of frames the input buffer can hold.
+/// This must fit the span of input needed to synthesize one segment, at the smallest ratio.
+const INPUT_FRAMES: usize = 12 * HOP + 4 * TOLERANCE;
+
+/// The shortest a clip can be stretched to, relative to its original length.
+pub const MIN_STRETCH: f32 = 0.25;
+/// The longest a clip can be stretched to, relative to its original length.
+pub const MAX_STRETCH: f32 = 4.0;
+/// The most a clip can be pitch shifted up or down, in semitones.
+pub const MAX_PITCH: f32 = 12.0;
+
+/// The smallest ratio the stretcher supports,
+/// leaving room to compensate for a pitch shift on top of the clip's own stretch.
+const MIN_RATIO: f32 = MIN_STRETCH / 2.0;
+/// The largest ratio the stretcher supports.
+const MAX_RATIO: f32 = MAX_STRETCH * 2.0;
+
+/// Changes the speed of the audio read from an [`AudioClipReader`] without changing its pitch,
+/// using waveform similarity overlap-add (WSOLA).
+///
+/// Segments of the input are picked at intervals of `HOP / stretch`,
+/// each moved by up to [`TOLERANCE`] frames to where it best continues the previous one,
+/// and overlap-added at intervals of `HOP` in the output.
+pub struct Stretcher {
+    /// Interleaved frames read from the reader, where the first one is at `input_start`.
+    input: Vec<Sample>,
+    input_frames: usize,
+    /// Position of the start of the input buffer, in frames read since the last reset.
+    input_start: usize,
+
+    /// Nominal position of the next segment, in frames read since the last reset.
+    analysis: f64,
+    /// Where the previous segment would have continued, in frames read since the last reset.
+    /// Is `None` if no segment has been synthesized since the last reset.
+    natural: Option<usize>,
+    /// Second half of the previous segment, with the falling half of the window applied.
+    tail: Vec<Sample>,
+
+    /// The latest synthesized frames.
+    synthesized: Vec<Sample>,
+    /// How many frames at the end of `synthesized` have not been output yet.
+    synthesized_unused: usize,
+
+    /// The number of frames output since the last reset.
+    position: usize,
+    output_buffer: Vec<Sample>,
+}
+impl Stretcher {
+    pub fn new(max_buffer_size: usize) -> Self {
+        Stretcher {
+            input: vec![0.0; INPUT_FRAMES * CHANNELS],
+            input_frames: 0,
+            input_start: 0,
+
+            analysis: 0.0,
+            natural: None,
+            tail: vec![0.0; HOP * CHANNELS],
+
+            synthesized: vec![0.0; HOP * CHANNELS],
+            synthesized_unused: 0,
+
+            position: 0,
+            output_buffer: vec![0.0; max_buffer_size * CHANNELS],
+        }
+    }
+
+    /// Start over from the current position of the reader,
+    /// which corresponds to `position` frames into the output.
+    pub fn reset(&mut self, position: usize) {
+        self.input_frames = 0;
+        self.input_start = 0;
+        self.analysis = 0.0;
+        self.natural = None;
+        self.synthesized_unused = 0;
+        self.position = position;
+    }
+
+    /// The number of frames output since the last reset, plus the position given to it.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Gain of the rising half of a Hann window of length `2 * HOP`, at frame `i`.
+    /// The falling half is the complement, so the overlapping windows always sum to one.
+    fn window(i: usize) -> f32 {
+        let x = (i as f32 / HOP as f32 * FRAC_PI_2).sin();
+        x * x
+    }
+
+    /// The `HOP` input frames starting at absolute position `position`.
+    fn segment(&self, position: usize) -> &[Sample] {
+        let i = position - self.input_start;
+        &self.input[i * CHANNELS..(i + HOP) * CHANNELS]
+    }
+
+    /// Read from the reader until the input buffer reaches absolute position `end`.
+    /// Past the end of the clip, the input is padded with silence.
+    fn fill_input(&mut self, reader: &mut AudioClipReader, end: usize, sample_rate: u32) {
+        let max_buffer_size = self.output_buffer.len() / CHANNELS;
+        while self.input_start + self.input_frames < end {
+            let requested = min(
+                end - self.input_start - self.input_frames,
+                min(max_buffer_size, INPUT_FRAMES - self.input_frames),
+            );
+            debug_assert_ne!(requested, 0, "Stretcher input buffer is too small");
+            let output = reader.output(&Info {
+                sample_rate,
+                buffer_size: requested,
+            });
+
+            let filled = self.input_frames * CHANNELS;
+            self.input[filled..filled + output.len()].copy_from_slice(output);
+            self.input[filled + output.len()..filled + requested * CHANNELS].fill(0.0);
+            self.input_frames += requested;
+        }
+    }
+
+    /// Throw out the input before absolute position `start`.
+    fn discard_input(&mut self, start: usize) {
+        let discarded = min(start.saturating_sub(self.input_start), self.input_frames);
+        self.input
+            .copy_within(discarded * CHANNELS..self.input_frames * CHANNELS, 0);
+        self.input_start += discarded;
+        self.input_frames -= discarded;
+    }
+
+    /// Find the position within [`TOLERANCE`] of `nominal`,
+    /// where a segment best lines up with the one starting at `natural`.
+    fn best_fit(&self, nominal: usize, natural: usize) -> usize {
+        let mut best = nominal;
+        let mut best_correlation = Sample::NEG_INFINITY;
+        for candidate in nominal.saturating_sub(TOLERANCE)..=nominal + TOLERANCE {
+            // Channels are correlated separately, since they might cancel out if summed
+            let correlation: Sample = zip(self.segment(candidate), self.segment(natural))
+                .map(|(a, b)| a * b)
+                .sum();
+            if correlation > best_correlation {
+                best_correlation = correlation;
+                best = candidate;
+            }
+        }
+        best
+    }
+
+    /// Synthesize the next `HOP` frames.
+    fn synthesize(&mut self, reader: &mut AudioClipReader, stretch: f32, sample_rate: u32) {
+        let nominal = self.analysis.round() as usize;
+
+        let end = match self.natural {
+            None => nominal + 2 * HOP,
+            Some(natural) => (nominal + TOLERANCE + 2 * HOP).max(natural + HOP),
+        };
+        self.fill_input(reader, end, sample_rate);
+
+        let segment = match self.natural {
+            None => {
+                // Nothing to line up with, so the first segment is let through unchanged
+                let start = nominal - self.input_start;
+                self.synthesized
+                    .copy_from_slice(&self.input[start * CHANNELS..(start + HOP) * CHANNELS]);
+                nominal
+            }
+            Some(natural) => {
+                let segment = self.best_fit(nominal, natural);
+                for i in 0..HOP {
+                    let gain = Self::window(i) as Sample;
+                    let start = (segment + i - self.input_start) * CHANNELS;
+                    for c in 0..CHANNELS {
+                        self.synthesized[i * CHANNELS + c] =
+                            self.tail[i * CHANNELS + c] + gain * self.input[start + c];
+                    }
+                }
+                segment
+            }
+        };
+
+        for i in 0..HOP {
+            let gain = 1.0 - Self::window(i) as Sample;
+            let start = (segment + HOP + i - self.input_start) * CHANNELS;
+            for c in 0..CHANNELS {
+                self.tail[i * CHANNELS + c] = gain * self.input[start + c];
+            }
+        }
+
+        let natural = segment + HOP;
+        self.natural = Some(natural);
+        self.analysis += HOP as f64 / stretch as f64;
+        self.synthesized_unused = HOP;
+
+        let next_nominal = self.analysis.round() as usize;
+        self.discard_input(min(next_nominal.saturating_sub(TOLERANCE), natural));
+    }
+
+    /// Outputs `buffer_size` frames (via the info parameter), read from `reader` and stretched by `stretch`.
+    ///
+    /// `stretch` must be between [`MIN_RATIO`] and [`MAX_RATIO`].
+    pub fn output(
+        &mut self,
+        reader: &mut AudioClipReader,
+        stretch: f32,
+        info: &Info,
+    ) -> &mut [Sample] {
+        let Info {
+            sample_rate,
+            buffer_size,
+        } = *info;
+        debug_assert!((MIN_RATIO..=MAX_RATIO).contains(&stretch));
+
+        let mut filled = 0;
+        while filled < buffer_size {
+            if self.synthesized_unused == 0 {
+                self.synthesize(reader, stretch, sample_rate);
+            }
+
+            let used = HOP - self.synthesized_unused;
+            let len = min(self.synthesized_unused, buffer_size - filled);
+            self.output_buffer[filled * CHANNELS..(filled + len) * CHANNELS]
+                .copy_from_slice(&self.synthesized[used * CHANNELS..(used + len) * CHANNELS]);
+
+            self.synthesized_unused -= len;
+            filled += len;
+        }
+
+        self.position += buffer_size;
+        &mut self.output_buffer[..buffer_size * CHANNELS]
+    }
+}
+impl Debug for Stretcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Stretcher")
+            .field("position", &self.position)
+            .field("analysis", &self.analysis)
+            .field("natural", &self.natural)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::engine::{
+        components::stored_audio_clip::StoredAudioClip,
+        utils::{key_generator::Key, test_file_path},
+    };
+    use crate::StoredAudioClipKey;
+
+    use super::*;
+
+    /// Stretch the first second of the test clip, which is a square wave with a period of 100 frames,
+    /// returning the number of sign changes and how far the reader got.
+    fn run(stretch: f32) -> (usize, usize) {
+        let ac = StoredAudioClip::import(
+            StoredAudioClipKey::new(0),
+            &test_file_path("48000 16-bit.wav"),
+        )
+        .unwrap();
+        let mut reader = AudioClipReader::new(Arc::new(ac), 1000, 48_000);
+        let mut stretcher = Stretcher::new(1000);
+        let info = Info {
+            sample_rate: 48_000,
+            buffer_size: 1000,
+        };
+
+        let mut sign_changes = 0;
+        let mut last = 1.0;
+        no_heap! {{
+            for _ in 0..48 {
+                let output = stretcher.output(&mut reader, stretch, &info);
+                for frame in output.chunks_exact(CHANNELS) {
+                    if frame[0] * last < 0.0 {
+                        sign_changes += 1;
+                    }
+                    if frame[0] != 0.0 {
+                        last = frame[0];
+                    }
+                }
+            }
+        }}
+
+        assert_eq!(stretcher.position(), 48_000);
+        (sign_changes, reader.position().into())
+    }
+
+    #[test]
+    fn pitch_is_kept() {
+        for stretch in [MIN_STRETCH, 0.5, 1.0, 2.0, MAX_STRETCH] {
+            let (sign_changes, _) = run(stretch);
+            // The same number as without stretching, give or take a few at the seams
+            assert!(
+                (950..=966).contains(&sign_changes),
+                "Stretch: {stretch}, sign changes: {sign_changes}"
+            );
+        }
+    }
+
+    #[test]
+    fn speed_is_changed() {
+        for stretch in [0.5, 2.0] {
+            let (_, read) = run(stretch);
+            let expected = 48_000.0 / stretch;
+            // The reader runs ahead of the output by up to a few segments
+            let lookahead = (INPUT_FRAMES + 1000) as f32;
+            assert!(
+                expected <= read as f32 + HOP as f32 && (read as f32) <= expected + lookahead,
+                "Stretch: {stretch}, read: {read}"
+            );
+        }
+    }
+}
@@ -0,0 +1,341 @@
+use std::{error::Error, fmt::Display};
+
+use super::Timestamp;
+
+/// Conversion between positions on the timeline and positions in samples.
+///
+/// This is implemented for a constant tempo given in BPM cents (`u16`), and for a [`TempoMap`].
+pub trait Tempo: Copy {
+    /// The number of samples from the start of the timeline to `timestamp`, rounding down.
+    fn samples(self, timestamp: Timestamp, sample_rate: u32) -> usize;
+
+    /// The timestamp of the sample at `samples`, rounding down.
+    fn timestamp(self, samples: usize, sample_rate: u32) -> Timestamp;
+
+    /// Like [`Tempo::timestamp`], but rounds up instead of down.
+    fn timestamp_ceil(self, samples: usize, sample_rate: u32) -> Timestamp;
+
+    /// The number of samples spanned by `duration`, when starting at `start`.
+    ///
+    /// This is measured between the positions in samples of the start and end,
+    /// so durations placed back to back add up exactly.
+    fn duration_samples(self, start: Timestamp, duration: Timestamp, sample_rate: u32) -> usize {
+        self.samples(start + duration, sample_rate) - self.samples(start, sample_rate)
+    }
+
+    /// The duration on the timeline spanned by `samples`, when starting at `start`, rounding up.
+    fn duration(self, start: Timestamp, samples: usize, sample_rate: u32) -> Timestamp {
+        let end_samples = self.samples(start, sample_rate) + samples;
+        self.timestamp_ceil(end_samples, sample_rate)
+            .saturating_sub(start)
+    }
+
+    /// The duration on the timeline spanned by `samples`, when ending at `end`, rounding up.
+    fn duration_until(self, end: Timestamp, samples: usize, sample_rate: u32) -> Timestamp {
+        let start_samples = self.samples(end, sample_rate).saturating_sub(samples);
+        end - self.timestamp(start_samples, sample_rate)
+    }
+}
+
+impl Tempo for u16 {
+    fn samples(self, timestamp: Timestamp, sample_rate: u32) -> usize {
+        timestamp.samples(sample_rate, self)
+    }
+    fn timestamp(self, samples: usize, sample_rate: u32) -> Timestamp {
+        Timestamp::from_samples(samples, sample_rate, self)
+    }
+    fn timestamp_ceil(self, samples: usize, sample_rate: u32) -> Timestamp {
+        Timestamp::from_samples_ceil(samples, sample_rate, self)
+    }
+    fn duration(self, _start: Timestamp, samples: usize, sample_rate: u32) -> Timestamp {
+        Timestamp::from_samples_ceil(samples, sample_rate, self)
+    }
+    fn duration_until(self, _end: Timestamp, samples: usize, sample_rate: u32) -> Timestamp {
+        Timestamp::from_samples_ceil(samples, sample_rate, self)
+    }
+}
+
+/// The tempo along the timeline, as a list of points where the tempo changes.
+///
+/// The tempo is constant between two points, and the first point is always at [`Timestamp::zero`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TempoMap {
+    /// Pairs of positions and tempos in BPM cents, sorted by position.
+    points: Vec<(Timestamp, u16)>,
+}
+impl TempoMap {
+    /// Create a map with a constant tempo.
+    pub fn new(bpm_cents: u16) -> Self {
+        Self {
+            points: vec![(Timestamp::zero(), bpm_cents)],
+        }
+    }
+
+    /// Create a map from the tempo at the start of the timeline, and a list of later changes.
+    ///
+    /// Changes at [`Timestamp::zero`] will override `bpm_cents`.
+    pub fn from_changes(bpm_cents: u16, changes: &[(Timestamp, u16)]) -> Self {
+        let mut tempo_map = Self::new(bpm_cents);
+        for &(position, bpm_cents) in changes {
+            tempo_map.insert(position, bpm_cents);
+        }
+        tempo_map
+    }
+
+    /// The tempo at the very start of the timeline.
+    pub fn initial_bpm_cents(&self) -> u16 {
+        self.points[0].1
+    }
+
+    /// The tempo at `position`.
+    pub fn bpm_cents_at(&self, position: Timestamp) -> u16 {
+        let i = self.points.partition_point(|&(start, _)| start <= position);
+        self.points[i - 1].1
+    }
+
+    /// All tempo changes after the start of the timeline, in order.
+    pub fn changes(&self) -> &[(Timestamp, u16)] {
+        &self.points[1..]
+    }
+
+    /// Set the tempo from `position` and until the next change.
+    /// If a change already exists at `position`, it is replaced.
+    pub fn insert(&mut self, position: Timestamp, bpm_cents: u16) {
+        match self
+            .points
+            .binary_search_by_key(&position, |&(start, _)| start)
+        {
+            Ok(i) => self.points[i].1 = bpm_cents,
+            Err(i) => self.points.insert(i, (position, bpm_cents)),
+        }
+    }
+
+    /// Remove the tempo change at `position`.
+    ///
+    /// The tempo at the start of the timeline cannot be removed.
+    pub fn remove(&mut self, position: Timestamp) -> Result<u16, InvalidTempoChangeError> {
+        match self
+            .points
+            .binary_search_by_key(&position, |&(start, _)| start)
+        {
+            Ok(i) if i != 0 => Ok(self.points.remove(i).1),
+            _ => Err(InvalidTempoChangeError { position }),
+        }
+    }
+
+    /// Returns the tempo if it is the same along the entire timeline.
+    fn constant(&self) -> Option<u16> {
+        match self.points.as_slice() {
+            [(_, bpm_cents)] => Some(*bpm_cents),
+            _ => None,
+        }
+    }
+
+    /// Runs `f` for each segment of constant tempo, with the segment's start, end and tempo,
+    /// and the number of samples from the start of the timeline to the start of the segment.
+    ///
+    /// Stops and returns the result once `f` returns `Some`.
+    /// The last segment ends at [`Timestamp::infinity`].
+    fn find_segment<F, R>(&self, sample_rate: u32, mut f: F) -> R
+    where
+        F: FnMut(Timestamp, Timestamp, u16, usize) -> Option<R>,
+    {
+        let mut start_samples = 0;
+        for (i, &(start, bpm_cents)) in self.points.iter().enumerate() {
+            let end = self
+                .points
+                .get(i + 1)
+                .map(|&(end, _)| end)
+                .unwrap_or(Timestamp::infinity());
+
+            if let Some(result) = f(start, end, bpm_cents, start_samples) {
+                return result;
+            }
+            start_samples += (end - start).samples(sample_rate, bpm_cents);
+        }
+        unreachable!("The last tempo segment is infinite");
+    }
+}
+impl Tempo for &TempoMap {
+    fn samples(self, timestamp: Timestamp, sample_rate: u32) -> usize {
+        if let Some(bpm_cents) = self.constant() {
+            return bpm_cents.samples(timestamp, sample_rate);
+        }
+
+        self.find_segment(sample_rate, |start, end, bpm_cents, start_samples| {
+            (timestamp < end || end == Timestamp::infinity())
+                .then(|| start_samples + (timestamp - start).samples(sample_rate, bpm_cents))
+        })
+    }
+
+    fn timestamp(self, samples: usize, sample_rate: u32) -> Timestamp {
+        if let Some(bpm_cents) = self.constant() {
+            return bpm_cents.timestamp(samples, sample_rate);
+        }
+
+        self.find_segment(sample_rate, |start, end, bpm_cents, start_samples| {
+            let is_last = end == Timestamp::infinity();
+            let end_samples = start_samples + (end - start).samples(sample_rate, bpm_cents);
+            (is_last || samples < end_samples).then(|| {
+                start + Timestamp::from_samples(samples - start_samples, sample_rate, bpm_cents)
+            })
+        })
+    }
+
+    fn timestamp_ceil(self, samples: usize, sample_rate: u32) -> Timestamp {
+        if let Some(bpm_cents) = self.constant() {
+            return bpm_cents.timestamp_ceil(samples, sample_rate);
+        }
+
+        self.find_segment(sample_rate, |start, end, bpm_cents, start_samples| {
+            let is_last = end == Timestamp::infinity();
+            let end_samples = start_samples + (end - start).samples(sample_rate, bpm_cents);
+            (is_last || samples <= end_samples).then(|| {
+                start
+                    + Timestamp::from_samples_ceil(samples - start_samples, sample_rate, bpm_cents)
+            })
+        })
+    }
+
+    fn duration(self, start: Timestamp, samples: usize, sample_rate: u32) -> Timestamp {
+        match self.constant() {
+            Some(bpm_cents) => bpm_cents.duration(start, samples, sample_rate),
+            None => {
+                let end_samples = self.samples(start, sample_rate) + samples;
+                self.timestamp_ceil(end_samples, sample_rate)
+                    .saturating_sub(start)
+            }
+        }
+    }
+
+    fn duration_until(self, end: Timestamp, samples: usize, sample_rate: u32) -> Timestamp {
+        match self.constant() {
+            Some(bpm_cents) => bpm_cents.duration_until(end, samples, sample_rate),
+            None => {
+                let start_samples = self.samples(end, sample_rate).saturating_sub(samples);
+                end - self.timestamp(start_samples, sample_rate)
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidTempoChangeError {
+    pub position: Timestamp,
+}
+impl Display for InvalidTempoChangeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let position = self.position;
+        write!(f, "No removable tempo change at {position:?}")
+    }
+}
+impl Error for InvalidTempoChangeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48_000;
+
+    #[test]
+    fn constant_matches_timestamp() {
+        let tempo_map = TempoMap::new(120_00);
+        let ts = Timestamp::from_beat_units(12_345);
+
+        assert_eq!(
+            (&tempo_map).samples(ts, SAMPLE_RATE),
+            ts.samples(SAMPLE_RATE, 120_00)
+        );
+        assert_eq!(
+            (&tempo_map).timestamp(123_456, SAMPLE_RATE),
+            Timestamp::from_samples(123_456, SAMPLE_RATE, 120_00)
+        );
+    }
+
+    #[test]
+    fn samples_integrate_over_changes() {
+        // 1 beat at 120 BPM is 24_000 samples, and at 60 BPM it is 48_000 samples
+        let tempo_map = TempoMap::from_changes(120_00, &[(Timestamp::from_beats(2), 60_00)]);
+
+        assert_eq!(
+            (&tempo_map).samples(Timestamp::from_beats(1), SAMPLE_RATE),
+            24_000
+        );
+        assert_eq!(
+            (&tempo_map).samples(Timestamp::from_beats(2), SAMPLE_RATE),
+            48_000
+        );
+        assert_eq!(
+            (&tempo_map).samples(Timestamp::from_beats(3), SAMPLE_RATE),
+            96_000
+        );
+    }
+
+    #[test]
+    fn timestamp_integrates_over_changes() {
+        let tempo_map = TempoMap::from_changes(120_00, &[(Timestamp::from_beats(2), 60_00)]);
+
+        assert_eq!(
+            (&tempo_map).timestamp(24_000, SAMPLE_RATE),
+            Timestamp::from_beats(1)
+        );
+        assert_eq!(
+            (&tempo_map).timestamp(48_000, SAMPLE_RATE),
+            Timestamp::from_beats(2)
+        );
+        assert_eq!(
+            (&tempo_map).timestamp(96_000, SAMPLE_RATE),
+            Timestamp::from_beats(3)
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let tempo_map = TempoMap::from_changes(
+            120_00,
+            &[
+                (Timestamp::from_beat_units(1_500), 93_17),
+                (Timestamp::from_beats(7), 171_00),
+            ],
+        );
+
+        for beat_units in (0..20_000).step_by(97) {
+            let ts = Timestamp::from_beat_units(beat_units);
+            let samples = (&tempo_map).samples(ts, SAMPLE_RATE);
+            assert_eq!((&tempo_map).timestamp_ceil(samples, SAMPLE_RATE), ts);
+        }
+    }
+
+    #[test]
+    fn insert_and_remove() {
+        let mut tempo_map = TempoMap::new(120_00);
+        tempo_map.insert(Timestamp::from_beats(4), 90_00);
+        tempo_map.insert(Timestamp::from_beats(2), 100_00);
+        tempo_map.insert(Timestamp::zero(), 110_00);
+
+        assert_eq!(tempo_map.initial_bpm_cents(), 110_00);
+        assert_eq!(
+            tempo_map.changes(),
+            &[
+                (Timestamp::from_beats(2), 100_00),
+                (Timestamp::from_beats(4), 90_00)
+            ]
+        );
+        assert_eq!(tempo_map.bpm_cents_at(Timestamp::from_beats(3)), 100_00);
+
+        assert_eq!(tempo_map.remove(Timestamp::from_beats(2)), Ok(100_00));
+        assert_eq!(tempo_map.bpm_cents_at(Timestamp::from_beats(3)), 110_00);
+        assert_eq!(
+            tempo_map.remove(Timestamp::from_beats(2)),
+            Err(InvalidTempoChangeError {
+                position: Timestamp::from_beats(2)
+            })
+        );
+        assert_eq!(
+            tempo_map.remove(Timestamp::zero()),
+            Err(InvalidTempoChangeError {
+                position: Timestamp::zero()
+            })
+        );
+    }
+}
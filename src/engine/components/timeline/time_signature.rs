@@ -0,0 +1,420 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::{max, min},
+    error::Error,
+    fmt::Display,
+};
+
+use super::Timestamp;
+
+/// The largest supported note value of a beat, i.e. sixty-fourth notes.
+pub const MAX_DENOMINATOR: u32 = 64;
+
+/// The number of beats in a bar, and the note value of each beat.
+///
+/// For example, 6/8 is six beats of eighth notes.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimeSignature {
+    numerator: u32,
+    denominator: u32,
+}
+impl TimeSignature {
+    /// The `numerator` must be at least 1,
+    /// and the `denominator` must be a power of two no larger than [`MAX_DENOMINATOR`].
+    pub fn new(numerator: u32, denominator: u32) -> Result<Self, InvalidTimeSignatureError> {
+        if numerator == 0 || !denominator.is_power_of_two() || denominator > MAX_DENOMINATOR {
+            return Err(InvalidTimeSignatureError {
+                numerator,
+                denominator,
+            });
+        }
+        Ok(Self {
+            numerator,
+            denominator,
+        })
+    }
+
+    /// The number of beats in each bar.
+    pub fn numerator(&self) -> u32 {
+        self.numerator
+    }
+    /// The note value of each beat, e.g. 4 for quarter notes.
+    pub fn denominator(&self) -> u32 {
+        self.denominator
+    }
+
+    /// The length of one beat of the time signature.
+    ///
+    /// A [`Timestamp`] beat is a quarter note, so this is only the same for a denominator of 4.
+    pub fn beat_length(&self) -> Timestamp {
+        // A whole note is four quarter notes
+        Timestamp::from_beat_units(Timestamp::from_beats(4).beat_units() / self.denominator)
+    }
+
+    /// The length of one bar of the time signature.
+    pub fn bar_length(&self) -> Timestamp {
+        self.beat_length() * self.numerator
+    }
+}
+impl Default for TimeSignature {
+    /// 4/4
+    fn default() -> Self {
+        Self {
+            numerator: 4,
+            denominator: 4,
+        }
+    }
+}
+
+/// The time signature along the timeline, as a list of points where it changes.
+///
+/// The time signature is constant between two points, and the first point is always at [`Timestamp::zero`].
+/// Bars are counted from each change, so a change placed in the middle of a bar cuts that bar short.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeSignatureMap {
+    /// Pairs of positions and time signatures, sorted by position.
+    points: Vec<(Timestamp, TimeSignature)>,
+}
+impl TimeSignatureMap {
+    /// Create a map with a constant time signature.
+    pub fn new(time_signature: TimeSignature) -> Self {
+        Self {
+            points: vec![(Timestamp::zero(), time_signature)],
+        }
+    }
+
+    /// Create a map from the time signature at the start of the timeline, and a list of later changes.
+    ///
+    /// Changes at [`Timestamp::zero`] will override `time_signature`.
+    pub fn from_changes(
+        time_signature: TimeSignature,
+        changes: &[(Timestamp, TimeSignature)],
+    ) -> Self {
+        let mut map = Self::new(time_signature);
+        for &(position, time_signature) in changes {
+            map.insert(position, time_signature);
+        }
+        map
+    }
+
+    /// The time signature at the very start of the timeline.
+    pub fn initial(&self) -> TimeSignature {
+        self.points[0].1
+    }
+
+    /// The time signature at `position`.
+    pub fn at(&self, position: Timestamp) -> TimeSignature {
+        let i = self.points.partition_point(|&(start, _)| start <= position);
+        self.points[i - 1].1
+    }
+
+    /// All time signature changes after the start of the timeline, in order.
+    pub fn changes(&self) -> &[(Timestamp, TimeSignature)] {
+        &self.points[1..]
+    }
+
+    /// Set the time signature from `position` and until the next change.
+    /// If a change already exists at `position`, it is replaced.
+    pub fn insert(&mut self, position: Timestamp, time_signature: TimeSignature) {
+        match self
+            .points
+            .binary_search_by_key(&position, |&(start, _)| start)
+        {
+            Ok(i) => self.points[i].1 = time_signature,
+            Err(i) => self.points.insert(i, (position, time_signature)),
+        }
+    }
+
+    /// Remove the time signature change at `position`.
+    ///
+    /// The time signature at the start of the timeline cannot be removed.
+    pub fn remove(
+        &mut self,
+        position: Timestamp,
+    ) -> Result<TimeSignature, InvalidTimeSignatureChangeError> {
+        match self
+            .points
+            .binary_search_by_key(&position, |&(start, _)| start)
+        {
+            Ok(i) if i != 0 => Ok(self.points.remove(i).1),
+            _ => Err(InvalidTimeSignatureChangeError { position }),
+        }
+    }
+
+    /// The bar, the beat within the bar, and the beat units within the beat, of `position`.
+    ///
+    /// Bars and beats are counted from 1, as they are usually displayed, while the beat units are counted from 0.
+    /// Beats are of the note value given by the time signature, rather than [`Timestamp`] beats.
+    pub fn bars_beats(&self, position: Timestamp) -> (u32, u32, u32) {
+        let mut bars_before = 0;
+        for (i, &(start, time_signature)) in self.points.iter().enumerate() {
+            let bar_units = time_signature.bar_length().beat_units();
+            match self.points.get(i + 1) {
+                Some(&(end, _)) if end <= position => {
+                    // A bar cut short by the next change still counts
+                    bars_before += (end - start).beat_units().div_ceil(bar_units);
+                }
+                _ => {
+                    let offset = (position - start).beat_units();
+                    let beat_units = time_signature.beat_length().beat_units();
+                    let within_bar = offset % bar_units;
+                    return (
+                        bars_before + offset / bar_units + 1,
+                        within_bar / beat_units + 1,
+                        within_bar % beat_units,
+                    );
+                }
+            }
+        }
+        unreachable!("The last time signature lasts forever");
+    }
+
+    /// The lines of a grid with the given `division`, from `from` and until, but not including, `to`.
+    ///
+    /// The grid starts over at each time signature change, and the lines are produced lazily,
+    /// so any range can be iterated without allocating.
+    pub fn grid(
+        &self,
+        from: Timestamp,
+        to: Timestamp,
+        division: GridDivision,
+    ) -> impl Iterator<Item = Timestamp> + '_ {
+        let segment = self.points.partition_point(|&(start, _)| start <= from) - 1;
+        let (start, time_signature) = self.points[segment];
+        let step = u64::from(division.step(time_signature).beat_units());
+        // The first line at or after `from`
+        let offset = u64::from((from - start).beat_units());
+        let first = u64::from(start.beat_units()) + offset.div_ceil(step) * step;
+        let first = Timestamp::from_beat_units(min(first, u64::from(u32::MAX)) as u32);
+
+        BeatGrid {
+            map: self,
+            segment,
+            position: min(first, self.segment_end(segment)),
+            to,
+            division,
+        }
+    }
+
+    /// The start of the segment after `segment`, or [`Timestamp::infinity`] for the last one.
+    fn segment_end(&self, segment: usize) -> Timestamp {
+        self.points
+            .get(segment + 1)
+            .map_or(Timestamp::infinity(), |&(end, _)| end)
+    }
+}
+impl Default for TimeSignatureMap {
+    fn default() -> Self {
+        Self::new(TimeSignature::default())
+    }
+}
+
+/// The spacing of the lines in a grid along the timeline, see [`TimeSignatureMap::grid`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GridDivision {
+    /// A line at the start of every bar.
+    Bar,
+    /// A line on every beat of the time signature, e.g. every eighth note in 6/8.
+    Beat,
+    /// Lines spaced evenly by the given length, counted from the start of each time signature.
+    ///
+    /// A length of zero is treated as the shortest possible length.
+    Length(Timestamp),
+}
+impl GridDivision {
+    fn step(self, time_signature: TimeSignature) -> Timestamp {
+        match self {
+            GridDivision::Bar => time_signature.bar_length(),
+            GridDivision::Beat => time_signature.beat_length(),
+            GridDivision::Length(length) => max(length, Timestamp::from_beat_units(1)),
+        }
+    }
+}
+
+/// Iterator over the lines of a grid, acquired via [`TimeSignatureMap::grid`].
+struct BeatGrid<'a> {
+    map: &'a TimeSignatureMap,
+    segment: usize,
+    /// The next line, unless it is past the end of the current segment.
+    position: Timestamp,
+    to: Timestamp,
+    division: GridDivision,
+}
+impl Iterator for BeatGrid<'_> {
+    type Item = Timestamp;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let &(_, time_signature) = self.map.points.get(self.segment)?;
+            let end = self.map.segment_end(self.segment);
+
+            if self.position >= end {
+                // The grid starts over at the next time signature
+                self.segment += 1;
+                self.position = end;
+                continue;
+            }
+            if self.position >= self.to {
+                return None;
+            }
+
+            let position = self.position;
+            let step = self.division.step(time_signature);
+            self.position = min(position.saturating_add(step), end);
+            return Some(position);
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidTimeSignatureError {
+    pub numerator: u32,
+    pub denominator: u32,
+}
+impl Display for InvalidTimeSignatureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let numerator = self.numerator;
+        let denominator = self.denominator;
+        write!(f, "{numerator}/{denominator} is not a valid time signature")
+    }
+}
+impl Error for InvalidTimeSignatureError {}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidTimeSignatureChangeError {
+    pub position: Timestamp,
+}
+impl Display for InvalidTimeSignatureChangeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let position = self.position;
+        write!(f, "No removable time signature change at {position:?}")
+    }
+}
+impl Error for InvalidTimeSignatureChangeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ts(numerator: u32, denominator: u32) -> TimeSignature {
+        TimeSignature::new(numerator, denominator).unwrap()
+    }
+
+    #[test]
+    fn invalid_time_signatures() {
+        assert!(TimeSignature::new(0, 4).is_err());
+        assert!(TimeSignature::new(4, 0).is_err());
+        assert!(TimeSignature::new(4, 3).is_err());
+        assert!(TimeSignature::new(4, 128).is_err());
+        assert!(TimeSignature::new(7, 8).is_ok());
+    }
+
+    #[test]
+    fn lengths() {
+        assert_eq!(ts(4, 4).bar_length(), Timestamp::from_beats(4));
+        assert_eq!(ts(6, 8).bar_length(), Timestamp::from_beats(3));
+        assert_eq!(ts(3, 2).beat_length(), Timestamp::from_beats(2));
+    }
+
+    #[test]
+    fn bars_beats_constant() {
+        let map = TimeSignatureMap::default();
+
+        assert_eq!(map.bars_beats(Timestamp::zero()), (1, 1, 0));
+        assert_eq!(map.bars_beats(Timestamp::from_beats(5)), (2, 2, 0));
+        assert_eq!(
+            map.bars_beats(Timestamp::from_beat_units(9 * 1024 + 100)),
+            (3, 2, 100)
+        );
+    }
+
+    #[test]
+    fn bars_beats_over_changes() {
+        // Two bars of 4/4, then 6/8 from the middle of the third bar
+        let map = TimeSignatureMap::from_changes(
+            ts(4, 4),
+            &[
+                (Timestamp::from_beats(8), ts(4, 4)),
+                (Timestamp::from_beats(10), ts(6, 8)),
+            ],
+        );
+
+        assert_eq!(map.bars_beats(Timestamp::from_beats(9)), (3, 2, 0));
+        // The third bar is cut short
+        assert_eq!(map.bars_beats(Timestamp::from_beats(10)), (4, 1, 0));
+        assert_eq!(
+            map.bars_beats(Timestamp::from_beat_units(10 * 1024 + 512)),
+            (4, 2, 0)
+        );
+        assert_eq!(map.bars_beats(Timestamp::from_beats(13)), (5, 1, 0));
+    }
+
+    #[test]
+    fn grid_starts_over_at_changes() {
+        // 3/4 until the middle of the second bar, then 2/4
+        let map = TimeSignatureMap::from_changes(ts(3, 4), &[(Timestamp::from_beats(5), ts(2, 4))]);
+
+        let bars: Vec<_> = map
+            .grid(
+                Timestamp::zero(),
+                Timestamp::from_beats(10),
+                GridDivision::Bar,
+            )
+            .collect();
+        assert_eq!(bars, [0, 3, 5, 7, 9].map(Timestamp::from_beats),);
+
+        let beats: Vec<_> = map
+            .grid(
+                Timestamp::from_beat_units(1),
+                Timestamp::from_beats(3),
+                GridDivision::Beat,
+            )
+            .collect();
+        assert_eq!(beats, [1, 2].map(Timestamp::from_beats));
+
+        // Multiples of 2 beats from the start of each time signature
+        let lengths: Vec<_> = map
+            .grid(
+                Timestamp::from_beats(3),
+                Timestamp::from_beats(8),
+                GridDivision::Length(Timestamp::from_beats(2)),
+            )
+            .collect();
+        assert_eq!(lengths, [4, 5, 7].map(Timestamp::from_beats));
+    }
+
+    #[test]
+    fn grid_is_lazy() {
+        let map = TimeSignatureMap::default();
+        let mut grid = map.grid(
+            Timestamp::zero(),
+            Timestamp::infinity(),
+            GridDivision::Length(Timestamp::zero()),
+        );
+
+        assert_eq!(
+            grid.nth(1_000_000),
+            Some(Timestamp::from_beat_units(1_000_000))
+        );
+    }
+
+    #[test]
+    fn insert_and_remove() {
+        let mut map = TimeSignatureMap::default();
+        map.insert(Timestamp::from_beats(8), ts(3, 4));
+        map.insert(Timestamp::zero(), ts(7, 8));
+
+        assert_eq!(map.initial(), ts(7, 8));
+        assert_eq!(map.at(Timestamp::from_beats(9)), ts(3, 4));
+        assert_eq!(map.changes(), &[(Timestamp::from_beats(8), ts(3, 4))]);
+
+        assert_eq!(map.remove(Timestamp::from_beats(8)), Ok(ts(3, 4)));
+        assert_eq!(
+            map.remove(Timestamp::from_beats(8)),
+            Err(InvalidTimeSignatureChangeError {
+                position: Timestamp::from_beats(8)
+            })
+        );
+        assert!(map.remove(Timestamp::zero()).is_err());
+    }
+}
@@ -3,6 +3,8 @@ use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 use std::ops::{Add, Mul, Sub};
 
+use super::TimeSignatureMap;
+
 const UNITS_PER_BEAT: u32 = 1024;
 
 #[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -50,6 +52,20 @@ impl Timestamp {
             beat_units: beat_units as u32,
         }
     }
+    /// Converts a number of seconds to a timestamp.
+    ///
+    /// The time is first rounded to the nearest whole sample, and then to the nearest beat unit,
+    /// so that converting the result back with [`Timestamp::seconds`] gives the nearest sample at or before it.
+    /// Negative times are treated as zero.
+    pub fn from_seconds(seconds: f64, sample_rate: u32, bpm_cents: u16) -> Self {
+        let samples = (seconds * sample_rate as f64).round().max(0.0);
+        let beat_units = (samples * bpm_cents as f64 * UNITS_PER_BEAT as f64
+            / (sample_rate as f64 * 60.0 * 100.0))
+            .round();
+        Self {
+            beat_units: beat_units as u32,
+        }
+    }
 
     /// 1 beat = 1024 beat units
     pub const fn beat_units(&self) -> u32 {
@@ -63,6 +79,37 @@ impl Timestamp {
         (self.beat_units as usize * sample_rate as usize * 60 * 100)
             / (bpm_cents as usize * UNITS_PER_BEAT as usize)
     }
+    /// Converts a timestamp to a number of seconds.
+    ///
+    /// This is the same as [`Timestamp::samples`] divided by the sample rate,
+    /// so the result is rounded down to a whole sample.
+    pub fn seconds(&self, sample_rate: u32, bpm_cents: u16) -> f64 {
+        self.samples(sample_rate, bpm_cents) as f64 / sample_rate as f64
+    }
+
+    /// The bar, the beat within the bar, and the beat units within the beat, of this position.
+    ///
+    /// See [`TimeSignatureMap::bars_beats`].
+    pub fn to_bars_beats(&self, time_signatures: &TimeSignatureMap) -> (u32, u32, u32) {
+        time_signatures.bars_beats(*self)
+    }
+
+    /// Rounds to the nearest multiple of `division`, rounding halfway cases up.
+    ///
+    /// A `division` of zero leaves the timestamp unchanged.
+    pub fn round_to(self, division: Self) -> Self {
+        if division.beat_units == 0 {
+            return self;
+        }
+        let remainder = self.beat_units % division.beat_units;
+        let down = self.beat_units - remainder;
+        if remainder * 2 < division.beat_units {
+            Self::from_beat_units(down)
+        } else {
+            // Stay representable, even if the multiple above is not
+            Self::from_beat_units(down.saturating_add(division.beat_units))
+        }
+    }
 
     pub fn checked_add(self, rhs: Self) -> Option<Self> {
         self.beat_units
@@ -199,6 +246,88 @@ mod tests {
         assert_eq!(result, (u32::MAX as usize * 40_000 * 60) / (100 * 1024));
     }
 
+    #[test]
+    fn beats_to_seconds() {
+        let ts = Timestamp::from_beats(3);
+        assert_eq!(ts.seconds(48_000, 120_00), 1.5);
+        assert_eq!(
+            ts.seconds(44_100, 137_50),
+            ts.samples(44_100, 137_50) as f64 / 44_100.0
+        );
+    }
+    #[test]
+    fn seconds_to_beats() {
+        let ts = Timestamp::from_seconds(1.5, 48_000, 120_00);
+        assert_eq!(ts.beats(), 3);
+        assert_eq!(ts.beat_units(), 3 * 1024);
+        assert_eq!(
+            Timestamp::from_seconds(-1.0, 48_000, 120_00),
+            Timestamp::zero()
+        );
+    }
+    #[test]
+    fn seconds_round_trip() {
+        for (sample_rate, bpm_cents) in [(48_000, 120_00), (44_100, 137_50), (22_050, 300_00)] {
+            for beat_units in (0..100_000).step_by(7) {
+                let ts = Timestamp::from_beat_units(beat_units);
+                let seconds = ts.seconds(sample_rate, bpm_cents);
+                assert_eq!(
+                    Timestamp::from_seconds(seconds, sample_rate, bpm_cents),
+                    ts,
+                    "Sample rate: {sample_rate}, BPM cents: {bpm_cents}"
+                );
+            }
+
+            for samples in (0..1_000_000).step_by(13) {
+                let seconds = samples as f64 / sample_rate as f64;
+                let ts = Timestamp::from_seconds(seconds, sample_rate, bpm_cents);
+                // Whole beat units are coarser than samples, so the nearest one is at most half a unit away
+                let error = (ts.seconds(sample_rate, bpm_cents) - seconds).abs();
+                let unit_seconds = 60.0 * 100.0 / (bpm_cents as f64 * 1024.0);
+                assert!(error <= unit_seconds / 2.0 + 1.0 / sample_rate as f64);
+            }
+        }
+    }
+
+    #[test]
+    fn round_to() {
+        let quarter = Timestamp::from_beat_units(256);
+        assert_eq!(
+            Timestamp::from_beat_units(127)
+                .round_to(quarter)
+                .beat_units(),
+            0
+        );
+        assert_eq!(
+            Timestamp::from_beat_units(128)
+                .round_to(quarter)
+                .beat_units(),
+            256
+        );
+        assert_eq!(
+            Timestamp::from_beat_units(700)
+                .round_to(quarter)
+                .beat_units(),
+            768
+        );
+        assert_eq!(
+            Timestamp::from_beat_units(1024)
+                .round_to(quarter)
+                .beat_units(),
+            1024
+        );
+        assert_eq!(
+            Timestamp::from_beat_units(1500).round_to(Timestamp::from_beats(1)),
+            Timestamp::from_beats(1)
+        );
+        assert_eq!(
+            Timestamp::from_beat_units(42)
+                .round_to(Timestamp::zero())
+                .beat_units(),
+            42
+        );
+    }
+
     #[test]
     fn add() {
         let a = Timestamp::from_beat_units(42);
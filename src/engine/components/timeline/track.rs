@@ -2,25 +2,31 @@ use intrusive_collections::rbtree::CursorOwning;
 use intrusive_collections::{Bound, RBTree};
 use serde::{Deserialize, Serialize};
 use std::cell::RefMut;
-use std::cmp::min;
+use std::cmp::{max, min};
 use std::collections::{HashMap, HashSet};
+use std::f32::consts::FRAC_PI_2;
 use std::fmt::Debug;
 use std::hash::{Hash, Hasher};
+use std::iter::zip;
+use std::ops::Range;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
-use super::audio_clip::{AudioClip, AudioClipKey, AudioClipState};
-use super::AudioClipProcessor;
-use crate::engine::components::audio_clip_reader::OriginalSamples;
+use super::audio_clip::{AudioClip, AudioClipKey, AudioClipState, EndBehavior};
+use super::{AudioClipProcessor, Tempo, TempoMap};
+use crate::engine::components::audio_clip_reader::{AudioClipReader, OriginalSamples};
+use crate::engine::components::noise::{Noise, NoiseSettings};
+use crate::engine::components::test_tone::{TestTone, TestToneSettings};
 use crate::engine::components::track::MixerTrackKey;
 use crate::engine::info::Info;
 use crate::engine::utils::dropper;
 use crate::engine::utils::key_generator::Key;
 use crate::engine::utils::rbtree_node::{TreeNode, TreeNodeAdapter};
+use crate::engine::utils::simd;
 use crate::engine::{Sample, CHANNELS};
 use crate::Timestamp;
 
-#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 pub struct TimelineTrackKey(u32);
 impl Key for TimelineTrackKey {
     type Id = u32;
@@ -32,30 +38,156 @@ impl Key for TimelineTrackKey {
     }
 }
 
+/// How clips placed on top of each other on a timeline track are handled.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverlapPolicy {
+    /// Clips may only overlap where they crossfade.
+    #[default]
+    Reject,
+    /// When a clip is added on top of the end of an earlier clip, the earlier clip is cropped to end where the new one starts.
+    ///
+    /// Otherwise the same as [`OverlapPolicy::Reject`].
+    TrimExisting,
+    /// Clips may overlap freely, and are summed where they do, as long as no two clips start at the same time.
+    Layer,
+}
+
 type TimelineTree = RBTree<TreeNodeAdapter<AudioClipProcessor>>;
 type TimelineCursor = CursorOwning<TreeNodeAdapter<AudioClipProcessor>>;
 
+/// Equal-power gain of a clip at the frame `pos` on the timeline,
+/// fading in over `fade_in` and out over `fade_out`, where it overlaps its neighbours.
+fn crossfade_gain(pos: usize, fade_in: &Range<usize>, fade_out: &Range<usize>) -> f32 {
+    let mut gain = 1.0;
+    if fade_in.contains(&pos) {
+        let x = (pos - fade_in.start) as f32 / fade_in.len() as f32;
+        gain *= (x * FRAC_PI_2).sin();
+    }
+    if fade_out.contains(&pos) {
+        let x = (pos - fade_out.start) as f32 / fade_out.len() as f32;
+        gain *= (x * FRAC_PI_2).cos();
+    }
+    gain
+}
+
 /// A mirror for the state of a `TimelineTrackProcessor`.
 /// Unlike the convention, this does not do any synchronization with the `TimelineTrackProcessor`.
 pub struct TimelineTrack {
     pub clips: HashMap<AudioClipKey, AudioClip>,
     pub output_track: MixerTrackKey,
+    pub name: String,
+    /// RGBA
+    pub color: u32,
+    pub overlap_policy: OverlapPolicy,
+    pub test_tone: Option<TestToneSettings>,
+    pub noise: Option<NoiseSettings>,
 }
 impl TimelineTrack {
     pub fn new(output: MixerTrackKey) -> Self {
         TimelineTrack {
             clips: HashMap::new(),
             output_track: output,
+            name: String::new(),
+            color: 0,
+            overlap_policy: OverlapPolicy::default(),
+            test_tone: None,
+            noise: None,
+        }
+    }
+
+    /// Whether the clip with key `key` could be placed from `start` to `end` without overlapping any other clips,
+    /// except for the end of the clip before it overlapping its start by at most `crossfade`,
+    /// or its end overlapping the start of the clip after it by at most the crossfade of that clip.
+    ///
+    /// A clip may never be covered entirely by another one, and no more than two clips may overlap at once.
+    ///
+    /// If the track layers its clips, the only requirement is that no other clip starts at `start`.
+    pub fn is_free(
+        &self,
+        key: AudioClipKey,
+        start: Timestamp,
+        end: Timestamp,
+        crossfade: Timestamp,
+        tempo: impl Tempo,
+    ) -> bool {
+        self.is_free_except(&[key], start, end, crossfade, tempo)
+    }
+
+    /// Whether a new clip without a crossfade could be placed from `start` to `end`.
+    ///
+    /// See [`Self::is_free`].
+    pub fn range_is_free(&self, start: Timestamp, end: Timestamp, tempo: impl Tempo) -> bool {
+        self.is_free_except(&[], start, end, Timestamp::zero(), tempo)
+    }
+
+    /// Same as [`Self::is_free`], except all of the clips in `ignored` are disregarded.
+    pub fn is_free_except(
+        &self,
+        ignored: &[AudioClipKey],
+        start: Timestamp,
+        end: Timestamp,
+        crossfade: Timestamp,
+        tempo: impl Tempo,
+    ) -> bool {
+        if self.overlap_policy == OverlapPolicy::Layer {
+            // The processor tells the clips apart by their start
+            return !self
+                .clips
+                .values()
+                .any(|other| !ignored.contains(&other.key) && other.start == start);
+        }
+
+        // The end of the clip overlapping the start, and the start of the clip overlapping the end
+        let mut before = None;
+        let mut after = None;
+
+        for other in self.clips.values() {
+            if ignored.contains(&other.key) {
+                continue;
+            }
+
+            let other_start = other.start;
+            let other_end = other.end(tempo);
+            if !(start < other_end && other_start < end) {
+                continue;
+            }
+
+            let fades_in =
+                other_start < start && other_end <= end && other_end <= start + crossfade;
+            let fades_out =
+                start < other_start && end <= other_end && end <= other_start + other.crossfade;
+            if fades_in && before.replace(other_end).is_none() {
+                continue;
+            }
+            if fades_out && after.replace(other_start).is_none() {
+                continue;
+            }
+            return false;
+        }
+
+        match (before, after) {
+            (Some(before), Some(after)) => before <= after,
+            _ => true,
         }
     }
+
+    /// The clips that start before `position` and end after it.
+    pub fn clips_across(&self, position: Timestamp, tempo: impl Tempo) -> Vec<AudioClipKey> {
+        self.clips
+            .values()
+            .filter(|clip| clip.start < position && position < clip.end(tempo))
+            .map(|clip| clip.key)
+            .collect()
+    }
 }
 
 pub struct TimelineTrackProcessor {
     position: Arc<AtomicUsize>,
     sample_rate: u32,
-    bpm_cents: u16,
+    tempo_map: Arc<TempoMap>,
 
-    /// The clip that is either currently playing, or will be played next.
+    /// The first clip that is either currently playing, or will be played next.
+    /// Clips after it may be playing as well, if they overlap it in a crossfade.
     /// If this is the null pointer then the track is past the last clip.
     ///
     /// Optional to allow temporary access to the tree.
@@ -63,6 +195,11 @@ pub struct TimelineTrackProcessor {
     ///
     /// All clips in this tree should be reset when the the position encounters them, not when it leaves them.
     relevant_clip: Option<TimelineCursor>,
+    /// Whether the clips are allowed to overlap freely, in which case they are summed without crossfading.
+    layered: bool,
+    /// Generated sources, heard regardless of the clips, and of whether the timeline is playing.
+    test_tone: Option<TestTone>,
+    noise: Option<Noise>,
 
     output_track: MixerTrackKey,
 }
@@ -71,7 +208,7 @@ impl TimelineTrackProcessor {
         output: MixerTrackKey,
         position: Arc<AtomicUsize>,
         sample_rate: u32,
-        bpm_cents: u16,
+        tempo_map: Arc<TempoMap>,
     ) -> Self {
         let tree = RBTree::new(TreeNodeAdapter::new());
         let relevant_clip = Some(tree.cursor_owning());
@@ -79,9 +216,12 @@ impl TimelineTrackProcessor {
         TimelineTrackProcessor {
             position,
             sample_rate,
-            bpm_cents,
+            tempo_map,
 
             relevant_clip,
+            layered: false,
+            test_tone: None,
+            noise: None,
 
             output_track: output,
         }
@@ -91,7 +231,52 @@ impl TimelineTrackProcessor {
         self.output_track
     }
 
+    /// Set whether the clips are allowed to overlap freely.
+    ///
+    /// While they are not, no more than two clips may overlap at once, and only where they crossfade.
+    pub fn set_layered(&mut self, layered: bool) {
+        self.layered = layered;
+
+        let pos_samples = self.position.load(Ordering::Relaxed);
+        let position = self.tempo_map.timestamp(pos_samples, self.sample_rate);
+        self.update_relevant_clip(position);
+    }
+
+    /// Replace the settings of the test tone, keeping its phase if it was already playing.
+    pub fn set_test_tone(&mut self, settings: Option<TestToneSettings>) {
+        match (&mut self.test_tone, settings) {
+            (Some(tone), Some(settings)) => tone.set_settings(settings),
+            (tone, settings) => *tone = settings.map(TestTone::new),
+        }
+    }
+
+    /// Replace the settings of the noise source, keeping the state of its filter if it was already playing.
+    pub fn set_noise(&mut self, settings: Option<NoiseSettings>) {
+        match (&mut self.noise, settings) {
+            (Some(noise), Some(settings)) => noise.set_settings(settings),
+            (noise, settings) => *noise = settings.map(Noise::new),
+        }
+    }
+
+    /// Add the test tone and noise, if any, on top of `buffer`.
+    pub fn output_generators(&mut self, info: &Info, buffer: &mut [Sample]) {
+        let buffer = &mut buffer[..info.buffer_size * CHANNELS];
+        if let Some(tone) = &mut self.test_tone {
+            tone.add_to(info.sample_rate, buffer);
+        }
+        if let Some(noise) = &mut self.noise {
+            noise.add_to(buffer);
+        }
+    }
+
+    /// Replace the tempo map.
+    /// Remember to call [`Self::jump`] afterwards, as the positions of all clips may have changed.
+    pub fn set_tempo_map(&mut self, tempo_map: Arc<TempoMap>) {
+        self.tempo_map = tempo_map;
+    }
+
     pub fn insert_clip(&mut self, clip: Box<TreeNode<AudioClipProcessor>>) {
+        let tempo_map = &*self.tempo_map;
         self.relevant_clip
             .as_mut()
             .unwrap()
@@ -99,18 +284,17 @@ impl TimelineTrackProcessor {
                 let mut clip_ref: RefMut<AudioClipProcessor> = (*clip).borrow_mut();
 
                 let pos_samples = self.position.load(Ordering::Relaxed);
-                let position =
-                    Timestamp::from_samples(pos_samples, self.sample_rate, self.bpm_cents);
-                let clip_end = clip_ref.end(self.bpm_cents);
+                let position = tempo_map.timestamp(pos_samples, self.sample_rate);
+                let clip_end = clip_ref.end(tempo_map);
                 let next = cursor.get();
 
                 let is_more_relevant = match next {
-                    Some(next) => position < clip_end && clip_end <= next.borrow().start,
+                    Some(next) => position < clip_end && clip_ref.start < next.borrow().start,
                     None => position < clip_end,
                 };
 
                 if clip_ref.start <= position && position < clip_end {
-                    clip_ref.jump(position, self.sample_rate, self.bpm_cents);
+                    clip_ref.jump(position, self.sample_rate, tempo_map);
                 }
 
                 drop(clip_ref);
@@ -143,64 +327,32 @@ impl TimelineTrackProcessor {
             .expect("Attempted to delete non-existing clip");
 
         let pos_samples = self.position.load(Ordering::Relaxed);
-        let position = Timestamp::from_samples(pos_samples, self.sample_rate, self.bpm_cents);
-        self.relevant_clip = Some(tree.upper_bound_owning(Bound::Included(&position)));
+        let position = self.tempo_map.timestamp(pos_samples, self.sample_rate);
+        self.relevant_clip = Some(tree.cursor_owning());
+        self.update_relevant_clip(position);
 
         el
     }
 
     pub fn move_clip(&mut self, old_start: Timestamp, new_start: Timestamp) {
         let sample_rate = self.sample_rate;
-        let bpm_cents = self.bpm_cents;
+        let tempo_map = Arc::clone(&self.tempo_map);
+        let tempo_map = &*tempo_map;
         let pos_samples = self.position.load(Ordering::Relaxed);
-        let position = Timestamp::from_samples(pos_samples, sample_rate, bpm_cents);
+        let position = tempo_map.timestamp(pos_samples, sample_rate);
 
         let new_end = self.with_clip_moving(old_start, |clip| {
             clip.start = new_start;
-            clip.end(bpm_cents)
+            clip.end(tempo_map)
         });
 
-        let relevant_start = self.map_relevant_clip_not_moving(|clip| clip.start);
-        let was_relevant = relevant_start == Some(new_start);
-
-        let is_relevant = if was_relevant {
-            let cursor = self.relevant_clip.as_mut().unwrap().as_cursor();
-            let next_clip_opt = cursor.peek_next().get();
-            let next_starts_before = match next_clip_opt {
-                Some(next) => next.borrow().start < new_start,
-                None => false,
-            };
-            let no_longer_relevant = new_end <= position || next_starts_before;
-            if no_longer_relevant {
-                self.update_relevant_clip(position);
-            }
-
-            !no_longer_relevant
-        } else {
-            // The clip has become relevant if it's like this:
-            //
-            //  position         Relevant clip or None
-            //     ↓                     ↓
-            //     |   ...moved ]   [ relevant ]
-            //
-            let has_become_relevant = position < new_end
-                && relevant_start.map_or(true, |relevant_start| new_end <= relevant_start);
-            if has_become_relevant {
-                self.relevant_clip
-                    .as_mut()
-                    .unwrap()
-                    .with_cursor_mut(|cursor| cursor.move_prev());
-                self.map_relevant_clip_not_moving(|clip| {
-                    clip.jump(position, sample_rate, bpm_cents);
-                });
-            }
-
-            has_become_relevant
-        };
+        self.update_relevant_clip(position);
 
-        if is_relevant {
-            self.map_relevant_clip_not_moving(|clip| {
-                clip.jump(position, sample_rate, bpm_cents);
+        // Clips are reset when the position reaches them,
+        // so only a clip that is already playing has to be brought in place
+        if new_start <= position && position < new_end {
+            self.with_clip_not_moving(new_start, |clip| {
+                clip.jump(position, sample_rate, tempo_map);
             });
         }
     }
@@ -213,81 +365,186 @@ impl TimelineTrackProcessor {
         new_start_offset: OriginalSamples,
     ) {
         let sample_rate = self.sample_rate;
-        let bpm_cents = self.bpm_cents;
+        let tempo_map = Arc::clone(&self.tempo_map);
+        let tempo_map = &*tempo_map;
         let pos_samples = self.position.load(Ordering::Relaxed);
-        let position = Timestamp::from_samples(pos_samples, sample_rate, bpm_cents);
+        let position = tempo_map.timestamp(pos_samples, sample_rate);
 
-        self.with_clip_not_moving(old_start, |clip| {
-            // While clip.start is the key, changing it will not change the position in the tree if no clips can ever be in an overlapping state.
+        let crop = |clip: &mut AudioClipProcessor| {
             clip.start = new_start;
             clip.length = Some(new_length);
             clip.start_offset = new_start_offset;
-        });
 
-        // TODO: only jump when start crosses position
-        self.with_relevant_clip_not_moving(|relevant_clip| {
-            if let Some(relevant_clip) = relevant_clip {
-                let relevant_was_cropped = relevant_clip.start == new_start;
-                let was_upcoming = position <= old_start;
-                let is_upcoming = position <= new_start;
-                if relevant_was_cropped && (was_upcoming || is_upcoming) {
-                    relevant_clip.jump(position, sample_rate, bpm_cents);
-                }
+            // TODO: only jump when start crosses position
+            let was_upcoming = position <= old_start;
+            let is_upcoming = position <= new_start;
+            // A reversed clip reads its region from the other end, so moving its start moves the read position
+            let reversed = clip.reversed;
+            if was_upcoming || is_upcoming || reversed {
+                clip.jump(position, sample_rate, tempo_map);
             }
-        });
+        };
+
+        if self.layered {
+            // The start may be moved past the starts of other clips
+            self.with_clip_moving(old_start, crop);
+            self.update_relevant_clip(position);
+        } else {
+            // While clip.start is the key, changing it will not change the position in the tree,
+            // since no clip can ever start inside another one, except in a crossfade at its end.
+            self.with_clip_not_moving(old_start, crop);
+        }
     }
 
-    pub fn crop_clip_end(&mut self, clip_start: Timestamp, new_length: Timestamp) {
+    pub fn crop_clip_end(
+        &mut self,
+        clip_start: Timestamp,
+        new_length: Timestamp,
+        new_start_offset: OriginalSamples,
+    ) {
         let sample_rate = self.sample_rate;
-        let bpm_cents = self.bpm_cents;
+        let tempo_map = Arc::clone(&self.tempo_map);
+        let tempo_map = &*tempo_map;
         let pos_samples = self.position.load(Ordering::Relaxed);
-        let position = Timestamp::from_samples(pos_samples, sample_rate, bpm_cents);
+        let position = tempo_map.timestamp(pos_samples, sample_rate);
 
-        let (start, old_end, new_end) = self.with_clip_not_moving(clip_start, |clip| {
-            let old_end = clip.end(bpm_cents);
+        self.with_clip_not_moving(clip_start, |clip| {
+            let old_end = clip.end(tempo_map);
             clip.length = Some(new_length);
-            (clip.start, old_end, clip.start + new_length)
+            clip.start_offset = new_start_offset;
+            let new_end = clip.start + new_length;
+
+            // A reversed clip reads its region from the other end, so moving its end moves the read position.
+            // Otherwise, the clip only has to be brought in place if it reaches the position again.
+            let resumed = clip.start <= position && old_end <= position && position < new_end;
+            if clip.reversed || resumed {
+                clip.jump(position, sample_rate, tempo_map);
+            }
         });
 
-        let move_prev = start <= position && old_end < position && position <= new_end;
-        let move_next = start <= position && new_end < position && position <= old_end;
-        let should_move = move_prev || move_next;
+        // The clip may have ended or resumed at the position
+        self.update_relevant_clip(position);
+    }
 
-        if should_move {
-            if let Some(relevant_clip) = self.relevant_clip.as_mut() {
-                relevant_clip.with_cursor_mut(|cursor| {
-                    if move_prev {
-                        cursor.move_prev();
+    pub fn set_clip_gain(&mut self, clip_start: Timestamp, gain: f32) {
+        self.with_clip_not_moving(clip_start, |clip| {
+            clip.gain = gain;
+        });
+    }
 
-                        if let Some(clip_cell) = cursor.get() {
-                            let mut clip = clip_cell.borrow_mut();
-                            clip.jump(position, sample_rate, bpm_cents);
-                        }
-                    }
-                    if move_next {
-                        cursor.move_next();
-                    }
-                });
+    pub fn swap_clip_gain_envelope(
+        &mut self,
+        clip_start: Timestamp,
+        gain_envelope: &mut Vec<(usize, f32)>,
+    ) {
+        self.with_clip_not_moving(clip_start, |clip| {
+            std::mem::swap(&mut clip.gain_envelope, gain_envelope);
+        });
+    }
+
+    pub fn set_clip_fades(
+        &mut self,
+        clip_start: Timestamp,
+        fade_in: Timestamp,
+        fade_out: Timestamp,
+    ) {
+        self.with_clip_not_moving(clip_start, |clip| {
+            clip.fade_in = fade_in;
+            clip.fade_out = fade_out;
+        });
+    }
+
+    pub fn set_clip_reversed(&mut self, clip_start: Timestamp, reversed: bool) {
+        let sample_rate = self.sample_rate;
+        let tempo_map = Arc::clone(&self.tempo_map);
+        let tempo_map = &*tempo_map;
+        let pos_samples = self.position.load(Ordering::Relaxed);
+        let position = tempo_map.timestamp(pos_samples, sample_rate);
+
+        self.with_clip_not_moving(clip_start, |clip| {
+            clip.reversed = reversed;
+
+            // The read position is mirrored, so a playing clip has to be brought back in place
+            clip.jump(position, sample_rate, tempo_map);
+        });
+    }
+
+    pub fn set_clip_active(&mut self, clip_start: Timestamp, active: bool) {
+        self.with_clip_not_moving(clip_start, |clip| {
+            clip.active = active;
+        });
+    }
+
+    pub fn set_clip_end_behavior(&mut self, clip_start: Timestamp, end_behavior: EndBehavior) {
+        let sample_rate = self.sample_rate;
+        let tempo_map = Arc::clone(&self.tempo_map);
+        let tempo_map = &*tempo_map;
+        let pos_samples = self.position.load(Ordering::Relaxed);
+        let position = tempo_map.timestamp(pos_samples, sample_rate);
+
+        self.with_clip_not_moving(clip_start, |clip| {
+            clip.end_behavior = end_behavior;
+
+            // A playing clip may be past the end of the source clip, where it now reads from elsewhere
+            if clip.start <= position && position < clip.end(tempo_map) {
+                clip.jump(position, sample_rate, tempo_map);
             }
-        }
+        });
+    }
+
+    pub fn set_clip_stretch(
+        &mut self,
+        clip_start: Timestamp,
+        stretch: f32,
+        new_length: Option<Timestamp>,
+    ) {
+        self.with_clip_not_moving(clip_start, |clip| {
+            clip.stretch = stretch;
+            clip.length = new_length;
+        });
+
+        // Both the end of the clip and the read position may have moved
+        self.jump();
+    }
+
+    pub fn swap_clip_reader(&mut self, clip_start: Timestamp, reader: &mut AudioClipReader) {
+        self.with_clip_not_moving(clip_start, |clip| {
+            clip.swap_reader(reader);
+        });
+
+        // The new reader has to be moved to the current position
+        self.jump();
     }
 
     /// Jump to the global position.
     /// Should be called when the global position changes.
     pub fn jump(&mut self) {
         let pos_samples = self.position.load(Ordering::Relaxed);
-        let position = Timestamp::from_samples(pos_samples, self.sample_rate, self.bpm_cents);
+        let position = self.tempo_map.timestamp(pos_samples, self.sample_rate);
 
         self.update_relevant_clip(position);
 
         let sample_rate = self.sample_rate;
-        let bpm_cents = self.bpm_cents;
+        let tempo_map = &*self.tempo_map;
 
-        self.with_relevant_clip_not_moving(|clip_opt| {
-            if let Some(clip) = clip_opt {
-                clip.jump(position, sample_rate, bpm_cents);
-            }
-        });
+        self.relevant_clip
+            .as_mut()
+            .unwrap()
+            .with_cursor_mut(|cursor| {
+                let mut clips = cursor.as_cursor();
+                while let Some(clip_cell) = clips.get() {
+                    clip_cell
+                        .borrow_mut()
+                        .jump(position, sample_rate, tempo_map);
+
+                    // Clips after the relevant one are only playing if they have started, e.g. in a crossfade
+                    clips.move_next();
+                    match clips.get() {
+                        Some(next) if next.borrow().start <= position => {}
+                        _ => break,
+                    }
+                }
+            });
     }
 
     /// Update the relevant clip to point to the clip that is relevant at `position`.
@@ -297,17 +554,44 @@ impl TimelineTrackProcessor {
             .take()
             .expect("self.relevant_clip is None")
             .into_inner();
-        self.relevant_clip = Some(tree.upper_bound_owning(Bound::Included(&position)));
+        let tempo_map = &*self.tempo_map;
+
+        if self.layered {
+            // Any of the clips before the position may still be playing,
+            // so the first one that has not ended is searched for from the front
+            let mut cursor = tree.front_owning();
+            cursor.with_cursor_mut(|cursor| {
+                while let Some(clip) = cursor.get() {
+                    if position < clip.borrow().end(tempo_map) {
+                        break;
+                    }
+                    cursor.move_next();
+                }
+            });
+            self.relevant_clip = Some(cursor);
+            return;
+        }
 
+        self.relevant_clip = Some(tree.upper_bound_owning(Bound::Included(&position)));
         self.relevant_clip
             .as_mut()
             .unwrap()
             .with_cursor_mut(|cursor| {
+                // The clip before may still be playing, if it crossfades into this one
+                if cursor.get().is_some() {
+                    while let Some(prev) = cursor.peek_prev().get() {
+                        if prev.borrow().end(tempo_map) <= position {
+                            break;
+                        }
+                        cursor.move_prev();
+                    }
+                }
+
                 let clip = cursor.get();
                 match clip {
                     None => cursor.move_next(),
                     Some(clip) => {
-                        let clip_end = clip.borrow().end(self.bpm_cents);
+                        let clip_end = clip.borrow().end(tempo_map);
                         if clip_end <= position {
                             cursor.move_next();
                         }
@@ -316,70 +600,125 @@ impl TimelineTrackProcessor {
             });
     }
 
-    pub fn output(&mut self, info: &Info, buffer: &mut [Sample]) {
+    /// Sums the output of all clips playing during the buffer into `buffer`.
+    ///
+    /// Where two clips overlap, they are crossfaded with complementary equal-power gain ramps,
+    /// unless the track is layered.
+    /// If `soloed_clip` is set, every other clip is silenced.
+    pub fn output(
+        &mut self,
+        info: &Info,
+        buffer: &mut [Sample],
+        soloed_clip: Option<AudioClipKey>,
+    ) {
         let Info {
             sample_rate,
             buffer_size,
         } = *info;
 
+        let position = self.position.load(Ordering::Relaxed);
+        let buffer_end = position + buffer_size;
+        buffer[..buffer_size * CHANNELS].fill(0.0);
+
+        let tempo_map = &*self.tempo_map;
+        let layered = self.layered;
         self.relevant_clip
             .as_mut()
             .unwrap()
             .with_cursor_mut(|cursor| {
-                let mut progress = 0;
-
-                while progress < buffer_size {
-                    let should_move;
-                    match cursor.get() {
-                        Some(clip_cell) => {
-                            let position = self.position.load(Ordering::Relaxed);
-                            let mut clip = clip_cell.borrow_mut();
-
-                            // Pad start with zero
-                            let clip_start = clip.start.samples(sample_rate, self.bpm_cents);
-                            if position + progress < clip_start {
-                                let end_zeroes = min(clip_start - position, buffer_size);
-                                buffer[progress * CHANNELS..end_zeroes * CHANNELS].fill(0.0);
-                                progress = end_zeroes;
-                            }
-
-                            // Fill with content
-                            let requested_buffer = buffer_size - progress;
-                            let output = clip.output(
-                                self.bpm_cents,
-                                &Info {
-                                    sample_rate,
-                                    buffer_size: requested_buffer,
-                                },
-                            );
-                            buffer[progress * CHANNELS..progress * CHANNELS + output.len()]
-                                .copy_from_slice(output);
-                            progress += output.len() / CHANNELS;
-
-                            // Determine if we should move on to next clip
-                            should_move = output.len() < requested_buffer;
+                // How many clips in a row from the relevant one have ended during this buffer
+                let mut ended = 0;
+                let mut visited = 0;
+                let mut clips = cursor.as_cursor();
+
+                while let Some(clip_cell) = clips.get() {
+                    let clip_start = tempo_map.samples(clip_cell.borrow().start, sample_rate);
+                    if buffer_end <= clip_start {
+                        break;
+                    }
+                    let clip_end =
+                        tempo_map.samples(clip_cell.borrow().end(tempo_map), sample_rate);
+
+                    // Where the clip overlaps its neighbours
+                    let fade_in = match clips.peek_prev().get() {
+                        _ if layered => 0..0,
+                        Some(prev) => {
+                            let prev_end =
+                                tempo_map.samples(prev.borrow().end(tempo_map), sample_rate);
+                            clip_start..max(clip_start, prev_end)
                         }
-
-                        None => {
-                            // No more clips, pad with zero
-                            buffer[progress * CHANNELS..buffer_size * CHANNELS].fill(0.0);
-                            break;
+                        None => 0..0,
+                    };
+                    let fade_out = match clips.peek_next().get() {
+                        _ if layered => 0..0,
+                        Some(next) => {
+                            let next_start = tempo_map.samples(next.borrow().start, sample_rate);
+                            min(next_start, clip_end)..clip_end
                         }
+                        None => 0..0,
+                    };
+
+                    let mut clip = clip_cell.borrow_mut();
+
+                    // Clips are reset when the position reaches them
+                    if position <= clip_start {
+                        clip.reset(sample_rate, tempo_map);
                     }
 
-                    // Needs to be out here to access &mut cursor
-                    if should_move {
-                        cursor.move_next();
-                        if let Some(clip_cell) = cursor.get() {
-                            let mut clip = clip_cell.borrow_mut();
-                            clip.reset(sample_rate);
+                    // Copied out, since the output borrows the clip
+                    let active = clip.active;
+                    let key = clip.key;
+
+                    // Pad start with zero
+                    let offset = clip_start.saturating_sub(position);
+                    let requested_buffer = buffer_size - offset;
+                    let output = clip.output(
+                        tempo_map,
+                        &Info {
+                            sample_rate,
+                            buffer_size: requested_buffer,
+                        },
+                    );
+                    let buffer = &mut buffer[offset * CHANNELS..offset * CHANNELS + output.len()];
+
+                    let audible = active && soloed_clip.is_none_or(|soloed| soloed == key);
+                    if !audible {
+                        // Only silenced, since it is still read above to stay in place
+                    } else if fade_in.is_empty() && fade_out.is_empty() {
+                        simd::add(buffer, output);
+                    } else {
+                        for (i, (frame, clip_frame)) in zip(
+                            buffer.chunks_exact_mut(CHANNELS),
+                            output.chunks_exact(CHANNELS),
+                        )
+                        .enumerate()
+                        {
+                            let gain = crossfade_gain(position + offset + i, &fade_in, &fade_out);
+                            for (sample, &clip_sample) in zip(frame, clip_frame) {
+                                *sample += gain as Sample * clip_sample;
+                            }
                         }
                     }
+
+                    // Determine if we should move on from this clip
+                    let has_ended = output.len() < requested_buffer * CHANNELS;
+                    if has_ended && ended == visited {
+                        ended += 1;
+                    }
+
+                    drop(clip);
+                    clips.move_next();
+                    visited += 1;
+                }
+
+                for _ in 0..ended {
+                    cursor.move_next();
                 }
             });
     }
 
     /// Run a function on the (optional) relevant clip, which must not alter the ordering of the clips.
+    #[cfg(test)]
     fn with_relevant_clip_not_moving<F, R>(&mut self, f: F) -> R
     where
         F: FnOnce(Option<&mut AudioClipProcessor>) -> R,
@@ -397,14 +736,6 @@ impl TimelineTrackProcessor {
             })
     }
 
-    /// Run a function on the relevant clip if one exists, which must not alter the ordering of the clips.
-    fn map_relevant_clip_not_moving<F, R>(&mut self, f: F) -> Option<R>
-    where
-        F: FnOnce(&mut AudioClipProcessor) -> R,
-    {
-        self.with_relevant_clip_not_moving(|clip_opt| clip_opt.map(f))
-    }
-
     /// Run a function on the clip at `clip_start`, which must not alter the ordering of the clips.
     ///
     /// This will _not_ update what the relevant clip is pointing to, so if the clip is moved or cropped the cursor might not point at the correct clip.
@@ -496,7 +827,7 @@ impl Debug for TimelineTrackProcessor {
         f.debug_struct("TimelineTrackProcessor")
             .field("position", &self.position)
             .field("sample_rate", &self.sample_rate)
-            .field("bpm_cents", &self.bpm_cents)
+            .field("tempo_map", &self.tempo_map)
             .field("output_track", &self.output_track)
             .finish_non_exhaustive()
     }
@@ -507,6 +838,19 @@ pub struct TimelineTrackState {
     pub key: TimelineTrackKey,
     pub clips: Vec<AudioClipState>,
     pub output_track: MixerTrackKey,
+    #[serde(default)]
+    pub name: String,
+    /// RGBA
+    #[serde(default)]
+    pub color: u32,
+    #[serde(default)]
+    pub overlap_policy: OverlapPolicy,
+    /// Generated on the track in addition to its clips, if set.
+    #[serde(default)]
+    pub test_tone: Option<TestToneSettings>,
+    /// Generated on the track in addition to its clips, if set.
+    #[serde(default)]
+    pub noise: Option<NoiseSettings>,
 }
 impl PartialEq for TimelineTrackState {
     fn eq(&self, other: &Self) -> bool {
@@ -526,7 +870,15 @@ impl PartialEq for TimelineTrackState {
             other.clips
         );
 
+        // The order of the clips is arbitrary
         self.key == other.key
+            && self_set == other_set
+            && self.output_track == other.output_track
+            && self.name == other.name
+            && self.color == other.color
+            && self.overlap_policy == other.overlap_policy
+            && self.test_tone == other.test_tone
+            && self.noise == other.noise
     }
 }
 impl Eq for TimelineTrackState {}
@@ -568,9 +920,11 @@ mod tests {
 
         AC.with(|ac| {
             Box::new(TreeNode::new(AudioClipProcessor::new(
+                AudioClipKey::new(start_beat_units),
                 Timestamp::from_beat_units(start_beat_units),
                 length_beat_units.map(Timestamp::from_beat_units),
                 OriginalSamples::new(0),
+                1.0,
                 AudioClipReader::new(Arc::clone(ac), max_buffer_size, 48_000),
             )))
         })
@@ -582,7 +936,7 @@ mod tests {
             MixerTrackKey::new(0),
             Arc::new(AtomicUsize::new(0)),
             SAMPLE_RATE,
-            BPM_CENTS,
+            Arc::new(TempoMap::new(BPM_CENTS)),
         );
         let c1 = clip(3, Some(1), 100);
         let c2 = clip(1, Some(2), 100);
@@ -612,7 +966,7 @@ mod tests {
             MixerTrackKey::new(0),
             Arc::clone(&pos),
             SAMPLE_RATE,
-            BPM_CENTS,
+            Arc::new(TempoMap::new(BPM_CENTS)),
         );
         let c1 = clip(1, Some(1), 3 * SBU);
         let c2 = clip(3, Some(2), 3 * SBU);
@@ -623,7 +977,7 @@ mod tests {
 
             // Empty
             let mut out = [0.0; BUFFER_SIZE * CHANNELS];
-            t.output(&info, &mut out[..]);
+            t.output(&info, &mut out[..], None);
             for &mut s in out.iter_mut() {
                 assert_eq!(s, 0.0);
             }
@@ -637,7 +991,7 @@ mod tests {
             });
 
             let mut out = [0.0; BUFFER_SIZE * CHANNELS];
-            t.output(&info, &mut out[..]);
+            t.output(&info, &mut out[..], None);
             for &mut s in out.iter_mut() {
                 assert_ne!(s, 0.0);
             }
@@ -645,7 +999,7 @@ mod tests {
 
             // Empty
             let mut out = [0.0; BUFFER_SIZE * CHANNELS];
-            t.output(&info, &mut out[..]);
+            t.output(&info, &mut out[..], None);
             for &mut s in out.iter_mut() {
                 assert_eq!(s, 0.0);
             }
@@ -663,7 +1017,7 @@ mod tests {
             t.output(&Info {
                 sample_rate: SAMPLE_RATE,
                 buffer_size: 3 * SBU,
-            }, &mut out[..]);
+            }, &mut out[..], None);
             for &s in &out[..CHANNELS * (2 * SBU)] {
                 assert_ne!(s, 0.0);
             }
@@ -678,7 +1032,7 @@ mod tests {
                 assert!(clip.is_none());
             });
             let mut out = [0.0; BUFFER_SIZE * CHANNELS];
-            t.output(&info, &mut out[..]);
+            t.output(&info, &mut out[..], None);
             for &mut s in out.iter_mut() {
                 assert_eq!(s, 0.0);
             }
@@ -700,7 +1054,7 @@ mod tests {
             MixerTrackKey::new(0),
             Arc::new(AtomicUsize::new(0)),
             SAMPLE_RATE,
-            BPM_CENTS,
+            Arc::new(TempoMap::new(BPM_CENTS)),
         );
         let c1 = clip(0, Some(1), BUFFER_SIZE);
         let c2 = clip(2, Some(1), BUFFER_SIZE);
@@ -720,7 +1074,7 @@ mod tests {
             t.output(&Info {
                 sample_rate: SAMPLE_RATE,
                 buffer_size: (c4_end + 3) * SBUC,
-            }, &mut out[..]);
+            }, &mut out[..], None);
 
             // c1
             for &s in &out[..SBUC] {
@@ -790,7 +1144,7 @@ mod tests {
             MixerTrackKey::new(0),
             Arc::clone(&pos),
             SAMPLE_RATE,
-            BPM_CENTS,
+            Arc::new(TempoMap::new(BPM_CENTS)),
         );
         let c1 = clip(1, Some(1), 100);
         let c2 = clip(3, Some(2), 100);
@@ -804,7 +1158,7 @@ mod tests {
 
             // Empty
             let mut out = [0.0; BUFFER_SIZE * CHANNELS];
-            t.output(&info, &mut out[..]);
+            t.output(&info, &mut out[..], None);
             for &mut s in out.iter_mut() {
                 assert_eq!(s, 0.0);
             }
@@ -822,7 +1176,7 @@ mod tests {
             t.output(&Info {
                 sample_rate: SAMPLE_RATE,
                 buffer_size: 3 * SBU,
-            }, &mut out[..]);
+            }, &mut out[..], None);
             for &s in &out[..CHANNELS * (2 * SBU)] {
                 assert_ne!(s, 0.0);
             }
@@ -838,7 +1192,7 @@ mod tests {
                 assert!(clip.is_none());
             });
             let mut out = [0.0; BUFFER_SIZE * CHANNELS];
-            t.output(&info, &mut out[..]);
+            t.output(&info, &mut out[..], None);
             for &mut s in out.iter_mut() {
                 assert_eq!(s, 0.0);
             }
@@ -854,7 +1208,7 @@ mod tests {
 
             // Empty
             let mut out = [0.0; BUFFER_SIZE * CHANNELS];
-            t.output(&info, &mut out[..]);
+            t.output(&info, &mut out[..], None);
             for &mut s in out.iter_mut() {
                 assert_eq!(s, 0.0);
             }
@@ -868,7 +1222,7 @@ mod tests {
             });
 
             let mut out = [0.0; BUFFER_SIZE * CHANNELS];
-            t.output(&info, &mut out[..]);
+            t.output(&info, &mut out[..], None);
             for &mut s in out.iter_mut() {
                 assert_ne!(s, 0.0);
             }
@@ -883,7 +1237,7 @@ mod tests {
             MixerTrackKey::new(0),
             Arc::clone(&p),
             SAMPLE_RATE,
-            BPM_CENTS,
+            Arc::new(TempoMap::new(BPM_CENTS)),
         );
         let c = clip(0, Some(1), 100);
 
@@ -916,7 +1270,7 @@ mod tests {
             MixerTrackKey::new(0),
             Arc::clone(&p),
             SAMPLE_RATE,
-            BPM_CENTS,
+            Arc::new(TempoMap::new(BPM_CENTS)),
         );
         let c = clip(1, Some(1), 100);
 
@@ -941,4 +1295,201 @@ mod tests {
             assert!(clip_opt.is_none());
         });
     }
+
+    #[test]
+    fn output_crossfade() {
+        const BUFFER_SIZE: usize = 5 * SBU;
+        let info = Info {
+            sample_rate: SAMPLE_RATE,
+            buffer_size: BUFFER_SIZE,
+        };
+        let track = |clips: Vec<Box<TreeNode<AudioClipProcessor>>>| {
+            let mut t = TimelineTrackProcessor::new(
+                MixerTrackKey::new(0),
+                Arc::new(AtomicUsize::new(0)),
+                SAMPLE_RATE,
+                Arc::new(TempoMap::new(BPM_CENTS)),
+            );
+            for clip in clips {
+                t.insert_clip(clip);
+            }
+            let mut out = vec![0.0; BUFFER_SIZE * CHANNELS];
+            t.output(&info, &mut out, None);
+            (t, out)
+        };
+
+        let (_, a) = track(vec![clip(0, Some(3), BUFFER_SIZE)]);
+        let (_, b) = track(vec![clip(2, Some(2), BUFFER_SIZE)]);
+        let (mut t, both) = track(vec![
+            clip(0, Some(3), BUFFER_SIZE),
+            clip(2, Some(2), BUFFER_SIZE),
+        ]);
+
+        let fade = 2 * SBU..3 * SBU;
+        for i in 0..BUFFER_SIZE {
+            let gain_a = crossfade_gain(i, &(0..0), &fade);
+            let gain_b = crossfade_gain(i, &fade, &(0..0));
+            if fade.contains(&i) {
+                // The gains are complementary in power
+                assert!((gain_a * gain_a + gain_b * gain_b - 1.0).abs() < 1e-6);
+            }
+
+            for c in 0..CHANNELS {
+                let s = i * CHANNELS + c;
+                assert!(
+                    (both[s] - (gain_a as Sample * a[s] + gain_b as Sample * b[s])).abs() < 1e-6
+                );
+            }
+        }
+
+        // Both clips have ended
+        t.with_relevant_clip_not_moving(|clip_opt| {
+            assert!(clip_opt.is_none());
+        });
+    }
+
+    #[test]
+    fn jump_into_crossfade() {
+        let p = Arc::new(AtomicUsize::new(0));
+        let mut t = TimelineTrackProcessor::new(
+            MixerTrackKey::new(0),
+            Arc::clone(&p),
+            SAMPLE_RATE,
+            Arc::new(TempoMap::new(BPM_CENTS)),
+        );
+        let c1 = clip(0, Some(4), 100);
+        let c2 = clip(2, Some(3), 100);
+
+        no_heap! {{
+            t.insert_clip(c1);
+            t.insert_clip(c2);
+
+            p.store(3 * SBU, Ordering::Relaxed);
+            t.jump();
+
+            // The first clip is still playing, even though the second one has started
+            t.with_relevant_clip_not_moving(|clip_opt| {
+                assert_eq!(clip_opt.unwrap().length.unwrap().beat_units(), 4);
+            });
+
+            let mut out = [0.0; 2 * SBU * CHANNELS];
+            t.output(&Info {
+                sample_rate: SAMPLE_RATE,
+                buffer_size: 2 * SBU,
+            }, &mut out[..], None);
+            for &s in out.iter() {
+                assert_ne!(s, 0.0);
+            }
+            p.fetch_add(2 * SBU, Ordering::Relaxed);
+
+            // Only the second clip is left
+            t.with_relevant_clip_not_moving(|clip_opt| {
+                assert_eq!(clip_opt.unwrap().length.unwrap().beat_units(), 3);
+            });
+        }}
+    }
+
+    #[test]
+    fn output_layered() {
+        const BUFFER_SIZE: usize = 5 * SBU;
+        let info = Info {
+            sample_rate: SAMPLE_RATE,
+            buffer_size: BUFFER_SIZE,
+        };
+        let track = |clips: Vec<Box<TreeNode<AudioClipProcessor>>>| {
+            let mut t = TimelineTrackProcessor::new(
+                MixerTrackKey::new(0),
+                Arc::new(AtomicUsize::new(0)),
+                SAMPLE_RATE,
+                Arc::new(TempoMap::new(BPM_CENTS)),
+            );
+            t.set_layered(true);
+            for clip in clips {
+                t.insert_clip(clip);
+            }
+            let mut out = vec![0.0; BUFFER_SIZE * CHANNELS];
+            t.output(&info, &mut out, None);
+            out
+        };
+
+        let a = track(vec![clip(0, Some(5), BUFFER_SIZE)]);
+        let b = track(vec![clip(1, Some(1), BUFFER_SIZE)]);
+        let c = track(vec![clip(3, Some(1), BUFFER_SIZE)]);
+        let all = track(vec![
+            clip(0, Some(5), BUFFER_SIZE),
+            clip(1, Some(1), BUFFER_SIZE),
+            clip(3, Some(1), BUFFER_SIZE),
+        ]);
+
+        // Summed without crossfading
+        for s in 0..BUFFER_SIZE * CHANNELS {
+            assert!((all[s] - (a[s] + b[s] + c[s])).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn output_inactive() {
+        const BUFFER_SIZE: usize = 2 * SBU;
+        let info = Info {
+            sample_rate: SAMPLE_RATE,
+            buffer_size: BUFFER_SIZE,
+        };
+        let pos = Arc::new(AtomicUsize::new(0));
+        let mut t = TimelineTrackProcessor::new(
+            MixerTrackKey::new(0),
+            Arc::clone(&pos),
+            SAMPLE_RATE,
+            Arc::new(TempoMap::new(BPM_CENTS)),
+        );
+        t.insert_clip(clip(0, Some(3), 3 * SBU));
+        t.insert_clip(clip(3, Some(1), 3 * SBU));
+
+        let mut out = vec![0.0; BUFFER_SIZE * CHANNELS];
+        no_heap! {{
+            t.set_clip_active(Timestamp::from_beat_units(0), false);
+            t.output(&info, &mut out, None);
+        }}
+        assert!(out.iter().all(|&s| s == 0.0));
+        pos.fetch_add(BUFFER_SIZE, Ordering::Relaxed);
+
+        // Picks up where it would have been, and the next clip plays as usual
+        t.set_clip_active(Timestamp::from_beat_units(0), true);
+        t.output(&info, &mut out, None);
+        assert!(out.iter().all(|&s| s != 0.0));
+    }
+
+    #[test]
+    fn jump_layered() {
+        let p = Arc::new(AtomicUsize::new(0));
+        let mut t = TimelineTrackProcessor::new(
+            MixerTrackKey::new(0),
+            Arc::clone(&p),
+            SAMPLE_RATE,
+            Arc::new(TempoMap::new(BPM_CENTS)),
+        );
+        t.set_layered(true);
+        let c1 = clip(0, Some(5), 100);
+        let c2 = clip(1, Some(1), 100);
+        let c3 = clip(3, Some(1), 100);
+
+        no_heap! {{
+            t.insert_clip(c1);
+            t.insert_clip(c2);
+            t.insert_clip(c3);
+
+            p.store(2 * SBU, Ordering::Relaxed);
+            t.jump();
+
+            // The first clip is still playing, even though a shorter clip after it has ended
+            t.with_relevant_clip_not_moving(|clip_opt| {
+                assert_eq!(clip_opt.unwrap().length.unwrap().beat_units(), 5);
+            });
+
+            p.store(5 * SBU, Ordering::Relaxed);
+            t.jump();
+            t.with_relevant_clip_not_moving(|clip_opt| {
+                assert!(clip_opt.is_none());
+            });
+        }}
+    }
 }
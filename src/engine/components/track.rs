@@ -1,91 +1,329 @@
 use serde::{Deserialize, Serialize};
 use std::hash::{Hash, Hasher};
 use std::iter::zip;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
 
 use super::audio_meter::{audio_meter, AudioMeter, AudioMeterProcessor};
+use super::automation::automation_value;
+use super::dc_blocker::DcBlocker;
+use super::effect::{
+    effect, Effect, EffectInterface, EffectKey, EffectOverflowError, EffectState,
+    InvalidEffectError,
+};
 use super::parameter::{f32_parameter, F32Parameter, F32ParameterProcessor};
+use super::spectrum_analyzer::{spectrum_analyzer, SpectrumAnalyzer, SpectrumAnalyzerProcessor};
+use super::timeline::Timestamp;
 use crate::engine::info::Info;
-use crate::engine::utils::key_generator::key_type;
+use crate::engine::utils::dropper::DBox;
+use crate::engine::utils::key_generator::{key_type, KeyGenerator};
+use crate::engine::utils::remote_push::{RemotePushable, RemotePushedVec, RemotePusherVec};
+use crate::engine::utils::ringbuffer::{self, ringbuffer};
+use crate::engine::utils::simd;
+use crate::engine::utils::unity_gain;
 use crate::engine::{Sample, CHANNELS};
 
 key_type!(pub struct MixerTrackKey(u32));
 
+/// Changes to the input gain, volume, panning and stereo width of the track are smoothed over roughly `smoothing` frames.
 pub fn mixer_track(
     key: MixerTrackKey,
     max_buffer_size: usize,
+    smoothing: usize,
 ) -> (MixerTrack, MixerTrackProcessor) {
     mixer_track_from_state(
         &MixerTrackState {
-            panning: 0.0,
-            volume: 1.0,
             key,
+            ..Default::default()
         },
         max_buffer_size,
+        smoothing,
     )
 }
 
 pub fn mixer_track_from_state(
     state: &MixerTrackState,
     max_buffer_size: usize,
+    smoothing: usize,
 ) -> (MixerTrack, MixerTrackProcessor) {
-    let (panning, panning_processor) = f32_parameter(state.panning, max_buffer_size);
-    let (volume, volume_processor) = f32_parameter(state.volume, max_buffer_size);
+    let (input_gain, input_gain_processor) =
+        f32_parameter(state.input_gain, max_buffer_size, smoothing);
+    let (panning, panning_processor) = f32_parameter(state.panning, max_buffer_size, smoothing);
+    let (volume, volume_processor) = f32_parameter(state.volume, max_buffer_size, smoothing);
+    let (stereo_width, stereo_width_processor) =
+        f32_parameter(state.stereo_width, max_buffer_size, smoothing);
     let (meter, meter_processor) = audio_meter();
+    let (spectrum, spectrum_processor) = spectrum_analyzer();
+
+    let muted1 = Arc::new(AtomicBool::new(state.muted));
+    let muted2 = Arc::clone(&muted1);
+    let soloed1 = Arc::new(AtomicBool::new(state.soloed));
+    let soloed2 = Arc::clone(&soloed1);
+    let (invert_left, invert_right) = state.phase_invert;
+    let phase_invert1 = Arc::new([AtomicBool::new(invert_left), AtomicBool::new(invert_right)]);
+    let phase_invert2 = Arc::clone(&phase_invert1);
+    let dc_block1 = Arc::new(AtomicBool::new(state.dc_block));
+    let dc_block2 = Arc::clone(&dc_block1);
+
+    let effect_key_generator = KeyGenerator::from_iter(state.effects.iter().map(|&(key, _)| key));
+    let mut effects = Vec::with_capacity(state.effects.len());
+    let mut effect_processors = Vec::with_capacity(state.effects.len());
+    for (key, state) in &state.effects {
+        let (effect, effect_processor) = effect(state);
+        effects.push((*key, effect));
+        effect_processors.push((*key, DBox::new(effect_processor)));
+    }
+    let (effect_processors_pusher, effect_processors_pushed) = effect_processors.into_remote_push();
+
+    let (sends_sender, sends_receiver) = ringbuffer();
+    let (sidechain_sender, sidechain_receiver) = ringbuffer();
+    let tapped1 = Arc::new(AtomicBool::new(false));
+    let tapped2 = Arc::clone(&tapped1);
+    let rank1 = Arc::new(AtomicUsize::new(0));
+    let rank2 = Arc::clone(&rank1);
+    let (volume_automation_sender, volume_automation_receiver) = ringbuffer();
+    let (panning_automation_sender, panning_automation_receiver) = ringbuffer();
 
     (
         MixerTrack {
             key: state.key,
+            name: state.name.clone(),
+            color: state.color,
 
+            input_gain,
             panning,
             volume,
+            stereo_width,
+            muted: muted1,
+            soloed: soloed1,
+            phase_invert: phase_invert1,
+            dc_block: dc_block1,
             meter,
+            spectrum,
+
+            effect_key_generator,
+            effects,
+            effect_processors: effect_processors_pusher,
+
+            sends: state.sends.clone(),
+            sends_sender,
+            sidechain: state.sidechain,
+            sidechain_sender,
+            tapped: tapped1,
+            rank: rank1,
+
+            volume_automation: state.volume_automation.clone(),
+            volume_automation_sender,
+            panning_automation: state.panning_automation.clone(),
+            panning_automation_sender,
         },
         MixerTrackProcessor {
+            input_gain: input_gain_processor,
             panning: panning_processor,
             volume: volume_processor,
+            stereo_width: stereo_width_processor,
+            muted: muted2,
+            soloed: soloed2,
+            phase_invert: phase_invert2,
+            dc_block: dc_block2,
+            dc_blocker: DcBlocker::new(),
             meter: meter_processor,
+            spectrum: spectrum_processor,
+            effects: effect_processors_pushed,
+
+            sends: DBox::new(state.sends.clone()),
+            sends_receiver,
+            sidechain: state.sidechain,
+            sidechain_receiver,
+            tapped: tapped2,
+            rank: rank2,
+            current_rank: 0,
+
+            volume_automation: DBox::new(state.volume_automation.clone()),
+            volume_automation_receiver,
+            panning_automation: DBox::new(state.panning_automation.clone()),
+            panning_automation_receiver,
         },
     )
 }
 
 pub struct MixerTrack {
     key: MixerTrackKey,
+    name: String,
+    /// RGBA
+    color: u32,
 
+    input_gain: F32Parameter,
     panning: F32Parameter,
     volume: F32Parameter,
+    stereo_width: F32Parameter,
+    muted: Arc<AtomicBool>,
+    soloed: Arc<AtomicBool>,
+    /// Left and right.
+    phase_invert: Arc<[AtomicBool; CHANNELS]>,
+    dc_block: Arc<AtomicBool>,
     meter: AudioMeter,
+    spectrum: SpectrumAnalyzer,
+
+    effect_key_generator: KeyGenerator<EffectKey>,
+    /// In the order they are applied.
+    effects: Vec<(EffectKey, EffectInterface)>,
+    effect_processors: RemotePusherVec<EffectKey, DBox<Box<dyn Effect>>>,
+
+    sends: Vec<(MixerTrackKey, f32)>,
+    sends_sender: ringbuffer::Sender<DBox<Vec<(MixerTrackKey, f32)>>>,
+    sidechain: Option<MixerTrackKey>,
+    sidechain_sender: ringbuffer::Sender<Option<MixerTrackKey>>,
+    /// Whether any other track uses this one as its sidechain.
+    tapped: Arc<AtomicBool>,
+    /// The number of sends or sidechains the signal has to pass through at most, to reach this track.
+    rank: Arc<AtomicUsize>,
+
+    volume_automation: Vec<(Timestamp, f32)>,
+    volume_automation_sender: ringbuffer::Sender<DBox<Vec<(Timestamp, f32)>>>,
+    panning_automation: Vec<(Timestamp, f32)>,
+    panning_automation_sender: ringbuffer::Sender<DBox<Vec<(Timestamp, f32)>>>,
 }
 impl MixerTrack {
     pub fn key(&self) -> MixerTrackKey {
         self.key
     }
 
-    pub fn panning(&self) -> Sample {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
+
+    /// The color of the track as RGBA, e.g. `0xFF0000FF` for opaque red.
+    pub fn color(&self) -> u32 {
+        self.color
+    }
+    pub fn set_color(&mut self, color: u32) {
+        self.color = color;
+    }
+
+    pub fn input_gain(&self) -> f32 {
+        self.input_gain.get()
+    }
+    /// Linear gain applied to the incoming signal, where `1.0` is unity gain.
+    ///
+    /// Applied before everything else on the track, including the effects,
+    /// unlike the volume which is applied after them.
+    pub fn set_input_gain(&self, value: f32) {
+        self.input_gain.set(value)
+    }
+
+    pub fn panning(&self) -> f32 {
         self.panning.get()
     }
-    pub fn set_panning(&self, value: Sample) {
+    pub fn set_panning(&self, value: f32) {
         self.panning.set(value)
     }
 
-    pub fn volume(&self) -> Sample {
+    pub fn volume(&self) -> f32 {
         self.volume.get()
     }
-    pub fn set_volume(&self, value: Sample) {
+    pub fn set_volume(&self, value: f32) {
         self.volume.set(value)
     }
 
+    pub fn stereo_width(&self) -> f32 {
+        self.stereo_width.get()
+    }
+    /// Scale the difference between the channels, via mid-side processing.
+    ///
+    /// 0 is mono, 1 leaves the signal unchanged, and values above 1 make it wider.
+    /// Applied after volume, before panning.
+    pub fn set_stereo_width(&self, value: f32) {
+        self.stereo_width.set(value)
+    }
+
+    /// Get the breakpoints of the volume automation, sorted by position.
+    pub fn volume_automation(&self) -> &[(Timestamp, f32)] {
+        &self.volume_automation
+    }
+    /// Automate the volume of the track along the timeline.
+    ///
+    /// The volume is linearly interpolated between the breakpoints as the playhead moves,
+    /// and holds the value of the first or last breakpoint outside of them.
+    /// While there are no breakpoints, [`Self::volume`] is used instead.
+    pub fn set_volume_automation(&mut self, mut points: Vec<(Timestamp, f32)>) {
+        points.sort_by_key(|&(position, _)| position);
+        self.volume_automation_sender
+            .send(DBox::new(points.clone()));
+        self.volume_automation = points;
+    }
+
+    /// Get the breakpoints of the panning automation, sorted by position.
+    pub fn panning_automation(&self) -> &[(Timestamp, f32)] {
+        &self.panning_automation
+    }
+    /// Automate the panning of the track along the timeline.
+    ///
+    /// Works like [`Self::set_volume_automation`], falling back to [`Self::panning`].
+    pub fn set_panning_automation(&mut self, mut points: Vec<(Timestamp, f32)>) {
+        points.sort_by_key(|&(position, _)| position);
+        self.panning_automation_sender
+            .send(DBox::new(points.clone()));
+        self.panning_automation = points;
+    }
+
+    pub fn muted(&self) -> bool {
+        self.muted.load(Ordering::Relaxed)
+    }
+    /// Silence the output of this track.
+    pub fn set_mute(&self, muted: bool) {
+        self.muted.store(muted, Ordering::Relaxed)
+    }
+
+    pub fn soloed(&self) -> bool {
+        self.soloed.load(Ordering::Relaxed)
+    }
+    /// While any track in the mixer is soloed, all tracks that are not soloed are silenced.
+    ///
+    /// Soloing has no effect on the master track.
+    pub fn set_solo(&self, soloed: bool) {
+        self.soloed.store(soloed, Ordering::Relaxed)
+    }
+
+    /// Whether the left and right channel are phase inverted, respectively.
+    pub fn phase_invert(&self) -> (bool, bool) {
+        let [left, right] = &*self.phase_invert;
+        (left.load(Ordering::Relaxed), right.load(Ordering::Relaxed))
+    }
+    /// Negate the samples of the chosen channels, e.g. to fix a miswired recording.
+    ///
+    /// Applied before the effects of the track.
+    pub fn set_phase_invert(&self, left: bool, right: bool) {
+        let [l, r] = &*self.phase_invert;
+        l.store(left, Ordering::Relaxed);
+        r.store(right, Ordering::Relaxed);
+    }
+
+    pub fn dc_block(&self) -> bool {
+        self.dc_block.load(Ordering::Relaxed)
+    }
+    /// Filter out any DC offset from the signal, e.g. of clips that were recorded with one.
+    ///
+    /// Applied before the effects of the track.
+    pub fn set_dc_block(&self, enabled: bool) {
+        self.dc_block.store(enabled, Ordering::Relaxed)
+    }
+
     /// Returns an array of the signals current peak, long-term peak and RMS-level for each channel in the form:
     /// - `[peak: [left, right], long_peak: [left, right], rms: [left, right]]`
     ///
     /// Results are scaled and smoothed to avoid jittering, suitable for reading every frame.
     /// If this is not desirable see [`Self::read_meter_raw`].
-    pub fn read_meter(&mut self) -> [[Sample; CHANNELS]; 3] {
+    pub fn read_meter(&mut self) -> [[f32; CHANNELS]; 3] {
         self.meter.read()
     }
     /// Same as [`Self::read_meter`], except results are not smoothed or scaled.
     ///
     /// Long peak stays in place for 1 second since it was last changed, before snapping to the current peak.
-    pub fn read_meter_raw(&self) -> [[Sample; CHANNELS]; 3] {
+    pub fn read_meter_raw(&self) -> [[f32; CHANNELS]; 3] {
         self.meter.read_raw()
     }
     /// Snap smoothed rms value to its current unsmoothed equivalent.
@@ -96,11 +334,137 @@ impl MixerTrack {
         self.meter.snap_rms();
     }
 
+    /// Whether the spectrum of the track's output is analyzed.
+    pub fn spectrum_enabled(&self) -> bool {
+        self.spectrum.enabled()
+    }
+    /// The analysis is skipped entirely while disabled, which it is by default,
+    /// so it should only be enabled while the spectrum is shown.
+    pub fn set_spectrum_enabled(&self, enabled: bool) {
+        self.spectrum.set_enabled(enabled)
+    }
+    /// Number of samples in each FFT of the spectrum analysis.
+    pub fn spectrum_size(&self) -> usize {
+        self.spectrum.size()
+    }
+    /// Larger sizes give a finer frequency resolution, but react slower to changes.
+    ///
+    /// The size is rounded up to the nearest power of two, between 64 and 16384, and is 2048 by default.
+    pub fn set_spectrum_size(&mut self, size: usize) {
+        self.spectrum.set_size(size)
+    }
+    /// Returns the linear magnitude of the track's most recent output, grouped into the given number of bins.
+    ///
+    /// The bins are spaced evenly from 0 Hz to half the sample rate,
+    /// each containing the highest magnitude within its range.
+    /// Is all zeroes until the spectrum has been enabled for a short while.
+    pub fn spectrum(&self, bins: usize) -> Vec<f32> {
+        self.spectrum.read(bins)
+    }
+
+    /// Add an effect to the end of the track's effect chain.
+    ///
+    /// Effects are applied in the order they were added, before volume and panning.
+    pub fn add_effect(&mut self, state: &EffectState) -> Result<EffectKey, EffectOverflowError> {
+        let key = self.effect_key_generator.next()?;
+        let (effect, effect_processor) = effect(state);
+        self.effects.push((key, effect));
+        self.effect_processors
+            .push((key, DBox::new(effect_processor)));
+        Ok(key)
+    }
+
+    pub fn effect(&self, key: EffectKey) -> Result<&EffectInterface, InvalidEffectError> {
+        self.effects
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, effect)| effect)
+            .ok_or(InvalidEffectError { key })
+    }
+    pub fn effect_mut(
+        &mut self,
+        key: EffectKey,
+    ) -> Result<&mut EffectInterface, InvalidEffectError> {
+        self.effects
+            .iter_mut()
+            .find(|(k, _)| *k == key)
+            .map(|(_, effect)| effect)
+            .ok_or(InvalidEffectError { key })
+    }
+
+    /// Get the keys of all effects on the track, in the order they are applied.
+    pub fn effects(&self) -> impl Iterator<Item = EffectKey> + '_ {
+        self.effects.iter().map(|&(key, _)| key)
+    }
+
+    /// Remove the effect from the track, returning its last state.
+    pub fn remove_effect(&mut self, key: EffectKey) -> Result<EffectState, InvalidEffectError> {
+        let index = self
+            .effects
+            .iter()
+            .position(|(k, _)| *k == key)
+            .ok_or(InvalidEffectError { key })?;
+
+        self.effect_key_generator
+            .free(key)
+            .expect("Effect key exists in effects but not in key_generator");
+        let (_, effect) = self.effects.remove(index);
+        self.effect_processors.remove(key);
+        Ok(effect.state())
+    }
+
+    /// Get the tracks that this track sends a copy of its output to, along with the gain of each send.
+    ///
+    /// Sends are added via [`Engine::add_send`](crate::Engine::add_send).
+    pub fn sends(&self) -> &[(MixerTrackKey, f32)] {
+        &self.sends
+    }
+    pub(super) fn set_sends(&mut self, sends: Vec<(MixerTrackKey, f32)>) {
+        self.sends_sender.send(DBox::new(sends.clone()));
+        self.sends = sends;
+    }
+
+    /// Get the track whose output the dynamics effects of this track react to, instead of the track's own signal.
+    ///
+    /// Sidechains are set via [`Engine::set_sidechain`](crate::Engine::set_sidechain).
+    pub fn sidechain(&self) -> Option<MixerTrackKey> {
+        self.sidechain
+    }
+    pub(super) fn set_sidechain(&mut self, sidechain: Option<MixerTrackKey>) {
+        self.sidechain_sender.send(sidechain);
+        self.sidechain = sidechain;
+    }
+
+    pub(super) fn set_tapped(&self, tapped: bool) {
+        self.tapped.store(tapped, Ordering::Relaxed);
+    }
+
+    pub(super) fn set_rank(&self, rank: usize) {
+        self.rank.store(rank, Ordering::Relaxed);
+    }
+
     /// Takes a snapshot of the current state of the track
     pub(crate) fn state(&self) -> MixerTrackState {
         MixerTrackState {
+            input_gain: self.input_gain.get(),
             panning: self.panning.get(),
             volume: self.volume.get(),
+            stereo_width: self.stereo_width.get(),
+            muted: self.muted(),
+            soloed: self.soloed(),
+            phase_invert: self.phase_invert(),
+            dc_block: self.dc_block(),
+            effects: self
+                .effects
+                .iter()
+                .map(|(key, effect)| (*key, effect.state()))
+                .collect(),
+            sends: self.sends.clone(),
+            sidechain: self.sidechain,
+            name: self.name.clone(),
+            color: self.color,
+            volume_automation: self.volume_automation.clone(),
+            panning_automation: self.panning_automation.clone(),
             key: self.key(),
         }
     }
@@ -110,26 +474,81 @@ impl MixerTrack {
 /// that is relevant to reconstructing it
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MixerTrackState {
+    /// Applied before the effects, unlike `volume`.
+    #[serde(default = "unity_gain")]
+    pub input_gain: f32,
     pub panning: f32,
     pub volume: f32,
+    /// 0 is mono, 1 is unchanged.
+    #[serde(default = "unchanged_width")]
+    pub stereo_width: f32,
+    #[serde(default)]
+    pub muted: bool,
+    #[serde(default)]
+    pub soloed: bool,
+    /// Whether the left and right channel are phase inverted, respectively.
+    #[serde(default)]
+    pub phase_invert: (bool, bool),
+    #[serde(default)]
+    pub dc_block: bool,
+    /// In the order they are applied.
+    #[serde(default)]
+    pub effects: Vec<(EffectKey, EffectState)>,
+    /// Target tracks along with the gain of each send.
+    #[serde(default)]
+    pub sends: Vec<(MixerTrackKey, f32)>,
+    /// The track whose output the dynamics effects react to, if not the track itself.
+    #[serde(default)]
+    pub sidechain: Option<MixerTrackKey>,
+    #[serde(default)]
+    pub name: String,
+    /// RGBA
+    #[serde(default)]
+    pub color: u32,
+    /// Breakpoints of the volume along the timeline, sorted by position.
+    #[serde(default)]
+    pub volume_automation: Vec<(Timestamp, f32)>,
+    /// Breakpoints of the panning along the timeline, sorted by position.
+    #[serde(default)]
+    pub panning_automation: Vec<(Timestamp, f32)>,
 
     pub key: MixerTrackKey,
 }
 impl Default for MixerTrackState {
     fn default() -> Self {
         Self {
+            input_gain: 1.0,
             panning: 0.0,
             volume: 1.0,
+            stereo_width: 1.0,
+            muted: false,
+            soloed: false,
+            phase_invert: (false, false),
+            dc_block: false,
+            effects: Vec::new(),
+            sends: Vec::new(),
+            sidechain: None,
+            name: String::new(),
+            color: 0,
+            volume_automation: Vec::new(),
+            panning_automation: Vec::new(),
 
             key: MixerTrackKey(0),
         }
     }
 }
+fn unchanged_width() -> f32 {
+    1.0
+}
 impl PartialEq for MixerTrackState {
     fn eq(&self, other: &Self) -> bool {
         let res = self.key == other.key;
 
         if res {
+            debug_assert_eq!(
+                self.input_gain, other.input_gain,
+                "Two tracks with the same key have different input gain"
+            );
             debug_assert_eq!(
                 self.panning, other.panning,
                 "Two tracks with the same key have different panning"
@@ -138,6 +557,54 @@ impl PartialEq for MixerTrackState {
                 self.volume, other.volume,
                 "Two tracks with the same key have different volume"
             );
+            debug_assert_eq!(
+                self.stereo_width, other.stereo_width,
+                "Two tracks with the same key have different stereo width"
+            );
+            debug_assert_eq!(
+                self.muted, other.muted,
+                "Two tracks with the same key have different mute states"
+            );
+            debug_assert_eq!(
+                self.soloed, other.soloed,
+                "Two tracks with the same key have different solo states"
+            );
+            debug_assert_eq!(
+                self.phase_invert, other.phase_invert,
+                "Two tracks with the same key have different phase inversion"
+            );
+            debug_assert_eq!(
+                self.dc_block, other.dc_block,
+                "Two tracks with the same key have different DC blocking"
+            );
+            debug_assert_eq!(
+                self.effects, other.effects,
+                "Two tracks with the same key have different effects"
+            );
+            debug_assert_eq!(
+                self.sends, other.sends,
+                "Two tracks with the same key have different sends"
+            );
+            debug_assert_eq!(
+                self.sidechain, other.sidechain,
+                "Two tracks with the same key have different sidechains"
+            );
+            debug_assert_eq!(
+                self.name, other.name,
+                "Two tracks with the same key have different names"
+            );
+            debug_assert_eq!(
+                self.color, other.color,
+                "Two tracks with the same key have different colors"
+            );
+            debug_assert_eq!(
+                self.volume_automation, other.volume_automation,
+                "Two tracks with the same key have different volume automation"
+            );
+            debug_assert_eq!(
+                self.panning_automation, other.panning_automation,
+                "Two tracks with the same key have different panning automation"
+            );
         }
 
         res
@@ -152,41 +619,178 @@ impl Hash for MixerTrackState {
 
 #[derive(Debug)]
 pub struct MixerTrackProcessor {
+    input_gain: F32ParameterProcessor,
     panning: F32ParameterProcessor,
     volume: F32ParameterProcessor,
+    stereo_width: F32ParameterProcessor,
+    muted: Arc<AtomicBool>,
+    soloed: Arc<AtomicBool>,
+    phase_invert: Arc<[AtomicBool; CHANNELS]>,
+    dc_block: Arc<AtomicBool>,
+    dc_blocker: DcBlocker,
     meter: AudioMeterProcessor,
+    spectrum: SpectrumAnalyzerProcessor,
+    effects: RemotePushedVec<EffectKey, DBox<Box<dyn Effect>>>,
+
+    sends: DBox<Vec<(MixerTrackKey, f32)>>,
+    sends_receiver: ringbuffer::Receiver<DBox<Vec<(MixerTrackKey, f32)>>>,
+    sidechain: Option<MixerTrackKey>,
+    sidechain_receiver: ringbuffer::Receiver<Option<MixerTrackKey>>,
+    tapped: Arc<AtomicBool>,
+    rank: Arc<AtomicUsize>,
+    /// Snapshot of `rank`, so it stays the same throughout each buffer.
+    current_rank: usize,
+
+    volume_automation: DBox<Vec<(Timestamp, f32)>>,
+    volume_automation_receiver: ringbuffer::Receiver<DBox<Vec<(Timestamp, f32)>>>,
+    panning_automation: DBox<Vec<(Timestamp, f32)>>,
+    panning_automation_receiver: ringbuffer::Receiver<DBox<Vec<(Timestamp, f32)>>>,
 }
 impl MixerTrackProcessor {
+    pub fn soloed(&self) -> bool {
+        self.soloed.load(Ordering::Relaxed)
+    }
+
+    pub fn sends(&self) -> &[(MixerTrackKey, f32)] {
+        &self.sends
+    }
+
+    pub fn sidechain(&self) -> Option<MixerTrackKey> {
+        self.sidechain
+    }
+
+    /// Whether the output of the track should be kept for the tracks using it as their sidechain.
+    pub fn tapped(&self) -> bool {
+        self.tapped.load(Ordering::Relaxed)
+    }
+
+    /// Tracks should be processed in order of rank, so every send reaches its target before the target is processed,
+    /// and every sidechain is ready before it is used.
+    pub fn rank(&self) -> usize {
+        self.current_rank
+    }
+
+    pub fn poll(&mut self) {
+        self.effects.poll();
+        for (_, effect) in self.effects.iter_mut() {
+            effect.poll();
+        }
+
+        while let Some(sends) = self.sends_receiver.recv() {
+            self.sends = sends;
+        }
+        while let Some(sidechain) = self.sidechain_receiver.recv() {
+            self.sidechain = sidechain;
+        }
+        self.current_rank = self.rank.load(Ordering::Relaxed);
+
+        while let Some(points) = self.volume_automation_receiver.recv() {
+            self.volume_automation = points;
+        }
+        while let Some(points) = self.panning_automation_receiver.recv() {
+            self.panning_automation = points;
+        }
+        self.spectrum.poll();
+    }
+
+    fn invert_phase(invert: [bool; CHANNELS], buffer: &mut [Sample]) {
+        for frame in buffer.chunks_exact_mut(CHANNELS) {
+            for (sample, invert) in zip(frame, invert) {
+                if invert {
+                    *sample = -*sample;
+                }
+            }
+        }
+    }
+
+    fn widen(width: f32, frame: &mut [Sample]) {
+        let mid = (frame[0] + frame[1]) / 2.0;
+        let side = (frame[0] - frame[1]) / 2.0 * width as Sample;
+        frame[0] = mid + side;
+        frame[1] = mid - side;
+    }
+
     fn pan(panning: f32, frame: &mut [Sample]) {
         // TODO: Pan laws
         let left_multiplier = (-panning + 1.0).clamp(0.0, 1.0);
-        frame[0] *= left_multiplier;
+        frame[0] *= left_multiplier as Sample;
 
         let right_multiplier = (panning + 1.0).clamp(0.0, 1.0);
-        frame[1] *= right_multiplier;
+        frame[1] *= right_multiplier as Sample;
     }
 
-    pub fn process(&mut self, info: &Info, buffer: &mut [Sample]) {
+    /// Process the buffer in place.
+    ///
+    /// If `silenced` is true, or the track is muted, the buffer is zeroed before processing.
+    /// Automation is evaluated at `playhead`, which should be the position at the start of the buffer.
+    /// If `sidechain` is given, the effects react to it instead of the buffer, and it should be at least `info.buffer_size` frames long.
+    pub fn process(
+        &mut self,
+        info: &Info,
+        buffer: &mut [Sample],
+        silenced: bool,
+        playhead: Timestamp,
+        sidechain: Option<&[Sample]>,
+    ) {
         let Info {
             sample_rate,
             buffer_size,
         } = *info;
 
-        let volume_buffer = self.volume.get(buffer_size);
-        let panning_buffer = self.panning.get(buffer_size);
+        if silenced || self.muted.load(Ordering::Relaxed) {
+            buffer.fill(0.0);
+        }
+
+        let input_gain_buffer = self.input_gain.get(buffer_size);
+        simd::scale_frames(&mut buffer[..buffer_size * CHANNELS], input_gain_buffer);
 
-        for ((frame, &mut volume), &mut panning) in zip(
-            zip(buffer.chunks_mut(CHANNELS), volume_buffer),
-            panning_buffer,
-        ) {
-            for sample in frame.iter_mut() {
-                *sample *= volume;
+        let invert = self
+            .phase_invert
+            .each_ref()
+            .map(|invert| invert.load(Ordering::Relaxed));
+        if invert.contains(&true) {
+            Self::invert_phase(invert, &mut buffer[..buffer_size * CHANNELS]);
+        }
+
+        if self.dc_block.load(Ordering::Relaxed) {
+            self.dc_blocker
+                .process(&mut buffer[..buffer_size * CHANNELS], sample_rate);
+        } else {
+            self.dc_blocker.reset();
+        }
+
+        for (_, effect) in self.effects.iter_mut() {
+            let buffer = &mut buffer[..buffer_size * CHANNELS];
+            match sidechain {
+                Some(sidechain) => {
+                    effect.process_sidechained(info, buffer, &sidechain[..buffer_size * CHANNELS])
+                }
+                None => effect.process(info, buffer),
             }
+        }
 
+        let volume_buffer = match automation_value(&self.volume_automation, playhead) {
+            Some(volume) => self.volume.get_towards(buffer_size, volume),
+            None => self.volume.get(buffer_size),
+        };
+        let panning_buffer = match automation_value(&self.panning_automation, playhead) {
+            Some(panning) => self.panning.get_towards(buffer_size, panning),
+            None => self.panning.get(buffer_size),
+        };
+
+        let width_buffer = self.stereo_width.get(buffer_size);
+
+        simd::scale_frames(buffer, volume_buffer);
+        for ((frame, &mut panning), &mut width) in zip(
+            zip(buffer.chunks_mut(CHANNELS), panning_buffer),
+            width_buffer,
+        ) {
+            Self::widen(width, frame);
             Self::pan(panning, frame);
         }
 
         self.meter.report(buffer, sample_rate as f32);
+        self.spectrum.report(&buffer[..buffer_size * CHANNELS]);
     }
 }
 
@@ -194,6 +798,61 @@ impl MixerTrackProcessor {
 mod tests {
     use super::*;
 
+    #[test]
+    fn invert_phase() {
+        let mut signal = [2.0, 3.0, -4.0, 5.0];
+
+        MixerTrackProcessor::invert_phase([false, true], &mut signal);
+
+        assert_eq!(signal, [2.0, -3.0, -4.0, -5.0]);
+    }
+
+    #[test]
+    fn input_gain() {
+        let (t, mut tp) = mixer_track(MixerTrackKey(0), 10, 0);
+        let info = Info {
+            sample_rate: 40_000,
+            buffer_size: 10,
+        };
+        t.set_input_gain(0.5);
+        t.set_volume(0.5);
+
+        let mut buffer = [1.0; 10 * CHANNELS];
+        no_heap! {{
+            tp.process(&info, &mut buffer, false, Timestamp::zero(), None);
+        }}
+
+        // Both stages are applied
+        assert!(buffer.iter().all(|&s| s == 0.25));
+    }
+
+    #[test]
+    fn stereo_width_mono() {
+        let mut signal = [2.0, 3.0];
+
+        MixerTrackProcessor::widen(0.0, &mut signal);
+
+        assert_eq!(signal, [2.5, 2.5]);
+    }
+
+    #[test]
+    fn stereo_width_unchanged() {
+        let mut signal = [2.0, 3.0];
+
+        MixerTrackProcessor::widen(1.0, &mut signal);
+
+        assert_eq!(signal, [2.0, 3.0]);
+    }
+
+    #[test]
+    fn stereo_width_wide() {
+        let mut signal = [2.0, 3.0];
+
+        MixerTrackProcessor::widen(2.0, &mut signal);
+
+        assert_eq!(signal, [1.5, 3.5]);
+    }
+
     #[test]
     fn pan_center() {
         let mut signal = [2.0, 3.0];
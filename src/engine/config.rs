@@ -6,6 +6,8 @@ use std::ops::RangeInclusive;
 use cpal::traits::DeviceTrait;
 use cpal::traits::HostTrait;
 
+use super::{MAX_BUFFER_SIZE_DEFAULT, MAX_EVENTS_PER_POLL_DEFAULT, PARAMETER_SMOOTHING_DEFAULT};
+
 const PREFERRED_SAMPLE_RATE: u32 = 48_000;
 const PREFERRED_BUFFER_SIZE: u32 = 512;
 
@@ -13,6 +15,38 @@ const PREFERRED_BUFFER_SIZE: u32 = 512;
 pub struct Config {
     pub output_device: OutputDevice,
     pub output_config: OutputConfig,
+
+    /// The device to record from, if any.
+    pub input_device: Option<InputDevice>,
+    /// If `None` while an input device is set, the device's default config is used.
+    pub input_config: Option<InputConfig>,
+
+    /// Dithering applied when outputting to an integer sample format.
+    pub dither: DitherMode,
+    /// The resampling used for clips with a different sample rate than the output.
+    pub resample_quality: ResampleQuality,
+    /// The number of frames each intermediate buffer has room for, when [`OutputConfig::buffer_size`] is `None`.
+    ///
+    /// If the device asks for more frames at once, they are processed in parts of this size,
+    /// and [`Engine::max_buffer_size_exceeded`](crate::Engine::max_buffer_size_exceeded) is set.
+    pub max_buffer_size: usize,
+    /// Seconds that changes to the volume, panning and stereo width of mixer tracks are smoothed over,
+    /// to avoid clicks when they are adjusted while playing.
+    ///
+    /// The parameters move most of the way to a new value within this time. `0.0` disables smoothing.
+    pub parameter_smoothing: f32,
+    /// The most changes to the timeline that are applied at the start of each buffer.
+    ///
+    /// Any further changes are held back until the next buffer, which keeps large batches of changes,
+    /// such as adding thousands of clips, from taking up the time needed for processing.
+    /// Raising it applies such batches sooner, at the risk of dropouts, while `usize::MAX` applies everything at once.
+    /// See [`Engine::event_queue_stats`](crate::Engine::event_queue_stats) for how many are waiting.
+    pub max_events_per_poll: usize,
+    /// Whether denormals are flushed to zero while processing.
+    ///
+    /// Denormals are the tiny values that signals pass through while fading out through feedback, like the tail of a delay.
+    /// Many CPUs are far slower at processing them, which can cause dropouts even though they are inaudible.
+    pub flush_denormals: bool,
 }
 impl Config {
     pub fn dummy() -> Self {
@@ -24,6 +58,14 @@ impl Config {
                 sample_rate: 48_000,
                 buffer_size: None,
             },
+            input_device: None,
+            input_config: None,
+            dither: DitherMode::None,
+            resample_quality: ResampleQuality::default(),
+            max_buffer_size: MAX_BUFFER_SIZE_DEFAULT,
+            parameter_smoothing: PARAMETER_SMOOTHING_DEFAULT,
+            max_events_per_poll: MAX_EVENTS_PER_POLL_DEFAULT,
+            flush_denormals: true,
         }
     }
 }
@@ -41,12 +83,62 @@ impl Default for Config {
         Self {
             output_device,
             output_config,
+            input_device: None,
+            input_config: None,
+            dither: DitherMode::None,
+            resample_quality: ResampleQuality::default(),
+            max_buffer_size: MAX_BUFFER_SIZE_DEFAULT,
+            parameter_smoothing: PARAMETER_SMOOTHING_DEFAULT,
+            max_events_per_poll: MAX_EVENTS_PER_POLL_DEFAULT,
+            flush_denormals: true,
         }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct OutputConfig {
+    /// Either 2 for stereo, or 1 for the output to be downmixed to mono.
+    pub channels: u16,
+    pub sample_format: SampleFormat,
+    pub sample_rate: u32,
+
+    /// Buffer size in frames.
+    /// If `None`, the default buffer size is used.
+    pub buffer_size: Option<u32>,
+}
+
+/// Noise added to the output before it is rounded to an integer sample format,
+/// which turns the distortion from quantization into a constant low noise floor.
+///
+/// Only 8 and 16-bit integer formats are affected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DitherMode {
+    #[default]
+    None,
+    /// Noise with a uniform distribution, spanning one step of the output format.
+    Rectangular { seed: u64 },
+    /// Noise with a triangular distribution, spanning two steps of the output format.
+    /// Unlike rectangular dither, this keeps the noise floor from depending on the signal.
+    Tpdf { seed: u64 },
+}
+
+/// How clips with a different sample rate than the output are resampled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResampleQuality {
+    /// Linear interpolation between neighbouring samples.
+    /// The cheapest option with no latency, but it lets through aliasing.
+    Linear,
+    /// Windowed sinc interpolation.
+    /// Close to the quality of [`ResampleQuality::Fft`], at a lower latency.
+    Sinc,
+    /// Resampling in the frequency domain, using FFTs.
+    /// The highest quality, but with the highest latency and cost for short clips.
+    #[default]
+    Fft,
+}
+
+#[derive(Debug, Clone)]
+pub struct InputConfig {
     pub channels: u16,
     pub sample_format: SampleFormat,
     pub sample_rate: u32,
@@ -71,26 +163,57 @@ impl Host {
         })
     }
 
-    pub fn output_devices(
-        &self,
-    ) -> Result<impl Iterator<Item = OutputDevice> + '_, HostUnavailableError> {
+    /// List all output devices currently available on this host.
+    ///
+    /// Devices whose name cannot be read are left out, since they cannot be referred to later.
+    pub fn output_devices(&self) -> Result<Vec<OutputDevice>, DevicesError> {
         Ok(self
-            .raw()?
-            .output_devices()
-            .unwrap()
-            .map(|device| OutputDevice {
+            .raw()
+            .map_err(DevicesError::HostUnavailable)?
+            .output_devices()?
+            .filter_map(|device| device.name().ok())
+            .map(|name| OutputDevice {
                 host: self.clone(),
-                name: device.name().unwrap(),
-            }))
+                name,
+            })
+            .collect())
     }
 
     pub fn default_output_device(&self) -> Result<Option<OutputDevice>, HostUnavailableError> {
         Ok(self
             .raw()?
             .default_output_device()
-            .map(|device| OutputDevice {
+            .and_then(|device| device.name().ok())
+            .map(|name| OutputDevice {
                 host: self.clone(),
-                name: device.name().unwrap(),
+                name,
+            }))
+    }
+
+    /// List all input devices currently available on this host.
+    ///
+    /// Devices whose name cannot be read are left out, since they cannot be referred to later.
+    pub fn input_devices(&self) -> Result<Vec<InputDevice>, DevicesError> {
+        Ok(self
+            .raw()
+            .map_err(DevicesError::HostUnavailable)?
+            .input_devices()?
+            .filter_map(|device| device.name().ok())
+            .map(|name| InputDevice {
+                host: self.clone(),
+                name,
+            })
+            .collect())
+    }
+
+    pub fn default_input_device(&self) -> Result<Option<InputDevice>, HostUnavailableError> {
+        Ok(self
+            .raw()?
+            .default_input_device()
+            .and_then(|device| device.name().ok())
+            .map(|name| InputDevice {
+                host: self.clone(),
+                name,
             }))
     }
 
@@ -141,13 +264,20 @@ impl OutputDevice {
         &self.name
     }
 
-    pub fn supported_config_ranges(
-        &self,
-    ) -> Result<impl Iterator<Item = OutputConfigRange>, DeviceUnavailableError> {
+    /// List the ranges of configs that this device supports.
+    pub fn supported_configs(&self) -> Result<Vec<OutputConfigRange>, SupportedConfigsError> {
         Ok(self
-            .raw()?
+            .raw()
+            .map_err(SupportedConfigsError::DeviceUnavailable)?
             .supported_output_configs()
-            .unwrap()
+            .map_err(|e| match e {
+                cpal::SupportedStreamConfigsError::DeviceNotAvailable => {
+                    SupportedConfigsError::DeviceUnavailable(
+                        DeviceUnavailableError::DeviceUnavailable(self.name.clone()),
+                    )
+                }
+                e => e.into(),
+            })?
             .map(|config| {
                 let channels = config.channels();
                 let sample_format = config.sample_format().into();
@@ -162,11 +292,15 @@ impl OutputDevice {
                     sample_rate,
                     buffer_size,
                 }
-            }))
+            })
+            .collect())
     }
 
     pub fn default_config_range(&self) -> Result<OutputConfigRange, DeviceUnavailableError> {
-        let config = self.raw()?.default_output_config().unwrap();
+        let config = self
+            .raw()?
+            .default_output_config()
+            .map_err(|_| DeviceUnavailableError::DeviceUnavailable(self.name.clone()))?;
         let channels = config.channels();
         let sample_format = config.sample_format().into();
         let sample_rate = config.sample_rate().0..=config.sample_rate().0;
@@ -196,7 +330,47 @@ impl OutputDevice {
             .map_err(|e| DeviceUnavailableError::HostUnavailable(e.name))?;
 
         host.output_devices()
-            .unwrap()
+            .map_err(|_| DeviceUnavailableError::DeviceUnavailable(self.name.clone()))?
+            .find(|device| device.name().map(|name| name == self.name).unwrap_or(false))
+            .ok_or_else(|| DeviceUnavailableError::DeviceUnavailable(self.name.clone()))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InputDevice {
+    host: Host,
+    name: String,
+}
+impl InputDevice {
+    pub fn host(&self) -> &Host {
+        &self.host
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn default_config(&self) -> Result<InputConfig, DeviceUnavailableError> {
+        let config = self
+            .raw()?
+            .default_input_config()
+            .map_err(|_| DeviceUnavailableError::DeviceUnavailable(self.name.clone()))?;
+        Ok(InputConfig {
+            channels: config.channels(),
+            sample_format: config.sample_format().into(),
+            sample_rate: config.sample_rate().0,
+            buffer_size: None,
+        })
+    }
+
+    pub(crate) fn raw(&self) -> Result<cpal::Device, DeviceUnavailableError> {
+        let host = self
+            .host
+            .raw()
+            .map_err(|e| DeviceUnavailableError::HostUnavailable(e.name))?;
+
+        host.input_devices()
+            .map_err(|_| DeviceUnavailableError::DeviceUnavailable(self.name.clone()))?
             .find(|device| device.name().map(|name| name == self.name).unwrap_or(false))
             .ok_or_else(|| DeviceUnavailableError::DeviceUnavailable(self.name.clone()))
     }
@@ -335,3 +509,61 @@ impl Display for DeviceUnavailableError {
     }
 }
 impl Error for DeviceUnavailableError {}
+
+#[derive(Debug, Clone)]
+pub enum DevicesError {
+    HostUnavailable(HostUnavailableError),
+    /// An error reported by the host's backend.
+    Backend(String),
+}
+impl Display for DevicesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DevicesError::HostUnavailable(e) => Display::fmt(e, f),
+            DevicesError::Backend(description) => {
+                write!(f, "Devices could not be listed: {}", description)
+            }
+        }
+    }
+}
+impl Error for DevicesError {}
+impl From<cpal::DevicesError> for DevicesError {
+    fn from(e: cpal::DevicesError) -> Self {
+        match e {
+            cpal::DevicesError::BackendSpecific { err } => DevicesError::Backend(err.description),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum SupportedConfigsError {
+    DeviceUnavailable(DeviceUnavailableError),
+    /// An error reported by the host's backend.
+    Backend(String),
+}
+impl Display for SupportedConfigsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SupportedConfigsError::DeviceUnavailable(e) => Display::fmt(e, f),
+            SupportedConfigsError::Backend(description) => {
+                write!(f, "Supported configs could not be listed: {}", description)
+            }
+        }
+    }
+}
+impl Error for SupportedConfigsError {}
+impl From<cpal::SupportedStreamConfigsError> for SupportedConfigsError {
+    fn from(e: cpal::SupportedStreamConfigsError) -> Self {
+        match e {
+            cpal::SupportedStreamConfigsError::DeviceNotAvailable => {
+                SupportedConfigsError::Backend("Device is not available".to_owned())
+            }
+            cpal::SupportedStreamConfigsError::InvalidArgument => {
+                SupportedConfigsError::Backend("Invalid argument".to_owned())
+            }
+            cpal::SupportedStreamConfigsError::BackendSpecific { err } => {
+                SupportedConfigsError::Backend(err.description)
+            }
+        }
+    }
+}
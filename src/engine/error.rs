@@ -1,8 +1,15 @@
 pub use super::{
-    config::{DeviceUnavailableError, HostUnavailableError},
-    AddClipError, AudioClipReconstructionError, AudioTrackOverflowError,
-    AudioTrackReconstructionError, ImportError, InvalidAudioClipError, InvalidAudioClipsError,
-    InvalidAudioTrackError, InvalidConfigError, InvalidMixerTrackError,
-    InvalidStoredAudioClipError, InvalidTimelineTrackError, MixerTrackOverflowError,
-    MoveAudioClipError, MoveAudioClipToTrackError, TimelineTrackOverflowError,
+    config::{DeviceUnavailableError, DevicesError, HostUnavailableError, SupportedConfigsError},
+    AddClipError, AddSendError, AudioClipReconstructionError, AudioTrackOverflowError,
+    AudioTrackReconstructionError, ClipOverflowError, CrossfadeError, DeleteTimeError,
+    EffectOverflowError, FileImportError, FreezeTrackError, ImportError, InvalidAudioClipError,
+    InvalidAudioClipsError, InvalidAudioTrackError, InvalidConfigError, InvalidEffectError,
+    InvalidLoopRegionError, InvalidMarkerError, InvalidMixerTrackError, InvalidSendError,
+    InvalidStoredAudioClipError, InvalidTempoChangeError, InvalidTimeSignatureChangeError,
+    InvalidTimeSignatureError, InvalidTimelineTrackError, LoadStateError, MarkerOverflowError,
+    MergeAudioClipsError, MixerTrackOverflowError, MoveAudioClipError, MoveAudioClipToTrackError,
+    RemoveStoredAudioClipError, RemoveTempoChangeError, RenderStemsError, RenderTrackError,
+    ReverseAudioClipError, SetOverlapPolicyError, SetSidechainError, SplitAudioClipError,
+    StartOutputCaptureError, StartRecordingError, StopOutputCaptureError, StopRecordingError,
+    StreamDeadError, StreamError, TempoOverlapError, TimelineTrackOverflowError,
 };
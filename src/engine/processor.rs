@@ -1,41 +1,80 @@
 use cpal::StreamConfig;
 use serde::{Deserialize, Serialize};
-use std::iter::zip;
+use std::{
+    cmp::min,
+    collections::HashSet,
+    fmt::Debug,
+    iter::zip,
+    mem,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
 use super::components::{
-    audio_clip_store::ImportError,
+    audio_clip_store::{AudioClipStoreState, DecodedClips, ImportError},
     mixer::{mixer, Mixer, MixerProcessor, MixerState},
-    timeline::{timeline, Timeline, TimelineProcessor, TimelineState},
+    output_capture::{output_capture, OutputCapture, OutputCaptureInput},
+    timeline::{
+        timeline_with_decoded, InvalidTimelineTrackError, Tempo, Timeline, TimelineProcessor,
+        TimelineState, TimelineTrackKey, Timestamp,
+    },
+    MixerTrackState,
+};
+use super::{
+    config::ResampleQuality, info::Info, utils::dither::Dither, Sample, CHANNELS,
+    PARAMETER_SMOOTHING_DEFAULT,
 };
-use super::{info::Info, Sample, CHANNELS};
 #[cfg(feature = "record_output")]
 use crate::wav_recorder::WavRecorder;
 
 /// Creates an corresponding pair of [`Processor`] and [`ProcessorInterface`].
 ///
 /// The [`Processor`] should live on the audio thread, while the [`ProcessorInterface`] should not.
+///
+/// Stored clips in `decoded_clips` are reused rather than imported again.
 pub fn processor(
     state: &ProcessorState,
     stream_config: &StreamConfig,
     max_buffer_size: usize,
+    resample_quality: ResampleQuality,
+    parameter_smoothing: f32,
+    decoded_clips: &DecodedClips,
 ) -> (ProcessorInterface, Processor, Vec<ImportError>) {
     let output_channels = stream_config.channels;
     let sample_rate = stream_config.sample_rate.0;
+    let smoothing = (parameter_smoothing.max(0.0) * sample_rate as f32).round() as usize;
 
-    let (timeline, timeline_processor, import_errors) =
-        timeline(&state.timeline, sample_rate, max_buffer_size);
-    let (mixer, mixer_processor) = mixer(&state.mixer, max_buffer_size);
+    let (timeline, timeline_processor, import_errors) = timeline_with_decoded(
+        &state.timeline,
+        sample_rate,
+        max_buffer_size,
+        resample_quality,
+        decoded_clips,
+    );
+    let (mixer, mixer_processor) = mixer(&state.mixer, max_buffer_size, smoothing);
+    let (output_capture, output_capture_input) = output_capture(sample_rate);
+    let max_buffer_size_exceeded1 = Arc::new(AtomicBool::new(false));
+    let max_buffer_size_exceeded2 = Arc::clone(&max_buffer_size_exceeded1);
 
     (
-        ProcessorInterface { mixer, timeline },
+        ProcessorInterface {
+            mixer,
+            timeline,
+            output_capture,
+            max_buffer_size_exceeded: max_buffer_size_exceeded1,
+        },
         Processor {
             output_channels,
             sample_rate,
-            #[cfg(debug_assertions)]
             max_buffer_size,
+            max_buffer_size_exceeded: max_buffer_size_exceeded2,
 
             mixer: mixer_processor,
             timeline: timeline_processor,
+            dither: Dither::default(),
+            output_capture: output_capture_input,
 
             #[cfg(feature = "record_output")]
             recorder: WavRecorder::new(
@@ -54,25 +93,209 @@ pub fn processor(
 pub struct ProcessorInterface {
     pub mixer: Mixer,
     pub timeline: Timeline,
+    pub output_capture: OutputCapture,
+    max_buffer_size_exceeded: Arc<AtomicBool>,
 }
 impl ProcessorInterface {
+    /// Whether the processor has been asked for a buffer bigger than its maximum buffer size.
+    pub fn max_buffer_size_exceeded(&self) -> bool {
+        self.max_buffer_size_exceeded.load(Ordering::Relaxed)
+    }
+
     pub fn state(&self) -> ProcessorState {
         ProcessorState {
             mixer: self.mixer.state(),
             timeline: self.timeline.state(),
         }
     }
+
+    /// Takes a snapshot of the timeline track along with the stored clips and tempo it depends on,
+    /// feeding into a copy of its mixer track where everything but the input gain and effects is left at its default.
+    ///
+    /// If `effects` is false, the input gain and effects are left out as well.
+    pub fn isolated_track_state(
+        &self,
+        key: TimelineTrackKey,
+        effects: bool,
+    ) -> Result<ProcessorState, InvalidTimelineTrackError> {
+        let track_state = self.timeline.track_state(key)?;
+        let mixer_track_state = self
+            .mixer
+            .track(track_state.output_track)
+            .expect("Timeline track outputs to a mixer track that does not exist")
+            .state();
+
+        let timeline_state = self.timeline.state();
+        let store_state = timeline_state.audio_clip_store;
+        let used: HashSet<_> = track_state
+            .clips
+            .iter()
+            .map(|clip_state| clip_state.inner)
+            .collect();
+
+        Ok(ProcessorState {
+            mixer: MixerState {
+                tracks: vec![MixerTrackState {
+                    key: mixer_track_state.key,
+                    input_gain: if effects {
+                        mixer_track_state.input_gain
+                    } else {
+                        1.0
+                    },
+                    effects: if effects {
+                        mixer_track_state.effects
+                    } else {
+                        Vec::new()
+                    },
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            timeline: TimelineState {
+                bpm_cents: timeline_state.bpm_cents,
+                tempo_changes: timeline_state.tempo_changes,
+                time_signature: timeline_state.time_signature,
+                time_signature_changes: timeline_state.time_signature_changes,
+                loop_region: None,
+                markers: Vec::new(),
+                audio_clip_store: AudioClipStoreState {
+                    clips: store_state
+                        .clips
+                        .into_iter()
+                        .filter(|(_, key)| used.contains(key))
+                        .collect(),
+                    streamed: store_state
+                        .streamed
+                        .into_iter()
+                        .filter(|(key, _)| used.contains(key))
+                        .collect(),
+                    downmix: store_state.downmix,
+                },
+                tracks: vec![track_state],
+            },
+        })
+    }
+}
+
+/// The biggest buffer requested from the processor while rendering offline.
+const RENDER_BUFFER_SIZE: usize = 1024;
+
+/// Renders the state offline from `start` to `end` on the timeline,
+/// returning the interleaved output at the given sample rate.
+///
+/// If `end` is not after `start`, the output is empty.
+///
+/// Clips are resampled at the highest quality.
+///
+/// Fails if any of the stored clips cannot be imported.
+pub fn render(
+    state: &ProcessorState,
+    sample_rate: u32,
+    start: Timestamp,
+    end: Timestamp,
+) -> Result<Vec<Sample>, ImportError> {
+    let mut render = render_iter(state, sample_rate, start, end, usize::MAX)?;
+    Ok(render.next().unwrap_or_default())
+}
+
+/// Same as [`render`], except the output is rendered lazily,
+/// in chunks of `chunk_frames` frames each, apart from the last one which may be shorter.
+///
+/// A `chunk_frames` of zero is treated as one.
+pub fn render_iter(
+    state: &ProcessorState,
+    sample_rate: u32,
+    start: Timestamp,
+    end: Timestamp,
+    chunk_frames: usize,
+) -> Result<RenderIter, ImportError> {
+    let (mut interface, mut processor, import_errors) = processor(
+        state,
+        &StreamConfig {
+            channels: CHANNELS as u16,
+            sample_rate: cpal::SampleRate(sample_rate),
+            buffer_size: cpal::BufferSize::Default,
+        },
+        RENDER_BUFFER_SIZE,
+        ResampleQuality::Fft,
+        PARAMETER_SMOOTHING_DEFAULT,
+        &DecodedClips::new(),
+    );
+    if let Some(error) = import_errors.into_iter().next() {
+        return Err(error);
+    }
+
+    let tempo_map = interface.timeline.tempo_map();
+    let frames = tempo_map
+        .samples(end, sample_rate)
+        .saturating_sub(tempo_map.samples(start, sample_rate));
+
+    interface.timeline.jump_to(start);
+    interface.timeline.play();
+    processor.poll();
+
+    Ok(RenderIter {
+        _interface: interface,
+        processor,
+        chunk_frames: chunk_frames.max(1),
+        remaining_frames: frames,
+    })
+}
+
+/// An offline render in progress, acquired via [`render_iter`].
+///
+/// Yields the interleaved output in chunks, rendering each one only once it is requested.
+pub struct RenderIter {
+    /// Kept alive along with the processor, even though nothing is changed after the render has started.
+    _interface: ProcessorInterface,
+    processor: Processor,
+    chunk_frames: usize,
+    remaining_frames: usize,
+}
+impl Iterator for RenderIter {
+    type Item = Vec<Sample>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining_frames == 0 {
+            return None;
+        }
+        let frames = min(self.chunk_frames, self.remaining_frames);
+        self.remaining_frames -= frames;
+
+        let mut chunk = Vec::with_capacity(frames * CHANNELS);
+        while chunk.len() < frames * CHANNELS {
+            let buffer_size = min(RENDER_BUFFER_SIZE, frames - chunk.len() / CHANNELS);
+            chunk.extend_from_slice(self.processor.output_samples(buffer_size));
+        }
+        Some(chunk)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let chunks = self.remaining_frames.div_ceil(self.chunk_frames);
+        (chunks, Some(chunks))
+    }
+}
+impl ExactSizeIterator for RenderIter {}
+impl Debug for RenderIter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RenderIter")
+            .field("chunk_frames", &self.chunk_frames)
+            .field("remaining_frames", &self.remaining_frames)
+            .finish_non_exhaustive()
+    }
 }
 
 /// Contatins all data that should persist from one buffer output to the next.
 pub struct Processor {
     output_channels: u16,
     sample_rate: u32,
-    #[cfg(debug_assertions)]
     max_buffer_size: usize,
+    max_buffer_size_exceeded: Arc<AtomicBool>,
 
     mixer: MixerProcessor,
     timeline: TimelineProcessor,
+    dither: Dither,
+    output_capture: OutputCaptureInput,
 
     #[cfg(feature = "record_output")]
     recorder: WavRecorder,
@@ -84,19 +307,56 @@ impl Processor {
         self.mixer.poll();
     }
 
+    /// Set the dithering applied when converting to the stream's sample type.
+    pub fn set_dither(&mut self, dither: Dither) {
+        self.dither = dither;
+    }
+
+    /// Set the maximum number of timeline events applied on each poll.
+    pub fn set_max_events_per_poll(&mut self, max_events_per_poll: usize) {
+        self.timeline.set_max_events_per_poll(max_events_per_poll);
+    }
+
     /// The function called to generate each audio buffer.
+    ///
+    /// The output must have either [`CHANNELS`] channels, or a single channel which the output is downmixed to.
+    ///
+    /// If `data` has room for more than the maximum buffer size, it is filled in parts of that size.
     pub fn output<T: cpal::Sample + cpal::FromSample<Sample>>(&mut self, data: &mut [T]) {
+        let output_channels = usize::from(self.output_channels);
+        let part_len = self.max_buffer_size * output_channels;
+        if data.len() > part_len {
+            self.max_buffer_size_exceeded.store(true, Ordering::Relaxed);
+        }
+
+        for part in data.chunks_mut(part_len) {
+            self.output_part(part);
+        }
+    }
+
+    fn output_part<T: cpal::Sample + cpal::FromSample<Sample>>(&mut self, data: &mut [T]) {
         // In some cases the buffer size can vary from one buffer to the next.
         let buffer_size = data.len() / usize::from(self.output_channels);
+        let output_channels = self.output_channels;
+        // Taken out while the buffer borrows the processor
+        let mut dither = mem::take(&mut self.dither);
 
         let buffer = self.output_samples(buffer_size);
 
         // Convert to stream's sample type.
-        for (&mut in_sample, out_sample) in zip(buffer, data) {
-            *out_sample = T::from_sample(in_sample);
+        if output_channels == 1 {
+            for (frame, out_sample) in zip(buffer.chunks_exact(CHANNELS), data) {
+                let sum: Sample = frame.iter().sum();
+                *out_sample = T::from_sample(dither.apply(sum / CHANNELS as Sample));
+            }
+        } else {
+            debug_assert_eq!(CHANNELS, usize::from(output_channels));
+            for (&mut in_sample, out_sample) in zip(buffer, data) {
+                *out_sample = T::from_sample(dither.apply(in_sample));
+            }
         }
-        // TODO: Scale channel counts.
-        debug_assert_eq!(CHANNELS, self.output_channels.into());
+
+        self.dither = dither;
     }
 
     fn output_samples(&mut self, buffer_size: usize) -> &mut [Sample] {
@@ -109,14 +369,23 @@ impl Processor {
             sample_rate: self.sample_rate,
             buffer_size,
         };
+        let playhead = self.timeline.playhead_position();
         let timeline_out = self.mixer.source_outs();
         self.timeline.output(timeline_out, &info);
-        let buffer = self.mixer.output(&info);
+        let buffer = self.mixer.output(&info, playhead);
+
+        for (sample, click) in zip(
+            buffer.iter_mut(),
+            self.timeline.metronome_output(buffer_size),
+        ) {
+            *sample += click;
+        }
 
         Self::clip(buffer);
+        self.output_capture.push(buffer);
 
         #[cfg(feature = "record_output")]
-        self.recorder.record(buffer);
+        self.recorder.record(buffer).unwrap();
 
         buffer
     }
@@ -132,6 +401,111 @@ impl Processor {
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
 pub struct ProcessorState {
-    mixer: MixerState,
-    timeline: TimelineState,
+    pub(crate) mixer: MixerState,
+    pub(crate) timeline: TimelineState,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{utils::test_file_path, Engine};
+
+    fn processor_with_channels(channels: u16) -> (ProcessorInterface, Processor) {
+        processor_with_state(&ProcessorState::default(), channels)
+    }
+
+    fn processor_with_state(
+        state: &ProcessorState,
+        channels: u16,
+    ) -> (ProcessorInterface, Processor) {
+        let (mut interface, processor, _) = processor(
+            state,
+            &StreamConfig {
+                channels,
+                sample_rate: cpal::SampleRate(48_000),
+                buffer_size: cpal::BufferSize::Default,
+            },
+            1024,
+            ResampleQuality::default(),
+            PARAMETER_SMOOTHING_DEFAULT,
+            &DecodedClips::new(),
+        );
+        interface.timeline.metronome().set_enabled(true);
+        interface.timeline.play();
+        (interface, processor)
+    }
+
+    #[test]
+    fn mono_output() {
+        let (_i1, mut stereo) = processor_with_channels(2);
+        let (_i2, mut mono) = processor_with_channels(1);
+
+        let mut stereo_data = vec![0.0_f32; 1024 * 2];
+        let mut mono_data = vec![0.0_f32; 1024];
+        no_heap! {{
+            stereo.poll();
+            stereo.output(&mut stereo_data);
+            mono.poll();
+            mono.output(&mut mono_data);
+        }}
+
+        // The metronome is centered, so the downmix is the same as either channel
+        assert!(mono_data.iter().any(|&s| s != 0.0));
+        for (frame, &sample) in zip(stereo_data.chunks_exact(2), &mono_data) {
+            assert_eq!(sample, (frame[0] + frame[1]) / 2.0);
+        }
+    }
+
+    #[test]
+    fn oversized_buffer() {
+        let (i1, mut whole) = processor_with_channels(2);
+        let (i2, mut parts) = processor_with_channels(2);
+
+        let mut whole_data = vec![0.0_f32; 3000 * 2];
+        let mut parts_data = vec![0.0_f32; 3000 * 2];
+        no_heap! {{
+            whole.poll();
+            whole.output(&mut whole_data);
+            parts.poll();
+            for part in parts_data.chunks_mut(1000 * 2) {
+                parts.output(part);
+            }
+        }}
+
+        assert!(i1.max_buffer_size_exceeded());
+        assert!(!i2.max_buffer_size_exceeded());
+        assert!(whole_data.iter().any(|&s| s != 0.0));
+        assert_eq!(whole_data, parts_data);
+    }
+
+    #[test]
+    fn oversized_buffer_through_tracks() {
+        // A callback far bigger than the intermediate buffers has to pass through the timeline and mixer as well
+        let mut e = Engine::dummy();
+        let at = e.add_audio_track().unwrap();
+        let tk = e.audio_timeline_track_key(at).unwrap();
+        let ck = e
+            .import_audio_clip(&test_file_path("48000 16-bit.wav"))
+            .unwrap();
+        e.add_audio_clip(tk, ck, Timestamp::zero(), None).unwrap();
+        let state = e.state().processor;
+
+        let (i1, mut whole) = processor_with_state(&state, 2);
+        let (_i2, mut parts) = processor_with_state(&state, 2);
+
+        let mut whole_data = vec![0.0_f32; 10_000 * 2];
+        let mut parts_data = vec![0.0_f32; 10_000 * 2];
+        no_heap! {{
+            whole.poll();
+            whole.output(&mut whole_data);
+            parts.poll();
+            for part in parts_data.chunks_mut(1024 * 2) {
+                parts.output(part);
+            }
+        }}
+
+        assert!(i1.max_buffer_size_exceeded());
+        assert!(whole_data.iter().any(|&s| s != 0.0));
+        assert_eq!(whole_data, parts_data);
+    }
 }
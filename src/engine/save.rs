@@ -0,0 +1,393 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt::Display;
+use std::hash::Hash;
+
+use super::{
+    AudioClipKey, AudioTrackKey, EngineState, MixerTrackKey, StoredAudioClipKey, TimelineTrackKey,
+};
+
+mod v1;
+
+/// Written at the start of the binary format, to recognize it.
+const MAGIC: &[u8; 4] = b"ADAE";
+
+/// Upgrades the state from each version of the format to the next, starting from version 1.
+///
+/// Fields that were added with a default are filled in when deserializing,
+/// so only changes that can not be expressed that way need a migration.
+const MIGRATIONS: [fn(&mut Value); EngineState::FORMAT_VERSION as usize - 1] = [
+    // 1 → 2: Only the encoding of the binary format changed
+    |_| {},
+];
+
+#[derive(Serialize)]
+struct SaveFileRef<'a> {
+    version: u32,
+    state: &'a EngineState,
+}
+#[derive(Deserialize)]
+struct SaveFile {
+    version: u32,
+    state: Value,
+}
+
+impl EngineState {
+    /// The version of the format written by [`EngineState::to_json`] and [`EngineState::to_bytes`].
+    ///
+    /// States saved with an older version of the format are upgraded when they are loaded.
+    pub const FORMAT_VERSION: u32 = 2;
+
+    /// Serialize the state to JSON, including the version of the format.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&SaveFileRef {
+            version: Self::FORMAT_VERSION,
+            state: self,
+        })
+        .expect("EngineState could not be serialized to JSON")
+    }
+
+    /// Deserialize a state previously serialized with [`EngineState::to_json`],
+    /// upgrading it if it was saved with an older version of the format.
+    pub fn from_json(json: &str) -> Result<Self, LoadStateError> {
+        let SaveFile { version, state } = serde_json::from_str(json).map_err(malformed)?;
+        Self::load(version, state)
+    }
+
+    /// Serialize the state to a compact binary format, prefixed by a header with the version of the format.
+    ///
+    /// The state itself is encoded as MessagePack with named fields,
+    /// so fields can be added to it without breaking states saved earlier.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::from(*MAGIC);
+        bytes.extend(Self::FORMAT_VERSION.to_le_bytes());
+        rmp_serde::encode::write_named(&mut bytes, self)
+            .expect("EngineState could not be serialized to bytes");
+        bytes
+    }
+
+    /// Deserialize a state previously serialized with [`EngineState::to_bytes`],
+    /// upgrading it if it was saved with an older version of the format.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, LoadStateError> {
+        let data = bytes
+            .strip_prefix(MAGIC)
+            .ok_or(LoadStateError::InvalidHeader)?;
+        let (version, data) = data
+            .split_first_chunk()
+            .ok_or(LoadStateError::InvalidHeader)?;
+        let version = u32::from_le_bytes(*version);
+        check_version(version)?;
+
+        let state = if version == 1 {
+            // Version 1 was encoded with bincode, which does not describe the fields it contains
+            let state: v1::EngineState = bincode::deserialize(data).map_err(malformed)?;
+            serde_json::to_value(state).map_err(malformed)?
+        } else {
+            rmp_serde::from_slice(data).map_err(malformed)?
+        };
+        Self::load(version, state)
+    }
+
+    /// Upgrade the `state` from `version` to the current version of the format, and deserialize it.
+    fn load(version: u32, mut state: Value) -> Result<Self, LoadStateError> {
+        check_version(version)?;
+        for migration in &MIGRATIONS[version as usize - 1..] {
+            migration(&mut state);
+        }
+
+        let state: EngineState = serde_json::from_value(state).map_err(malformed)?;
+        state.validate()?;
+        Ok(state)
+    }
+
+    /// Check that no key is used more than once, which the rest of the engine assumes.
+    fn validate(&self) -> Result<(), LoadStateError> {
+        let mixer = &self.processor.mixer;
+        let timeline = &self.processor.timeline;
+
+        unique(self.audio_tracks.iter().map(|&(key, _, _)| key))
+            .map_err(LoadStateError::DuplicateAudioTrack)?;
+        unique(
+            mixer
+                .tracks
+                .iter()
+                .chain([&mixer.master])
+                .map(|track| track.key),
+        )
+        .map_err(LoadStateError::DuplicateMixerTrack)?;
+        unique(timeline.tracks.iter().map(|track| track.key))
+            .map_err(LoadStateError::DuplicateTimelineTrack)?;
+        // Clip keys are unique across the entire timeline
+        unique(
+            timeline
+                .tracks
+                .iter()
+                .flat_map(|track| &track.clips)
+                .map(|clip| clip.key),
+        )
+        .map_err(LoadStateError::DuplicateAudioClip)?;
+        unique(timeline.audio_clip_store.clips.iter().map(|&(_, key)| key))
+            .map_err(LoadStateError::DuplicateStoredAudioClip)?;
+
+        Ok(())
+    }
+}
+
+fn check_version(version: u32) -> Result<(), LoadStateError> {
+    if version == 0 || version > EngineState::FORMAT_VERSION {
+        return Err(LoadStateError::UnsupportedVersion(version));
+    }
+    Ok(())
+}
+
+fn malformed(error: impl Display) -> LoadStateError {
+    LoadStateError::Malformed(error.to_string())
+}
+
+/// Returns the first key that has already been seen, if any.
+fn unique<K: Hash + Eq + Copy>(keys: impl Iterator<Item = K>) -> Result<(), K> {
+    let mut seen = HashSet::new();
+    for key in keys {
+        if !seen.insert(key) {
+            return Err(key);
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum LoadStateError {
+    /// The data does not start with the expected header.
+    InvalidHeader,
+    /// The data is of a version of the format that is not supported, e.g. because it was saved by a newer version.
+    UnsupportedVersion(u32),
+    /// The data could not be parsed, with a description of why.
+    Malformed(String),
+    DuplicateAudioTrack(AudioTrackKey),
+    DuplicateMixerTrack(MixerTrackKey),
+    DuplicateTimelineTrack(TimelineTrackKey),
+    DuplicateAudioClip(AudioClipKey),
+    DuplicateStoredAudioClip(StoredAudioClipKey),
+}
+impl Display for LoadStateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidHeader => write!(f, "The data is not a saved engine state"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "Version {version} of the save format is not supported")
+            }
+            Self::Malformed(message) => write!(f, "The saved engine state is malformed: {message}"),
+            Self::DuplicateAudioTrack(key) => {
+                write!(f, "The audio track key, {key:?}, is used more than once")
+            }
+            Self::DuplicateMixerTrack(key) => {
+                write!(f, "The mixer track key, {key:?}, is used more than once")
+            }
+            Self::DuplicateTimelineTrack(key) => {
+                write!(f, "The timeline track key, {key:?}, is used more than once")
+            }
+            Self::DuplicateAudioClip(key) => {
+                write!(f, "The audio clip key, {key:?}, is used more than once")
+            }
+            Self::DuplicateStoredAudioClip(key) => {
+                write!(
+                    f,
+                    "The stored audio clip key, {key:?}, is used more than once"
+                )
+            }
+        }
+    }
+}
+impl Error for LoadStateError {}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::engine::{utils::test_file_path, EndBehavior, Engine, Timestamp};
+
+    fn state() -> EngineState {
+        let mut e = Engine::dummy();
+        let at = e.add_audio_track().unwrap();
+        let tk = e.audio_timeline_track_key(at).unwrap();
+        let ck = e
+            .import_audio_clip(&test_file_path("44100 16-bit.wav"))
+            .unwrap();
+        let ac = e
+            .add_audio_clip(tk, ck, Timestamp::from_beats(1), None)
+            .unwrap();
+        e.audio_clip_set_gain(ac, 0.3).unwrap();
+        e.audio_clip_set_end_behavior(ac, EndBehavior::Loop)
+            .unwrap();
+        e.set_bpm_cents(133_33).unwrap();
+        e.state()
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let state = state();
+        let json = state.to_json();
+        assert_eq!(EngineState::from_json(&json), Ok(state));
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let state = state();
+        let bytes = state.to_bytes();
+        assert!(bytes.starts_with(MAGIC));
+        assert_eq!(EngineState::from_bytes(&bytes), Ok(state));
+    }
+
+    #[test]
+    fn deterministic_output() {
+        let state = || {
+            let mut e = Engine::dummy();
+            let ck = e
+                .import_audio_clip(&test_file_path("44100 16-bit.wav"))
+                .unwrap();
+            for _ in 0..8 {
+                let at = e.add_audio_track().unwrap();
+                let tk = e.audio_timeline_track_key(at).unwrap();
+                for beat in 0..8 {
+                    e.add_audio_clip(
+                        tk,
+                        ck,
+                        Timestamp::from_beats(beat),
+                        Some(Timestamp::from_beats(1)),
+                    )
+                    .unwrap();
+                }
+            }
+            e.state()
+        };
+
+        // Each engine iterates its tracks and clips in a different order
+        let (state1, state2) = (state(), state());
+        assert_eq!(state1.to_json(), state2.to_json());
+        assert_eq!(state1.to_bytes(), state2.to_bytes());
+
+        let tracks = &state1.processor.timeline.tracks;
+        assert!(tracks.windows(2).all(|w| w[0].key < w[1].key));
+        assert!(tracks
+            .iter()
+            .all(|track| track.clips.windows(2).all(|w| w[0].key < w[1].key)));
+    }
+
+    #[test]
+    fn invalid_data() {
+        assert_eq!(
+            EngineState::from_bytes(b"WAVE"),
+            Err(LoadStateError::InvalidHeader)
+        );
+        assert_eq!(
+            EngineState::from_bytes(b"ADAE\x01"),
+            Err(LoadStateError::InvalidHeader)
+        );
+        assert!(matches!(
+            EngineState::from_bytes(b"ADAE\x01\x00\x00\x00\xFF"),
+            Err(LoadStateError::Malformed(_))
+        ));
+        assert!(matches!(
+            EngineState::from_json("{"),
+            Err(LoadStateError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn newer_version() {
+        let mut bytes = state().to_bytes();
+        let version = EngineState::FORMAT_VERSION + 1;
+        bytes[4..8].copy_from_slice(&version.to_le_bytes());
+        assert_eq!(
+            EngineState::from_bytes(&bytes),
+            Err(LoadStateError::UnsupportedVersion(version))
+        );
+
+        let json = format!(r#"{{"version":{version},"state":null}}"#);
+        assert_eq!(
+            EngineState::from_json(&json),
+            Err(LoadStateError::UnsupportedVersion(version))
+        );
+    }
+
+    #[test]
+    fn version_1() {
+        // Saved by version 1 of the engine, with everything its state could hold
+        let bytes = fs::read(test_file_path("state v1.bin")).unwrap();
+        let json = fs::read_to_string(test_file_path("state v1.json")).unwrap();
+
+        let state = EngineState::from_bytes(&bytes).unwrap();
+        assert_eq!(EngineState::from_json(&json), Ok(state.clone()));
+
+        let mixer = &state.processor.mixer;
+        let track = mixer.tracks.iter().find(|t| t.name == "Vocals").unwrap();
+        assert_eq!(track.volume, 0.75);
+        assert_eq!(track.effects.len(), 1);
+        assert_eq!(mixer.master_limiter.unwrap().ceiling, 0.9);
+        let timeline = &state.processor.timeline;
+        assert_eq!(timeline.bpm_cents, 100_00);
+        assert_eq!(timeline.markers[0].name, "Chorus");
+        let clip = timeline
+            .tracks
+            .iter()
+            .flat_map(|t| &t.clips)
+            .find(|c| c.name == "Intro")
+            .unwrap();
+        assert_eq!(clip.gain, 0.5);
+        assert_eq!(clip.pitch, 2.5);
+
+        // Added after version 1
+        assert_eq!(track.stereo_width, 1.0);
+        assert!(!track.dc_block);
+        assert!(clip.active);
+        assert_eq!(clip.end_behavior, EndBehavior::Silence);
+        assert_eq!(mixer.master_gain, 1.0);
+    }
+
+    #[test]
+    fn missing_fields_are_defaulted() {
+        let state = state();
+
+        // As if the state was saved before the gain of clips was added
+        let mut value = serde_json::to_value(&state).unwrap();
+        let clip = &mut value["processor"]["timeline"]["tracks"][0]["clips"][0];
+        clip.as_object_mut().unwrap().remove("gain").unwrap();
+        let mut expected = state.clone();
+        expected.processor.timeline.tracks[0].clips[0].gain = 1.0;
+
+        let json = serde_json::json!({ "version": 1, "state": value }).to_string();
+        assert_eq!(EngineState::from_json(&json), Ok(expected.clone()));
+
+        let mut bytes = Vec::from(*MAGIC);
+        bytes.extend(EngineState::FORMAT_VERSION.to_le_bytes());
+        rmp_serde::encode::write_named(&mut bytes, &value).unwrap();
+        assert_eq!(EngineState::from_bytes(&bytes), Ok(expected));
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        let mut state = state();
+        let track = state.processor.timeline.tracks[0].clone();
+        let clip = track.clips[0].clone();
+        state.processor.timeline.tracks[0].clips.push(clip.clone());
+
+        assert_eq!(
+            EngineState::from_json(&state.to_json()),
+            Err(LoadStateError::DuplicateAudioClip(clip.key))
+        );
+        assert_eq!(
+            EngineState::from_bytes(&state.to_bytes()),
+            Err(LoadStateError::DuplicateAudioClip(clip.key))
+        );
+
+        let audio_track = state.audio_tracks[0];
+        state.audio_tracks.push(audio_track);
+        assert_eq!(
+            EngineState::from_bytes(&state.to_bytes()),
+            Err(LoadStateError::DuplicateAudioTrack(audio_track.0))
+        );
+    }
+}
@@ -0,0 +1,129 @@
+//! The engine state as it was saved in version 1 of the format.
+//!
+//! The binary format of version 1 was encoded with bincode, which reads fields by their position rather than their name,
+//! so it can only be decoded into the exact structs it was written from.
+//! These are frozen here, and must not change along with the current state.
+//! Their fields are named as in the current state, so they are carried over by serializing them,
+//! leaving the fields added since to their defaults.
+//!
+//! Keys are stored as their inner integers, which is how they are serialized as well.
+
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[derive(Serialize, Deserialize)]
+pub struct EngineState {
+    processor: ProcessorState,
+    audio_tracks: Vec<(u32, u32, u32)>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ProcessorState {
+    mixer: MixerState,
+    timeline: TimelineState,
+}
+
+#[derive(Serialize, Deserialize)]
+struct MixerState {
+    tracks: Vec<MixerTrackState>,
+    master: MixerTrackState,
+    master_limiter: Option<LimiterSettings>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct MixerTrackState {
+    panning: f32,
+    volume: f32,
+    muted: bool,
+    soloed: bool,
+    effects: Vec<(u32, EffectState)>,
+    sends: Vec<(u32, f32)>,
+    name: String,
+    color: u32,
+    volume_automation: Vec<(Timestamp, f32)>,
+    panning_automation: Vec<(Timestamp, f32)>,
+    key: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+enum EffectState {
+    Compressor(CompressorSettings),
+}
+
+#[derive(Serialize, Deserialize)]
+struct CompressorSettings {
+    threshold: f32,
+    ratio: f32,
+    attack: f32,
+    release: f32,
+    makeup_gain: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LimiterSettings {
+    ceiling: f32,
+    release: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TimelineState {
+    bpm_cents: u16,
+    tempo_changes: Vec<(Timestamp, u16)>,
+    loop_region: Option<(Timestamp, Timestamp)>,
+    markers: Vec<Marker>,
+    audio_clip_store: AudioClipStoreState,
+    tracks: Vec<TimelineTrackState>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Timestamp {
+    beat_units: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Marker {
+    key: u32,
+    position: Timestamp,
+    name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AudioClipStoreState {
+    clips: Vec<(PathBuf, u32)>,
+    streamed: Vec<(u32, usize)>,
+    downmix: Downmix,
+}
+
+#[derive(Serialize, Deserialize)]
+enum Downmix {
+    Itu,
+    Matrix(Vec<[f32; 2]>),
+}
+
+#[derive(Serialize, Deserialize)]
+struct TimelineTrackState {
+    key: u32,
+    clips: Vec<AudioClipState>,
+    output_track: u32,
+    name: String,
+    color: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AudioClipState {
+    key: u32,
+    start_offset: usize,
+    start: Timestamp,
+    length: Option<Timestamp>,
+    gain: f32,
+    gain_envelope: Vec<(usize, f32)>,
+    fade_in: Timestamp,
+    fade_out: Timestamp,
+    reversed: bool,
+    stretch: f32,
+    pitch: f32,
+    crossfade: Timestamp,
+    name: String,
+    color: u32,
+    inner: u32,
+}
@@ -1,14 +1,22 @@
+pub mod denormals;
+pub mod dither;
 pub mod dropper;
 pub mod key_generator;
 pub mod rbtree_node;
 pub mod remote_push;
 pub mod ringbuffer;
+pub mod simd;
+pub mod worker_pool;
 
 use std::any::Any;
 use std::fmt::Debug;
 use std::iter::zip;
 use std::sync::atomic::{AtomicU32, Ordering};
 
+#[cfg(test)]
+use std::f32::consts::TAU;
+#[cfg(test)]
+use std::ops::Range;
 #[cfg(test)]
 use std::path::PathBuf;
 
@@ -79,6 +87,16 @@ pub fn test_file_path(file_name: &str) -> PathBuf {
     ))
 }
 
+/// An interleaved sine at 48 kHz, identical in all channels, covering the given range of frames.
+#[cfg(test)]
+pub fn test_sine(frequency: f32, amplitude: Sample, frames: Range<usize>) -> Vec<Sample> {
+    frames
+        .flat_map(|i| {
+            [amplitude * (TAU * frequency * i as f32 / 48_000.0).sin() as Sample; CHANNELS]
+        })
+        .collect()
+}
+
 /// Atomic supporting storing and loading of an f32, via the raw bits of a u32.
 pub struct AtomicF32 {
     inner: AtomicU32,
@@ -104,20 +122,37 @@ impl Debug for AtomicF32 {
     }
 }
 
-/// Root Mean Square of a single buffer.
+/// Root Mean Square of each channel in a single interleaved buffer.
 pub fn rms(buffer: &[Sample]) -> [f32; CHANNELS] {
     let buffer_size = (buffer.len() / CHANNELS) as f64;
     let mut averages = [0.0; CHANNELS];
 
     for frame in buffer.chunks(CHANNELS) {
         for (sample, average) in zip(frame, &mut averages) {
-            *average += f64::from(sample.powi(2)) / buffer_size;
+            *average += sample.powi(2) as f64 / buffer_size;
         }
     }
 
     averages.map(|x| (x as f32).sqrt())
 }
 
+/// Convert a linear amplitude to dBFS, where `1.0` is 0 dBFS.
+///
+/// Silence is negative infinity.
+pub fn amplitude_to_db(amplitude: f32) -> f32 {
+    20.0 * amplitude.abs().log10()
+}
+/// Convert dBFS to a linear amplitude, where 0 dBFS is `1.0`.
+///
+/// The inverse of [`amplitude_to_db`] for non-negative amplitudes.
+pub fn db_to_amplitude(db: f32) -> f32 {
+    10_f32.powf(db / 20.0)
+}
+/// Linear gain that leaves the signal unchanged, for use as a `#[serde(default)]`.
+pub fn unity_gain() -> f32 {
+    1.0
+}
+
 /// Calculates simple moving average with an internal history buffer.
 #[derive(Debug)]
 pub struct MovingAverage {
@@ -234,6 +269,22 @@ mod tests {
         assert_eq!(result, expected)
     }
 
+    #[test]
+    fn decibels() {
+        assert_eq!(amplitude_to_db(1.0), 0.0);
+        assert!((amplitude_to_db(-0.1) + 20.0).abs() < 1e-5);
+        assert_eq!(amplitude_to_db(0.0), f32::NEG_INFINITY);
+        assert_eq!(db_to_amplitude(0.0), 1.0);
+        assert_eq!(db_to_amplitude(f32::NEG_INFINITY), 0.0);
+
+        for amplitude in [0.001, 0.25, 0.5, 2.0] {
+            let result = db_to_amplitude(amplitude_to_db(amplitude));
+            assert!((result - amplitude).abs() < 1e-6 * amplitude);
+        }
+        // Roughly 6 dB per doubling
+        assert!((amplitude_to_db(0.5) + 6.0206).abs() < 1e-4);
+    }
+
     #[test]
     fn moving_average() {
         let mut ma = MovingAverage::new(1.0, 10);
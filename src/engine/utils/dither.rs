@@ -0,0 +1,137 @@
+use crate::engine::config::{DitherMode, SampleFormat, SampleFormatInt, SampleFormatIntUnsigned};
+use crate::engine::Sample;
+
+/// Adds dither noise to samples before they are converted to an integer sample format,
+/// and rounds them to the nearest value representable in it.
+///
+/// Only 8 and 16-bit formats are dithered,
+/// since the steps of wider formats are finer than the precision of [`Sample`] itself.
+#[derive(Debug)]
+pub struct Dither {
+    mode: DitherMode,
+    /// The distance between two consecutive values of the output format,
+    /// or `None` if it isn't dithered.
+    step: Option<Sample>,
+    /// State of the xorshift generator the noise is drawn from.
+    state: u64,
+}
+impl Dither {
+    pub fn new(mode: DitherMode, sample_format: &SampleFormat) -> Self {
+        let bits = match sample_format {
+            SampleFormat::Int(SampleFormatInt::I8)
+            | SampleFormat::IntUnsigned(SampleFormatIntUnsigned::U8) => Some(8),
+            SampleFormat::Int(SampleFormatInt::I16)
+            | SampleFormat::IntUnsigned(SampleFormatIntUnsigned::U16) => Some(16),
+            _ => None,
+        };
+        let seed = match mode {
+            DitherMode::None => 0,
+            DitherMode::Rectangular { seed } | DitherMode::Tpdf { seed } => seed,
+        };
+
+        Dither {
+            mode,
+            step: bits.map(|bits| Sample::powi(2.0, 1 - bits)),
+            // Xorshift gets stuck at zero, and needs a few rounds to get going from small seeds
+            state: seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1,
+        }
+    }
+
+    /// Uniformly distributed in the range `[-0.5, 0.5)`.
+    fn uniform(&mut self) -> Sample {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        // The top 24 bits fit exactly in the mantissa of an f32
+        (self.state >> 40) as Sample / (1 << 24) as Sample - 0.5
+    }
+
+    pub fn apply(&mut self, sample: Sample) -> Sample {
+        let Some(step) = self.step else {
+            return sample;
+        };
+
+        // Measured in steps of the output format
+        let noise = match self.mode {
+            DitherMode::None => return sample,
+            DitherMode::Rectangular { .. } => self.uniform(),
+            DitherMode::Tpdf { .. } => self.uniform() + self.uniform(),
+        };
+
+        // Rounded here, since the conversion itself truncates
+        let quantized = (sample / step + noise).round() * step;
+        quantized.clamp(-1.0, 1.0 - step)
+    }
+}
+
+impl Default for Dither {
+    /// Leaves every sample untouched.
+    fn default() -> Self {
+        Dither {
+            mode: DitherMode::None,
+            step: None,
+            state: 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::config::SampleFormatFloat;
+
+    use super::*;
+
+    const I16: SampleFormat = SampleFormat::Int(SampleFormatInt::I16);
+
+    #[test]
+    fn deterministic_under_seed() {
+        let output = |seed| {
+            let mut dither = Dither::new(DitherMode::Tpdf { seed }, &I16);
+            (0..100)
+                .map(|i| dither.apply(i as Sample / 100.0))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(output(1), output(1));
+        assert_ne!(output(1), output(2));
+    }
+
+    #[test]
+    fn only_integer_formats() {
+        let mut float = Dither::new(
+            DitherMode::Tpdf { seed: 0 },
+            &SampleFormat::Float(SampleFormatFloat::F32),
+        );
+        let mut wide = Dither::new(
+            DitherMode::Tpdf { seed: 0 },
+            &SampleFormat::Int(SampleFormatInt::I32),
+        );
+        let mut none = Dither::new(DitherMode::None, &I16);
+
+        for sample in [0.0, 0.123_456, -0.5, 0.999] {
+            assert_eq!(float.apply(sample), sample);
+            assert_eq!(wide.apply(sample), sample);
+            assert_eq!(none.apply(sample), sample);
+        }
+    }
+
+    #[test]
+    fn preserves_level_below_step() {
+        let step = 1.0 / 32_768.0;
+        let sample = 0.3 * step;
+        let mut dither = Dither::new(DitherMode::Tpdf { seed: 42 }, &I16);
+
+        let n = 100_000;
+        let mut sum = 0.0;
+        for _ in 0..n {
+            let output = dither.apply(sample);
+            // Exactly representable, so the conversion doesn't truncate it any further
+            assert_eq!((output / step).fract(), 0.0);
+            sum += output as f64;
+        }
+
+        // Truncation would output silence, whereas the dithered output averages out to the input
+        let average = (sum / n as f64) as Sample;
+        assert!((average - sample).abs() < 0.02 * step, "Average: {average}");
+    }
+}
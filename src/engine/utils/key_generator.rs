@@ -23,7 +23,7 @@ pub trait Key: Copy + Eq + Hash + Debug {
 /// ```
 macro_rules! key_type {
     ($v:vis struct $name:ident($id:ty)) => {
-        #[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+        #[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
         $v struct $name($id);
         impl crate::engine::utils::key_generator::Key for $name {
             type Id = $id;
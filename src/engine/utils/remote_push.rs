@@ -78,6 +78,45 @@ where
     }
 }
 
+/// Keeps the order in which elements were pushed, unlike [`RemotePusherHashMap`].
+pub type RemotePusherVec<K, V> = RemotePusher<(K, V), K, Vec<(K, V)>>;
+pub type RemotePushedVec<K, V> = RemotePushed<(K, V), K, Vec<(K, V)>>;
+impl<K, V> RemotePushable<(K, V), K> for Vec<(K, V)>
+where
+    K: Send + Debug + Eq,
+    V: Send + Debug,
+{
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity()
+    }
+
+    fn with_capacity(capacity: usize) -> Self {
+        Vec::with_capacity(capacity)
+    }
+
+    fn push(&mut self, element: (K, V)) {
+        self.push(element);
+    }
+
+    fn remove(&mut self, key: K) -> bool {
+        match self.iter().position(|(k, _)| *k == key) {
+            Some(index) => {
+                self.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn transplant(&mut self, other: &mut Self) {
+        other.append(self);
+    }
+}
+
 #[derive(Debug)]
 pub enum RemotePushEvent<E, K, C>
 where
@@ -498,4 +537,52 @@ mod tests {
             assert_eq!(rped.len(), 0);
         }
     }
+    mod vec {
+        use super::*;
+
+        #[test]
+        fn push_keeps_order() {
+            let (mut rper, mut rped) = Vec::new().into_remote_push();
+
+            rper.push(("mop", 2));
+            rper.push_multiple(vec![("stop", 5), ("flop", 1)]);
+
+            no_heap! {{
+                rped.poll();
+            }}
+
+            assert_eq!(*rped, vec![("mop", 2), ("stop", 5), ("flop", 1)]);
+        }
+
+        #[test]
+        fn reallocate() {
+            let (mut rper, mut rped) = Vec::with_capacity(4).into_remote_push();
+
+            let entries: Vec<(usize, usize)> = (0..5).map(|i| (i, i)).collect();
+            for &entry in entries.iter() {
+                rper.push(entry);
+
+                no_heap! {{
+                    rped.poll();
+                }}
+            }
+
+            assert!(rped.capacity() > 4);
+            assert_eq!(*rped, entries);
+        }
+
+        #[test]
+        fn remove_keeps_order() {
+            let (mut rper, mut rped) = Vec::new().into_remote_push();
+
+            rper.push_multiple(vec![("mop", 2), ("stop", 5), ("flop", 1)]);
+            rper.remove("stop");
+
+            no_heap! {{
+                rped.poll();
+            }}
+
+            assert_eq!(*rped, vec![("mop", 2), ("flop", 1)]);
+        }
+    }
 }
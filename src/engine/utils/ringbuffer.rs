@@ -1,4 +1,12 @@
 //! Ringbuffer based channel, reallocated by the sender
+use std::{
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
 use ringbuf::{
     traits::{Consumer, Observer, Producer, Split},
     HeapCons, HeapProd, HeapRb,
@@ -10,34 +18,82 @@ pub fn ringbuffer_with_capacity<T: Send>(capacity: usize) -> (Sender<T>, Receive
     // There will be allocated enough room for capacity elements, plus one more slot for the reallocation
     let rb = HeapRb::new(capacity + 1);
     let (producer, consumer) = rb.split();
+    let received1 = Arc::new(AtomicU64::new(0));
+    let received2 = Arc::clone(&received1);
     (
-        Sender { inner: producer },
+        Sender {
+            inner: producer,
+            sent: 0,
+            dropped: 0,
+            reallocations: 0,
+            received: received1,
+        },
         Receiver {
             inner: DBox::new(consumer),
+            received: received2,
         },
     )
 }
 
+/// Counters of the events sent from the interface to the processor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct EventQueueStats {
+    /// Events that have been sent, but not yet received by the processor.
+    ///
+    /// This grows if events are sent faster than the processor polls them, e.g. while the audio thread is stalled.
+    pub pending: u64,
+    /// Events that could not be sent, and were lost, since the queue was created.
+    pub dropped: u64,
+    /// How many times the queue has filled up and been reallocated with room for more events.
+    pub reallocations: u64,
+}
+
 pub fn ringbuffer<T: Send>() -> (Sender<T>, Receiver<T>) {
     ringbuffer_with_capacity(64)
 }
 
 pub struct Sender<T: Send> {
     inner: HeapProd<Event<T>>,
+    sent: u64,
+    dropped: u64,
+    reallocations: u64,
+    /// Counted by the receiver.
+    received: Arc<AtomicU64>,
 }
 impl<T> Sender<T>
 where
     T: Send,
 {
     /// Might heap-allocate a new ringbuffer
-    pub fn send(&mut self, element: T) {
+    ///
+    /// Returns whether the element was sent. If not, it is counted in [`EventQueueStats::dropped`].
+    pub fn send(&mut self, element: T) -> bool {
         self.ensure_capacity();
-        let _result = self.inner.try_push(Event::Element(element));
+        let result = self.inner.try_push(Event::Element(element));
 
         #[cfg(debug_assertions)]
-        if _result.is_err() {
+        if result.is_err() {
             panic!("Sender::ensure_capacity failed to do its job")
         }
+
+        match result {
+            Ok(()) => {
+                self.sent += 1;
+                true
+            }
+            Err(_) => {
+                self.dropped += 1;
+                false
+            }
+        }
+    }
+
+    pub fn stats(&self) -> EventQueueStats {
+        EventQueueStats {
+            pending: self.sent - self.received.load(Ordering::Relaxed),
+            dropped: self.dropped,
+            reallocations: self.reallocations,
+        }
     }
 
     fn ensure_capacity(&mut self) {
@@ -47,6 +103,7 @@ where
             let (producer, consumer) = HeapRb::new(new_capacity).split();
             let _result = self.inner.try_push(Event::Reallocated(Box::new(consumer)));
             self.inner = producer;
+            self.reallocations += 1;
 
             #[cfg(debug_assertions)]
             if _result.is_err() {
@@ -58,6 +115,12 @@ where
 
 pub struct Receiver<T: 'static + Send> {
     inner: DBox<HeapCons<Event<T>>>,
+    received: Arc<AtomicU64>,
+}
+impl<T: Send> Debug for Receiver<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
 }
 impl<T> Receiver<T>
 where
@@ -68,7 +131,10 @@ where
             match self.inner.try_pop() {
                 None => return None,
                 Some(event) => match event {
-                    Event::Element(e) => return Some(e),
+                    Event::Element(e) => {
+                        self.received.fetch_add(1, Ordering::Relaxed);
+                        return Some(e);
+                    }
                     Event::Reallocated(new) => {
                         self.inner = DBox::from(new);
                     }
@@ -176,6 +242,29 @@ mod tests {
         assert_eq!(r, vec![1, 2, 3, 4, 5]);
     }
 
+    #[test]
+    fn stats() {
+        let (mut sender, mut receiver) = ringbuffer_with_capacity(1);
+
+        assert!(sender.send(1));
+        assert!(sender.send(2));
+        assert!(sender.send(3));
+        assert_eq!(
+            sender.stats(),
+            EventQueueStats {
+                pending: 3,
+                dropped: 0,
+                reallocations: 1,
+            }
+        );
+
+        receiver.recv();
+        receiver.recv();
+        assert_eq!(sender.stats().pending, 1);
+        receiver.recv();
+        assert_eq!(sender.stats().pending, 0);
+    }
+
     #[test]
     fn iter() {
         let (mut sender, mut receiver) = ringbuffer();
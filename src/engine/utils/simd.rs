@@ -0,0 +1,243 @@
+//! Buffer operations on the hot paths, processing several samples per instruction.
+//!
+//! Uses SSE2 on x86_64, where it is always available, and plain loops elsewhere,
+//! or when samples are 64-bit (the `f64` feature).
+//! Either way, the results are the same as the plain loops in [`scalar`].
+//! All functions only touch as many samples as the shorter of their arguments.
+
+#[cfg(all(target_arch = "x86_64", not(feature = "f64")))]
+use std::cmp::min;
+
+use crate::engine::Sample;
+#[cfg(all(target_arch = "x86_64", not(feature = "f64")))]
+use crate::engine::CHANNELS;
+
+/// `buffer[i] += input[i]`
+pub fn add(buffer: &mut [Sample], input: &[Sample]) {
+    #[cfg(all(target_arch = "x86_64", not(feature = "f64")))]
+    {
+        let len = min(buffer.len(), input.len());
+        let simd_len = len - len % 4;
+        // SAFETY: SSE2 is part of x86_64, and every access is within `simd_len`
+        unsafe {
+            use std::arch::x86_64::*;
+            for i in (0..simd_len).step_by(4) {
+                let b = _mm_loadu_ps(buffer.as_ptr().add(i));
+                let x = _mm_loadu_ps(input.as_ptr().add(i));
+                _mm_storeu_ps(buffer.as_mut_ptr().add(i), _mm_add_ps(b, x));
+            }
+        }
+        scalar::add(&mut buffer[simd_len..], &input[simd_len..]);
+    }
+    #[cfg(not(all(target_arch = "x86_64", not(feature = "f64"))))]
+    scalar::add(buffer, input);
+}
+
+/// `buffer[i] += gain * input[i]`
+pub fn add_scaled(buffer: &mut [Sample], input: &[Sample], gain: f32) {
+    #[cfg(all(target_arch = "x86_64", not(feature = "f64")))]
+    {
+        let len = min(buffer.len(), input.len());
+        let simd_len = len - len % 4;
+        // SAFETY: SSE2 is part of x86_64, and every access is within `simd_len`
+        unsafe {
+            use std::arch::x86_64::*;
+            let g = _mm_set1_ps(gain);
+            for i in (0..simd_len).step_by(4) {
+                let b = _mm_loadu_ps(buffer.as_ptr().add(i));
+                let x = _mm_loadu_ps(input.as_ptr().add(i));
+                let scaled = _mm_mul_ps(g, x);
+                _mm_storeu_ps(buffer.as_mut_ptr().add(i), _mm_add_ps(b, scaled));
+            }
+        }
+        scalar::add_scaled(&mut buffer[simd_len..], &input[simd_len..], gain);
+    }
+    #[cfg(not(all(target_arch = "x86_64", not(feature = "f64"))))]
+    scalar::add_scaled(buffer, input, gain);
+}
+
+/// `buffer[i] *= gains[i / CHANNELS]`, i.e. each frame of the interleaved `buffer` is scaled by its own gain.
+pub fn scale_frames(buffer: &mut [Sample], gains: &[f32]) {
+    #[cfg(all(target_arch = "x86_64", not(feature = "f64")))]
+    {
+        // Two stereo frames fit in a register
+        const FRAMES: usize = 4 / CHANNELS;
+        let frames = min(buffer.len() / CHANNELS, gains.len());
+        let simd_frames = if CHANNELS == 2 {
+            frames - frames % FRAMES
+        } else {
+            0
+        };
+        // SAFETY: SSE2 is part of x86_64, and every access is within `simd_frames`
+        unsafe {
+            use std::arch::x86_64::*;
+            for f in (0..simd_frames).step_by(FRAMES) {
+                let i = f * CHANNELS;
+                let g = _mm_set_ps(gains[f + 1], gains[f + 1], gains[f], gains[f]);
+                let b = _mm_loadu_ps(buffer.as_ptr().add(i));
+                _mm_storeu_ps(buffer.as_mut_ptr().add(i), _mm_mul_ps(b, g));
+            }
+        }
+        scalar::scale_frames(&mut buffer[simd_frames * CHANNELS..], &gains[simd_frames..]);
+    }
+    #[cfg(not(all(target_arch = "x86_64", not(feature = "f64"))))]
+    scalar::scale_frames(buffer, gains);
+}
+
+/// `sum[i] += input[i]`, where the sum is kept at double precision.
+pub fn accumulate(sum: &mut [f64], input: &[Sample]) {
+    #[cfg(all(target_arch = "x86_64", not(feature = "f64")))]
+    {
+        let len = min(sum.len(), input.len());
+        let simd_len = len - len % 4;
+        // SAFETY: SSE2 is part of x86_64, and every access is within `simd_len`
+        unsafe {
+            use std::arch::x86_64::*;
+            for i in (0..simd_len).step_by(4) {
+                let x = _mm_loadu_ps(input.as_ptr().add(i));
+                let low = _mm_cvtps_pd(x);
+                let high = _mm_cvtps_pd(_mm_movehl_ps(x, x));
+
+                let s = sum.as_mut_ptr().add(i);
+                _mm_storeu_pd(s, _mm_add_pd(_mm_loadu_pd(s), low));
+                _mm_storeu_pd(s.add(2), _mm_add_pd(_mm_loadu_pd(s.add(2)), high));
+            }
+        }
+        scalar::accumulate(&mut sum[simd_len..], &input[simd_len..]);
+    }
+    #[cfg(not(all(target_arch = "x86_64", not(feature = "f64"))))]
+    scalar::accumulate(sum, input);
+}
+
+/// `output[i] = sum[i]`, rounded to the nearest [`Sample`].
+pub fn narrow(output: &mut [Sample], sum: &[f64]) {
+    #[cfg(all(target_arch = "x86_64", not(feature = "f64")))]
+    {
+        let len = min(output.len(), sum.len());
+        let simd_len = len - len % 4;
+        // SAFETY: SSE2 is part of x86_64, and every access is within `simd_len`
+        unsafe {
+            use std::arch::x86_64::*;
+            for i in (0..simd_len).step_by(4) {
+                let low = _mm_cvtpd_ps(_mm_loadu_pd(sum.as_ptr().add(i)));
+                let high = _mm_cvtpd_ps(_mm_loadu_pd(sum.as_ptr().add(i + 2)));
+                _mm_storeu_ps(output.as_mut_ptr().add(i), _mm_movelh_ps(low, high));
+            }
+        }
+        scalar::narrow(&mut output[simd_len..], &sum[simd_len..]);
+    }
+    #[cfg(not(all(target_arch = "x86_64", not(feature = "f64"))))]
+    scalar::narrow(output, sum);
+}
+
+/// The same operations one sample at a time.
+pub mod scalar {
+    use std::iter::zip;
+
+    use crate::engine::{Sample, CHANNELS};
+
+    pub fn add(buffer: &mut [Sample], input: &[Sample]) {
+        for (sample, &x) in zip(buffer, input) {
+            *sample += x;
+        }
+    }
+
+    pub fn add_scaled(buffer: &mut [Sample], input: &[Sample], gain: f32) {
+        for (sample, &x) in zip(buffer, input) {
+            *sample += gain as Sample * x;
+        }
+    }
+
+    pub fn scale_frames(buffer: &mut [Sample], gains: &[f32]) {
+        for (frame, &gain) in zip(buffer.chunks_exact_mut(CHANNELS), gains) {
+            for sample in frame {
+                *sample *= gain as Sample;
+            }
+        }
+    }
+
+    pub fn accumulate(sum: &mut [f64], input: &[Sample]) {
+        for (s, &x) in zip(sum, input) {
+            *s += x as f64;
+        }
+    }
+
+    pub fn narrow(output: &mut [Sample], sum: &[f64]) {
+        for (sample, &s) in zip(output, sum) {
+            *sample = s as Sample;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::CHANNELS;
+
+    /// Deterministic, but irregular values.
+    fn signal(len: usize, seed: usize) -> Vec<Sample> {
+        (0..len)
+            .map(|i| (((i + seed) * 7919 % 1000) as Sample / 500.0) - 1.0)
+            .collect()
+    }
+
+    // Odd lengths, so the tail is handled by the scalar loop
+    const LENGTHS: [usize; 5] = [0, 1, 7, 64, 1027];
+
+    #[test]
+    fn add_matches_scalar() {
+        for len in LENGTHS {
+            let input = signal(len, 1);
+            let mut simd = signal(len, 2);
+            let mut expected = simd.clone();
+
+            no_heap! {{
+                add(&mut simd, &input);
+                add_scaled(&mut simd, &input, 0.3);
+                scalar::add(&mut expected, &input);
+                scalar::add_scaled(&mut expected, &input, 0.3);
+            }}
+
+            assert_eq!(simd, expected);
+        }
+    }
+
+    #[test]
+    fn scale_frames_matches_scalar() {
+        for len in LENGTHS {
+            let gains: Vec<_> = signal(len, 3).iter().map(|&g| g as f32).collect();
+            let mut simd = signal(len * CHANNELS, 4);
+            let mut expected = simd.clone();
+
+            no_heap! {{
+                scale_frames(&mut simd, &gains);
+                scalar::scale_frames(&mut expected, &gains);
+            }}
+
+            assert_eq!(simd, expected);
+        }
+    }
+
+    #[test]
+    fn accumulate_matches_scalar() {
+        for len in LENGTHS {
+            let inputs: Vec<_> = (0..10).map(|seed| signal(len, seed)).collect();
+            let mut simd = vec![0.5; len];
+            let mut expected = simd.clone();
+            let mut simd_out = vec![0.0; len];
+            let mut expected_out = vec![0.0; len];
+
+            no_heap! {{
+                for input in &inputs {
+                    accumulate(&mut simd, input);
+                    scalar::accumulate(&mut expected, input);
+                }
+                narrow(&mut simd_out, &simd);
+                scalar::narrow(&mut expected_out, &expected);
+            }}
+
+            assert_eq!(simd, expected);
+            assert_eq!(simd_out, expected_out);
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use std::{
+    any::Any,
+    fmt::Debug,
+    hint::spin_loop,
+    panic::{self, AssertUnwindSafe},
+    ptr::null_mut,
+    sync::{
+        atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    thread::{self, JoinHandle},
+};
+
+use super::denormals::{self, FlushDenormals};
+
+/// How many times to check whether the workers are done, before yielding to them.
+const MAX_SPINS: u32 = 1000;
+
+/// A fixed set of threads, that the audio thread can spread work across without allocating or locking.
+///
+/// The threads are spawned when the pool is created, and joined when it is dropped.
+pub struct WorkerPool {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+}
+impl WorkerPool {
+    /// Spawn `workers` threads, which help out the thread calling [`WorkerPool::run_each`].
+    pub fn new(workers: usize) -> Self {
+        let shared = Arc::new(Shared {
+            batch: AtomicPtr::new(null_mut()),
+            generation: AtomicU64::new(0),
+            active: AtomicUsize::new(0),
+            stopped: AtomicBool::new(false),
+            panicked: AtomicBool::new(false),
+            panic: Mutex::new(None),
+        });
+
+        let workers = (0..workers)
+            .map(|_| {
+                let shared = Arc::clone(&shared);
+                thread::spawn(move || shared.work())
+            })
+            .collect();
+
+        WorkerPool { shared, workers }
+    }
+
+    /// Call `f` on each of the `items`, spread across the workers and the calling thread,
+    /// and wait for all of them to finish.
+    ///
+    /// The workers flush denormals to zero while running `f`, if the calling thread does.
+    /// If `f` panics on one of the workers, the panic is passed on to the calling thread once all of them have finished.
+    pub fn run_each<T: Send>(&self, items: &mut [T], f: impl Fn(&mut T) + Sync) {
+        let len = items.len();
+        let items = ItemsPtr(items.as_mut_ptr());
+        let run = |i: usize| {
+            debug_assert!(i < len);
+            // SAFETY: Each index is only claimed once, and the items outlive the batch
+            let item = unsafe { &mut *items.get(i) };
+            f(item);
+        };
+        let batch = Batch {
+            next: AtomicUsize::new(0),
+            len,
+            run: &run,
+            flush_denormals: denormals::flushing(),
+        };
+
+        self.shared
+            .active
+            .store(self.workers.len(), Ordering::Release);
+        // The lifetime is erased, since the batch is only used until every worker has left it
+        let batch_ptr = &batch as *const Batch<'_> as *mut Batch<'static>;
+        self.shared.batch.store(batch_ptr, Ordering::Release);
+        self.shared.generation.fetch_add(1, Ordering::Release);
+        for worker in &self.workers {
+            worker.thread().unpark();
+        }
+
+        {
+            let _wait = WaitForWorkers(&self.shared);
+            batch.work();
+        }
+
+        if self.shared.panicked.swap(false, Ordering::Acquire) {
+            let payload = self
+                .shared
+                .panic
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .take()
+                .expect("Worker panicked without leaving its payload");
+            panic::resume_unwind(payload);
+        }
+    }
+}
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        self.shared.stopped.store(true, Ordering::Release);
+        for worker in self.workers.drain(..) {
+            worker.thread().unpark();
+            // Panics in the batches are caught and passed on by `run_each`, so there is nothing left to report
+            let _ = worker.join();
+        }
+    }
+}
+impl Debug for WorkerPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WorkerPool")
+            .field("workers", &self.workers.len())
+            .finish_non_exhaustive()
+    }
+}
+
+struct Shared {
+    /// The batch currently being worked on, if any.
+    batch: AtomicPtr<Batch<'static>>,
+    /// Incremented each time a new batch is started.
+    generation: AtomicU64,
+    /// The number of workers that have not yet finished the current batch.
+    active: AtomicUsize,
+    stopped: AtomicBool,
+    /// Whether a worker has panicked during the current batch, leaving its payload in `panic`.
+    panicked: AtomicBool,
+    panic: Mutex<Option<Box<dyn Any + Send>>>,
+}
+impl Shared {
+    /// The loop run by each worker thread.
+    fn work(&self) {
+        let mut seen = 0;
+        loop {
+            if self.stopped.load(Ordering::Acquire) {
+                return;
+            }
+
+            let generation = self.generation.load(Ordering::Acquire);
+            if generation == seen {
+                // Can wake up spuriously, hence the generation check
+                thread::park();
+                continue;
+            }
+            seen = generation;
+
+            let batch = self.batch.load(Ordering::Acquire);
+            // SAFETY: The batch is kept alive until every worker has decremented `active`
+            let batch = unsafe { &*batch };
+            let flush = FlushDenormals::new(batch.flush_denormals);
+            let result = panic::catch_unwind(AssertUnwindSafe(|| batch.work()));
+            drop(flush);
+            if let Err(payload) = result {
+                // Only the first panic is passed on
+                let mut panic = self.panic.lock().unwrap_or_else(PoisonError::into_inner);
+                panic.get_or_insert(payload);
+                self.panicked.store(true, Ordering::Release);
+            }
+            // Decremented even if `f` panicked, since the calling thread is waiting for it
+            self.active.fetch_sub(1, Ordering::Release);
+        }
+    }
+}
+
+/// Waits for every worker to leave the current batch when dropped.
+///
+/// Since the batch lives on the stack of the calling thread, this also has to happen if it panics while working on it.
+struct WaitForWorkers<'a>(&'a Shared);
+impl Drop for WaitForWorkers<'_> {
+    fn drop(&mut self) {
+        // Parking would require waking this thread up again, which takes too long on the audio thread.
+        // Yielding lets the workers run, if there are fewer cores than threads.
+        let mut spins = 0;
+        while self.0.active.load(Ordering::Acquire) != 0 {
+            if spins < MAX_SPINS {
+                spin_loop();
+                spins += 1;
+            } else {
+                thread::yield_now();
+            }
+        }
+        self.0.batch.store(null_mut(), Ordering::Release);
+    }
+}
+
+struct Batch<'a> {
+    /// The index of the next item to be claimed.
+    next: AtomicUsize,
+    len: usize,
+    run: &'a (dyn Fn(usize) + Sync),
+    /// Whether the thread that started the batch flushes denormals to zero.
+    flush_denormals: bool,
+}
+impl Batch<'_> {
+    /// Claim and run items until there are none left.
+    fn work(&self) {
+        loop {
+            let i = self.next.fetch_add(1, Ordering::Relaxed);
+            if i >= self.len {
+                return;
+            }
+            (self.run)(i);
+        }
+    }
+}
+
+/// Pointer to the items of a batch, which can be shared between the threads working on it.
+struct ItemsPtr<T>(*mut T);
+// SAFETY: Each item is only accessed by the one thread that claimed it
+unsafe impl<T: Send> Sync for ItemsPtr<T> {}
+impl<T> ItemsPtr<T> {
+    /// # Safety
+    /// `i` must be within the items.
+    unsafe fn get(&self, i: usize) -> *mut T {
+        self.0.add(i)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_each_item_once() {
+        let pool = WorkerPool::new(3);
+        let mut items = vec![0; 1000];
+
+        for _ in 0..100 {
+            no_heap! {{
+                pool.run_each(&mut items, |item| *item += 1);
+            }}
+        }
+
+        assert!(items.iter().all(|&item| item == 100));
+    }
+
+    #[test]
+    fn panic_is_passed_on() {
+        let pool = WorkerPool::new(3);
+        let mut items: Vec<usize> = (0..1000).collect();
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            pool.run_each(&mut items, |item| {
+                if *item == 500 {
+                    panic!("Oh no");
+                }
+            });
+        }));
+        let payload = result.unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"Oh no"));
+
+        // The pool keeps working, and can be dropped afterwards
+        pool.run_each(&mut items, |item| *item += 1);
+        assert_eq!(items[0], 1);
+    }
+
+    #[test]
+    fn without_workers() {
+        let pool = WorkerPool::new(0);
+        let mut items = [1, 2, 3];
+
+        pool.run_each(&mut items, |item| *item *= 2);
+
+        assert_eq!(items, [2, 4, 6]);
+    }
+
+    #[test]
+    fn workers_follow_denormal_mode() {
+        let pool = WorkerPool::new(3);
+        let mut items = vec![false; 100];
+
+        let _flush = FlushDenormals::new(true);
+        pool.run_each(&mut items, |item| *item = denormals::flushing());
+        assert_eq!(items.iter().all(|&item| item), denormals::flushing());
+    }
+}
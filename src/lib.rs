@@ -1,4 +1,6 @@
 #![allow(clippy::inconsistent_digit_grouping)]
+// Casts between `Sample` and `f32`/`f64` are only needed with one of the sample formats
+#![allow(clippy::unnecessary_cast)]
 
 #[cfg(feature = "custom_debug_output")]
 #[macro_use]
@@ -22,14 +24,19 @@ macro_rules! allow_heap {
     };
 }
 
-#[cfg(any(feature = "record_output", test))]
 mod wav_recorder;
 
 mod engine;
 pub use engine::{
-    error, inverse_meter_scale, meter_scale, AudioClip, AudioClipKey, AudioClipState,
-    AudioTrackKey, AudioTrackState, Engine, EngineState, MixerTrack, MixerTrackKey,
-    StoredAudioClip, StoredAudioClipKey, TimelineTrackKey, Timestamp,
+    amplitude_to_db, db_to_amplitude, error, inverse_meter_scale, meter_scale, rms, AudioClip,
+    AudioClipKey, AudioClipState, AudioTrackKey, AudioTrackState, Compressor, CompressorSettings,
+    Delay, DelaySettings, DelayTime, Direction, Downmix, EffectInterface, EffectKey, EffectState,
+    EndBehavior, Engine, EngineState, EventQueueStats, FrozenTrack, GridDivision, ImportHandle,
+    LimiterSettings, LoudnessReading, Marker, MarkerKey, MixPoint, MixerTrack, MixerTrackKey,
+    NoiseSettings, NoiseType, OverlapPolicy, RenderIter, SaturationSettings, StoredAudioClip,
+    StoredAudioClipKey, Tempo, TempoMap, TestToneSettings, TimeSignature, TimeSignatureMap,
+    TimelineTrackKey, Timestamp, WavFormat, Waveform, MAX_DELAY, MAX_DENOMINATOR,
+    MAX_EVENTS_PER_POLL_DEFAULT, MAX_PITCH, MAX_SAMPLE_RATE, MAX_STRETCH, MIN_STRETCH,
 };
 
 pub mod config {
@@ -37,7 +44,8 @@ pub mod config {
 
     // Expose everything except the error types, which are instead placed in the error module.
     pub use config::{
-        Config, Host, OutputConfig, OutputConfigRange, OutputDevice, SampleFormat,
-        SampleFormatFloat, SampleFormatInt, SampleFormatIntUnsigned,
+        Config, DitherMode, Host, InputConfig, InputDevice, OutputConfig, OutputConfigRange,
+        OutputDevice, ResampleQuality, SampleFormat, SampleFormatFloat, SampleFormatInt,
+        SampleFormatIntUnsigned,
     };
 }
@@ -1,9 +1,7 @@
 use std::{
     alloc::{GlobalAlloc, System},
-    backtrace::Backtrace,
     cell::Cell,
     marker::PhantomData,
-    thread,
 };
 
 #[cfg(not(debug_assertions))]
@@ -111,17 +109,21 @@ pub trait ErrorHandler {
     fn error(msg: &str);
 }
 
+#[cfg(not(debug_assertions))]
 pub struct PrintError;
+#[cfg(not(debug_assertions))]
 impl ErrorHandler for PrintError {
     fn error(msg: &str) {
-        eprintln!("{}\n{}", msg, Backtrace::force_capture());
+        eprintln!("{}\n{}", msg, std::backtrace::Backtrace::force_capture());
     }
 }
 
+#[cfg(debug_assertions)]
 pub struct PanicError;
+#[cfg(debug_assertions)]
 impl ErrorHandler for PanicError {
     fn error(msg: &str) {
-        if !thread::panicking() {
+        if !std::thread::panicking() {
             panic!("{}", msg);
         }
     }
@@ -177,6 +179,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::replace_box)]
     fn allow_inside() {
         let mut b = Box::new(5);
         no_heap! {{
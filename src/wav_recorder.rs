@@ -1,16 +1,22 @@
-// Should be available for testing and debugging without necessarily being used elsewhere
-#![allow(dead_code)]
+use std::{fs, io, path::Path};
 
-use std::{fs, io};
+use crate::engine::Sample;
 
+/// Writes interleaved samples to a 32-bit floating point .wav file as they arrive.
 pub struct WavRecorder {
     writer: hound::WavWriter<io::BufWriter<fs::File>>,
 }
 
 impl WavRecorder {
+    /// Records to `recorded.wav` in the root of the crate, for debugging.
+    #[cfg(feature = "record_output")]
     pub fn new(channels: u16, sample_rate: u32) -> Self {
         const PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/recorded.wav");
+        Self::create(Path::new(PATH), channels, sample_rate).unwrap()
+    }
 
+    /// Create the file at `path`, overwriting it if it already exists.
+    pub fn create(path: &Path, channels: u16, sample_rate: u32) -> Result<Self, hound::Error> {
         let spec = hound::WavSpec {
             channels,
             sample_rate,
@@ -18,14 +24,22 @@ impl WavRecorder {
             sample_format: hound::SampleFormat::Float,
         };
 
-        WavRecorder {
-            writer: hound::WavWriter::create(PATH, spec).unwrap(),
-        }
+        Ok(WavRecorder {
+            writer: hound::WavWriter::create(path, spec)?,
+        })
     }
 
-    pub fn record(&mut self, buffer: &[f32]) {
-        for sample in buffer {
-            self.writer.write_sample(*sample).unwrap();
+    pub fn record(&mut self, buffer: &[Sample]) -> Result<(), hound::Error> {
+        for &sample in buffer {
+            self.writer.write_sample(sample as f32)?;
         }
+        Ok(())
+    }
+
+    /// Write the header of the file, which is needed for it to be read.
+    ///
+    /// This is also done when dropped, but errors are then ignored.
+    pub fn finalize(self) -> Result<(), hound::Error> {
+        self.writer.finalize()
     }
 }
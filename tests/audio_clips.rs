@@ -1,7 +1,15 @@
+#![allow(clippy::inconsistent_digit_grouping)]
+
+use std::{env, fs, path::Path};
+
 mod utils;
 use adae::{
-    error::{MoveAudioClipError, MoveAudioClipToTrackError},
-    AudioClipKey, Engine, Timestamp,
+    error::{
+        AddClipError, CrossfadeError, DeleteTimeError, MergeAudioClipsError, MoveAudioClipError,
+        MoveAudioClipToTrackError, RemoveTempoChangeError, ReverseAudioClipError,
+        SetOverlapPolicyError, SplitAudioClipError, TempoOverlapError,
+    },
+    AudioClipKey, AudioClipState, Direction, Engine, OverlapPolicy, Timestamp, MAX_PITCH,
 };
 use utils::import_audio_clip;
 
@@ -34,6 +42,391 @@ fn add_audio_clip() {
     );
 }
 
+#[test]
+fn audio_clip_accessors() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let tk = e.audio_timeline_track_key(at).unwrap();
+    let ck = import_audio_clip(&mut e);
+    let ac = e
+        .add_audio_clip(tk, ck, Timestamp::zero(), Some(Timestamp::from_beats(4)))
+        .unwrap();
+
+    let bpm_cents = e.bpm_cents();
+    let clip = e.audio_clip(ac).unwrap();
+    assert_eq!(clip.stored_clip_key(), ck);
+    assert_eq!(clip.start_offset(), 0);
+
+    let stored_clip = e.stored_audio_clip(ck).unwrap();
+    assert_eq!(clip.source_sample_rate(), 44_100);
+    assert_eq!(clip.source_sample_rate(), stored_clip.sample_rate());
+    assert_eq!(clip.source_channels(), stored_clip.channels());
+    // The engine runs at 48 kHz
+    assert!(clip.resampled());
+    assert_eq!(
+        clip.current_length(48_000, bpm_cents),
+        Timestamp::from_beats(4).samples(48_000, bpm_cents)
+    );
+
+    e.audio_clip_crop_start(ac, Timestamp::from_beats(3))
+        .unwrap();
+
+    let sample_rate = e.stored_audio_clip(ck).unwrap().sample_rate();
+    let clip = e.audio_clip(ac).unwrap();
+    assert_eq!(
+        clip.start_offset(),
+        Timestamp::from_beats(1).samples(sample_rate, bpm_cents)
+    );
+    assert_eq!(
+        clip.current_length(48_000, bpm_cents),
+        Timestamp::from_beats(3).samples(48_000, bpm_cents)
+    );
+}
+
+#[test]
+fn audio_clip_count() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let tk = e.audio_timeline_track_key(at).unwrap();
+    assert_eq!(e.audio_clip_count(tk), Ok(0));
+
+    let ck = import_audio_clip(&mut e);
+    let ac1 = e
+        .add_audio_clip(
+            tk,
+            ck,
+            Timestamp::from_beats(0),
+            Some(Timestamp::from_beats(4)),
+        )
+        .unwrap();
+    let ac2 = e
+        .add_audio_clip(
+            tk,
+            ck,
+            Timestamp::from_beats(4),
+            Some(Timestamp::from_beats(4)),
+        )
+        .unwrap();
+    assert_eq!(e.audio_clip_count(tk), Ok(2));
+
+    let count_at = |e: &Engine, beat_units| {
+        e.audio_clip_count_at(tk, Timestamp::from_beat_units(beat_units))
+            .unwrap()
+    };
+    assert_eq!(count_at(&e, 0), 1);
+    assert_eq!(count_at(&e, 4 * 1024), 1);
+    assert_eq!(count_at(&e, 8 * 1024), 0);
+
+    // Both clips are playing in the crossfade
+    e.set_crossfade(ac1, ac2, Timestamp::from_beats(1)).unwrap();
+    assert_eq!(count_at(&e, 4 * 1024 + 512), 2);
+    assert_eq!(count_at(&e, 5 * 1024), 1);
+
+    e.delete_audio_clip(ac1).unwrap();
+    assert_eq!(e.audio_clip_count(tk), Ok(1));
+    assert_eq!(count_at(&e, 0), 0);
+}
+
+#[test]
+fn audio_clip_at() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let tk = e.audio_timeline_track_key(at).unwrap();
+
+    let ck = import_audio_clip(&mut e);
+    let ac1 = e
+        .add_audio_clip(
+            tk,
+            ck,
+            Timestamp::from_beats(0),
+            Some(Timestamp::from_beats(4)),
+        )
+        .unwrap();
+    let ac2 = e
+        .add_audio_clip(
+            tk,
+            ck,
+            Timestamp::from_beats(4),
+            Some(Timestamp::from_beats(4)),
+        )
+        .unwrap();
+
+    let clip_at = |e: &Engine, beat_units| {
+        e.audio_clip_at(tk, Timestamp::from_beat_units(beat_units))
+            .unwrap()
+    };
+    assert_eq!(clip_at(&e, 0), Some(ac1));
+    assert_eq!(clip_at(&e, 4 * 1024 - 1), Some(ac1));
+    // The end of a clip is not part of it
+    assert_eq!(clip_at(&e, 4 * 1024), Some(ac2));
+    assert_eq!(clip_at(&e, 8 * 1024), None);
+
+    // The later clip takes precedence in the crossfade
+    e.set_crossfade(ac1, ac2, Timestamp::from_beats(1)).unwrap();
+    assert_eq!(clip_at(&e, 3 * 1024 + 512), Some(ac1));
+    assert_eq!(clip_at(&e, 4 * 1024 + 256), Some(ac2));
+
+    e.delete_audio_clip(ac2).unwrap();
+    assert_eq!(clip_at(&e, 5 * 1024), None);
+}
+
+#[test]
+fn timeline_length() {
+    let mut e = Engine::dummy();
+    assert_eq!(e.timeline_length(), Timestamp::zero());
+
+    let at1 = e.add_audio_track().unwrap();
+    let tk1 = e.audio_timeline_track_key(at1).unwrap();
+    let at2 = e.add_audio_track().unwrap();
+    let tk2 = e.audio_timeline_track_key(at2).unwrap();
+    assert_eq!(e.timeline_length(), Timestamp::zero());
+
+    let ck = import_audio_clip(&mut e);
+    e.add_audio_clip(
+        tk1,
+        ck,
+        Timestamp::from_beats(0),
+        Some(Timestamp::from_beats(2)),
+    )
+    .unwrap();
+    assert_eq!(e.timeline_length(), Timestamp::from_beats(2));
+
+    let ac = e
+        .add_audio_clip(
+            tk2,
+            ck,
+            Timestamp::from_beats(5),
+            Some(Timestamp::from_beats(1)),
+        )
+        .unwrap();
+    assert_eq!(e.timeline_length(), Timestamp::from_beats(6));
+
+    e.delete_audio_clip(ac).unwrap();
+    assert_eq!(e.timeline_length(), Timestamp::from_beats(2));
+}
+
+#[test]
+fn insert_and_delete_time() {
+    let mut e = Engine::dummy();
+    let at1 = e.add_audio_track().unwrap();
+    let tk1 = e.audio_timeline_track_key(at1).unwrap();
+    let at2 = e.add_audio_track().unwrap();
+    let tk2 = e.audio_timeline_track_key(at2).unwrap();
+
+    let ck = import_audio_clip(&mut e);
+    for (tk, start, length) in [(tk1, 0, 2), (tk1, 3, 1), (tk1, 5, 2), (tk2, 1, 5)] {
+        e.add_audio_clip(
+            tk,
+            ck,
+            Timestamp::from_beats(start),
+            Some(Timestamp::from_beats(length)),
+        )
+        .unwrap();
+    }
+    let clips = |e: &Engine, tk| {
+        let bpm_cents = e.bpm_cents();
+        let mut clips: Vec<(Timestamp, Timestamp)> = e
+            .audio_clips(tk)
+            .unwrap()
+            .map(|clip| (clip.start(), clip.length(bpm_cents)))
+            .collect();
+        clips.sort();
+        clips
+    };
+    let beats = |clips: &[(u32, u32)]| -> Vec<(Timestamp, Timestamp)> {
+        clips
+            .iter()
+            .map(|&(start, length)| (Timestamp::from_beats(start), Timestamp::from_beats(length)))
+            .collect()
+    };
+
+    // Only clips starting at or after the point are moved
+    e.insert_time(Timestamp::from_beats(4), Timestamp::from_beats(2))
+        .unwrap();
+    assert_eq!(clips(&e, tk1), beats(&[(0, 2), (3, 1), (7, 2)]));
+    assert_eq!(clips(&e, tk2), beats(&[(1, 5)]));
+
+    assert_eq!(
+        e.delete_time(Timestamp::from_beats(3), Timestamp::from_beats(3)),
+        Err(DeleteTimeError::EmptyRange)
+    );
+
+    // The clip crossing both ends keeps only the parts outside of the range
+    e.delete_time(Timestamp::from_beats(3), Timestamp::from_beats(5))
+        .unwrap();
+    assert_eq!(clips(&e, tk1), beats(&[(0, 2), (5, 2)]));
+    assert_eq!(clips(&e, tk2), beats(&[(1, 2), (3, 1)]));
+    assert_eq!(e.timeline_length(), Timestamp::from_beats(7));
+}
+
+#[test]
+fn range_is_free() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let tk = e.audio_timeline_track_key(at).unwrap();
+
+    let ck = import_audio_clip(&mut e);
+    let ac = e
+        .add_audio_clip(
+            tk,
+            ck,
+            Timestamp::from_beats(2),
+            Some(Timestamp::from_beats(2)),
+        )
+        .unwrap();
+
+    let is_free = |e: &Engine, start, end| {
+        e.range_is_free(tk, Timestamp::from_beats(start), Timestamp::from_beats(end))
+            .unwrap()
+    };
+    assert!(is_free(&e, 0, 2));
+    assert!(is_free(&e, 4, 6));
+    assert!(!is_free(&e, 1, 3));
+    assert!(!is_free(&e, 3, 5));
+    assert!(!is_free(&e, 0, 8));
+
+    // Agrees with actually adding a clip
+    assert_eq!(
+        e.add_audio_clip(
+            tk,
+            ck,
+            Timestamp::from_beats(3),
+            Some(Timestamp::from_beats(2))
+        ),
+        Err(AddClipError::Overlapping)
+    );
+
+    e.delete_audio_clip(ac).unwrap();
+    assert!(is_free(&e, 0, 8));
+}
+
+#[test]
+fn layered_overlap_policy() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let tk = e.audio_timeline_track_key(at).unwrap();
+    let ck = import_audio_clip(&mut e);
+    let add = |e: &mut Engine, start, length| {
+        e.add_audio_clip(
+            tk,
+            ck,
+            Timestamp::from_beats(start),
+            Some(Timestamp::from_beats(length)),
+        )
+    };
+
+    assert_eq!(e.track_overlap_policy(tk), Ok(OverlapPolicy::Reject));
+    add(&mut e, 0, 4).unwrap();
+    assert_eq!(add(&mut e, 1, 1), Err(AddClipError::Overlapping));
+
+    e.set_track_overlap_policy(tk, OverlapPolicy::Layer)
+        .unwrap();
+    assert_eq!(e.track_overlap_policy(tk), Ok(OverlapPolicy::Layer));
+    add(&mut e, 1, 1).unwrap();
+    add(&mut e, 2, 4).unwrap();
+    // Clips can not start at the same time
+    assert_eq!(add(&mut e, 2, 1), Err(AddClipError::Overlapping));
+    assert_eq!(e.audio_clip_count_at(tk, Timestamp::from_beats(1)), Ok(2));
+
+    // The layered clips would not be allowed otherwise
+    assert_eq!(
+        e.set_track_overlap_policy(tk, OverlapPolicy::Reject),
+        Err(SetOverlapPolicyError::Overlapping)
+    );
+    assert_eq!(e.track_overlap_policy(tk), Ok(OverlapPolicy::Layer));
+
+    let (e, _) = Engine::dummy_from_state(&e.state());
+    assert_eq!(e.track_overlap_policy(tk), Ok(OverlapPolicy::Layer));
+    assert_eq!(e.audio_clips(tk).unwrap().count(), 3);
+}
+
+#[test]
+fn trim_existing_overlap_policy() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let tk = e.audio_timeline_track_key(at).unwrap();
+    let ck = import_audio_clip(&mut e);
+
+    e.set_track_overlap_policy(tk, OverlapPolicy::TrimExisting)
+        .unwrap();
+    let first = e
+        .add_audio_clip(
+            tk,
+            ck,
+            Timestamp::from_beats(0),
+            Some(Timestamp::from_beats(4)),
+        )
+        .unwrap();
+    let second = e
+        .add_audio_clip(
+            tk,
+            ck,
+            Timestamp::from_beats(2),
+            Some(Timestamp::from_beats(4)),
+        )
+        .unwrap();
+
+    let tempo_map = e.tempo_map();
+    let first = e.audio_clip(first).unwrap();
+    assert_eq!(first.end(tempo_map), Timestamp::from_beats(2));
+    let second = e.audio_clip(second).unwrap();
+    assert_eq!(second.start(), Timestamp::from_beats(2));
+
+    // Clips starting inside the new one are still not trimmed
+    assert_eq!(
+        e.add_audio_clip(
+            tk,
+            ck,
+            Timestamp::from_beats(1),
+            Some(Timestamp::from_beats(2))
+        ),
+        Err(AddClipError::Overlapping)
+    );
+
+    e.delete_audio_track(at).unwrap();
+    assert_eq!(
+        e.set_track_overlap_policy(tk, OverlapPolicy::Layer),
+        Err(SetOverlapPolicyError::InvalidTimelineTrack(tk))
+    );
+}
+
+#[test]
+fn tempo_change_overlapping() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let tk = e.audio_timeline_track_key(at).unwrap();
+    let ck = import_audio_clip(&mut e);
+
+    // Keeps its length in samples
+    let a = e.add_audio_clip(tk, ck, Timestamp::zero(), None).unwrap();
+    let a_end = e.audio_clip(a).unwrap().end(e.tempo_map());
+    let b = e
+        .add_audio_clip(tk, ck, a_end, Some(Timestamp::from_beats(4)))
+        .unwrap();
+
+    // Would stretch the first clip over the second one
+    assert_eq!(
+        e.set_bpm_cents(150_00),
+        Err(TempoOverlapError { keys: vec![a, b] })
+    );
+    assert_eq!(e.bpm_cents(), 120_00);
+    assert_eq!(e.audio_clip(a).unwrap().end(e.tempo_map()), a_end);
+
+    // Shrinks the first clip, leaving a gap
+    e.add_tempo_change(Timestamp::from_beats(1), 60_00).unwrap();
+    let a_end = e.audio_clip(a).unwrap().end(e.tempo_map());
+    assert!(a_end < e.audio_clip(b).unwrap().start());
+
+    e.audio_clip_move(b, a_end).unwrap();
+    assert_eq!(
+        e.remove_tempo_change(Timestamp::from_beats(1)),
+        Err(RemoveTempoChangeError::Overlapping(TempoOverlapError {
+            keys: vec![a, b]
+        }))
+    );
+    assert_eq!(e.tempo_map().changes(), [(Timestamp::from_beats(1), 60_00)]);
+}
+
 #[test]
 fn delete_audio_clip() {
     let mut e = Engine::dummy();
@@ -134,6 +527,71 @@ fn reconstruct_audio_clip() {
     assert_eq!(ac, ac_new);
 }
 
+#[test]
+fn reconstruct_audio_clip_state() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let tk = e.audio_timeline_track_key(at).unwrap();
+
+    let ck = import_audio_clip(&mut e);
+    let ac = e
+        .add_audio_clip(
+            tk,
+            ck,
+            Timestamp::from_beats(1),
+            Some(Timestamp::from_beats(2)),
+        )
+        .unwrap();
+    let default_state = e.delete_audio_clip(ac).unwrap();
+    let ac = e.reconstruct_audio_clip(tk, default_state.clone()).unwrap();
+
+    e.audio_clip_set_fade_in(ac, Timestamp::from_beats(1))
+        .unwrap();
+    e.audio_clip_set_fade_out(ac, Timestamp::from_beat_units(512))
+        .unwrap();
+    e.audio_clip_set_gain(ac, 0.5).unwrap();
+    e.audio_clip_set_gain_envelope(ac, vec![(0, 0.25), (10_000, 1.0)])
+        .unwrap();
+
+    let s = e.delete_audio_clip(ac).unwrap();
+    assert_ne!(s, default_state);
+    assert_eq!(s.fade_in, Timestamp::from_beats(1));
+    assert_eq!(s.fade_out, Timestamp::from_beat_units(512));
+    assert_eq!(s.gain, 0.5);
+    assert_eq!(s.gain_envelope, [(0, 0.25), (10_000, 1.0)]);
+
+    // Every parameter survives being reconstructed
+    let ac = e.reconstruct_audio_clip(tk, s.clone()).unwrap();
+    assert_eq!(e.delete_audio_clip(ac).unwrap(), s);
+}
+
+#[test]
+fn state_compares_clips() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let tk = e.audio_timeline_track_key(at).unwrap();
+
+    let ck = import_audio_clip(&mut e);
+    let ac = e
+        .add_audio_clip(
+            tk,
+            ck,
+            Timestamp::from_beats(0),
+            Some(Timestamp::from_beats(1)),
+        )
+        .unwrap();
+    let state = e.state();
+    assert_eq!(e.state(), state);
+
+    e.audio_clip_move(ac, Timestamp::from_beats(2)).unwrap();
+    assert_ne!(e.state(), state);
+    e.audio_clip_move(ac, Timestamp::from_beats(0)).unwrap();
+    assert_eq!(e.state(), state);
+
+    e.audio_clip_set_gain(ac, 0.5).unwrap();
+    assert_ne!(e.state(), state);
+}
+
 #[test]
 fn reconstruct_audio_clips() {
     let mut e = Engine::dummy();
@@ -170,6 +628,51 @@ fn reconstruct_audio_clips() {
     assert_eq!(acs, acs_new);
 }
 
+#[test]
+fn duplicate_audio_clip() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let tk = e.audio_timeline_track_key(at).unwrap();
+
+    let ck = import_audio_clip(&mut e);
+    let ac = e
+        .add_audio_clip(tk, ck, Timestamp::from_beats(0), None)
+        .unwrap();
+    e.audio_clip_crop_start(ac, Timestamp::from_beats(1))
+        .unwrap();
+    e.audio_clip_set_gain(ac, 0.5).unwrap();
+    e.audio_clip_set_fade_in(ac, Timestamp::from_beats(1))
+        .unwrap();
+
+    let start = e.audio_clip(ac).unwrap().start();
+    let dup = e
+        .duplicate_audio_clip(ac, start + Timestamp::from_beats(1))
+        .unwrap();
+
+    assert_ne!(dup, ac);
+    assert_eq!(e.audio_clips(tk).unwrap().count(), 2);
+    let original = e.audio_clip(ac).unwrap();
+    let copy = e.audio_clip(dup).unwrap();
+    assert_eq!(copy.start(), start + Timestamp::from_beats(1));
+    assert_eq!(copy.stored_clip_key(), original.stored_clip_key());
+    assert_eq!(copy.gain(), 0.5);
+    assert_eq!(copy.fade_in(), Timestamp::from_beats(1));
+    assert_eq!(
+        copy.waveform(e.tempo_map()),
+        original.waveform(e.tempo_map())
+    );
+
+    assert_eq!(
+        e.duplicate_audio_clip(ac, start),
+        Err(AddClipError::Overlapping)
+    );
+    e.delete_audio_clip(dup).unwrap();
+    assert_eq!(
+        e.duplicate_audio_clip(dup, Timestamp::from_beats(10)),
+        Err(AddClipError::InvalidAudioClip(dup))
+    );
+}
+
 #[test]
 fn move_audo_clip() {
     let mut e = Engine::dummy();
@@ -224,37 +727,129 @@ fn move_audo_clip_overlapping() {
 }
 
 #[test]
-fn crop_audio_clip_start() {
+fn move_audio_clips() {
     let mut e = Engine::dummy();
     let at = e.add_audio_track().unwrap();
-
+    let tk = e.audio_timeline_track_key(at).unwrap();
     let ck = import_audio_clip(&mut e);
-    let ack = e
-        .add_audio_clip(
-            e.audio_timeline_track_key(at).unwrap(),
+
+    let add = |e: &mut Engine, start| {
+        e.add_audio_clip(
+            tk,
             ck,
-            Timestamp::from_beats(0),
-            Some(Timestamp::from_beats(2)),
+            Timestamp::from_beats(start),
+            Some(Timestamp::from_beats(1)),
         )
-        .unwrap();
+        .unwrap()
+    };
+    let acs = [add(&mut e, 0), add(&mut e, 1), add(&mut e, 2)];
+    add(&mut e, 5);
+    let starts = |e: &Engine| acs.map(|ac| e.audio_clip(ac).unwrap().start().beats());
 
-    let r = e.audio_clip_crop_start(ack, Timestamp::from_beats(1));
+    // Each clip is moved to where the next one was
+    e.move_audio_clips(&acs, Timestamp::from_beats(1), Direction::Forward)
+        .unwrap();
+    assert_eq!(starts(&e), [1, 2, 3]);
 
-    let ac = e.audio_clip(ack).unwrap();
+    assert_eq!(
+        e.move_audio_clips(&acs, Timestamp::from_beats(2), Direction::Forward),
+        Err(MoveAudioClipError::Overlapping)
+    );
+    assert_eq!(
+        e.move_audio_clips(&acs, Timestamp::from_beats(2), Direction::Backward),
+        Err(MoveAudioClipError::OutOfBounds)
+    );
+    assert_eq!(starts(&e), [1, 2, 3]);
 
-    assert_eq!(r, Ok(()));
-    assert_eq!(ac.start(), Timestamp::from_beats(1));
-    assert_eq!(ac.length(e.bpm_cents()), Timestamp::from_beats(1));
+    e.move_audio_clips(&acs, Timestamp::from_beats(1), Direction::Backward)
+        .unwrap();
+    assert_eq!(starts(&e), [0, 1, 2]);
 }
 
 #[test]
-fn crop_audio_clip_start_overlapping() {
+fn quantize_audio_clips() {
     let mut e = Engine::dummy();
     let at = e.add_audio_track().unwrap();
-
+    let tk = e.audio_timeline_track_key(at).unwrap();
     let ck = import_audio_clip(&mut e);
-    let ack = e
-        .add_audio_clip(
+
+    let add = |e: &mut Engine, start| {
+        e.add_audio_clip(
+            tk,
+            ck,
+            Timestamp::from_beat_units(start),
+            Some(Timestamp::from_beats(1)),
+        )
+        .unwrap()
+    };
+    let ac1 = add(&mut e, 1100);
+    let obstacle = add(&mut e, 3100);
+    let ac2 = add(&mut e, 4200);
+    let ac3 = add(&mut e, 6000);
+
+    e.quantize_audio_clip(ac3, Timestamp::from_beats(1))
+        .unwrap();
+    assert_eq!(e.audio_clip(ac3).unwrap().start(), Timestamp::from_beats(6));
+
+    // The second clip would overlap the obstacle, so the first one is moved back
+    assert_eq!(
+        e.quantize_audio_clips(&[ac1, ac2], Timestamp::from_beats(1)),
+        Err(MoveAudioClipError::Overlapping)
+    );
+    assert_eq!(
+        e.audio_clip(ac1).unwrap().start(),
+        Timestamp::from_beat_units(1100)
+    );
+    assert_eq!(
+        e.audio_clip(ac2).unwrap().start(),
+        Timestamp::from_beat_units(4200)
+    );
+
+    e.delete_audio_clip(obstacle).unwrap();
+    e.quantize_audio_clips(&[ac1, ac2], Timestamp::from_beat_units(512))
+        .unwrap();
+    assert_eq!(
+        e.audio_clip(ac1).unwrap().start(),
+        Timestamp::from_beat_units(1024)
+    );
+    assert_eq!(
+        e.audio_clip(ac2).unwrap().start(),
+        Timestamp::from_beat_units(4096)
+    );
+}
+
+#[test]
+fn crop_audio_clip_start() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+
+    let ck = import_audio_clip(&mut e);
+    let ack = e
+        .add_audio_clip(
+            e.audio_timeline_track_key(at).unwrap(),
+            ck,
+            Timestamp::from_beats(0),
+            Some(Timestamp::from_beats(2)),
+        )
+        .unwrap();
+
+    let r = e.audio_clip_crop_start(ack, Timestamp::from_beats(1));
+
+    let ac = e.audio_clip(ack).unwrap();
+
+    assert_eq!(r, Ok(()));
+    assert_eq!(ac.start(), Timestamp::from_beats(1));
+    assert_eq!(ac.length(e.bpm_cents()), Timestamp::from_beats(1));
+}
+
+#[test]
+fn crop_audio_clip_start_overlapping() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+
+    let ck = import_audio_clip(&mut e);
+    let ack = e
+        .add_audio_clip(
             e.audio_timeline_track_key(at).unwrap(),
             ck,
             Timestamp::from_beats(0),
@@ -388,6 +983,56 @@ fn crop_audio_clip_end_overlapping() {
     assert_eq!(ac.length(e.bpm_cents()), Timestamp::from_beats(1));
 }
 
+#[test]
+fn crop_audio_clips() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let tk = e.audio_timeline_track_key(at).unwrap();
+    let ck = import_audio_clip(&mut e);
+
+    let add = |e: &mut Engine, start, length| {
+        e.add_audio_clip(
+            tk,
+            ck,
+            Timestamp::from_beats(start),
+            Some(Timestamp::from_beats(length)),
+        )
+        .unwrap()
+    };
+    let acs = [add(&mut e, 0, 2), add(&mut e, 2, 2), add(&mut e, 6, 2)];
+    let bounds = |e: &Engine| {
+        acs.map(|ac| {
+            let ac = e.audio_clip(ac).unwrap();
+            (ac.start().beats(), ac.length(e.bpm_cents()).beats())
+        })
+    };
+
+    let b = |beats| Timestamp::from_beats(beats);
+    e.audio_clip_crop_end_multiple(&[(acs[0], b(1)), (acs[1], b(4))])
+        .unwrap();
+    assert_eq!(bounds(&e), [(0, 1), (2, 4), (6, 2)]);
+
+    // The second clip would overlap the third, so neither is cropped
+    assert_eq!(
+        e.audio_clip_crop_end_multiple(&[(acs[0], b(2)), (acs[1], b(5))]),
+        Err(MoveAudioClipError::Overlapping)
+    );
+    assert_eq!(bounds(&e), [(0, 1), (2, 4), (6, 2)]);
+
+    e.audio_clip_crop_start_multiple(&[(acs[1], b(3)), (acs[2], b(1))])
+        .unwrap();
+    assert_eq!(bounds(&e), [(0, 1), (3, 3), (7, 1)]);
+    e.audio_clip_crop_end(acs[1], b(4)).unwrap();
+    assert_eq!(bounds(&e), [(0, 1), (3, 4), (7, 1)]);
+
+    // The third clip would start before the second one ends
+    assert_eq!(
+        e.audio_clip_crop_start_multiple(&[(acs[1], b(5)), (acs[2], b(2))]),
+        Err(MoveAudioClipError::Overlapping)
+    );
+    assert_eq!(bounds(&e), [(0, 1), (3, 4), (7, 1)]);
+}
+
 #[test]
 fn move_audio_clip_to_another_track() {
     let mut e = Engine::dummy();
@@ -499,3 +1144,790 @@ fn waveform() {
         }
     }
 }
+
+#[test]
+fn set_audio_clip_gain() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+
+    let ck = import_audio_clip(&mut e);
+    let ac = e
+        .add_audio_clip(
+            e.audio_timeline_track_key(at).unwrap(),
+            ck,
+            Timestamp::from_beats(0),
+            None,
+        )
+        .unwrap();
+
+    assert_eq!(e.audio_clip(ac).unwrap().gain(), 1.0);
+
+    e.audio_clip_set_gain(ac, 0.5).unwrap();
+    assert_eq!(e.audio_clip(ac).unwrap().gain(), 0.5);
+
+    // Gain should survive a round-trip through the clip's state
+    let s = e.delete_audio_clip(ac).unwrap();
+    assert_eq!(s.gain, 0.5);
+    let ac = e
+        .reconstruct_audio_clip(e.audio_timeline_track_key(at).unwrap(), s)
+        .unwrap();
+    assert_eq!(e.audio_clip(ac).unwrap().gain(), 0.5);
+}
+
+#[test]
+fn set_audio_clip_active() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let tk = e.audio_timeline_track_key(at).unwrap();
+
+    let ck = import_audio_clip(&mut e);
+    let ac = e
+        .add_audio_clip(tk, ck, Timestamp::from_beats(0), None)
+        .unwrap();
+    assert!(e.audio_clip(ac).unwrap().active());
+
+    e.audio_clip_set_active(ac, false).unwrap();
+    assert!(!e.audio_clip(ac).unwrap().active());
+    // Stays on the timeline
+    assert_eq!(e.audio_clip_count(tk).unwrap(), 1);
+
+    let s = e.delete_audio_clip(ac).unwrap();
+    assert!(!s.active);
+    let ac = e.reconstruct_audio_clip(tk, s).unwrap();
+    assert!(!e.audio_clip(ac).unwrap().active());
+
+    e.audio_clip_set_active(ac, true).unwrap();
+    assert!(e.audio_clip(ac).unwrap().active());
+
+    let deleted = e.delete_audio_clip(ac).unwrap().key;
+    assert!(e.audio_clip_set_active(deleted, false).is_err());
+}
+
+#[test]
+fn solo_audio_clip() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let tk = e.audio_timeline_track_key(at).unwrap();
+
+    let ck = import_audio_clip(&mut e);
+    let ac1 = e
+        .add_audio_clip(tk, ck, Timestamp::from_beats(0), None)
+        .unwrap();
+    let ac2 = e
+        .add_audio_clip(tk, ck, Timestamp::from_beats(100), None)
+        .unwrap();
+    assert_eq!(e.soloed_audio_clip(), None);
+
+    e.solo_audio_clip(Some(ac1)).unwrap();
+    assert_eq!(e.soloed_audio_clip(), Some(ac1));
+    e.solo_audio_clip(Some(ac2)).unwrap();
+    assert_eq!(e.soloed_audio_clip(), Some(ac2));
+    e.solo_audio_clip(None).unwrap();
+    assert_eq!(e.soloed_audio_clip(), None);
+
+    e.solo_audio_clip(Some(ac1)).unwrap();
+    let deleted = e.delete_audio_clip(ac1).unwrap().key;
+    assert_eq!(e.soloed_audio_clip(), None);
+    assert!(e.solo_audio_clip(Some(deleted)).is_err());
+}
+
+#[test]
+fn set_audio_clip_fades() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+
+    let ck = import_audio_clip(&mut e);
+    let ac = e
+        .add_audio_clip(
+            e.audio_timeline_track_key(at).unwrap(),
+            ck,
+            Timestamp::from_beats(0),
+            Some(Timestamp::from_beats(4)),
+        )
+        .unwrap();
+
+    assert_eq!(e.audio_clip(ac).unwrap().fade_in(), Timestamp::zero());
+    assert_eq!(e.audio_clip(ac).unwrap().fade_out(), Timestamp::zero());
+
+    e.audio_clip_set_fade_in(ac, Timestamp::from_beats(1))
+        .unwrap();
+    // Longer than the clip, which should be allowed
+    e.audio_clip_set_fade_out(ac, Timestamp::from_beats(8))
+        .unwrap();
+
+    let s = e.delete_audio_clip(ac).unwrap();
+    assert_eq!(s.fade_in, Timestamp::from_beats(1));
+    assert_eq!(s.fade_out, Timestamp::from_beats(8));
+
+    let ac = e
+        .reconstruct_audio_clip(e.audio_timeline_track_key(at).unwrap(), s)
+        .unwrap();
+    assert_eq!(
+        e.audio_clip(ac).unwrap().fade_in(),
+        Timestamp::from_beats(1)
+    );
+    assert_eq!(
+        e.audio_clip(ac).unwrap().fade_out(),
+        Timestamp::from_beats(8)
+    );
+}
+
+#[test]
+fn set_audio_clip_gain_envelope() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let tt = e.audio_timeline_track_key(at).unwrap();
+
+    let ck = import_audio_clip(&mut e);
+    let ac = e
+        .add_audio_clip(tt, ck, Timestamp::from_beats(0), None)
+        .unwrap();
+    assert!(e.audio_clip(ac).unwrap().gain_envelope().is_empty());
+
+    // Sorted by position
+    e.audio_clip_set_gain_envelope(ac, vec![(40_000, 1.0), (0, 0.0)])
+        .unwrap();
+    assert_eq!(
+        e.audio_clip(ac).unwrap().gain_envelope(),
+        [(0, 0.0), (40_000, 1.0)]
+    );
+
+    // Follows the clip when it is moved
+    e.audio_clip_move(ac, Timestamp::from_beats(4)).unwrap();
+    assert_eq!(
+        e.audio_clip(ac).unwrap().gain_envelope(),
+        [(0, 0.0), (40_000, 1.0)]
+    );
+
+    let s = e.delete_audio_clip(ac).unwrap();
+    assert_eq!(s.gain_envelope, [(0, 0.0), (40_000, 1.0)]);
+    let ac = e.reconstruct_audio_clip(tt, s).unwrap();
+    assert_eq!(
+        e.audio_clip(ac).unwrap().gain_envelope(),
+        [(0, 0.0), (40_000, 1.0)]
+    );
+
+    // The second half continues from where the envelope was split
+    let (first, second) = e
+        .split_audio_clip(ac, Timestamp::from_beat_units(4 * 1024 + 128))
+        .unwrap();
+    assert_eq!(
+        e.audio_clip(first).unwrap().gain_envelope(),
+        [(0, 0.0), (40_000, 1.0)]
+    );
+    let second_envelope = e.audio_clip(second).unwrap().gain_envelope();
+    assert_eq!(second_envelope.len(), 2);
+    let (start, start_gain) = second_envelope[0];
+    let (end, end_gain) = second_envelope[1];
+    assert_eq!(start, 0);
+    assert!(0.0 < start_gain && start_gain < 1.0);
+    assert!(end < 40_000);
+    assert_eq!(end_gain, 1.0);
+}
+
+#[test]
+fn import_audio_clip_streaming() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+
+    let path = Path::new(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/test_files/44100 16-bit.wav"
+    ));
+    let ck = e.import_audio_clip_streaming(path, 100_000).unwrap();
+
+    let stored = e.stored_audio_clip(ck).unwrap();
+    assert!(stored.audio_data().is_none());
+    assert_eq!(stored.length(), 1_322_978);
+    assert_eq!(stored.sample_rate(), 44_100);
+
+    // Importing the same file again returns the existing clip
+    assert_eq!(e.import_audio_clip_streaming(path, 100_000).unwrap(), ck);
+
+    e.add_audio_clip(
+        e.audio_timeline_track_key(at).unwrap(),
+        ck,
+        Timestamp::from_beats(0),
+        None,
+    )
+    .unwrap();
+
+    let state = e.state();
+    drop(e);
+    let (e, import_errors) = Engine::dummy_from_state(&state);
+    assert_eq!(import_errors.count(), 0);
+    assert_eq!(e.state(), state);
+    assert!(e.stored_audio_clip(ck).unwrap().audio_data().is_none());
+}
+
+#[test]
+fn split_audio_clip() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let tk = e.audio_timeline_track_key(at).unwrap();
+
+    let ck = import_audio_clip(&mut e);
+    let ac = e
+        .add_audio_clip(
+            tk,
+            ck,
+            Timestamp::from_beats(2),
+            Some(Timestamp::from_beats(4)),
+        )
+        .unwrap();
+    e.audio_clip_set_fade_in(ac, Timestamp::from_beats(1))
+        .unwrap();
+    e.audio_clip_set_fade_out(ac, Timestamp::from_beats(1))
+        .unwrap();
+
+    let (first, second) = e.split_audio_clip(ac, Timestamp::from_beats(3)).unwrap();
+
+    assert!(e.audio_clip(ac).is_err());
+    assert_eq!(e.audio_clips(tk).unwrap().count(), 2);
+
+    let bpm_cents = e.bpm_cents();
+    let first = e.audio_clip(first).unwrap();
+    assert_eq!(first.start(), Timestamp::from_beats(2));
+    assert_eq!(first.length(bpm_cents), Timestamp::from_beats(1));
+    assert_eq!(first.stored_clip_key(), ck);
+    assert_eq!(first.fade_in(), Timestamp::from_beats(1));
+    assert_eq!(first.fade_out(), Timestamp::zero());
+
+    let second = e.audio_clip(second).unwrap();
+    assert_eq!(second.start(), Timestamp::from_beats(3));
+    assert_eq!(second.length(bpm_cents), Timestamp::from_beats(3));
+    assert_eq!(second.stored_clip_key(), ck);
+    assert_eq!(second.fade_in(), Timestamp::zero());
+    assert_eq!(second.fade_out(), Timestamp::from_beats(1));
+    assert!(!first.overlaps(second, bpm_cents));
+}
+
+#[test]
+fn split_audio_clip_out_of_bounds() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+
+    let ck = import_audio_clip(&mut e);
+    let ac = e
+        .add_audio_clip(
+            e.audio_timeline_track_key(at).unwrap(),
+            ck,
+            Timestamp::from_beats(2),
+            Some(Timestamp::from_beats(4)),
+        )
+        .unwrap();
+
+    for at in [
+        Timestamp::from_beats(1),
+        Timestamp::from_beats(2),
+        Timestamp::from_beats(6),
+        Timestamp::from_beats(7),
+    ] {
+        assert_eq!(
+            e.split_audio_clip(ac, at),
+            Err(SplitAudioClipError::OutOfBounds)
+        );
+    }
+    assert!(e.audio_clip(ac).is_ok());
+}
+
+#[test]
+fn reverse_audio_clip() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let tk = e.audio_timeline_track_key(at).unwrap();
+
+    let ck = import_audio_clip(&mut e);
+    let ac = e
+        .add_audio_clip(tk, ck, Timestamp::from_beats(2), None)
+        .unwrap();
+    let bpm_cents = e.bpm_cents();
+    let length = e.audio_clip(ac).unwrap().length(bpm_cents);
+    assert!(!e.audio_clip(ac).unwrap().reversed());
+
+    e.reverse_audio_clip(ac).unwrap();
+    assert!(e.audio_clip(ac).unwrap().reversed());
+    assert_eq!(e.audio_clip(ac).unwrap().length(bpm_cents), length);
+
+    let s = e.delete_audio_clip(ac).unwrap();
+    assert!(s.reversed);
+    assert_eq!(s.length, None);
+
+    let ac = e.reconstruct_audio_clip(tk, s).unwrap();
+    assert!(e.audio_clip(ac).unwrap().reversed());
+    assert_eq!(e.audio_clip(ac).unwrap().length(bpm_cents), length);
+
+    // Toggles back
+    e.reverse_audio_clip(ac).unwrap();
+    assert!(!e.audio_clip(ac).unwrap().reversed());
+}
+
+#[test]
+fn reverse_audio_clip_streaming() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+
+    let path = Path::new(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/test_files/44100 16-bit.wav"
+    ));
+    let ck = e.import_audio_clip_streaming(path, 100_000).unwrap();
+    let ac = e
+        .add_audio_clip(
+            e.audio_timeline_track_key(at).unwrap(),
+            ck,
+            Timestamp::from_beats(0),
+            None,
+        )
+        .unwrap();
+
+    assert_eq!(
+        e.reverse_audio_clip(ac),
+        Err(ReverseAudioClipError::Streamed)
+    );
+    assert!(!e.audio_clip(ac).unwrap().reversed());
+}
+
+#[test]
+fn split_reversed_audio_clip() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let tk = e.audio_timeline_track_key(at).unwrap();
+
+    let ck = import_audio_clip(&mut e);
+    let ac = e
+        .add_audio_clip(
+            tk,
+            ck,
+            Timestamp::from_beats(2),
+            Some(Timestamp::from_beats(4)),
+        )
+        .unwrap();
+    e.reverse_audio_clip(ac).unwrap();
+
+    let (first, second) = e.split_audio_clip(ac, Timestamp::from_beats(3)).unwrap();
+    let first = e.delete_audio_clip(first).unwrap();
+    let second = e.delete_audio_clip(second).unwrap();
+
+    // The first part plays the end of the region backwards, and the second part the start
+    assert!(first.reversed && second.reversed);
+    assert_eq!(usize::from(second.start_offset), 0);
+    assert!(first.start_offset > second.start_offset);
+}
+
+#[test]
+fn merge_audio_clips() {
+    for reversed in [false, true] {
+        let mut e = Engine::dummy();
+        let at = e.add_audio_track().unwrap();
+        let tk = e.audio_timeline_track_key(at).unwrap();
+
+        let ck = import_audio_clip(&mut e);
+        let ac = e
+            .add_audio_clip(
+                tk,
+                ck,
+                Timestamp::from_beats(2),
+                Some(Timestamp::from_beats(4)),
+            )
+            .unwrap();
+        e.audio_clip_set_stretch(ac, 1.5).unwrap();
+        e.audio_clip_set_fade_in(ac, Timestamp::from_beats(1))
+            .unwrap();
+        e.audio_clip_set_fade_out(ac, Timestamp::from_beats(1))
+            .unwrap();
+        if reversed {
+            e.reverse_audio_clip(ac).unwrap();
+        }
+        let original = e.delete_audio_clip(ac).unwrap();
+        let ac = e.reconstruct_audio_clip(tk, original.clone()).unwrap();
+
+        let (first, rest) = e.split_audio_clip(ac, Timestamp::from_beats(3)).unwrap();
+        let (second, third) = e
+            .split_audio_clip(rest, Timestamp::from_beat_units(4 * 1024 + 100))
+            .unwrap();
+
+        // In any order
+        let merged = e.merge_audio_clips(&[third, first, second]).unwrap();
+        assert_eq!(e.audio_clips(tk).unwrap().count(), 1);
+        assert!(e.audio_clip(first).is_err());
+
+        let merged = e.delete_audio_clip(merged).unwrap();
+        assert_eq!(
+            AudioClipState {
+                key: original.key,
+                ..merged
+            },
+            original
+        );
+    }
+}
+
+#[test]
+fn merge_audio_clips_invalid() {
+    let mut e = Engine::dummy();
+    let at1 = e.add_audio_track().unwrap();
+    let tk1 = e.audio_timeline_track_key(at1).unwrap();
+    let at2 = e.add_audio_track().unwrap();
+    let tk2 = e.audio_timeline_track_key(at2).unwrap();
+
+    let ck = import_audio_clip(&mut e);
+    let add = |e: &mut Engine, tk, start, length| {
+        e.add_audio_clip(
+            tk,
+            ck,
+            Timestamp::from_beats(start),
+            Some(Timestamp::from_beats(length)),
+        )
+        .unwrap()
+    };
+    let ac = add(&mut e, tk1, 0, 4);
+    let (first, second) = e.split_audio_clip(ac, Timestamp::from_beats(2)).unwrap();
+
+    assert_eq!(
+        e.merge_audio_clips(&[first]),
+        Err(MergeAudioClipsError::TooFewClips)
+    );
+
+    let other_track = add(&mut e, tk2, 4, 1);
+    assert_eq!(
+        e.merge_audio_clips(&[first, other_track]),
+        Err(MergeAudioClipsError::DifferentTracks)
+    );
+
+    // Separated by a gap
+    let later = add(&mut e, tk1, 5, 1);
+    assert_eq!(
+        e.merge_audio_clips(&[second, later]),
+        Err(MergeAudioClipsError::NotContiguous)
+    );
+
+    // Starts the source clip over, instead of continuing it
+    let next = add(&mut e, tk1, 4, 1);
+    assert_eq!(
+        e.merge_audio_clips(&[second, next]),
+        Err(MergeAudioClipsError::NotContinuous)
+    );
+
+    e.audio_clip_set_gain(second, 0.5).unwrap();
+    assert_eq!(
+        e.merge_audio_clips(&[first, second]),
+        Err(MergeAudioClipsError::NotContinuous)
+    );
+    e.audio_clip_set_gain(second, 1.0).unwrap();
+
+    e.delete_audio_clip(first).unwrap();
+    assert!(matches!(
+        e.merge_audio_clips(&[first, second]),
+        Err(MergeAudioClipsError::InvalidClip(_))
+    ));
+    assert!(e.audio_clip(second).is_ok());
+}
+
+#[test]
+fn stretch_audio_clip() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let tk = e.audio_timeline_track_key(at).unwrap();
+
+    let ck = import_audio_clip(&mut e);
+    let ac1 = e
+        .add_audio_clip(tk, ck, Timestamp::from_beats(0), None)
+        .unwrap();
+    let ac2 = e
+        .add_audio_clip(
+            tk,
+            ck,
+            Timestamp::from_beats(200),
+            Some(Timestamp::from_beats(4)),
+        )
+        .unwrap();
+    let bpm_cents = e.bpm_cents();
+    let length = e.audio_clip(ac1).unwrap().length(bpm_cents);
+    assert_eq!(e.audio_clip(ac1).unwrap().stretch(), 1.0);
+
+    e.audio_clip_set_stretch(ac1, 2.0).unwrap();
+    e.audio_clip_set_stretch(ac2, 0.5).unwrap();
+    let stretched_length = e.audio_clip(ac1).unwrap().length(bpm_cents);
+    assert!(
+        stretched_length
+            .beat_units()
+            .abs_diff(2 * length.beat_units())
+            <= 1
+    );
+    assert_eq!(
+        e.audio_clip(ac2).unwrap().length(bpm_cents),
+        Timestamp::from_beats(2)
+    );
+
+    // Stretching the first clip further would make it overlap the second one
+    assert_eq!(
+        e.audio_clip_set_stretch(ac1, 4.0),
+        Err(MoveAudioClipError::Overlapping)
+    );
+    assert_eq!(e.audio_clip(ac1).unwrap().stretch(), 2.0);
+
+    let s = e.delete_audio_clip(ac1).unwrap();
+    assert_eq!(s.stretch, 2.0);
+    let ac1 = e.reconstruct_audio_clip(tk, s).unwrap();
+    assert_eq!(e.audio_clip(ac1).unwrap().stretch(), 2.0);
+    assert_eq!(
+        e.audio_clip(ac1).unwrap().length(bpm_cents),
+        stretched_length
+    );
+}
+
+#[test]
+fn pitch_audio_clip() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let tk = e.audio_timeline_track_key(at).unwrap();
+
+    let ck = import_audio_clip(&mut e);
+    let ac = e
+        .add_audio_clip(tk, ck, Timestamp::from_beats(0), None)
+        .unwrap();
+    let bpm_cents = e.bpm_cents();
+    let length = e.audio_clip(ac).unwrap().length(bpm_cents);
+    assert_eq!(e.audio_clip(ac).unwrap().pitch(), 0.0);
+
+    e.audio_clip_set_pitch(ac, 3.25).unwrap();
+    assert_eq!(e.audio_clip(ac).unwrap().pitch(), 3.25);
+    assert_eq!(e.audio_clip(ac).unwrap().length(bpm_cents), length);
+
+    e.audio_clip_set_pitch(ac, -100.0).unwrap();
+    assert_eq!(e.audio_clip(ac).unwrap().pitch(), -MAX_PITCH);
+    assert_eq!(e.audio_clip(ac).unwrap().length(bpm_cents), length);
+
+    let s = e.delete_audio_clip(ac).unwrap();
+    assert_eq!(s.pitch, -MAX_PITCH);
+    let ac = e.reconstruct_audio_clip(tk, s).unwrap();
+    assert_eq!(e.audio_clip(ac).unwrap().pitch(), -MAX_PITCH);
+
+    e.delete_audio_clip(ac).unwrap();
+    assert!(e.audio_clip_set_pitch(ac, 1.0).is_err());
+}
+
+#[test]
+fn crossfade_audio_clips() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let tk = e.audio_timeline_track_key(at).unwrap();
+
+    let ck = import_audio_clip(&mut e);
+    let ac1 = e
+        .add_audio_clip(
+            tk,
+            ck,
+            Timestamp::from_beats(0),
+            Some(Timestamp::from_beats(4)),
+        )
+        .unwrap();
+    let ac2 = e
+        .add_audio_clip(
+            tk,
+            ck,
+            Timestamp::from_beats(4),
+            Some(Timestamp::from_beats(4)),
+        )
+        .unwrap();
+    let ac3 = e
+        .add_audio_clip(
+            tk,
+            ck,
+            Timestamp::from_beats(10),
+            Some(Timestamp::from_beats(1)),
+        )
+        .unwrap();
+    let bpm_cents = e.bpm_cents();
+
+    // Overlapping is still rejected without a crossfade
+    assert_eq!(
+        e.audio_clip_move(ac2, Timestamp::from_beats(3)),
+        Err(MoveAudioClipError::Overlapping)
+    );
+
+    e.set_crossfade(ac1, ac2, Timestamp::from_beats(1)).unwrap();
+    assert_eq!(
+        e.audio_clip(ac1).unwrap().length(bpm_cents),
+        Timestamp::from_beats(5)
+    );
+    assert_eq!(
+        e.audio_clip(ac2).unwrap().crossfade(),
+        Timestamp::from_beats(1)
+    );
+
+    // The crossfade can get shorter, but not longer
+    e.audio_clip_move(
+        ac2,
+        Timestamp::from_beats(4) + Timestamp::from_beat_units(512),
+    )
+    .unwrap();
+    assert_eq!(
+        e.audio_clip_move(ac2, Timestamp::from_beats(3)),
+        Err(MoveAudioClipError::Overlapping)
+    );
+    e.audio_clip_move(ac2, Timestamp::from_beats(4)).unwrap();
+
+    // The clips must be next to each other, on the same track
+    assert_eq!(
+        e.set_crossfade(ac2, ac1, Timestamp::from_beats(1)),
+        Err(CrossfadeError::NotAdjacent)
+    );
+    assert_eq!(
+        e.set_crossfade(ac1, ac3, Timestamp::from_beats(1)),
+        Err(CrossfadeError::NotAdjacent)
+    );
+
+    // The crossfade can't cover a clip
+    assert_eq!(
+        e.set_crossfade(ac2, ac3, Timestamp::from_beats(2)),
+        Err(CrossfadeError::Overlapping)
+    );
+    assert_eq!(e.audio_clip(ac3).unwrap().crossfade(), Timestamp::zero());
+    assert_eq!(
+        e.audio_clip(ac2).unwrap().length(bpm_cents),
+        Timestamp::from_beats(4)
+    );
+
+    // Splitting inside the crossfade would leave a clip covered by another one
+    assert_eq!(
+        e.split_audio_clip(
+            ac2,
+            Timestamp::from_beats(4) + Timestamp::from_beat_units(512)
+        ),
+        Err(SplitAudioClipError::InCrossfade)
+    );
+    assert_eq!(
+        e.split_audio_clip(
+            ac1,
+            Timestamp::from_beats(4) + Timestamp::from_beat_units(512)
+        ),
+        Err(SplitAudioClipError::InCrossfade)
+    );
+
+    let s = e.delete_audio_clip(ac2).unwrap();
+    assert_eq!(s.crossfade, Timestamp::from_beats(1));
+    let ac2 = e.reconstruct_audio_clip(tk, s).unwrap();
+    assert_eq!(
+        e.audio_clip(ac2).unwrap().crossfade(),
+        Timestamp::from_beats(1)
+    );
+
+    // A crossfade of zero removes it
+    e.set_crossfade(ac1, ac2, Timestamp::zero()).unwrap();
+    assert_eq!(
+        e.audio_clip(ac1).unwrap().length(bpm_cents),
+        Timestamp::from_beats(4)
+    );
+    assert_eq!(
+        e.audio_clip_crop_end(ac1, Timestamp::from_beats(5)),
+        Err(MoveAudioClipError::Overlapping)
+    );
+}
+
+#[test]
+fn move_audio_clip_with_crossfade() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let tk = e.audio_timeline_track_key(at).unwrap();
+
+    let ck = import_audio_clip(&mut e);
+    let add = |e: &mut Engine, start, length| {
+        e.add_audio_clip(
+            tk,
+            ck,
+            Timestamp::from_beats(start),
+            Some(Timestamp::from_beats(length)),
+        )
+        .unwrap()
+    };
+    let ac1 = add(&mut e, 0, 4);
+    let ac2 = add(&mut e, 10, 2);
+    let ac3 = add(&mut e, 20, 4);
+
+    // Overlapping both neighbours
+    e.audio_clip_move_with_crossfade(ac2, Timestamp::from_beats(3))
+        .unwrap();
+    assert_eq!(e.audio_clip(ac2).unwrap().start(), Timestamp::from_beats(3));
+    assert_eq!(
+        e.audio_clip(ac2).unwrap().crossfade(),
+        Timestamp::from_beats(1)
+    );
+    e.audio_clip_move_with_crossfade(ac3, Timestamp::from_beats(4))
+        .unwrap();
+    assert_eq!(
+        e.audio_clip(ac3).unwrap().crossfade(),
+        Timestamp::from_beats(1)
+    );
+    assert_eq!(
+        e.audio_clip_count_at(tk, Timestamp::from_beat_units(3 * 1024 + 512)),
+        Ok(2)
+    );
+
+    // Covering a clip entirely is still rejected, and nothing changes
+    assert_eq!(
+        e.audio_clip_move_with_crossfade(ac2, Timestamp::zero()),
+        Err(MoveAudioClipError::Overlapping)
+    );
+    assert_eq!(e.audio_clip(ac2).unwrap().start(), Timestamp::from_beats(3));
+    assert_eq!(
+        e.audio_clip(ac2).unwrap().crossfade(),
+        Timestamp::from_beats(1)
+    );
+    // As is overlapping two clips at once
+    assert_eq!(
+        e.audio_clip_move_with_crossfade(ac1, Timestamp::from_beats(3)),
+        Err(MoveAudioClipError::Overlapping)
+    );
+
+    // Moving clear of the clip before removes the crossfade with it
+    e.audio_clip_move_with_crossfade(ac3, Timestamp::from_beats(8))
+        .unwrap();
+    assert_eq!(e.audio_clip(ac3).unwrap().crossfade(), Timestamp::zero());
+    assert_eq!(
+        e.audio_clip_move(ac3, Timestamp::from_beats(4)),
+        Err(MoveAudioClipError::Overlapping)
+    );
+}
+
+#[test]
+fn trim_clip_silence() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let tk = e.audio_timeline_track_key(at).unwrap();
+
+    // One second of silence on each side of a one second tone
+    let path = env::temp_dir().join("adae trim_clip_silence.wav");
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: 48_000,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+    for i in 0..3 * 48_000 {
+        let sample = if (48_000..2 * 48_000).contains(&i) {
+            0.5
+        } else {
+            0.0
+        };
+        writer.write_sample(sample).unwrap();
+    }
+    writer.finalize().unwrap();
+    let ck = e.import_audio_clip(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let ac = e
+        .add_audio_clip(tk, ck, Timestamp::from_beats(4), None)
+        .unwrap();
+    e.trim_clip_silence(ac, -60.0).unwrap();
+
+    // At 120 BPM, one second is two beats
+    let bpm_cents = e.bpm_cents();
+    let clip = e.audio_clip(ac).unwrap();
+    assert_eq!(clip.start(), Timestamp::from_beats(6));
+    assert_eq!(clip.length(bpm_cents), Timestamp::from_beats(2));
+    let state = e.delete_audio_clip(ac).unwrap();
+    assert_eq!(usize::from(state.start_offset), 48_000);
+}
@@ -1,7 +1,14 @@
-use std::iter::zip;
+use std::{env::temp_dir, fs, iter::zip, path::Path};
 
 mod utils;
-use adae::{AudioTrackKey, AudioTrackState, Engine, Timestamp};
+use adae::{
+    error::{
+        FreezeTrackError, InvalidTimelineTrackError, RenderTrackError, StartRecordingError,
+        StopRecordingError,
+    },
+    AudioTrackKey, AudioTrackState, CompressorSettings, EffectState, Engine, NoiseSettings,
+    NoiseType, TestToneSettings, Timestamp, WavFormat, Waveform,
+};
 use utils::import_audio_clip;
 
 #[test]
@@ -204,3 +211,323 @@ fn reconstruct_audio_tracks() {
 
     assert_eq!(e.audio_tracks().count(), 42);
 }
+
+#[test]
+fn reorder_audio_tracks() {
+    let mut e = Engine::dummy();
+    let ats: Vec<AudioTrackKey> = e.add_audio_tracks(4).unwrap().collect();
+    assert_eq!(e.audio_tracks().collect::<Vec<_>>(), ats);
+
+    e.reorder_audio_track(ats[3], 0).unwrap();
+    e.reorder_audio_track(ats[0], 100).unwrap();
+    let order = [ats[3], ats[1], ats[2], ats[0]];
+    assert_eq!(e.audio_tracks().collect::<Vec<_>>(), order);
+    assert_eq!(e.audio_track_index(ats[2]), Ok(2));
+
+    // Put back where they were deleted from
+    let s = e.delete_audio_track(ats[1]).unwrap();
+    e.reconstruct_audio_track(s).unwrap();
+    assert_eq!(e.audio_tracks().collect::<Vec<_>>(), order);
+    let ss = e.delete_audio_tracks([ats[0], ats[3]]).unwrap();
+    assert_eq!(e.audio_tracks().collect::<Vec<_>>(), [ats[1], ats[2]]);
+    assert_eq!(
+        e.reconstruct_audio_tracks(ss).unwrap().collect::<Vec<_>>(),
+        [ats[0], ats[3]]
+    );
+    assert_eq!(e.audio_tracks().collect::<Vec<_>>(), order);
+
+    // Kept in the state
+    let (e, _) = Engine::dummy_from_state(&e.state());
+    assert_eq!(e.audio_tracks().collect::<Vec<_>>(), order);
+}
+
+#[test]
+fn names_and_colors() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let mk = e.audio_mixer_track_key(at).unwrap();
+    let tk = e.audio_timeline_track_key(at).unwrap();
+
+    let ck = import_audio_clip(&mut e);
+    let ac = e.add_audio_clip(tk, ck, Timestamp::zero(), None).unwrap();
+
+    assert_eq!(e.mixer_track(mk).unwrap().name(), "");
+    assert_eq!(e.timeline_track_color(tk).unwrap(), 0);
+    assert_eq!(e.audio_clip(ac).unwrap().name(), "");
+
+    let mt = e.mixer_track_mut(mk).unwrap();
+    mt.set_name("Drums".to_owned());
+    mt.set_color(0xFF0000FF);
+    e.set_timeline_track_name(tk, "Drum lane".to_owned())
+        .unwrap();
+    e.set_timeline_track_color(tk, 0x00FF00FF).unwrap();
+    e.audio_clip_set_name(ac, "Loop".to_owned()).unwrap();
+    e.audio_clip_set_color(ac, 0x0000FFFF).unwrap();
+
+    let s = e.delete_audio_track(at).unwrap();
+    let at_new = e.reconstruct_audio_track(s).unwrap();
+    let mk = e.audio_mixer_track_key(at_new).unwrap();
+    let tk = e.audio_timeline_track_key(at_new).unwrap();
+
+    let mt = e.mixer_track(mk).unwrap();
+    assert_eq!(mt.name(), "Drums");
+    assert_eq!(mt.color(), 0xFF0000FF);
+    assert_eq!(e.timeline_track_name(tk).unwrap(), "Drum lane");
+    assert_eq!(e.timeline_track_color(tk).unwrap(), 0x00FF00FF);
+    let clip = e.audio_clip(ac).unwrap();
+    assert_eq!(clip.name(), "Loop");
+    assert_eq!(clip.color(), 0x0000FFFF);
+}
+
+#[test]
+fn test_tone() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let tk = e.audio_timeline_track_key(at).unwrap();
+    assert_eq!(e.track_test_tone(tk), Ok(None));
+
+    let settings = TestToneSettings {
+        frequency: 440.0,
+        waveform: Waveform::Saw,
+        amplitude: 0.25,
+    };
+    e.set_track_test_tone(tk, Some(settings)).unwrap();
+    assert_eq!(e.track_test_tone(tk), Ok(Some(settings)));
+
+    // Kept when the track is reconstructed
+    let s = e.delete_audio_track(at).unwrap();
+    let at_new = e.reconstruct_audio_track(s).unwrap();
+    let tk = e.audio_timeline_track_key(at_new).unwrap();
+    assert_eq!(e.track_test_tone(tk), Ok(Some(settings)));
+
+    e.set_track_test_tone(tk, None).unwrap();
+    assert_eq!(e.track_test_tone(tk), Ok(None));
+
+    e.delete_audio_track(at_new).unwrap();
+    assert_eq!(
+        e.set_track_test_tone(tk, Some(settings)),
+        Err(InvalidTimelineTrackError { key: tk })
+    );
+}
+
+#[test]
+fn noise() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let tk = e.audio_timeline_track_key(at).unwrap();
+    assert_eq!(e.track_noise(tk), Ok(None));
+
+    let settings = NoiseSettings {
+        noise_type: NoiseType::Pink,
+        amplitude: 0.5,
+    };
+    e.set_track_noise(tk, Some(settings)).unwrap();
+    assert_eq!(e.track_noise(tk), Ok(Some(settings)));
+    // Independent of the test tone
+    assert_eq!(e.track_test_tone(tk), Ok(None));
+
+    // Kept when the track is reconstructed
+    let s = e.delete_audio_track(at).unwrap();
+    let at_new = e.reconstruct_audio_track(s).unwrap();
+    let tk = e.audio_timeline_track_key(at_new).unwrap();
+    assert_eq!(e.track_noise(tk), Ok(Some(settings)));
+
+    e.delete_audio_track(at_new).unwrap();
+    assert_eq!(
+        e.set_track_noise(tk, None),
+        Err(InvalidTimelineTrackError { key: tk })
+    );
+}
+
+#[test]
+fn recording_requires_input_device() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+
+    e.arm_track(at).unwrap();
+    assert_eq!(e.armed_track(), Some(at));
+    assert_eq!(e.start_recording(), Err(StartRecordingError::NoInputDevice));
+    assert!(!e.is_recording());
+    assert_eq!(
+        e.stop_recording(Path::new("recording.wav"), WavFormat::default()),
+        Err(StopRecordingError::NotRecording)
+    );
+
+    e.delete_audio_track(at).unwrap();
+    assert_eq!(e.armed_track(), None);
+}
+
+#[test]
+fn freeze_track() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let mk = e.audio_mixer_track_key(at).unwrap();
+    let tk = e.audio_timeline_track_key(at).unwrap();
+    let path = temp_dir().join("adae_freeze_track_test.wav");
+
+    assert_eq!(
+        e.freeze_track(at, &path, true),
+        Err(FreezeTrackError::Empty)
+    );
+
+    let ck = import_audio_clip(&mut e);
+    let start = Timestamp::from_beats(1);
+    let ac = e
+        .add_audio_clip(tk, ck, start, Some(Timestamp::from_beats(2)))
+        .unwrap();
+    e.mixer_track_mut(mk)
+        .unwrap()
+        .add_effect(&EffectState::Compressor(CompressorSettings::default()))
+        .unwrap();
+
+    let frozen = e.freeze_track(at, &path, true).unwrap();
+
+    // Two beats at 120 BPM
+    assert_eq!(
+        e.stored_audio_clip(frozen.stored_clip).unwrap().length(),
+        48_000
+    );
+    assert_eq!(frozen.clips.len(), 1);
+    assert_eq!(frozen.clips[0].key, ac);
+    assert_eq!(frozen.effects.len(), 1);
+    assert_eq!(e.mixer_track(mk).unwrap().effects().count(), 0);
+
+    let clips: Vec<_> = e.audio_clips(tk).unwrap().collect();
+    assert_eq!(clips.len(), 1);
+    assert_eq!(Some(clips[0].key), frozen.clip);
+    assert_eq!(clips[0].start(), start);
+    assert_eq!(clips[0].stored_clip_key(), frozen.stored_clip);
+
+    // Undo
+    e.delete_audio_clip(frozen.clip.unwrap()).unwrap();
+    let reconstructed: Vec<_> = e
+        .reconstruct_audio_clips(tk, frozen.clips)
+        .unwrap()
+        .collect();
+    assert_eq!(reconstructed, [ac]);
+    assert_eq!(e.audio_clips(tk).unwrap().count(), 1);
+}
+
+#[test]
+fn render_track_region() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let tk = e.audio_timeline_track_key(at).unwrap();
+    let ck = import_audio_clip(&mut e);
+    e.add_audio_clip(
+        tk,
+        ck,
+        Timestamp::from_beats(1),
+        Some(Timestamp::from_beats(2)),
+    )
+    .unwrap();
+
+    // Four beats at 120 BPM
+    let samples = e
+        .render_track_region(tk, Timestamp::zero(), Timestamp::from_beats(4))
+        .unwrap();
+    assert_eq!(samples.len(), 96_000 * 2);
+    assert!(samples[..24_000 * 2].iter().all(|&s| s == 0.0));
+    assert!(samples[24_000 * 2..72_000 * 2].iter().any(|&s| s != 0.0));
+    assert!(samples[72_000 * 2..].iter().all(|&s| s == 0.0));
+
+    let empty = e
+        .render_track_region(tk, Timestamp::from_beats(2), Timestamp::from_beats(1))
+        .unwrap();
+    assert!(empty.is_empty());
+
+    e.delete_audio_track(at).unwrap();
+    assert_eq!(
+        e.render_track_region(tk, Timestamp::zero(), Timestamp::from_beats(4)),
+        Err(RenderTrackError::InvalidTimelineTrack(
+            InvalidTimelineTrackError { key: tk }
+        ))
+    );
+}
+
+#[test]
+fn render_iter() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let tk = e.audio_timeline_track_key(at).unwrap();
+    let ck = import_audio_clip(&mut e);
+    e.add_audio_clip(
+        tk,
+        ck,
+        Timestamp::from_beats(1),
+        Some(Timestamp::from_beats(2)),
+    )
+    .unwrap();
+
+    // Four beats at 120 BPM, where the last chunk is shorter
+    let chunks = e
+        .render_iter(Timestamp::zero(), Timestamp::from_beats(4), 10_000)
+        .unwrap();
+    assert_eq!(chunks.len(), 10);
+    let chunks: Vec<_> = chunks.collect();
+    assert!(chunks[..9].iter().all(|chunk| chunk.len() == 10_000 * 2));
+    assert_eq!(chunks[9].len(), 6_000 * 2);
+
+    let samples = chunks.concat();
+    assert!(samples[..24_000 * 2].iter().all(|&s| s == 0.0));
+    assert!(samples[24_000 * 2..72_000 * 2].iter().any(|&s| s != 0.0));
+
+    // Chunking does not affect the output
+    let whole: Vec<_> = e
+        .render_iter(Timestamp::zero(), Timestamp::from_beats(4), usize::MAX)
+        .unwrap()
+        .collect();
+    assert_eq!(whole, [samples]);
+
+    let mut empty = e
+        .render_iter(Timestamp::from_beats(2), Timestamp::from_beats(1), 10_000)
+        .unwrap();
+    assert!(empty.next().is_none());
+}
+
+#[test]
+fn render_stems() {
+    let mut e = Engine::dummy();
+    let dir = temp_dir().join("adae_render_stems_test");
+    fs::create_dir_all(&dir).unwrap();
+
+    let ck = import_audio_clip(&mut e);
+    let ats: Vec<_> = (0..2)
+        .map(|_| {
+            let at = e.add_audio_track().unwrap();
+            let tk = e.audio_timeline_track_key(at).unwrap();
+            e.add_audio_clip(tk, ck, Timestamp::zero(), None).unwrap();
+            at
+        })
+        .collect();
+    let muted = e.audio_mixer_track_key(ats[1]).unwrap();
+    e.mixer_track(muted).unwrap().set_mute(true);
+
+    let stems = e
+        .render_stems(
+            &dir,
+            Timestamp::zero(),
+            Timestamp::from_beats(2),
+            true,
+            WavFormat::I16,
+        )
+        .unwrap();
+    assert_eq!(stems.len(), 1);
+    assert_eq!(stems[0].0, ats[0]);
+
+    let stems = e
+        .render_stems(
+            &dir,
+            Timestamp::zero(),
+            Timestamp::from_beats(2),
+            false,
+            WavFormat::I16,
+        )
+        .unwrap();
+    assert_eq!(stems.iter().map(|&(at, _)| at).collect::<Vec<_>>(), ats);
+    for (_, path) in stems {
+        let stem = e.import_audio_clip(&path).unwrap();
+        // Two beats at 120 BPM
+        assert_eq!(e.stored_audio_clip(stem).unwrap().length(), 48_000);
+    }
+}
@@ -1,4 +1,12 @@
-use adae::Engine;
+use std::{thread::sleep, time::Duration};
+
+mod utils;
+use adae::{
+    error::{AddSendError, SetSidechainError},
+    CompressorSettings, DelaySettings, DelayTime, EffectInterface, EffectState, Engine,
+    LimiterSettings, MixPoint, SaturationSettings, TestToneSettings, Timestamp,
+};
+use utils::import_audio_clip;
 
 #[test]
 fn set_panning() {
@@ -25,3 +33,351 @@ fn set_volume() {
 
     assert_eq!(mt.volume(), 0.123);
 }
+
+#[test]
+fn set_input_gain() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let mt = e
+        .mixer_track_mut(e.audio_mixer_track_key(at).unwrap())
+        .unwrap();
+
+    assert_eq!(mt.input_gain(), 1.0);
+    mt.set_input_gain(2.0);
+    assert_eq!(mt.input_gain(), 2.0);
+    // Separate from the volume
+    assert_eq!(mt.volume(), 1.0);
+
+    let s = e.delete_audio_track(at).unwrap();
+    let at = e.reconstruct_audio_track(s).unwrap();
+    let mt = e.mixer_track(e.audio_mixer_track_key(at).unwrap()).unwrap();
+
+    assert_eq!(mt.input_gain(), 2.0);
+}
+
+#[test]
+fn set_phase_invert() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let mt = e
+        .mixer_track_mut(e.audio_mixer_track_key(at).unwrap())
+        .unwrap();
+
+    assert_eq!(mt.phase_invert(), (false, false));
+    mt.set_phase_invert(false, true);
+    assert_eq!(mt.phase_invert(), (false, true));
+
+    let s = e.delete_audio_track(at).unwrap();
+    let at = e.reconstruct_audio_track(s).unwrap();
+    let mt = e.mixer_track(e.audio_mixer_track_key(at).unwrap()).unwrap();
+
+    assert_eq!(mt.phase_invert(), (false, true));
+}
+
+#[test]
+fn set_dc_block() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let mt = e
+        .mixer_track_mut(e.audio_mixer_track_key(at).unwrap())
+        .unwrap();
+
+    assert!(!mt.dc_block());
+    mt.set_dc_block(true);
+    assert!(mt.dc_block());
+
+    let s = e.delete_audio_track(at).unwrap();
+    let at = e.reconstruct_audio_track(s).unwrap();
+    let mt = e.mixer_track(e.audio_mixer_track_key(at).unwrap()).unwrap();
+
+    assert!(mt.dc_block());
+}
+
+#[test]
+fn spectrum() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let tk = e.audio_timeline_track_key(at).unwrap();
+    let ck = import_audio_clip(&mut e);
+    e.add_audio_clip(tk, ck, Timestamp::zero(), None).unwrap();
+    let mk = e.audio_mixer_track_key(at).unwrap();
+
+    let mt = e.mixer_track_mut(mk).unwrap();
+    assert!(!mt.spectrum_enabled());
+    mt.set_spectrum_enabled(true);
+    mt.set_spectrum_size(4096);
+    assert_eq!(mt.spectrum_size(), 4096);
+
+    e.play().unwrap();
+    sleep(Duration::from_millis(300));
+    e.pause().unwrap();
+
+    let spectrum = e.mixer_track(mk).unwrap().spectrum(100);
+    assert_eq!(spectrum.len(), 100);
+    assert!(spectrum.iter().any(|&m| m > 0.0));
+}
+
+#[test]
+fn set_stereo_width() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let mt = e
+        .mixer_track_mut(e.audio_mixer_track_key(at).unwrap())
+        .unwrap();
+
+    assert_eq!(mt.stereo_width(), 1.0);
+    mt.set_stereo_width(0.0);
+    assert_eq!(mt.stereo_width(), 0.0);
+
+    let s = e.delete_audio_track(at).unwrap();
+    let at = e.reconstruct_audio_track(s).unwrap();
+    let mt = e.mixer_track(e.audio_mixer_track_key(at).unwrap()).unwrap();
+
+    assert_eq!(mt.stereo_width(), 0.0);
+}
+
+#[test]
+fn mute_and_solo_round_trip() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let mt = e
+        .mixer_track_mut(e.audio_mixer_track_key(at).unwrap())
+        .unwrap();
+
+    assert!(!mt.muted());
+    assert!(!mt.soloed());
+
+    mt.set_mute(true);
+    mt.set_solo(true);
+
+    assert!(mt.muted());
+    assert!(mt.soloed());
+
+    let s = e.delete_audio_track(at).unwrap();
+    let at = e.reconstruct_audio_track(s).unwrap();
+    let mt = e.mixer_track(e.audio_mixer_track_key(at).unwrap()).unwrap();
+
+    assert!(mt.muted());
+    assert!(mt.soloed());
+}
+
+#[test]
+fn compressor_round_trip() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let mt = e
+        .mixer_track_mut(e.audio_mixer_track_key(at).unwrap())
+        .unwrap();
+
+    let ek = mt
+        .add_effect(&EffectState::Compressor(CompressorSettings::default()))
+        .unwrap();
+    let EffectInterface::Compressor(c) = mt.effect_mut(ek).unwrap() else {
+        panic!("Expected a compressor");
+    };
+    c.set_ratio(8.0);
+    c.set_makeup_gain(2.0);
+
+    let s = e.delete_audio_track(at).unwrap();
+    let at = e.reconstruct_audio_track(s).unwrap();
+    let mt = e.mixer_track(e.audio_mixer_track_key(at).unwrap()).unwrap();
+
+    assert_eq!(mt.effects().collect::<Vec<_>>(), [ek]);
+    let EffectInterface::Compressor(c) = mt.effect(ek).unwrap() else {
+        panic!("Expected a compressor");
+    };
+    assert_eq!(c.ratio(), 8.0);
+    assert_eq!(c.makeup_gain(), 2.0);
+}
+
+#[test]
+fn delay_round_trip() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let mt = e
+        .mixer_track_mut(e.audio_mixer_track_key(at).unwrap())
+        .unwrap();
+
+    let ek = mt
+        .add_effect(&EffectState::Delay(DelaySettings::default()))
+        .unwrap();
+    let EffectInterface::Delay(d) = mt.effect_mut(ek).unwrap() else {
+        panic!("Expected a delay");
+    };
+    d.set_time(DelayTime::Synced(Timestamp::from_beat_units(512)));
+    d.set_feedback(0.6);
+
+    let s = e.delete_audio_track(at).unwrap();
+    let at = e.reconstruct_audio_track(s).unwrap();
+    let mt = e.mixer_track(e.audio_mixer_track_key(at).unwrap()).unwrap();
+
+    let EffectInterface::Delay(d) = mt.effect(ek).unwrap() else {
+        panic!("Expected a delay");
+    };
+    assert_eq!(d.time(), DelayTime::Synced(Timestamp::from_beat_units(512)));
+    assert_eq!(d.feedback(), 0.6);
+}
+
+#[test]
+fn send_round_trip() {
+    let mut e = Engine::dummy();
+    let at1 = e.add_audio_track().unwrap();
+    let at2 = e.add_audio_track().unwrap();
+    let mt1 = e.audio_mixer_track_key(at1).unwrap();
+    let mt2 = e.audio_mixer_track_key(at2).unwrap();
+
+    e.add_send(mt1, mt2, 0.5).unwrap();
+    assert_eq!(e.add_send(mt2, mt1, 0.5), Err(AddSendError::FeedbackLoop));
+    assert_eq!(e.mixer_track(mt1).unwrap().sends(), [(mt2, 0.5)]);
+
+    let s = e.delete_audio_track(at1).unwrap();
+    let at1 = e.reconstruct_audio_track(s).unwrap();
+    let mt1 = e.audio_mixer_track_key(at1).unwrap();
+    assert_eq!(e.mixer_track(mt1).unwrap().sends(), [(mt2, 0.5)]);
+
+    e.remove_send(mt1, mt2).unwrap();
+    assert!(e.mixer_track(mt1).unwrap().sends().is_empty());
+    assert!(e.remove_send(mt1, mt2).is_err());
+}
+
+#[test]
+fn sidechain_round_trip() {
+    let mut e = Engine::dummy();
+    let at1 = e.add_audio_track().unwrap();
+    let at2 = e.add_audio_track().unwrap();
+    let mt1 = e.audio_mixer_track_key(at1).unwrap();
+    let mt2 = e.audio_mixer_track_key(at2).unwrap();
+
+    assert_eq!(e.mixer_track(mt1).unwrap().sidechain(), None);
+    e.set_sidechain(mt1, Some(mt2)).unwrap();
+    assert_eq!(
+        e.set_sidechain(mt2, Some(mt1)),
+        Err(SetSidechainError::FeedbackLoop)
+    );
+    assert_eq!(e.add_send(mt1, mt2, 0.5), Err(AddSendError::FeedbackLoop));
+    assert_eq!(e.mixer_track(mt1).unwrap().sidechain(), Some(mt2));
+
+    let s = e.delete_audio_track(at1).unwrap();
+    let at1 = e.reconstruct_audio_track(s).unwrap();
+    let mt1 = e.audio_mixer_track_key(at1).unwrap();
+    assert_eq!(e.mixer_track(mt1).unwrap().sidechain(), Some(mt2));
+
+    e.delete_audio_track(at2).unwrap();
+    assert_eq!(e.mixer_track(mt1).unwrap().sidechain(), None);
+}
+
+#[test]
+fn automation_round_trip() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let mt = e
+        .mixer_track_mut(e.audio_mixer_track_key(at).unwrap())
+        .unwrap();
+
+    assert!(mt.volume_automation().is_empty());
+
+    mt.set_volume_automation(vec![
+        (Timestamp::from_beats(8), 0.5),
+        (Timestamp::from_beats(0), 1.0),
+    ]);
+    mt.set_panning_automation(vec![(Timestamp::from_beats(2), -1.0)]);
+
+    let volume_automation = [
+        (Timestamp::from_beats(0), 1.0),
+        (Timestamp::from_beats(8), 0.5),
+    ];
+    assert_eq!(mt.volume_automation(), volume_automation);
+
+    let s = e.delete_audio_track(at).unwrap();
+    let at = e.reconstruct_audio_track(s).unwrap();
+    let mt = e.mixer_track(e.audio_mixer_track_key(at).unwrap()).unwrap();
+
+    assert_eq!(mt.volume_automation(), volume_automation);
+    assert_eq!(mt.panning_automation(), [(Timestamp::from_beats(2), -1.0)]);
+}
+
+#[test]
+fn master_limiter_round_trip() {
+    let mut e = Engine::dummy();
+    assert_eq!(e.master_limiter(), None);
+
+    let settings = LimiterSettings {
+        ceiling: 0.9,
+        release: 0.2,
+    };
+    e.set_master_limiter(Some(settings));
+    assert_eq!(e.master_limiter(), Some(settings));
+
+    let (e, _) = Engine::dummy_from_state(&e.state());
+    assert_eq!(e.master_limiter(), Some(settings));
+}
+
+#[test]
+fn master_gain_round_trip() {
+    let mut e = Engine::dummy();
+    assert_eq!(e.master_gain(), 1.0);
+
+    e.set_master_gain(0.5);
+    assert_eq!(e.master_gain(), 0.5);
+    // Separate from the volume of the master track
+    assert_eq!(e.master().volume(), 1.0);
+
+    let (e, _) = Engine::dummy_from_state(&e.state());
+    assert_eq!(e.master_gain(), 0.5);
+}
+
+#[test]
+fn master_saturation_round_trip() {
+    let mut e = Engine::dummy();
+    assert_eq!(e.master_saturation(), None);
+
+    let settings = SaturationSettings {
+        drive: 3.0,
+        mix: 0.7,
+    };
+    e.set_master_saturation(Some(settings));
+    assert_eq!(e.master_saturation(), Some(settings));
+
+    let (e, _) = Engine::dummy_from_state(&e.state());
+    assert_eq!(e.master_saturation(), Some(settings));
+}
+
+#[test]
+fn master_loudness() {
+    let (mut e, mut p) = Engine::dummy_with_processor();
+    let at = e.add_audio_track().unwrap();
+    let tk = e.audio_timeline_track_key(at).unwrap();
+    // A sine peaking at -18 dBFS in both channels
+    e.set_track_test_tone(tk, Some(TestToneSettings::default()))
+        .unwrap();
+
+    assert!(!e.master_loudness_enabled());
+    assert_eq!(e.master_loudness().integrated, f32::NEG_INFINITY);
+
+    e.set_master_loudness_enabled(true);
+    p.poll();
+    // Exactly one second, well past the first 400 ms gating block
+    let mut buffer = vec![0.0_f32; 2 * 1000];
+    for _ in 0..48 {
+        p.output(&mut buffer);
+    }
+
+    assert!(e.master_loudness_enabled());
+    let loudness = e.master_loudness();
+    assert!((loudness.momentary + 18.0).abs() < 1.0, "{loudness:?}");
+    assert!((loudness.integrated + 18.0).abs() < 1.0, "{loudness:?}");
+}
+
+#[test]
+fn mix_point() {
+    let mut mp = MixPoint::new(2);
+
+    mp.add(&[0.75, -0.25, 0.5, 0.0]);
+    mp.add(&[0.75, -0.25, -0.5, 0.0]);
+    // Not clipped
+    assert_eq!(mp.get(), &[1.5, -0.5, 0.0, 0.0][..]);
+
+    mp.reset();
+    mp.add(&[0.1, 0.2]);
+    assert_eq!(&mp.get()[..2], &[0.1, 0.2][..]);
+}
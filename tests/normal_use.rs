@@ -1,8 +1,8 @@
 extern crate adae;
 
-use std::{path::Path, thread::sleep, time::Duration};
+use std::{env::temp_dir, path::Path, thread::sleep, time::Duration};
 
-use adae::{AudioTrackKey, TimelineTrackKey, Timestamp};
+use adae::{error::StopOutputCaptureError, AudioTrackKey, TimelineTrackKey, Timestamp};
 
 #[test]
 fn play_around() {
@@ -18,9 +18,9 @@ fn play_around() {
         .collect();
 
     // Play and stop
-    engine.play();
+    engine.play().unwrap();
     sleep(Duration::from_secs(1));
-    engine.pause();
+    engine.pause().unwrap();
 
     // Add clips
     let stored_clip_key = engine
@@ -47,8 +47,8 @@ fn play_around() {
         .unwrap();
 
     // Jump to start and play
-    engine.jump_to(Timestamp::zero());
-    engine.play();
+    engine.jump_to(Timestamp::zero()).unwrap();
+    engine.play().unwrap();
     sleep(Duration::from_secs(1));
 
     // Insert clip before playhead while playing
@@ -62,7 +62,7 @@ fn play_around() {
         .unwrap();
 
     sleep(Duration::from_secs(1));
-    engine.pause();
+    engine.pause().unwrap();
 
     // Close and load from state
     let state = engine.state();
@@ -107,3 +107,45 @@ fn stretch_around() {
 
     // No rounding error should result in an overflow
 }
+
+#[test]
+fn list_hosts() {
+    // Listing must not require any devices to be present
+    let hosts = adae::Engine::available_hosts();
+    for host in hosts {
+        assert!(!host.name().is_empty());
+    }
+}
+
+#[test]
+fn processing_load() {
+    let engine = adae::Engine::dummy();
+    sleep(Duration::from_millis(100));
+
+    // The dummy stream outputs as fast as it can, so this is far below 1
+    let load = engine.processing_load();
+    assert!(load > 0.0 && load < 1.0, "Load: {load}");
+}
+
+#[test]
+fn output_capture() {
+    let (mut e, mut p) = adae::Engine::dummy_with_processor();
+    let path = temp_dir().join("adae_output_capture_engine_test.wav");
+
+    e.start_output_capture(&path).unwrap();
+    assert!(e.is_capturing_output());
+    let mut buffer = vec![0.0_f32; 2 * 1000];
+    p.poll();
+    p.output(&mut buffer);
+    p.output(&mut buffer);
+    e.stop_output_capture().unwrap();
+    assert!(!e.is_capturing_output());
+    assert_eq!(
+        e.stop_output_capture(),
+        Err(StopOutputCaptureError::NotCapturing)
+    );
+
+    let reader = hound::WavReader::open(&path).unwrap();
+    assert_eq!(reader.spec().sample_rate, 48_000);
+    assert_eq!(reader.duration(), 2000);
+}
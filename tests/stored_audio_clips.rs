@@ -1,4 +1,9 @@
-use adae::Engine;
+use std::{env, fs, path::Path, thread, time::Duration};
+
+use adae::{
+    error::{InvalidStoredAudioClipError, RemoveStoredAudioClipError},
+    Engine, Timestamp,
+};
 
 mod utils;
 use utils::import_audio_clip;
@@ -21,6 +26,54 @@ fn get_from_key() {
     assert_eq!(ac.key(), ck);
 }
 
+#[test]
+fn usage() {
+    let mut e = Engine::dummy();
+    let at1 = e.add_audio_track().unwrap();
+    let at2 = e.add_audio_track().unwrap();
+    let tk1 = e.audio_timeline_track_key(at1).unwrap();
+    let tk2 = e.audio_timeline_track_key(at2).unwrap();
+    let ck = import_audio_clip(&mut e);
+    assert_eq!(e.stored_clip_usage(ck), 0);
+
+    let ac = e.add_audio_clip(tk1, ck, Timestamp::zero(), None).unwrap();
+    e.add_audio_clip(tk2, ck, Timestamp::zero(), None).unwrap();
+    assert_eq!(e.stored_clip_usage(ck), 2);
+
+    e.delete_audio_clip(ac).unwrap();
+    assert_eq!(e.stored_clip_usage(ck), 1);
+}
+
+#[test]
+fn remove() {
+    let mut e = Engine::dummy();
+    let at = e.add_audio_track().unwrap();
+    let tk = e.audio_timeline_track_key(at).unwrap();
+    let ck = import_audio_clip(&mut e);
+    let ac = e.add_audio_clip(tk, ck, Timestamp::zero(), None).unwrap();
+
+    assert_eq!(
+        e.remove_stored_audio_clip(ck),
+        Err(RemoveStoredAudioClipError::InUse(ck))
+    );
+    assert!(e.stored_audio_clip(ck).is_ok());
+
+    e.delete_audio_clip(ac).unwrap();
+    e.remove_stored_audio_clip(ck).unwrap();
+
+    assert!(e.stored_audio_clip(ck).is_err());
+    assert_eq!(e.stored_audio_clips().count(), 0);
+    assert_eq!(
+        e.remove_stored_audio_clip(ck),
+        Err(RemoveStoredAudioClipError::InvalidClip(ck))
+    );
+
+    // The file is imported anew
+    let ck = import_audio_clip(&mut e);
+    assert_eq!(e.stored_audio_clips().count(), 1);
+    assert!(e.add_audio_clip(tk, ck, Timestamp::zero(), None).is_ok());
+}
+
 #[test]
 fn length() {
     let mut e = Engine::dummy();
@@ -30,3 +83,96 @@ fn length() {
 
     assert_eq!(ac.length(), 1_322_978);
 }
+
+#[test]
+fn reimport_same_file() {
+    let mut e = Engine::dummy();
+    let ck1 = import_audio_clip(&mut e);
+    let ck2 = import_audio_clip(&mut e);
+
+    assert_eq!(ck1, ck2);
+    assert_eq!(e.stored_audio_clips().count(), 1);
+}
+
+#[test]
+fn reimport_same_contents() {
+    let mut e = Engine::dummy();
+    let ck1 = import_audio_clip(&mut e);
+
+    let copy = env::temp_dir().join("adae reimport_same_contents.wav");
+    fs::copy(e.stored_audio_clip_path(ck1).unwrap(), &copy).unwrap();
+    let ck2 = e.import_audio_clip(&copy).unwrap();
+    fs::remove_file(&copy).unwrap();
+
+    assert_eq!(ck1, ck2);
+    assert_eq!(e.stored_audio_clips().count(), 1);
+}
+
+#[test]
+fn is_resampling() {
+    let mut e = Engine::dummy();
+    let ck1 = import_audio_clip(&mut e);
+    let ck2 = e
+        .import_audio_clip(Path::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/test_files/48000 16-bit.wav"
+        )))
+        .unwrap();
+
+    // The dummy engine runs at 48 kHz
+    assert_eq!(e.is_resampling(ck1), Ok(true));
+    assert_eq!(e.is_resampling(ck2), Ok(false));
+
+    e.remove_stored_audio_clip(ck1).unwrap();
+    assert_eq!(
+        e.is_resampling(ck1),
+        Err(InvalidStoredAudioClipError { key: ck1 })
+    );
+}
+
+#[test]
+fn path() {
+    let mut e = Engine::dummy();
+    let ck = import_audio_clip(&mut e);
+
+    let path = e.stored_audio_clip_path(ck).unwrap();
+
+    assert!(path.ends_with("44100 16-bit.wav"));
+}
+
+#[test]
+fn import_async() {
+    let mut e = Engine::dummy();
+    let path = Path::new(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/test_files/44100 16-bit.wav"
+    ));
+
+    let handle = e.import_audio_clip_async(path);
+    // Not usable before it is finished
+    assert_eq!(e.stored_audio_clips().count(), 0);
+
+    while !handle.is_finished() {
+        thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!(handle.progress(), (1_322_978, Some(1_322_978)));
+
+    let ck = e.finish_audio_clip_import(handle).unwrap();
+    assert_eq!(e.stored_audio_clip(ck).unwrap().length(), 1_322_978);
+    assert_eq!(e.stored_audio_clips().count(), 1);
+
+    // Already imported
+    let handle = e.import_audio_clip_async(path);
+    assert!(handle.is_finished());
+    assert_eq!(e.finish_audio_clip_import(handle), Ok(ck));
+}
+
+#[test]
+fn import_async_error() {
+    let mut e = Engine::dummy();
+
+    let handle = e.import_audio_clip_async(Path::new("lorem ipsum"));
+
+    assert!(e.finish_audio_clip_import(handle).is_err());
+    assert_eq!(e.stored_audio_clips().count(), 0);
+}
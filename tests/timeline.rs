@@ -1,4 +1,14 @@
-use adae::{Engine, Timestamp};
+#![allow(clippy::inconsistent_digit_grouping)]
+
+use std::num::NonZeroU32;
+
+use adae::{
+    error::{
+        InvalidLoopRegionError, InvalidMarkerError, InvalidTempoChangeError,
+        InvalidTimeSignatureChangeError, InvalidTimeSignatureError, RemoveTempoChangeError,
+    },
+    Direction, Engine, EventQueueStats, GridDivision, TimeSignature, Timestamp,
+};
 
 #[test]
 fn create_dummy_engine() {
@@ -8,19 +18,61 @@ fn create_dummy_engine() {
 #[test]
 fn play() {
     let mut e = Engine::dummy();
-    e.play();
+    e.play().unwrap();
 }
 
 #[test]
 fn pause() {
     let mut e = Engine::dummy();
-    e.pause();
+    e.pause().unwrap();
+}
+
+#[test]
+fn is_playing() {
+    let mut e = Engine::dummy();
+    assert!(!e.is_playing());
+    e.play().unwrap();
+    assert!(e.is_playing());
+    e.pause().unwrap();
+    assert!(!e.is_playing());
 }
 
 #[test]
 fn jump_to() {
     let mut e = Engine::dummy();
-    e.jump_to(Timestamp::from_beats(42));
+    e.jump_to(Timestamp::from_beats(42)).unwrap();
+}
+
+/// Wait for the playhead to reach `position`, after a jump.
+fn wait_for_playhead(e: &mut Engine, position: Timestamp) {
+    for _ in 0..1000 {
+        if e.playhead_position() == position {
+            return;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+    assert_eq!(e.playhead_position(), position);
+}
+
+#[test]
+fn jump_relative() {
+    let mut e = Engine::dummy();
+    e.jump_to(Timestamp::from_beats(8)).unwrap();
+    wait_for_playhead(&mut e, Timestamp::from_beats(8));
+
+    e.nudge(-3).unwrap();
+    wait_for_playhead(&mut e, Timestamp::from_beats(5));
+
+    e.jump_relative(Timestamp::from_beat_units(512), Direction::Forward)
+        .unwrap();
+    wait_for_playhead(&mut e, Timestamp::from_beat_units(5 * 1024 + 512));
+
+    // Stops at the start of the timeline
+    e.jump_relative(Timestamp::from_beats(10), Direction::Backward)
+        .unwrap();
+    wait_for_playhead(&mut e, Timestamp::zero());
+    e.nudge(2).unwrap();
+    wait_for_playhead(&mut e, Timestamp::from_beats(2));
 }
 
 #[test]
@@ -28,4 +80,278 @@ fn get_playhead_position() {
     let mut e = Engine::dummy();
     let p = e.playhead_position();
     assert_eq!(p, Timestamp::from_beats(0));
+    assert_eq!(e.playhead_samples(), 0);
+    assert_eq!(e.playhead_seconds(), 0.0);
+}
+
+#[test]
+fn playhead_samples_and_seconds() {
+    let mut e = Engine::dummy();
+    e.set_bpm_cents(60_00).unwrap();
+    e.jump_to(Timestamp::from_beats(2)).unwrap();
+    wait_for_playhead(&mut e, Timestamp::from_beats(2));
+
+    // Two beats at 60 BPM
+    let sample_rate = e.config().output_config.sample_rate;
+    assert_eq!(e.playhead_samples(), 2 * u64::from(sample_rate));
+    assert_eq!(e.playhead_seconds(), 2.0);
+}
+
+#[test]
+fn time_signatures() {
+    let mut e = Engine::dummy();
+    assert_eq!(e.time_signatures().initial(), TimeSignature::default());
+    assert_eq!(
+        Timestamp::from_beats(4).to_bars_beats(e.time_signatures()),
+        (2, 1, 0)
+    );
+
+    e.set_time_signature(Timestamp::zero(), 3, 4).unwrap();
+    e.set_time_signature(Timestamp::from_beats(6), 6, 8)
+        .unwrap();
+    assert_eq!(
+        e.set_time_signature(Timestamp::from_beats(8), 5, 6),
+        Err(InvalidTimeSignatureError {
+            numerator: 5,
+            denominator: 6
+        })
+    );
+
+    assert_eq!(
+        Timestamp::from_beats(4).to_bars_beats(e.time_signatures()),
+        (2, 2, 0)
+    );
+    assert_eq!(
+        Timestamp::from_beats(7).to_bars_beats(e.time_signatures()),
+        (3, 3, 0)
+    );
+
+    let state = e.state();
+    drop(e);
+    let (mut e, import_errors) = Engine::dummy_from_state(&state);
+    assert_eq!(import_errors.count(), 0);
+    assert_eq!(e.state(), state);
+    assert_eq!(
+        e.time_signatures().at(Timestamp::from_beats(7)),
+        TimeSignature::new(6, 8).unwrap()
+    );
+
+    e.remove_time_signature(Timestamp::from_beats(6)).unwrap();
+    assert_eq!(
+        e.remove_time_signature(Timestamp::from_beats(6)),
+        Err(InvalidTimeSignatureChangeError {
+            position: Timestamp::from_beats(6)
+        })
+    );
+    assert!(e.remove_time_signature(Timestamp::zero()).is_err());
+}
+
+#[test]
+fn beat_grid() {
+    let mut e = Engine::dummy();
+    e.set_time_signature(Timestamp::from_beats(8), 6, 8)
+        .unwrap();
+
+    let bars: Vec<_> = e
+        .beat_grid(
+            Timestamp::zero(),
+            Timestamp::from_beats(12),
+            GridDivision::Bar,
+        )
+        .collect();
+    assert_eq!(bars, [0, 4, 8, 11].map(Timestamp::from_beats));
+
+    let beats: Vec<_> = e
+        .beat_grid(
+            Timestamp::from_beats(7),
+            Timestamp::from_beats(9),
+            GridDivision::Beat,
+        )
+        .collect();
+    assert_eq!(
+        beats,
+        [7 * 1024, 8 * 1024, 8 * 1024 + 512].map(Timestamp::from_beat_units)
+    );
+
+    // Unbounded
+    let mut grid = e.beat_grid(
+        Timestamp::zero(),
+        Timestamp::infinity(),
+        GridDivision::Length(Timestamp::from_beats(1)),
+    );
+    assert_eq!(grid.nth(100), Some(Timestamp::from_beats(100)));
+}
+
+#[test]
+fn set_bpm_cents() {
+    let mut e = Engine::dummy();
+    e.add_tempo_change(Timestamp::from_beats(8), 90_00).unwrap();
+    e.jump_to(Timestamp::from_beats(4)).unwrap();
+    wait_for_playhead(&mut e, Timestamp::from_beats(4));
+
+    e.set_bpm_cents(60_00).unwrap();
+
+    assert_eq!(e.bpm_cents(), 60_00);
+    assert_eq!(e.tempo_map().changes(), [(Timestamp::from_beats(8), 90_00)]);
+    // The playhead stays at the same beat, even though it is now further into the timeline in samples
+    wait_for_playhead(&mut e, Timestamp::from_beats(4));
+}
+
+#[test]
+fn tempo_changes() {
+    let mut e = Engine::dummy();
+    e.add_tempo_change(Timestamp::from_beats(8), 90_00).unwrap();
+    e.add_tempo_change(Timestamp::from_beats(16), 140_00)
+        .unwrap();
+
+    assert_eq!(e.bpm_cents(), 120_00);
+    assert_eq!(e.tempo_map().bpm_cents_at(Timestamp::from_beats(10)), 90_00);
+    assert_eq!(
+        e.tempo_map().changes(),
+        &[
+            (Timestamp::from_beats(8), 90_00),
+            (Timestamp::from_beats(16), 140_00)
+        ]
+    );
+
+    e.remove_tempo_change(Timestamp::from_beats(8)).unwrap();
+    assert_eq!(
+        e.tempo_map().bpm_cents_at(Timestamp::from_beats(10)),
+        120_00
+    );
+    assert_eq!(
+        e.remove_tempo_change(Timestamp::from_beats(8)),
+        Err(RemoveTempoChangeError::InvalidTempoChange(
+            InvalidTempoChangeError {
+                position: Timestamp::from_beats(8)
+            }
+        ))
+    );
+    assert!(e.remove_tempo_change(Timestamp::zero()).is_err());
+
+    let state = e.state();
+    drop(e);
+    let (e, import_errors) = Engine::dummy_from_state(&state);
+    assert_eq!(import_errors.count(), 0);
+    assert_eq!(e.state(), state);
+    assert_eq!(
+        e.tempo_map().bpm_cents_at(Timestamp::from_beats(20)),
+        140_00
+    );
+}
+
+#[test]
+fn loop_region() {
+    let mut e = Engine::dummy();
+    assert_eq!(e.loop_region(), None);
+
+    let region = (Timestamp::from_beats(4), Timestamp::from_beats(8));
+    e.set_loop_region(Some(region)).unwrap();
+    assert_eq!(e.loop_region(), Some(region));
+
+    assert_eq!(
+        e.set_loop_region(Some((Timestamp::from_beats(8), Timestamp::from_beats(4)))),
+        Err(InvalidLoopRegionError {
+            start: Timestamp::from_beats(8),
+            end: Timestamp::from_beats(4),
+        })
+    );
+    assert_eq!(e.loop_region(), Some(region));
+
+    let state = e.state();
+    drop(e);
+    let (mut e, import_errors) = Engine::dummy_from_state(&state);
+    assert_eq!(import_errors.count(), 0);
+    assert_eq!(e.state(), state);
+    assert_eq!(e.loop_region(), Some(region));
+
+    e.set_loop_region(None).unwrap();
+    assert_eq!(e.loop_region(), None);
+}
+
+#[test]
+fn markers() {
+    let mut e = Engine::dummy();
+    let verse = e
+        .add_marker(Timestamp::from_beats(16), "Verse".to_owned())
+        .unwrap();
+    let intro = e.add_marker(Timestamp::zero(), "Intro".to_owned()).unwrap();
+    let chorus = e
+        .add_marker(Timestamp::from_beats(32), "Chorus".to_owned())
+        .unwrap();
+
+    let names: Vec<&str> = e.markers().map(|m| m.name.as_str()).collect();
+    assert_eq!(names, ["Intro", "Verse", "Chorus"]);
+
+    assert_eq!(e.jump_to_next_marker(), Ok(Some(verse)));
+    wait_for_playhead(&mut e, Timestamp::from_beats(16));
+    assert_eq!(e.jump_to_next_marker(), Ok(Some(chorus)));
+    wait_for_playhead(&mut e, Timestamp::from_beats(32));
+    assert_eq!(e.jump_to_next_marker(), Ok(None));
+    assert_eq!(e.jump_to_previous_marker(), Ok(Some(verse)));
+    wait_for_playhead(&mut e, Timestamp::from_beats(16));
+
+    assert_eq!(e.remove_marker(intro).unwrap().name, "Intro");
+    assert_eq!(
+        e.remove_marker(intro),
+        Err(InvalidMarkerError { key: intro })
+    );
+    assert_eq!(e.jump_to_previous_marker(), Ok(None));
+
+    let state = e.state();
+    drop(e);
+    let (mut e, import_errors) = Engine::dummy_from_state(&state);
+    assert_eq!(import_errors.count(), 0);
+    assert_eq!(e.state(), state);
+    let keys: Vec<_> = e.markers().map(|m| m.key).collect();
+    assert_eq!(keys, [verse, chorus]);
+
+    // Keys of existing markers are not reused
+    let outro = e
+        .add_marker(Timestamp::from_beats(48), "Outro".to_owned())
+        .unwrap();
+    assert!(outro != verse && outro != chorus);
+}
+
+#[test]
+fn metronome() {
+    let mut e = Engine::dummy();
+    assert!(!e.metronome_enabled());
+    assert_eq!(e.metronome_beats_per_bar(), None);
+
+    e.set_metronome_enabled(true);
+    e.set_metronome_gain(0.5);
+    e.set_metronome_beats_per_bar(NonZeroU32::new(4));
+    e.play().unwrap();
+
+    assert!(e.metronome_enabled());
+    assert_eq!(e.metronome_gain(), 0.5);
+    assert_eq!(e.metronome_beats_per_bar(), NonZeroU32::new(4));
+}
+
+#[test]
+fn event_queue_stats() {
+    let (mut e, mut p) = Engine::dummy_with_processor();
+    p.poll();
+    assert_eq!(e.event_queue_stats(), EventQueueStats::default());
+
+    for beat in 0..100 {
+        e.jump_to(Timestamp::from_beats(beat)).unwrap();
+    }
+    let stats = e.event_queue_stats();
+    assert_eq!(stats.pending, 100);
+    assert_eq!(stats.dropped, 0);
+    assert!(stats.reallocations > 0);
+
+    p.poll();
+    assert_eq!(e.event_queue_stats().pending, 0);
+
+    // Held back until the following polls
+    p.set_max_events_per_poll(1);
+    e.jump_to(Timestamp::from_beats(1)).unwrap();
+    e.jump_to(Timestamp::from_beats(2)).unwrap();
+    p.poll();
+    assert_eq!(e.event_queue_stats().pending, 1);
+    p.poll();
+    assert_eq!(e.event_queue_stats().pending, 0);
 }